@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/internal_protocol.rs"]
+mod internal_protocol;
+
+use internal_protocol::InternalMessage;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = InternalMessage::try_deserialize(data) {
+        // Lo que se lee tiene que escribirse igual
+        assert_eq!(
+            InternalMessage::try_deserialize(&message.to_bytes()),
+            Ok(message)
+        );
+    }
+});
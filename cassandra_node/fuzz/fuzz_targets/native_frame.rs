@@ -0,0 +1,18 @@
+#![no_main]
+
+use common::frame::compression::Compression;
+use common::frame::Frame;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // El primer byte elige la compresion acordada en el STARTUP
+    let Some((choice, bytes)) = data.split_first() else {
+        return;
+    };
+    let compression = match choice % 3 {
+        0 => None,
+        1 => Some(Compression::Lz4),
+        _ => Some(Compression::Snappy),
+    };
+    let _ = Frame::try_deserialize(bytes, compression);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// El parser es parte del binario del nodo, asi que se compila aca como un modulo
+#[allow(dead_code)]
+#[path = "../../src/query_parser/mod.rs"]
+mod query_parser;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = query_parser::try_parse(data);
+});
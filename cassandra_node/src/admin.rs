@@ -0,0 +1,69 @@
+use crate::node::GossipInformation;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Answer to the STATUS admin request: what a node knows about itself and the rest of the
+/// cluster.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeStatus {
+    pub node_id: String,
+    pub ip: String,
+    pub port_native_protocol: u16,
+    pub port_gossip_query: u16,
+    pub schema_version: String,
+    /// The gossip table of the node.
+    pub nodes: Vec<GossipInformation>,
+    /// Hints waiting to be delivered, by destination node id.
+    pub pending_hints: BTreeMap<String, usize>,
+}
+
+/// Answer to the HEALTH admin request and to the `/health` HTTP endpoints. A node that
+/// answers is live; it is ready to take client traffic once it loaded its data and joined
+/// the cluster.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeHealth {
+    pub node_id: String,
+    pub live: bool,
+    pub ready: bool,
+    /// Whether the node finished warming up its tables.
+    pub data_loaded: bool,
+    /// Whether the node received the gossip table of another node, or is a seed.
+    pub gossip_joined: bool,
+}
+
+/// A range of tokens whose primary replica is a node. Part of the answer to the RING admin
+/// request.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenRange {
+    pub node_id: String,
+    /// First token of the range, inclusive.
+    pub start: u64,
+    /// Last token of the range, inclusive.
+    pub end: u64,
+    /// Fraction of the ring covered by the range.
+    pub ownership: f64,
+}
+
+/// A keyspace as seen by a node.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyspaceDescription {
+    pub replication_strategy: String,
+    pub replication_factor: usize,
+    pub tables: Vec<String>,
+}
+
+/// Answer to the DESCRIBE_CLUSTER admin request: the schema of a node. Nodes that agree on
+/// the schema report the same `schema_version`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClusterDescription {
+    pub node_id: String,
+    pub schema_version: String,
+    /// Ids of the nodes in the gossip table, with their status.
+    pub nodes: BTreeMap<String, String>,
+    pub keyspaces: BTreeMap<String, KeyspaceDescription>,
+    /// Hints waiting to be delivered to any node.
+    pub pending_hints: usize,
+    /// Problems of the schema, like keyspaces with fewer replicas than their replication
+    /// factor.
+    pub warnings: Vec<String>,
+}
@@ -0,0 +1,234 @@
+use chrono::Utc;
+use common::config::AuditConfig;
+use serde::Serialize;
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// User recorded for the clients that didn't identify themselves.
+const ANONYMOUS_USER: &str = "anonymous";
+
+/// Whether a statement changes the schema or the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum StatementKind {
+    #[serde(rename = "DDL")]
+    Ddl,
+    #[serde(rename = "DML")]
+    Dml,
+}
+
+/// A line of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    /// The user the client identified itself as. It is only checked against its password on
+    /// nodes whose `[auth]` section has users; on the others it's a label the client chose.
+    pub claimed_user: String,
+    /// Address of the client connection.
+    pub source: String,
+    pub kind: StatementKind,
+    pub keyspace: String,
+    pub statement: String,
+    /// Whether the node answered the statement without an error.
+    pub success: bool,
+}
+
+/// The append-only file where a node records the schema changes and data mutations of its
+/// clients, one JSON object per line.
+#[derive(Debug)]
+pub struct AuditLog {
+    config: AuditConfig,
+    path: String,
+    /// Held while appending, so that concurrent entries don't interleave.
+    file_lock: Mutex<()>,
+}
+
+impl AuditLog {
+    /// Creates the audit log of a node. Nothing is written until a statement is audited.
+    ///
+    /// # Parameters
+    /// - `node_id`: The id of the node, which names its audit file.
+    /// - `config`: Whether the log is enabled, where it's written and which keyspaces it covers.
+    pub fn new(node_id: &str, config: AuditConfig) -> Self {
+        let path = format!("{}/{}.log", config.dir, node_id);
+        Self {
+            config,
+            path,
+            file_lock: Mutex::new(()),
+        }
+    }
+
+    /// Records a statement of a client if it changes the schema or the data of an audited
+    /// keyspace. Reads and `USE` are not recorded.
+    ///
+    /// # Parameters
+    /// - `user`: The user the client identified itself as.
+    /// - `source`: The address of the client.
+    /// - `keyspace`: The keyspace of the connection, used when the statement doesn't name one.
+    /// - `statement`: The text of the statement.
+    /// - `success`: Whether the node answered the statement without an error.
+    pub fn record(
+        &self,
+        user: Option<&str>,
+        source: SocketAddr,
+        keyspace: Option<&str>,
+        statement: &str,
+        success: bool,
+    ) -> io::Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let Some(kind) = statement_kind(statement) else {
+            return Ok(());
+        };
+        let keyspace = statement_keyspace(statement)
+            .or(keyspace.map(str::to_string))
+            .unwrap_or_default();
+        if !self.config.audits(&keyspace) {
+            return Ok(());
+        }
+
+        let entry = AuditEntry {
+            timestamp: Utc::now().to_rfc3339(),
+            claimed_user: user.unwrap_or(ANONYMOUS_USER).to_string(),
+            source: source.to_string(),
+            kind,
+            keyspace,
+            statement: statement.trim().to_string(),
+            success,
+        };
+        self.append(&entry)
+    }
+
+    fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        let _guard = self
+            .file_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        create_dir_all(&self.config.dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+/// Returns whether a statement changes the schema or the data, or `None` for reads and
+/// `USE`.
+pub fn statement_kind(statement: &str) -> Option<StatementKind> {
+    let keyword = statement.split_whitespace().next()?.to_uppercase();
+    match keyword.as_str() {
+        "CREATE" | "ALTER" | "DROP" | "TRUNCATE" => Some(StatementKind::Ddl),
+        "INSERT" | "UPDATE" | "DELETE" | "BEGIN" => Some(StatementKind::Dml),
+        _ => None,
+    }
+}
+
+/// Returns the keyspace a statement names: the keyspace of `CREATE/ALTER/DROP KEYSPACE`, or
+/// the prefix of a `keyspace.table` name. `None` if the statement uses the keyspace of the
+/// connection.
+pub fn statement_keyspace(statement: &str) -> Option<String> {
+    let words: Vec<&str> = statement
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ';')
+        .filter(|word| !word.is_empty())
+        .collect();
+    let position = words.iter().position(|word| {
+        matches!(
+            word.to_uppercase().as_str(),
+            "KEYSPACE" | "TABLE" | "INTO" | "UPDATE" | "FROM"
+        )
+    })?;
+    let name = words[position + 1..]
+        .iter()
+        .find(|word| !matches!(word.to_uppercase().as_str(), "IF" | "NOT" | "EXISTS"))?;
+
+    if words[position].eq_ignore_ascii_case("KEYSPACE") {
+        return Some(name.to_string());
+    }
+    name.split_once('.')
+        .map(|(keyspace, _)| keyspace.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn statements_are_classified_with_their_keyspace() {
+        let create = "CREATE KEYSPACE IF NOT EXISTS flights WITH replication = {}";
+        assert_eq!(statement_kind(create), Some(StatementKind::Ddl));
+        assert_eq!(statement_keyspace(create), Some("flights".to_string()));
+
+        let insert = "INSERT INTO airports.flights (id) VALUES (1);";
+        assert_eq!(statement_kind(insert), Some(StatementKind::Dml));
+        assert_eq!(statement_keyspace(insert), Some("airports".to_string()));
+
+        assert_eq!(
+            statement_keyspace("UPDATE flights SET state = 'Landed'"),
+            None
+        );
+        assert_eq!(statement_kind("SELECT * FROM airports.flights"), None);
+        assert_eq!(statement_kind("USE airports"), None);
+    }
+
+    #[test]
+    fn audit_log_records_mutations_of_audited_keyspaces() {
+        let dir = std::env::temp_dir().join("aero_audit_log");
+        let _ = fs::remove_dir_all(&dir);
+        let config = AuditConfig {
+            enabled: true,
+            dir: dir.to_string_lossy().to_string(),
+            keyspaces: vec!["airports".to_string()],
+        };
+        let audit = AuditLog::new("node_audit", config);
+        let source: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+
+        audit
+            .record(
+                Some("ops"),
+                source,
+                Some("airports"),
+                "DELETE FROM flights",
+                true,
+            )
+            .unwrap();
+        audit
+            .record(
+                None,
+                source,
+                None,
+                "INSERT INTO other.flights (id) VALUES (1)",
+                true,
+            )
+            .unwrap();
+        audit
+            .record(
+                None,
+                source,
+                Some("airports"),
+                "SELECT * FROM flights",
+                true,
+            )
+            .unwrap();
+        audit
+            .record(None, source, None, "DROP TABLE airports.flights", false)
+            .unwrap();
+
+        let lines = fs::read_to_string(dir.join("node_audit.log")).unwrap();
+        let entries: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["claimed_user"], "ops");
+        assert_eq!(entries[0]["source"], "127.0.0.1:50000");
+        assert_eq!(entries[0]["kind"], "DML");
+        assert_eq!(entries[1]["claimed_user"], "anonymous");
+        assert_eq!(entries[1]["kind"], "DDL");
+        assert_eq!(entries[1]["success"], false);
+    }
+}
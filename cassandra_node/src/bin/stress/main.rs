@@ -0,0 +1,255 @@
+use std::time::Instant;
+use std::{env, process, thread};
+
+use common::client_manager::{ClientManager, ConsistencyLevel, Value};
+use common::config::{gather_public_addresses, Config};
+use common::models::rng::run_seed;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use report::Report;
+use workload::{KeyDistribution, KeyGenerator, Operation, Profile};
+
+mod report;
+mod workload;
+
+const USAGE: &str = "usage: stress <write|read|mixed> [--nodes host:port,...] [--ops N] \
+[--threads N] [--keys N] [--dist uniform|sequential|gaussian] [--cl LEVEL] \
+[--ratio writes:reads] [--rf N] [--seed N]";
+
+const KEYSPACE: &str = "stress";
+const TABLE: &str = "standard1";
+
+/// The options of a run, from the command line.
+#[derive(Debug, Clone, PartialEq)]
+struct StressOptions {
+    profile: Profile,
+    /// The native protocol addresses of the nodes, those of the config file if empty.
+    nodes: Vec<String>,
+    operations: u64,
+    threads: u64,
+    keys: u64,
+    distribution: KeyDistribution,
+    consistency: ConsistencyLevel,
+    replication_factor: u32,
+    seed: Option<u64>,
+}
+
+impl StressOptions {
+    /// Parses the arguments after the name of the binary.
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let Some(profile) = args.first() else {
+            return Err(USAGE.to_string());
+        };
+        let mut ratio = "1:1".to_string();
+        let mut options = StressOptions {
+            profile: Profile::Write,
+            nodes: vec![],
+            operations: 10_000,
+            threads: 4,
+            keys: 1_000,
+            distribution: KeyDistribution::Uniform,
+            consistency: ConsistencyLevel::One,
+            replication_factor: 1,
+            seed: None,
+        };
+
+        let mut args = args[1..].iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing the value of {}\n{}", flag, USAGE))?;
+            let number = || -> Result<u64, String> {
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid value {} for {}", value, flag))
+            };
+            match flag.as_str() {
+                "--nodes" => options.nodes = value.split(',').map(str::to_string).collect(),
+                "--ops" => options.operations = number()?,
+                "--threads" => options.threads = number()?.max(1),
+                "--keys" => options.keys = number()?.max(1),
+                "--dist" => options.distribution = value.parse()?,
+                "--cl" => options.consistency = value.parse()?,
+                "--ratio" => ratio = value.clone(),
+                "--rf" => options.replication_factor = number()?.max(1) as u32,
+                "--seed" => options.seed = Some(number()?),
+                _ => return Err(format!("Unknown option {}\n{}", flag, USAGE)),
+            }
+        }
+        options.profile = Profile::parse(profile, &ratio)?;
+        Ok(options)
+    }
+}
+
+/// Runs `stress <profile> [options]` against a running cluster and prints the throughput
+/// and latency percentiles of each operation. The reads look up the keys a write run
+/// stored, so a `write` run with the same `--keys` should go first.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let options = StressOptions::parse(args)?;
+    let nodes = if options.nodes.is_empty() {
+        let config = Config::new().map_err(|e| format!("Can't load the config: {}", e))?;
+        gather_public_addresses(&config)
+    } else {
+        options.nodes.clone()
+    };
+    let mut client = ClientManager::new(&nodes)
+        .map_err(|e| format!("Can't connect to {}: {}", nodes.join(","), e))?;
+    create_schema(&mut client, options.replication_factor)?;
+
+    let seed = run_seed(options.seed);
+    println!(
+        "Running {} operations ({:?}) with {} threads on {} keys ({:?}) at {}, seed {}",
+        options.operations,
+        options.profile,
+        options.threads,
+        options.keys,
+        options.distribution,
+        options.consistency,
+        seed
+    );
+
+    let keys = KeyGenerator::new(options.distribution, options.keys);
+    let started = Instant::now();
+    let workers: Vec<_> = (0..options.threads)
+        .map(|i| {
+            // El resto de las operaciones queda en los primeros hilos
+            let operations = options.operations / options.threads
+                + u64::from(i < options.operations % options.threads);
+            let rng = StdRng::seed_from_u64(seed.wrapping_add(i));
+            let (nodes, options, keys) = (nodes.clone(), options.clone(), keys.clone());
+            thread::spawn(move || run_worker(&nodes, &options, keys, rng, operations))
+        })
+        .collect();
+
+    let mut report = Report::default();
+    for worker in workers {
+        let worker = worker
+            .join()
+            .map_err(|_| "A worker thread panicked".to_string())??;
+        report.merge(worker);
+    }
+    let elapsed = started.elapsed();
+
+    println!("Finished in {:.2} s", elapsed.as_secs_f64());
+    for (operation, summary) in report.summaries(elapsed) {
+        println!("{:<6} {}", operation.name(), summary);
+    }
+    println!("{:<6} {}", "total", report.total(elapsed));
+    Ok(())
+}
+
+/// Creates the keyspace and table of the runs unless they exist, keeping the rows of
+/// earlier runs for the reads. The nodes need a clustering column, so each key has a single
+/// row in `slot` 0.
+fn create_schema(client: &mut ClientManager, replication_factor: u32) -> Result<(), String> {
+    let schema = client.metadata()?;
+    if schema.keyspace(KEYSPACE).is_none() {
+        client.query(
+            format!(
+                "CREATE KEYSPACE {} WITH REPLICATION = {{ 'class' : 'SimpleStrategy', 'replication_factor' : {} }};",
+                KEYSPACE, replication_factor
+            ),
+            ConsistencyLevel::All,
+        )?;
+    }
+    if schema.table(KEYSPACE, TABLE).is_none() {
+        client.use_keyspace(KEYSPACE)?;
+        client.query(
+            format!(
+                "CREATE TABLE {} (id BIGINT, slot INT, payload TEXT, PRIMARY KEY ((id), slot));",
+                TABLE
+            ),
+            ConsistencyLevel::All,
+        )?;
+    }
+    Ok(())
+}
+
+/// Sends the operations of a thread on its own connections.
+///
+/// # Parameters
+/// - `nodes`: The addresses of the nodes.
+/// - `options`: The options of the run.
+/// - `keys`: The generator of the keys, shared by every thread.
+/// - `rng`: The generator of the thread, derived from the seed of the run.
+/// - `operations`: How many operations the thread sends.
+fn run_worker(
+    nodes: &[String],
+    options: &StressOptions,
+    keys: KeyGenerator,
+    mut rng: StdRng,
+    operations: u64,
+) -> Result<Report, String> {
+    let mut client = ClientManager::builder(nodes)
+        .default_consistency(options.consistency)
+        .keyspace(KEYSPACE)
+        .build()
+        .map_err(|e| format!("Can't connect to the cluster: {}", e))?;
+    let insert = client.prepare(&format!(
+        "INSERT INTO {} (id, slot, payload) VALUES (?, 0, ?);",
+        TABLE
+    ))?;
+    let select = client.prepare(&format!("SELECT * FROM {} WHERE id = ?;", TABLE))?;
+
+    let mut report = Report::default();
+    for _ in 0..operations {
+        let operation = options.profile.next_operation(&mut rng);
+        let key = Value::Bigint(keys.next_key(&mut rng) as i64);
+        let started = Instant::now();
+        let result = match operation {
+            Operation::Write => {
+                let payload = Value::Text(format!("{:032x}", rng.random::<u128>()));
+                client.execute(&insert, &[key, payload], None)
+            }
+            Operation::Read => client.execute(&select, &[key], None),
+        };
+        report.record(operation, started.elapsed(), result.is_ok());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn options_are_parsed_from_the_command_line() {
+        let options = StressOptions::parse(&args(
+            "mixed --ratio 1:3 --ops 500 --threads 8 --dist gaussian --cl quorum --nodes a:1,b:2",
+        ))
+        .unwrap();
+        assert_eq!(
+            options.profile,
+            Profile::Mixed {
+                writes: 1,
+                reads: 3
+            }
+        );
+        assert_eq!((options.operations, options.threads), (500, 8));
+        assert_eq!(options.distribution, KeyDistribution::Gaussian);
+        assert_eq!(options.consistency, ConsistencyLevel::Quorum);
+        assert_eq!(options.nodes, ["a:1", "b:2"]);
+
+        assert_eq!(
+            StressOptions::parse(&args("read")).unwrap().profile,
+            Profile::Read
+        );
+        assert!(StressOptions::parse(&args("write --ops")).is_err());
+        assert!(StressOptions::parse(&args("write --threads many")).is_err());
+        assert!(StressOptions::parse(&args("scan")).is_err());
+        assert!(StressOptions::parse(&[]).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::workload::Operation;
+
+/// The latencies of the operations of a kind, to compute their percentiles exactly.
+#[derive(Debug, Clone, Default)]
+struct Latencies {
+    latencies: Vec<Duration>,
+    errors: u64,
+}
+
+/// The operations of a run, recorded by each thread and then merged.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    operations: BTreeMap<Operation, Latencies>,
+}
+
+/// The throughput and latencies of the operations of a kind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub operations: u64,
+    pub errors: u64,
+    pub throughput: f64,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Report {
+    /// Records an operation. Failed operations count as errors, without latency.
+    pub fn record(&mut self, operation: Operation, latency: Duration, success: bool) {
+        let latencies = self.operations.entry(operation).or_default();
+        if success {
+            latencies.latencies.push(latency);
+        } else {
+            latencies.errors += 1;
+        }
+    }
+
+    /// Adds the operations of another thread.
+    pub fn merge(&mut self, other: Report) {
+        for (operation, other) in other.operations {
+            let latencies = self.operations.entry(operation).or_default();
+            latencies.latencies.extend(other.latencies);
+            latencies.errors += other.errors;
+        }
+    }
+
+    /// Summarizes the operations of each kind.
+    ///
+    /// # Parameters
+    /// - `elapsed`: How long the run took, to compute the throughput.
+    pub fn summaries(&self, elapsed: Duration) -> BTreeMap<Operation, Summary> {
+        self.operations
+            .iter()
+            .map(|(operation, latencies)| (*operation, summary(latencies, elapsed)))
+            .collect()
+    }
+
+    /// Summarizes every operation of the run together.
+    pub fn total(&self, elapsed: Duration) -> Summary {
+        let mut total = Latencies::default();
+        for latencies in self.operations.values() {
+            total.latencies.extend(&latencies.latencies);
+            total.errors += latencies.errors;
+        }
+        summary(&total, elapsed)
+    }
+}
+
+fn summary(latencies: &Latencies, elapsed: Duration) -> Summary {
+    let mut sorted = latencies.latencies.clone();
+    sorted.sort();
+    let operations = sorted.len() as u64 + latencies.errors;
+    let seconds = elapsed.as_secs_f64();
+    Summary {
+        operations,
+        errors: latencies.errors,
+        throughput: if seconds > 0.0 {
+            operations as f64 / seconds
+        } else {
+            0.0
+        },
+        mean: match sorted.len() {
+            0 => Duration::ZERO,
+            count => sorted.iter().sum::<Duration>() / count as u32,
+        },
+        p50: percentile(&sorted, 0.50),
+        p95: percentile(&sorted, 0.95),
+        p99: percentile(&sorted, 0.99),
+        max: sorted.last().copied().unwrap_or_default(),
+    }
+}
+
+/// Returns the latency below which `quantile` of the sorted latencies fall, by nearest rank.
+fn percentile(sorted: &[Duration], quantile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((sorted.len() as f64) * quantile).ceil().max(1.0) as usize;
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = |latency: Duration| latency.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{:>8} ops {:>6} errors {:>10.1} op/s   mean {:>7.2} ms   p50 {:>7.2} ms   p95 {:>7.2} ms   p99 {:>7.2} ms   max {:>7.2} ms",
+            self.operations,
+            self.errors,
+            self.throughput,
+            millis(self.mean),
+            millis(self.p50),
+            millis(self.p95),
+            millis(self.p99),
+            millis(self.max)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_report_merges_the_threads_and_computes_percentiles() {
+        let mut first = Report::default();
+        for millis in 1..=50 {
+            first.record(Operation::Write, Duration::from_millis(millis), true);
+        }
+        let mut second = Report::default();
+        for millis in 51..=100 {
+            second.record(Operation::Write, Duration::from_millis(millis), true);
+        }
+        second.record(Operation::Write, Duration::from_secs(10), false);
+        second.record(Operation::Read, Duration::from_millis(4), true);
+        first.merge(second);
+
+        let summaries = first.summaries(Duration::from_secs(2));
+        let writes = &summaries[&Operation::Write];
+        assert_eq!((writes.operations, writes.errors), (101, 1));
+        assert_eq!(writes.throughput, 50.5);
+        assert_eq!(writes.p50, Duration::from_millis(50));
+        assert_eq!(writes.p95, Duration::from_millis(95));
+        assert_eq!(writes.p99, Duration::from_millis(99));
+        assert_eq!(writes.max, Duration::from_millis(100));
+        assert_eq!(writes.mean, Duration::from_micros(50_500));
+        assert_eq!(summaries[&Operation::Read].p99, Duration::from_millis(4));
+
+        let total = first.total(Duration::from_secs(2));
+        assert_eq!(total.operations, 102);
+        assert_eq!(total.max, Duration::from_millis(100));
+    }
+}
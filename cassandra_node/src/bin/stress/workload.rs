@@ -0,0 +1,164 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use rand::Rng;
+
+/// An operation of a workload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operation {
+    Write,
+    Read,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Write => "write",
+            Operation::Read => "read",
+        }
+    }
+}
+
+/// The operations a run sends: only writes, only reads, or both in a ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Write,
+    Read,
+    Mixed { writes: u32, reads: u32 },
+}
+
+impl Profile {
+    /// Parses a profile.
+    ///
+    /// # Parameters
+    /// - `name`: `write`, `read` or `mixed`.
+    /// - `ratio`: The writes and reads of a mixed profile as `writes:reads`, e.g. `1:3`.
+    pub fn parse(name: &str, ratio: &str) -> Result<Self, String> {
+        match name {
+            "write" => Ok(Profile::Write),
+            "read" => Ok(Profile::Read),
+            "mixed" => {
+                let invalid = || format!("Invalid ratio {}, expected writes:reads", ratio);
+                let (writes, reads) = ratio.split_once(':').ok_or_else(invalid)?;
+                let writes: u32 = writes.trim().parse().map_err(|_| invalid())?;
+                let reads: u32 = reads.trim().parse().map_err(|_| invalid())?;
+                if writes + reads == 0 {
+                    return Err(invalid());
+                }
+                Ok(Profile::Mixed { writes, reads })
+            }
+            _ => Err(format!("Unknown profile {}", name)),
+        }
+    }
+
+    /// Draws the next operation of the profile.
+    pub fn next_operation(&self, rng: &mut impl Rng) -> Operation {
+        match *self {
+            Profile::Write => Operation::Write,
+            Profile::Read => Operation::Read,
+            Profile::Mixed { writes, reads } => {
+                if rng.random_range(0..writes + reads) < writes {
+                    Operation::Write
+                } else {
+                    Operation::Read
+                }
+            }
+        }
+    }
+}
+
+/// How the keys of the operations are chosen among the population.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDistribution {
+    /// Every key is as likely.
+    Uniform,
+    /// The keys are used in order, shared by every thread, so a write run fills the
+    /// population.
+    Sequential,
+    /// The keys around the middle of the population are the most used, as hot partitions.
+    Gaussian,
+}
+
+impl FromStr for KeyDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "uniform" => Ok(KeyDistribution::Uniform),
+            "sequential" | "seq" => Ok(KeyDistribution::Sequential),
+            "gaussian" => Ok(KeyDistribution::Gaussian),
+            _ => Err(format!("Unknown key distribution {}", s)),
+        }
+    }
+}
+
+/// Chooses the keys of the operations of every thread of a run.
+#[derive(Debug, Clone)]
+pub struct KeyGenerator {
+    distribution: KeyDistribution,
+    keys: u64,
+    next: Arc<AtomicU64>,
+}
+
+impl KeyGenerator {
+    /// # Parameters
+    /// - `distribution`: How the keys are chosen.
+    /// - `keys`: The size of the population, the keys go from 0 to `keys - 1`.
+    pub fn new(distribution: KeyDistribution, keys: u64) -> Self {
+        Self {
+            distribution,
+            keys: keys.max(1),
+            next: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Returns the key of the next operation.
+    pub fn next_key(&self, rng: &mut impl Rng) -> u64 {
+        match self.distribution {
+            KeyDistribution::Uniform => rng.random_range(0..self.keys),
+            KeyDistribution::Sequential => self.next.fetch_add(1, Ordering::Relaxed) % self.keys,
+            KeyDistribution::Gaussian => {
+                // Box-Muller, con el 99.7% de las claves dentro de la poblacion
+                let u1: f64 = rng.random_range(f64::EPSILON..1.0);
+                let u2: f64 = rng.random();
+                let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                let mean = (self.keys - 1) as f64 / 2.0;
+                let key = mean + normal * self.keys as f64 / 6.0;
+                key.round().clamp(0.0, (self.keys - 1) as f64) as u64
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn keys_and_operations_follow_the_workload() {
+        let mut rng = StdRng::seed_from_u64(3);
+
+        let sequential = KeyGenerator::new(KeyDistribution::Sequential, 3);
+        let shared = sequential.clone();
+        let mut keys: Vec<u64> = (0..4).map(|_| sequential.next_key(&mut rng)).collect();
+        keys.push(shared.next_key(&mut rng));
+        assert_eq!(keys, [0, 1, 2, 0, 1]);
+
+        let gaussian = KeyGenerator::new(KeyDistribution::Gaussian, 100);
+        let keys: Vec<u64> = (0..1000).map(|_| gaussian.next_key(&mut rng)).collect();
+        assert!(keys.iter().all(|key| *key < 100));
+        let middle = keys.iter().filter(|key| (34..=66).contains(*key)).count();
+        assert!(middle > 600, "{} keys in the middle", middle);
+
+        let mixed = Profile::parse("mixed", "1:3").unwrap();
+        let writes = (0..1000)
+            .filter(|_| mixed.next_operation(&mut rng) == Operation::Write)
+            .count();
+        assert!((200..300).contains(&writes), "{} writes", writes);
+        assert!(Profile::parse("mixed", "0:0").is_err());
+        assert_eq!(Profile::parse("read", "").unwrap(), Profile::Read);
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use common::config::{ChaosConfig, FaultRule};
+use common::models::rng::run_seed;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Length of the header of an internal message: its type, opcode and body length.
+const HEADER_LEN: usize = 6;
+
+/// What happens to a message on its way to another node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Drop,
+    Delay(Duration),
+    Duplicate,
+    Corrupt,
+}
+
+/// Decides the faults of the messages a node sends to the others, following the rules of the
+/// `[chaos]` section that apply to it.
+#[derive(Debug)]
+pub struct FaultInjector {
+    node_id: String,
+    rules: Vec<FaultRule>,
+    seed: u64,
+    rng: Mutex<StdRng>,
+}
+
+impl FaultInjector {
+    /// Creates the injector of the messages sent by a node.
+    ///
+    /// # Parameters
+    /// - `node_id`: The id of the node that sends the messages.
+    /// - `config`: The `[chaos]` section of the config.
+    ///
+    /// # Returns
+    /// The injector, or `None` if the faults are disabled or none applies to the node.
+    pub fn new(node_id: &str, config: &ChaosConfig) -> Option<Self> {
+        let rules: Vec<FaultRule> = config
+            .faults
+            .iter()
+            .filter(|rule| rule.from == "*" || rule.from == node_id)
+            .cloned()
+            .collect();
+        if !config.enabled || rules.is_empty() {
+            return None;
+        }
+        let seed = run_seed(config.seed);
+        Some(Self {
+            node_id: node_id.to_string(),
+            rules,
+            seed,
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        })
+    }
+
+    /// Returns the seed of the faults, to replay them.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Draws the faults of a message sent to a node. A dropped message has no other fault.
+    ///
+    /// # Parameters
+    /// - `to`: The id of the destination node.
+    pub fn faults(&self, to: &str) -> Vec<Fault> {
+        let Ok(mut rng) = self.rng.lock() else {
+            return vec![];
+        };
+        let mut faults = vec![];
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(&self.node_id, to))
+        {
+            if rng.random_bool(rule.drop) {
+                return vec![Fault::Drop];
+            }
+            if rng.random_bool(rule.delay) {
+                faults.push(Fault::Delay(Duration::from_millis(rule.delay_ms)));
+            }
+            if rng.random_bool(rule.duplicate) {
+                faults.push(Fault::Duplicate);
+            }
+            if rng.random_bool(rule.corrupt) {
+                faults.push(Fault::Corrupt);
+            }
+        }
+        faults
+    }
+
+    /// Changes a byte of the body of a serialized message, or of its header if it has no
+    /// body, keeping its length so the node reads the whole message.
+    pub fn corrupt(&self, bytes: &mut [u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let position = match self.rng.lock() {
+            Ok(mut rng) if bytes.len() > HEADER_LEN => rng.random_range(HEADER_LEN..bytes.len()),
+            _ => bytes.len() - 1,
+        };
+        // Con el bit alto el byte deja de ser ASCII
+        bytes[position] ^= 0xFF;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(rule: FaultRule) -> ChaosConfig {
+        ChaosConfig {
+            enabled: true,
+            seed: Some(7),
+            faults: vec![rule],
+        }
+    }
+
+    #[test]
+    fn faults_follow_the_rules_of_each_pair_of_nodes() {
+        let rule = FaultRule {
+            from: "node1".to_string(),
+            to: "node2".to_string(),
+            drop: 0.5,
+            ..FaultRule::default()
+        };
+        assert!(FaultInjector::new("node2", &config(rule.clone())).is_none());
+        assert!(FaultInjector::new(
+            "node1",
+            &ChaosConfig {
+                enabled: false,
+                ..config(rule.clone())
+            }
+        )
+        .is_none());
+
+        let injector = FaultInjector::new("node1", &config(rule)).unwrap();
+        assert!(injector.faults("node3").is_empty());
+        let dropped = (0..1000)
+            .filter(|_| injector.faults("node2") == [Fault::Drop])
+            .count();
+        assert!((400..600).contains(&dropped), "dropped {}", dropped);
+
+        // Con la misma semilla se repiten las mismas fallas
+        let replayed = FaultInjector::new(
+            "node1",
+            &config(FaultRule {
+                from: "*".to_string(),
+                drop: 0.5,
+                ..FaultRule::default()
+            }),
+        )
+        .unwrap();
+        let again = FaultInjector::new(
+            "node1",
+            &config(FaultRule {
+                drop: 0.5,
+                ..FaultRule::default()
+            }),
+        )
+        .unwrap();
+        let draws = |injector: &FaultInjector| -> Vec<_> {
+            (0..20).map(|_| injector.faults("node2")).collect()
+        };
+        assert_eq!(draws(&replayed), draws(&again));
+    }
+
+    #[test]
+    fn corrupted_messages_keep_their_length() {
+        let injector = FaultInjector::new(
+            "node1",
+            &config(FaultRule {
+                delay: 1.0,
+                delay_ms: 20,
+                corrupt: 1.0,
+                ..FaultRule::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(
+            injector.faults("node2"),
+            [Fault::Delay(Duration::from_millis(20)), Fault::Corrupt]
+        );
+
+        let original = vec![1, 3, 0, 0, 0, 4, b'b', b'o', b'd', b'y'];
+        let mut corrupted = original.clone();
+        injector.corrupt(&mut corrupted);
+        assert_eq!(corrupted.len(), original.len());
+        assert_eq!(corrupted[..HEADER_LEN], original[..HEADER_LEN]);
+        assert_eq!(
+            corrupted
+                .iter()
+                .zip(&original)
+                .filter(|(a, b)| a != b)
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::storage_format::{self, FileKind};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// Name of the file, inside the data directory of a node, where the pending cleanups are
+/// persisted.
+pub const PENDING_CLEANUPS_FILE: &str = "pending_cleanups";
+
+/// A partition that doesn't belong to this node anymore. Its rows are kept until every one
+/// of its replicas confirmed it received them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingCleanup {
+    /// The table of the partition, as `keyspace.table`.
+    pub table: String,
+    pub partition_keys: Vec<String>,
+    /// The replicas that confirmed they received the rows of the partition.
+    pub confirmed: Vec<String>,
+}
+
+/// The partitions a node has to send to their new replicas before deleting them.
+///
+/// Every change is written to disk, so a node that restarts before the replicas confirm
+/// neither loses the partitions nor forgets to delete them.
+#[derive(Debug)]
+pub struct CleanupLog {
+    path: String,
+    pending: Mutex<Vec<PendingCleanup>>,
+}
+
+impl CleanupLog {
+    /// Loads the pending cleanups stored in `path`, or starts empty if there are none.
+    ///
+    /// # Parameters
+    /// - `path`: The file where the pending cleanups are persisted.
+    pub fn load(path: &str) -> Self {
+        let pending = storage_format::read_file(path, FileKind::Cleanups)
+            .ok()
+            .and_then(|payload| serde_json::from_slice(&payload).ok())
+            .unwrap_or_default();
+        CleanupLog {
+            path: path.to_string(),
+            pending: Mutex::new(pending),
+        }
+    }
+
+    /// Returns the partitions waiting for their replicas to confirm.
+    pub fn pending(&self) -> Vec<PendingCleanup> {
+        match self.pending.lock() {
+            Ok(pending) => pending.clone(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Records that a partition has to be sent to its replicas before being deleted. A
+    /// partition already recorded keeps the confirmations it received.
+    ///
+    /// # Parameters
+    /// - `table`: The table of the partition, as `keyspace.table`.
+    /// - `partition_keys`: The values of the partition key of the partition.
+    pub fn record(&self, table: &str, partition_keys: &[String]) -> Result<(), String> {
+        self.update(|pending| {
+            if !pending
+                .iter()
+                .any(|cleanup| cleanup.is(table, partition_keys))
+            {
+                pending.push(PendingCleanup {
+                    table: table.to_string(),
+                    partition_keys: partition_keys.to_vec(),
+                    confirmed: vec![],
+                });
+            }
+        })
+    }
+
+    /// Records that a replica confirmed it received the rows of a partition.
+    ///
+    /// # Parameters
+    /// - `table`: The table of the partition, as `keyspace.table`.
+    /// - `partition_keys`: The values of the partition key of the partition.
+    /// - `node_id`: The id of the replica.
+    pub fn confirm(
+        &self,
+        table: &str,
+        partition_keys: &[String],
+        node_id: &str,
+    ) -> Result<(), String> {
+        self.update(|pending| {
+            let cleanup = pending
+                .iter_mut()
+                .find(|cleanup| cleanup.is(table, partition_keys));
+            if let Some(cleanup) = cleanup {
+                if !cleanup.confirmed.iter().any(|id| id == node_id) {
+                    cleanup.confirmed.push(node_id.to_string());
+                }
+            }
+        })
+    }
+
+    /// Forgets a partition, once it was deleted or if it belongs to this node again.
+    pub fn remove(&self, table: &str, partition_keys: &[String]) -> Result<(), String> {
+        self.update(|pending| pending.retain(|cleanup| !cleanup.is(table, partition_keys)))
+    }
+
+    /// Applies `f` to the pending cleanups and persists the result.
+    fn update(&self, f: impl FnOnce(&mut Vec<PendingCleanup>)) -> Result<(), String> {
+        let mut pending = self
+            .pending
+            .lock()
+            .map_err(|_| "Error locking pending cleanups".to_string())?;
+        f(&mut pending);
+        let payload = serde_json::to_vec(&*pending)
+            .map_err(|e| format!("Error serializing pending cleanups: {}", e))?;
+        storage_format::write_file(&self.path, FileKind::Cleanups, &payload)
+            .map_err(|e| format!("Error writing pending cleanups: {}", e))
+    }
+}
+
+impl PendingCleanup {
+    fn is(&self, table: &str, partition_keys: &[String]) -> bool {
+        self.table == table && self.partition_keys == partition_keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_cleanups_survive_a_restart() {
+        let path = "./data/cleanup_test/pending_cleanups";
+        let _ = std::fs::remove_file(path);
+        let log = CleanupLog::load(path);
+        let eze = vec!["EZE".to_string()];
+        let aep = vec!["AEP".to_string()];
+        log.record("airports.flights", &eze).unwrap();
+        log.record("airports.flights", &aep).unwrap();
+        log.confirm("airports.flights", &eze, "node2").unwrap();
+        // Volver a anotar una partición no pierde sus confirmaciones
+        log.record("airports.flights", &eze).unwrap();
+        log.confirm("airports.flights", &eze, "node2").unwrap();
+        log.remove("airports.flights", &aep).unwrap();
+
+        let restarted = CleanupLog::load(path);
+        assert_eq!(
+            restarted.pending(),
+            vec![PendingCleanup {
+                table: "airports.flights".to_string(),
+                partition_keys: eze,
+                confirmed: vec!["node2".to_string()],
+            }]
+        );
+    }
+}
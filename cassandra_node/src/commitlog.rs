@@ -0,0 +1,183 @@
+use crate::encrypted_table::{open_at_rest, seal_at_rest};
+use crate::internal_protocol::InternalMessage;
+use crate::storage_format::{self, FileKind};
+use std::sync::{Mutex, RwLock};
+use std::{fs, io};
+
+/// Prefix of the files of the segments of the commit log.
+const SEGMENT_PREFIX: &str = "segment-";
+
+/// The writes a node applied in memory but didn't flush to disk yet, so a node that stops
+/// between two flushes doesn't lose them.
+///
+/// Each write is appended to the current segment, sealed with the at-rest key, before it is
+/// applied. A flush starts a new segment and, once every table is on disk, discards the
+/// previous ones; the segments left when the node starts are replayed over the tables loaded
+/// from disk.
+#[derive(Debug)]
+pub struct CommitLog {
+    dir: String,
+    /// Number of the segment the writes are appended to.
+    segment: RwLock<u64>,
+    /// Serializes the appends of concurrent writes.
+    appending: Mutex<()>,
+}
+
+impl CommitLog {
+    /// Opens the commit log in `dir`. The writes go to a new segment, after the ones left by
+    /// the previous run.
+    ///
+    /// # Parameters
+    /// - `dir`: The commit log directory of the node.
+    pub fn open(dir: &str) -> Self {
+        let last_segment = segments(dir).last().copied().unwrap_or(0);
+        CommitLog {
+            dir: dir.to_string(),
+            segment: RwLock::new(last_segment + 1),
+            appending: Mutex::new(()),
+        }
+    }
+
+    /// Appends a write to the commit log and then applies it with `apply`. A new segment isn't
+    /// started while `apply` runs, so the flush that discards the segment sees the write.
+    ///
+    /// # Parameters
+    /// - `message`: The write.
+    /// - `apply`: Applies the write in memory.
+    ///
+    /// # Returns
+    /// The result of `apply`, or an error if the write couldn't be appended, in which case it
+    /// isn't applied.
+    pub fn record<T>(
+        &self,
+        message: &InternalMessage,
+        apply: impl FnOnce() -> Result<T, String>,
+    ) -> Result<T, String> {
+        let segment = self
+            .segment
+            .read()
+            .map_err(|_| "Error locking the commit log".to_string())?;
+        {
+            let _appending = self
+                .appending
+                .lock()
+                .map_err(|_| "Error locking the commit log".to_string())?;
+            storage_format::append_record(
+                &self.segment_path(*segment),
+                FileKind::CommitLog,
+                &seal_at_rest(&message.to_bytes()),
+            )
+            .map_err(|e| format!("Error writing the commit log: {}", e))?;
+        }
+        apply()
+    }
+
+    /// Starts a new segment, once the writes appended to the previous ones were applied.
+    ///
+    /// # Returns
+    /// The number of the new segment, to discard the previous ones once they are flushed.
+    pub fn start_segment(&self) -> Result<u64, String> {
+        let mut segment = self
+            .segment
+            .write()
+            .map_err(|_| "Error locking the commit log".to_string())?;
+        *segment += 1;
+        Ok(*segment)
+    }
+
+    /// Removes the segments before `segment`, once the writes they hold are on disk.
+    pub fn discard_before(&self, segment: u64) -> io::Result<()> {
+        for old_segment in segments(&self.dir) {
+            if old_segment < segment {
+                fs::remove_file(self.segment_path(old_segment))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the writes of the segments left on disk, in the order they were appended. The
+    /// records that can't be opened are skipped.
+    pub fn replay(&self) -> Vec<InternalMessage> {
+        let mut writes = vec![];
+        for segment in segments(&self.dir) {
+            let path = self.segment_path(segment);
+            let records = match storage_format::read_records(&path, FileKind::CommitLog) {
+                Ok(records) => records,
+                Err(e) => {
+                    eprintln!("Error reading the commit log {}: {}", path, e);
+                    continue;
+                }
+            };
+            for record in records {
+                match open_at_rest(&record)
+                    .and_then(|bytes| InternalMessage::try_deserialize(&bytes))
+                {
+                    Ok(message) => writes.push(message),
+                    Err(e) => eprintln!("Error reading a write of the commit log {}: {}", path, e),
+                }
+            }
+        }
+        writes
+    }
+
+    fn segment_path(&self, segment: u64) -> String {
+        format!("{}/{}{}", self.dir, SEGMENT_PREFIX, segment)
+    }
+}
+
+/// Returns the numbers of the segments in `dir`, sorted.
+fn segments(dir: &str) -> Vec<u64> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut segments: Vec<u64> = entries
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name();
+            // Los temporales no son segmentos, el número no se parsea
+            file_name
+                .to_str()?
+                .strip_prefix(SEGMENT_PREFIX)?
+                .parse()
+                .ok()
+        })
+        .collect();
+    segments.sort();
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(body: &str) -> InternalMessage {
+        InternalMessage::Query {
+            opcode: 2,
+            body: body.to_string(),
+            keyspace_name: "airports".to_string(),
+            trace_id: "0123456789abcdef".to_string(),
+        }
+    }
+
+    #[test]
+    fn writes_are_replayed_until_their_segment_is_discarded() {
+        std::env::set_var("DB_KEY", "82917");
+        let dir = "./data/commitlog_test";
+        let _ = fs::remove_dir_all(dir);
+        let first = write("INSERT INTO flights (id, origin) VALUES (1, 'Ushuaia');");
+        let second = write("INSERT INTO flights (id) VALUES (2);");
+
+        let log = CommitLog::open(dir);
+        log.record(&first, || Ok(())).unwrap();
+        let flushed = log.start_segment().unwrap();
+        log.record(&second, || Ok(())).unwrap();
+        let segment = fs::read(format!("{}/{}1", dir, SEGMENT_PREFIX)).unwrap();
+        assert!(!segment.windows(7).any(|w| w == b"Ushuaia"));
+
+        // Si el nodo se cae antes de terminar el flush, se reaplican las dos escrituras
+        let restarted = CommitLog::open(dir);
+        assert_eq!(restarted.replay(), vec![first, second.clone()]);
+
+        restarted.discard_before(flushed).unwrap();
+        assert_eq!(restarted.replay(), vec![second]);
+    }
+}
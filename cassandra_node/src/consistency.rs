@@ -1,11 +1,28 @@
+use crate::inflight;
 use common::frame::messages::consistency_level::ConsistencyLevel;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use crate::inflight::CANCEL_POLL_INTERVAL;
+use std::time::{Duration, Instant};
+
+/// Reasons why a consistency level was not met.
+#[derive(Debug, PartialEq)]
+pub enum ConsistencyError {
+    /// Not every node answered before the timeout of the consistency level.
+    Timeout { received: usize, required: usize },
+    /// Not enough replicas were alive to send the query to, or every node answered but not
+    /// enough of them succeeded. `received` counts the live replicas or the successes.
+    Unavailable { received: usize, required: usize },
+    /// The query was killed with the KILL_QUERY admin request while waiting.
+    Cancelled,
+}
 
-/// This enum has One, Quorum and All consistency levels.
-/// 
+/// This enum has Any, One, Quorum and All consistency levels.
 /// 
-#[derive(Debug)]
+/// Any only applies to writes: a hint stored for a replica that couldn't be reached counts
+/// as its answer.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Consistency {
+    Any,
     One,
     Quorum,
     All,
@@ -21,19 +38,32 @@ impl Consistency {
         }
     }*/
 
+    /// Converts the consistency for nodes back into the native protocol's consistency level.
+    pub fn to_consistency_level(&self) -> ConsistencyLevel {
+        match self {
+            Consistency::Any => ConsistencyLevel::Any,
+            Consistency::One => ConsistencyLevel::One,
+            Consistency::Quorum => ConsistencyLevel::Quorum,
+            Consistency::All => ConsistencyLevel::All,
+        }
+    }
+
     /// Converts native protocol's concistency level into consistency for nodes.
     /// 
     /// #Parameters
     /// - `consistency_level`: Level of consistency recieved from native protocol.
     /// 
+    /// - `default`: Consistency used if the level isn't supported by the nodes.
+    ///
     /// #Returns
-    /// The same consistency (if it's not consistency level One, Quorum or All, returns One).
-    pub fn from_consistency_level(consistency_level: ConsistencyLevel) -> Self {
+    /// The same consistency (if it's not consistency level Any, One, Quorum or All, returns `default`).
+    pub fn from_consistency_level(consistency_level: ConsistencyLevel, default: &Consistency) -> Self {
         match consistency_level {
+            ConsistencyLevel::Any => Consistency::Any,
             ConsistencyLevel::One => Consistency::One,
             ConsistencyLevel::Quorum => Consistency::Quorum,
             ConsistencyLevel::All => Consistency::All,
-            _ => Consistency::One,
+            _ => default.clone(),
         }
     }
 
@@ -46,12 +76,36 @@ impl Consistency {
     /// Usize with the number of nodes to check.
     pub fn required_nodes(&self, nodes_to_resend_query: usize) -> usize {
         match self {
-            Consistency::One => 1,
+            Consistency::Any | Consistency::One => 1,
             Consistency::Quorum => nodes_to_resend_query / 2 + 1,
             Consistency::All => nodes_to_resend_query,
         }
     }
-    /*
+
+    /// Returns the consistency of a read: Any only applies to writes, so a read at Any uses
+    /// `default`.
+    pub fn for_reads(self, default: &Consistency) -> Self {
+        match self {
+            Consistency::Any => default.clone(),
+            consistency => consistency,
+        }
+    }
+
+    /// Returns the responses still required after storing hints for `hinted` replicas: at
+    /// Any each hint counts as a response, at the other levels only the replicas count.
+    pub fn required_after_hints(&self, required: usize, hinted: usize) -> usize {
+        match self {
+            Consistency::Any => required.saturating_sub(hinted),
+            _ => required,
+        }
+    }
+
+    /// Returns whether a hint stored for a replica counts as its response.
+    pub fn counts_hints(&self) -> bool {
+        *self == Consistency::Any
+    }
+
+    /// Parses a consistency level by its name: ONE, QUORUM or ALL.
     pub fn from_str_to_enum(s: &str) -> Result<Self, String> {
         match s.to_uppercase().as_str() {
             "ONE" => Ok(Consistency::One),
@@ -59,29 +113,66 @@ impl Consistency {
             "ALL" => Ok(Consistency::All),
             _ => Err("Nivel de consistencia inválido".to_string()),
         }
-    }*/
+    }
+
+    /// Checks, before sending a query, that enough of its replicas are alive to meet the
+    /// consistency level.
+    ///
+    /// #Parameters
+    /// - `replicas`: number of replicas of the query.
+    /// - `alive`: number of those replicas that are alive.
+    ///
+    /// #Returns
+    /// The number of responses required, or Err(ConsistencyError::Unavailable) with the live
+    /// replicas if they are not enough.
+    pub fn check_alive_replicas(
+        &self,
+        replicas: usize,
+        alive: usize,
+    ) -> Result<usize, ConsistencyError> {
+        let required = self.required_nodes(replicas);
+        // Con ANY alcanza con guardar un hint para alguna réplica
+        if alive < required && !(self.counts_hints() && replicas > 0) {
+            return Err(ConsistencyError::Unavailable {
+                received: alive,
+                required,
+            });
+        }
+        Ok(required)
+    }
 
-    /// Verifies if the consistency level is met.
+    /// Verifies if the consistency level is met, waiting at most `timeout` for the responses.
     /// 
     /// #Parameters
     /// - `rx`: reciever that contains the respones from nodes.
     /// - `nodes_to_resend_query`: number of nodes to which the query is sent.
+    /// - `required`: number of successful responses that meet the consistency level, given
+    ///   by `check_alive_replicas`.
+    /// - `timeout`: the read or write timeout of the coordinator, from the `[timeouts]` config.
     /// 
     /// #Returns
-    /// Ok(responses) if consistency is met or Err("No se alcanzó el consistency level") if it is not met.
+    /// Ok(responses) if consistency is met, Err(ConsistencyError::Timeout) if the nodes didn't answer
+    /// in time, Err(ConsistencyError::Unavailable) if too many of them failed or
+    /// Err(ConsistencyError::Cancelled) if the query was killed.
     pub fn check_consistency_level(
         &self,
         rx: &Receiver<Result<String, String>>,
         nodes_to_resend_query: usize,
-    ) -> Result<Vec<String>, String> {
+        required: usize,
+        timeout: Duration,
+    ) -> Result<Vec<String>, ConsistencyError> {
+        let deadline = Instant::now() + timeout;
         let mut total_recibidas = 0;
         let mut ok_recibidas = 0;
+        let mut timed_out = false;
         let mut responses = vec![];
-        while ok_recibidas < self.required_nodes(nodes_to_resend_query)
-            && total_recibidas < nodes_to_resend_query
-        {
-            // agrego un timeout para que no se quede esperando infinitamente
-            let response_received = rx.recv();
+        while ok_recibidas < required && total_recibidas < nodes_to_resend_query {
+            if inflight::is_cancelled() {
+                return Err(ConsistencyError::Cancelled);
+            }
+            // El timeout es de toda la request, no de cada respuesta
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let response_received = rx.recv_timeout(remaining.min(CANCEL_POLL_INTERVAL));
 
             match response_received {
                 Ok(Ok(response)) => {
@@ -99,22 +190,31 @@ impl Consistency {
                     // println!("Error al recibir respuesta: {}", e);
                     total_recibidas += 1;
                 }
-                Err(_) => {
+                Err(RecvTimeoutError::Timeout) => {
+                    if Instant::now() >= deadline {
+                        timed_out = true;
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
                     // println!("Se rompió la channel: {}", e);
                     break;
                 }
             }
         }
 
-        if responses.len() == self.required_nodes(nodes_to_resend_query) {
-            //quito el print de las responses
-
-            // println!("Se alcanzó el consistency level");
-
-            Ok(responses.clone())
+        if responses.len() == required {
+            Ok(responses)
+        } else if timed_out {
+            Err(ConsistencyError::Timeout {
+                received: responses.len(),
+                required,
+            })
         } else {
-            // println!("No se alcanzó el consistency level");
-            Err("No se alcanzó el consistency level".to_string())
+            Err(ConsistencyError::Unavailable {
+                received: responses.len(),
+                required,
+            })
         }
     }
 }
@@ -126,17 +226,42 @@ mod tests_consistency_lv {
     use std::thread;
     use std::time::Duration;
 
-    use common::client_manager::ClientManager;
+    use common::client_manager::{ClientManager, ConsistencyLevel};
 
+    use crate::consistency::{Consistency, ConsistencyError};
+    use crate::inflight::InFlightQueries;
     use crate::handler_nodes::{
         start_gossip, start_node_gossip_query_protocol, start_node_native_protocol,
     };
     use crate::internal_protocol::InternalMessage;
     use crate::node::{GossipInformation, Node};
+    use crate::test_cluster::temp_storage;
+
+    /// Connects to the gossip port of a node, waiting for its listener to start.
+    fn connect_to_gossip_port(address: &str) -> std::io::Result<TcpStream> {
+        let mut attempts = 0;
+        loop {
+            match TcpStream::connect(address) {
+                Ok(stream) => return Ok(stream),
+                Err(e) if attempts >= 50 => return Err(e),
+                Err(_) => {
+                    attempts += 1;
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    }
 
     #[test]
     fn inserto_dato_con_consistency_level_one() -> Result<(), Box<dyn std::error::Error>> {
-        let node1 = Arc::new(Node::new("Node1", "localhost", 51000, 61000));
+        let storage = temp_storage("inserto_dato_con_consistency_level_one");
+        let node1 = Arc::new(Node::new_with_storage(
+            "Node1",
+            "localhost",
+            51000,
+            61000,
+            storage.clone(),
+        ));
         let node1_clone1 = Arc::clone(&node1);
         let node1_clone2 = Arc::clone(&node1);
         thread::spawn(move || start_node_native_protocol(node1_clone1));
@@ -144,11 +269,11 @@ mod tests_consistency_lv {
         let node1_clone3 = Arc::clone(&node1);
         start_gossip(node1_clone3, 1000);
 
-        let node2 = Arc::new(Node::new("Node2", "localhost", 51001, 61001));
+        let node2 = Arc::new(Node::new_with_storage("Node2", "localhost", 51001, 61001, storage));
         let node2_clone1 = Arc::clone(&node2);
         let node2_clone2 = Arc::clone(&node2);
 
-        if let Ok(mut stream) = TcpStream::connect("localhost:61000") {
+        if let Ok(mut stream) = connect_to_gossip_port("localhost:61000") {
             let gossip_table = match node2.get_gossip_table() {
                 Ok(gossip_table) => gossip_table,
                 Err(e) => {
@@ -166,10 +291,12 @@ mod tests_consistency_lv {
                 println!("Error al enviar el mensaje de gossip:new node.");
             }
 
-            let mut gossip_table_response = String::new();
+            let mut gossip_table_response = vec![];
 
-            match stream.read_to_string(&mut gossip_table_response) {
+            match stream.read_to_end(&mut gossip_table_response) {
                 Ok(_) => {
+                    // La respuesta viene con el encabezado del mensaje interno
+                    let gossip_table_response = String::from_utf8_lossy(&gossip_table_response);
                     println!(
                         "Gossip table recibido por nodo 2: {}",
                         gossip_table_response
@@ -202,12 +329,12 @@ mod tests_consistency_lv {
                 "CREATE KEYSPACE flights_keyspace
                 WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 2};"
                     .to_string(),
-                "ONE",
+                ConsistencyLevel::One,
             )
             .expect("Error al ejecutar la query");
 
         client_manager
-            .query("USE flights_keyspace;".to_string(), "ONE")
+            .query("USE flights_keyspace;".to_string(), ConsistencyLevel::One)
             .expect("Error al ejecutar la query");
 
         client_manager
@@ -225,13 +352,13 @@ mod tests_consistency_lv {
                     PRIMARY KEY ((origin_airport_id), departure_time)
                 );"
                 .to_string(),
-                "ONE",
+                ConsistencyLevel::One,
             )
             .expect("Error al ejecutar create table");
 
         client_manager.query("INSERT INTO flight_status_by_origin
                 (flight_id, origin_airport_id, destination_airport_id, departure_time, arrival_time, status)
-                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time');".to_string(),"ONE")
+                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time');".to_string(),ConsistencyLevel::One)
                 .expect("Error al ejecutar insert");
         thread::sleep(Duration::from_secs(3));
 
@@ -240,7 +367,14 @@ mod tests_consistency_lv {
 
     #[test]
     fn inserto_dato_con_consistency_level_quorum() -> Result<(), Box<dyn std::error::Error>> {
-        let node1 = Arc::new(Node::new("Node1", "localhost", 52000, 62000));
+        let storage = temp_storage("inserto_dato_con_consistency_level_quorum");
+        let node1 = Arc::new(Node::new_with_storage(
+            "QuorumNode1",
+            "localhost",
+            52000,
+            62000,
+            storage.clone(),
+        ));
         let node1_clone1 = Arc::clone(&node1);
         let node1_clone2 = Arc::clone(&node1);
         thread::spawn(move || start_node_native_protocol(node1_clone1));
@@ -248,11 +382,17 @@ mod tests_consistency_lv {
         let node1_clone3 = Arc::clone(&node1);
         start_gossip(node1_clone3, 1000);
 
-        let node2 = Arc::new(Node::new("Node2", "localhost", 52001, 62001));
+        let node2 = Arc::new(Node::new_with_storage(
+            "QuorumNode2",
+            "localhost",
+            52001,
+            62001,
+            storage.clone(),
+        ));
         let node2_clone1 = Arc::clone(&node2);
         let node2_clone2 = Arc::clone(&node2);
 
-        if let Ok(mut stream) = TcpStream::connect("localhost:62000") {
+        if let Ok(mut stream) = connect_to_gossip_port("localhost:62000") {
             let gossip_table = match node2.get_gossip_table() {
                 Ok(gossip_table) => gossip_table,
                 Err(e) => {
@@ -270,10 +410,12 @@ mod tests_consistency_lv {
                 println!("Error al enviar el mensaje de gossip:new node.");
             }
 
-            let mut gossip_table_response = String::new();
+            let mut gossip_table_response = vec![];
 
-            match stream.read_to_string(&mut gossip_table_response) {
+            match stream.read_to_end(&mut gossip_table_response) {
                 Ok(_) => {
+                    // La respuesta viene con el encabezado del mensaje interno
+                    let gossip_table_response = String::from_utf8_lossy(&gossip_table_response);
                     println!(
                         "Gossip table recibido por nodo 2: {}",
                         gossip_table_response
@@ -296,11 +438,17 @@ mod tests_consistency_lv {
         let node2_clone3 = Arc::clone(&node2);
         start_gossip(node2_clone3, 1000);
 
-        let node3 = Arc::new(Node::new("Node3", "localhost", 52002, 62002));
+        let node3 = Arc::new(Node::new_with_storage(
+            "QuorumNode3",
+            "localhost",
+            52002,
+            62002,
+            storage,
+        ));
         let node3_clone1 = Arc::clone(&node3);
         let node3_clone2 = Arc::clone(&node3);
 
-        if let Ok(mut stream) = TcpStream::connect("localhost:62000") {
+        if let Ok(mut stream) = connect_to_gossip_port("localhost:62000") {
             let gossip_table = match node3.get_gossip_table() {
                 Ok(gossip_table) => gossip_table,
                 Err(e) => {
@@ -318,10 +466,12 @@ mod tests_consistency_lv {
                 println!("Error al enviar el mensaje de gossip:new node.");
             }
 
-            let mut gossip_table_response = String::new();
+            let mut gossip_table_response = vec![];
 
-            match stream.read_to_string(&mut gossip_table_response) {
+            match stream.read_to_end(&mut gossip_table_response) {
                 Ok(_) => {
+                    // La respuesta viene con el encabezado del mensaje interno
+                    let gossip_table_response = String::from_utf8_lossy(&gossip_table_response);
                     println!(
                         "Gossip table recibido por nodo 3: {}",
                         gossip_table_response
@@ -357,12 +507,12 @@ mod tests_consistency_lv {
                 "CREATE KEYSPACE flights_keyspace
                 WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 3};"
                     .to_string(),
-                "ONE",
+                ConsistencyLevel::One,
             )
             .expect("Error al ejecutar la query");
 
         client_manager
-            .query("USE flights_keyspace;".to_string(), "ONE")
+            .query("USE flights_keyspace;".to_string(), ConsistencyLevel::One)
             .expect("Error al ejecutar la query");
 
         client_manager
@@ -380,13 +530,13 @@ mod tests_consistency_lv {
                     PRIMARY KEY ((origin_airport_id), departure_time)
                 );"
                 .to_string(),
-                "ONE",
+                ConsistencyLevel::One,
             )
             .expect("Error al ejecutar create table");
 
         client_manager.query("INSERT INTO flight_status_by_origin
                 (flight_id, origin_airport_id, destination_airport_id, departure_time, arrival_time, status)
-                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time');".to_string(),"QUORUM")
+                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time');".to_string(),ConsistencyLevel::Quorum)
                 .expect("Error al ejecutar insert");
 
         thread::sleep(Duration::from_secs(3));
@@ -396,7 +546,14 @@ mod tests_consistency_lv {
 
     #[test]
     fn inserto_dato_con_consistency_level_all() -> Result<(), Box<dyn std::error::Error>> {
-        let node1 = Arc::new(Node::new("Node1", "localhost", 53000, 63000));
+        let storage = temp_storage("inserto_dato_con_consistency_level_all");
+        let node1 = Arc::new(Node::new_with_storage(
+            "AllNode1",
+            "localhost",
+            53000,
+            63000,
+            storage.clone(),
+        ));
         let node1_clone1 = Arc::clone(&node1);
         let node1_clone2 = Arc::clone(&node1);
         thread::spawn(move || start_node_native_protocol(node1_clone1));
@@ -404,11 +561,17 @@ mod tests_consistency_lv {
         let node1_clone3 = Arc::clone(&node1);
         start_gossip(node1_clone3, 1000);
 
-        let node2 = Arc::new(Node::new("Node2", "localhost", 53001, 63001));
+        let node2 = Arc::new(Node::new_with_storage(
+            "AllNode2",
+            "localhost",
+            53001,
+            63001,
+            storage.clone(),
+        ));
         let node2_clone1 = Arc::clone(&node2);
         let node2_clone2 = Arc::clone(&node2);
 
-        if let Ok(mut stream) = TcpStream::connect("localhost:63000") {
+        if let Ok(mut stream) = connect_to_gossip_port("localhost:63000") {
             let gossip_table = match node2.get_gossip_table() {
                 Ok(gossip_table) => gossip_table,
                 Err(e) => {
@@ -426,10 +589,12 @@ mod tests_consistency_lv {
                 println!("Error al enviar el mensaje de gossip:new node.");
             }
 
-            let mut gossip_table_response = String::new();
+            let mut gossip_table_response = vec![];
 
-            match stream.read_to_string(&mut gossip_table_response) {
+            match stream.read_to_end(&mut gossip_table_response) {
                 Ok(_) => {
+                    // La respuesta viene con el encabezado del mensaje interno
+                    let gossip_table_response = String::from_utf8_lossy(&gossip_table_response);
                     println!(
                         "Gossip table recibido por nodo 2: {}",
                         gossip_table_response
@@ -452,11 +617,17 @@ mod tests_consistency_lv {
         let node2_clone3 = Arc::clone(&node2);
         start_gossip(node2_clone3, 1000);
 
-        let node3 = Arc::new(Node::new("Node3", "localhost", 53002, 63002));
+        let node3 = Arc::new(Node::new_with_storage(
+            "AllNode3",
+            "localhost",
+            53002,
+            63002,
+            storage,
+        ));
         let node3_clone1 = Arc::clone(&node3);
         let node3_clone2 = Arc::clone(&node3);
 
-        if let Ok(mut stream) = TcpStream::connect("localhost:63000") {
+        if let Ok(mut stream) = connect_to_gossip_port("localhost:63000") {
             let gossip_table = match node3.get_gossip_table() {
                 Ok(gossip_table) => gossip_table,
                 Err(e) => {
@@ -474,10 +645,12 @@ mod tests_consistency_lv {
                 println!("Error al enviar el mensaje de gossip:new node.");
             }
 
-            let mut gossip_table_response = String::new();
+            let mut gossip_table_response = vec![];
 
-            match stream.read_to_string(&mut gossip_table_response) {
+            match stream.read_to_end(&mut gossip_table_response) {
                 Ok(_) => {
+                    // La respuesta viene con el encabezado del mensaje interno
+                    let gossip_table_response = String::from_utf8_lossy(&gossip_table_response);
                     println!(
                         "Gossip table recibido por nodo 3: {}",
                         gossip_table_response
@@ -513,12 +686,12 @@ mod tests_consistency_lv {
                 "CREATE KEYSPACE flights_keyspace
                 WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 3};"
                     .to_string(),
-                "ONE",
+                ConsistencyLevel::One,
             )
             .expect("Error al ejecutar la query");
 
         client_manager
-            .query("USE flights_keyspace;".to_string(), "ONE")
+            .query("USE flights_keyspace;".to_string(), ConsistencyLevel::One)
             .expect("Error al ejecutar la query");
 
         client_manager
@@ -536,17 +709,111 @@ mod tests_consistency_lv {
                     PRIMARY KEY ((origin_airport_id), departure_time)
                 );"
                 .to_string(),
-                "ONE",
+                ConsistencyLevel::One,
             )
             .expect("Error al ejecutar create table");
 
         client_manager.query("INSERT INTO flight_status_by_origin
                 (flight_id, origin_airport_id, destination_airport_id, departure_time, arrival_time, status)
-                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time');".to_string(),"ALL")
+                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time');".to_string(),ConsistencyLevel::All)
                 .expect("Error al ejecutar insert");
 
         thread::sleep(Duration::from_secs(3));
 
         Ok(())
     }
+
+    #[test]
+    fn no_responden_a_tiempo_y_devuelve_timeout() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(Ok("ok".to_string())).unwrap();
+
+        let result = Consistency::Quorum.check_consistency_level(
+            &rx,
+            3,
+            2,
+            Duration::from_millis(50),
+        );
+        assert_eq!(
+            result,
+            Err(ConsistencyError::Timeout {
+                received: 1,
+                required: 2
+            })
+        );
+    }
+
+    #[test]
+    fn responden_con_errores_y_devuelve_unavailable() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(Ok("ok".to_string())).unwrap();
+        tx.send(Err("error".to_string())).unwrap();
+        tx.send(Err("error".to_string())).unwrap();
+
+        let result = Consistency::Quorum.check_consistency_level(
+            &rx,
+            3,
+            2,
+            Duration::from_millis(50),
+        );
+        assert_eq!(
+            result,
+            Err(ConsistencyError::Unavailable {
+                received: 1,
+                required: 2
+            })
+        );
+    }
+
+    #[test]
+    fn sin_suficientes_replicas_vivas_devuelve_unavailable() {
+        assert_eq!(Consistency::Quorum.check_alive_replicas(3, 2), Ok(2));
+        assert_eq!(Consistency::One.check_alive_replicas(3, 1), Ok(1));
+        assert_eq!(
+            Consistency::Quorum.check_alive_replicas(5, 2),
+            Err(ConsistencyError::Unavailable {
+                received: 2,
+                required: 3
+            })
+        );
+        assert_eq!(
+            Consistency::All.check_alive_replicas(2, 1),
+            Err(ConsistencyError::Unavailable {
+                received: 1,
+                required: 2
+            })
+        );
+    }
+
+    #[test]
+    fn con_any_alcanza_un_hint() {
+        assert_eq!(Consistency::Any.check_alive_replicas(3, 0), Ok(1));
+        assert!(Consistency::Any.check_alive_replicas(0, 0).is_err());
+        assert_eq!(Consistency::Any.required_after_hints(1, 2), 0);
+        assert_eq!(Consistency::One.required_after_hints(1, 2), 1);
+        assert_eq!(
+            Consistency::Any.for_reads(&Consistency::Quorum),
+            Consistency::Quorum
+        );
+        assert_eq!(
+            Consistency::from_consistency_level(ConsistencyLevel::Any, &Consistency::One),
+            Consistency::Any
+        );
+    }
+
+    #[test]
+    fn una_query_cancelada_deja_de_esperar() {
+        let queries = Arc::new(InFlightQueries::default());
+        let _guard = queries.register("query_cancelada", "SELECT * FROM flights", "");
+        let (_tx, rx) = std::sync::mpsc::channel();
+        assert!(queries.kill("query_cancelada"));
+
+        let result = Consistency::All.check_consistency_level(
+            &rx,
+            3,
+            3,
+            Duration::from_secs(5),
+        );
+        assert_eq!(result, Err(ConsistencyError::Cancelled));
+    }
 }
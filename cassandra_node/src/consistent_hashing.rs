@@ -1,5 +1,5 @@
 use crate::node::GossipInformation;
-use std::hash::{Hash, Hasher};
+use common::client_manager::partition_token;
 
 #[derive(Debug, Clone)]
 pub struct ConsistentHash;
@@ -14,10 +14,9 @@ impl ConsistentHash {
     /// #Parameters
     /// - `partition_keys`: Vector of partition keys.
     /// 
-    pub fn hash_vector(&self, partition_keys: &Vec<String>) -> u64 {
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-        format!("{:?}", partition_keys).hash(&mut hasher);
-        hasher.finish()
+    pub fn hash_vector(&self, partition_keys: &[String]) -> u64 {
+        // El token Murmur3 de Cassandra, el mismo que calculan los clientes con TokenAwarePolicy
+        partition_token(partition_keys)
     }
 
     /// Gets the node id for a vector of partition_keys.
@@ -31,23 +30,62 @@ impl ConsistentHash {
     /// Node id according to the partition keys and offset.
     pub fn get_node_id(
         &self,
-        partition_keys: &Vec<String>,
+        partition_keys: &[String],
         gossip_table: &[GossipInformation],
         offset: usize,
     ) -> Result<String, String> {
         let num_nodes = gossip_table.len();
+        match self.range_index(partition_keys, num_nodes) {
+            Some(i) => Ok(gossip_table[(i + offset) % num_nodes].node_id.clone()),
+            None => Err("Error hashing partition keys to get node".to_string()),
+        }
+    }
+
+    /// Gets the position of the token range a vector of partition keys belongs to.
+    ///
+    /// #Parameters
+    /// - `partition_keys`: Vector of partition keys.
+    /// - `num_nodes`: Number of nodes of the ring, one range each.
+    ///
+    /// #Returns
+    /// The position of the node that is the primary replica of the partition, `None` if the
+    /// ring has no nodes.
+    pub fn range_index(&self, partition_keys: &[String], num_nodes: usize) -> Option<usize> {
+        if num_nodes == 0 {
+            return None;
+        }
         let range_len = u64::MAX / num_nodes as u64;
         let hashed = self.hash_vector(partition_keys);
-        for i in 0..gossip_table.len() {
-            if hashed <= (i as u64 + 1) * range_len {
-                if i + offset < num_nodes {
-                    return Ok(gossip_table[i + offset].node_id.clone());
-                } else {
-                    return Ok(gossip_table[i + offset - num_nodes].node_id.clone());
-                }
-            }
+        // El último nodo se queda con el resto de la división
+        (0..num_nodes).find(|i| hashed <= (*i as u64 + 1) * range_len || *i == num_nodes - 1)
+    }
+
+    /// Gets the range of hashes each node is the primary replica of.
+    ///
+    /// #Parameters
+    /// - `gossip_table`: Contains gossip information of nodes.
+    ///
+    /// #Returns
+    /// The id of each node with the first and last hash of its range, both inclusive.
+    pub fn token_ranges(&self, gossip_table: &[GossipInformation]) -> Vec<(String, u64, u64)> {
+        if gossip_table.is_empty() {
+            return vec![];
         }
-        Err("Error hashing partition keys to get node".to_string())
+        let range_len = u64::MAX / gossip_table.len() as u64;
+        gossip_table
+            .iter()
+            .enumerate()
+            .map(|(i, gossip_info)| {
+                // Mismos límites que usa get_node_id
+                let start = if i == 0 { 0 } else { i as u64 * range_len + 1 };
+                let end = if i == gossip_table.len() - 1 {
+                    u64::MAX
+                } else {
+                    (i as u64 + 1) * range_len
+                };
+                (gossip_info.node_id.clone(), start, end)
+            })
+            .collect()
     }
 }
 
@@ -56,3 +94,49 @@ impl Default for ConsistentHash {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::client_manager::token;
+
+    fn gossip_table(nodes: usize) -> Vec<GossipInformation> {
+        (0..nodes)
+            .map(|i| GossipInformation {
+                node_id: format!("node{}", i),
+                ip: "127.0.0.1".to_string(),
+                port_native_protocol: format!("{}", 9042 + i),
+                port_gossip_query: format!("{}", 7000 + i),
+                last_heartbeat: 0,
+                status: "Normal".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_token_has_the_owner_of_its_range() {
+        let hash = ConsistentHash::new();
+        let gossip_table = gossip_table(3);
+        let ranges = hash.token_ranges(&gossip_table);
+        assert_eq!(ranges[0].1, 0);
+        assert_eq!(ranges[2].2, u64::MAX);
+
+        for id in 0..50 {
+            let partition_keys = vec![id.to_string()];
+            let position = hash.hash_vector(&partition_keys);
+            let owner = ranges
+                .iter()
+                .find(|(_, start, end)| (*start..=*end).contains(&position))
+                .unwrap();
+            assert_eq!(
+                hash.get_node_id(&partition_keys, &gossip_table, 0).unwrap(),
+                owner.0
+            );
+            assert_eq!(position, (token(&partition_keys) as u64) ^ (1 << 63));
+            let range = hash.range_index(&partition_keys, 3).unwrap();
+            assert_eq!(ranges[range].0, owner.0);
+        }
+        assert_eq!(hash.range_index(&["EZE".to_string()], 0), None);
+        assert!(hash.get_node_id(&["EZE".to_string()], &[], 0).is_err());
+    }
+}
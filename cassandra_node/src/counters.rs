@@ -0,0 +1,242 @@
+use crate::encrypted_table::{open_at_rest, seal_at_rest};
+use crate::storage_format::{self, FileKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Name of the file, inside the data directory of a node, where the counter shards are persisted.
+pub const COUNTER_SHARDS_FILE: &str = "counter_shards";
+
+/// The part of a counter written by one replica. Only the replica that owns a shard changes
+/// it, and every change increments its clock, so the newest version of a shard is the one
+/// with the highest clock.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    pub value: i64,
+    pub clock: u64,
+}
+
+/// The shards of a counter, by the id of the replica that owns them.
+pub type Shards = HashMap<String, Shard>;
+
+/// Body of a COUNTER message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CounterMutation {
+    /// Sent to the leader replica: it increments its own shards and sends them to the other
+    /// replicas, waiting for `required` of them (counting itself) to apply them.
+    Increment {
+        table_name: String,
+        /// Values of the primary key columns of the row.
+        row_key: HashMap<String, String>,
+        increments: HashMap<String, i64>,
+        replicas: Vec<String>,
+        required: usize,
+    },
+    /// Sent by the leader to the other replicas: the new value of its shards, by column.
+    Replicate {
+        table_name: String,
+        row_key: HashMap<String, String>,
+        node_id: String,
+        shards: HashMap<String, Shard>,
+    },
+}
+
+/// Merges the shards of a counter read from another replica: keeps the newest version of each.
+///
+/// # Parameters
+/// - `shards`: The shards to update.
+/// - `other`: The shards read from another replica.
+pub fn merge_shards(shards: &mut Shards, other: &Shards) {
+    for (node_id, shard) in other {
+        let current = shards.entry(node_id.to_string()).or_default();
+        if shard.clock > current.clock {
+            *current = *shard;
+        }
+    }
+}
+
+/// Returns the value of a counter: the sum of its shards.
+pub fn total(shards: &Shards) -> i64 {
+    shards.values().map(|shard| shard.value).sum()
+}
+
+/// The shards of every counter column a replica holds, by row and column.
+///
+/// Every change is written to disk before it is answered, so a replica never reuses a clock
+/// after restarting.
+#[derive(Debug)]
+pub struct CounterStore {
+    path: String,
+    rows: Mutex<HashMap<String, HashMap<String, Shards>>>,
+}
+
+impl CounterStore {
+    /// Loads the counter shards stored in `path`, sealed with the at-rest key, or starts empty
+    /// if there are none. Shards written in clear by an older version are still read.
+    ///
+    /// # Parameters
+    /// - `path`: The file where the shards are persisted.
+    pub fn load(path: &str) -> Self {
+        let rows = storage_format::read_file(path, FileKind::Counters)
+            .ok()
+            .and_then(|payload| {
+                // Los contadores anteriores a sellarlos están en claro
+                let payload = open_at_rest(&payload).unwrap_or(payload);
+                serde_json::from_slice(&payload).ok()
+            })
+            .unwrap_or_default();
+        CounterStore {
+            path: path.to_string(),
+            rows: Mutex::new(rows),
+        }
+    }
+
+    /// Increments the shards a replica owns in the counters of a row.
+    ///
+    /// # Parameters
+    /// - `key`: The row.
+    /// - `node_id`: The id of the replica.
+    /// - `increments`: The amount each counter column changes.
+    ///
+    /// # Returns
+    /// The new shards of the replica by column, or Err(String) if they couldn't be persisted.
+    pub fn increment(
+        &self,
+        key: &str,
+        node_id: &str,
+        increments: &HashMap<String, i64>,
+    ) -> Result<HashMap<String, Shard>, String> {
+        self.update(key, |columns| {
+            increments
+                .iter()
+                .map(|(column, increment)| {
+                    let shard = columns
+                        .entry(column.to_string())
+                        .or_default()
+                        .entry(node_id.to_string())
+                        .or_default();
+                    shard.value += increment;
+                    shard.clock += 1;
+                    (column.to_string(), *shard)
+                })
+                .collect()
+        })
+    }
+
+    /// Applies the shards of another replica to the counters of a row, keeping the newest
+    /// version of each.
+    ///
+    /// # Parameters
+    /// - `key`: The row.
+    /// - `node_id`: The id of the replica that owns the shards.
+    /// - `shards`: Its shards by column.
+    pub fn merge(
+        &self,
+        key: &str,
+        node_id: &str,
+        shards: &HashMap<String, Shard>,
+    ) -> Result<(), String> {
+        self.update(key, |columns| {
+            for (column, shard) in shards {
+                let other = Shards::from([(node_id.to_string(), *shard)]);
+                merge_shards(columns.entry(column.to_string()).or_default(), &other);
+            }
+        })
+    }
+
+    /// Returns the shards this replica holds for a counter column of a row.
+    pub fn shards(&self, key: &str, column: &str) -> Shards {
+        match self.rows.lock() {
+            Ok(rows) => rows
+                .get(key)
+                .and_then(|columns| columns.get(column))
+                .cloned()
+                .unwrap_or_default(),
+            Err(_) => Shards::new(),
+        }
+    }
+
+    /// Applies `f` to the counters of a row and persists the result, sealed with the at-rest
+    /// key.
+    fn update<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut HashMap<String, Shards>) -> T,
+    ) -> Result<T, String> {
+        let mut rows = self
+            .rows
+            .lock()
+            .map_err(|_| "Error locking counter shards".to_string())?;
+        let result = f(rows.entry(key.to_string()).or_default());
+        let payload = serde_json::to_vec(&*rows)
+            .map_err(|e| format!("Error serializing counter shards: {}", e))?;
+        storage_format::write_file(&self.path, FileKind::Counters, &seal_at_rest(&payload))
+            .map_err(|e| format!("Error writing counter shards: {}", e))?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_store(name: &str) -> CounterStore {
+        let path = format!("./data/counters_test/{}", name);
+        let _ = std::fs::remove_file(&path);
+        CounterStore::load(&path)
+    }
+
+    #[test]
+    fn test_each_replica_increments_its_own_shard() {
+        let store = empty_store("increment");
+        let increments = HashMap::from([("seats".to_string(), 3)]);
+        store.increment("row", "node_1", &increments).unwrap();
+        let shards = store.increment("row", "node_1", &increments).unwrap();
+        assert_eq!(shards["seats"], Shard { value: 6, clock: 2 });
+
+        let decrement = HashMap::from([("seats".to_string(), -1)]);
+        store.increment("row", "node_2", &decrement).unwrap();
+        assert_eq!(total(&store.shards("row", "seats")), 5);
+        assert_eq!(total(&store.shards("other_row", "seats")), 0);
+    }
+
+    #[test]
+    fn test_merge_keeps_the_newest_shard() {
+        let store = empty_store("merge");
+        let newer = HashMap::from([("seats".to_string(), Shard { value: 4, clock: 2 })]);
+        let older = HashMap::from([("seats".to_string(), Shard { value: 1, clock: 1 })]);
+        store.merge("row", "node_1", &newer).unwrap();
+        store.merge("row", "node_1", &older).unwrap();
+        assert_eq!(total(&store.shards("row", "seats")), 4);
+
+        let mut shards = store.shards("row", "seats");
+        merge_shards(
+            &mut shards,
+            &Shards::from([("node_2".to_string(), Shard { value: 2, clock: 1 })]),
+        );
+        assert_eq!(total(&shards), 6);
+    }
+
+    #[test]
+    fn test_shards_survive_a_restart() {
+        let store = empty_store("restart");
+        let increments = HashMap::from([("seats".to_string(), 2)]);
+        store.increment("row", "node_1", &increments).unwrap();
+
+        let reloaded = CounterStore::load("./data/counters_test/restart");
+        let shards = reloaded.increment("row", "node_1", &increments).unwrap();
+        assert_eq!(shards["seats"], Shard { value: 4, clock: 2 });
+    }
+
+    #[test]
+    fn test_shards_are_sealed_on_disk() {
+        let store = empty_store("sealed");
+        let increments = HashMap::from([("asientos_libres".to_string(), 2)]);
+        store.increment("row", "node_1", &increments).unwrap();
+
+        let bytes = std::fs::read("./data/counters_test/sealed").unwrap();
+        assert!(!bytes.windows(15).any(|w| w == b"asientos_libres"));
+        let reloaded = CounterStore::load("./data/counters_test/sealed");
+        assert_eq!(total(&reloaded.shards("row", "asientos_libres")), 2);
+    }
+}
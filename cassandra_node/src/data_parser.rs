@@ -1,10 +1,46 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::{self, File},
-    io::{BufRead, BufReader},
+    io::{self, BufRead, BufReader},
+    path::Path,
 };
 
-use crate::{encrypted_table::table::Table, node::GossipInformation};
+use crate::{
+    counters::COUNTER_SHARDS_FILE,
+    encrypted_table::table::Table,
+    node::GossipInformation,
+    paxos::PAXOS_STATE_FILE,
+    replication_strategy::ReplicationStrategy,
+    storage_format::{self, FileKind},
+};
+use serde::{Deserialize, Serialize};
+
+/// Directory, inside the data directory of a node, with one file per peer of its gossip table.
+pub const GOSSIP_DIR: &str = "gossip";
+
+/// The keyspaces file of a node: every keyspace with its replication options, serialized
+/// as a single JSON document.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyspacesDocument {
+    pub keyspaces: BTreeMap<String, ReplicationStrategy>,
+}
+
+impl KeyspacesDocument {
+    /// Builds the document of the given keyspaces.
+    pub fn new(keyspaces: &HashMap<String, ReplicationStrategy>) -> Self {
+        KeyspacesDocument {
+            keyspaces: keyspaces
+                .iter()
+                .map(|(name, strategy)| (name.clone(), strategy.clone()))
+                .collect(),
+        }
+    }
+
+    /// Serializes the document into the payload of the keyspaces file.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        serde_json::to_vec_pretty(self).map_err(|e| e.to_string())
+    }
+}
 
 /// Parsea una línea con comas en un vector de Strings.
 pub fn parse_columns(line: &str) -> Result<Vec<String>, String> {
@@ -51,8 +87,8 @@ pub fn parse_row(columns: &[String], line: &str) -> Result<HashMap<String, Strin
 
 // ------------------------  Recovery node data ------------------------
 
-pub fn load_tables_path(node_id: &str) -> Result<Vec<String>, String> {
-    let path = format!("./data/{}", node_id);
+pub fn load_tables_path(dir: &str) -> Result<Vec<String>, String> {
+    let path = dir.to_string();
 
     // Leer el directorio
     let entries =
@@ -74,7 +110,12 @@ pub fn load_tables_path(node_id: &str) -> Result<Vec<String>, String> {
                 .file_name()
                 .and_then(|name| name.to_str())
                 .ok_or_else(|| format!("Nombre de archivo inválido en {:?}", path))?;
-            if file_name.ends_with("keyspaces") || file_name.ends_with("gossip_table") {
+            if file_name.ends_with("keyspaces")
+                || file_name.ends_with("gossip_table")
+                || file_name.ends_with(PAXOS_STATE_FILE)
+                || file_name.ends_with(COUNTER_SHARDS_FILE)
+                || file_name.ends_with(".tmp")
+            {
                 continue;
             }
             table_names.push(file_name.to_string());
@@ -84,55 +125,141 @@ pub fn load_tables_path(node_id: &str) -> Result<Vec<String>, String> {
     Ok(table_names)
 }
 
-pub fn load_keyspaces(node_id: &str) -> Result<Vec<(String, String, String)>, String> {
-    let path = format!("./data/{}/keyspaces", node_id);
+/// Mueve un archivo corrupto del directorio de datos del nodo a `<dir>/quarantine`,
+/// para que no vuelva a cargarse pero quede disponible para inspección.
+///
+/// # Returns
+/// El path al que se movió el archivo.
+pub fn quarantine_file(dir: &str, file_name: &str) -> Result<String, String> {
+    let quarantine_dir = format!("{}/quarantine", dir);
+    fs::create_dir_all(&quarantine_dir)
+        .map_err(|e| format!("Error al crear el directorio {}: {}", quarantine_dir, e))?;
+
+    let source = format!("{}/{}", dir, file_name);
+    let destination = format!(
+        "{}/{}.{}",
+        quarantine_dir,
+        file_name,
+        chrono::Utc::now().timestamp_millis()
+    );
+    fs::rename(&source, &destination)
+        .map_err(|e| format!("Error al mover {} a {}: {}", source, destination, e))?;
 
-    let file =
-        File::open(&path).map_err(|e| format!("Error al abrir el archivo {}: {}", path, e))?;
-    let reader = BufReader::new(file);
+    Ok(destination)
+}
 
-    let mut keyspaces_data: Vec<(String, String, String)> = vec![];
+/// Loads the keyspaces persisted in `dir`.
+///
+/// # Returns
+/// Each keyspace with its replication strategy, or an error if the file can't be read.
+pub fn load_keyspaces(dir: &str) -> Result<HashMap<String, ReplicationStrategy>, String> {
+    let path = format!("{}/keyspaces", dir);
 
-    for (i, linea) in reader.lines().enumerate() {
-        let line = linea.map_err(|e| format!("Error al leer la línea {}: {}", i + 1, e))?;
-
-        let mut keyspace_data: (String, String, String) = ("".to_string(), "".to_string(), "".to_string());
+    let payload = storage_format::read_file(&path, FileKind::Keyspaces)
+        .map_err(|e| format!("Error al abrir el archivo {}: {}", path, e))?;
+    let document: KeyspacesDocument = serde_json::from_slice(&payload)
+        .map_err(|e| format!("Error al leer el archivo {}: {}", path, e))?;
 
-        let keyspaces_parts: Vec<String> = line
-            .split(',')
-            .map(|s| s.trim().to_string()) // Eliminar espacios en blanco y convertir a String
-            .collect();
+    Ok(document.keyspaces.into_iter().collect())
+}
 
-        if keyspaces_parts.is_empty() || keyspaces_parts.len() != 3 {
+/// Migrates a keyspaces file of version 2 or older, with one `name,strategy,rf` line per
+/// keyspace, to the JSON document of the current version.
+pub fn migrate_csv_keyspaces(payload: Vec<u8>) -> Result<Vec<u8>, String> {
+    let content = String::from_utf8(payload)
+        .map_err(|e| format!("El archivo de keyspaces no es texto: {}", e))?;
+
+    let mut keyspaces: HashMap<String, ReplicationStrategy> = HashMap::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        // Se separa desde la derecha, así un nombre con comas no corre las columnas.
+        let parts: Vec<&str> = line.rsplitn(3, ',').map(|s| s.trim()).collect();
+        if parts.len() != 3 {
             return Err("Error: la cantidad de datos del keyspace no es 3".to_string());
         }
+        let (replication_factor, strategy, name) = (parts[0], parts[1], parts[2]);
+
+        let replication_strategy = match strategy {
+            "SimpleStrategy" => ReplicationStrategy::new_simple(replication_factor.to_string()),
+            "RandomStrategy" => ReplicationStrategy::new_random(replication_factor.to_string()),
+            _ => return Err(format!("Invalid replication strategy: {}", strategy)),
+        };
+        keyspaces.insert(name.to_string(), replication_strategy);
+    }
 
-        keyspace_data.0 = keyspaces_parts[0].to_string();
-        keyspace_data.1 = keyspaces_parts[1].to_string();
-        keyspace_data.2 = keyspaces_parts[2].to_string();
+    KeyspacesDocument::new(&keyspaces).to_bytes()
+}
 
-        keyspaces_data.push(keyspace_data);
+/// Writes the given entries of the gossip table, one file per peer, leaving the files of the
+/// other peers untouched.
+///
+/// # Parameters
+/// - `dir`: The data directory of the node.
+/// - `peers`: The entries to write.
+pub fn save_gossip_peers(dir: &str, peers: &[GossipInformation]) -> io::Result<()> {
+    for peer in peers {
+        // El id viene de otros nodos, no puede salirse del directorio
+        if peer.node_id.is_empty()
+            || peer.node_id.starts_with('.')
+            || peer.node_id.contains(['/', '\\'])
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid node id {}", peer.node_id),
+            ));
+        }
+        let json = serde_json::to_vec(peer).map_err(io::Error::other)?;
+        let path = format!("{}/{}/{}", dir, GOSSIP_DIR, peer.node_id);
+        storage_format::write_file(&path, FileKind::Gossip, &json)?;
     }
-
-    Ok(keyspaces_data)
+    Ok(())
 }
 
-pub fn load_gossip_table(node_id: &str) -> Result<Vec<GossipInformation>, String> {
-    let path = format!("./data/{}/gossip_table", node_id);
+/// Loads the gossip table persisted in `dir`. A `gossip_table` file of the previous layout,
+/// with the whole table in it, is split into one file per peer and removed.
+///
+/// # Returns
+/// The entries of the gossip table sorted by node id, empty if none was persisted.
+pub fn load_gossip_table(dir: &str) -> Result<Vec<GossipInformation>, String> {
+    let legacy_path = format!("{}/gossip_table", dir);
+    if Path::new(&legacy_path).exists() {
+        let payload = storage_format::read_file(&legacy_path, FileKind::Gossip)
+            .map_err(|e| format!("Error al abrir el archivo {}: {}", legacy_path, e))?;
+        let gossip_table: Vec<GossipInformation> = serde_json::from_slice(&payload)
+            .map_err(|e| format!("Error al leer el archivo {}: {}", legacy_path, e))?;
+        save_gossip_peers(dir, &gossip_table)
+            .and_then(|_| fs::remove_file(&legacy_path))
+            .map_err(|e| format!("Error al migrar el archivo {}: {}", legacy_path, e))?;
+    }
 
-    let file =
-        File::open(&path).map_err(|e| format!("Error al abrir el archivo {}: {}", path, e))?;
-    let reader = BufReader::new(file);
+    let peers_dir = format!("{}/{}", dir, GOSSIP_DIR);
+    let entries = match fs::read_dir(&peers_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(format!("Error al leer el directorio {}: {}", peers_dir, e)),
+    };
 
-    // Serialize from json
-    let gossip_table: Vec<GossipInformation> = serde_json::from_reader(reader)
-        .map_err(|e| format!("Error al leer el archivo {}: {}", path, e))?;
+    let mut gossip_table: Vec<GossipInformation> = vec![];
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("Error al leer el directorio {}: {}", peers_dir, e))?
+            .path();
+        let path = path.to_string_lossy().to_string();
+        if path.ends_with(".tmp") {
+            continue;
+        }
+        let payload = storage_format::read_file(&path, FileKind::Gossip)
+            .map_err(|e| format!("Error al abrir el archivo {}: {}", path, e))?;
+        let peer: GossipInformation = serde_json::from_slice(&payload)
+            .map_err(|e| format!("Error al leer el archivo {}: {}", path, e))?;
+        gossip_table.push(peer);
+    }
+    gossip_table.sort_by(|a, b| a.node_id.cmp(&b.node_id));
 
     Ok(gossip_table)
 }
 
-pub fn load_table(node_id: &str ,file_name: &str) -> Result<Table, String> {
-    let path = format!("./data/{}/{}",node_id, file_name);
+pub fn load_table(dir: &str, file_name: &str) -> Result<Table, String> {
+    let path = format!("{}/{}", dir, file_name);
 
     let file =
         File::open(&path).map_err(|e| format!("Error al abrir el archivo {}: {}", path, e))?;
@@ -0,0 +1,134 @@
+use common::frame::messages::prepared::Value;
+use common::frame::messages::query_result::row_set::ColumnType;
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+/// The clustering key values of a row, as the key of the rows of a `Partition`.
+///
+/// Keys are ordered by the types the clustering columns were declared with, so in an INT
+/// column `9` goes before `10`, timestamps go by their instant and timeuuids by their time.
+/// The values are kept as they were written too, to serialize them back unchanged.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClusteringKey {
+    texts: Vec<String>,
+    values: Vec<Value>,
+}
+
+impl ClusteringKey {
+    /// Creates the key of the values of the clustering columns of a row.
+    ///
+    /// # Parameters
+    /// - `texts`: The values, as the nodes store them, in the order of the clustering columns.
+    /// - `types`: The types of the clustering columns. A value without a type is a text.
+    pub fn new(texts: Vec<String>, types: &[ColumnType]) -> Self {
+        let values = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                types
+                    .get(i)
+                    .copied()
+                    .unwrap_or(ColumnType::Text)
+                    .value_of(text)
+            })
+            .collect();
+        ClusteringKey { texts, values }
+    }
+
+    /// Returns the values as they were written.
+    pub fn texts(&self) -> &[String] {
+        &self.texts
+    }
+}
+
+impl Ord for ClusteringKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.values
+            .iter()
+            .zip(&other.values)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| self.values.len().cmp(&other.values.len()))
+    }
+}
+
+impl PartialOrd for ClusteringKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ClusteringKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for ClusteringKey {}
+
+/// Compares two values of the same clustering column. A null goes first and a value that
+/// isn't of the type of the column goes last, compared as a text.
+fn compare(a: &Value, b: &Value) -> Ordering {
+    a.compare(b).unwrap_or_else(|| rank(a).cmp(&rank(b)))
+}
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Text(_) => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(texts: &[&str], types: &[ColumnType]) -> ClusteringKey {
+        ClusteringKey::new(texts.iter().map(|text| text.to_string()).collect(), types)
+    }
+
+    #[test]
+    fn keys_are_ordered_by_the_types_of_their_columns() {
+        let types = [ColumnType::Int, ColumnType::Text];
+        let mut keys = [
+            key(&["10", "a"], &types),
+            key(&["9", "b"], &types),
+            key(&["9", "a"], &types),
+            key(&["-1", "z"], &types),
+        ];
+        keys.sort();
+        let texts: Vec<&[String]> = keys.iter().map(|key| key.texts()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                &["-1".to_string(), "z".to_string()][..],
+                &["9".to_string(), "a".to_string()],
+                &["9".to_string(), "b".to_string()],
+                &["10".to_string(), "a".to_string()],
+            ]
+        );
+
+        // Un texto en una columna de texto se ordena como texto
+        assert!(key(&["10"], &[ColumnType::Text]) < key(&["9"], &[ColumnType::Text]));
+        assert_eq!(
+            key(&["01"], &[ColumnType::Int]),
+            key(&["1"], &[ColumnType::Int])
+        );
+    }
+
+    #[test]
+    fn timeuuids_are_ordered_by_their_time() {
+        let types = [ColumnType::Timeuuid];
+        // El mismo reloj, con el segundo uuid generado después del primero
+        let earlier = key(&["ffffffff-0000-11ef-8000-000000000000"], &types);
+        let later = key(&["00000000-0001-11ef-8000-000000000000"], &types);
+        assert!(earlier < later);
+
+        let timestamps = [ColumnType::Timestamp];
+        assert!(
+            key(&["2024-05-01 10:30:00"], &timestamps)
+                < key(&["2024-05-01T11:00:00Z"], &timestamps)
+        );
+    }
+}
@@ -1,9 +1,10 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::HashMap,
     io::{self, Cursor, Read},
 };
 
-use super::table::{Partition, Table};
+use super::table::{Partition, RowPosition, Table};
+use common::frame::messages::query_result::row_set::ColumnType;
 
 impl Table {
     pub fn to_bytes(&self) -> Vec<u8> {
@@ -33,26 +34,70 @@ impl Table {
         let clustering_key_columns = read_string_list(&mut cursor)?;
         let columns = read_string_map(&mut cursor)?;
 
+        let mut table = Table {
+            table_name,
+            partition_key_columns,
+            clustering_key_columns,
+            columns,
+            partitions: HashMap::new(),
+        };
+        let clustering_key_types = table.clustering_key_types();
+
         // Read the number of partitions
         let partition_count = read_short(&mut cursor)? as usize;
-        let mut partitions = HashMap::with_capacity(partition_count);
+        table.partitions.reserve(partition_count);
 
         for _ in 0..partition_count {
             let partition_key = read_string_list(&mut cursor)?;
-            let partition = read_partition(&mut cursor)?;
-            partitions.insert(partition_key, partition);
+            let partition = read_partition(&mut cursor, &clustering_key_types)?;
+            table.partitions.insert(partition_key, partition);
         }
 
-        Ok(Table {
-            table_name,
-            partition_key_columns,
-            clustering_key_columns,
-            columns,
-            partitions,
+        Ok(table)
+    }
+}
+
+impl RowPosition {
+    /// Serializes the position into an opaque token that can be handed to clients.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write_string_list(&mut buffer, &self.partition_key);
+        write_string_list(&mut buffer, &self.clustering_key);
+        buffer
+    }
+
+    /// Deserializes a token produced by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<RowPosition> {
+        let mut cursor = Cursor::new(bytes);
+        let partition_key = read_string_list(&mut cursor)?;
+        let clustering_key = read_string_list(&mut cursor)?;
+        Ok(RowPosition {
+            partition_key,
+            clustering_key,
         })
     }
 }
 
+/// Serializes a single partition together with its partition key values.
+pub fn partition_to_bytes(partition_key: &[String], partition: &Partition) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    write_string_list(&mut buffer, partition_key);
+    write_partition(&mut buffer, partition);
+    buffer
+}
+
+/// Deserializes a partition written by `partition_to_bytes`, ordering its rows with the
+/// types of the clustering key columns of its table.
+pub fn partition_from_bytes(
+    bytes: &[u8],
+    clustering_key_types: &[ColumnType],
+) -> io::Result<(Vec<String>, Partition)> {
+    let mut cursor = Cursor::new(bytes);
+    let partition_key = read_string_list(&mut cursor)?;
+    let partition = read_partition(&mut cursor, clustering_key_types)?;
+    Ok((partition_key, partition))
+}
+
 // Write a [short]
 pub fn write_short(buffer: &mut Vec<u8>, value: u16) {
     buffer.extend_from_slice(&value.to_be_bytes());
@@ -93,7 +138,7 @@ pub fn write_partition(buffer: &mut Vec<u8>, partition: &Partition) {
 
     // Write each row (key-value pairs)
     for (key, value) in &partition.rows {
-        write_string_list(buffer, key);
+        write_string_list(buffer, key.texts());
 
         // Write the inner map's key-value pairs
         write_string_map(
@@ -137,21 +182,21 @@ pub fn read_string_map(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<(String, St
 }
 
 // Read a [partition]
-pub fn read_partition(cursor: &mut Cursor<&[u8]>) -> io::Result<Partition> {
+pub fn read_partition(
+    cursor: &mut Cursor<&[u8]>,
+    clustering_key_types: &[ColumnType],
+) -> io::Result<Partition> {
     let clustering_key_columns = read_string_list(cursor)?;
     let row_count = read_short(cursor)? as usize;
 
-    let mut rows = BTreeMap::new();
+    let mut partition = Partition::new(clustering_key_columns, clustering_key_types.to_vec());
     for _ in 0..row_count {
-        let key = read_string_list(cursor)?;
+        let key = partition.clustering_key(read_string_list(cursor)?);
         let value = read_string_map(cursor)?
             .into_iter()
             .collect::<HashMap<String, String>>();
-        rows.insert(key, value);
+        partition.rows.insert(key, value);
     }
 
-    Ok(Partition {
-        clustering_key_columns,
-        rows,
-    })
+    Ok(partition)
 }
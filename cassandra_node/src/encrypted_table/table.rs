@@ -1,6 +1,11 @@
-use crate::query_parser::{expression::evaluate_expression, expression::Expression};
+use super::clustering_key::ClusteringKey;
+use crate::query_parser::expression::{
+    evaluate_expression_with_types, extract_equalities, Expression,
+};
+use common::client_manager::partition_token;
+use common::frame::messages::query_result::row_set::ColumnType;
 use serde::Deserialize;
-use std::{collections::{BTreeMap, HashMap}, fs::{self, File}, io::{BufWriter, Write}};
+use std::{borrow::Borrow, cmp::Ordering, collections::{BTreeMap, HashMap}, fs::{self, File}, io::{BufWriter, Write}, ops::Bound};
 
 /// This struct represents a table including its parts.
 /// 
@@ -22,9 +27,51 @@ pub struct Table {
 pub struct Partition {
     // holds data with the same partition key
     // rows with specific partition key. Hashmap column key: value.
-    // rows are ordered based on clustering keys
+    // rows are ordered based on clustering keys, compared with the types of their columns
     pub clustering_key_columns: Vec<String>,
-    pub rows: BTreeMap<Vec<String>, HashMap<String, String>>,
+    pub clustering_key_types: Vec<ColumnType>,
+    pub rows: BTreeMap<ClusteringKey, HashMap<String, String>>,
+}
+
+/// Opaque position of a row inside a table, used to resume a scan right after it.
+///
+/// Rows are visited in the order of the ring, by the token of their partition key and then by
+/// partition key and clustering key, so a position stays valid even if rows are inserted or
+/// deleted between pages, and any replica can resume from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowPosition {
+    pub(super) partition_key: Vec<String>,
+    pub(super) clustering_key: Vec<String>,
+}
+
+impl RowPosition {
+    /// Creates the position of the row with the given partition and clustering key values,
+    /// as the nodes store them.
+    pub fn new(partition_key: Vec<String>, clustering_key: Vec<String>) -> Self {
+        RowPosition {
+            partition_key,
+            clustering_key,
+        }
+    }
+}
+
+/// A single write applied as part of a batch with `Table::apply_batch`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    Insert(HashMap<String, String>),
+    Update {
+        values_to_update: HashMap<String, String>,
+        condition: Expression,
+    },
+    Delete(Expression),
+}
+
+/// A page of rows returned by `Table::select_page`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub rows: Vec<HashMap<String, String>>,
+    /// Position to resume from, or `None` if there are no more rows.
+    pub next: Option<RowPosition>,
 }
 
 impl Table {
@@ -104,7 +151,10 @@ impl Table {
             partition.insert(row)?;
         } else {
             // if not, create a new partition, insert row into partition, and insert partition into table
-            let mut partition = Partition::new(self.clustering_key_columns.clone());
+            let mut partition = Partition::new(
+                self.clustering_key_columns.clone(),
+                self.clustering_key_types(),
+            );
             partition.insert(row)?;
             self.partitions.insert(partition_keys, partition);
         }
@@ -122,9 +172,10 @@ impl Table {
         values_to_update: HashMap<String, String>,
         condition: &Expression,
     ) -> Result<(), String> {
+        let column_types = self.column_types();
         for partition in self.partitions.values_mut() {
             for row in partition.rows.values_mut() {
-                let result = evaluate_expression(condition, row);
+                let result = evaluate_expression_with_types(condition, row, &column_types);
                 if let Ok(true) = result {
                     for (column, value) in values_to_update.iter() {
                         if self.columns.iter().any(|(col, _)| col == column) {
@@ -141,16 +192,42 @@ impl Table {
         Ok(())
     }
 
+    /// Applies several mutations as a single unit: either all of them are applied or,
+    /// if any of them fails, the table is left as it was before the batch.
+    ///
+    /// #Parameters
+    /// - `mutations`: The inserts, updates and deletes to apply, in order.
+    ///
+    pub fn apply_batch(&mut self, mutations: Vec<Mutation>) -> Result<(), String> {
+        let snapshot = self.partitions.clone();
+        for mutation in mutations {
+            let result = match mutation {
+                Mutation::Insert(row) => self.insert(row),
+                Mutation::Update {
+                    values_to_update,
+                    condition,
+                } => self.update(values_to_update, &condition),
+                Mutation::Delete(condition) => self.delete(&condition),
+            };
+            if let Err(e) = result {
+                self.partitions = snapshot;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
     /// Deletes a row on the table given a condition.
     /// 
     /// #Parameters
     /// - `condition`: Contains the condition to search on the table.
     ///
     pub fn delete(&mut self, condition: &Expression) -> Result<(), String> {
+        let column_types = self.column_types();
         for partition in self.partitions.values_mut() {
             let mut rows_to_delete = vec![];
             for (key, row) in partition.rows.iter() {
-                let result = evaluate_expression(condition, row);
+                let result = evaluate_expression_with_types(condition, row, &column_types);
                 if let Ok(true) = result {
                     rows_to_delete.push(key.clone());
                 } else if let Err(e) = result {
@@ -187,6 +264,27 @@ impl Table {
         self.clustering_key_columns.clone()
     }
 
+    /// Gets the type each column was declared with, to evaluate conditions on the rows.
+    pub fn column_types(&self) -> HashMap<String, ColumnType> {
+        self.columns
+            .iter()
+            .map(|(column, column_type)| (column.clone(), ColumnType::parse(column_type)))
+            .collect()
+    }
+
+    /// Gets the types the clustering key columns were declared with.
+    pub fn clustering_key_types(&self) -> Vec<ColumnType> {
+        self.clustering_key_columns
+            .iter()
+            .map(|column| {
+                self.columns
+                    .iter()
+                    .find(|(name, _)| name == column)
+                    .map_or(ColumnType::Text, |(_, column_type)| ColumnType::parse(column_type))
+            })
+            .collect()
+    }
+
     /// Gets a vector containing the rows of the table.
     pub fn get_vector_of_rows(&self) -> Vec<HashMap<String, String>> {
         let mut rows = vec![];
@@ -256,7 +354,8 @@ impl Table {
         matching_rows
     }
 
-    /// Finds rows that match the condition.
+    /// Finds rows that match the condition. If the condition fixes every partition key
+    /// column with `=`, only the rows of that partition are evaluated.
     /// 
     /// #Parameters
     /// - 'condition': Contains the consition to evaluate on each row.
@@ -264,10 +363,11 @@ impl Table {
     /// #Returns
     ///- Returns the selected rows.
     pub fn select_if(&self, condition: &Expression) -> Vec<HashMap<String, String>> {
+        let column_types = self.column_types();
         let mut selected_rows = vec![];
-        for partition in self.partitions.values() {
+        for partition in self.partitions_for_condition(condition) {
             for row in partition.rows.values() {
-                let result = evaluate_expression(condition, row);
+                let result = evaluate_expression_with_types(condition, row, &column_types);
                 if let Ok(true) = result {
                     selected_rows.push(row.clone());
                 } else if let Err(_e) = result {
@@ -278,16 +378,94 @@ impl Table {
         selected_rows
     }
 
+    /// Returns the partitions a condition can match: only the one it fixes with `=` on every
+    /// partition key column, or all of them otherwise.
+    fn partitions_for_condition(&self, condition: &Expression) -> Vec<&Partition> {
+        let equalities = extract_equalities(condition);
+        let partition_keys = self
+            .partition_key_columns
+            .iter()
+            .map(|column| equalities.get(column).cloned())
+            .collect::<Option<Vec<String>>>();
+        match partition_keys {
+            Some(partition_keys) => self.partitions.get(&partition_keys).into_iter().collect(),
+            None => self.partitions.values().collect(),
+        }
+    }
+
+    /// Iterates over the rows of the table in the order of the ring (see `ring_order`) and then
+    /// by clustering key, starting right after `after` (or from the beginning if it is `None`).
+    ///
+    /// #Parameters
+    /// - 'after': Position of the last row already visited.
+    ///
+    /// #Returns
+    ///- An iterator of each row together with its position.
+    pub fn rows_after<'a>(
+        &'a self,
+        after: Option<&RowPosition>,
+    ) -> impl Iterator<Item = (RowPosition, &'a HashMap<String, String>)> + 'a {
+        let mut partition_keys: Vec<&Vec<String>> = self
+            .partitions
+            .keys()
+            .filter(|key| match after {
+                Some(after) => ring_order(key, &after.partition_key).is_ge(),
+                None => true,
+            })
+            .collect();
+        partition_keys.sort_by(|a, b| ring_order(a, b));
+
+        let after = after.cloned();
+        partition_keys.into_iter().flat_map(move |partition_key| {
+            let partition = &self.partitions[partition_key];
+            let lower = match &after {
+                Some(after) if &after.partition_key == partition_key => {
+                    Bound::Excluded(partition.clustering_key(after.clustering_key.clone()))
+                }
+                _ => Bound::Unbounded,
+            };
+            partition
+                .rows
+                .range((lower, Bound::Unbounded))
+                .map(move |(clustering_key, row)| {
+                    let position = RowPosition {
+                        partition_key: partition_key.clone(),
+                        clustering_key: clustering_key.texts().to_vec(),
+                    };
+                    (position, row)
+                })
+        })
+    }
+
+    /// Finds up to `page_size` rows that match the condition, resuming after `after`.
+    ///
+    /// #Parameters
+    /// - 'condition': Contains the condition to evaluate on each row.
+    /// - 'after': Position returned by the previous page, if any.
+    /// - 'page_size': Maximum amount of rows to return.
+    ///
+    /// #Returns
+    ///- The selected rows and the position to resume from.
+    pub fn select_page(
+        &self,
+        condition: &Expression,
+        after: Option<&RowPosition>,
+        page_size: usize,
+    ) -> Result<Page, String> {
+        page_of_rows(self.rows_after(after), condition, &self.column_types(), page_size)
+    }
+
     /// Deletes rows that match the condition.
     /// 
     /// #Parameters
     /// - 'condition': Contains the consition to evaluate on each row.
     /// 
     pub fn delete_if(&mut self, condition: &Expression) -> Result<(), String> {
+        let column_types = self.column_types();
         let mut rows_to_delete = vec![];
         for partition in self.partitions.values_mut() {
             for (key, row) in partition.rows.iter() {
-                let result = evaluate_expression(condition, row);
+                let result = evaluate_expression_with_types(condition, row, &column_types);
                 if let Ok(true) = result {
                     rows_to_delete.push(key.clone());
                 } else if let Err(e) = result {
@@ -438,13 +616,22 @@ fn row_matches_query(
 }
 
 impl Partition {
-    pub fn new(clustering_key_columns: Vec<String>) -> Self {
+    pub fn new(clustering_key_columns: Vec<String>, clustering_key_types: Vec<ColumnType>) -> Self {
         Partition {
             clustering_key_columns,
+            clustering_key_types,
             rows: BTreeMap::new(),
         }
     }
 
+    /// Gets the key of the rows with the given clustering key values.
+    ///
+    /// #Parameters
+    /// - 'clustering_keys': Contains the values of the clustering key columns, in order.
+    pub fn clustering_key(&self, clustering_keys: Vec<String>) -> ClusteringKey {
+        ClusteringKey::new(clustering_keys, &self.clustering_key_types)
+    }
+
     /// Inserts row into partition.
     /// 
     /// #Parameters
@@ -463,12 +650,13 @@ impl Partition {
                 ));
             }
         }
-        self.rows.insert(clustering_keys, row); // insert row into partition's btree, with clustering keys as key
+        let clustering_key = self.clustering_key(clustering_keys);
+        self.rows.insert(clustering_key, row); // insert row into partition's btree, with clustering keys as key
         Ok(())
     }
 
     /// Gets the rows of the partition.
-    pub fn get_rows(&self) -> &BTreeMap<Vec<String>, HashMap<String, String>> {
+    pub fn get_rows(&self) -> &BTreeMap<ClusteringKey, HashMap<String, String>> {
         &self.rows
     }
 
@@ -478,6 +666,53 @@ impl Partition {
     }
 }
 
+/// Orders two partition keys like the ring: by their token, the same on every node, and
+/// then by their values.
+pub fn ring_order(a: &[String], b: &[String]) -> Ordering {
+    partition_token(a)
+        .cmp(&partition_token(b))
+        .then_with(|| a.cmp(b))
+}
+
+/// Takes up to `page_size` rows that match the condition from rows visited in order.
+///
+/// #Parameters
+/// - 'rows': The rows to visit, each together with its position.
+/// - 'condition': Contains the condition to evaluate on each row.
+/// - 'column_types': The types of the columns of the table.
+/// - 'page_size': Maximum amount of rows to return.
+///
+/// #Returns
+///- The selected rows and, if there are more rows to visit, the position of the last one.
+pub(super) fn page_of_rows<R: Borrow<HashMap<String, String>>>(
+    rows: impl Iterator<Item = (RowPosition, R)>,
+    condition: &Expression,
+    column_types: &HashMap<String, ColumnType>,
+    page_size: usize,
+) -> Result<Page, String> {
+    let mut page = vec![];
+    let mut last_position = None;
+    for (position, row) in rows {
+        if page.len() == page_size {
+            // Hay más filas: la próxima página arranca después de la última devuelta.
+            return Ok(Page {
+                rows: page,
+                next: last_position,
+            });
+        }
+        if evaluate_expression_with_types(condition, row.borrow(), column_types)
+            .map_err(|e| e.to_string())?
+        {
+            page.push(row.borrow().clone());
+            last_position = Some(position);
+        }
+    }
+    Ok(Page {
+        rows: page,
+        next: None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::query_parser::expression::Operand;
@@ -529,7 +764,7 @@ mod tests {
         assert!(table.partitions.contains_key(&partition_keys));
 
         let partition = table.partitions.get(&partition_keys).unwrap();
-        let clustering_keys = vec!["1".to_string()];
+        let clustering_keys = partition.clustering_key(vec!["1".to_string()]);
         assert!(partition.rows.contains_key(&clustering_keys));
 
         let inserted_row = partition.rows.get(&clustering_keys).unwrap();
@@ -555,6 +790,58 @@ mod tests {
         assert_eq!(values, vec![&second_entry_row, &first_entry_row]);
     }
 
+    #[test]
+    fn test_rows_are_ordered_by_the_type_of_the_clustering_key() {
+        let mut table = Table::new(
+            "table_name".to_string(),
+            vec!["id".to_string()],
+            vec!["order".to_string()],
+            vec![
+                ("id".to_string(), "TEXT".to_string()),
+                ("order".to_string(), "INT".to_string()),
+                ("data".to_string(), "TEXT".to_string()),
+            ],
+        );
+        for order in ["10", "9", "-3", "100"] {
+            let _ = insert_into_table(&mut table, "111", order, "data");
+        }
+
+        let rows = table.get_rows_from_partition(&vec!["111".to_string()]);
+        let orders: Vec<&str> = rows.iter().map(|row| row["order"].as_str()).collect();
+        assert_eq!(orders, vec!["-3", "9", "10", "100"]);
+
+        let first = table.select_page(&Expression::True, None, 2).unwrap();
+        let second = table
+            .select_page(&Expression::True, first.next.as_ref(), 2)
+            .unwrap();
+        assert_eq!(second.rows, vec![create_row("111", "10", "data"), create_row("111", "100", "data")]);
+    }
+
+    #[test]
+    fn test_select_if_compares_with_the_types_of_the_columns() {
+        let mut table = Table::new(
+            "table_name".to_string(),
+            vec!["id".to_string()],
+            vec!["order".to_string()],
+            vec![
+                ("id".to_string(), "TEXT".to_string()),
+                ("order".to_string(), "BIGINT".to_string()),
+                ("data".to_string(), "TEXT".to_string()),
+            ],
+        );
+        for order in ["9", "10", "10000000000"] {
+            let _ = insert_into_table(&mut table, "111", order, "data");
+        }
+        let condition = Expression::Comparison {
+            left: Operand::Column("order".to_string()),
+            operator: ">".to_string(),
+            right: Operand::Integer("9".to_string()),
+        };
+
+        let rows = table.select_if(&condition);
+        assert_eq!(rows, vec![create_row("111", "10", "data"), create_row("111", "10000000000", "data")]);
+    }
+
     #[test]
     fn test_missing_partition_key() {
         let mut table = create_table(vec!["id".to_string()], vec!["order".to_string()]);
@@ -687,4 +974,142 @@ mod tests {
         assert!(table.contains_row(&row3));
         assert!(!table.contains_row(&create_row("111", "4", "data4")));
     }
+
+    #[test]
+    fn test_select_if_only_scans_the_fixed_partition() {
+        let mut table = create_table(vec!["id".to_string()], vec!["order".to_string()]);
+        for (id, order) in [("111", "1"), ("111", "2"), ("111", "3"), ("222", "2")] {
+            let _ = insert_into_table(&mut table, id, order, "data");
+        }
+        let condition = Expression::And {
+            left: Box::new(Expression::Comparison {
+                left: Operand::Column("id".to_string()),
+                operator: "=".to_string(),
+                right: Operand::String("111".to_string()),
+            }),
+            right: Box::new(Expression::Comparison {
+                left: Operand::Column("order".to_string()),
+                operator: ">".to_string(),
+                right: Operand::String("1".to_string()),
+            }),
+        };
+
+        let rows = table.select_if(&condition);
+        assert_eq!(rows, vec![create_row("111", "2", "data"), create_row("111", "3", "data")]);
+
+        // Sin la clave de partición se recorren todas las particiones.
+        let Expression::And { right, .. } = condition else {
+            unreachable!()
+        };
+        assert_eq!(table.select_if(&right).len(), 3);
+
+        let missing = Expression::Comparison {
+            left: Operand::Column("id".to_string()),
+            operator: "=".to_string(),
+            right: Operand::String("333".to_string()),
+        };
+        assert!(table.select_if(&missing).is_empty());
+    }
+
+    #[test]
+    fn test_select_page_resumes_in_clustering_order() {
+        let mut table = create_table(vec!["id".to_string()], vec!["order".to_string()]);
+        for (id, order) in [("222", "2"), ("111", "3"), ("111", "1"), ("222", "1"), ("111", "2")] {
+            let _ = insert_into_table(&mut table, id, order, "data");
+        }
+
+        let first = table.select_page(&Expression::True, None, 2).unwrap();
+        assert_eq!(first.rows, vec![create_row("111", "1", "data"), create_row("111", "2", "data")]);
+
+        let second = table
+            .select_page(&Expression::True, first.next.as_ref(), 2)
+            .unwrap();
+        assert_eq!(second.rows, vec![create_row("111", "3", "data"), create_row("222", "1", "data")]);
+
+        let third = table
+            .select_page(&Expression::True, second.next.as_ref(), 2)
+            .unwrap();
+        assert_eq!(third.rows, vec![create_row("222", "2", "data")]);
+        assert!(third.next.is_none());
+    }
+
+    #[test]
+    fn test_select_page_filters_and_survives_deletes() {
+        let mut table = create_table(vec!["id".to_string()], vec!["order".to_string()]);
+        for order in ["1", "2", "3", "4"] {
+            let _ = insert_into_table(&mut table, "111", order, "data");
+        }
+        let condition = Expression::Not {
+            right: Box::new(Expression::Comparison {
+                left: Operand::Column("order".to_string()),
+                operator: "=".to_string(),
+                right: Operand::String("2".to_string()),
+            }),
+        };
+
+        let first = table.select_page(&condition, None, 1).unwrap();
+        assert_eq!(first.rows, vec![create_row("111", "1", "data")]);
+
+        // El row en la posición devuelta se borra antes de pedir la siguiente página.
+        table.delete_partition(&vec!["111".to_string()]).unwrap();
+        for order in ["3", "4"] {
+            let _ = insert_into_table(&mut table, "111", order, "data");
+        }
+        let second = table.select_page(&condition, first.next.as_ref(), 5).unwrap();
+        assert_eq!(second.rows, vec![create_row("111", "3", "data"), create_row("111", "4", "data")]);
+        assert!(second.next.is_none());
+    }
+
+    #[test]
+    fn test_row_position_roundtrip() {
+        let position = RowPosition {
+            partition_key: vec!["111".to_string()],
+            clustering_key: vec!["1".to_string(), "a".to_string()],
+        };
+        let bytes = position.to_bytes();
+        assert_eq!(RowPosition::from_bytes(&bytes).unwrap(), position);
+        assert!(RowPosition::from_bytes(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let mut table = create_table(vec!["id".to_string()], vec!["order".to_string()]);
+        let condition = Expression::Comparison {
+            left: Operand::Column("order".to_string()),
+            operator: "=".to_string(),
+            right: Operand::String("1".to_string()),
+        };
+        let mut values_to_update = HashMap::new();
+        values_to_update.insert("data".to_string(), "updated".to_string());
+
+        let result = table.apply_batch(vec![
+            Mutation::Insert(create_row("111", "1", "data1")),
+            Mutation::Insert(create_row("111", "2", "data2")),
+            Mutation::Update {
+                values_to_update,
+                condition: condition.clone(),
+            },
+        ]);
+
+        assert!(result.is_ok());
+        assert!(table.contains_row(&create_row("111", "1", "updated")));
+        assert!(table.contains_row(&create_row("111", "2", "data2")));
+    }
+
+    #[test]
+    fn test_failed_batch_leaves_table_unchanged() {
+        let mut table = create_table(vec!["id".to_string()], vec!["order".to_string()]);
+        let _ = insert_into_table(&mut table, "111", "1", "data1");
+        let mut invalid_row = create_row("222", "1", "data");
+        invalid_row.insert("missing_column".to_string(), "value".to_string());
+
+        let result = table.apply_batch(vec![
+            Mutation::Insert(create_row("111", "2", "data2")),
+            Mutation::Delete(Expression::True),
+            Mutation::Insert(invalid_row),
+        ]);
+
+        assert!(result.is_err());
+        assert_eq!(table.get_vector_of_rows(), vec![create_row("111", "1", "data1")]);
+    }
 }
@@ -1,19 +1,27 @@
 use super::node::Node;
 use crate::internal_protocol::InternalMessage;
 use crate::native_protocol::handle_native_protocol_connection;
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read};
 use std::io::Write;
-use std::net::TcpListener;
+use std::net::{TcpListener, TcpStream};
 
 use crate::log::Logger;
+use crate::storage_format;
+use crate::thread_pool::{PoolError, ThreadPool};
 use std::sync::Arc;
+use std::time::Duration;
 
 use std::thread;
 use std::vec;
-/// Binds a `TcpListener` to all network interfaces (0.0.0.0) on the specified port.
+
+/// How long the listener waits for the request of a connection it is going to reject.
+const OVERLOADED_READ_TIMEOUT: Duration = Duration::from_secs(1);
+/// Binds a `TcpListener` to the listen address of the node (0.0.0.0, all network interfaces,
+/// by default) on the specified port.
 /// 
 /// # Arguments
 ///
+/// - `address`: The local address to bind to.
 /// - `port`: The port number on which the server will listen.
 /// - `protocol_type`: A string describing the protocol type (e.g., "native", "gossip").
 ///   This information is used for logging purposes.
@@ -23,8 +31,8 @@ use std::vec;
 /// Returns a `TcpListener` bound to the specified port. If binding fails, the function
 /// logs the error and exits the program.
 ///
-fn listen_on_all_interfaces(port: u16, protocol_type: &str) -> TcpListener {
-    let full_address = format!("0.0.0.0:{}", port); // 0.0.0.0 != localhost
+fn listen_on(address: &str, port: u16, protocol_type: &str) -> TcpListener {
+    let full_address = format!("{}:{}", address, port); // 0.0.0.0 != localhost
     match TcpListener::bind(&full_address) {
         Ok(listener) => {
             println!("Escuchando {} protocol en {}", protocol_type, &full_address);
@@ -46,38 +54,44 @@ fn listen_on_all_interfaces(port: u16, protocol_type: &str) -> TcpListener {
 
 pub fn start_node_gossip_query_protocol(node: Arc<Node>) {
     let port = node.get_port_gossip_query();
-    let nodes_listener = listen_on_all_interfaces(port, "internal");
+    let nodes_listener = listen_on(node.get_listen_address(), port, "internal");
+    serve_node_gossip_query_protocol(node, nodes_listener);
+}
 
+/// Handles the internal messages of the connections accepted by a listener already bound,
+/// e.g. to an ephemeral port.
+///
+/// #Parameters
+/// - `node`: The node that will handle the incoming messages.
+/// - `nodes_listener`: The listener of the gossip query port of the node.
+///
+pub fn serve_node_gossip_query_protocol(node: Arc<Node>, nodes_listener: TcpListener) {
     let _ = Logger::new(node.get_id());
+    let overload = node.get_overload_config();
+    let pool = ThreadPool::new(
+        &format!("{}-internal", node.get_id()),
+        overload.internal_worker_threads,
+        overload.internal_queue_capacity,
+    );
 
     for stream in nodes_listener.incoming() {
         match stream {
-            Ok(mut stream) => {
-             
-                InternalMessage::deserialize_from_stream(&mut stream)
-                    .map(|message| node.receive_internal_message(&message))
-                    .map(|response| match response {
-                        Ok(response) => {
-                            let result = InternalMessage::Response {
-                                opcode: 0,
-                                body: response,
-                            }
-                            .write_to_stream(&mut stream);
-                            if let Err(e) = result {
-                                eprintln!("Error al escribir en el stream: {}", e);
-                            }
-                        }
-                        Err(e) => {
-                            let result = InternalMessage::Response { opcode: 1, body: e }
-                                .write_to_stream(&mut stream);
-                            if let Err(e) = result {
-                                eprintln!("Error al escribir en el stream: {}", e);
-                            }
-                        }
-                    })
-                    .unwrap_or_else(|e| {
-                        eprintln!("Error al parsear el mensaje interno: {}", e);
-                    });
+            Ok(stream) => {
+                let Ok(rejected_stream) = stream.try_clone() else {
+                    eprintln!("Error al clonar la conexión interna");
+                    continue;
+                };
+                let node_clone = Arc::clone(&node);
+                match pool.try_execute(move || handle_internal_connection(stream, &node_clone)) {
+                    Ok(()) => {}
+                    Err(PoolError::Saturated) => {
+                        node.get_metrics().record_shed();
+                        reject_overloaded_connection(rejected_stream);
+                    }
+                    Err(PoolError::ShutDown) => {
+                        eprintln!("Error al encolar la conexión interna: el pool está cerrado");
+                    }
+                }
             }
             Err(e) => {
                 eprintln!("Error en la conexión: {}", e);
@@ -86,16 +100,83 @@ pub fn start_node_gossip_query_protocol(node: Arc<Node>) {
     }
 }
 
+/// Answers a connection with an `Overloaded` error, used when every internal worker is busy.
+/// The request is read first so the other node gets the response instead of a reset connection.
+///
+/// #Parameters
+/// - `stream`: The connection with the node that sent the message.
+///
+fn reject_overloaded_connection(mut stream: TcpStream) {
+    let _ = stream.set_read_timeout(Some(OVERLOADED_READ_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(OVERLOADED_READ_TIMEOUT));
+    let _ = InternalMessage::deserialize_from_stream(&mut stream);
+    let result = InternalMessage::Response {
+        opcode: 1,
+        body: "Overloaded".to_string(),
+    }
+    .write_to_stream(&mut stream);
+    if let Err(e) = result {
+        eprintln!("Error al escribir en el stream: {}", e);
+    }
+}
 
-/// Starts the native protocol listener for the node. 
+/// Reads an internal message from a connection, lets the node handle it and writes back the response.
+///
+/// #Parameters
+/// - `stream`: The connection with the node that sent the message.
+/// - `node`: The node that will handle the message.
+///
+fn handle_internal_connection(mut stream: TcpStream, node: &Node) {
+    InternalMessage::deserialize_from_stream(&mut stream)
+        .map(|message| node.receive_internal_message(&message))
+        .map(|response| match response {
+            Ok(response) => {
+                let result = InternalMessage::Response {
+                    opcode: 0,
+                    body: response,
+                }
+                .write_to_stream(&mut stream);
+                if let Err(e) = result {
+                    eprintln!("Error al escribir en el stream: {}", e);
+                }
+            }
+            Err(e) => {
+                let result = InternalMessage::Response { opcode: 1, body: e }
+                    .write_to_stream(&mut stream);
+                if let Err(e) = result {
+                    eprintln!("Error al escribir en el stream: {}", e);
+                }
+            }
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("Error al parsear el mensaje interno: {}", e);
+        });
+}
+
+
+/// Starts the native protocol listener for the node, once it finished warming up.
 ///
 /// #Parameters
 /// - `node`: The node that will handle the incoming messages.
 /// 
 pub fn start_node_native_protocol(node: Arc<Node>) {
+    // No se aceptan clientes hasta que el nodo termine el warm-up
+    while !node.is_ready() {
+        thread::sleep(std::time::Duration::from_millis(100));
+    }
     let port = node.get_port_native_protocol();
-    let client_listener = listen_on_all_interfaces(port, "native");
+    let client_listener = listen_on(node.get_listen_address(), port, "native");
+    serve_node_native_protocol(node, client_listener);
+}
 
+/// Handles the clients of the connections accepted by a listener already bound, e.g. to an
+/// ephemeral port.
+///
+/// #Parameters
+/// - `node`: The node that will handle the incoming messages.
+/// - `client_listener`: The listener of the native protocol port of the node.
+///
+pub fn serve_node_native_protocol(node: Arc<Node>, client_listener: TcpListener) {
     for stream in client_listener.incoming() {
         match stream {
             Ok(stream) => {
@@ -136,6 +217,126 @@ pub fn start_flush(node: Arc<Node>, interval_in_ms: u64) {
     });
 }
 
+/// Starts a thread that syncs to disk the files written with the periodic fsync policy every
+/// `interval_in_ms`.
+pub fn start_fsync(interval_in_ms: u64) {
+    thread::spawn(move || loop {
+        thread::sleep(std::time::Duration::from_millis(interval_in_ms));
+        if let Err(e) = storage_format::sync_written_files() {
+            eprintln!("Error syncing files to disk: {}", e);
+        }
+    });
+}
+
+/// Starts a thread that compacts the tables of the node every `interval_in_ms`.
+pub fn start_compaction(node: Arc<Node>, interval_in_ms: u64) {
+    thread::spawn(move || loop {
+        thread::sleep(std::time::Duration::from_millis(interval_in_ms));
+        node.compact();
+    });
+}
+
+/// Starts an HTTP listener that serves the metrics of the node at `/metrics`, in the
+/// Prometheus text format.
+///
+/// #Parameters
+/// - `node`: The node whose metrics are served.
+/// - `port`: The port of the HTTP listener.
+///
+pub fn start_metrics_endpoint(node: Arc<Node>, port: u16) {
+    start_http_endpoint(node, port, "metrics", metrics_http_response);
+}
+
+/// Starts an HTTP listener that serves the liveness of the node at `/health/live` and its
+/// readiness at `/health/ready`, so orchestrators and clients can wait for the node.
+///
+/// #Parameters
+/// - `node`: The node whose health is served.
+/// - `port`: The port of the HTTP listener.
+///
+pub fn start_health_endpoint(node: Arc<Node>, port: u16) {
+    start_http_endpoint(node, port, "health", health_http_response);
+}
+
+/// Starts a thread that answers each HTTP request on `port` with `respond`, which gets the
+/// first line of the request.
+fn start_http_endpoint(
+    node: Arc<Node>,
+    port: u16,
+    protocol: &str,
+    respond: fn(&Node, &str) -> String,
+) {
+    let listener = listen_on(node.get_listen_address(), port, protocol);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let _ = stream.set_read_timeout(Some(OVERLOADED_READ_TIMEOUT));
+            let mut request_line = String::new();
+            if BufReader::new(&stream).read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let response = respond(&node, &request_line);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+}
+
+/// Builds the HTTP response to a request for the metrics of the node.
+///
+/// #Parameters
+/// - `node`: The node whose metrics are served.
+/// - `request_line`: The first line of the HTTP request, e.g. `GET /metrics HTTP/1.1`.
+///
+fn metrics_http_response(node: &Node, request_line: &str) -> String {
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => match node.prometheus_metrics() {
+            Ok(page) => ("200 OK", "text/plain; version=0.0.4", page),
+            Err(e) => ("500 Internal Server Error", "text/plain", e),
+        },
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "Method not allowed\n".to_string()),
+    };
+    http_response(status, content_type, &body)
+}
+
+/// Builds the HTTP response to a health check. `/health/live` answers 200 while the node
+/// runs; `/health/ready` answers 503 until it loaded its data and joined the cluster.
+///
+/// #Parameters
+/// - `node`: The node whose health is served.
+/// - `request_line`: The first line of the HTTP request, e.g. `GET /health/ready HTTP/1.1`.
+///
+fn health_http_response(node: &Node, request_line: &str) -> String {
+    let health = node.health();
+    let body = serde_json::to_string(&health).unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/health/live")) => ("200 OK", "application/json", body),
+        (Some("GET"), Some("/health/ready")) if health.ready => {
+            ("200 OK", "application/json", body)
+        }
+        (Some("GET"), Some("/health/ready")) => {
+            ("503 Service Unavailable", "application/json", body)
+        }
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "Method not allowed\n".to_string()),
+    };
+    http_response(status, content_type, &body)
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
 /// Starts the native protocol listener for the node without using the native protocol.
 /// 
 /// #Parameters
@@ -221,710 +422,439 @@ pub fn start_node_native_protocol_without_native(node: Arc<Node>) {
     }
 }
 
-// Para estos test usamos los metodos de sin native protocol (para poder aislar las pruebas)
+// Los tests de varios nodos levantan un TestCluster dentro del proceso, con puertos efimeros
 #[cfg(test)]
 mod test_handler {
 
     use super::*;
-    use common::client_manager::ClientManager;
+    use common::client_manager::{ClientManager, ConsistencyLevel};
     use crate::node::GossipInformation;
+    use crate::test_cluster::{eventually, temp_storage, TestCluster};
+    use common::config::{ChaosConfig, FaultRule};
     use std::collections::HashMap;
     use std::net::TcpStream;
-    use std::vec;
 
     #[test]
-    fn creo_un_nodo_y_inserto_un_dato() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 10011, 10012));
-
-        let node1_clone = Arc::clone(&nodo1);
-        let node2_clone = Arc::clone(&nodo1);
-
-        thread::spawn(move || start_node_native_protocol_without_native(node1_clone));
-
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone));
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        let mut queries = vec![];
-
-        queries.push("CREATE KEYSPACE keyspace1 WITH REPLICATION = { 'class' : 'SimpleStrategy', 'replication_factor' : 1 };");
-        queries.push("USE keyspace1;");
-        queries.push("CREATE TABLE vuelos (id_flight INT, from_city TEXT, to_city TEXT, departure_time TEXT, PRIMARY KEY ((from_city), departure_time));");
-        queries.push("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '22');");
-
-        for query in queries {
-            if let Ok(mut stream) = TcpStream::connect("localhost:10011") {
-                stream.write_all(query.as_bytes()).unwrap();
-                println!("Envio la query");
-            } else {
-                println!("Error al conectar al nodo1.");
-            }
-            thread::sleep(std::time::Duration::from_millis(1000));
-        }
-
-        thread::sleep(std::time::Duration::from_millis(2000));
-
-        let mut values_vuelos1 = HashMap::new();
-        values_vuelos1.insert("id_flight".to_string(), "1010".to_string());
-        values_vuelos1.insert("from_city".to_string(), "Rio".to_string());
-        values_vuelos1.insert("to_city".to_string(), "Catamarca".to_string());
-        values_vuelos1.insert("departure_time".to_string(), "22".to_string());
+    fn metrics_endpoint_serves_prometheus_page() {
+        let node = Arc::new(Node::new_with_storage(
+            "NodoMetrics",
+            "localhost",
+            10101,
+            10102,
+            temp_storage("metrics_endpoint_serves_prometheus_page"),
+        ));
+        start_metrics_endpoint(Arc::clone(&node), 10103);
+
+        let mut stream = TcpStream::connect("localhost:10103").unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE aero_operation_duration_seconds histogram"));
+
+        assert!(metrics_http_response(&node, "GET / HTTP/1.1").starts_with("HTTP/1.1 404"));
+        assert!(metrics_http_response(&node, "POST /metrics HTTP/1.1").starts_with("HTTP/1.1 405"));
+    }
 
-        let node1_1 = nodo1.clone();
+    #[test]
+    fn health_endpoint_waits_for_readiness() {
+        let node = Arc::new(Node::new_with_storage(
+            "NodoHealth",
+            "localhost",
+            10104,
+            10105,
+            temp_storage("health_endpoint_waits_for_readiness"),
+        ));
+        node.start_joining();
+        start_health_endpoint(Arc::clone(&node), 10106);
+
+        let mut stream = TcpStream::connect("localhost:10106").unwrap();
+        stream.write_all(b"GET /health/ready HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"));
+        assert!(response.contains("\"gossip_joined\":false"));
+
+        assert!(health_http_response(&node, "GET /health/live HTTP/1.1").starts_with("HTTP/1.1 200"));
+        node.update_gossip_table(&[GossipInformation {
+            node_id: "NodoHealth2".to_string(),
+            ..node.get_gossip_table().unwrap()[0].clone()
+        }]);
+        assert!(health_http_response(&node, "GET /health/ready HTTP/1.1").starts_with("HTTP/1.1 200"));
+    }
 
-        node1_1.show();
+    /// Returns whether a node has a row with every one of `values`.
+    fn has_row(node: &Node, keyspace: &str, table: &str, values: &HashMap<String, String>) -> bool {
+        node.get_table(keyspace, table)
+            .map(|table| table.get_vector_of_rows())
+            .unwrap_or_default()
+            .iter()
+            .any(|row| {
+                values
+                    .iter()
+                    .all(|(key, value)| row.get(key) == Some(value))
+            })
+    }
 
-        let vector_rows = node1_1
-            .get_table("keyspace1", "vuelos")
-            .unwrap()
-            .get_vector_of_rows();
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
 
-        println!("Vector de rows: {:?}", vector_rows);
+    /// Creates `keyspace1.vuelos` from a client, waiting until every node of the cluster has the table.
+    fn create_vuelos(
+        cluster: &TestCluster,
+        client: &mut ClientManager,
+        replication_factor: usize,
+        primary_key: &str,
+    ) {
+        client
+            .query(
+                format!("CREATE KEYSPACE keyspace1 WITH REPLICATION = {{ 'class' : 'SimpleStrategy', 'replication_factor' : {} }};", replication_factor),
+                ConsistencyLevel::All,
+            )
+            .expect("Error al crear el keyspace");
+        client
+            .use_keyspace("keyspace1")
+            .expect("Error al usar el keyspace");
+        client
+            .query(
+                format!("CREATE TABLE vuelos (id_flight INT, from_city TEXT, to_city TEXT, departure_time TEXT, PRIMARY KEY {});", primary_key),
+                ConsistencyLevel::All,
+            )
+            .expect("Error al crear la tabla");
+        eventually("the table on every node", || {
+            cluster
+                .nodes()
+                .iter()
+                .all(|node| node.get_table("keyspace1", "vuelos").is_some())
+        });
+    }
 
-        assert!(vector_rows.contains(&values_vuelos1));
+    #[test]
+    fn creo_un_nodo_y_inserto_un_dato() {
+        let cluster = TestCluster::start(1);
+        let mut client = cluster.client();
+        create_vuelos(&cluster, &mut client, 1, "((from_city), departure_time)");
+
+        client.query("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '22');".to_string(), ConsistencyLevel::One)
+            .expect("Error al ejecutar insert");
+
+        let values_vuelos1 = values(&[
+            ("id_flight", "1010"),
+            ("from_city", "Rio"),
+            ("to_city", "Catamarca"),
+            ("departure_time", "22"),
+        ]);
+        assert!(has_row(
+            cluster.node(0),
+            "keyspace1",
+            "vuelos",
+            &values_vuelos1
+        ));
     }
 
     #[test]
     fn creo2_nodos_y_intercambian_datos_de_gossip() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 10021, 10022));
-        let nodo2 = Arc::new(Node::new("Nodo2", "localhost", 20021, 20022));
-
-        let node1_clone = Arc::clone(&nodo1);
-        let node1_clone2 = Arc::clone(&nodo1);
-
-        thread::spawn(move || start_node_gossip_query_protocol(node1_clone2));
-        thread::sleep(std::time::Duration::from_millis(1000));
-        thread::spawn(move || start_node_native_protocol_without_native(node1_clone));
-
-        let node2_clone = Arc::clone(&nodo2);
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone));
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        if let Ok(mut stream) = TcpStream::connect("localhost:10022") {
-            let gossip_table = match nodo2.get_gossip_table() {
-                Ok(gossip_table) => gossip_table,
-                Err(e) => {
-                    println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                    return;
-                }
-            };
-
-            let gossip_messsage = InternalMessage::Gossip {
-                opcode: 1,
-                body: serde_json::to_string(&gossip_table).unwrap(),
-            };
-
-            if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                println!("Error al enviar el mensaje de gossip:new node.");
-            }
-
-            let mut gossip_table_response = String::new();
-
-            match stream.read_to_string(&mut gossip_table_response) {
-                Ok(_) => {
-                    let start = gossip_table_response.find('[').unwrap_or(0);
-                    let gossip_table: Vec<GossipInformation> =
-                        serde_json::from_str(&gossip_table_response[start..]).unwrap();
-                    nodo2.update_gossip_table(&gossip_table);
-                }
-                Err(e) => {
-                    println!("Error al leer del stream o timeout: {}", e);
-                }
-            }
-        } else {
-            println!("Error al conectar al nodo1.");
-        }
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        let gossip_table_nodo1 = match nodo1.get_gossip_table() {
-            Ok(gossip_table) => gossip_table,
-            Err(e) => {
-                println!("Error al obtener la tabla de gossip del nodo1: {}", e);
-                return;
-            }
-        };
-
-        let gossip_table_nodo2 = match nodo2.get_gossip_table() {
-            Ok(gossip_table) => gossip_table,
-            Err(e) => {
-                println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                return;
-            }
-        };
+        let cluster = TestCluster::start(2);
 
+        let gossip_table_nodo1 = cluster.node(0).get_gossip_table().unwrap();
+        let gossip_table_nodo2 = cluster.node(1).get_gossip_table().unwrap();
+        assert_eq!(gossip_table_nodo1.len(), 2);
         assert_eq!(gossip_table_nodo1.len(), gossip_table_nodo2.len());
     }
 
     #[test]
     fn creo_5_nodos_y_aplico_gossip() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 10031, 10032));
-        let nodo2 = Arc::new(Node::new("Nodo2", "localhost", 20031, 20032));
-        let nodo3 = Arc::new(Node::new("Nodo3", "localhost", 30031, 30032));
-        let nodo4 = Arc::new(Node::new("Nodo4", "localhost", 40031, 40032));
-        let nodo5 = Arc::new(Node::new("Nodo5", "localhost", 50031, 50032));
-
-        let node1_clone = Arc::clone(&nodo1);
-        let node1_clone2 = Arc::clone(&nodo1);
-
-        thread::spawn(move || start_node_native_protocol_without_native(node1_clone));
-        thread::spawn(move || start_node_gossip_query_protocol(node1_clone2));
-
-        let node2_clone = Arc::clone(&nodo2);
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone));
-        let node3_clone = Arc::clone(&nodo3);
-        thread::spawn(move || start_node_gossip_query_protocol(node3_clone));
-        let node4_clone = Arc::clone(&nodo4);
-        thread::spawn(move || start_node_gossip_query_protocol(node4_clone));
-        let node5_clone = Arc::clone(&nodo5);
-        thread::spawn(move || start_node_gossip_query_protocol(node5_clone));
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        for node in [&nodo2, &nodo3, &nodo4, &nodo5] {
-            if let Ok(mut stream) = TcpStream::connect("localhost:10032") {
-                let gossip_table = match node.get_gossip_table() {
-                    Ok(gossip_table) => gossip_table,
-                    Err(e) => {
-                        println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                        return;
-                    }
-                };
-                let gossip_messsage = InternalMessage::Gossip {
-                    opcode: 1,
-                    body: serde_json::to_string(&gossip_table).unwrap(),
-                };
-
-                if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                    println!("Error al enviar el mensaje de gossip:new node.");
-                }
+        let cluster = TestCluster::start(5);
 
-                let mut gossip_table_response = String::new();
-                match stream.read_to_string(&mut gossip_table_response) {
-                    Ok(_) => {
-                        let start = gossip_table_response.find('[').unwrap_or(0);
-                        let gossip_table: Vec<GossipInformation> =
-                            serde_json::from_str(&gossip_table_response[start..]).unwrap();
-                        node.update_gossip_table(&gossip_table);
-                        println!("Llega al final");
-                    }
-                    Err(e) => {
-                        println!("Error al leer del stream o timeout: {}", e);
-                    }
-                }
-            } else {
-                println!("Error al conectar al nodo1.");
-            }
-        }
-        thread::sleep(std::time::Duration::from_millis(300));
-
-        for node in [
-            Arc::clone(&nodo1),
-            Arc::clone(&nodo2),
-            Arc::clone(&nodo3),
-            Arc::clone(&nodo4),
-            Arc::clone(&nodo5),
-        ] {
-            start_gossip(node, 1000);
+        for node in cluster.nodes() {
+            assert_eq!(node.get_gossip_table().unwrap().len(), 5);
         }
-        println!("Espero rondas de gossip");
-        thread::sleep(std::time::Duration::from_secs(5));
-
-        assert_eq!(
-            nodo1.get_gossip_table().unwrap().len(),
-            nodo2.get_gossip_table().unwrap().len()
-        );
-        assert_eq!(
-            nodo2.get_gossip_table().unwrap().len(),
-            nodo3.get_gossip_table().unwrap().len()
-        );
-        assert_eq!(
-            nodo4.get_gossip_table().unwrap().len(),
-            nodo5.get_gossip_table().unwrap().len()
-        );
     }
 
     #[test]
     fn realizo_un_insert_con_3_nodos() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 10041, 10042));
-        let nodo2 = Arc::new(Node::new("Nodo2", "localhost", 20041, 20042));
-        let nodo3 = Arc::new(Node::new("Nodo3", "localhost", 30041, 30042));
-
-        let node1_clone = Arc::clone(&nodo1);
-        let node1_clone2 = Arc::clone(&nodo1);
-
-        thread::spawn(move || start_node_native_protocol_without_native(node1_clone));
-        thread::spawn(move || start_node_gossip_query_protocol(node1_clone2));
-
-        let node2_clone = Arc::clone(&nodo2);
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone));
-
-        let node3_clone = Arc::clone(&nodo3);
-        let node3_2clone = Arc::clone(&nodo3);
-        thread::spawn(move || start_node_gossip_query_protocol(node3_clone));
-        thread::spawn(move || start_node_native_protocol_without_native(node3_2clone));
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        for node in vec![&nodo2, &nodo3].into_iter() {
-            if let Ok(mut stream) = TcpStream::connect("localhost:10042") {
-                let gossip_table = match node.get_gossip_table() {
-                    Ok(gossip_table) => gossip_table,
-                    Err(e) => {
-                        println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                        return;
-                    }
-                };
-
-                let gossip_messsage = InternalMessage::Gossip {
-                    opcode: 1,
-                    body: serde_json::to_string(&gossip_table).unwrap(),
-                };
-
-                if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                    println!("Error al enviar el mensaje de gossip:new node.");
-                }
-                let mut gossip_table_response = String::new();
-                match stream.read_to_string(&mut gossip_table_response) {
-                    Ok(_) => {
-                        let start = gossip_table_response.find('[').unwrap_or(0);
-                        let gossip_table: Vec<GossipInformation> =
-                            serde_json::from_str(&gossip_table_response[start..]).unwrap();
-                        node.update_gossip_table(&gossip_table);
-                    }
-                    Err(e) => {
-                        println!("Error al leer del stream o timeout: {}", e);
-                    }
-                }
-            } else {
-                println!("Error al conectar al nodo1.");
-            }
-        }
-        thread::sleep(std::time::Duration::from_millis(3000));
-
-        for node in [Arc::clone(&nodo1), Arc::clone(&nodo2), Arc::clone(&nodo3)] {
-            start_gossip(node, 1000);
+        let cluster = TestCluster::start(3);
+        let mut client = cluster.client();
+        create_vuelos(&cluster, &mut client, 3, "((from_city), departure_time)");
+
+        client.query("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '22');".to_string(), ConsistencyLevel::All)
+            .expect("Error al ejecutar insert");
+
+        let values_vuelos1 = values(&[
+            ("id_flight", "1010"),
+            ("from_city", "Rio"),
+            ("to_city", "Catamarca"),
+            ("departure_time", "22"),
+        ]);
+        // Con replication factor 3 cada nodo tiene la fila
+        for node in cluster.nodes() {
+            assert!(has_row(node, "keyspace1", "vuelos", &values_vuelos1));
         }
-        println!("Espero 3 segundos");
-        thread::sleep(std::time::Duration::from_secs(3));
-
-        let mut queries = vec![];
-        queries.push("CREATE KEYSPACE keyspace1 WITH REPLICATION = { 'class' : 'SimpleStrategy', 'replication_factor' : 3 };");
-        queries.push("USE keyspace1;");
-        queries.push("CREATE TABLE vuelos (id_flight INT, from_city TEXT, to_city TEXT, departure_time TEXT, PRIMARY KEY ((from_city), departure_time));");
-        queries.push("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '22');");
-
-        for query in queries {
-            if let Ok(mut stream) = TcpStream::connect("localhost:30041") {
-                stream.write_all(query.as_bytes()).unwrap();
-                println!("Envio la query");
-            } else {
-                println!("Error al conectar al nodo1.");
-            }
-            thread::sleep(std::time::Duration::from_millis(1000));
-        }
-
-        thread::sleep(std::time::Duration::from_millis(2000));
-
-        let mut values_vuelos1 = HashMap::new();
-        values_vuelos1.insert("id_flight".to_string(), "1010".to_string());
-        values_vuelos1.insert("from_city".to_string(), "Rio".to_string());
-        values_vuelos1.insert("to_city".to_string(), "Catamarca".to_string());
-        values_vuelos1.insert("departure_time".to_string(), "22".to_string());
-
-        let node1_1 = nodo1.clone();
-
-        assert!(node1_1
-            .get_table("keyspace1", "vuelos")
-            .unwrap()
-            .get_vector_of_rows()
-            .contains(&values_vuelos1));
     }
 
     #[test]
     fn realizo_varios_insert_con_datos_similares_en_3_nodos_y_piso_los_datos() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 10051, 10052));
-        let nodo2 = Arc::new(Node::new("Nodo2", "localhost", 20051, 20052));
-        let nodo3 = Arc::new(Node::new("Nodo3", "localhost", 30051, 30052));
-
-        let node1_clone = Arc::clone(&nodo1);
-        thread::spawn(move || start_node_gossip_query_protocol(node1_clone));
-        let node2_clone = Arc::clone(&nodo2);
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone));
-
-        let node3_clone = Arc::clone(&nodo3);
-        let node3_clone_native = Arc::clone(&nodo3);
-        thread::spawn(move || start_node_native_protocol_without_native(node3_clone_native));
-
-        thread::spawn(move || start_node_gossip_query_protocol(node3_clone));
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        for node in vec![&nodo2, &nodo3].into_iter() {
-            if let Ok(mut stream) = TcpStream::connect("localhost:10052") {
-                let gossip_table = match node.get_gossip_table() {
-                    Ok(gossip_table) => gossip_table,
-                    Err(e) => {
-                        println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                        return;
-                    }
-                };
-
-                let gossip_messsage = InternalMessage::Gossip {
-                    opcode: 1,
-                    body: serde_json::to_string(&gossip_table).unwrap(),
-                };
-
-                if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                    println!("Error al enviar el mensaje de gossip:new node.");
-                }
-
-                let mut gossip_table_response = String::new();
-                match stream.read_to_string(&mut gossip_table_response) {
-                    Ok(_) => {
-                        let start = gossip_table_response.find('[').unwrap_or(0);
-                        let gossip_table: Vec<GossipInformation> =
-                            serde_json::from_str(&gossip_table_response[start..]).unwrap();
-                        node.update_gossip_table(&gossip_table);
-                    }
-                    Err(e) => {
-                        println!("Error al leer del stream o timeout: {}", e);
-                    }
-                }
-            } else {
-                println!("Error al conectar al nodo1.");
-            }
-        }
-        thread::sleep(std::time::Duration::from_millis(300));
-
-        for node in [Arc::clone(&nodo1), Arc::clone(&nodo2), Arc::clone(&nodo3)] {
-            start_gossip(node, 1000);
-        }
-        println!("Espero 3 segundos");
-        thread::sleep(std::time::Duration::from_secs(3));
-
-        //Ojo que aca la primarykey = (from_city, departure_time) = ()
-        let mut queries = vec![];
-        queries.push("CREATE KEYSPACE keyspace1 WITH REPLICATION = { 'class' : 'SimpleStrategy', 'replication_factor' : 1 };");
-        queries.push("USE keyspace1;");
-        queries.push("CREATE TABLE vuelos (id_flight INT, from_city TEXT, to_city TEXT, departure_time TEXT, PRIMARY KEY ((from_city), departure_time));");
-        queries.push("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '22');");
-        queries.push("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Jujuy', '22');");
-
-        for query in queries {
-            if let Ok(mut stream) = TcpStream::connect("localhost:30051") {
-                stream.write_all(query.as_bytes()).unwrap();
-                println!("Envio la query");
-            } else {
-                println!("Error al conectar al nodo1.");
-            }
-            thread::sleep(std::time::Duration::from_millis(1000));
+        let cluster = TestCluster::start(3);
+        let mut client = cluster.client();
+        //Ojo que aca la primarykey = (from_city, departure_time)
+        create_vuelos(&cluster, &mut client, 1, "((from_city), departure_time)");
+
+        for query in [
+            "INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '22');",
+            "INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Jujuy', '22');",
+        ] {
+            client.query(query.to_string(), ConsistencyLevel::One).expect("Error al ejecutar insert");
         }
 
-        thread::sleep(std::time::Duration::from_millis(2000));
-
-        let mut values_vuelos1 = HashMap::new();
-        values_vuelos1.insert("id_flight".to_string(), "1010".to_string());
-        values_vuelos1.insert("from_city".to_string(), "Rio".to_string());
-        values_vuelos1.insert("to_city".to_string(), "Catamarca".to_string());
-        values_vuelos1.insert("departure_time".to_string(), "22".to_string());
-
-        let mut values_vuelos2 = HashMap::new();
-        values_vuelos2.insert("id_flight".to_string(), "1010".to_string());
-        values_vuelos2.insert("from_city".to_string(), "Rio".to_string());
-        values_vuelos2.insert("to_city".to_string(), "Jujuy".to_string());
-        values_vuelos2.insert("departure_time".to_string(), "22".to_string());
-
-        let node1_1 = nodo1.clone();
+        let values_vuelos1 = values(&[
+            ("from_city", "Rio"),
+            ("to_city", "Catamarca"),
+            ("departure_time", "22"),
+        ]);
+        let values_vuelos2 = values(&[
+            ("from_city", "Rio"),
+            ("to_city", "Jujuy"),
+            ("departure_time", "22"),
+        ]);
 
         //Como el realice un insert con mismo id y partition key y clustering key se pisa el valor
-        assert!(!node1_1
-            .get_table("keyspace1", "vuelos")
-            .unwrap()
-            .get_vector_of_rows()
-            .contains(&values_vuelos1));
-
-        assert!(node1_1
-            .get_table("keyspace1", "vuelos")
-            .unwrap()
-            .get_vector_of_rows()
-            .contains(&values_vuelos2));
-
-        node1_1.show();
+        eventually("the row of Jujuy on its replica", || {
+            cluster
+                .nodes()
+                .iter()
+                .any(|node| has_row(node, "keyspace1", "vuelos", &values_vuelos2))
+        });
+        assert!(!cluster.nodes().iter().any(|node| has_row(
+            node,
+            "keyspace1",
+            "vuelos",
+            &values_vuelos1
+        )));
     }
 
     #[test]
     fn realizo_varios_inserts_y_compurebo_los_datos_con_un_select() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 10061, 10062));
-        let nodo2 = Arc::new(Node::new("Nodo2", "localhost", 20061, 20062));
-        let nodo3 = Arc::new(Node::new("Nodo3", "localhost", 30061, 30062));
-
-        let node1_clone = Arc::clone(&nodo1);
-
-        thread::spawn(move || start_node_gossip_query_protocol(node1_clone));
-        let node2_clone = Arc::clone(&nodo2);
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone));
-
-        let node3_clone = Arc::clone(&nodo3);
-        let node3_clone_native = Arc::clone(&nodo3);
-        thread::spawn(move || start_node_native_protocol_without_native(node3_clone_native));
-        thread::spawn(move || start_node_gossip_query_protocol(node3_clone));
-
-        thread::sleep(std::time::Duration::from_millis(1000));
+        let cluster = TestCluster::start(3);
+        let mut client = cluster.client();
+        create_vuelos(&cluster, &mut client, 1, "((id_flight), from_city)");
 
-        for node in vec![&nodo2, &nodo3].into_iter() {
-            if let Ok(mut stream) = TcpStream::connect("localhost:10062") {
-                let gossip_table = match node.get_gossip_table() {
-                    Ok(gossip_table) => gossip_table,
-                    Err(e) => {
-                        println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                        return;
-                    }
-                };
-
-                let gossip_messsage = InternalMessage::Gossip {
-                    opcode: 1,
-                    body: serde_json::to_string(&gossip_table).unwrap(),
-                };
-
-                if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                    println!("Error al enviar el mensaje de gossip:new node.");
-                }
-
-                let mut gossip_table_response = String::new();
-                match stream.read_to_string(&mut gossip_table_response) {
-                    Ok(_) => {
-                        let start = gossip_table_response.find('[').unwrap_or(0);
-                        let gossip_table: Vec<GossipInformation> =
-                            serde_json::from_str(&gossip_table_response[start..]).unwrap();
-                        node.update_gossip_table(&gossip_table);
-                    }
-                    Err(e) => {
-                        println!("Error al leer del stream o timeout: {}", e);
-                    }
-                }
-            } else {
-                println!("Error al conectar al nodo1.");
-            }
+        for query in [
+            "INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '21');",
+            "INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1011, 'Bariloche', 'Chubut', '22');",
+        ] {
+            client.query(query.to_string(), ConsistencyLevel::One).expect("Error al ejecutar insert");
         }
-        thread::sleep(std::time::Duration::from_millis(1000));
 
-        for node in [Arc::clone(&nodo1), Arc::clone(&nodo2), Arc::clone(&nodo3)] {
-            start_gossip(node, 1000);
+        for (id_flight, expected) in [
+            (
+                "1010",
+                values(&[
+                    ("id_flight", "1010"),
+                    ("from_city", "Rio"),
+                    ("to_city", "Catamarca"),
+                    ("departure_time", "21"),
+                ]),
+            ),
+            (
+                "1011",
+                values(&[
+                    ("id_flight", "1011"),
+                    ("from_city", "Bariloche"),
+                    ("to_city", "Chubut"),
+                    ("departure_time", "22"),
+                ]),
+            ),
+        ] {
+            let rows = client
+                .query_rows(
+                    format!("SELECT * FROM vuelos WHERE id_flight = '{}';", id_flight),
+                    ConsistencyLevel::One,
+                )
+                .expect("Error al ejecutar select");
+            assert!(
+                rows.iter().any(|row| expected
+                    .iter()
+                    .all(|(key, value)| row.get::<String>(key).is_ok_and(|v| v == *value))),
+                "El select de {} no devolvio {:?}: {:?}",
+                id_flight,
+                expected,
+                rows
+            );
         }
+    }
 
-        println!("Espero 3 segundos");
-        thread::sleep(std::time::Duration::from_secs(5));
-
-        //Ojo que aca la primarykey = (from_city, departure_time) = ()
-        //mirar comunicacion entre nodos por que creo que falla cuando R.F = 1 (no esta agarrando bien los nodos)
-        let mut queries = vec![];
-        queries.push("CREATE KEYSPACE keyspace1 WITH REPLICATION = { 'class' : 'SimpleStrategy', 'replication_factor' : 1};");
-        queries.push("USE keyspace1;");
-        queries.push("CREATE TABLE vuelos (id_flight INT, from_city TEXT, to_city TEXT, departure_time TEXT, PRIMARY KEY ((id_flight), from_city));");
-        queries.push("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '21');");
-        queries.push("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1011, 'Bariloche', 'Chubut', '22');");
-
-        for query in queries {
-            if let Ok(mut stream) = TcpStream::connect("localhost:30061") {
-                stream.write_all(query.as_bytes()).unwrap();
-                println!("Envio la query");
-            } else {
-                println!("Error al conectar al nodo1.");
-            }
-            thread::sleep(std::time::Duration::from_millis(1000));
-        }
+    #[test]
+    fn los_mensajes_perdidos_quedan_como_hints() {
+        let cluster = TestCluster::start(2);
+        let (coordinator, replica) = (cluster.node(0), cluster.node(1));
+        let mut client = ClientManager::new(&cluster.native_addresses()[..1]).unwrap();
+        create_vuelos(&cluster, &mut client, 2, "((from_city), departure_time)");
+
+        // El coordinador pierde todo lo que le manda a la otra replica
+        coordinator.set_faults(&ChaosConfig {
+            enabled: true,
+            seed: Some(1),
+            faults: vec![FaultRule {
+                to: replica.get_id().to_string(),
+                drop: 1.0,
+                ..FaultRule::default()
+            }],
+        });
+        let insert = "INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '22');";
+        assert!(client
+            .query(insert.to_string(), ConsistencyLevel::All)
+            .is_err());
+        client
+            .query(insert.to_string(), ConsistencyLevel::One)
+            .expect("Error al ejecutar insert");
+
+        let values_vuelos1 = values(&[("from_city", "Rio"), ("to_city", "Catamarca")]);
+        assert!(has_row(coordinator, "keyspace1", "vuelos", &values_vuelos1));
+        assert!(!has_row(replica, "keyspace1", "vuelos", &values_vuelos1));
+        let peers = coordinator.get_metrics().snapshot().peers;
+        assert!(peers[replica.get_id()].hint_fallbacks >= 1);
+    }
 
-        thread::sleep(std::time::Duration::from_millis(2000));
-
-        let mut values_vuelos1 = HashMap::new();
-        values_vuelos1.insert("id_flight".to_string(), "1010".to_string());
-        values_vuelos1.insert("from_city".to_string(), "Rio".to_string());
-        values_vuelos1.insert("to_city".to_string(), "Catamarca".to_string());
-        values_vuelos1.insert("departure_time".to_string(), "21".to_string());
-
-        let mut select_values: Vec<HashMap<String, String>> = vec![];
-        let query = "SELECT * FROM vuelos WHERE id_flight = '1010';";
-        if let Ok(mut stream) = TcpStream::connect("localhost:30061") {
-            stream.write_all(query.as_bytes()).unwrap();
-            println!("Envio la query");
-            let mut buffer = vec![0; 8000]; // Inicializa un buffer vacío
-            stream.read(&mut buffer).unwrap();
-            let response = String::from_utf8_lossy(&buffer).to_string();
-            let mut string = String::new();
-            for c in response.chars() {
-                if c != '\0' {
-                    string.push(c);
-                }
-            }
-            select_values = serde_json::from_str(&string).unwrap();
-        } else {
-            println!("Error al conectar al nodo1.");
-        }
-        thread::sleep(std::time::Duration::from_millis(2000));
-
-        assert!(select_values.contains(&values_vuelos1));
-
-        let mut values_vuelos2 = HashMap::new();
-        values_vuelos2.insert("id_flight".to_string(), "1011".to_string());
-        values_vuelos2.insert("from_city".to_string(), "Bariloche".to_string());
-        values_vuelos2.insert("to_city".to_string(), "Chubut".to_string());
-        values_vuelos2.insert("departure_time".to_string(), "22".to_string());
-
-        let mut select_values: Vec<HashMap<String, String>> = vec![];
-        let query = "SELECT * FROM vuelos WHERE id_flight = '1011';";
-        if let Ok(mut stream) = TcpStream::connect("localhost:30061") {
-            stream.write_all(query.as_bytes()).unwrap();
-            println!("Envio la query");
-            let mut buffer = vec![0; 8000]; // Inicializa un buffer vacío
-            stream.read(&mut buffer).unwrap();
-            let response = String::from_utf8_lossy(&buffer).to_string();
-            let mut string = String::new();
-            for c in response.chars() {
-                if c != '\0' {
-                    string.push(c);
-                }
-            }
-            select_values = serde_json::from_str(&string).unwrap();
-        } else {
-            println!("Error al conectar al nodo1.");
+    #[test]
+    fn las_paginas_de_un_select_siguen_desde_la_ultima_fila() {
+        let cluster = TestCluster::start(3);
+        let mut client = cluster.client();
+        create_vuelos(&cluster, &mut client, 2, "((id_flight), from_city)");
+        for id_flight in 1..=25 {
+            client
+                .query(
+                    format!("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES ({}, 'Rio', 'Catamarca', '22');", id_flight),
+                    ConsistencyLevel::All,
+                )
+                .expect("Error al ejecutar insert");
         }
-        thread::sleep(std::time::Duration::from_millis(2000));
 
-        assert!(select_values.contains(&values_vuelos2));
+        let ids = |client: &mut ClientManager, query: &str| -> Vec<i32> {
+            let mut ids: Vec<i32> = client
+                .query_iter(query.to_string(), ConsistencyLevel::Quorum, 4)
+                .map(|row| row.unwrap().get::<i32>("id_flight").unwrap())
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&mut client, "SELECT * FROM vuelos;"), (1..=25).collect::<Vec<_>>());
+        // El LIMIT cuenta las filas de todas las páginas
+        assert_eq!(ids(&mut client, "SELECT * FROM vuelos LIMIT 10;").len(), 10);
     }
 
     #[test]
-    fn test_read_repair() {
-        let node1 = Arc::new(Node::new("Node1", "localhost", 10071, 10072));
-        let node1_clone1 = Arc::clone(&node1);
-        let node1_clone2 = Arc::clone(&node1);
-        thread::spawn(move || start_node_native_protocol(node1_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node1_clone2));
-        let node1_clone3 = Arc::clone(&node1);
-        start_gossip(node1_clone3, 1000);
-
-        let node2 = Arc::new(Node::new("Node2", "localhost", 20071, 20072));
-        let node2_clone1 = Arc::clone(&node2);
-        let node2_clone2 = Arc::clone(&node2);
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        if let Ok(mut stream) = TcpStream::connect("localhost:10072") {
-            let gossip_table = match node2.get_gossip_table() {
-                Ok(gossip_table) => gossip_table,
-                Err(e) => {
-                    println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                    return;
-                }
-            };
-
-            let gossip_messsage = InternalMessage::Gossip {
-                opcode: 1,
-                body: serde_json::to_string(&gossip_table).unwrap(),
-            };
-
-            if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                println!("Error al enviar el mensaje de gossip:new node.");
-            }
-
-            let mut gossip_table_response = String::new();
-
-            match stream.read_to_string(&mut gossip_table_response) {
-                Ok(_) => {
-                    println!(
-                        "Gossip table recibido por nodo 2: {}",
-                        gossip_table_response
-                    );
-                    let start = gossip_table_response.find('[').unwrap_or(0);
-                    let gossip_table: Vec<GossipInformation> =
-                        serde_json::from_str(&gossip_table_response[start..]).unwrap();
-                    node2.update_gossip_table(&gossip_table);
-                }
-                Err(e) => {
-                    println!("Error al leer del stream o timeout: {}", e);
-                }
-            }
-        } else {
-            println!("Error al conectar al nodo1.");
+    fn un_select_sin_partition_key_lee_cada_rango_de_sus_replicas() {
+        let cluster = TestCluster::start(3);
+        let coordinator = cluster.node(0);
+        let mut client = ClientManager::new(&cluster.native_addresses()[..1]).unwrap();
+        create_vuelos(&cluster, &mut client, 2, "((id_flight), from_city)");
+        for id_flight in 1..=6 {
+            client
+                .query(
+                    format!("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES ({}, 'Rio', 'Catamarca', '22');", id_flight),
+                    ConsistencyLevel::All,
+                )
+                .expect("Error al ejecutar insert");
         }
 
-        thread::spawn(move || start_node_native_protocol(node2_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone2));
-        let node2_clone3 = Arc::clone(&node2);
-        start_gossip(node2_clone3, 1000);
-
-        let node3 = Arc::new(Node::new("Node3", "localhost", 30071, 30072));
-        let node3_clone1 = Arc::clone(&node3);
-        let node3_clone2 = Arc::clone(&node3);
-
-        if let Ok(mut stream) = TcpStream::connect("localhost:10072") {
-            let gossip_table = match node3.get_gossip_table() {
-                Ok(gossip_table) => gossip_table,
-                Err(e) => {
-                    println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                    return;
-                }
-            };
-
-            let gossip_messsage = InternalMessage::Gossip {
-                opcode: 1,
-                body: serde_json::to_string(&gossip_table).unwrap(),
-            };
-
-            if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                println!("Error al enviar el mensaje de gossip:new node.");
-            }
+        // El coordinador no llega a un nodo que gossip todavia no marco como muerto
+        coordinator.set_faults(&ChaosConfig {
+            enabled: true,
+            seed: Some(1),
+            faults: vec![FaultRule {
+                to: cluster.node(2).get_id().to_string(),
+                drop: 1.0,
+                ..FaultRule::default()
+            }],
+        });
+        // Cada rango tiene otra replica que responde
+        let rows = client
+            .query_rows("SELECT * FROM vuelos;".to_string(), ConsistencyLevel::One)
+            .expect("Error al ejecutar select");
+        assert_eq!(rows.len(), 6);
+        assert!(client
+            .query_rows("SELECT * FROM vuelos;".to_string(), ConsistencyLevel::All)
+            .is_err());
+    }
 
-            let mut gossip_table_response = String::new();
-
-            match stream.read_to_string(&mut gossip_table_response) {
-                Ok(_) => {
-                    println!(
-                        "Gossip table recibido por nodo 3: {}",
-                        gossip_table_response
-                    );
-                    let gossip_table: Vec<GossipInformation> =
-                        serde_json::from_str(&gossip_table_response).unwrap();
-                    node3.update_gossip_table(&gossip_table);
-                }
-                Err(e) => {
-                    println!("Error al leer del stream o timeout: {}", e);
-                }
-            }
-        } else {
-            println!("Error al conectar al nodo1.");
+    #[test]
+    fn un_contador_cuyo_lider_no_responde_lo_lidera_otra_replica() {
+        let cluster = TestCluster::start(3);
+        let coordinator = cluster.node(0);
+        let mut client = ClientManager::new(&cluster.native_addresses()[..1]).unwrap();
+        client
+            .query(
+                "CREATE KEYSPACE keyspace1 WITH REPLICATION = { 'class' : 'SimpleStrategy', 'replication_factor' : 2 };".to_string(),
+                ConsistencyLevel::All,
+            )
+            .expect("Error al crear el keyspace");
+        client
+            .use_keyspace("keyspace1")
+            .expect("Error al usar el keyspace");
+        client
+            .query(
+                "CREATE TABLE asientos (id_flight INT, clase TEXT, vendidos COUNTER, PRIMARY KEY ((id_flight), clase));"
+                    .to_string(),
+                ConsistencyLevel::All,
+            )
+            .expect("Error al crear la tabla");
+        eventually("the table on every node", || {
+            cluster
+                .nodes()
+                .iter()
+                .all(|node| node.get_table("keyspace1", "asientos").is_some())
+        });
+
+        // El coordinador no llega a un nodo que gossip todavia no marco como muerto
+        coordinator.set_faults(&ChaosConfig {
+            enabled: true,
+            seed: Some(1),
+            faults: vec![FaultRule {
+                to: cluster.node(1).get_id().to_string(),
+                drop: 1.0,
+                ..FaultRule::default()
+            }],
+        });
+        for id_flight in 1..=10 {
+            client
+                .query(
+                    format!("UPDATE asientos SET vendidos = vendidos + 1 WHERE id_flight = {} AND clase = 'turista';", id_flight),
+                    ConsistencyLevel::One,
+                )
+                .expect("Error al ejecutar update");
+        }
+
+        coordinator.set_faults(&ChaosConfig::default());
+        for id_flight in 1..=10 {
+            let rows = client
+                .query_rows(
+                    format!("SELECT * FROM asientos WHERE id_flight = {};", id_flight),
+                    ConsistencyLevel::All,
+                )
+                .expect("Error al ejecutar select");
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].get::<i64>("vendidos").unwrap(), 1);
         }
+    }
 
-        thread::spawn(move || start_node_native_protocol(node3_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node3_clone2));
-        let node3_clone3 = Arc::clone(&node3);
-        start_gossip(node3_clone3, 1000);
-        thread::sleep(std::time::Duration::from_millis(3000));
-
-        let addresses: Vec<String> =
-            vec!["localhost:10071".to_string(), "localhost:20071".to_string()];
-        let mut client_manager = match ClientManager::new(&addresses) {
-            Ok(client_manager) => client_manager,
-            Err(e) => {
-                eprintln!("Error al crear el client manager: {:?}", e);
-                return;
-            }
-        };
-
-        client_manager
+    /// Creates `flights_keyspace.flight_status_by_origin` from a client, waiting until every node has the table, and inserts a flight.
+    fn create_flight_status(cluster: &TestCluster, client: &mut ClientManager, insert: &str) {
+        client
             .query(
                 "CREATE KEYSPACE flights_keyspace
                 WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 3};"
                     .to_string(),
-                "ONE",
+                ConsistencyLevel::All,
             )
             .expect("Error al ejecutar la query");
-
-        client_manager
-            .query("USE flights_keyspace;".to_string(), "ONE")
+        client
+            .use_keyspace("flights_keyspace")
             .expect("Error al ejecutar la query");
-
-        client_manager
+        client
             .query(
                 "CREATE TABLE flight_status_by_origin (
                     flight_id BIGINT,
@@ -940,534 +870,132 @@ mod test_handler {
                     PRIMARY KEY ((origin_airport_id), departure_time)
                 );"
                 .to_string(),
-                "ONE",
+                ConsistencyLevel::All,
             )
             .expect("Error al ejecutar create table");
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-        client_manager.query("INSERT INTO flight_status_by_origin
-                (flight_id, origin_airport_id, destination_airport_id, departure_time, arrival_time, status)
-                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time');".to_string(),"QUORUM")
-                .expect("Error al ejecutar insert");
-
-        thread::sleep(std::time::Duration::from_millis(3000));
-
-        let values: HashMap<String, String> = vec![
-            ("flight_id".to_string(), "10001".to_string()),
-            ("origin_airport_id".to_string(), "20".to_string()),
-            ("destination_airport_id".to_string(), "8888".to_string()),
-            (
-                "departure_time".to_string(),
-                "2024-09-27 09:00:00".to_string(),
-            ),
-            (
-                "arrival_time".to_string(),
-                "2024-09-27 18:00:00".to_string(),
-            ),
-            ("status".to_string(), "new status".to_string()),
-            (
-                "_timestamp".to_string(),
-                "2030-01-01 12:00:00".to_string(),
-            )
-        ]
-        .into_iter()
-        .collect();
-
-        let keyspace_name = "flights_keyspace".to_string();
-        let table_name = "flight_status_by_origin".to_string();
-
-        let _ = node3.insert_row(&keyspace_name, &table_name, values.clone());
-
-        match client_manager.query(
-            "SELECT * FROM flight_status_by_origin WHERE origin_airport_id = '20' ;".to_string(),
-            "ALL",
-        ) {
-            Ok(response) => {
-                println!("Response: {:?}", response);
-            }
-            Err(e) => {
-                eprintln!("Error al ejecutar la query: {:?}", e);
-            }
-        }
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        //Deberian tener todos le vector actualizado
-
-        // print vector de rows de cada nodo
-        for node in [&node1, &node2, &node3] {
-            println!("Node: {}", node.get_id());
-            for row in node
-                .get_table(&keyspace_name, &table_name)
-                .unwrap()
-                .get_vector_of_rows()
-            {
-                println!("{:?}", row);
-            }
-        }
-
-        for (key, value) in &values {
-            assert!(node2
-                .get_table(&keyspace_name, &table_name)
-                .unwrap()
-                .get_vector_of_rows()
-                .iter()
-                .any(|row| row.get(key).map(|v| v == value).unwrap_or(false)), 
-                "node2: El valor '{value}' para '{key}' no coincide con el esperado."
-            );
-        
-            assert!(node1
-                .get_table(&keyspace_name, &table_name)
-                .unwrap()
-                .get_vector_of_rows()
-                .iter()
-                .any(|row| row.get(key).map(|v| v == value).unwrap_or(false)),
-                "node1: El valor '{value}' para '{key}' no coincide con el esperado."
-            );
-        }
+        eventually("the table on every node", || {
+            cluster.nodes().iter().all(|node| {
+                node.get_table("flights_keyspace", "flight_status_by_origin")
+                    .is_some()
+            })
+        });
+        client
+            .query(insert.to_string(), ConsistencyLevel::All)
+            .expect("Error al ejecutar insert");
     }
 
     #[test]
-    fn test_update() {
-        let node1 = Arc::new(Node::new("Node1", "localhost", 10081, 10082));
-        let node1_clone1 = Arc::clone(&node1);
-        let node1_clone2 = Arc::clone(&node1);
-        thread::spawn(move || start_node_native_protocol(node1_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node1_clone2));
-        let node1_clone3 = Arc::clone(&node1);
-        start_gossip(node1_clone3, 1000);
-
-        let node2 = Arc::new(Node::new("Node2", "localhost", 20081, 20082));
-        let node2_clone1 = Arc::clone(&node2);
-        let node2_clone2 = Arc::clone(&node2);
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        if let Ok(mut stream) = TcpStream::connect("localhost:10082") {
-            let gossip_table = match node2.get_gossip_table() {
-                Ok(gossip_table) => gossip_table,
-                Err(e) => {
-                    println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                    return;
-                }
-            };
-
-            let gossip_messsage = InternalMessage::Gossip {
-                opcode: 1,
-                body: serde_json::to_string(&gossip_table).unwrap(),
-            };
-
-            if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                println!("Error al enviar el mensaje de gossip:new node.");
-            }
-
-            let mut gossip_table_response = String::new();
-
-            match stream.read_to_string(&mut gossip_table_response) {
-                Ok(_) => {
-                    println!(
-                        "Gossip table recibido por nodo 2: {}",
-                        gossip_table_response
-                    );
-                    let start = gossip_table_response.find('[').unwrap_or(0);
-                    let gossip_table: Vec<GossipInformation> =
-                        serde_json::from_str(&gossip_table_response[start..]).unwrap();
-                    node2.update_gossip_table(&gossip_table);
-                }
-                Err(e) => {
-                    println!("Error al leer del stream o timeout: {}", e);
-                }
-            }
-        } else {
-            println!("Error al conectar al nodo1.");
-        }
-
-        thread::spawn(move || start_node_native_protocol(node2_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone2));
-
-        let node2_clone3 = Arc::clone(&node2);
-        start_gossip(node2_clone3, 1000);
-
-        let node3 = Arc::new(Node::new("Node3", "localhost", 30081, 30082));
-        let node3_clone1 = Arc::clone(&node3);
-        let node3_clone2 = Arc::clone(&node3);
-
-        if let Ok(mut stream) = TcpStream::connect("localhost:10082") {
-            let gossip_table = match node3.get_gossip_table() {
-                Ok(gossip_table) => gossip_table,
-                Err(e) => {
-                    println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                    return;
-                }
-            };
-            let gossip_messsage = InternalMessage::Gossip {
-                opcode: 1,
-                body: serde_json::to_string(&gossip_table).unwrap(),
-            };
-
-            if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                println!("Error al enviar el mensaje de gossip:new node.");
-            }
-
-            let mut gossip_table_response = String::new();
-
-            match stream.read_to_string(&mut gossip_table_response) {
-                Ok(_) => {
-                    println!(
-                        "Gossip table recibido por nodo 3: {}",
-                        gossip_table_response
-                    );
-                    let gossip_table: Vec<GossipInformation> =
-                        serde_json::from_str(&gossip_table_response).unwrap();
-                    node3.update_gossip_table(&gossip_table);
-                }
-                Err(e) => {
-                    println!("Error al leer del stream o timeout: {}", e);
-                }
-            }
-        } else {
-            println!("Error al conectar al nodo1.");
-        }
-
-        thread::spawn(move || start_node_native_protocol(node3_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node3_clone2));
-        let node3_clone3 = Arc::clone(&node3);
-        start_gossip(node3_clone3, 1000);
-        thread::sleep(std::time::Duration::from_millis(3000));
-
-        let addresses: Vec<String> =
-            vec!["localhost:10081".to_string(), "localhost:20081".to_string()];
-        let mut client_manager = match ClientManager::new(&addresses) {
-            Ok(client_manager) => client_manager,
-            Err(e) => {
-                eprintln!("Error al crear el client manager: {:?}", e);
-                return;
-            }
-        };
-
-        client_manager
+    fn test_read_repair() {
+        let cluster = TestCluster::start(3);
+        let mut client = cluster.client();
+        create_flight_status(&cluster, &mut client, "INSERT INTO flight_status_by_origin
+                (flight_id, origin_airport_id, destination_airport_id, departure_time, arrival_time, status)
+                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time');");
+
+        let values = values(&[
+            ("flight_id", "10001"),
+            ("origin_airport_id", "20"),
+            ("destination_airport_id", "8888"),
+            ("departure_time", "2024-09-27 09:00:00"),
+            ("arrival_time", "2024-09-27 18:00:00"),
+            ("status", "new status"),
+            ("_timestamp", "2030-01-01 12:00:00"),
+        ]);
+        let keyspace_name = "flights_keyspace";
+        let table_name = "flight_status_by_origin";
+        // Solo el tercer nodo tiene la version mas nueva de la fila
+        cluster
+            .node(2)
+            .insert_row(keyspace_name, table_name, values.clone())
+            .unwrap();
+
+        client
             .query(
-                "CREATE KEYSPACE flights_keyspace
-                WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 3};"
+                "SELECT * FROM flight_status_by_origin WHERE origin_airport_id = '20' ;"
                     .to_string(),
-                "ALL",
+                ConsistencyLevel::All,
             )
             .expect("Error al ejecutar la query");
 
-        client_manager
-            .query("USE flights_keyspace;".to_string(), "ONE")
-            .expect("Error al ejecutar la query");
-
-        client_manager
-            .query(
-                "CREATE TABLE flight_status_by_origin (
-                    flight_id BIGINT,
-                    origin_airport_id INT,
-                    destination_airport_id INT,
-                    departure_time TIMESTAMP,
-                    arrival_time TIMESTAMP,
-                    status TEXT,
-                    airplane_id INT,
-                    airplane_model TEXT,
-                    max_fuel INT,
-                    timestamp TIMESTAMP,
-                    PRIMARY KEY ((origin_airport_id), departure_time)
-                );"
-                .to_string(),
-                "ONE",
-            )
-            .expect("Error al ejecutar create table");
+        //Deberian tener todos la fila actualizada
+        eventually("the read repair of every replica", || {
+            cluster
+                .nodes()
+                .iter()
+                .all(|node| has_row(node, keyspace_name, table_name, &values))
+        });
+    }
 
-        thread::sleep(std::time::Duration::from_millis(1000));
-        client_manager.query("INSERT INTO flight_status_by_origin
+    #[test]
+    fn test_update() {
+        let cluster = TestCluster::start(3);
+        let mut client = cluster.client();
+        create_flight_status(&cluster, &mut client, "INSERT INTO flight_status_by_origin
                 (flight_id, origin_airport_id, destination_airport_id, departure_time, arrival_time, status, timestamp)
-                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time', '2024-01-01 00:00:00');".to_string(),"QUORUM")
-                .expect("Error al ejecutar insert");
+                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time', '2024-01-01 00:00:00');");
 
-        thread::sleep(std::time::Duration::from_millis(1000));
-        client_manager
+        client
             .query(
                 "UPDATE flight_status_by_origin
                 SET status = 'delayed' WHERE flight_id = 10001;"
                     .to_string(),
-                "QUORUM",
+                ConsistencyLevel::All,
             )
             .expect("Error al ejecutar update");
 
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        let values: HashMap<String, String> = vec![
-            ("flight_id".to_string(), "10001".to_string()),
-            ("origin_airport_id".to_string(), "20".to_string()),
-            ("destination_airport_id".to_string(), "8888".to_string()),
-            (
-                "departure_time".to_string(),
-                "2024-09-27 09:00:00".to_string(),
-            ),
-            (
-                "arrival_time".to_string(),
-                "2024-09-27 18:00:00".to_string(),
-            ),
-            ("status".to_string(), "delayed".to_string()),
-        ]
-        .into_iter()
-        .collect();
-
-        let keyspace_name = "flights_keyspace".to_string();
-        let table_name = "flight_status_by_origin".to_string();
-
-        println!(
-            "{:?}",
-            node2
-                .get_table(&keyspace_name, &table_name)
-                .unwrap()
-                .get_vector_of_rows()
-        );
-        
-        for (key, value) in &values {
-            assert!(node2
-                .get_table(&keyspace_name, &table_name)
-                .unwrap()
-                .get_vector_of_rows()
-                .iter()
-                .any(|row| row.get(key).map(|v| v == value).unwrap_or(false)), 
-                "node2: El valor '{value}' para '{key}' no coincide con el esperado."
-            );
-        
-            assert!(node1
-                .get_table(&keyspace_name, &table_name)
-                .unwrap()
-                .get_vector_of_rows()
-                .iter()
-                .any(|row| row.get(key).map(|v| v == value).unwrap_or(false)),
-                "node1: El valor '{value}' para '{key}' no coincide con el esperado."
-            );
-
-            assert!(node3
-                .get_table(&keyspace_name, &table_name)
-                .unwrap()
-                .get_vector_of_rows()
-                .iter()
-                .any(|row| row.get(key).map(|v| v == value).unwrap_or(false)),
-                "node3: El valor '{value}' para '{key}' no coincide con el esperado."
+        let values = values(&[
+            ("flight_id", "10001"),
+            ("origin_airport_id", "20"),
+            ("destination_airport_id", "8888"),
+            ("departure_time", "2024-09-27 09:00:00"),
+            ("arrival_time", "2024-09-27 18:00:00"),
+            ("status", "delayed"),
+        ]);
+        for node in cluster.nodes() {
+            assert!(
+                has_row(node, "flights_keyspace", "flight_status_by_origin", &values),
+                "{}: la fila no tiene los valores esperados",
+                node.get_id()
             );
         }
     }
 
     #[test]
     fn test_delete() {
-        let node1 = Arc::new(Node::new("Node1", "localhost", 10091, 10092));
-        let node1_clone1 = Arc::clone(&node1);
-        let node1_clone2 = Arc::clone(&node1);
-        thread::spawn(move || start_node_native_protocol(node1_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node1_clone2));
-        let node1_clone3 = Arc::clone(&node1);
-        start_gossip(node1_clone3, 1000);
-
-        let node2 = Arc::new(Node::new("Node2", "localhost", 20091, 20092));
-        let node2_clone1 = Arc::clone(&node2);
-        let node2_clone2 = Arc::clone(&node2);
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-
-        if let Ok(mut stream) = TcpStream::connect("localhost:10092") {
-            let gossip_table = match node2.get_gossip_table() {
-                Ok(gossip_table) => gossip_table,
-                Err(e) => {
-                    println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                    return;
-                }
-            };
-
-            let gossip_messsage = InternalMessage::Gossip {
-                opcode: 1,
-                body: serde_json::to_string(&gossip_table).unwrap(),
-            };
-
-            if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                println!("Error al enviar el mensaje de gossip:new node.");
-            }
-
-            let mut gossip_table_response = String::new();
-
-            match stream.read_to_string(&mut gossip_table_response) {
-                Ok(_) => {
-                    println!(
-                        "Gossip table recibido por nodo 2: {}",
-                        gossip_table_response
-                    );
-                    let start = gossip_table_response.find('[').unwrap_or(0);
-                    let gossip_table: Vec<GossipInformation> =
-                        serde_json::from_str(&gossip_table_response[start..]).unwrap();
-                    node2.update_gossip_table(&gossip_table);
-                }
-                Err(e) => {
-                    println!("Error al leer del stream o timeout: {}", e);
-                }
-            }
-        } else {
-            println!("Error al conectar al nodo1.");
-        }
-
-        thread::spawn(move || start_node_native_protocol(node2_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node2_clone2));
-        let node2_clone3 = Arc::clone(&node2);
-        start_gossip(node2_clone3, 1000);
-
-        let node3 = Arc::new(Node::new("Node3", "localhost", 30091, 30092));
-        let node3_clone1 = Arc::clone(&node3);
-        let node3_clone2 = Arc::clone(&node3);
-
-        if let Ok(mut stream) = TcpStream::connect("localhost:10092") {
-            let gossip_table = match node3.get_gossip_table() {
-                Ok(gossip_table) => gossip_table,
-                Err(e) => {
-                    println!("Error al obtener la tabla de gossip del nodo2: {}", e);
-                    return;
-                }
-            };
-
-            let gossip_messsage = InternalMessage::Gossip {
-                opcode: 1,
-                body: serde_json::to_string(&gossip_table).unwrap(),
-            };
-
-            if { gossip_messsage.write_to_stream(&mut stream) }.is_err() {
-                println!("Error al enviar el mensaje de gossip:new node.");
-            }
-
-            let mut gossip_table_response = String::new();
-
-            match stream.read_to_string(&mut gossip_table_response) {
-                Ok(_) => {
-                    println!(
-                        "Gossip table recibido por nodo 3: {}",
-                        gossip_table_response
-                    );
-                    let gossip_table: Vec<GossipInformation> =
-                        serde_json::from_str(&gossip_table_response).unwrap();
-                    node3.update_gossip_table(&gossip_table);
-                }
-                Err(e) => {
-                    println!("Error al leer del stream o timeout: {}", e);
-                }
-            }
-        } else {
-            println!("Error al conectar al nodo1.");
-        }
-
-        thread::spawn(move || start_node_native_protocol(node3_clone1));
-        thread::spawn(move || start_node_gossip_query_protocol(node3_clone2));
-        let node3_clone3 = Arc::clone(&node3);
-        start_gossip(node3_clone3, 1000);
-        thread::sleep(std::time::Duration::from_millis(3000));
-
-        let addresses: Vec<String> =
-            vec!["localhost:10091".to_string(), "localhost:20091".to_string()];
-        let mut client_manager = match ClientManager::new(&addresses) {
-            Ok(client_manager) => client_manager,
-            Err(e) => {
-                eprintln!("Error al crear el client manager: {:?}", e);
-                return;
-            }
-        };
-
-        client_manager
-            .query(
-                "CREATE KEYSPACE flights_keyspace
-                WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 3};"
-                    .to_string(),
-                "ALL",
-            )
-            .expect("Error al ejecutar la query");
-
-        client_manager
-            .query("USE flights_keyspace;".to_string(), "ONE")
-            .expect("Error al ejecutar la query");
-
-        client_manager
-            .query(
-                "CREATE TABLE flight_status_by_origin (
-                    flight_id BIGINT,
-                    origin_airport_id INT,
-                    destination_airport_id INT,
-                    departure_time TIMESTAMP,
-                    arrival_time TIMESTAMP,
-                    status TEXT,
-                    airplane_id INT,
-                    airplane_model TEXT,
-                    max_fuel INT,
-                    timestamp TIMESTAMP,
-                    PRIMARY KEY ((origin_airport_id), departure_time)
-                );"
-                .to_string(),
-                "ONE",
-            )
-            .expect("Error al ejecutar create table");
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-        client_manager.query("INSERT INTO flight_status_by_origin
+        let cluster = TestCluster::start(3);
+        let mut client = cluster.client();
+        create_flight_status(&cluster, &mut client, "INSERT INTO flight_status_by_origin
                 (flight_id, origin_airport_id, destination_airport_id, departure_time, arrival_time, status, timestamp)
-                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time', '2024-01-01 00:00:00');".to_string(),"QUORUM")
-                .expect("Error al ejecutar insert");
-
-        thread::sleep(std::time::Duration::from_millis(1000));
-        client_manager
+                VALUES (10001, 20, 8888, '2024-09-27 09:00:00', '2024-09-27 18:00:00', 'on_time', '2024-01-01 00:00:00');");
+
+        let values = values(&[
+            ("flight_id", "10001"),
+            ("origin_airport_id", "20"),
+            ("status", "on_time"),
+            ("timestamp", "2024-01-01 00:00:00"),
+        ]);
+        for node in cluster.nodes() {
+            assert!(has_row(
+                node,
+                "flights_keyspace",
+                "flight_status_by_origin",
+                &values
+            ));
+        }
+
+        client
             .query(
                 "DELETE FROM flight_status_by_origin WHERE flight_id = 10001;".to_string(),
-                "QUORUM",
+                ConsistencyLevel::All,
             )
-            .expect("Error al ejecutar update");
-
-        thread::sleep(std::time::Duration::from_millis(1000));
+            .expect("Error al ejecutar delete");
 
-        let values: HashMap<String, String> = vec![
-            ("flight_id".to_string(), "10001".to_string()),
-            ("origin_airport_id".to_string(), "20".to_string()),
-            ("destination_airport_id".to_string(), "8888".to_string()),
-            (
-                "departure_time".to_string(),
-                "2024-09-27 09:00:00".to_string(),
-            ),
-            (
-                "arrival_time".to_string(),
-                "2024-09-27 18:00:00".to_string(),
-            ),
-            ("status".to_string(), "on_time".to_string()),
-            ("timestamp".to_string(), "2024-01-01 00:00:00".to_string()),
-        ]
-        .into_iter()
-        .collect();
-
-        let keyspace_name = "flights_keyspace".to_string();
-        let table_name = "flight_status_by_origin".to_string();
-
-        println!(
-            "{:?}",
-            node2
-                .get_table(&keyspace_name, &table_name)
-                .unwrap()
-                .get_vector_of_rows()
-        );
-
-        assert!(!node3
-            .get_table(&keyspace_name, &table_name)
-            .unwrap()
-            .get_vector_of_rows()
-            .contains(&values));
-
-        assert!(!node2
-            .get_table(&keyspace_name, &table_name)
-            .unwrap()
-            .get_vector_of_rows()
-            .contains(&values));
-
-        assert!(!node1
-            .get_table(&keyspace_name, &table_name)
-            .unwrap()
-            .get_vector_of_rows()
-            .contains(&values));
+        for node in cluster.nodes() {
+            assert!(
+                !has_row(node, "flights_keyspace", "flight_status_by_origin", &values),
+                "{}: la fila no se borro",
+                node.get_id()
+            );
+        }
     }
 }
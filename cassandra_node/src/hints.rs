@@ -0,0 +1,271 @@
+use crate::encrypted_table::{open_at_rest, seal_at_rest};
+use crate::internal_protocol::InternalMessage;
+use crate::query_parser::{parse_instruction, ParsedQuery};
+use crate::storage_format::{self, FileKind};
+use chrono::{DateTime, Utc};
+use common::frame::messages::prepared::WRITE_TIMESTAMP_FORMAT;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::{fs, io};
+
+/// Opcode of the HINT query messages.
+pub const HINT_OPCODE: u8 = 11;
+
+/// Body of a HINT message: a write a coordinator couldn't deliver to a replica, sent to it
+/// when it comes back. The replica doesn't apply it to the rows written at or after the
+/// `_timestamp` of the write, so a late hint doesn't overwrite newer data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hint {
+    /// The `_timestamp` the write carries, or when the coordinator sent it for the writes
+    /// without one, such as a DELETE.
+    pub timestamp: String,
+    /// Opcode of the write: INSERT, UPDATE, DELETE or BATCH.
+    pub opcode: u8,
+    pub body: String,
+}
+
+/// Returns the message stored as a hint for a message that couldn't be delivered: the writes
+/// are sent as a HINT with their timestamp, and any other message as it is.
+///
+/// # Parameters
+/// - `message`: The message that couldn't be delivered.
+/// - `sent_at`: When the coordinator sent it.
+pub fn hint_message(message: &InternalMessage, sent_at: DateTime<Utc>) -> InternalMessage {
+    match message {
+        InternalMessage::Query {
+            opcode: opcode @ (2 | 4 | 5 | 6),
+            body,
+            keyspace_name,
+            trace_id,
+        } => {
+            let timestamp = match opcode {
+                6 => None,
+                _ => mutation_timestamp(body),
+            };
+            let hint = Hint {
+                timestamp: timestamp
+                    .unwrap_or_else(|| sent_at.format(WRITE_TIMESTAMP_FORMAT).to_string()),
+                opcode: *opcode,
+                body: body.to_string(),
+            };
+            match serde_json::to_string(&hint) {
+                Ok(body) => InternalMessage::Query {
+                    opcode: HINT_OPCODE,
+                    body,
+                    keyspace_name: keyspace_name.to_string(),
+                    trace_id: trace_id.to_string(),
+                },
+                Err(_) => message.clone(),
+            }
+        }
+        _ => message.clone(),
+    }
+}
+
+/// Persists the hints a node keeps for another one in `dir`, one file per node with a record
+/// per hint, each sealed with the at-rest key. The file is removed once no hints are left.
+///
+/// # Parameters
+/// - `dir`: The hints directory of the node that keeps them.
+/// - `node_id`: The id of the node they are for.
+/// - `hints`: Its hints.
+///
+/// # Returns
+/// An `io::Result` indicating the success of the operation.
+pub fn save_hints(dir: &str, node_id: &str, hints: &[InternalMessage]) -> io::Result<()> {
+    let path = format!("{}/{}", dir, node_id);
+    if hints.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let records: Vec<Vec<u8>> = hints
+        .iter()
+        .map(|hint| seal_at_rest(&hint.to_bytes()))
+        .collect();
+    storage_format::write_file(
+        &path,
+        FileKind::Hints,
+        &storage_format::encode_records(&records),
+    )
+}
+
+/// Appends a hint to the ones persisted in `dir` for a node, without rewriting them.
+///
+/// # Parameters
+/// - `dir`: The hints directory of the node that keeps it.
+/// - `node_id`: The id of the node it is for.
+/// - `hint`: The hint.
+///
+/// # Returns
+/// An `io::Result` indicating the success of the operation.
+pub fn append_hint(dir: &str, node_id: &str, hint: &InternalMessage) -> io::Result<()> {
+    let path = format!("{}/{}", dir, node_id);
+    storage_format::append_record(&path, FileKind::Hints, &seal_at_rest(&hint.to_bytes()))
+}
+
+/// Loads the hints persisted in `dir` by `save_hints` and `append_hint`, by the node they are
+/// for. Files that can't be read are skipped.
+///
+/// # Parameters
+/// - `dir`: The hints directory of the node.
+pub fn load_hints(dir: &str) -> HashMap<String, Vec<InternalMessage>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let node_id = path.file_name()?.to_str()?.to_string();
+            // Los temporales son escrituras que no terminaron
+            if node_id.ends_with(".tmp") {
+                return None;
+            }
+            let records = storage_format::read_records(path.to_str()?, FileKind::Hints).ok()?;
+            let hints = records
+                .iter()
+                .map(|record| InternalMessage::try_deserialize(&open_at_rest(record)?))
+                .collect::<Result<Vec<InternalMessage>, String>>()
+                .ok()?;
+            Some((node_id, hints))
+        })
+        .collect()
+}
+
+/// Migrates a hints file of version 3, with every hint sealed together, to one record per hint.
+pub fn migrate_sealed_hints(payload: Vec<u8>) -> Result<Vec<u8>, String> {
+    let hints = InternalMessage::try_deserialize_all(&open_at_rest(&payload)?)?;
+    let records: Vec<Vec<u8>> = hints
+        .iter()
+        .map(|hint| seal_at_rest(&hint.to_bytes()))
+        .collect();
+    Ok(storage_format::encode_records(&records))
+}
+
+/// Returns the `_timestamp` the coordinator stamped on an INSERT or an UPDATE, if it has one.
+///
+/// # Parameters
+/// - `statement`: The INSERT or UPDATE.
+pub fn mutation_timestamp(statement: &str) -> Option<String> {
+    match parse_instruction(statement).ok()? {
+        ParsedQuery::Insert { rows_to_insert, .. } => rows_to_insert
+            .iter()
+            .find_map(|row| row.get("_timestamp").cloned()),
+        ParsedQuery::Update {
+            values_to_update, ..
+        } => values_to_update.get("_timestamp").cloned(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn only_writes_are_hinted_with_their_timestamp() {
+        let timestamp = Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+        let insert = InternalMessage::Query {
+            opcode: 2,
+            body: "INSERT INTO flights (id, _timestamp) VALUES (1, '2024-05-01 10:29:59.123456');"
+                .to_string(),
+            keyspace_name: "airports".to_string(),
+            trace_id: "0123456789abcdef".to_string(),
+        };
+        let InternalMessage::Query {
+            opcode,
+            body,
+            keyspace_name,
+            ..
+        } = hint_message(&insert, timestamp)
+        else {
+            panic!("A hint is a query message");
+        };
+        assert_eq!(opcode, HINT_OPCODE);
+        assert_eq!(keyspace_name, "airports");
+        let hint: Hint = serde_json::from_str(&body).unwrap();
+        // El hint lleva el _timestamp de la escritura, no la hora en que se envió
+        assert_eq!(hint.timestamp, "2024-05-01 10:29:59.123456");
+        assert_eq!(hint.opcode, 2);
+        assert_eq!(
+            hint.body,
+            "INSERT INTO flights (id, _timestamp) VALUES (1, '2024-05-01 10:29:59.123456');"
+        );
+
+        let delete = InternalMessage::Query {
+            opcode: 5,
+            body: "DELETE FROM flights WHERE id = 1;".to_string(),
+            keyspace_name: "airports".to_string(),
+            trace_id: "0123456789abcdef".to_string(),
+        };
+        let InternalMessage::Query { body, .. } = hint_message(&delete, timestamp) else {
+            panic!("A hint is a query message");
+        };
+        let hint: Hint = serde_json::from_str(&body).unwrap();
+        assert_eq!(hint.timestamp, "2024-05-01 10:30:00.000000");
+
+        let commit = InternalMessage::Query {
+            opcode: 9,
+            body: "{}".to_string(),
+            keyspace_name: "airports".to_string(),
+            trace_id: "0123456789abcdef".to_string(),
+        };
+        assert_eq!(hint_message(&commit, timestamp), commit);
+    }
+
+    #[test]
+    fn hints_survive_a_restart_sealed() {
+        std::env::set_var("DB_KEY", "82917");
+        let dir = "./data/hints_test";
+        let _ = fs::remove_dir_all(dir);
+        let insert = InternalMessage::Query {
+            opcode: 2,
+            body: "INSERT INTO flights (id, origin) VALUES (1, 'Ushuaia');".to_string(),
+            keyspace_name: "airports".to_string(),
+            trace_id: "0123456789abcdef".to_string(),
+        };
+        let hints = vec![hint_message(&insert, Utc::now()), insert.clone()];
+        save_hints(dir, "node_2", &hints).unwrap();
+
+        let bytes = fs::read(format!("{}/node_2", dir)).unwrap();
+        assert!(!bytes.windows(7).any(|w| w == b"Ushuaia"));
+        assert_eq!(load_hints(dir), HashMap::from([("node_2".to_string(), hints)]));
+
+        save_hints(dir, "node_2", &[]).unwrap();
+        assert!(load_hints(dir).is_empty());
+    }
+
+    #[test]
+    fn hints_are_appended_and_migrated_from_a_single_sealed_blob() {
+        std::env::set_var("DB_KEY", "82917");
+        let dir = "./data/hints_append_test";
+        let _ = fs::remove_dir_all(dir);
+        let insert = InternalMessage::Query {
+            opcode: 2,
+            body: "INSERT INTO flights (id) VALUES (1);".to_string(),
+            keyspace_name: "airports".to_string(),
+            trace_id: "0123456789abcdef".to_string(),
+        };
+        let delete = InternalMessage::Query {
+            opcode: 5,
+            body: "DELETE FROM flights WHERE id = 1;".to_string(),
+            keyspace_name: "airports".to_string(),
+            trace_id: "0123456789abcdef".to_string(),
+        };
+
+        // Version 3: todos los hints sellados juntos
+        let blob: Vec<u8> = [insert.to_bytes(), delete.to_bytes()].concat();
+        let mut bytes = storage_format::encode(FileKind::Hints, &seal_at_rest(&blob));
+        bytes[5..7].copy_from_slice(&3u16.to_be_bytes());
+        fs::create_dir_all(dir).unwrap();
+        fs::write(format!("{}/node_3", dir), bytes).unwrap();
+
+        append_hint(dir, "node_3", &insert).unwrap();
+        assert_eq!(
+            load_hints(dir)["node_3"],
+            vec![insert.clone(), delete, insert]
+        );
+    }
+}
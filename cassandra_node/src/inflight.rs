@@ -0,0 +1,219 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often a thread working on a query checks whether it was killed while it waits.
+pub const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+thread_local! {
+    /// Cancellation flag of the query the current thread coordinates, if any.
+    static CURRENT_QUERY: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// A query a node is coordinating. Part of the answer to the QUERIES admin request.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InFlightQuery {
+    pub query_id: String,
+    pub query: String,
+    pub keyspace: String,
+    pub started_at: String,
+    pub elapsed_ms: u128,
+    /// What the coordinator is doing: parsing, waiting for the replicas, repairing...
+    pub state: String,
+}
+
+#[derive(Debug)]
+struct Entry {
+    query: String,
+    keyspace: String,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    state: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// The queries a node is coordinating, so that they can be listed and a stuck one aborted.
+#[derive(Debug, Default)]
+pub struct InFlightQueries {
+    queries: Mutex<HashMap<String, Entry>>,
+}
+
+/// Keeps a query registered while the coordinator handles it, and makes it the query of
+/// the current thread. Dropping it unregisters the query.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    queries: Arc<InFlightQueries>,
+    query_id: String,
+}
+
+impl InFlightQueries {
+    /// Registers a query received from a client.
+    ///
+    /// # Parameters
+    /// - `query_id`: The trace id generated for the query.
+    /// - `query`: The text of the query.
+    /// - `keyspace`: The keyspace of the connection.
+    ///
+    /// # Returns
+    /// The guard that unregisters the query when the coordinator is done with it.
+    pub fn register(
+        self: &Arc<Self>,
+        query_id: &str,
+        query: &str,
+        keyspace: &str,
+    ) -> InFlightGuard {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Ok(mut queries) = self.queries.lock() {
+            queries.insert(
+                query_id.to_string(),
+                Entry {
+                    query: query.to_string(),
+                    keyspace: keyspace.to_string(),
+                    started_at: Utc::now(),
+                    started: Instant::now(),
+                    state: "parsing".to_string(),
+                    cancelled: Arc::clone(&cancelled),
+                },
+            );
+        }
+        CURRENT_QUERY.with(|current| *current.borrow_mut() = Some(cancelled));
+        InFlightGuard {
+            queries: Arc::clone(self),
+            query_id: query_id.to_string(),
+        }
+    }
+
+    /// Updates what the coordinator of a query is doing.
+    pub fn set_state(&self, query_id: &str, state: &str) {
+        if let Ok(mut queries) = self.queries.lock() {
+            if let Some(entry) = queries.get_mut(query_id) {
+                entry.state = state.to_string();
+            }
+        }
+    }
+
+    /// Returns the queries being coordinated, the oldest first.
+    pub fn list(&self) -> Vec<InFlightQuery> {
+        let Ok(queries) = self.queries.lock() else {
+            return vec![];
+        };
+        let mut list: Vec<InFlightQuery> = queries
+            .iter()
+            .map(|(query_id, entry)| InFlightQuery {
+                query_id: query_id.clone(),
+                query: entry.query.clone(),
+                keyspace: entry.keyspace.clone(),
+                started_at: entry.started_at.to_rfc3339(),
+                elapsed_ms: entry.started.elapsed().as_millis(),
+                state: entry.state.clone(),
+            })
+            .collect();
+        list.sort_by_key(|query| Reverse(query.elapsed_ms));
+        list
+    }
+
+    /// Aborts a query: its coordinator stops waiting for the replicas and returns an error
+    /// to the client, and the workers sending it to other nodes stop waiting for them.
+    ///
+    /// # Returns
+    /// Whether the query was being coordinated.
+    pub fn kill(&self, query_id: &str) -> bool {
+        let Ok(mut queries) = self.queries.lock() else {
+            return false;
+        };
+        match queries.get_mut(query_id) {
+            Some(entry) => {
+                entry.cancelled.store(true, Ordering::SeqCst);
+                entry.state = "killed".to_string();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Ok(mut queries) = self.queries.queries.lock() {
+            queries.remove(&self.query_id);
+        }
+        CURRENT_QUERY.with(|current| *current.borrow_mut() = None);
+    }
+}
+
+/// Returns whether the query coordinated by the current thread was killed.
+pub fn is_cancelled() -> bool {
+    CURRENT_QUERY.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .is_some_and(|cancelled| cancelled.load(Ordering::SeqCst))
+    })
+}
+
+/// Returns the cancellation flag of the query coordinated by the current thread, to hand it
+/// to the jobs that work on it in other threads.
+pub fn current_query() -> Option<Arc<AtomicBool>> {
+    CURRENT_QUERY.with(|current| current.borrow().clone())
+}
+
+/// Runs a job on behalf of a query, so that `is_cancelled` answers for that query while it
+/// runs, and then restores the query of the current thread.
+///
+/// # Parameters
+/// - `query`: The cancellation flag given by `current_query`, or `None` if the job isn't part
+///   of a query.
+/// - `job`: The job to run.
+pub fn run_for_query<T>(query: Option<Arc<AtomicBool>>, job: impl FnOnce() -> T) -> T {
+    let previous = CURRENT_QUERY.with(|current| current.replace(query));
+    let result = job();
+    CURRENT_QUERY.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_are_listed_until_done_and_can_be_killed() {
+        let queries = Arc::new(InFlightQueries::default());
+        let guard = queries.register("q1", "SELECT * FROM flights", "airports");
+        queries.set_state("q1", "waiting for replicas");
+
+        let list = queries.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].state, "waiting for replicas");
+        assert!(!is_cancelled());
+
+        assert!(queries.kill("q1"));
+        assert!(is_cancelled());
+        assert!(!queries.kill("q2"));
+
+        drop(guard);
+        assert!(queries.list().is_empty());
+        assert!(!is_cancelled());
+    }
+
+    #[test]
+    fn the_jobs_of_a_killed_query_see_it_killed() {
+        let queries = Arc::new(InFlightQueries::default());
+        let _guard = queries.register("q1", "SELECT * FROM flights", "airports");
+        let query = current_query();
+
+        let job = |query: Option<Arc<AtomicBool>>| {
+            // El hilo del trabajo vuelve a no tener query al terminar
+            std::thread::spawn(move || (run_for_query(query, is_cancelled), is_cancelled()))
+                .join()
+                .unwrap()
+        };
+        assert_eq!(job(query.clone()), (false, false));
+        assert!(queries.kill("q1"));
+        assert_eq!(job(query), (true, false));
+    }
+}
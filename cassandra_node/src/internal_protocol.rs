@@ -1,5 +1,9 @@
+use rand::{rng, Rng};
 use std::{io::Read, io::Write, net::TcpStream};
 
+/// Largest body of a message, so a malformed length is rejected before reading it.
+const MAX_BODY_LENGTH: usize = 256 * 1024 * 1024;
+
 #[derive(Debug, Clone,PartialEq)]
 /// Enum representing the different types of messages that can be sent between nodes
 /// 
@@ -14,22 +18,43 @@ use std::{io::Read, io::Write, net::TcpStream};
 /// 4. The next n bytes are the body of the message
 /// 5. If the message is a Query message, the next byte is the length of the keyspace name
 /// 6. The next n bytes are the keyspace name
-/// 7. If the message is a Query message, the next byte is the length of the consistency level
-/// 8. The next n bytes are the consistency level
+/// 7. If the message is a Query message, the next byte is the length of the trace id
+/// 8. The next n bytes are the trace id
 /// 
 pub enum InternalMessage {
-    /// Gossip message (0): GOSSIP, NEW_NODE
+    /// Gossip message (0): GOSSIP, NEW_NODE, METRICS, PEER_FAILURES, STATUS, RING, DESCRIBE_CLUSTER,
+    /// LOG_LEVEL
     Gossip {
-        /// 0: GOSSIP, 1: NEW_NODE
+        /// 0: GOSSIP, 1: NEW_NODE,
+        /// 2: METRICS (the body is ignored and the response is a JSON snapshot of the metrics),
+        /// 3: PEER_FAILURES (the body is ignored and the response is a JSON object with the
+        /// connection failures, timeouts and hint fallbacks of each node),
+        /// 4: STATUS, 5: RING, 6: DESCRIBE_CLUSTER (admin requests: the body is ignored and the
+        /// response is the JSON of a `NodeStatus`, a list of `TokenRange` or a `ClusterDescription`),
+        /// 7: LOG_LEVEL (the body is the new level, or empty to keep it; the response is the
+        /// level in effect),
+        /// 8: HEALTH (the body is ignored and the response is the JSON of a `NodeHealth`),
+        /// 9: QUERIES (the body is ignored and the response is the JSON list of the
+        /// `InFlightQuery`s the node coordinates), 10: KILL_QUERY (the body is the id of the
+        /// query to abort)
         opcode: u8,
         body: String,
     },
-    /// Query message (1): CREATE_KEYSPACE, CREATE_TABLE, INSERT, SELECT, UPDATE, DELETE
+    /// Query message (1): CREATE_KEYSPACE, CREATE_TABLE, INSERT, SELECT, UPDATE, DELETE, BATCH, PAXOS, COUNTER, HINT,
+    /// SELECT_PAGE
     Query {
-        /// 0: CREATE_KEYSPACE, 1: CREATE_TABLE, 2: INSERT, 3: SELECT, 4: UPDATE, 5: DELETE
+        /// 0: CREATE_KEYSPACE, 1: CREATE_TABLE, 2: INSERT, 3: SELECT, 4: UPDATE, 5: DELETE,
+        /// 6: BATCH (the body is a JSON array of INSERT/UPDATE/DELETE queries on a single table),
+        /// 7: PAXOS_PREPARE, 8: PAXOS_PROPOSE, 9: PAXOS_COMMIT (rounds of a lightweight
+        /// transaction: the body is the JSON of a `Prepare` or a `ProposalMessage`),
+        /// 10: COUNTER (the body is the JSON of a `CounterMutation`),
+        /// 11: HINT (a write stored for a replica that was down: the body is the JSON of a `Hint`),
+        /// 12: SELECT_PAGE (a page of the rows of a SELECT: the body is the JSON of a `PageRequest`)
         opcode: u8,
         body: String,
         keyspace_name: String,
+        /// Id generated by the coordinator of the request, logged by every node that handles it
+        trace_id: String,
     },
     /// Response message (2): OK, ERROR
     Response {
@@ -51,6 +76,18 @@ impl InternalMessage {
     /// An empty Result Ok if the message was successfully written to the stream, or an error message if it failed
     /// 
     pub fn write_to_stream(&self, stream: &mut TcpStream) -> Result<(), String> {
+        stream
+            .write_all(&self.to_bytes())
+            .map_err(|e| format!("Error writing to stream: {}", e))
+    }
+
+    /// Serialize the message to a byte vector according to the protocol.
+    /// 
+    /// # Returns
+    /// 
+    /// The bytes written to the stream by `write_to_stream`
+    /// 
+    pub fn to_bytes(&self) -> Vec<u8> {
         let mut buffer = vec![];
         match self {
             InternalMessage::Gossip { opcode, body } => {
@@ -59,15 +96,12 @@ impl InternalMessage {
                 let body_len: u32 = body.len() as u32;
                 buffer.extend_from_slice(&body_len.to_be_bytes());
                 buffer.extend_from_slice(body.as_bytes());
-
-                if let Err(e) = stream.write_all(&buffer) {
-                    return Err(format!("Error writing to stream: {}", e));
-                }
             }
             InternalMessage::Query {
                 opcode,
                 body,
                 keyspace_name,
+                trace_id,
             } => {
                 buffer.push(1);
                 buffer.push(*opcode);
@@ -76,10 +110,8 @@ impl InternalMessage {
                 buffer.extend_from_slice(body.as_bytes());
                 buffer.push(keyspace_name.len() as u8);
                 buffer.extend_from_slice(keyspace_name.as_bytes());
-
-                if let Err(e) = stream.write_all(&buffer) {
-                    return Err(format!("Error writing to stream: {}", e));
-                }
+                buffer.push(trace_id.len() as u8);
+                buffer.extend_from_slice(trace_id.as_bytes());
             }
             InternalMessage::Response { opcode, body } => {
                 buffer.push(2);
@@ -87,14 +119,9 @@ impl InternalMessage {
                 let body_len: u32 = body.len() as u32;
                 buffer.extend_from_slice(&body_len.to_be_bytes());
                 buffer.extend_from_slice(body.as_bytes());
-
-                if let Err(e) = stream.write_all(&buffer) {
-                    return Err(format!("Error writing to stream: {}", e));
-                }
             }
         }
-
-        Ok(())
+        buffer
     }
 
     /// Deserialize a message from a TcpStream according to the protocol.
@@ -108,47 +135,152 @@ impl InternalMessage {
     /// An InternalMessage if the message was successfully read from the stream, or an error message if it failed
     /// 
     pub fn deserialize_from_stream(stream: &mut TcpStream) -> Result<Self, String> {
-        let mut message_type = [0u8; 1];
-        let mut opcode = [0u8; 1];
-        let mut body_length = [0u8; 4];
-        stream
-            .read_exact(&mut message_type)
-            .map_err(|e| e.to_string())?;
-        stream.read_exact(&mut opcode).map_err(|e| e.to_string())?;
-        stream
-            .read_exact(&mut body_length)
-            .map_err(|e| e.to_string())?;
-        let mut body = vec![0u8; u32::from_be_bytes(body_length) as usize];
-        stream.read_exact(&mut body).map_err(|e| e.to_string())?;
-        let body_as_string = match String::from_utf8(body.clone()) {
-            Ok(body_as_string) => body_as_string,
-            Err(e) => return Err(e.to_string()),
-        };
-        match message_type[0] {
-            0 => Ok(InternalMessage::Gossip {
-                opcode: opcode[0],
-                body: body_as_string,
-            }),
+        Self::read_from(stream)
+    }
+
+    /// Deserializes a message from its bytes, without panicking on malformed ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes of a single whole message
+    ///
+    /// # Returns
+    ///
+    /// The message, or an error message if the bytes are not a valid message or there are
+    /// bytes after it
+    ///
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = bytes;
+        let message = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(format!("{} bytes after the message", reader.len()));
+        }
+        Ok(message)
+    }
+
+    /// Deserializes the messages written one after another by `to_bytes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes of the messages
+    ///
+    /// # Returns
+    ///
+    /// The messages, or an error message if the bytes are not valid messages
+    ///
+    pub fn try_deserialize_all(bytes: &[u8]) -> Result<Vec<Self>, String> {
+        let mut reader = bytes;
+        let mut messages = vec![];
+        while !reader.is_empty() {
+            messages.push(Self::read_from(&mut reader)?);
+        }
+        Ok(messages)
+    }
+
+    fn read_from(reader: &mut impl Read) -> Result<Self, String> {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header).map_err(|e| e.to_string())?;
+        let [message_type, opcode, body_length @ ..] = header;
+        let body_length = u32::from_be_bytes(body_length) as usize;
+        if body_length > MAX_BODY_LENGTH {
+            return Err(format!("Body of {} bytes is too large", body_length));
+        }
+        let body = read_string(reader, body_length)?;
+        match message_type {
+            0 => Ok(InternalMessage::Gossip { opcode, body }),
             1 => {
-                let mut keyspace_name_length = [0u8; 1];
-                stream
-                    .read_exact(&mut keyspace_name_length)
-                    .map_err(|e| e.to_string())?;
-                let mut keyspace_name = vec![0u8; keyspace_name_length[0] as usize];
-                stream
-                    .read_exact(&mut keyspace_name)
-                    .map_err(|e| e.to_string())?;
+                let keyspace_name_length = read_bytes(reader, 1)?[0] as usize;
+                let keyspace_name = read_string(reader, keyspace_name_length)?;
+                let trace_id_length = read_bytes(reader, 1)?[0] as usize;
+                let trace_id = read_string(reader, trace_id_length)?;
                 Ok(InternalMessage::Query {
-                    opcode: opcode[0],
-                    body: body_as_string,
-                    keyspace_name: String::from_utf8(keyspace_name).map_err(|e| e.to_string())?,
+                    opcode,
+                    body,
+                    keyspace_name,
+                    trace_id,
                 })
             }
-            2 => Ok(InternalMessage::Response {
-                opcode: opcode[0],
-                body: body_as_string,
-            }),
+            2 => Ok(InternalMessage::Response { opcode, body }),
             _ => Err("Invalid message type".to_string()),
         }
     }
 }
+
+/// Reads `length` bytes. They are read as they arrive, so a malformed length doesn't
+/// reserve memory the message doesn't have.
+fn read_bytes(reader: &mut impl Read, length: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    reader
+        .take(length as u64)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if bytes.len() < length {
+        return Err(format!("Expected {} bytes, found {}", length, bytes.len()));
+    }
+    Ok(bytes)
+}
+
+fn read_string(reader: &mut impl Read, length: usize) -> Result<String, String> {
+    String::from_utf8(read_bytes(reader, length)?).map_err(|e| e.to_string())
+}
+
+/// Generates the id used to trace a request across the nodes that handle it.
+///
+/// # Returns
+///
+/// A random id of 16 hexadecimal characters
+///
+pub fn new_trace_id() -> String {
+    format!("{:016x}", rng().random::<u64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_query_keeps_trace_id_across_the_wire() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        let trace_id = new_trace_id();
+        let message = InternalMessage::Query {
+            opcode: 3,
+            body: "SELECT * FROM flights WHERE id = 1;".to_string(),
+            keyspace_name: "aerolineas".to_string(),
+            trace_id: trace_id.clone(),
+        };
+        message.write_to_stream(&mut client).unwrap();
+
+        assert_eq!(InternalMessage::deserialize_from_stream(&mut server), Ok(message));
+        assert_eq!(trace_id.len(), 16);
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected_without_panicking() {
+        let message = InternalMessage::Query {
+            opcode: 1,
+            body: "INSERT INTO flights (id) VALUES (1);".to_string(),
+            keyspace_name: "aerolineas".to_string(),
+            trace_id: new_trace_id(),
+        };
+        let bytes = message.to_bytes();
+        assert_eq!(InternalMessage::try_deserialize(&bytes), Ok(message));
+
+        // Le falta el trace id, le sobra un byte o dice traer 4 GB
+        assert!(InternalMessage::try_deserialize(&bytes[..bytes.len() - 1]).is_err());
+        assert!(InternalMessage::try_deserialize(&[bytes.clone(), vec![0]].concat()).is_err());
+        assert!(InternalMessage::try_deserialize(&[2, 0, 0xFF, 0xFF, 0xFF, 0xFF]).is_err());
+        assert!(InternalMessage::try_deserialize(&[0, 1, 0, 0, 0, 1, 0xFF]).is_err());
+        assert!(InternalMessage::try_deserialize(&[3, 0, 0, 0, 0, 0]).is_err());
+        assert_eq!(
+            InternalMessage::try_deserialize(&[2, 0, 0, 0, 0, 2, b'o', b'k']),
+            Ok(InternalMessage::Response {
+                opcode: 0,
+                body: "ok".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,656 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::chaos::{Fault, FaultInjector};
+use crate::hints::{append_hint, hint_message, save_hints};
+use crate::inflight::{self, CANCEL_POLL_INTERVAL};
+use crate::internal_protocol::InternalMessage;
+use crate::log::{Logger, RateLimitedLogger};
+use crate::metrics::{Metrics, Operation, PeerFailure};
+use crate::node::GossipInformation;
+
+/// Minimum time between two warnings about the same failure with the same node.
+const FAILURE_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Why a message couldn't be exchanged with another node.
+#[derive(Debug, PartialEq)]
+pub enum ExchangeError {
+    /// The message didn't reach the node.
+    Undelivered(String),
+    /// The message was sent, but no valid response came back.
+    Failed(String),
+    /// The query the message belongs to was killed, so it wasn't sent or its response wasn't
+    /// waited for.
+    Cancelled,
+}
+
+/// How a message sent with `send_write` reached its node.
+#[derive(Debug, PartialEq)]
+pub enum Delivery {
+    /// The node answered with this body.
+    Answered(String),
+    /// The node couldn't be reached and the message was stored as a hint for it, which
+    /// counts for the ANY consistency level.
+    Hinted(String),
+}
+
+impl From<ExchangeError> for String {
+    fn from(error: ExchangeError) -> Self {
+        match error {
+            ExchangeError::Undelivered(e) | ExchangeError::Failed(e) => e,
+            ExchangeError::Cancelled => "Query killed".to_string(),
+        }
+    }
+}
+
+/// Sends internal messages to other nodes on behalf of a node.
+///
+/// It only holds the shared state needed to reach the other nodes (the gossip table and the
+/// hints), so a single instance behind an `Arc` can be handed to every fan-out job instead of a
+/// copy of the whole node.
+#[derive(Debug)]
+pub struct InternodeClient {
+    node_id: String,
+    gossip_table: Arc<RwLock<Vec<GossipInformation>>>,
+    hints: Arc<RwLock<HashMap<String, Vec<InternalMessage>>>>,
+    /// Directory where the hints are persisted, so they survive a restart.
+    hints_dir: String,
+    logger: Logger,
+    metrics: Arc<Metrics>,
+    warnings: RateLimitedLogger,
+    /// Maximum time to wait for another node to read or answer a request.
+    request_timeout: Duration,
+    /// Faults injected in the messages sent, only in test clusters.
+    faults: RwLock<Option<FaultInjector>>,
+}
+
+impl InternodeClient {
+    /// Creates a client that shares the gossip table and hints of a node.
+    ///
+    /// # Parameters
+    /// - `node_id`: The id of the node that sends the messages.
+    /// - `gossip_table`: The gossip table of the node, used to find the address of the other nodes.
+    /// - `hints`: The hints of the node, where failed writes are stored.
+    /// - `hints_dir`: The directory where the hints are persisted.
+    /// - `logger`: The logger of the node.
+    /// - `metrics`: The metrics of the node, where requests, stored hints and failures are recorded.
+    /// - `request_timeout`: Maximum time to wait for another node to read or answer a request.
+    pub fn new(
+        node_id: &str,
+        gossip_table: Arc<RwLock<Vec<GossipInformation>>>,
+        hints: Arc<RwLock<HashMap<String, Vec<InternalMessage>>>>,
+        hints_dir: &str,
+        logger: Logger,
+        metrics: Arc<Metrics>,
+        request_timeout: Duration,
+    ) -> Self {
+        InternodeClient {
+            node_id: node_id.to_string(),
+            gossip_table,
+            hints,
+            hints_dir: hints_dir.to_string(),
+            warnings: RateLimitedLogger::new(logger.clone(), FAILURE_WARNING_INTERVAL),
+            logger,
+            metrics,
+            request_timeout,
+            faults: RwLock::new(None),
+        }
+    }
+
+    /// Injects faults in the messages the node sends from now on, or stops if `faults` is
+    /// `None`.
+    pub fn set_faults(&self, faults: Option<FaultInjector>) {
+        if let Some(faults) = &faults {
+            let _ = self.logger.warn(&format!(
+                "Injecting faults in the messages to other nodes, with seed {}",
+                faults.seed()
+            ));
+        }
+        if let Ok(mut current) = self.faults.write() {
+            *current = faults;
+        }
+    }
+
+    /// Sends a message to another node and waits for its response. If the node can't be
+    /// reached, the message is stored as a hint to be delivered when it comes back.
+    ///
+    /// # Parameters
+    /// - `to_send`: The `InternalMessage` to send.
+    /// - `node_id`: The id of the destination node.
+    ///
+    /// # Returns
+    /// Ok(String) with the body of the response, or a descriptive Err(String).
+    pub fn send(&self, to_send: &InternalMessage, node_id: &str) -> Result<String, String> {
+        match self.send_write(to_send, node_id)? {
+            Delivery::Answered(response) => Ok(response),
+            Delivery::Hinted(e) => Err(e),
+        }
+    }
+
+    /// Same as `send`, but tells apart the failures after which the message was stored as a
+    /// hint.
+    ///
+    /// # Returns
+    /// Ok(Delivery) with the response or the error after which the hint was stored, or a
+    /// descriptive Err(String) if the message was neither answered nor stored.
+    pub fn send_write(&self, to_send: &InternalMessage, node_id: &str) -> Result<Delivery, String> {
+        let start = Instant::now();
+        let delivery = self.send_storing_hint(to_send, node_id);
+        // Un mensaje guardado como hint cuenta como request fallido
+        let answered = matches!(delivery, Ok(Delivery::Answered(_)));
+        self.metrics
+            .record(Operation::InternodeRequest, start.elapsed(), answered);
+        delivery
+    }
+
+    fn send_storing_hint(
+        &self,
+        to_send: &InternalMessage,
+        node_id: &str,
+    ) -> Result<Delivery, String> {
+        let destination = self.address_of(node_id)?;
+        // El hint lleva la hora en que se envió la escritura, no la de cuando falló
+        let sent_at = Utc::now();
+
+        let _ = self
+            .logger
+            .log(format!("Attempting resend to {}", &destination).as_str());
+
+        match self.exchange(to_send, node_id, &destination) {
+            Ok(response) => {
+                let _ = self
+                    .logger
+                    .log(format!("Query resent to {}", &destination).as_str());
+                Ok(Delivery::Answered(response))
+            }
+            Err(ExchangeError::Undelivered(e)) => {
+                self.store_hint(node_id, to_send, sent_at)?;
+                Ok(Delivery::Hinted(e))
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Sends a message to another node and waits for its response, without storing hints.
+    /// Used for query types that don't require them, as SELECT.
+    ///
+    /// # Parameters
+    /// - `to_send`: The `InternalMessage` to send.
+    /// - `node_id`: The id of the destination node.
+    ///
+    /// # Returns
+    /// Ok(String) with the body of the response, or a descriptive Err(String).
+    pub fn send_without_hint(
+        &self,
+        to_send: &InternalMessage,
+        node_id: &str,
+    ) -> Result<String, String> {
+        self.metrics.time(Operation::InternodeRequest, || {
+            let destination = self.address_of(node_id)?;
+            Ok(self.exchange(to_send, node_id, &destination)?)
+        })
+    }
+
+    /// Same as `send_without_hint`, but tells apart the failures after which the message
+    /// surely didn't reach the node, so a message that isn't idempotent can be sent to
+    /// another one.
+    ///
+    /// # Parameters
+    /// - `to_send`: The `InternalMessage` to send.
+    /// - `node_id`: The id of the destination node.
+    ///
+    /// # Returns
+    /// Ok(String) with the body of the response, Err(ExchangeError::Undelivered) if the node
+    /// couldn't be reached, or Err(ExchangeError::Failed) if the message may have reached it.
+    pub fn send_at_most_once(
+        &self,
+        to_send: &InternalMessage,
+        node_id: &str,
+    ) -> Result<String, ExchangeError> {
+        let start = Instant::now();
+        let response = self
+            .address_of(node_id)
+            .map_err(ExchangeError::Undelivered)
+            .and_then(|destination| self.exchange(to_send, node_id, &destination));
+        self.metrics.record(
+            Operation::InternodeRequest,
+            start.elapsed(),
+            response.is_ok(),
+        );
+        response
+    }
+
+    /// Sends a message to a node that may not be in the gossip table yet, as the nodes that
+    /// receive data when they join the cluster, and waits for its response.
+    ///
+    /// # Parameters
+    /// - `to_send`: The `InternalMessage` to send.
+    /// - `node`: The gossip information of the destination node.
+    ///
+    /// # Returns
+    /// Ok(String) with the body of the response, or a descriptive Err(String).
+    pub fn send_to(
+        &self,
+        to_send: &InternalMessage,
+        node: &GossipInformation,
+    ) -> Result<String, String> {
+        self.metrics.time(Operation::InternodeRequest, || {
+            let destination = format!("{}:{}", node.ip, node.port_gossip_query);
+            Ok(self.exchange(to_send, &node.node_id, &destination)?)
+        })
+    }
+
+    /// Sends the hints stored for a node that came back to life, and forgets the ones that
+    /// were delivered.
+    ///
+    /// # Parameters
+    /// - `node_id`: The id of the node.
+    /// - `node_ip`: The ip of the node.
+    /// - `node_port`: The gossip port of the node.
+    pub fn send_hints(&self, node_id: &str, node_ip: &str, node_port: &str) {
+        let _span =
+            tracing::info_span!("send_hints", node = %self.node_id, peer = node_id).entered();
+        let mut hints = match self.hints.write() {
+            Ok(hints) => hints,
+            _ => {
+                return;
+            }
+        };
+
+        let hints_to_send = match hints.get_mut(node_id) {
+            Some(hints_to_send) => hints_to_send,
+            None => {
+                return;
+            }
+        };
+
+        let destination = format!("{}:{}", node_ip, node_port);
+        let pending = hints_to_send.len();
+        // Se quedan solo los hints que no se pudieron enviar
+        hints_to_send.retain(|hint| match connect(&destination, self.request_timeout) {
+            Ok(mut stream) => match hint.write_to_stream(&mut stream) {
+                Ok(_) => false,
+                Err(e) => {
+                    self.record_failure(
+                        node_id,
+                        PeerFailure::ConnectionFailure,
+                        &format!("Error writing hint to {}: {}", destination, e),
+                    );
+                    true
+                }
+            },
+            Err(e) => {
+                self.record_failure(
+                    node_id,
+                    failure_of(&e),
+                    &format!("Error connecting to {} to send hints: {}", destination, e),
+                );
+                true
+            }
+        });
+        tracing::info!(
+            delivered = pending - hints_to_send.len(),
+            pending = hints_to_send.len(),
+            "hints sent"
+        );
+        if let Err(e) = save_hints(&self.hints_dir, node_id, hints_to_send) {
+            tracing::error!(peer = node_id, error = %e, "failed to persist hints");
+        }
+    }
+
+    /// Sends a message and waits for the response, recording the failures of the node. If the
+    /// query of the current thread is killed, it stops waiting and frees the thread.
+    #[tracing::instrument(
+        name = "internode_request",
+        level = "debug",
+        skip(self, to_send),
+        fields(node = %self.node_id, peer = node_id)
+    )]
+    fn exchange(
+        &self,
+        to_send: &InternalMessage,
+        node_id: &str,
+        destination: &str,
+    ) -> Result<String, ExchangeError> {
+        if inflight::is_cancelled() {
+            return Err(ExchangeError::Cancelled);
+        }
+        let injector = self.faults.read().ok();
+        let injector = injector.as_ref().and_then(|injector| injector.as_ref());
+        let faults = injector
+            .map(|injector| injector.faults(node_id))
+            .unwrap_or_default();
+        for fault in &faults {
+            match fault {
+                Fault::Drop => {
+                    self.record_failure(
+                        node_id,
+                        PeerFailure::ConnectionFailure,
+                        &format!("Message to {} dropped by the fault injector", destination),
+                    );
+                    return Err(ExchangeError::Undelivered(
+                        "Error connecting to node".to_string(),
+                    ));
+                }
+                Fault::Delay(delay) => thread::sleep(*delay),
+                Fault::Duplicate | Fault::Corrupt => {}
+            }
+        }
+
+        let mut stream = connect(destination, self.request_timeout).map_err(|e| {
+            self.record_failure(
+                node_id,
+                failure_of(&e),
+                &format!("Error connecting to {}: {}", destination, e),
+            );
+            ExchangeError::Undelivered("Error connecting to node".to_string())
+        })?;
+
+        let mut bytes = to_send.to_bytes();
+        if let (Some(injector), true) = (injector, faults.contains(&Fault::Corrupt)) {
+            injector.corrupt(&mut bytes);
+        }
+        if faults.contains(&Fault::Duplicate) {
+            // La copia llega por otra conexion y su respuesta se descarta
+            if let Ok(mut copy) = connect(destination, self.request_timeout) {
+                let _ = copy.write_all(&bytes);
+            }
+        }
+
+        if let Err(e) = stream.write_all(&bytes) {
+            self.record_failure(
+                node_id,
+                PeerFailure::ConnectionFailure,
+                &format!("Error writing to {}: {}", destination, e),
+            );
+            return Err(ExchangeError::Undelivered(format!(
+                "Error resending query: {}",
+                e
+            )));
+        }
+
+        if let Err(e) = wait_for_response(&stream, self.request_timeout) {
+            if inflight::is_cancelled() {
+                return Err(ExchangeError::Cancelled);
+            }
+            self.record_failure(
+                node_id,
+                failure_of(&e),
+                &format!("No response from {}: {}", destination, e),
+            );
+            return Err(ExchangeError::Failed(format!(
+                "Error waiting for response: {}",
+                e
+            )));
+        }
+
+        read_response(&mut stream).map_err(ExchangeError::Failed)
+    }
+
+    /// Counts a failed message to a node and logs a warning, at most once per interval for
+    /// each node and kind of failure.
+    fn record_failure(&self, node_id: &str, failure: PeerFailure, detail: &str) {
+        tracing::warn!(peer = node_id, %failure, detail);
+        self.metrics.record_peer_failure(node_id, failure);
+        self.warnings.warn(
+            &format!("{}-{:?}", node_id, failure),
+            &format!("{} with node {} ({})", failure, node_id, detail),
+        );
+    }
+
+    /// Stores a message that couldn't be delivered to a node, or that wasn't sent because
+    /// gossip marks the node as dead, to be delivered when it comes back. Writes are stored
+    /// with `sent_at`, so the node doesn't apply them over rows written later.
+    pub fn store_hint(
+        &self,
+        node_id: &str,
+        to_send: &InternalMessage,
+        sent_at: DateTime<Utc>,
+    ) -> Result<(), String> {
+        tracing::warn!(peer = node_id, "storing hint");
+        self.record_failure(
+            node_id,
+            PeerFailure::HintFallback,
+            "storing query for hinted-handoff",
+        );
+        self.metrics.time(Operation::HintStore, || {
+            let mut hints_for_all_nodes = match self.hints.write() {
+                Ok(hints) => hints,
+                Err(_) => {
+                    return Err("Error locking hints".to_string());
+                }
+            };
+            let hint = hint_message(to_send, sent_at);
+            hints_for_all_nodes
+                .entry(node_id.to_string())
+                .or_default()
+                .push(hint.clone());
+            // Se agrega solo el hint nuevo al archivo, sin reescribir los anteriores
+            append_hint(&self.hints_dir, node_id, &hint)
+                .map_err(|e| format!("Error writing hints: {}", e))
+        })
+    }
+
+    /// Looks up the gossip address of a node in the gossip table.
+    fn address_of(&self, node_id: &str) -> Result<String, String> {
+        let gossip_table = match self.gossip_table.read() {
+            Ok(gossip_table) => gossip_table,
+            Err(_) => return Err("Error locking gossip table".to_string()),
+        };
+        gossip_table
+            .iter()
+            .find(|gossip_info| gossip_info.node_id == node_id)
+            .map(|gossip_info| format!("{}:{}", gossip_info.ip, gossip_info.port_gossip_query))
+            .ok_or("Node not found".to_string())
+    }
+}
+
+/// Connects to a node. A node that doesn't answer can't block the caller forever.
+fn connect(destination: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(destination)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+/// Waits until the response of a node starts to arrive, the timeout expires, or the query of
+/// the current thread is killed.
+fn wait_for_response(stream: &TcpStream, timeout: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    let mut first_byte = [0u8; 1];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no response in time",
+            ));
+        }
+        // Se espera de a poco para ver si la query fue cancelada
+        stream.set_read_timeout(Some(remaining.min(CANCEL_POLL_INTERVAL)))?;
+        match stream.peek(&mut first_byte) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed without a response",
+                ))
+            }
+            Ok(_) => return stream.set_read_timeout(Some(timeout)),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if inflight::is_cancelled() {
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "query killed"));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Classifies an error talking to a node: the node didn't answer in time, or the
+/// connection failed.
+fn failure_of(error: &io::Error) -> PeerFailure {
+    match error.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => PeerFailure::Timeout,
+        _ => PeerFailure::ConnectionFailure,
+    }
+}
+
+/// Reads the response of a node: opcode 0 is a success and any other opcode an error.
+fn read_response(stream: &mut TcpStream) -> Result<String, String> {
+    match InternalMessage::deserialize_from_stream(stream) {
+        Ok(InternalMessage::Response { opcode, body }) => {
+            if opcode == 0 {
+                Ok(body)
+            } else {
+                Err(body)
+            }
+        }
+        Ok(_) => Err("Invalid response".to_string()),
+        Err(_) => Err("Error deserializing response".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hints::{load_hints, Hint, HINT_OPCODE};
+
+    fn client_with_unreachable_node() -> InternodeClient {
+        let gossip_table = vec![GossipInformation {
+            node_id: "unreachable".to_string(),
+            ip: "127.0.0.1".to_string(),
+            // Puerto reservado en el que nadie escucha
+            port_native_protocol: "1".to_string(),
+            port_gossip_query: "1".to_string(),
+            last_heartbeat: 0,
+            status: "Live".to_string(),
+        }];
+        InternodeClient::new(
+            "internode_test",
+            Arc::new(RwLock::new(gossip_table)),
+            Arc::new(RwLock::new(HashMap::new())),
+            "./data/hints/internode_test",
+            Logger::new("internode_test"),
+            Arc::new(Metrics::new()),
+            Duration::from_secs(10),
+        )
+    }
+
+    fn query() -> InternalMessage {
+        InternalMessage::Query {
+            opcode: 2,
+            body: "INSERT INTO t (id) VALUES (1);".to_string(),
+            keyspace_name: "ks".to_string(),
+            trace_id: "0123456789abcdef".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_unreachable_node_stores_hint() {
+        // Los hints se agregan al log, no se arrastran los de otra corrida
+        let _ = std::fs::remove_dir_all("./data/hints/internode_test");
+        let client = client_with_unreachable_node();
+        assert!(client.send(&query(), "unreachable").is_err());
+        // La escritura se guarda con la hora en que se envió
+        let hints = client.hints.read().unwrap()["unreachable"].clone();
+        assert_eq!(hints.len(), 1);
+        assert_eq!(
+            load_hints("./data/hints/internode_test")["unreachable"],
+            hints
+        );
+        let InternalMessage::Query { opcode, body, .. } = &hints[0] else {
+            panic!("A hint is a query message");
+        };
+        assert_eq!(*opcode, HINT_OPCODE);
+        let hint: Hint = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            (hint.opcode, hint.body.as_str()),
+            (2, "INSERT INTO t (id) VALUES (1);")
+        );
+
+        let metrics = client.metrics.snapshot();
+        assert_eq!(metrics.operations["internode_request"].errors, 1);
+        assert_eq!(metrics.operations["hint_store"].count, 1);
+        assert_eq!(metrics.peers["unreachable"].connection_failures, 1);
+        assert_eq!(metrics.peers["unreachable"].hint_fallbacks, 1);
+
+        assert!(matches!(
+            client.send_write(&query(), "unreachable"),
+            Ok(Delivery::Hinted(_))
+        ));
+    }
+
+    #[test]
+    fn test_send_without_hint_does_not_store_hint() {
+        let client = client_with_unreachable_node();
+        assert!(client.send_without_hint(&query(), "unreachable").is_err());
+        assert!(client.hints.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_send_at_most_once_tells_apart_unreachable_nodes() {
+        let client = client_with_unreachable_node();
+        assert!(matches!(
+            client.send_at_most_once(&query(), "unreachable"),
+            Err(ExchangeError::Undelivered(_))
+        ));
+        assert!(client.hints.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_killed_query_stops_waiting_for_the_node() {
+        use crate::inflight::InFlightQueries;
+        use std::net::TcpListener;
+
+        // Un nodo que acepta la conexion pero nunca responde
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let silent = thread::spawn(move || listener.accept().map(|(stream, _)| stream));
+        let client = client_with_unreachable_node();
+        if let Ok(mut gossip_table) = client.gossip_table.write() {
+            gossip_table[0].port_gossip_query = port;
+        }
+
+        let queries = Arc::new(InFlightQueries::default());
+        let _guard = queries.register("query_lenta", "INSERT INTO t (id) VALUES (1);", "ks");
+        let killer = {
+            let queries = Arc::clone(&queries);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                queries.kill("query_lenta")
+            })
+        };
+        let start = Instant::now();
+        assert_eq!(
+            client.send_at_most_once(&query(), "unreachable"),
+            Err(ExchangeError::Cancelled)
+        );
+        // Sin esperar el timeout de 10 segundos ni guardar un hint
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(killer.join().unwrap());
+        assert!(client.send(&query(), "unreachable").is_err());
+        assert!(client.hints.read().unwrap().is_empty());
+        drop(silent.join());
+    }
+
+    #[test]
+    fn test_unknown_node_is_an_error() {
+        let client = client_with_unreachable_node();
+        assert_eq!(
+            client.send(&query(), "missing"),
+            Err("Node not found".to_string())
+        );
+    }
+}
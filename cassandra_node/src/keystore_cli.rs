@@ -0,0 +1,104 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use common::{
+    config::{Config, FsyncPolicy, SecurityConfig},
+    security::{credentials::hash_password, keystore::Keystore},
+};
+
+const USAGE: &str =
+    "usage: cassandra_node keystore <generate|rotate> <keystore_path> [passphrase_file]";
+
+/// Runs `cassandra_node keystore <command> <keystore_path> [passphrase_file]`:
+/// - `generate` writes a new keystore with a random key.
+/// - `rotate` adds a new active key to an existing keystore. The tables are re-encrypted
+///   with it the next time each node loads and flushes them.
+///
+/// Without a passphrase file the passphrase is read from stdin.
+///
+/// # Parameters
+/// - `args`: The arguments after `keystore`.
+pub fn run_keystore_command(args: &[String]) -> Result<(), String> {
+    let (Some(command), Some(path)) = (args.first(), args.get(1)) else {
+        return Err(USAGE.to_string());
+    };
+    let passphrase = match args.get(2) {
+        Some(passphrase_file) => read_passphrase(passphrase_file),
+        None => prompt_passphrase(),
+    }
+    .map_err(|e| format!("can't read the passphrase: {}", e))?;
+
+    let keystore = match command.as_str() {
+        "generate" => {
+            if fs::metadata(path).is_ok() {
+                return Err(format!(
+                    "{} already exists, use rotate to change its key",
+                    path
+                ));
+            }
+            Keystore::generate()
+        }
+        "rotate" => {
+            let mut keystore = Keystore::load(path, &passphrase).map_err(|e| e.to_string())?;
+            keystore.rotate();
+            keystore
+        }
+        _ => return Err(USAGE.to_string()),
+    };
+    // Sin archivo de configuración se usa la política por defecto
+    let fsync = Config::new().map_or(FsyncPolicy::Always, |config| config.storage.fsync_policy());
+    keystore
+        .save(path, &passphrase, fsync)
+        .map_err(|e| format!("can't write {}: {}", path, e))?;
+    println!("Keystore {} has {} key(s)", path, keystore.keys().len());
+    Ok(())
+}
+
+/// Runs `cassandra_node password`: reads a password from stdin and prints its hash, to add
+/// its user to the `[auth]` section of the config file.
+pub fn run_password_command() -> Result<(), String> {
+    print!("Password: ");
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let password = read_line().map_err(|e| format!("can't read the password: {}", e))?;
+    if password.is_empty() {
+        return Err("the password must not be empty".to_string());
+    }
+    println!("{}", hash_password(&password));
+    Ok(())
+}
+
+/// Loads the keystore configured in the `[security]` section, if any.
+///
+/// # Parameters
+/// - `security`: The security settings of the config file.
+///
+/// # Returns
+/// `None` when the nodes use `DB_KEY`, or the keystore.
+pub fn load_keystore(security: &SecurityConfig) -> io::Result<Option<Keystore>> {
+    let Some((keystore_path, passphrase_file)) = security.keystore() else {
+        return Ok(None);
+    };
+    let passphrase = read_passphrase(passphrase_file)?;
+    Keystore::load(keystore_path, &passphrase).map(Some)
+}
+
+/// Reads a passphrase file, ignoring the trailing newline.
+fn read_passphrase(passphrase_file: &str) -> io::Result<String> {
+    let passphrase = fs::read_to_string(passphrase_file)?;
+    Ok(passphrase.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn prompt_passphrase() -> io::Result<String> {
+    print!("Passphrase: ");
+    io::stdout().flush()?;
+    read_line()
+}
+
+/// Reads a line of stdin without its newline.
+fn read_line() -> io::Result<String> {
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
@@ -1,8 +1,135 @@
-use std::fs::{create_dir_all, OpenOptions};
-use std::io::Write;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{LazyLock, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use chrono::DateTime;
+use common::config::LoggingConfig;
+use flate2::{write::GzEncoder, Compression};
+use tracing_subscriber::EnvFilter;
+
+/// Lowest level that is logged, shared by every logger of the process so that it can be
+/// changed while the node runs.
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+/// Whether entries are written as JSON objects instead of text lines.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+/// When the log files are rotated. Without a policy they grow without bound.
+static ROTATION: RwLock<Option<RotationPolicy>> = RwLock::new(None);
+/// When the current file of each log was started. The lock is also held while writing, so
+/// that a file isn't rotated in the middle of an entry.
+static LOG_FILES: LazyLock<Mutex<HashMap<String, SystemTime>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// When a log file is rotated and how many rotated files are kept. The rotated files are
+/// named `<log>.1` (the newest) to `<log>.<retained_files>`, plus `.gz` if compressed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationPolicy {
+    pub max_bytes: u64,
+    pub max_age: Option<Duration>,
+    pub retained_files: usize,
+    pub compress: bool,
+}
+
+impl From<&LoggingConfig> for RotationPolicy {
+    fn from(logging: &LoggingConfig) -> Self {
+        RotationPolicy {
+            max_bytes: logging.max_file_size_mb * 1024 * 1024,
+            max_age: (logging.max_file_age_hours > 0)
+                .then(|| Duration::from_secs(logging.max_file_age_hours * 3600)),
+            retained_files: logging.retained_files,
+            compress: logging.compress,
+        }
+    }
+}
+
+/// Severity of a log entry, from the most to the least verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    const ALL: [LogLevel; 5] = [
+        LogLevel::Trace,
+        LogLevel::Debug,
+        LogLevel::Info,
+        LogLevel::Warn,
+        LogLevel::Error,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "TRACE",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+
+    /// Parses a level name, ignoring case.
+    ///
+    /// #Parameters
+    /// - `s`: The name of the level: trace, debug, info, warn or error.
+    ///
+    pub fn from_str_to_enum(s: &str) -> Result<Self, String> {
+        LogLevel::ALL
+            .into_iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Invalid log level: {}", s))
+    }
+}
+
+/// Changes the lowest level that is logged.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::SeqCst);
+}
+
+/// Returns the lowest level that is logged.
+pub fn level() -> LogLevel {
+    LogLevel::ALL[LEVEL.load(Ordering::SeqCst) as usize]
+}
+
+/// Applies the `[logging]` section of the config file.
+///
+/// #Parameters
+/// - `logging`: The level and the format (text or json) of the logs.
+///
+pub fn configure(logging: &LoggingConfig) -> Result<(), String> {
+    set_level(LogLevel::from_str_to_enum(&logging.level)?);
+    JSON_OUTPUT.store(logging.format.eq_ignore_ascii_case("json"), Ordering::SeqCst);
+    if let Ok(mut rotation) = ROTATION.write() {
+        *rotation = Some(RotationPolicy::from(logging));
+    }
+    if logging.tracing {
+        init_tracing(&logging.level)?;
+    }
+    Ok(())
+}
+
+/// Prints the `tracing` spans and events of the node subsystems to stdout. The `RUST_LOG`
+/// environment variable filters them (e.g. `RUST_LOG=cassandra_node::internode=debug`),
+/// otherwise `level` does.
+///
+/// #Parameters
+/// - `level`: The level of the `[logging]` section.
+///
+fn init_tracing(level: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(level.to_lowercase()));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_thread_names(true)
+        .try_init()
+        .map_err(|e| format!("Can't install the tracing subscriber: {}", e))
+}
 
 /// This struct represents the logger for each node.
 /// 
@@ -17,22 +144,58 @@ impl Logger {
         Logger { id: id.to_string() }
     }
 
-    /// Creates log and writes the message recieved.
+    /// Creates log and writes the message recieved, at the `Info` level.
     /// 
     /// #Parameters
     /// - `message`: String with the message to log.
     /// 
     pub fn log(&self, message: &str) -> Result<(), Box<dyn std::error::Error>> {
-        // Crear directorio de logs si no existe
-        let log_dir = "logs";
-        create_dir_all(log_dir)?;
+        self.log_with(LogLevel::Info, message, &[])
+    }
+
+    /// Logs a message under the trace id of the request it belongs to, so a single request
+    /// can be followed through the logs of every node that handled it.
+    ///
+    /// #Parameters
+    /// - `trace_id`: The trace id generated by the coordinator of the request.
+    /// - `message`: String with the message to log.
+    ///
+    pub fn log_traced(&self, trace_id: &str, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.log_with(LogLevel::Info, message, &[("query_id", trace_id)])
+    }
+
+    /// Logs a message at the `Debug` level.
+    pub fn debug(&self, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.log_with(LogLevel::Debug, message, &[])
+    }
+
+    /// Logs a message at the `Warn` level.
+    pub fn warn(&self, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.log_with(LogLevel::Warn, message, &[])
+    }
+
+    /// Logs a message at the `Error` level.
+    pub fn error(&self, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.log_with(LogLevel::Error, message, &[])
+    }
 
-        // Ruta del archivo de log
-        let log_path = format!("{}/{}.log", log_dir, self.id);
-        let mut file = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(log_path)?;
+    /// Writes an entry to the log file of the node and to stdout, unless its level is below
+    /// the current one. Every entry carries the id of the node.
+    ///
+    /// #Parameters
+    /// - `level`: The severity of the entry.
+    /// - `message`: String with the message to log.
+    /// - `fields`: Key-value pairs that describe the entry, e.g. the `table` or `query_id`.
+    ///
+    pub fn log_with(
+        &self,
+        level: LogLevel,
+        message: &str,
+        fields: &[(&str, &str)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if level < self::level() {
+            return Ok(());
+        }
 
         // Obtener el tiempo actual
         let time = SystemTime::now();
@@ -45,11 +208,298 @@ impl Logger {
         let timestamp = naive_date.format("%Y-%m-%d %H:%M:%S%.3f").to_string();
 
         // Formatear el mensaje y escribir en el archivo
-        let to_log = format!("[{}] {}: {}\n", timestamp, self.id, message);
-        file.write_all(to_log.as_bytes())?;
+        let to_log = format_entry(
+            &timestamp,
+            level,
+            &self.id,
+            message,
+            fields,
+            JSON_OUTPUT.load(Ordering::SeqCst),
+        );
+        let rotation = ROTATION.read().ok().and_then(|rotation| rotation.clone());
+        write_entry("logs", &self.id, &to_log, rotation.as_ref())?;
 
         // Imprimir también en stdout
         println!("{}", to_log);
         Ok(())
     }
 }
+
+/// Appends an entry to the log file of a node, rotating the file first if the entry would
+/// make it too big or the file is too old.
+///
+/// #Parameters
+/// - `log_dir`: The directory of the log files, created if it doesn't exist.
+/// - `id`: The id of the node, which names its log file.
+/// - `entry`: The formatted entry.
+/// - `rotation`: When to rotate the file, `None` to never rotate it.
+///
+fn write_entry(
+    log_dir: &str,
+    id: &str,
+    entry: &str,
+    rotation: Option<&RotationPolicy>,
+) -> io::Result<()> {
+    create_dir_all(log_dir)?;
+    let log_path = format!("{}/{}.log", log_dir, id);
+    let mut log_files = LOG_FILES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if let Some(policy) = rotation {
+        if let Ok(metadata) = fs::metadata(&log_path) {
+            // Un archivo de una ejecucion anterior cuenta desde que se creo
+            let started = *log_files.entry(log_path.clone()).or_insert_with(|| {
+                metadata.created().or(metadata.modified()).unwrap_or_else(|_| SystemTime::now())
+            });
+            let too_big = metadata.len() > 0
+                && metadata.len() + entry.len() as u64 > policy.max_bytes;
+            let too_old = policy.max_age.is_some_and(|max_age| {
+                started.elapsed().is_ok_and(|age| age >= max_age)
+            });
+            if too_big || too_old {
+                rotate(&log_path, policy)?;
+                log_files.insert(log_path.clone(), SystemTime::now());
+            }
+        }
+    }
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&log_path)?;
+    file.write_all(entry.as_bytes())
+}
+
+/// Renames the log file to `<log>.1`, compressing it if the policy says so, after shifting
+/// the older rotated files by one and deleting the ones past `retained_files`.
+fn rotate(log_path: &str, policy: &RotationPolicy) -> io::Result<()> {
+    let rotated = |n: usize| {
+        let plain = format!("{}.{}", log_path, n);
+        let compressed = format!("{}.gz", plain);
+        [plain, compressed]
+    };
+
+    for path in rotated(policy.retained_files.max(1)) {
+        if Path::new(&path).exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    if policy.retained_files == 0 {
+        return fs::remove_file(log_path);
+    }
+    for n in (1..policy.retained_files).rev() {
+        for (from, to) in rotated(n).into_iter().zip(rotated(n + 1)) {
+            if Path::new(&from).exists() {
+                fs::rename(from, to)?;
+            }
+        }
+    }
+
+    let [plain, compressed] = rotated(1);
+    if policy.compress {
+        let mut encoder = GzEncoder::new(File::create(&compressed)?, Compression::default());
+        io::copy(&mut File::open(log_path)?, &mut encoder)?;
+        encoder.finish()?;
+        fs::remove_file(log_path)
+    } else {
+        fs::rename(log_path, plain)
+    }
+}
+
+/// Formats a log entry as a text line, `[timestamp] LEVEL node_id: message key=value...`,
+/// or as a JSON object with the same data.
+fn format_entry(
+    timestamp: &str,
+    level: LogLevel,
+    node_id: &str,
+    message: &str,
+    fields: &[(&str, &str)],
+    json: bool,
+) -> String {
+    if json {
+        let mut entry = serde_json::Map::new();
+        entry.insert("timestamp".to_string(), timestamp.into());
+        entry.insert("level".to_string(), level.as_str().into());
+        entry.insert("node_id".to_string(), node_id.into());
+        entry.insert("message".to_string(), message.into());
+        for (key, value) in fields {
+            entry.insert(key.to_string(), (*value).into());
+        }
+        return format!("{}\n", serde_json::Value::Object(entry));
+    }
+
+    let mut line = format!("[{}] {} {}: {}", timestamp, level.as_str(), node_id, message);
+    for (key, value) in fields {
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    line.push('\n');
+    line
+}
+
+/// Logs repeated warnings at most once per interval for each key, so a node that keeps
+/// failing doesn't flood the log. The warnings skipped in between are counted and reported
+/// with the next one that is logged.
+#[derive(Debug)]
+pub struct RateLimitedLogger {
+    logger: Logger,
+    interval: Duration,
+    last_logged: Mutex<HashMap<String, (Instant, u64)>>,
+}
+
+impl RateLimitedLogger {
+    pub fn new(logger: Logger, interval: Duration) -> Self {
+        RateLimitedLogger {
+            logger,
+            interval,
+            last_logged: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Logs a warning unless another one with the same key was logged less than an interval ago.
+    ///
+    /// #Parameters
+    /// - `key`: Identifies the kind of warning, e.g. the node and the failure.
+    /// - `message`: String with the message to log.
+    ///
+    /// #Returns
+    /// Whether the warning was logged.
+    pub fn warn(&self, key: &str, message: &str) -> bool {
+        let Ok(mut last_logged) = self.last_logged.lock() else {
+            return false;
+        };
+        let now = Instant::now();
+        match last_logged.get_mut(key) {
+            Some((last, suppressed)) if now.duration_since(*last) < self.interval => {
+                *suppressed += 1;
+                false
+            }
+            entry => {
+                let suppressed = entry.map(|(_, suppressed)| *suppressed).unwrap_or(0);
+                let message = if suppressed > 0 {
+                    format!("{} ({} similar warnings suppressed)", message, suppressed)
+                } else {
+                    message.to_string()
+                };
+                let _ = self.logger.warn(message.as_str());
+                last_logged.insert(key.to_string(), (now, 0));
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_entries_carry_level_and_fields() {
+        let entry = format_entry(
+            "2024-01-01 00:00:00.000",
+            LogLevel::Warn,
+            "node1",
+            "Timeout",
+            &[("table", "ks.flights"), ("query_id", "abc")],
+            false,
+        );
+        assert_eq!(
+            entry,
+            "[2024-01-01 00:00:00.000] WARN node1: Timeout table=ks.flights query_id=abc\n"
+        );
+    }
+
+    #[test]
+    fn test_json_entries() {
+        let entry = format_entry(
+            "2024-01-01 00:00:00.000",
+            LogLevel::Info,
+            "node1",
+            "Table \"created\"",
+            &[("table", "ks.flights")],
+            true,
+        );
+        let entry: serde_json::Value = serde_json::from_str(&entry).unwrap();
+        assert_eq!(entry["level"], "INFO");
+        assert_eq!(entry["node_id"], "node1");
+        assert_eq!(entry["message"], "Table \"created\"");
+        assert_eq!(entry["table"], "ks.flights");
+    }
+
+    fn rotated_files(log_dir: &str) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(log_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_logs_are_rotated_by_size_and_compressed() {
+        let log_dir = "./data/log_rotation_size";
+        let _ = fs::remove_dir_all(log_dir);
+        let policy = RotationPolicy {
+            max_bytes: 20,
+            max_age: None,
+            retained_files: 2,
+            compress: true,
+        };
+
+        for entry in ["first entry\n", "second entry\n", "third entry\n", "fourth entry\n"] {
+            write_entry(log_dir, "node", entry, Some(&policy)).unwrap();
+        }
+
+        assert_eq!(
+            rotated_files(log_dir),
+            vec!["node.log", "node.log.1.gz", "node.log.2.gz"]
+        );
+        assert_eq!(fs::read_to_string(format!("{}/node.log", log_dir)).unwrap(), "fourth entry\n");
+        let mut decoder =
+            flate2::read::GzDecoder::new(File::open(format!("{}/node.log.1.gz", log_dir)).unwrap());
+        let mut newest_rotated = String::new();
+        io::Read::read_to_string(&mut decoder, &mut newest_rotated).unwrap();
+        assert_eq!(newest_rotated, "third entry\n");
+    }
+
+    #[test]
+    fn test_logs_are_rotated_by_age() {
+        let log_dir = "./data/log_rotation_age";
+        let _ = fs::remove_dir_all(log_dir);
+        let policy = RotationPolicy {
+            max_bytes: u64::MAX,
+            max_age: Some(Duration::ZERO),
+            retained_files: 5,
+            compress: false,
+        };
+
+        write_entry(log_dir, "node", "first entry\n", Some(&policy)).unwrap();
+        write_entry(log_dir, "node", "second entry\n", Some(&policy)).unwrap();
+
+        assert_eq!(rotated_files(log_dir), vec!["node.log", "node.log.1"]);
+        assert_eq!(
+            fs::read_to_string(format!("{}/node.log.1", log_dir)).unwrap(),
+            "first entry\n"
+        );
+    }
+
+    #[test]
+    fn test_log_levels_are_ordered_and_parsed() {
+        assert!(LogLevel::Trace < LogLevel::Debug && LogLevel::Warn < LogLevel::Error);
+        assert_eq!(LogLevel::from_str_to_enum("warn"), Ok(LogLevel::Warn));
+        assert!(LogLevel::from_str_to_enum("verbose").is_err());
+    }
+
+    #[test]
+    fn test_repeated_warnings_are_suppressed() {
+        let warnings = RateLimitedLogger::new(Logger::new("rate_limited_test"), Duration::from_secs(60));
+        assert!(warnings.warn("node 1", "Connection failure"));
+        assert!(!warnings.warn("node 1", "Connection failure"));
+        assert!(warnings.warn("node 2", "Connection failure"));
+    }
+
+    #[test]
+    fn test_warnings_are_logged_again_after_the_interval() {
+        let warnings = RateLimitedLogger::new(Logger::new("rate_limited_test"), Duration::ZERO);
+        assert!(warnings.warn("node 1", "Timeout"));
+        assert!(warnings.warn("node 1", "Timeout"));
+    }
+}
@@ -1,25 +1,48 @@
 #![allow(dead_code)]
 
-use std::{env, net::TcpStream, sync::Arc, thread};
-
-use common::config::Config;
-use handler_nodes::{start_gossip, start_flush, start_node_gossip_query_protocol, start_node_native_protocol};
+use std::{env, fs, net::TcpStream, sync::Arc, thread};
+
+use common::config::{Config, FsyncPolicy, NodeConfig};
+use encrypted_table::use_keystore;
+use handler_nodes::{
+    start_compaction, start_flush, start_fsync, start_gossip, start_health_endpoint,
+    start_metrics_endpoint, start_node_gossip_query_protocol, start_node_native_protocol,
+};
 use internal_protocol::InternalMessage;
+use keystore_cli::{load_keystore, run_keystore_command, run_password_command};
 use node::{GossipInformation, Node};
 
+mod admin;
+mod audit;
+mod chaos;
+mod cleanup;
+mod commitlog;
 mod data_parser;
 mod consistency;
 mod consistent_hashing;
+mod counters;
 mod encrypted_table;
 mod handler_nodes;
+mod hints;
+mod inflight;
 mod internal_protocol;
+mod internode;
+mod keystore_cli;
 mod lock_test;
 mod log;
+mod metrics;
 mod native_protocol;
 mod node;
+mod paging;
+mod paxos;
+mod placement;
 mod query_parser;
 mod replication_strategy;
+mod storage_format;
+mod thread_pool;
 mod query_builder;
+#[cfg(test)]
+mod test_cluster;
 
 fn connect_to_first_node(node: &Node, first_node_address: &str) {
     if let Ok(mut stream) = TcpStream::connect(first_node_address) {
@@ -49,7 +72,46 @@ fn connect_to_first_node(node: &Node, first_node_address: &str) {
     }
 }
 
-fn get_node() -> Result<Node, Box<(dyn std::error::Error)>> {
+/// Checks the config and the environment the node runs in before starting it, and loads
+/// the keys of the keystore if one is configured.
+///
+/// # Parameters
+/// - `config`: The config of the cluster.
+/// - `node_config`: The config of the node being started.
+///
+/// # Returns
+/// Every problem found, so they can all be fixed at once.
+fn startup_problems(config: &Config, node_config: &NodeConfig) -> Vec<String> {
+    let mut problems = config.validate().err().unwrap_or_default();
+
+    match load_keystore(&config.security) {
+        Ok(Some(keystore)) => use_keystore(&keystore),
+        Ok(None) => {
+            dotenv::dotenv().ok();
+            if env::var("DB_KEY").map_or(true, |key| key.is_empty()) {
+                problems.push(
+                    "neither a keystore nor DB_KEY is set, the node can't encrypt its tables"
+                        .to_string(),
+                );
+            }
+        }
+        Err(e) => problems.push(format!("can't load the keystore: {}", e)),
+    }
+
+    let storage = &config.storage;
+    for dir in [
+        storage.node_data_dir(&node_config.id),
+        storage.node_hints_dir(&node_config.id),
+        storage.node_commitlog_dir(&node_config.id),
+    ] {
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::read_dir(&dir)) {
+            problems.push(format!("can't use directory {}: {}", dir, e));
+        }
+    }
+    problems
+}
+
+fn get_node() -> Result<(Node, NodeConfig, Config), Box<(dyn std::error::Error)>> {
     let config = Config::new()?;
 
     let args: Vec<String> = env::args().collect();
@@ -76,33 +138,57 @@ fn get_node() -> Result<Node, Box<(dyn std::error::Error)>> {
 
     let node_config = &config.nodes[node_id];
 
-    let node_ip = custom_address.unwrap_or(&node_config.address);
-    let node = Node::new(
+    let problems = startup_problems(&config, node_config);
+    if !problems.is_empty() {
+        return Err(format!("Invalid configuration:\n  - {}", problems.join("\n  - ")).into());
+    }
+    log::configure(&config.logging)?;
+    storage_format::set_fsync_policy(config.storage.fsync_policy());
+
+    // Se anuncia la direccion de broadcast pero se escucha en la de listen
+    let node_ip = custom_address.map_or(node_config.broadcast_address(), String::as_str);
+    let node = Node::new_with_config(
         &node_config.id,
         node_ip,
         node_config.public_port,
         node_config.private_port,
-    );
-
-    if node_id != 0 {
-        let node_ip = custom_address.unwrap_or(&config.nodes[0].address);
-        let private_port = config.nodes[0].private_port;
-        let first_node_address = format!("{}:{}", node_ip, private_port);
-        connect_to_first_node(&node, &first_node_address);
-    } else {
-        for i in 1..config.nodes.len() {
-            let node_ip = custom_address.unwrap_or(&config.nodes[i].address);
-            let private_port = config.nodes[i].private_port;
-            let first_node_address = format!("{}:{}", node_ip, private_port);
-            connect_to_first_node(&node, &first_node_address);
+        config.node_settings(),
+    )
+    .with_listen_address(node_config.listen_address());
+    // Hasta terminar el warm-up el nodo se anuncia como Joining
+    node.start_warm_up();
+
+    // Un nodo se une al cluster por las semillas; una semilla le avisa a todos los demás
+    let seeds = config.cluster.seed_ids(&config.nodes);
+    let is_seed = seeds.contains(&node_config.id);
+    // Hasta recibir la tabla de gossip de otro nodo, un nodo que no es semilla no esta listo
+    if !is_seed {
+        node.start_joining();
+    }
+    for other in &config.nodes {
+        if other.id == node_config.id || !(is_seed || seeds.contains(&other.id)) {
+            continue;
         }
+        let node_ip = custom_address.map_or(other.broadcast_address(), String::as_str);
+        let first_node_address = format!("{}:{}", node_ip, other.private_port);
+        connect_to_first_node(&node, &first_node_address);
     }
 
-    Ok(node)
+    let node_config = node_config.clone();
+    Ok((node, node_config, config))
 }
 
 fn main() -> Result<(), Box<(dyn std::error::Error)>> {
-    let node = get_node()?;
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("keystore") {
+        return Ok(run_keystore_command(&args[2..])?);
+    }
+    if args.get(1).map(String::as_str) == Some("password") {
+        return Ok(run_password_command()?);
+    }
+
+    let (node, node_config, config) = get_node()?;
+    let cluster = &config.cluster;
 
     let node = Arc::new(node);
 
@@ -114,9 +200,21 @@ fn main() -> Result<(), Box<(dyn std::error::Error)>> {
     let gossip_handle = thread::spawn(move || start_node_gossip_query_protocol(node_clone_gossip));
 
     let node_clone_start_gossip = Arc::clone(&node);
-    start_gossip(node_clone_start_gossip, 1000);
+    start_gossip(node_clone_start_gossip, cluster.gossip_interval_ms);
     let node_clone_start_flush = Arc::clone(&node);
-    start_flush(node_clone_start_flush, 10000);
+    start_flush(node_clone_start_flush, cluster.flush_interval_ms);
+    if config.storage.fsync_policy() == FsyncPolicy::Periodic {
+        start_fsync(config.storage.fsync_interval_ms);
+    }
+    if config.compaction.enabled {
+        start_compaction(Arc::clone(&node), config.compaction.interval_ms);
+    }
+    if node_config.metrics_port != 0 {
+        start_metrics_endpoint(Arc::clone(&node), node_config.metrics_port);
+    }
+    if node_config.health_port != 0 {
+        start_health_endpoint(Arc::clone(&node), node_config.health_port);
+    }
     native_handle.join().unwrap();
     gossip_handle.join().unwrap();
 
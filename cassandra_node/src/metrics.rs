@@ -0,0 +1,542 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper bounds, in microseconds, of the buckets of every latency histogram. Latencies above
+/// the last bound fall in an extra overflow bucket.
+const BUCKET_BOUNDS_MICROS: [u64; 16] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// The operations whose count and latency are recorded by a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// A SELECT coordinated by the node.
+    Read,
+    /// An INSERT, UPDATE or DELETE coordinated by the node.
+    Write,
+    /// A request sent to another node.
+    InternodeRequest,
+    /// A message stored as a hint for a node that couldn't be reached.
+    HintStore,
+    /// A round of the gossip protocol.
+    GossipRound,
+    /// A flush of the keyspaces and tables to disk.
+    Flush,
+}
+
+impl Operation {
+    const ALL: [Operation; 6] = [
+        Operation::Read,
+        Operation::Write,
+        Operation::InternodeRequest,
+        Operation::HintStore,
+        Operation::GossipRound,
+        Operation::Flush,
+    ];
+
+    /// Name of the operation in the snapshots.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::InternodeRequest => "internode_request",
+            Operation::HintStore => "hint_store",
+            Operation::GossipRound => "gossip_round",
+            Operation::Flush => "flush",
+        }
+    }
+}
+
+/// The ways a message to another node can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerFailure {
+    /// The node couldn't be reached, or the connection broke while sending the message.
+    ConnectionFailure,
+    /// The node didn't answer in time.
+    Timeout,
+    /// The message was stored as a hint because it couldn't be delivered.
+    HintFallback,
+}
+
+impl fmt::Display for PeerFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerFailure::ConnectionFailure => write!(f, "Connection failure"),
+            PeerFailure::Timeout => write!(f, "Timeout"),
+            PeerFailure::HintFallback => write!(f, "Hint fallback"),
+        }
+    }
+}
+
+/// Failed messages to a single node.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PeerSnapshot {
+    pub connection_failures: u64,
+    pub timeouts: u64,
+    pub hint_fallbacks: u64,
+}
+
+/// A latency histogram with fixed buckets. Every counter is atomic, so recording never
+/// blocks the threads that serve requests.
+#[derive(Debug)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKET_BOUNDS_MICROS.len() + 1],
+    errors: AtomicU64,
+    sum_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            errors: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+            max_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, elapsed: Duration, success: bool) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKET_BOUNDS_MICROS
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MICROS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let buckets: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let count = buckets.iter().sum();
+        HistogramSnapshot {
+            count,
+            errors: self.errors.load(Ordering::Relaxed),
+            sum_micros: self.sum_micros.load(Ordering::Relaxed),
+            max_micros: self.max_micros.load(Ordering::Relaxed),
+            p50_micros: percentile(&buckets, count, 0.50),
+            p95_micros: percentile(&buckets, count, 0.95),
+            p99_micros: percentile(&buckets, count, 0.99),
+            buckets: BUCKET_BOUNDS_MICROS
+                .iter()
+                .map(|bound| Some(*bound))
+                .chain(std::iter::once(None))
+                .zip(buckets)
+                .map(|(le_micros, count)| BucketSnapshot { le_micros, count })
+                .collect(),
+        }
+    }
+}
+
+/// Estimates a percentile as the upper bound of the bucket where it falls.
+fn percentile(buckets: &[u64], count: u64, quantile: f64) -> u64 {
+    if count == 0 {
+        return 0;
+    }
+    let rank = ((count as f64) * quantile).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (i, bucket) in buckets.iter().enumerate() {
+        seen += bucket;
+        if seen >= rank {
+            return BUCKET_BOUNDS_MICROS.get(i).copied().unwrap_or(u64::MAX);
+        }
+    }
+    u64::MAX
+}
+
+/// A bucket of a histogram: how many operations took at most `le_micros`
+/// (`None` for the overflow bucket).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BucketSnapshot {
+    pub le_micros: Option<u64>,
+    pub count: u64,
+}
+
+/// The state of a histogram at a point in time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub errors: u64,
+    pub sum_micros: u64,
+    pub max_micros: u64,
+    pub p50_micros: u64,
+    pub p95_micros: u64,
+    pub p99_micros: u64,
+    pub buckets: Vec<BucketSnapshot>,
+}
+
+/// The state of every metric of a node at a point in time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    /// Histogram of each operation, by operation name.
+    #[serde(flatten)]
+    pub operations: BTreeMap<&'static str, HistogramSnapshot>,
+    /// Requests rejected with `Overloaded` because a worker pool was saturated.
+    pub shed_requests: u64,
+    /// Failed messages to each node, by node id.
+    pub peers: BTreeMap<String, PeerSnapshot>,
+}
+
+impl MetricsSnapshot {
+    /// Writes the histograms and counters in the Prometheus text format, with the latencies
+    /// in seconds.
+    ///
+    /// # Parameters
+    /// - `writer`: The page being built.
+    pub fn write_prometheus(&self, writer: &mut PrometheusWriter) {
+        let duration = "aero_operation_duration_seconds";
+        writer.family(
+            duration,
+            "histogram",
+            "Latency of the operations of the node.",
+        );
+        for (operation, histogram) in &self.operations {
+            let mut cumulative = 0;
+            for bucket in &histogram.buckets {
+                cumulative += bucket.count;
+                let le = match bucket.le_micros {
+                    Some(le_micros) => (le_micros as f64 / 1_000_000.0).to_string(),
+                    None => "+Inf".to_string(),
+                };
+                writer.sample(
+                    &format!("{}_bucket", duration),
+                    &[("operation", operation), ("le", &le)],
+                    cumulative as f64,
+                );
+            }
+            writer.sample(
+                &format!("{}_sum", duration),
+                &[("operation", operation)],
+                histogram.sum_micros as f64 / 1_000_000.0,
+            );
+            writer.sample(
+                &format!("{}_count", duration),
+                &[("operation", operation)],
+                histogram.count as f64,
+            );
+        }
+
+        let errors = "aero_operation_errors_total";
+        writer.family(errors, "counter", "Operations of the node that failed.");
+        for (operation, histogram) in &self.operations {
+            writer.sample(errors, &[("operation", operation)], histogram.errors as f64);
+        }
+
+        let shed = "aero_shed_requests_total";
+        writer.family(
+            shed,
+            "counter",
+            "Requests rejected because a worker pool was saturated.",
+        );
+        writer.sample(shed, &[], self.shed_requests as f64);
+
+        let failures = "aero_peer_failures_total";
+        writer.family(failures, "counter", "Messages to other nodes that failed.");
+        for (peer, snapshot) in &self.peers {
+            for (failure, count) in [
+                ("connection_failure", snapshot.connection_failures),
+                ("timeout", snapshot.timeouts),
+                ("hint_fallback", snapshot.hint_fallbacks),
+            ] {
+                writer.sample(
+                    failures,
+                    &[("peer", peer), ("failure", failure)],
+                    count as f64,
+                );
+            }
+        }
+    }
+}
+
+/// Builds a page of metrics in the Prometheus text exposition format.
+#[derive(Debug, Default)]
+pub struct PrometheusWriter {
+    page: String,
+}
+
+impl PrometheusWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a metric family, whose samples must follow.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the metric.
+    /// - `kind`: `counter`, `gauge` or `histogram`.
+    /// - `help`: What the metric measures.
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        self.page.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+    }
+
+    /// Adds a sample of the current family.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the sample, the family name plus a suffix for histograms.
+    /// - `labels`: The labels of the sample.
+    /// - `value`: The value of the sample.
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.page.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+                .collect();
+            self.page.push_str(&format!("{{{}}}", labels.join(",")));
+        }
+        self.page.push_str(&format!(" {}\n", value));
+    }
+
+    /// Returns the page.
+    pub fn finish(self) -> String {
+        self.page
+    }
+}
+
+/// Escapes the backslashes, quotes and newlines of a label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counts and latency histograms of the operations of a node.
+///
+/// A single instance is shared (behind an `Arc`) by the node, its internode client and its
+/// background threads.
+#[derive(Debug)]
+pub struct Metrics {
+    histograms: [Histogram; Operation::ALL.len()],
+    shed_requests: AtomicU64,
+    peers: Mutex<HashMap<String, PeerSnapshot>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Creates a `Metrics` with every counter in zero.
+    pub fn new() -> Self {
+        Metrics {
+            histograms: std::array::from_fn(|_| Histogram::new()),
+            shed_requests: AtomicU64::new(0),
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records an operation that took `elapsed`.
+    ///
+    /// # Parameters
+    /// - `operation`: The operation.
+    /// - `elapsed`: How long it took.
+    /// - `success`: Whether it succeeded; failures are also counted as errors.
+    pub fn record(&self, operation: Operation, elapsed: Duration, success: bool) {
+        self.histograms[operation as usize].record(elapsed, success);
+    }
+
+    /// Runs `f` and records how long it took. `f` returns whether the operation succeeded
+    /// along with its result.
+    ///
+    /// # Parameters
+    /// - `operation`: The operation.
+    /// - `f`: The operation to time.
+    ///
+    /// # Returns
+    /// The result of `f`.
+    pub fn time<T, E>(
+        &self,
+        operation: Operation,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let start = Instant::now();
+        let result = f();
+        self.record(operation, start.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Records a request rejected because a worker pool was saturated.
+    pub fn record_shed(&self) {
+        self.shed_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a message to another node that failed.
+    ///
+    /// # Parameters
+    /// - `node_id`: The id of the destination node.
+    /// - `failure`: How the message failed.
+    pub fn record_peer_failure(&self, node_id: &str, failure: PeerFailure) {
+        let Ok(mut peers) = self.peers.lock() else {
+            return;
+        };
+        let peer = peers.entry(node_id.to_string()).or_default();
+        match failure {
+            PeerFailure::ConnectionFailure => peer.connection_failures += 1,
+            PeerFailure::Timeout => peer.timeouts += 1,
+            PeerFailure::HintFallback => peer.hint_fallbacks += 1,
+        }
+    }
+
+    /// Returns the failed messages to each node, by node id.
+    pub fn peers_snapshot(&self) -> BTreeMap<String, PeerSnapshot> {
+        match self.peers.lock() {
+            Ok(peers) => peers
+                .iter()
+                .map(|(node_id, peer)| (node_id.clone(), peer.clone()))
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        }
+    }
+
+    /// Returns the current state of every metric.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            operations: Operation::ALL
+                .iter()
+                .map(|operation| {
+                    (
+                        operation.as_str(),
+                        self.histograms[*operation as usize].snapshot(),
+                    )
+                })
+                .collect(),
+            shed_requests: self.shed_requests.load(Ordering::Relaxed),
+            peers: self.peers_snapshot(),
+        }
+    }
+
+    /// Serializes the current state of every metric to JSON.
+    ///
+    /// # Returns
+    /// Ok(String) with the JSON, or Err(String) if it can't be serialized.
+    pub fn snapshot_json(&self) -> Result<String, String> {
+        serde_json::to_string(&self.snapshot())
+            .map_err(|e| format!("Error serializing metrics: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_histograms_are_cumulative() {
+        let metrics = Metrics::new();
+        metrics.record(Operation::Read, Duration::from_micros(80), true);
+        metrics.record(Operation::Read, Duration::from_millis(3), false);
+        metrics.record_peer_failure("node \"2\"", PeerFailure::Timeout);
+
+        let mut writer = PrometheusWriter::new();
+        metrics.snapshot().write_prometheus(&mut writer);
+        let page = writer.finish();
+
+        assert!(page.contains("# TYPE aero_operation_duration_seconds histogram\n"));
+        assert!(page.contains(
+            "aero_operation_duration_seconds_bucket{operation=\"read\",le=\"0.0001\"} 1\n"
+        ));
+        assert!(page.contains(
+            "aero_operation_duration_seconds_bucket{operation=\"read\",le=\"0.005\"} 2\n"
+        ));
+        assert!(page.contains(
+            "aero_operation_duration_seconds_bucket{operation=\"read\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(page.contains("aero_operation_duration_seconds_count{operation=\"read\"} 2\n"));
+        assert!(page.contains("aero_operation_errors_total{operation=\"read\"} 1\n"));
+        assert!(page
+            .contains("aero_peer_failures_total{peer=\"node \\\"2\\\"\",failure=\"timeout\"} 1\n"));
+    }
+
+    #[test]
+    fn test_record_counts_and_buckets() {
+        let metrics = Metrics::new();
+        metrics.record(Operation::Read, Duration::from_micros(80), true);
+        metrics.record(Operation::Read, Duration::from_millis(3), true);
+        metrics.record(Operation::Read, Duration::from_secs(20), false);
+
+        let snapshot = metrics.snapshot();
+        let reads = &snapshot.operations["read"];
+        assert_eq!(reads.count, 3);
+        assert_eq!(reads.errors, 1);
+        assert_eq!(reads.max_micros, 20_000_000);
+        assert_eq!(reads.buckets[0].count, 1);
+        assert_eq!(reads.buckets[5].count, 1);
+        assert_eq!(reads.buckets.last().unwrap().count, 1);
+        assert_eq!(reads.p50_micros, 5_000);
+        assert_eq!(snapshot.operations["write"].count, 0);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let metrics = Metrics::new();
+        for _ in 0..99 {
+            metrics.record(Operation::Flush, Duration::from_micros(200), true);
+        }
+        metrics.record(Operation::Flush, Duration::from_millis(40), true);
+
+        let snapshot = metrics.snapshot();
+        let flushes = &snapshot.operations["flush"];
+        assert_eq!(flushes.p50_micros, 250);
+        assert_eq!(flushes.p95_micros, 250);
+        assert_eq!(flushes.max_micros, 40_000);
+    }
+
+    #[test]
+    fn test_time_records_errors() {
+        let metrics = Metrics::new();
+        let result: Result<(), String> =
+            metrics.time(Operation::InternodeRequest, || Err("down".to_string()));
+        assert!(result.is_err());
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.operations["internode_request"].count, 1);
+        assert_eq!(snapshot.operations["internode_request"].errors, 1);
+    }
+
+    #[test]
+    fn test_snapshot_json() {
+        let metrics = Metrics::new();
+        metrics.record(Operation::GossipRound, Duration::from_millis(1), true);
+        let json: serde_json::Value =
+            serde_json::from_str(&metrics.snapshot_json().unwrap()).unwrap();
+        assert_eq!(json["gossip_round"]["count"], 1);
+        assert_eq!(json["hint_store"]["count"], 0);
+        assert_eq!(json["shed_requests"], 0);
+    }
+
+    #[test]
+    fn test_peer_failures_are_counted_per_node() {
+        let metrics = Metrics::new();
+        metrics.record_peer_failure("2", PeerFailure::ConnectionFailure);
+        metrics.record_peer_failure("2", PeerFailure::HintFallback);
+        metrics.record_peer_failure("3", PeerFailure::Timeout);
+
+        let peers = metrics.snapshot().peers;
+        assert_eq!(
+            peers["2"],
+            PeerSnapshot {
+                connection_failures: 1,
+                timeouts: 0,
+                hint_fallbacks: 1,
+            }
+        );
+        assert_eq!(peers["3"].timeouts, 1);
+    }
+}
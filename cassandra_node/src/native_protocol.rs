@@ -3,9 +3,11 @@ use common::security::EncryptionHandler;
 use common::frame::server_handle::ConnectionState;
 
 use crate::node::Node;
+use common::frame::messages::event::ClusterEvent;
 use std::io;
 use std::io::Error;
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
 
 /// Attempts to create a new `Connection` from the given `stream`.
@@ -33,21 +35,35 @@ impl common::frame::server_handle::Node for Node {
     ) -> Result<common::frame::messages::query_result::QueryResult, common::frame::messages::error::ErrorCode> {
         self.resend_query_as_internal_message(query, keyspace)
     }
+
+    fn prepare(&self, query_string: &str) -> Vec<u8> {
+        self.prepare_statement(query_string)
+    }
+
+    fn prepared_statement(&self, id: &[u8]) -> Option<String> {
+        self.prepared_statement(id)
+    }
 }
 
 struct Connection {
     stream: TcpStream,
+    peer_addr: SocketAddr,
     connection_state: ConnectionState,
     keyspace: Option<String>,
+    /// The user the client identified itself as when authenticating, checked against its
+    /// password if the node requires credentials.
+    user: Option<String>,
     encryption_handler: EncryptionHandler,
 }
 
 impl Connection {
     fn new(stream: TcpStream) -> io::Result<Self> {
         Ok(Self {
+            peer_addr: stream.peer_addr()?,
             stream,
             connection_state: ConnectionState::Uninitialized,
             keyspace: None,
+            user: None,
             encryption_handler: EncryptionHandler::new(23, 5),
         })
     }
@@ -63,21 +79,55 @@ impl Connection {
     fn handle_request(&mut self, request: Frame, node: Arc<Node>) -> Result<Frame, String> {
         match self.connection_state {
             ConnectionState::Uninitialized => {
-                Ok(request.handle_uninitialized(&mut self.connection_state))
+                Ok(request
+                    .handle_uninitialized(&mut self.connection_state, &mut self.encryption_handler))
             }
             ConnectionState::Ready => self.generate_response(request, node),
-            _ => Ok(request
-                .handle_authentication(&mut self.connection_state, &mut self.encryption_handler)),
+            // El primer token trae el usuario; si el nodo pide credenciales, se reemplaza por el
+            // que mandó la contraseña
+            ConnectionState::UnAuthenticated | ConnectionState::VerifyingCredentials => {
+                self.user = request.auth_user();
+                Ok(request.handle_authentication(
+                    &mut self.connection_state,
+                    &mut self.encryption_handler,
+                    node.auth(),
+                ))
+            }
+            _ => Ok(request.handle_authentication(
+                &mut self.connection_state,
+                &mut self.encryption_handler,
+                node.auth(),
+            )),
         }
     }
 
     fn generate_response(&mut self, request: Frame, node: Arc<Node>) -> Result<Frame, String> {
-        Ok(request.generate_response(node, &mut self.keyspace))
+        let keyspace = self.keyspace.clone();
+        let response = request.generate_response(Arc::clone(&node) as _, &mut self.keyspace);
+        // De una query preparada se registra el texto con los marcadores
+        let statement = match request.prepared_statement_id() {
+            Some(id) => node.prepared_statement(id),
+            None => request.query_string().map(str::to_string),
+        };
+        if let Some(statement) = statement {
+            node.audit(
+                self.user.as_deref(),
+                self.peer_addr,
+                keyspace.as_deref(),
+                &statement,
+                !response.is_error(),
+            );
+        }
+        Ok(response)
     }
 
     fn connection_loop(&mut self, node: Arc<Node>) -> Result<(), String> {
         match self.read() {
             Ok(request) => {
+                // Se suscribe antes de responder para no perder eventos
+                let registration = request
+                    .registered_events()
+                    .map(|event_types| (event_types.to_vec(), node.subscribe_events()));
                 let response = self.handle_request(request, node)?;
 
                 if let Err(e) = self.write(&response) {
@@ -87,13 +137,37 @@ impl Connection {
                     }
                 }
 
-                Ok(())
+                match registration {
+                    Some((event_types, events)) if !response.is_error() => {
+                        self.push_events(&event_types, events)
+                    }
+                    _ => Ok(()),
+                }
             }
 
             Err(e) => self.connection_error(e),
         }
     }
 
+    /// Sends the client the events of the types it registered for. The connection is only
+    /// used for events from then on, until the client closes it.
+    fn push_events(
+        &mut self,
+        event_types: &[String],
+        events: Receiver<ClusterEvent>,
+    ) -> Result<(), String> {
+        for event in events {
+            if !event_types.iter().any(|event_type| event_type == event.event_type()) {
+                continue;
+            }
+            if let Err(e) = self.write(&Frame::new_event(event)) {
+                println!("El cliente registrado para eventos ha cerrado la conexión.");
+                return Err(e.to_string());
+            }
+        }
+        Err("The node stopped publishing events".to_string())
+    }
+
     fn connection_error(&mut self, e: Error) -> Result<(), String> {
         if !is_legitimate_error(&e) {
             return Ok(());
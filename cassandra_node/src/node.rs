@@ -1,26 +1,67 @@
-use crate::consistency::Consistency;
+use crate::admin::{ClusterDescription, KeyspaceDescription, NodeHealth, NodeStatus, TokenRange};
+use crate::audit::AuditLog;
+use crate::inflight::{self, InFlightQuery, InFlightQueries, CANCEL_POLL_INTERVAL};
+use crate::consistency::{Consistency, ConsistencyError};
 use crate::consistent_hashing::ConsistentHash;
-use crate::data_parser::{load_keyspaces, load_tables_path, load_gossip_table};
-use crate::encrypted_table::table::Table;
+use crate::counters::{merge_shards, total, CounterMutation, CounterStore, Shards, COUNTER_SHARDS_FILE};
+use crate::data_parser::{load_keyspaces, load_tables_path, load_gossip_table, quarantine_file, save_gossip_peers, KeyspacesDocument};
+use crate::encrypted_table::table::{Mutation, RowPosition, Table};
 use crate::encrypted_table::EncryptedTable;
-use crate::internal_protocol::InternalMessage;
-use crate::log::Logger;
-use crate::query_parser::expression::{extract_value_supposing_column_equals_value, Expression};
+use crate::internal_protocol::{new_trace_id, InternalMessage};
+use crate::chaos::FaultInjector;
+use crate::cleanup::{CleanupLog, PENDING_CLEANUPS_FILE};
+use crate::commitlog::CommitLog;
+use crate::hints::{load_hints, Hint};
+use crate::internode::{Delivery, ExchangeError, InternodeClient};
+use crate::log::{self, LogLevel, Logger};
+use crate::metrics::{Metrics, Operation, PrometheusWriter};
+use crate::paging::{PageRequest, PagingState, SELECT_PAGE_OPCODE};
+use crate::placement::PlacementCache;
+use crate::paxos::{Ballot, PaxosStore, Prepare, Promise, Proposal, ProposalMessage, PAXOS_STATE_FILE};
+use crate::query_parser::expression::{
+    evaluate_expression, evaluate_expression_with_types, extract_equalities, Expression, Operand,
+};
 use crate::query_parser::{parse_instruction, ParsedQuery};
-use crate::replication_strategy::ReplicationStrategy;
-use crate::query_builder::{insert_message_from_row_and_tablename, create_keyspace_query, create_table_query, add_timestamp_to_insert_message, add_timestamp_to_update_message};
+use crate::replication_strategy::{OversizedReplication, ReplicationStrategy};
+use crate::storage_format::{self, FileKind};
+use crate::thread_pool::{PoolError, ThreadPool};
+use crate::query_builder::{insert_message_from_row_and_tablename, insert_message_from_rows, update_message, delete_message, create_keyspace_query, create_table_query, add_timestamp_to_insert_message, add_timestamp_to_update_message};
 use chrono::{NaiveDateTime, TimeZone, Utc};
-use common::frame::messages::error::ErrorCode;
+use common::frame::messages::error::{ErrorCode, WriteType};
+use common::client_manager::partition_token;
+use common::frame::messages::event::ClusterEvent;
+use common::config::{AuthConfig, ChaosConfig, NodeSettings, OverloadConfig, StorageConfig, TimeoutConfig};
 use common::frame::messages::query::Query;
+use common::frame::messages::prepared::{prepared_id, quote, Value};
+use common::frame::messages::query_result::row_set::{Column, ColumnType, RowSet};
 use common::frame::messages::query_result::QueryResult;
 use rand::{rng, Rng};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
 use std::f64::consts::E;
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
+use tracing::{debug_span, info_span, Span};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 use std::{fs, vec};
 
+/// Number of Paxos rounds a coordinator tries before answering a conditional statement with a
+/// timeout.
+const MAX_CAS_ATTEMPTS: u32 = 5;
+/// Base of the random wait between Paxos rounds that lost against another coordinator.
+const CAS_BACKOFF_MILLIS: u64 = 10;
+/// The nodes listen on all the interfaces unless configured otherwise.
+const DEFAULT_LISTEN_ADDRESS: &str = "0.0.0.0";
+/// Keyspace of the read-only tables that describe the schema, answered by each node from its
+/// own schema: `keyspaces`, `tables` and `columns`.
+const SYSTEM_SCHEMA: &str = "system_schema";
+/// Keyspace of the read-only tables that describe the cluster as seen by the coordinator:
+/// `peers`.
+const SYSTEM: &str = "system";
+
 //Comunicacion interna entre nodos
 //Remplazamos el uso de serde con este mini protocolo interno
 // pub enum InternalMessage {
@@ -58,6 +99,19 @@ pub struct GossipInformation {
     pub status: String,
 }
 
+impl GossipInformation {
+    /// Returns the address clients connect to the node at, `ip:port`.
+    pub fn native_address(&self) -> String {
+        format!("{}:{}", self.ip, self.port_native_protocol)
+    }
+}
+
+/// A table shared between threads, with its own lock.
+type SharedTable = Arc<RwLock<EncryptedTable>>;
+
+/// The values of a row, by column.
+type Row = HashMap<String, String>;
+
 /// Represents the node in our distributed system
 ///
 ///  # Fields
@@ -71,25 +125,70 @@ pub struct GossipInformation {
 ///    predictable and load-balanced manner.
 /// - `data`: Stores the data managed by this node. The keys are table names in the format
 ///    `keyspace_name.table_name`. Note: The keyspace name must not contain periods (`.`).
+///   Each table has its own lock, so operations on different tables don't block each other;
+///   the map lock is only held to look up, create or load tables.
 /// - `keyspaces`: Configuration for keyspaces in the node. Maps keyspace names to their
 ///    replication strategies.
 /// - `hints`: A shared structure for holding unacknowledged write hints for eventual consistency
 ///    during node outages. Keys represent nodes for which the hints are maintained.
 /// - `logger`: A logger instance for tracking node activity and debugging.
-///
+/// - `storage`: Directories where the node persists its data and hints.
+/// - `keyspaces_dirty`: Whether the keyspaces changed since they were last flushed to disk.
+/// - `overload`: Sizes of the worker pools, past which requests are rejected as `Overloaded`.
+/// - `metrics`: Counts and latencies of the reads, writes, internode requests, hints, gossip
+///   rounds and flushes of the node.
 #[derive(Clone, Debug)]
 pub struct Node {
     id: String,
     ip: String,
+    /// Address the ports are bound to; `ip` is the one advertised to the other nodes.
+    listen_address: String,
     port_native_protocol: u16,
     port_gossip_query: u16,
 
     pub gossip_table: Arc<RwLock<Vec<GossipInformation>>>,
     consistent_hash: ConsistentHash,
-    data: Arc<RwLock<HashMap<String, EncryptedTable>>>,
+    /// The replicas of each token range of each keyspace, emptied when the ring changes.
+    placement: Arc<PlacementCache>,
+    data: Arc<RwLock<HashMap<String, SharedTable>>>,
     keyspaces: Arc<RwLock<HashMap<String, ReplicationStrategy>>>,
     hints: Arc<RwLock<HashMap<String, Vec<InternalMessage>>>>,
     logger: Logger,
+    storage: StorageConfig,
+    overload: OverloadConfig,
+    timeouts: TimeoutConfig,
+    /// Consistency used when a client asks for a level the nodes don't support.
+    default_consistency: Consistency,
+    /// What to do with a `CREATE KEYSPACE` whose replication factor is larger than the cluster.
+    oversized_replication: OversizedReplication,
+    keyspaces_dirty: Arc<AtomicBool>,
+    workers: Arc<ThreadPool>,
+    internode: Arc<InternodeClient>,
+    metrics: Arc<Metrics>,
+    /// Promises and accepted proposals of the lightweight transactions this node is a replica for.
+    paxos: Arc<PaxosStore>,
+    /// The shards of the counter columns this node is a replica for.
+    counters: Arc<CounterStore>,
+    /// The partitions that don't belong to this node anymore, kept until their replicas
+    /// confirm they received them.
+    cleanups: Arc<CleanupLog>,
+    /// The writes applied in memory since the last flush.
+    commit_log: Arc<CommitLog>,
+    /// Whether the node finished warming up and accepts client traffic.
+    ready: Arc<AtomicBool>,
+    /// Whether the node received the gossip table of another node of the cluster.
+    joined: Arc<AtomicBool>,
+    /// Where the schema changes and data mutations of the clients are recorded.
+    audit: Arc<AuditLog>,
+    /// The users the clients must authenticate as, with their passwords.
+    auth: Arc<AuthConfig>,
+    /// The queries this node is coordinating.
+    inflight: Arc<InFlightQueries>,
+    /// The statements prepared by the clients, by id. They are not persisted: after a
+    /// restart the clients prepare them again.
+    prepared_statements: Arc<RwLock<HashMap<Vec<u8>, String>>>,
+    /// The client connections registered for the changes of the cluster.
+    event_subscribers: Arc<RwLock<Vec<mpsc::Sender<ClusterEvent>>>>,
 }
 
 impl Node {
@@ -105,6 +204,86 @@ impl Node {
     /// A fully initialized `Node` with default values for its components.
     ///
     pub fn new(id: &str, ip: &str, port_native_protocol: u16, port_gossip_query: u16) -> Self {
+        Self::new_with_storage(
+            id,
+            ip,
+            port_native_protocol,
+            port_gossip_query,
+            StorageConfig::default(),
+        )
+    }
+
+    /// Creates a new instance of a `Node` that stores its files in the given directories.
+    ///
+    /// #Parameters
+    /// - `id`: A unique identifier for this node.
+    /// - `ip`: The IP address of the node as a string.
+    /// - `port_native_protocol`: The port number for the native client protocol.
+    /// - `port_gossip_query`: The port number used for the gossip communication protocol.
+    /// - `storage`: The data and hints directories.
+    ///
+    /// # Returns
+    /// A fully initialized `Node`, with the data previously stored in `storage` loaded.
+    ///
+    pub fn new_with_storage(
+        id: &str,
+        ip: &str,
+        port_native_protocol: u16,
+        port_gossip_query: u16,
+        storage: StorageConfig,
+    ) -> Self {
+        Self::new_with_config(
+            id,
+            ip,
+            port_native_protocol,
+            port_gossip_query,
+            NodeSettings {
+                storage,
+                ..NodeSettings::default()
+            },
+        )
+    }
+
+    /// Creates a new instance of a `Node` with the settings of the config file.
+    ///
+    /// #Parameters
+    /// - `id`: A unique identifier for this node.
+    /// - `ip`: The IP address of the node as a string.
+    /// - `port_native_protocol`: The port number for the native client protocol.
+    /// - `port_gossip_query`: The port number used for the gossip communication protocol.
+    /// - `settings`: The directories, worker pool sizes, timeouts and cluster settings.
+    ///
+    /// # Returns
+    /// A fully initialized `Node`, with the data previously stored in its data directory loaded.
+    ///
+    pub fn new_with_config(
+        id: &str,
+        ip: &str,
+        port_native_protocol: u16,
+        port_gossip_query: u16,
+        settings: NodeSettings,
+    ) -> Self {
+        let NodeSettings {
+            storage,
+            overload,
+            cluster,
+            timeouts,
+            audit,
+            auth,
+            chaos,
+        } = settings;
+        let default_consistency = Consistency::from_str_to_enum(&cluster.default_consistency)
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}, using ONE", e, cluster.default_consistency);
+                Consistency::One
+            });
+        let oversized_replication =
+            OversizedReplication::from_str_to_enum(&cluster.oversized_replication).unwrap_or_else(
+                |e| {
+                    eprintln!("{}: {}, using REJECT", e, cluster.oversized_replication);
+                    OversizedReplication::Reject
+                },
+            );
         let gossip_information = GossipInformation {
             node_id: id.to_string(),
             ip: ip.to_string(),
@@ -114,21 +293,78 @@ impl Node {
             status: "Live".to_string(),
         };
 
-        let gossip_table = vec![gossip_information];
+        let gossip_table = Arc::new(RwLock::new(vec![gossip_information]));
+        let hints_dir = storage.node_hints_dir(id);
+        let hints = Arc::new(RwLock::new(load_hints(&hints_dir)));
+        let metrics = Arc::new(Metrics::new());
+        let internode = InternodeClient::new(
+            id,
+            Arc::clone(&gossip_table),
+            Arc::clone(&hints),
+            &hints_dir,
+            Logger::new(id),
+            Arc::clone(&metrics),
+            timeouts.internal_request(),
+        );
+        internode.set_faults(FaultInjector::new(id, &chaos));
+
+        let paxos = PaxosStore::load(&format!(
+            "{}/{}",
+            storage.node_data_dir(id),
+            PAXOS_STATE_FILE
+        ));
+        let counters = CounterStore::load(&format!(
+            "{}/{}",
+            storage.node_data_dir(id),
+            COUNTER_SHARDS_FILE
+        ));
+        let cleanups = CleanupLog::load(&format!(
+            "{}/{}",
+            storage.node_data_dir(id),
+            PENDING_CLEANUPS_FILE
+        ));
+        let commit_log = CommitLog::open(&storage.node_commitlog_dir(id));
 
         let node = Node {
             id: id.to_string(),
             ip: ip.to_string(),
+            listen_address: DEFAULT_LISTEN_ADDRESS.to_string(),
             port_native_protocol,
             port_gossip_query,
-            gossip_table: Arc::new(RwLock::new(gossip_table)),
+            gossip_table,
             consistent_hash: ConsistentHash::new(),
+            placement: Arc::new(PlacementCache::new()),
             data: Arc::new(RwLock::new(HashMap::new())),
             keyspaces: Arc::new(RwLock::new(HashMap::new())),
-            hints: Arc::new(RwLock::new(HashMap::new())),
+            hints,
             logger: Logger::new(id),
+            storage,
+            keyspaces_dirty: Arc::new(AtomicBool::new(false)),
+            workers: Arc::new(ThreadPool::new(
+                &format!("{}-workers", id),
+                overload.worker_threads,
+                overload.worker_queue_capacity,
+            )),
+            overload,
+            timeouts,
+            default_consistency,
+            oversized_replication,
+            internode: Arc::new(internode),
+            metrics,
+            paxos: Arc::new(paxos),
+            counters: Arc::new(counters),
+            cleanups: Arc::new(cleanups),
+            commit_log: Arc::new(commit_log),
+            ready: Arc::new(AtomicBool::new(true)),
+            joined: Arc::new(AtomicBool::new(true)),
+            audit: Arc::new(AuditLog::new(id, audit)),
+            auth: Arc::new(auth),
+            inflight: Arc::new(InFlightQueries::default()),
+            prepared_statements: Arc::new(RwLock::new(HashMap::new())),
+            event_subscribers: Arc::new(RwLock::new(Vec::new())),
         };
         node.load_data();
+        node.replay_commit_log();
 
         node
     }
@@ -144,6 +380,247 @@ impl Node {
         self.logger.clone()
     }
 
+    /// Returns the sizes of the worker pools of this node.
+    pub fn get_overload_config(&self) -> &OverloadConfig {
+        &self.overload
+    }
+
+    /// Runs a job in the node's worker pool, used for requests to other nodes. If the pool is
+    /// saturated the job is dropped and counted as a shed request.
+    ///
+    /// # Parameters
+    /// - `job`: The closure to run.
+    ///
+    /// # Returns
+    /// `Ok(())` if the job was queued, or `Err(ErrorCode::Overloaded)` if it was shed.
+    fn run_in_background<F>(&self, job: F) -> Result<(), ErrorCode>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // El trabajo corre dentro del span de quien lo encola, para seguir la causalidad, y ve
+        // si su query fue cancelada
+        let span = Span::current();
+        let query = inflight::current_query();
+        let job = move || span.in_scope(|| inflight::run_for_query(query, job));
+        match self.workers.try_execute(job) {
+            Ok(()) => Ok(()),
+            Err(PoolError::Saturated) => {
+                self.metrics.record_shed();
+                let _ = self
+                    .logger
+                    .warn("Worker pool saturated, shedding request to other nodes");
+                Err(ErrorCode::Overloaded)
+            }
+            Err(PoolError::ShutDown) => {
+                let _ = self.logger.error("Worker pool is shut down");
+                Err(ErrorCode::ServerError)
+            }
+        }
+    }
+
+    // ------------------------ Warm-up ------------------------
+
+    /// Starts warming up the node in the background: the tables loaded from disk decrypt their
+    /// partitions into memory, one thread per table. Until it finishes the node gossips itself
+    /// as `Joining`, so the other nodes don't send it requests, and doesn't accept clients.
+    ///
+    /// # Returns
+    /// The handle of the thread doing the warm-up.
+    pub fn start_warm_up(&self) -> thread::JoinHandle<()> {
+        self.ready.store(false, Ordering::SeqCst);
+        self.set_own_status();
+        let node = self.clone();
+        thread::spawn(move || node.warm_up())
+    }
+
+    /// Returns whether the node finished warming up and accepts client traffic.
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    /// Returns the users the clients must authenticate as, none if any client can connect.
+    pub fn auth(&self) -> &AuthConfig {
+        &self.auth
+    }
+
+    /// Records a statement of a client in the audit log of the node, if it changes the
+    /// schema or the data of an audited keyspace.
+    ///
+    /// # Parameters
+    /// - `user`: The user the client identified itself as.
+    /// - `source`: The address of the client.
+    /// - `keyspace`: The keyspace of the connection.
+    /// - `statement`: The text of the statement.
+    /// - `success`: Whether the statement was answered without an error.
+    pub fn audit(
+        &self,
+        user: Option<&str>,
+        source: SocketAddr,
+        keyspace: Option<&str>,
+        statement: &str,
+        success: bool,
+    ) {
+        if let Err(e) = self.audit.record(user, source, keyspace, statement, success) {
+            let _ = self
+                .logger
+                .error(format!("Error writing the audit log: {}", e).as_str());
+        }
+    }
+
+    /// Stores a statement prepared by a client.
+    ///
+    /// # Returns
+    /// The id the clients execute the statement with.
+    pub fn prepare_statement(&self, query_string: &str) -> Vec<u8> {
+        let id = prepared_id(query_string);
+        if let Ok(mut prepared_statements) = self.prepared_statements.write() {
+            prepared_statements.insert(id.clone(), query_string.to_string());
+        }
+        id
+    }
+
+    /// Returns the statement prepared with the given id, `None` if it was prepared before the
+    /// node restarted.
+    pub fn prepared_statement(&self, id: &[u8]) -> Option<String> {
+        self.prepared_statements.read().ok()?.get(id).cloned()
+    }
+
+    /// Registers a client connection for the changes of the cluster this node sees.
+    ///
+    /// # Returns
+    /// The receiver of the events, until it is dropped.
+    pub fn subscribe_events(&self) -> mpsc::Receiver<ClusterEvent> {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut subscribers) = self.event_subscribers.write() {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    /// Sends an event to every registered client connection, forgetting the closed ones.
+    fn publish_event(&self, event: ClusterEvent) {
+        tracing::debug!(event = ?event, "publishing cluster event");
+        if let Ok(mut subscribers) = self.event_subscribers.write() {
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Returns the queries this node is coordinating, the oldest first.
+    pub fn inflight_queries(&self) -> Vec<InFlightQuery> {
+        self.inflight.list()
+    }
+
+    /// Aborts a query this node is coordinating: it stops waiting for the replicas and
+    /// returns an error to the client.
+    ///
+    /// # Parameters
+    /// - `query_id`: The id of the query, as listed by `inflight_queries`.
+    ///
+    /// # Returns
+    /// The killed query id, or an error if the node isn't coordinating it.
+    pub fn kill_query(&self, query_id: &str) -> Result<String, String> {
+        if !self.inflight.kill(query_id) {
+            return Err(format!("Query {} is not running on {}", query_id, self.id));
+        }
+        let _ = self.logger.log_traced(query_id, "Query killed by an admin request");
+        Ok(query_id.to_string())
+    }
+
+    /// Marks the node as not joined until it receives the gossip table of another node.
+    /// Seeds don't need to call it, they can form a cluster on their own.
+    pub fn start_joining(&self) {
+        self.joined.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns the liveness and readiness of the node: it is ready when it finished warming
+    /// up and joined the cluster.
+    pub fn health(&self) -> NodeHealth {
+        let data_loaded = self.is_ready();
+        let gossip_joined = self.joined.load(Ordering::SeqCst);
+        NodeHealth {
+            node_id: self.id.clone(),
+            live: true,
+            ready: data_loaded && gossip_joined,
+            data_loaded,
+            gossip_joined,
+        }
+    }
+
+    fn warm_up(&self) {
+        let _span = info_span!("warm_up", node = %self.id).entered();
+        let started = Instant::now();
+        let tables = self.get_tables().unwrap_or_default();
+        let partitions: usize = thread::scope(|scope| {
+            let handles: Vec<_> = tables
+                .iter()
+                .map(|(table_name, table)| {
+                    let span = debug_span!("warm_up_table", table = %table_name);
+                    scope.spawn(move || {
+                        let _span = span.entered();
+                        match table.read() {
+                            Ok(table) => table.warm_up(),
+                            Err(_) => 0,
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or(0))
+                .sum()
+        });
+
+        self.ready.store(true, Ordering::SeqCst);
+        self.set_own_status();
+        tracing::info!(partitions, tables = tables.len(), "warm-up finished");
+        let _ = self.logger.log(
+            format!(
+                "Warm-up finished in {:?}: {} partitions of {} tables loaded, accepting clients",
+                started.elapsed(),
+                partitions,
+                tables.len()
+            )
+            .as_str(),
+        );
+    }
+
+    /// Returns the status this node gossips about itself.
+    fn own_status(&self) -> &'static str {
+        if self.is_ready() {
+            "Live"
+        } else {
+            "Joining"
+        }
+    }
+
+    /// Updates the status of this node in its gossip table, so the change is gossiped on the
+    /// next round.
+    fn set_own_status(&self) {
+        if let Ok(mut gossip_table) = self.gossip_table.write() {
+            for gossip_info in gossip_table.iter_mut() {
+                if gossip_info.node_id == self.id {
+                    if gossip_info.status != self.own_status() {
+                        self.placement.invalidate();
+                    }
+                    gossip_info.status = self.own_status().to_string();
+                    gossip_info.last_heartbeat = Utc::now().timestamp();
+                }
+            }
+        }
+    }
+
+    // ------------------------ Storage ------------------------
+
+    /// Returns the directory where this node stores its tables, keyspaces and gossip table.
+    pub fn data_dir(&self) -> String {
+        self.storage.node_data_dir(&self.id)
+    }
+
+    /// Returns the directories configured for this node.
+    pub fn get_storage(&self) -> &StorageConfig {
+        &self.storage
+    }
+
     // ------------------------ Debug ------------------------
 
     /// Displays the contents of all tables stored in the node.
@@ -152,8 +629,8 @@ impl Node {
     /// and calls the `show` method on each `EncryptedTable` to display its contents.
     ///
     pub fn show(&self) {
-        let data = match self.data.read() {
-            Ok(data) => data.clone(),
+        let tables = match self.get_tables() {
+            Ok(tables) => tables,
 
             Err(e) => {
                 eprintln!("Error reading data: {}", e);
@@ -161,8 +638,10 @@ impl Node {
             }
         };
 
-        for table in data.values() {
-            table.show();
+        for (_, table) in tables {
+            if let Ok(table) = table.read() {
+                table.show();
+            }
         }
     }
 
@@ -176,16 +655,9 @@ impl Node {
     /// An `Option` containing the requested table if it exists, or `None` if it does not.
     ///
     pub fn get_table(&self, keyspace_name: &str, table_name: &str) -> Option<Table> {
-        let data = match self.data.read() {
-            Ok(data) => data.clone(),
-
-            Err(_) => {
-                return None;
-            }
-        };
-
-        data.get(format!("{}.{}", keyspace_name, table_name).as_str())
-            .map(|table| table.get_table())
+        let table = self.get_shared_table(keyspace_name, table_name).ok()?;
+        let table = table.read().ok()?;
+        Some(table.get_table())
     }
 
     // ------------------------ Getter ------------------------
@@ -221,18 +693,51 @@ impl Node {
         }
     }
 
-    fn get_data(&self) -> Result<HashMap<String, EncryptedTable>, String> {
+    /// Returns a handle to every table. Only the map lock is taken, and only while the
+    /// handles are cloned; each table must then be locked on its own.
+    fn get_tables(&self) -> Result<Vec<(String, SharedTable)>, String> {
         match self.data.read() {
-            Ok(data) => Ok(data.clone()),
+            Ok(data) => Ok(data
+                .iter()
+                .map(|(name, table)| (name.clone(), Arc::clone(table)))
+                .collect()),
             Err(_) => Err("Failed locking data".to_string()),
         }
     }
 
+    /// Returns a handle to the given table, without locking it.
+    fn get_shared_table(&self, keyspace_name: &str, table_name: &str) -> Result<SharedTable, String> {
+        let data = match self.data.read() {
+            Ok(data) => data,
+            Err(_) => return Err("Error locking data".to_string()),
+        };
+        match data.get(&format!("{}.{}", keyspace_name, table_name)) {
+            Some(table) => Ok(Arc::clone(table)),
+            None => Err("Table not found".to_string()),
+        }
+    }
+
     /// Retrieves the IP address of this node.
     ///
     pub fn get_ip(&self) -> &str {
         &self.ip
     }
+
+    /// Retrieves the address this node binds its ports to.
+    ///
+    pub fn get_listen_address(&self) -> &str {
+        &self.listen_address
+    }
+
+    /// Binds the ports of the node to `listen_address` instead of all the interfaces, while
+    /// the other nodes keep dialing the advertised IP.
+    ///
+    /// # Parameters
+    /// - `listen_address`: The local address to bind to.
+    pub fn with_listen_address(mut self, listen_address: &str) -> Self {
+        self.listen_address = listen_address.to_string();
+        self
+    }
     /// Retrieves the unique identifier (ID) of this node.
     ///
     pub fn get_id(&self) -> &str {
@@ -259,16 +764,41 @@ impl Node {
                 return;
             }
         };
+        let previous_membership = gossip_membership(&local_gossip_table);
+        if received_gossip_table.iter().any(|info| info.node_id != self.id) {
+            self.joined.store(true, Ordering::SeqCst);
+        }
         let mut new_node_detected = false;
+        let mut node_revived = false;
+        let mut node_died = false;
         let mut new_nodes_info = vec![];
+        let mut events = vec![];
         for gossip_info in received_gossip_table.iter().cloned() {
             let mut found = false;
             for local_gossip_info in local_gossip_table.iter_mut() {
                 if local_gossip_info.node_id == gossip_info.node_id {
                     found = true;
                     if local_gossip_info.last_heartbeat < gossip_info.last_heartbeat {
-                        if local_gossip_info.status == "Dead" && gossip_info.status == "Live" {
-                            // Si el nodo estaba muerto y ahora esta vivo, enviamos hints
+                        if local_gossip_info.status != gossip_info.status {
+                            tracing::info!(
+                                peer = %gossip_info.node_id,
+                                from = %local_gossip_info.status,
+                                to = %gossip_info.status,
+                                "peer status changed"
+                            );
+                            if gossip_info.node_id != self.id {
+                                if let Some(event) =
+                                    status_event(&local_gossip_info.status, &gossip_info)
+                                {
+                                    events.push(event);
+                                }
+                            }
+                        }
+                        if local_gossip_info.status != "Dead" && gossip_info.status == "Dead" {
+                            node_died = true;
+                        }
+                        if local_gossip_info.status != "Live" && gossip_info.status == "Live" {
+                            // Si el nodo estaba muerto o arrancando y ahora esta vivo, enviamos hints
                             let _ = self.logger.log(
                                 format!(
                                     "Node {} is marked live again, sending hints",
@@ -276,14 +806,15 @@ impl Node {
                                 )
                                 .as_str(),
                             );
+                            node_revived = true;
                             // mando a un thread para que mande los hints
-                            let self_arc = Arc::new(self.clone());
+                            let internode = Arc::clone(&self.internode);
                             let gossip_info_cloned = gossip_info.clone();
-                            std::thread::spawn(move || {
-                                self_arc.send_hints(
-                                    gossip_info_cloned.node_id,
-                                    gossip_info_cloned.ip,
-                                    gossip_info_cloned.port_gossip_query,
+                            let _ = self.run_in_background(move || {
+                                internode.send_hints(
+                                    &gossip_info_cloned.node_id,
+                                    &gossip_info_cloned.ip,
+                                    &gossip_info_cloned.port_gossip_query,
                                 );
                             });
                         }
@@ -294,46 +825,72 @@ impl Node {
                 }
             }
             if !found {
+                tracing::info!(peer = %gossip_info.node_id, status = %gossip_info.status, "new peer");
                 local_gossip_table.push(gossip_info.clone());
+                if gossip_info.node_id != self.id {
+                    events.push(ClusterEvent::NewNode(gossip_info.native_address()));
+                }
                 new_node_detected = true;
                 new_nodes_info.push(gossip_info.clone());
             }
         }
         local_gossip_table.sort_by(|a, b| a.node_id.cmp(&b.node_id));
-        self.flush_gossip_table(local_gossip_table.to_vec());
+        // Los heartbeats cambian en cada ronda; solo se persisten los pares cuya membresía cambió.
+        let membership = gossip_membership(&local_gossip_table);
+        if membership != previous_membership {
+            // Con el lock tomado, nadie calcula réplicas con la tabla anterior
+            self.placement.invalidate();
+            let changed_peers: Vec<GossipInformation> = local_gossip_table
+                .iter()
+                .zip(&membership)
+                .filter(|(_, peer_membership)| !previous_membership.contains(peer_membership))
+                .map(|(gossip_info, _)| gossip_info.clone())
+                .collect();
+            self.flush_gossip_table(&changed_peers);
+        }
 
         std::mem::drop(local_gossip_table);
 
+        for event in events {
+            self.publish_event(event);
+        }
+        if node_died {
+            self.warn_under_replicated_keyspaces();
+        }
+
         if new_node_detected {
             let _ = self.logger.log(
                 "New node detected, reassigning data...."
             );
             // Reassign data
             self.reassign_data(new_nodes_info);
+        } else if node_revived {
+            // Las particiones que esperaban a un nodo caído se le vuelven a enviar
+            self.stream_pending_cleanups(&new_trace_id());
         }
     }
 
-    /// Flushes the gossip table to disk so it can be retrieved after a node restart.
-    fn flush_gossip_table(&self, local_gossip_table: Vec<GossipInformation>) {
-        // Write to disk every information of the gossip table
-        let dir = format!("./data/{}", self.id);
-        let file = format!("{}/gossip_table", dir);
-        if let Err(e) = fs::create_dir_all(&dir) {
-            eprintln!("Error creating directory: {}", e);
-        }
-        let json = match serde_json::to_string(&local_gossip_table) {
-            Ok(json) => json,
-            Err(e) => {
-                eprintln!("Error serializing gossip table: {}", e);
-                return;
-            }
-        };
-        if let Err(e) = fs::write(&file, json) {
+    /// Flushes the given entries of the gossip table to disk so they can be retrieved after a
+    /// node restart. Each peer has its own file, so the unchanged peers are not rewritten.
+    ///
+    /// # Parameters
+    /// - `changed_peers`: The entries that changed since they were last flushed.
+    fn flush_gossip_table(&self, changed_peers: &[GossipInformation]) {
+        if let Err(e) = save_gossip_peers(&self.data_dir(), changed_peers) {
             eprintln!("Error writing gossip table to disk: {}", e);
         }
     }
 
+    /// Sends the schema to the new nodes and records the partitions that don't belong to this
+    /// node anymore, which are deleted only once their replicas confirm they received them.
+    ///
+    /// # Parameters
+    /// - `new_nodes`: The nodes that joined the cluster.
     fn reassign_data(&self, new_nodes: Vec<GossipInformation>) {
+        let trace_id = new_trace_id();
+        let _ = self
+            .logger
+            .log_traced(&trace_id, "Reassigning data to the new nodes");
         let keyspaces = match self.get_keyspaces() {
             Ok(keyspaces) => keyspaces,
             Err(_) => {
@@ -341,7 +898,7 @@ impl Node {
                 return;
             }
         };
-        let data = match self.get_data() {
+        let data = match self.get_tables() {
             Ok(data) => data,
             Err(_) => {
                 eprintln!("Error getting data");
@@ -356,20 +913,25 @@ impl Node {
                 opcode: 0,
                 body,
                 keyspace_name: "".to_string(),
+                trace_id: trace_id.clone(),
             };
             for node_info in &new_nodes {
-                let _ = send_internal_message_and_return_response(&create_keyspace_message, &node_info.ip, &node_info.port_gossip_query);
+                let _ = self.internode.send_to(&create_keyspace_message, node_info);
             }
-            for (_, table) in &data {
-                let body = create_table_query(&table.get_table());
-                let create_table_message = InternalMessage::Query {
-                    opcode: 1,
-                    body,
-                    keyspace_name: "".to_string(),
-                };
-                for node_info in &new_nodes {
-                    let _ = send_internal_message_and_return_response(&create_table_message, &node_info.ip, &node_info.port_gossip_query);
-                }
+        }
+        for (_, table) in &data {
+            let (body, keyspace_name) = match table.read() {
+                Ok(table) => (create_table_query(&table.get_table()), table.get_keyspace_name()),
+                Err(_) => continue,
+            };
+            let create_table_message = InternalMessage::Query {
+                opcode: 1,
+                body,
+                keyspace_name,
+                trace_id: trace_id.clone(),
+            };
+            for node_info in &new_nodes {
+                let _ = self.internode.send_to(&create_table_message, node_info);
             }
         }
         
@@ -379,152 +941,168 @@ impl Node {
             return;
         };
 
-        let mut partitions_to_reassign: Vec<Vec<String>> = vec![];
-        // Check for every partition if it is still in one of the correct nodes, if not, send it to the correct node
-        for (_, table) in data.iter() {
-            let keyspace_name = table.get_keyspace_name();
-            let replication_strategy = if let Some(replication_strategy) = keyspaces.get(&keyspace_name) {
-                replication_strategy.clone()
-            } else {
-                continue;
+        // Se anotan las particiones que ya no le corresponden antes de enviarlas, así no se
+        // pierden ni quedan sin borrar si el nodo se reinicia antes de que las réplicas confirmen
+        for (table_name_with_keyspace, table) in data.iter() {
+            let table = match table.read() {
+                Ok(table) => table,
+                Err(_) => continue,
+            };
+            let replication_strategy = match keyspaces.get(&table.get_keyspace_name()) {
+                Some(replication_strategy) => replication_strategy,
+                None => continue,
             };
             for (partition_keys, _) in table.get_partitions() {
-                // Check the nodes that should have the partition with the replication strategy
                 let nodes = replication_strategy.get_replica_nodes(
                     &partition_keys,
                     &local_gossip_table,
                     &self.consistent_hash,
                 );
-                if !nodes.contains(&self.id) && !partitions_to_reassign.contains(&partition_keys) {
-                    partitions_to_reassign.push(partition_keys.clone());
+                if !nodes.contains(&self.id) {
+                    if let Err(e) = self.cleanups.record(table_name_with_keyspace, &partition_keys) {
+                        let _ = self.logger.error(&e);
+                    }
                 }
             }
         }
 
-        // Identify non-corresponding partitions and send rows from them to the correct nodes
-        for (table_name_with_keyspace, table) in data.iter() {
-            let keyspace_name = table_name_with_keyspace.split('.').collect::<Vec<&str>>()[0];
-            let table_name = table_name_with_keyspace.split('.').collect::<Vec<&str>>()[1];
-            for partition_keys in partitions_to_reassign.clone() {
-                let replication_strategy =
-                if let Some(replication_strategy) = (&keyspaces).get(keyspace_name) {
-                    replication_strategy.clone()
-                } else {
+        self.stream_pending_cleanups(&trace_id);
+    }
+
+    /// Sends each partition that doesn't belong to this node anymore to the replicas that
+    /// didn't confirm they received it yet, and deletes it once all of them confirmed. The
+    /// replicas that gossip marks as dead are skipped, and the partition is kept for the
+    /// next attempt: when a node joins the cluster or one comes back to life.
+    ///
+    /// # Parameters
+    /// - `trace_id`: The trace id of the messages sent to the replicas.
+    fn stream_pending_cleanups(&self, trace_id: &str) {
+        let pending = self.cleanups.pending();
+        if pending.is_empty() {
+            return;
+        }
+        let (keyspaces, gossip_table) = match (self.get_keyspaces(), self.get_gossip_table()) {
+            (Ok(keyspaces), Ok(gossip_table)) => (keyspaces, gossip_table),
+            _ => return,
+        };
+
+        for cleanup in pending {
+            let forget = || {
+                if let Err(e) = self.cleanups.remove(&cleanup.table, &cleanup.partition_keys) {
+                    let _ = self.logger.error(&e);
+                }
+            };
+            let Some((keyspace_name, table_name)) = cleanup.table.split_once('.') else {
+                forget();
+                continue;
+            };
+            let (table, replication_strategy) = match (
+                self.get_shared_table(keyspace_name, table_name),
+                keyspaces.get(keyspace_name),
+            ) {
+                (Ok(table), Some(replication_strategy)) => (table, replication_strategy),
+                // La tabla o el keyspace ya no existen, no queda nada por borrar
+                _ => {
+                    forget();
+                    continue;
+                }
+            };
+            let replica_nodes = replication_strategy.get_replica_nodes(
+                &cleanup.partition_keys,
+                &gossip_table,
+                &self.consistent_hash,
+            );
+            if replica_nodes.contains(&self.id) {
+                // La partición volvió a corresponderle a este nodo
+                forget();
+                continue;
+            }
+            let rows_to_send = match table.read() {
+                Ok(table) => table.get_rows_from_partition(&cleanup.partition_keys),
+                Err(_) => continue,
+            };
+            if rows_to_send.is_empty() {
+                forget();
+                continue;
+            }
+            // Se envía la partición entera en un único batch.
+            let statements: Vec<String> = rows_to_send
+                .iter()
+                .map(|row| insert_message_from_row_and_tablename(row, table_name))
+                .collect();
+            let body = match serde_json::to_string(&statements) {
+                Ok(body) => body,
+                Err(_) => continue,
+            };
+            let internal_message = InternalMessage::Query {
+                opcode: 6,
+                body,
+                keyspace_name: keyspace_name.to_string(),
+                trace_id: trace_id.to_string(),
+            };
+
+            let mut confirmed = cleanup.confirmed.clone();
+            for node_id in &replica_nodes {
+                if confirmed.contains(node_id) {
+                    continue;
+                }
+                let Some(node_info) = gossip_table
+                    .iter()
+                    .find(|node_info| &node_info.node_id == node_id && node_info.status != "Dead")
+                else {
                     continue;
                 };
-                let replica_nodes = replication_strategy.get_replica_nodes(
-                    &partition_keys,
-                    &local_gossip_table,
-                    &self.consistent_hash,
-                );
-                let rows_to_send = table.get_rows_from_partition(&partition_keys);
-                for row in rows_to_send {
-                    let body = insert_message_from_row_and_tablename(&row, table_name);
-                    let internal_message = InternalMessage::Query {
-                        opcode: 2,
-                        body,
-                        keyspace_name: keyspace_name.to_string(),
-                    };
-                    for node_id in &replica_nodes {
-                        for new_node_info in &new_nodes {
-                            if node_id == &new_node_info.node_id {
-                                if let Ok(_) = send_internal_message_and_return_response(&internal_message, &new_node_info.ip, &new_node_info.port_gossip_query) {
-                                    let _ = self.logger.log(
-                                        format!("Data reassigned from {} to {}", self.id, node_id).as_str(),
-                                    );
-                                } else {
-                                    let _ = self.logger.log(
-                                        format!("Error reassigning data to {}", node_id).as_str(),
-                                    );
-                                }
+                match self.internode.send_to(&internal_message, node_info) {
+                    Ok(_) => {
+                        let _ = self.logger.log(
+                            format!("Data reassigned from {} to {}", self.id, node_id).as_str(),
+                        );
+                        match self.cleanups.confirm(&cleanup.table, &cleanup.partition_keys, node_id) {
+                            Ok(()) => confirmed.push(node_id.to_string()),
+                            Err(e) => {
+                                let _ = self.logger.error(&e);
                             }
-                            break;
                         }
                     }
+                    Err(e) => {
+                        let _ = self.logger.error(
+                            format!("Error reassigning data to {}: {}", node_id, e).as_str(),
+                        );
+                    }
                 }
             }
-        }
 
-        // Delete the partitions from the node
-        let mut data = match self.data.write() {
-            Ok(data) => data,
-            Err(_) => {
-                eprintln!("Error getting data for write");
-                return;
-            }
-        };
-        for (_, table) in data.iter_mut() {
-            for partition_keys in partitions_to_reassign.clone() {
-                if let Err(_) = table.delete_partition(&partition_keys) {
-                    eprintln!("Partition already deleted from table");
+            // Solo se borra cuando todas las réplicas confirmaron que la recibieron
+            if !replica_nodes.is_empty()
+                && replica_nodes.iter().all(|node_id| confirmed.contains(node_id))
+            {
+                match table.write() {
+                    Ok(mut table) => {
+                        if table.delete_partition(&cleanup.partition_keys).is_err() {
+                            eprintln!("Partition already deleted from table");
+                        }
+                        forget();
+                    }
+                    Err(_) => eprintln!("Error getting data for write"),
                 }
             }
         }
-
     }
 
 
-    /// Sends the pending hints to the specified node.
+    /// Calculates the value of φ (phi) given a lambda (λ) parameter and elapsed time.
     ///
-    /// # Parameters
-    /// - `node_id`: The unique identifier of the node to which the hints will be sent.
-    /// - `node_ip`: The IP address of the node to which the hints will be sent.
-    /// - `node_port`: The port number of the node to which the hints will be sent.
+    /// #Parameters
+    /// - 'lambda': The rate of event occurrence
+    /// - 'elapsed_time': The time elapsed
     ///
-    fn send_hints(&self, node_id: String, node_ip: String, node_port: String) {
-        let mut hints = match self.hints.write() {
-            Ok(hints) => hints,
-            _ => {
-                return;
-            }
-        };
-
-        let hints_to_send = match hints.get_mut(&node_id) {
-            Some(hints_to_send) => hints_to_send,
-            None => {
-                return;
-            }
-        };
-
-        let mut hints_successful: Vec<InternalMessage> = vec![];
-
-        for hint in hints_to_send.iter() {
-            let destination = format!("{}:{}", node_ip, node_port);
-            if let Ok(mut stream) = TcpStream::connect(&destination) {
-                if let Err(e) = hint.write_to_stream(&mut stream) {
-                    eprintln!("Error writing to stream: {}", e);
-                } else {
-                    hints_successful.push(hint.clone());
-                }
-            } else {
-                eprintln!(
-                    "Error connecting from node {} to {:?}",
-                    self.id, &destination
-                );
-            }
-        }
-
-        // Borro todos los hints que fueron enviados del vector hints_to_send
-        for hint in hints_successful {
-            if let Some(index) = hints_to_send.iter().position(|x| *x == hint) {
-                hints_to_send.remove(index);
-            }
-        }
-    }
-
-    /// Calculates the value of φ (phi) given a lambda (λ) parameter and elapsed time.
-    ///
-    /// #Parameters
-    /// - 'lambda': The rate of event occurrence
-    /// - 'elapsed_time': The time elapsed
-    ///
-    /// #Returns
-    ///- Returns the calculated value of φ
-    ///
-    pub fn calcular_phi(lambda: f64, tiempo_transcurrido: f64) -> f64 {
-        let probabilidad = 1.0 - (E.powf(-lambda * tiempo_transcurrido));
-        -probabilidad.log10()
-    }
+    /// #Returns
+    ///- Returns the calculated value of φ
+    ///
+    pub fn calcular_phi(lambda: f64, tiempo_transcurrido: f64) -> f64 {
+        let probabilidad = 1.0 - (E.powf(-lambda * tiempo_transcurrido));
+        -probabilidad.log10()
+    }
 
     /// Sends periodic gossip messages to other nodes in the system and updates gossip tables.
     ///
@@ -532,17 +1110,33 @@ impl Node {
     /// - 'interval': The interval in milliseconds at which gossip messages are sent
     ///
     pub fn gossip(&self, interval: u64) {
+        let _span = debug_span!("gossip_round", node = %self.id).entered();
+        let start = Instant::now();
+        let marked_dead = self.gossip_round(interval);
+        self.metrics
+            .record(Operation::GossipRound, start.elapsed(), true);
+        if marked_dead > 0 {
+            self.warn_under_replicated_keyspaces();
+        }
+    }
+
+    /// Marks as dead the nodes whose heartbeat is too old and sends the gossip table to a live
+    /// node.
+    ///
+    /// # Returns
+    /// The number of nodes marked as dead in this round.
+    fn gossip_round(&self, interval: u64) -> usize {
         // println!("[{}] Attempting to send gossip", Utc::now().format("%Y-%m-%d %H:%M:%S"));
         let mut local_gossip_table = match self.gossip_table.write() {
             Ok(gossip_table) => gossip_table,
 
             _ => {
-                return;
+                return 0;
             }
         };
 
         if local_gossip_table.len() == 1 {
-            return;
+            return 0;
         }
         // P(t-T) = 1-e^(-λ(t-T))
         // Phi = -log(P(t-T))
@@ -556,10 +1150,14 @@ impl Node {
         // 1. Calcular el tiempo transcurrido desde el ultimo mensaje recibido
 
         let tiempo_actual = Utc::now().timestamp();
+        let mut dead_nodes = vec![];
 
         for gossip_info in local_gossip_table.iter_mut() {
             if gossip_info.node_id == self.id {
-                gossip_info.status = "Live".to_string();
+                if gossip_info.status != self.own_status() {
+                    self.placement.invalidate();
+                }
+                gossip_info.status = self.own_status().to_string();
                 gossip_info.last_heartbeat = tiempo_actual;
                 continue;
             }
@@ -568,7 +1166,7 @@ impl Node {
             let interval_in_seconds = interval as f64 / 1000.0;
             let phi = Node::calcular_phi(interval_in_seconds, tiempo_transcurrido as f64);
             if phi < 0.0000000015 {
-                let _ = self.logger.log(
+                let _ = self.logger.warn(
                     format!(
                         "Node {} is marked dead, {} seconds has passed since its last heartbeat",
                         gossip_info.node_id, tiempo_transcurrido
@@ -577,9 +1175,15 @@ impl Node {
                 );
                 if gossip_info.status == "Live" {
                     gossip_info.status = "Dead".to_string();
+                    self.placement.invalidate();
+                    dead_nodes.push(ClusterEvent::Down(gossip_info.native_address()));
                 }
             }
         }
+        let marked_dead = dead_nodes.len();
+        for event in dead_nodes {
+            self.publish_event(event);
+        }
 
         let mut rng = rng();
 
@@ -607,7 +1211,7 @@ impl Node {
 
         if random_node_info.node_id.is_empty() {
             println!("No node alive to gossip with");
-            return;
+            return marked_dead;
         }
 
         let destination = format!(
@@ -636,6 +1240,7 @@ impl Node {
                 eprintln!("Error serializing gossip table: {}", e);
             }
         }
+        marked_dead
     }
 
     // ------------------------ Direct Keyspace Management ------------------------
@@ -675,6 +1280,8 @@ impl Node {
                 return Err("Invalid replication strategy".to_string());
             }
         }
+        self.placement.invalidate_keyspace(keyspace_name);
+        self.keyspaces_dirty.store(true, Ordering::SeqCst);
         Ok(())
     }
 
@@ -697,6 +1304,110 @@ impl Node {
         keyspaces.contains_key(keyspace_name)
     }
 
+    /// Returns the number of nodes of the cluster: every node of the gossip table, even those
+    /// that are down at the moment.
+    fn cluster_size(&self) -> usize {
+        match self.gossip_table.read() {
+            Ok(gossip_table) => gossip_table.len(),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the number of nodes that can hold a replica now: those of the gossip table
+    /// that aren't marked as dead.
+    fn alive_node_count(&self) -> usize {
+        match self.gossip_table.read() {
+            Ok(gossip_table) => gossip_table
+                .iter()
+                .filter(|gossip_info| gossip_info.status != "Dead")
+                .count(),
+            Err(_) => 0,
+        }
+    }
+
+    /// Checks that a `CREATE KEYSPACE` doesn't ask for more replicas than there are nodes in
+    /// the cluster, so its partitions aren't stored with fewer replicas than it says.
+    ///
+    /// # Parameters
+    /// - `query_str`: The statement sent by the client.
+    /// - `keyspace_name`: The keyspace it creates.
+    /// - `replication_strategy`: The name of its replication strategy.
+    /// - `replication_factor`: Its replication factor.
+    ///
+    /// # Returns
+    /// The statement to send to the nodes: the same one, or one with the replication factor
+    /// clamped to the size of the cluster if the node is configured to clamp it. An error if
+    /// it is configured to reject it.
+    fn check_replication(
+        &self,
+        query_str: &str,
+        keyspace_name: &str,
+        replication_strategy: &str,
+        replication_factor: &str,
+    ) -> Result<String, String> {
+        if replication_strategy != "SimpleStrategy" {
+            // Las réplicas rechazan las otras estrategias
+            return Ok(query_str.to_string());
+        }
+        let replication_factor =
+            ReplicationStrategy::new_simple(replication_factor.to_string()).get_replication_factor();
+        let cluster_size = self.cluster_size();
+        if replication_factor <= cluster_size {
+            return Ok(query_str.to_string());
+        }
+        let message = format!(
+            "Keyspace {} asks for replication factor {} but the cluster has {} nodes",
+            keyspace_name, replication_factor, cluster_size
+        );
+        match self.oversized_replication {
+            OversizedReplication::Reject => {
+                let _ = self.logger.warn(&message);
+                Err(message)
+            }
+            OversizedReplication::Clamp => {
+                let _ = self.logger.warn(
+                    format!("{}, using replication factor {}", message, cluster_size).as_str(),
+                );
+                Ok(create_keyspace_query(
+                    keyspace_name,
+                    ReplicationStrategy::new_simple(cluster_size.to_string()),
+                ))
+            }
+        }
+    }
+
+    /// Returns a warning for each keyspace whose replication factor is larger than the nodes
+    /// of the cluster that aren't dead, since its partitions have fewer replicas than it asks
+    /// for.
+    pub fn schema_warnings(&self) -> Result<Vec<String>, String> {
+        let alive_nodes = self.alive_node_count();
+        let mut warnings: Vec<String> = self
+            .get_keyspaces()?
+            .into_iter()
+            .filter(|(_, replication_strategy)| {
+                replication_strategy.get_replication_factor() > alive_nodes
+            })
+            .map(|(keyspace_name, replication_strategy)| {
+                format!(
+                    "Keyspace {} has replication factor {} but only {} nodes are alive",
+                    keyspace_name,
+                    replication_strategy.get_replication_factor(),
+                    alive_nodes
+                )
+            })
+            .collect();
+        warnings.sort();
+        Ok(warnings)
+    }
+
+    /// Logs the keyspaces left with fewer replicas than their replication factor after a node
+    /// of the cluster died.
+    fn warn_under_replicated_keyspaces(&self) {
+        for warning in self.schema_warnings().unwrap_or_default() {
+            let _ = self.logger.warn(&warning);
+        }
+    }
+
     // ------------------------ Direct Table Management ------------------------
     // Se utilizan cuando se quiere manejar data directamente
 
@@ -732,7 +1443,10 @@ impl Node {
         };
 
         let encrypted_table = EncryptedTable::new(table);
-        data.insert(format!("{}.{}", keyspace_name, table_name), encrypted_table);
+        data.insert(
+            format!("{}.{}", keyspace_name, table_name),
+            Arc::new(RwLock::new(encrypted_table)),
+        );
     }
 
     /// Inserts a new row into the specified table
@@ -745,28 +1459,21 @@ impl Node {
     /// # Returns
     /// An `Ok(())` value if the row was inserted successfully, or an `Err(String)` with an error message if the operation failed.
 
+    #[tracing::instrument(level = "debug", skip_all, fields(node = %self.id, keyspace = keyspace_name, table = table_name))]
     pub fn insert_row(
         &self,
         keyspace_name: &str,
         table_name: &str,
         values: HashMap<String, String>,
     ) -> Result<(), String> {
-        let mut data = match self.data.write() {
-            Ok(data) => {
-                // println!("Entre a bloquear data");
-                data
-            }
-
+        let table = self.get_shared_table(keyspace_name, table_name)?;
+        let mut table = match table.write() {
+            Ok(table) => table,
             Err(_) => {
-                return Err("Error locking data".to_string());
+                return Err("Error locking table".to_string());
             }
         };
-
-        if let Some(table) = data.get_mut(&format!("{}.{}", keyspace_name, table_name)) {
-            table.insert(values)
-        } else {
-            Err("Table not found".to_string())
-        }
+        table.insert(values)
     }
     /// Update a row in the specified table
     ///
@@ -778,6 +1485,7 @@ impl Node {
     ///
     /// # Returns
     /// An `Ok(())` value if the row was updated successfully, or an `Err(String)` with an error message if the operation failed.
+    #[tracing::instrument(level = "debug", skip_all, fields(node = %self.id, keyspace = keyspace_name, table = table_name))]
     pub fn update_row(
         &self,
         keyspace_name: &str,
@@ -785,22 +1493,14 @@ impl Node {
         values_to_update: HashMap<String, String>,
         condition: &Expression,
     ) -> Result<(), String> {
-        let mut data = match self.data.write() {
-            Ok(data) => {
-                // println!("Entre a bloquear data");
-                data
-            }
-
+        let table = self.get_shared_table(keyspace_name, table_name)?;
+        let mut table = match table.write() {
+            Ok(table) => table,
             Err(_) => {
-                return Err("Error locking data".to_string());
+                return Err("Error locking table".to_string());
             }
         };
-
-        if let Some(table) = data.get_mut(&format!("{}.{}", keyspace_name, table_name)) {
-            table.update(values_to_update, condition)
-        } else {
-            Err("Table not found".to_string())
-        }
+        table.update(values_to_update, condition)
     }
 
     /// Deletes a row in the specified table
@@ -812,27 +1512,244 @@ impl Node {
     ///
     /// # Returns
     /// An `Ok(())` value if the row was deleted successfully, or an `Err(String)` with an error message if the operation failed.
+    #[tracing::instrument(level = "debug", skip_all, fields(node = %self.id, keyspace = keyspace_name, table = table_name))]
     pub fn delete_row(
         &self,
         keyspace_name: &str,
         table_name: &str,
         condition: &Expression,
     ) -> Result<(), String> {
-        let mut data = match self.data.write() {
-            Ok(data) => {
-                // println!("Entre a bloquear data");
-                data
+        let table = self.get_shared_table(keyspace_name, table_name)?;
+        let mut table = match table.write() {
+            Ok(table) => table,
+            Err(_) => {
+                return Err("Error locking table".to_string());
             }
+        };
+        table.delete(condition)
+    }
 
+    /// Applies several inserts, updates and deletes to a table atomically, under a single lock.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The name of the keyspace containing the table.
+    /// - `table_name`: The name of the table the mutations apply to.
+    /// - `mutations`: The mutations to apply, in order.
+    ///
+    /// # Returns
+    /// An `Ok(())` value if every mutation was applied, or an `Err(String)` if any of them failed,
+    /// in which case the table is left unchanged.
+    #[tracing::instrument(level = "debug", skip_all, fields(node = %self.id, keyspace = keyspace_name, table = table_name))]
+    pub fn apply_batch(
+        &self,
+        keyspace_name: &str,
+        table_name: &str,
+        mutations: Vec<Mutation>,
+    ) -> Result<(), String> {
+        let table = self.get_shared_table(keyspace_name, table_name)?;
+        let mut table = match table.write() {
+            Ok(table) => table,
             Err(_) => {
-                return Err("Error locking data".to_string());
+                return Err("Error locking table".to_string());
             }
         };
+        table.apply_batch(mutations)
+    }
 
-        if let Some(table) = data.get_mut(&format!("{}.{}", keyspace_name, table_name)) {
-            table.delete(condition)
-        } else {
-            Err("Table not found".to_string())
+    /// Applies a batch received as an internal message: a JSON array of INSERT, UPDATE and
+    /// DELETE statements that all target the same table.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The name of the keyspace containing the table.
+    /// - `body`: The JSON array of statements.
+    ///
+    /// # Returns
+    /// The name of the table the batch was applied to, or an `Err(String)` if it failed.
+    fn apply_internal_batch(&self, keyspace_name: &str, body: &str) -> Result<String, String> {
+        let statements: Vec<String> = serde_json::from_str(body)
+            .map_err(|e| format!("Error deserializing batch: {}", e))?;
+
+        let mut batch_table_name: Option<String> = None;
+        let mut mutations = vec![];
+        for statement in statements {
+            let parsed_query = parse_instruction(&statement)
+                .map_err(|e| format!("Error parsing query: {}", e))?;
+            let (table_name, mut statement_mutations) = mutations_from_parsed_query(parsed_query)?;
+            match &batch_table_name {
+                Some(batch_table_name) if *batch_table_name != table_name => {
+                    return Err("All the statements of a batch must target the same table".to_string());
+                }
+                Some(_) => {}
+                None => batch_table_name = Some(table_name),
+            }
+            mutations.append(&mut statement_mutations);
+        }
+
+        let table_name = batch_table_name.ok_or("Empty batch".to_string())?;
+        self.apply_batch(keyspace_name, &table_name, mutations)?;
+        Ok(table_name)
+    }
+
+    /// Applies a write another node kept as a hint while this node was down. The rows written
+    /// after the hint are left out of each statement, so a late hint doesn't overwrite newer
+    /// data but still writes the rest of its rows.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The keyspace of the write.
+    /// - `body`: The JSON of the `Hint`.
+    /// - `trace_id`: The trace id of the write.
+    ///
+    /// # Returns
+    /// The response to the write, or a descriptive Err(String).
+    fn apply_hint(&self, keyspace_name: &str, body: &str, trace_id: &str) -> Result<String, String> {
+        let hint: Hint =
+            serde_json::from_str(body).map_err(|e| format!("Error deserializing hint: {}", e))?;
+        let statements: Vec<String> = match hint.opcode {
+            // BATCH: el body es una lista de queries
+            6 => serde_json::from_str(&hint.body)
+                .map_err(|e| format!("Error deserializing batch: {}", e))?,
+            _ => vec![hint.body.clone()],
+        };
+        let mut current = vec![];
+        for statement in statements {
+            match self.without_newer_rows(keyspace_name, &statement, &hint.timestamp) {
+                Some(narrowed) if narrowed == statement => current.push(narrowed),
+                narrowed => {
+                    let _ = self.logger.log_traced(
+                        trace_id,
+                        format!("Hinted rows older than their last write left out of: {}", statement)
+                            .as_str(),
+                    );
+                    current.extend(narrowed);
+                }
+            }
+        }
+
+        let body = match (hint.opcode, current.as_slice()) {
+            (_, []) => return Ok("Hint older than the rows it writes".to_string()),
+            (6, _) => serde_json::to_string(&current)
+                .map_err(|e| format!("Error serializing batch: {}", e))?,
+            (_, [statement, ..]) => statement.to_string(),
+        };
+        self.receive_internal_message(&InternalMessage::Query {
+            opcode: hint.opcode,
+            body,
+            keyspace_name: keyspace_name.to_string(),
+            trace_id: trace_id.to_string(),
+        })
+    }
+
+    /// Returns an INSERT, UPDATE or DELETE restricted to the rows that don't have a
+    /// `_timestamp` at or after the one of the write, or `timestamp` if it has none: the
+    /// newer rows are left out of an INSERT, and excluded from the condition of an UPDATE or a
+    /// DELETE. `None` if every row it writes is newer.
+    fn without_newer_rows(
+        &self,
+        keyspace_name: &str,
+        statement: &str,
+        timestamp: &str,
+    ) -> Option<String> {
+        let unchanged = Some(statement.to_string());
+        let Ok(parsed_query) = parse_instruction(statement) else {
+            return unchanged;
+        };
+        let Ok(table_name) = parsed_query.get_table_name() else {
+            return unchanged;
+        };
+        let Ok(table) = self.get_shared_table(keyspace_name, &table_name) else {
+            return unchanged;
+        };
+        let Ok(table) = table.read() else {
+            return unchanged;
+        };
+        let mut key_columns = table.get_partition_key_columns();
+        key_columns.extend(table.get_clustering_key_columns());
+
+        // Con el mismo _timestamp la fila ya tiene esta escritura, o una que la desempata
+        let written_after = |row: &HashMap<String, String>, timestamp: &str| {
+            let timestamp = ColumnType::Timestamp.value_of(timestamp);
+            row.get("_timestamp").is_some_and(|written| {
+                ColumnType::Timestamp
+                    .value_of(written)
+                    .compare(&timestamp)
+                    .is_some_and(|ordering| ordering.is_ge())
+            })
+        };
+
+        // Las filas más nuevas se excluyen de la condición por su clave primaria
+        let narrowed_condition = |condition: &Expression, timestamp: &str| {
+            let matched = table.select_if(condition);
+            let newer: Vec<Expression> = matched
+                .iter()
+                .filter(|row| written_after(row, timestamp))
+                .filter_map(|row| key_condition(&key_columns, row))
+                .collect();
+            if !matched.is_empty() && newer.len() == matched.len() {
+                return None;
+            }
+            Some(newer.into_iter().fold(condition.clone(), |narrowed, key| {
+                let excluded = Expression::Not {
+                    right: Box::new(key),
+                };
+                match narrowed {
+                    Expression::True => excluded,
+                    narrowed => Expression::And {
+                        left: Box::new(narrowed),
+                        right: Box::new(excluded),
+                    },
+                }
+            }))
+        };
+
+        match parsed_query {
+            ParsedQuery::Insert {
+                table_name,
+                columns_in_order,
+                rows_to_insert,
+                ..
+            } => {
+                let rows_count = rows_to_insert.len();
+                let rows: Vec<HashMap<String, String>> = rows_to_insert
+                    .into_iter()
+                    .filter(|row| {
+                        let timestamp = row.get("_timestamp").map_or(timestamp, String::as_str);
+                        let Some(condition) = key_condition(&key_columns, row) else {
+                            return true;
+                        };
+                        !table
+                            .select_if(&condition)
+                            .iter()
+                            .any(|written| written_after(written, timestamp))
+                    })
+                    .collect();
+                match rows.len() {
+                    0 => None,
+                    n if n == rows_count => unchanged,
+                    _ => Some(insert_message_from_rows(&table_name, &columns_in_order, &rows)),
+                }
+            }
+            ParsedQuery::Update {
+                table_name,
+                values_to_update,
+                condition,
+                ..
+            } => {
+                let timestamp = values_to_update
+                    .get("_timestamp")
+                    .map_or(timestamp, String::as_str);
+                match narrowed_condition(&condition, timestamp)? {
+                    narrowed if narrowed == condition => unchanged,
+                    narrowed => Some(update_message(&table_name, &values_to_update, &narrowed)),
+                }
+            }
+            ParsedQuery::Delete {
+                table_name,
+                condition,
+            } => match narrowed_condition(&condition, timestamp)? {
+                narrowed if narrowed == condition => unchanged,
+                narrowed => Some(delete_message(&table_name, &narrowed)),
+            },
+            _ => unchanged,
         }
     }
 
@@ -857,13 +1774,25 @@ impl Node {
         } else {
             return Err("Error parsing query".to_string());
         };
+        let trace_id = new_trace_id();
 
         match &query_parsed {
-            ParsedQuery::CreateKeyspace { .. } => {
+            ParsedQuery::CreateKeyspace {
+                keyspace_name,
+                replication_strategy,
+                replication_factor,
+            } => {
+                let body = self.check_replication(
+                    query_str,
+                    keyspace_name,
+                    replication_strategy,
+                    replication_factor,
+                )?;
                 let to_send = InternalMessage::Query {
                     opcode: 0,
-                    body: query_str.to_string(),
+                    body,
                     keyspace_name: "not_necessary".to_string(),
+                    trace_id: trace_id.clone(),
                 };
                 let nodes_to_resend_query = self.get_all_nodes();
                 let mut responses = vec![];
@@ -877,6 +1806,7 @@ impl Node {
                     opcode: 1,
                     body: query_str.to_string(),
                     keyspace_name: keyspace_name.to_string(),
+                    trace_id: trace_id.clone(),
                 };
                 let nodes_to_resend_query = self.get_all_nodes();
                 let mut responses = vec![];
@@ -894,6 +1824,7 @@ impl Node {
                     opcode: 2,
                     body: query_str.to_string(),
                     keyspace_name: keyspace_name.to_string(),
+                    trace_id: trace_id.clone(),
                 };
 
                 let nodes_to_resend_query =
@@ -904,14 +1835,20 @@ impl Node {
                 }
                 responses[0].clone()
             }
-            ParsedQuery::Select { condition, .. } => {
+            ParsedQuery::Select {
+                condition,
+                table_name,
+                ..
+            } => {
                 let to_send = InternalMessage::Query {
                     opcode: 3,
                     body: query_str.to_string(),
                     keyspace_name: keyspace_name.to_string(),
+                    trace_id: trace_id.clone(),
                 };
 
-                let nodes_to_resend_query = self.get_nodes_for_condition(keyspace_name, condition);
+                let nodes_to_resend_query =
+                    self.get_nodes_for_condition(keyspace_name, table_name, condition);
 
                 let mut responses = vec![];
                 for node_id in &nodes_to_resend_query {
@@ -920,26 +1857,38 @@ impl Node {
 
                 responses[0].clone()
             }
-            ParsedQuery::Update { .. } => {
+            ParsedQuery::Update {
+                table_name,
+                condition,
+                ..
+            } => {
                 let to_send = InternalMessage::Query {
                     opcode: 4,
                     body: query_str.to_string(),
                     keyspace_name: keyspace_name.to_string(),
+                    trace_id: trace_id.clone(),
                 };
-                let nodes_to_resend_query = self.get_all_nodes();
+                let nodes_to_resend_query =
+                    self.get_nodes_for_mutation(keyspace_name, table_name, condition);
                 let mut responses = vec![];
                 for node_id in &nodes_to_resend_query {
                     responses.push(self.resend(&to_send, node_id));
                 }
                 responses[0].clone()
             }
-            ParsedQuery::Delete { .. } => {
+            ParsedQuery::Delete {
+                table_name,
+                condition,
+                ..
+            } => {
                 let to_send = InternalMessage::Query {
                     opcode: 5,
                     body: query_str.to_string(),
                     keyspace_name: keyspace_name.to_string(),
+                    trace_id: trace_id.clone(),
                 };
-                let nodes_to_resend_query = self.get_all_nodes();
+                let nodes_to_resend_query =
+                    self.get_nodes_for_mutation(keyspace_name, table_name, condition);
                 let mut responses = vec![];
                 for node_id in &nodes_to_resend_query {
                     responses.push(self.resend(&to_send, node_id));
@@ -963,42 +1912,36 @@ impl Node {
     /// - `responses`: A vector of responses from different nodes.
     /// - `keyspace_name`: The name of the keyspace to which the query belongs.
     /// - `table_name`: The name of the table to which the query belongs.
+    /// - `trace_id`: The trace id of the query, sent along with the repairs.
     ///
     /// # Returns
-    /// A `String` containing the response with the most recent timestamp.
+    /// The response with the most recent timestamp,
     /// in case of error returns a string with the error message.
 
     pub fn read_repair(
         &self,
-        responses: &[String],
+        responses: &[RowSet],
         keyspace_name: &str,
         table_name: &str,
-    ) -> String {
+        trace_id: &str,
+    ) -> Result<RowSet, String> {
         let mut last_timestamp = 0;
         let mut last_index = 0;
         let mut found_mismatch = false;
 
         for (i, response) in responses.iter().enumerate() {
-            let rows: Vec<HashMap<String, String>> = match serde_json::from_str(response) {
-                Ok(rows) => rows,
-                Err(e) => {
-                    eprintln!("Error deserializing response: {}", e);
-                    continue;
-                }
-            };
-
-            for row in rows {
-                if let Some(timestamp_str) = row.get("_timestamp") {
+            for row in &response.rows {
+                if let Some(timestamp_str) = response.value(row, "_timestamp").to_text() {
                     let naive_dt =
-                        match NaiveDateTime::parse_from_str(timestamp_str, "%Y-%m-%d %H:%M:%S") {
+                        match NaiveDateTime::parse_from_str(&timestamp_str, "%Y-%m-%d %H:%M:%S%.f") {
                             Ok(dt) => dt,
                             Err(_) => {
                                 eprintln!("Error parsing timestamp");
-                                return "Error parsing timestamp".to_string();
+                                return Err("Error parsing timestamp".to_string());
                             }
                         };
 
-                    let timestamp = Utc.from_utc_datetime(&naive_dt).timestamp();
+                    let timestamp = Utc.from_utc_datetime(&naive_dt).timestamp_micros();
                     if timestamp > last_timestamp {
                         last_timestamp = timestamp;
                         last_index = i;
@@ -1010,17 +1953,8 @@ impl Node {
         }
 
         if found_mismatch {
-            let rows: Vec<HashMap<String, String>> =
-                match serde_json::from_str(&responses[last_index]) {
-                    Ok(rows) => rows,
-                    Err(e) => {
-                        eprintln!("Error deserializing row: {}", e);
-                        return "Error deserializing row".to_string();
-                    }
-                };
-
-            if let Some(row) = rows.first() {
-                let values = row.clone();
+            // La fila se reenvía como la guardan los nodos, con sus valores como texto
+            if let Some(values) = responses[last_index].text_rows().into_iter().next() {
                 let nodes_to_resend_query =
                     self.get_nodes_for_insert(keyspace_name, table_name, &values);
                 let body = generate_insert_cql(table_name, values);
@@ -1028,90 +1962,900 @@ impl Node {
                     opcode: 2,
                     body: body.clone(),
                     keyspace_name: keyspace_name.to_string(),
+                    trace_id: trace_id.to_string(),
                 };
 
-                let self_arc = Arc::new(self.clone());
                 let _ = self.logger.log(
                     format!("Read repair needed on {:?}", nodes_to_resend_query.clone()).as_str(),
                 );
                 for node_id in nodes_to_resend_query.clone() {
+                    if node_id == self.id {
+                        let _ = self.receive_internal_message(&to_send);
+                        continue;
+                    }
                     let to_send = to_send.clone();
-                    let self_arc = Arc::clone(&self_arc);
-                    std::thread::spawn(move || {
-                        // println!("nodo a enviar: {}", &node_id);
-                        let _ = self_arc.resend(&to_send, &node_id);
+                    let internode = Arc::clone(&self.internode);
+                    let _ = self.run_in_background(move || {
+                        let _ = internode.send(&to_send, &node_id);
                     });
                 }
             }
         }
 
-        responses[last_index].clone()
+        responses
+            .get(last_index)
+            .cloned()
+            .ok_or_else(|| "No responses to repair".to_string())
     }
 
-    // ------------------------  Resend Query ------------------------//
+    // ------------------------ Counters ------------------------
 
-    /// Resends a query as an internal message to the corresponding nodes.
+    /// Executes an UPDATE of counter columns: a leader replica increments its own shard of
+    /// each counter and replicates the new value to the other replicas of the row.
+    ///
+    /// Increments aren't idempotent, so they are never stored as hints, and they are only sent
+    /// to another live replica when the leader couldn't be reached, as then it surely didn't
+    /// apply them.
     ///
     /// # Parameters
-    ///  `query`: A `Query` containing the string representation of the query to be executed.
-    ///  `current_keyspace`: keyspace name indicating the context under which the query should be run.
+    /// - `table_name`: The table of the counters.
+    /// - `condition`: The WHERE clause, that must fix the whole primary key.
+    /// - `increments`: The amount each counter column changes.
+    /// - `keyspace_name`: The keyspace of the table.
+    /// - `consistency_level`: The consistency level required to the replicas.
+    /// - `trace_id`: The trace id of the query.
     ///
     /// # Returns
-    /// A `QueryResult` containing the response from the first node to respond, or an `ErrorCode` if the operation failed.
-
-    pub fn resend_query_as_internal_message(
+    /// `QueryResult::Void` if the increment was applied, or Err(ErrorCode) if it failed.
+    fn execute_counter_update(
         &self,
-        query: Query,
-        current_keyspace: Option<String>,
+        table_name: &str,
+        condition: &Expression,
+        increments: &HashMap<String, i64>,
+        keyspace_name: &str,
+        consistency_level: &Consistency,
+        trace_id: &str,
     ) -> Result<QueryResult, ErrorCode> {
-        let query_str = query.query_string;
-        let _ = self
-            .logger
-            .log(format!("Received query from client: {}", query_str).as_str());
-        let consistency_level = Consistency::from_consistency_level(query.consistency_level);
+        let (primary_key_columns, counter_columns) = self
+            .counter_table_columns(keyspace_name, table_name)
+            .map_err(|_| ErrorCode::Invalid)?;
+        if increments
+            .keys()
+            .any(|column| !counter_columns.contains(column))
+        {
+            let _ = self
+                .logger
+                .log_traced(trace_id, "Only counter columns can be incremented");
+            return Err(ErrorCode::Invalid);
+        }
 
-        let local_gossip_table = match self.gossip_table.read() {
-            Ok(gossip_table) => gossip_table.clone(),
-            Err(_) => {
-                return Err(ErrorCode::UnavailableException);
-            }
-        };
+        let equalities = extract_equalities(condition);
+        let mut row_key = HashMap::new();
+        for column in primary_key_columns {
+            let value = equalities.get(&column).ok_or(ErrorCode::Invalid)?;
+            row_key.insert(column, value.to_string());
+        }
 
-        let Ok(query_parsed) = parse_instruction(&query_str) else {
-            eprintln!("Error parsing query");
-            return Err(ErrorCode::SyntaxError);
+        let replicas = self.get_nodes_for_condition(keyspace_name, table_name, condition);
+        if replicas.is_empty() {
+            return Err(ErrorCode::Invalid);
+        }
+        let (mut leaders, _, required) = self.live_replicas(replicas.clone(), consistency_level)?;
+        // El coordinador es el líder si es réplica de la fila
+        if let Some(index) = leaders.iter().position(|node_id| *node_id == self.id) {
+            leaders.swap(0, index);
+        }
+        let mutation = CounterMutation::Increment {
+            table_name: table_name.to_string(),
+            row_key,
+            increments: increments.clone(),
+            replicas: replicas.clone(),
+            required,
         };
-
-        if current_keyspace.is_none() {
-            if let ParsedQuery::CreateKeyspace { .. } | ParsedQuery::UseKeyspace { .. } =
-                query_parsed
-            {
-                // do nothing
+        let to_send = InternalMessage::Query {
+            opcode: 10,
+            body: serde_json::to_string(&mutation).map_err(|_| ErrorCode::ServerError)?,
+            keyspace_name: keyspace_name.to_string(),
+            trace_id: trace_id.to_string(),
+        };
+        let mut error = "No live replica to lead the counter update".to_string();
+        for leader in leaders {
+            let _ = self.logger.log_traced(
+                trace_id,
+                format!("Counter update led by {} on replicas {:?}", leader, replicas).as_str(),
+            );
+            let response = if leader == self.id {
+                self.receive_internal_message(&to_send)
+                    .map_err(ExchangeError::Failed)
             } else {
-                eprintln!("No keyspace set");
-                return Err(ErrorCode::Invalid);
+                self.internode.send_at_most_once(&to_send, &leader)
+            };
+            match response {
+                Ok(_) => return Ok(QueryResult::Void),
+                // El líder no recibió el incremento: otra réplica puede liderarlo
+                Err(ExchangeError::Undelivered(e)) => error = e,
+                Err(ExchangeError::Failed(e)) => {
+                    error = e;
+                    break;
+                }
+                Err(ExchangeError::Cancelled) => return Err(ErrorCode::ServerError),
             }
         }
-        let mut keyspace_name = "".to_string();
-        if let Some(keyspace_name_as_string) = current_keyspace {
-            keyspace_name = keyspace_name_as_string;
-        }
+        let _ = self.logger.log_with(
+            LogLevel::Error,
+            format!("Counter update failed: {}", error).as_str(),
+            &[("query_id", trace_id), ("table", table_name)],
+        );
+        Err(ErrorCode::WriteTimeout {
+            consistency: consistency_level.to_consistency_level(),
+            received: 0,
+            block_for: required as i32,
+            write_type: WriteType::Counter,
+        })
+    }
 
-        match &query_parsed {
-            ParsedQuery::CreateKeyspace { .. } => {
-                let to_send = InternalMessage::Query {
-                    opcode: 0,
-                    body: query_str.to_string(),
-                    keyspace_name: "not_neccessary".to_string(),
+    /// Handles a COUNTER message, as the leader of an increment or as a replica receiving the
+    /// shards of the leader.
+    fn apply_counter_mutation(
+        &self,
+        keyspace_name: &str,
+        body: &str,
+        trace_id: &str,
+    ) -> Result<String, String> {
+        let mutation: CounterMutation = serde_json::from_str(body)
+            .map_err(|e| format!("Error deserializing counter mutation: {}", e))?;
+        match mutation {
+            CounterMutation::Increment {
+                table_name,
+                row_key,
+                increments,
+                replicas,
+                required,
+            } => {
+                let key = counter_key(keyspace_name, &table_name, &row_key);
+                let shards = self.counters.increment(&key, &self.id, &increments)?;
+                self.insert_row(keyspace_name, &table_name, row_key.clone())?;
+
+                let replicate = CounterMutation::Replicate {
+                    table_name,
+                    row_key,
+                    node_id: self.id.clone(),
+                    shards,
+                };
+                let to_send = InternalMessage::Query {
+                    opcode: 10,
+                    body: serde_json::to_string(&replicate)
+                        .map_err(|e| format!("Error serializing counter shards: {}", e))?,
+                    keyspace_name: keyspace_name.to_string(),
+                    trace_id: trace_id.to_string(),
+                };
+                let (tx, rx) = mpsc::channel();
+                for node_id in replicas.into_iter().filter(|node_id| *node_id != self.id) {
+                    let internode = Arc::clone(&self.internode);
+                    let to_send = to_send.clone();
+                    let tx = tx.clone();
+                    // El valor del shard es absoluto: reenviarlo como hint es seguro
+                    let queued = self.run_in_background(move || {
+                        let _ = tx.send(internode.send(&to_send, &node_id));
+                    });
+                    if queued.is_err() {
+                        return Err("Overloaded".to_string());
+                    }
+                }
+                drop(tx);
+
+                // El líder ya aplicó el incremento
+                let deadline = Instant::now() + self.timeouts.counter_replication();
+                let mut applied = 1;
+                while applied < required {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match rx.recv_timeout(remaining) {
+                        Ok(Ok(_)) => applied += 1,
+                        Ok(Err(_)) => {}
+                        Err(_) => break,
+                    }
+                }
+                if applied < required {
+                    return Err(format!(
+                        "Counter shards applied on {} of {} replicas",
+                        applied, required
+                    ));
+                }
+                Ok("Counter incremented successfully".to_string())
+            }
+            CounterMutation::Replicate {
+                table_name,
+                row_key,
+                node_id,
+                shards,
+            } => {
+                let key = counter_key(keyspace_name, &table_name, &row_key);
+                self.counters.merge(&key, &node_id, &shards)?;
+                self.insert_row(keyspace_name, &table_name, row_key)?;
+                let _ = self.logger.log_traced(
+                    trace_id,
+                    format!("Counter shards of {} applied", node_id).as_str(),
+                );
+                Ok("Counter shards applied successfully".to_string())
+            }
+        }
+    }
+
+    /// Returns the primary key columns and the counter columns of a table.
+    fn counter_table_columns(
+        &self,
+        keyspace_name: &str,
+        table_name: &str,
+    ) -> Result<(Vec<String>, Vec<String>), String> {
+        let table = self.get_shared_table(keyspace_name, table_name)?;
+        let table = table.read().map_err(|_| "Error locking table".to_string())?;
+        let mut primary_key_columns = table.get_partition_key_columns();
+        primary_key_columns.extend(table.get_clustering_key_columns());
+        Ok((primary_key_columns, counter_columns(&table.get_columns())))
+    }
+
+    /// Answers a SELECT_PAGE: the rows of a page of a SELECT, read from the position of the
+    /// last row of the previous page, so only the partitions the page walks are decrypted.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The keyspace of the table.
+    /// - `body`: The JSON of the `PageRequest`.
+    /// - `trace_id`: The trace id of the query.
+    ///
+    /// # Returns
+    /// The JSON of the `RowSet` of the page, or an error message.
+    fn select_page(&self, keyspace_name: &str, body: &str, trace_id: &str) -> Result<String, String> {
+        let request: PageRequest = serde_json::from_str(body)
+            .map_err(|e| format!("Error deserializing page request: {}", e))?;
+        let Ok(ParsedQuery::Select {
+            table_name,
+            condition,
+            ..
+        }) = parse_instruction(&request.query)
+        else {
+            return Err("Opcode doesn't match query".to_string());
+        };
+        let table = self.get_shared_table(keyspace_name, &table_name)?;
+        let table = table.read().map_err(|_| "Error locking table".to_string())?;
+        let (rows, _) = table.select_page(&condition, request.position.as_deref(), request.page_size)?;
+        self.select_response(keyspace_name, &table_name, &table, rows, trace_id)
+    }
+
+    /// Builds the response of a replica to a SELECT: the rows with the type of each column
+    /// and the shards of their counters.
+    fn select_response(
+        &self,
+        keyspace_name: &str,
+        table_name: &str,
+        table: &EncryptedTable,
+        mut rows: Vec<HashMap<String, String>>,
+        trace_id: &str,
+    ) -> Result<String, String> {
+        self.fill_counter_shards(keyspace_name, table_name, table, &mut rows);
+        // Las filas se responden con el tipo de cada columna
+        let mut response = table_row_set(keyspace_name, table_name, &table.get_columns());
+        response.extend_from_text(&rows);
+
+        match serde_json::to_string(&response) {
+            Ok(json) => {
+                let _ = self.logger.log_traced(
+                    trace_id,
+                    format!("Returning select values from table: {}", table_name).as_str(),
+                );
+                Ok(json)
+            }
+            Err(e) => Err(format!("Error serializing response: {}", e)),
+        }
+    }
+
+    /// Fills the counter columns of the rows read from a table with the shards this replica
+    /// holds, so the coordinator can merge them with the ones of the other replicas.
+    fn fill_counter_shards(
+        &self,
+        keyspace_name: &str,
+        table_name: &str,
+        table: &EncryptedTable,
+        rows: &mut [HashMap<String, String>],
+    ) {
+        let counter_columns = counter_columns(&table.get_columns());
+        if counter_columns.is_empty() {
+            return;
+        }
+        let mut key_columns = table.get_partition_key_columns();
+        key_columns.extend(table.get_clustering_key_columns());
+        for row in rows {
+            let row_key: HashMap<String, String> = key_columns
+                .iter()
+                .filter_map(|column| Some((column.clone(), row.get(column)?.clone())))
+                .collect();
+            let key = counter_key(keyspace_name, table_name, &row_key);
+            for column in &counter_columns {
+                let shards = self.counters.shards(&key, column);
+                if let Ok(shards) = serde_json::to_string(&shards) {
+                    row.insert(column.clone(), shards);
+                }
+            }
+        }
+    }
+
+    // ------------------------ Paxos ------------------------
+
+    /// Executes a lightweight transaction (INSERT ... IF NOT EXISTS or UPDATE ... IF) with a
+    /// Paxos round between the replicas of the partition, so only one of the conditional
+    /// writes racing on a partition is applied.
+    ///
+    /// # Parameters
+    /// - `query_parsed`: The conditional INSERT or UPDATE.
+    /// - `query_str`: The statement sent by the client.
+    /// - `keyspace_name`: The keyspace of the table.
+    /// - `consistency_level`: The consistency level required to commit the write.
+    /// - `trace_id`: The trace id of the query.
+    ///
+    /// # Returns
+    /// The rows with the `[applied]` column: only `true` if the write was applied, or `false`
+    /// and the current rows of the partition if the condition didn't hold. Err(ErrorCode) if
+    /// the round couldn't be completed.
+    fn execute_cas(
+        &self,
+        query_parsed: &ParsedQuery,
+        query_str: &str,
+        keyspace_name: &str,
+        consistency_level: &Consistency,
+        trace_id: &str,
+    ) -> Result<QueryResult, ErrorCode> {
+        let (table_name, read_condition, opcode, statement) = match query_parsed {
+            ParsedQuery::Insert {
+                table_name,
+                rows_to_insert,
+                ..
+            } => {
+                let row = rows_to_insert.first().ok_or(ErrorCode::Invalid)?;
+                let read_condition = self.primary_key_condition(keyspace_name, table_name, row)?;
+                (table_name, read_condition, 2, add_timestamp_to_insert_message(query_str))
+            }
+            ParsedQuery::Update {
+                table_name,
+                condition,
+                ..
+            } => (
+                table_name,
+                condition.clone(),
+                4,
+                add_timestamp_to_update_message(query_str),
+            ),
+            _ => return Err(ErrorCode::Invalid),
+        };
+
+        let (partition_key_columns, primary_key_columns, columns) =
+            match self.get_shared_table(keyspace_name, table_name) {
+                Ok(table) => match table.read() {
+                    Ok(table) => {
+                        let partition_key_columns = table.get_partition_key_columns();
+                        let mut primary_key_columns = partition_key_columns.clone();
+                        primary_key_columns.extend(table.get_clustering_key_columns());
+                        (partition_key_columns, primary_key_columns, table.get_columns())
+                    }
+                    Err(_) => return Err(ErrorCode::ServerError),
+                },
+                Err(_) => return Err(ErrorCode::Invalid),
+            };
+        let equalities = extract_equalities(&read_condition);
+        let partition_values: Vec<String> = partition_key_columns
+            .iter()
+            .map(|column| equalities.get(column).cloned().unwrap_or_default())
+            .collect();
+        let key = format!("{}.{}:{:?}", keyspace_name, table_name, partition_values);
+
+        let replicas = self.get_nodes_for_condition(keyspace_name, table_name, &read_condition);
+        if replicas.is_empty() {
+            // Una transacción liviana se aplica sobre una sola partición
+            let _ = self
+                .logger
+                .log_traced(trace_id, "Conditional statement doesn't restrict the partition key");
+            return Err(ErrorCode::Invalid);
+        }
+        let quorum = replicas.len() / 2 + 1;
+        let _ = self.logger.log_traced(
+            trace_id,
+            format!("Paxos round on {} with replicas {:?}", key, replicas).as_str(),
+        );
+
+        let mut newest_seen: Option<Ballot> = None;
+        let mut promised = 0;
+        for attempt in 0..MAX_CAS_ATTEMPTS {
+            if attempt > 0 {
+                contention_backoff(attempt);
+            }
+            if inflight::is_cancelled() {
+                let _ = self
+                    .logger
+                    .log_traced(trace_id, format!("Paxos round on {} killed", key).as_str());
+                return Err(ErrorCode::ServerError);
+            }
+            let ballot = Ballot::new(&self.id, newest_seen.as_ref());
+
+            // PREPARE
+            let prepare = Prepare {
+                key: key.clone(),
+                ballot: ballot.clone(),
+                table_name: table_name.to_string(),
+                read_condition: read_condition.clone(),
+            };
+            let responses = self.send_paxos_message(
+                &replicas,
+                7,
+                to_paxos_body(&prepare)?,
+                keyspace_name,
+                trace_id,
+                quorum,
+            )?;
+            let promises: Vec<Promise> = responses
+                .iter()
+                .filter_map(|response| serde_json::from_str(response).ok())
+                .collect();
+            for promise in &promises {
+                if newest_seen
+                    .as_ref()
+                    .is_none_or(|newest| promise.highest_promised > *newest)
+                {
+                    newest_seen = Some(promise.highest_promised.clone());
+                }
+            }
+            let promises: Vec<Promise> = promises.into_iter().filter(|p| p.promised).collect();
+            promised = promises.len();
+            if promised < quorum {
+                let _ = self
+                    .logger
+                    .log_traced(trace_id, "Paxos prepare rejected by a newer ballot, retrying");
+                continue;
+            }
+
+            // Una propuesta aceptada y no confirmada de otro coordinador se termina primero
+            let most_recent_commit = promises
+                .iter()
+                .filter_map(|promise| promise.most_recent_commit.clone())
+                .max();
+            let in_progress = promises
+                .iter()
+                .filter_map(|promise| promise.accepted.clone())
+                .filter(|accepted| {
+                    most_recent_commit
+                        .as_ref()
+                        .is_none_or(|committed| accepted.ballot > *committed)
+                })
+                .max_by(|a, b| a.ballot.cmp(&b.ballot));
+            if let Some(in_progress) = in_progress {
+                let _ = self
+                    .logger
+                    .log_traced(trace_id, "Finishing an in-progress Paxos proposal");
+                let proposal = Proposal {
+                    ballot,
+                    ..in_progress
+                };
+                self.propose_and_commit(
+                    &replicas,
+                    &key,
+                    proposal,
+                    keyspace_name,
+                    consistency_level,
+                    trace_id,
+                )?;
+                continue;
+            }
+
+            let responses: Vec<RowSet> = promises
+                .iter()
+                .map(|promise| {
+                    let mut rows = table_row_set(keyspace_name, table_name, &columns);
+                    rows.extend_from_text(&promise.rows);
+                    rows
+                })
+                .collect();
+            let rows = merge_select_responses(&responses, &primary_key_columns, &[], None);
+            let applies = match query_parsed {
+                ParsedQuery::Update {
+                    if_condition: Some(if_condition),
+                    ..
+                } => {
+                    let column_types = rows
+                        .columns
+                        .iter()
+                        .map(|column| (column.name.clone(), column.column_type))
+                        .collect();
+                    !rows.rows.is_empty()
+                        && rows.text_rows().iter().all(|row| {
+                            evaluate_expression_with_types(if_condition, row, &column_types)
+                                .unwrap_or(false)
+                        })
+                }
+                _ => rows.rows.is_empty(),
+            };
+            if !applies {
+                let _ = self
+                    .logger
+                    .log_traced(trace_id, "Condition not met, statement not applied");
+                return Ok(cas_result(false, rows));
+            }
+
+            // PROPOSE y COMMIT
+            let proposal = Proposal {
+                ballot,
+                opcode,
+                statement: statement.clone(),
+            };
+            if self.propose_and_commit(
+                &replicas,
+                &key,
+                proposal,
+                keyspace_name,
+                consistency_level,
+                trace_id,
+            )? {
+                let _ = self
+                    .logger
+                    .log_traced(trace_id, "Conditional statement applied");
+                return Ok(cas_result(true, RowSet::new(keyspace_name, table_name, vec![])));
+            }
+        }
+
+        let _ = self.logger.log_traced(
+            trace_id,
+            format!("Paxos round on {} gave up after {} attempts", key, MAX_CAS_ATTEMPTS).as_str(),
+        );
+        Err(ErrorCode::WriteTimeout {
+            consistency: consistency_level.to_consistency_level(),
+            received: promised as i32,
+            block_for: quorum as i32,
+            write_type: WriteType::Cas,
+        })
+    }
+
+    /// Sends a proposal to the replicas and, if a quorum accepts it, commits it.
+    ///
+    /// # Returns
+    /// Ok(true) if the proposal was committed, Ok(false) if a newer ballot prevented it from
+    /// being accepted, or Err(ErrorCode) if not enough replicas confirmed the commit.
+    fn propose_and_commit(
+        &self,
+        replicas: &[String],
+        key: &str,
+        proposal: Proposal,
+        keyspace_name: &str,
+        consistency_level: &Consistency,
+        trace_id: &str,
+    ) -> Result<bool, ErrorCode> {
+        let message = ProposalMessage {
+            key: key.to_string(),
+            proposal,
+        };
+        let body = to_paxos_body(&message)?;
+        let quorum = replicas.len() / 2 + 1;
+
+        let accepted =
+            self.send_paxos_message(replicas, 8, body.clone(), keyspace_name, trace_id, quorum)?;
+        if accepted.len() < quorum {
+            return Ok(false);
+        }
+
+        let required = consistency_level.required_nodes(replicas.len());
+        let committed =
+            self.send_paxos_message(replicas, 9, body, keyspace_name, trace_id, required)?;
+        if committed.len() < required {
+            return Err(ErrorCode::WriteTimeout {
+                consistency: consistency_level.to_consistency_level(),
+                received: committed.len() as i32,
+                block_for: required as i32,
+                write_type: WriteType::Cas,
+            });
+        }
+        Ok(true)
+    }
+
+    /// Sends a Paxos message to every replica and waits until `required` of them answered
+    /// successfully, all of them answered, or the timeout expires. Commits to unreachable
+    /// replicas are stored as hints, so they are applied when the replica comes back.
+    ///
+    /// # Returns
+    /// The successful answers, or Err(ErrorCode) if the worker pool is saturated or the query
+    /// was killed.
+    fn send_paxos_message(
+        &self,
+        replicas: &[String],
+        opcode: u8,
+        body: String,
+        keyspace_name: &str,
+        trace_id: &str,
+        required: usize,
+    ) -> Result<Vec<String>, ErrorCode> {
+        let to_send = InternalMessage::Query {
+            opcode,
+            body,
+            keyspace_name: keyspace_name.to_string(),
+            trace_id: trace_id.to_string(),
+        };
+        let (tx, rx) = mpsc::channel();
+        for node_id in replicas {
+            if *node_id == self.id {
+                continue;
+            }
+            let internode = Arc::clone(&self.internode);
+            let to_send = to_send.clone();
+            let node_id = node_id.clone();
+            let tx = tx.clone();
+            self.run_in_background(move || {
+                let response = if opcode == 9 {
+                    internode.send(&to_send, &node_id)
+                } else {
+                    internode.send_without_hint(&to_send, &node_id)
+                };
+                let _ = tx.send(response);
+            })?;
+        }
+        if replicas.contains(&self.id) {
+            let _ = tx.send(self.receive_internal_message(&to_send));
+        }
+        drop(tx);
+
+        let deadline = Instant::now() + self.timeouts.paxos_phase();
+        let mut responses = vec![];
+        while responses.len() < required {
+            if inflight::is_cancelled() {
+                return Err(ErrorCode::ServerError);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining.min(CANCEL_POLL_INTERVAL)) {
+                Ok(Ok(response)) => responses.push(response),
+                Ok(Err(e)) => {
+                    let _ = self.logger.log_with(
+                        LogLevel::Error,
+                        format!("Paxos message failed: {}", e).as_str(),
+                        &[("query_id", trace_id)],
+                    );
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) if Instant::now() < deadline => {}
+                // Respondieron todas las réplicas o se terminó el tiempo
+                Err(_) => break,
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Handles a PAXOS_PREPARE message: promises the ballot if it's the newest one seen for
+    /// the partition, and reads the rows the conditional statement depends on.
+    fn paxos_prepare(&self, keyspace_name: &str, body: &str) -> Result<String, String> {
+        let prepare: Prepare = serde_json::from_str(body)
+            .map_err(|e| format!("Error deserializing Paxos prepare: {}", e))?;
+        let (promised, highest_promised, accepted, most_recent_commit) =
+            self.paxos.prepare(&prepare.key, &prepare.ballot)?;
+        let rows = match self.get_shared_table(keyspace_name, &prepare.table_name) {
+            Ok(table) if promised => table
+                .read()
+                .map_err(|_| "Error locking table".to_string())?
+                .select_if(&prepare.read_condition),
+            _ => vec![],
+        };
+        let promise = Promise {
+            promised,
+            highest_promised,
+            accepted,
+            most_recent_commit,
+            rows,
+        };
+        serde_json::to_string(&promise).map_err(|e| format!("Error serializing Paxos promise: {}", e))
+    }
+
+    /// Handles a PAXOS_PROPOSE message: accepts the proposal unless a newer ballot was promised.
+    fn paxos_propose(&self, body: &str) -> Result<String, String> {
+        let message: ProposalMessage = serde_json::from_str(body)
+            .map_err(|e| format!("Error deserializing Paxos proposal: {}", e))?;
+        if self.paxos.propose(&message.key, &message.proposal)? {
+            Ok("Proposal accepted".to_string())
+        } else {
+            Err("Proposal rejected: a newer ballot was promised".to_string())
+        }
+    }
+
+    /// Handles a PAXOS_COMMIT message: applies the statement of the proposal, unless a newer
+    /// one was already committed on the partition.
+    fn paxos_commit(
+        &self,
+        keyspace_name: &str,
+        body: &str,
+        trace_id: &str,
+    ) -> Result<String, String> {
+        let message: ProposalMessage = serde_json::from_str(body)
+            .map_err(|e| format!("Error deserializing Paxos commit: {}", e))?;
+        if !self.paxos.commit(&message.key, &message.proposal)? {
+            return Ok("Proposal already committed".to_string());
+        }
+        self.receive_internal_message(&InternalMessage::Query {
+            opcode: message.proposal.opcode,
+            body: message.proposal.statement,
+            keyspace_name: keyspace_name.to_string(),
+            trace_id: trace_id.to_string(),
+        })
+    }
+
+    /// Builds the condition that selects the row of an INSERT: an equality on each column of
+    /// the primary key.
+    fn primary_key_condition(
+        &self,
+        keyspace_name: &str,
+        table_name: &str,
+        row: &HashMap<String, String>,
+    ) -> Result<Expression, ErrorCode> {
+        let table = self
+            .get_shared_table(keyspace_name, table_name)
+            .map_err(|_| ErrorCode::Invalid)?;
+        let key_columns = match table.read() {
+            Ok(table) => {
+                let mut columns = table.get_partition_key_columns();
+                columns.extend(table.get_clustering_key_columns());
+                columns
+            }
+            Err(_) => return Err(ErrorCode::ServerError),
+        };
+
+        key_condition(&key_columns, row).ok_or(ErrorCode::Invalid)
+    }
+
+    // ------------------------  Resend Query ------------------------//
+
+    /// Resends a query as an internal message to the corresponding nodes.
+    ///
+    /// # Parameters
+    ///  `query`: A `Query` containing the string representation of the query to be executed.
+    ///  `current_keyspace`: keyspace name indicating the context under which the query should be run.
+    ///
+    /// # Returns
+    /// A `QueryResult` containing the response from the first node to respond, or an `ErrorCode` if the operation failed.
+    /// Reads and writes are recorded in the metrics of the node.
+    pub fn resend_query_as_internal_message(
+        &self,
+        query: Query,
+        current_keyspace: Option<String>,
+    ) -> Result<QueryResult, ErrorCode> {
+        match operation_of(&query.query_string) {
+            Some(operation) => self.metrics.time(operation, || {
+                self.coordinate_query(query, current_keyspace)
+            }),
+            None => self.coordinate_query(query, current_keyspace),
+        }
+    }
+
+    fn coordinate_query(
+        &self,
+        query: Query,
+        current_keyspace: Option<String>,
+    ) -> Result<QueryResult, ErrorCode> {
+        let query_str = query.query_string;
+        let page_size = query.result_page_size;
+        let paging_state = query.paging_state;
+        let trace_id = new_trace_id();
+        let _span = info_span!(
+            "coordinator",
+            node = %self.id,
+            query_id = %trace_id,
+            keyspace = current_keyspace.as_deref().unwrap_or_default()
+        )
+        .entered();
+        tracing::debug!(query = %query_str, "received query from client");
+        let _inflight = self.inflight.register(
+            &trace_id,
+            &query_str,
+            current_keyspace.as_deref().unwrap_or_default(),
+        );
+        let _ = self
+            .logger
+            .log_traced(&trace_id, format!("Received query from client: {}", query_str).as_str());
+        let consistency_level =
+            Consistency::from_consistency_level(query.consistency_level, &self.default_consistency);
+
+        let Ok(query_parsed) = parse_instruction(&query_str) else {
+            eprintln!("Error parsing query");
+            return Err(ErrorCode::SyntaxError);
+        };
+        self.inflight.set_state(&trace_id, "coordinating");
+
+        // Las tablas de system_schema las responde el coordinador con su esquema
+        if let ParsedQuery::Select {
+            table_name,
+            condition,
+            ..
+        } = &query_parsed
+        {
+            let system_rows = if let Some(system_table) =
+                table_name.strip_prefix(&format!("{}.", SYSTEM_SCHEMA))
+            {
+                Some(self.system_schema_rows(system_table, condition))
+            } else {
+                table_name
+                    .strip_prefix(&format!("{}.", SYSTEM))
+                    .map(|system_table| self.system_rows(system_table, condition))
+            };
+            if let Some(rows) = system_rows {
+                return match rows {
+                    Ok(rows) => Ok(QueryResult::from_row_set(&rows, None)),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        Err(ErrorCode::Invalid)
+                    }
+                };
+            }
+        }
+
+        if current_keyspace.is_none() {
+            if let ParsedQuery::CreateKeyspace { .. } | ParsedQuery::UseKeyspace { .. } =
+                query_parsed
+            {
+                // do nothing
+            } else {
+                eprintln!("No keyspace set");
+                return Err(ErrorCode::Invalid);
+            }
+        }
+        let mut keyspace_name = "".to_string();
+        if let Some(keyspace_name_as_string) = current_keyspace {
+            keyspace_name = keyspace_name_as_string;
+        }
+
+        if let ParsedQuery::Update {
+            table_name,
+            condition,
+            counter_increments,
+            ..
+        } = &query_parsed
+        {
+            if !counter_increments.is_empty() {
+                return self.execute_counter_update(
+                    table_name,
+                    condition,
+                    counter_increments,
+                    &keyspace_name,
+                    &consistency_level,
+                    &trace_id,
+                );
+            }
+        }
+
+        if query_parsed.is_conditional() {
+            self.inflight.set_state(&trace_id, "paxos");
+            return self.execute_cas(
+                &query_parsed,
+                &query_str,
+                &keyspace_name,
+                &consistency_level,
+                &trace_id,
+            );
+        }
+
+        match &query_parsed {
+            ParsedQuery::CreateKeyspace {
+                keyspace_name,
+                replication_strategy,
+                replication_factor,
+            } => {
+                let body = self
+                    .check_replication(
+                        &query_str,
+                        keyspace_name,
+                        replication_strategy,
+                        replication_factor,
+                    )
+                    .map_err(|_| ErrorCode::ConfigError)?;
+                let to_send = InternalMessage::Query {
+                    opcode: 0,
+                    body,
+                    keyspace_name: "not_neccessary".to_string(),
+                    trace_id: trace_id.clone(),
                 };
                 let nodes_to_resend_query = self.get_all_nodes();
                 let _ = self
                     .logger
-                    .log(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
+                    .debug(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
                 let mut responses = vec![];
-                let self_cloned = Arc::new(self.clone());
                 for node_id in &nodes_to_resend_query {
-                    responses.push(self_cloned.resend(&to_send, node_id));
+                    responses.push(self.resend(&to_send, node_id));
                 }
 
                 let final_response =
@@ -1141,15 +2885,15 @@ impl Node {
                     opcode: 1,
                     body: query_str.to_string(),
                     keyspace_name,
+                    trace_id: trace_id.clone(),
                 };
                 let nodes_to_resend_query = self.get_all_nodes();
                 let _ = self
                     .logger
-                    .log(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
+                    .debug(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
                 let mut responses = vec![];
-                let self_cloned = Arc::new(self.clone());
                 for node_id in &nodes_to_resend_query {
-                    responses.push(self_cloned.resend(&to_send, node_id));
+                    responses.push(self.resend(&to_send, node_id));
                 }
 
                 let final_response =
@@ -1177,22 +2921,42 @@ impl Node {
 
             ParsedQuery::Insert {
                 table_name,
+                columns_in_order,
                 rows_to_insert,
                 ..
             } => {
+                // Las filas de particiones con distintas replicas se envian por separado
+                let groups =
+                    self.group_rows_by_replicas(&keyspace_name, table_name, rows_to_insert);
+                if groups.len() > 1 {
+                    for rows in groups {
+                        let group_query = Query::default(
+                            insert_message_from_rows(table_name, columns_in_order, &rows),
+                            query.consistency_level,
+                        );
+                        self.coordinate_query(group_query, Some(keyspace_name.clone()))?;
+                    }
+                    return Ok(QueryResult::Void);
+                }
+
                 let query_str = add_timestamp_to_insert_message(&query_str);
 
                 let to_send = InternalMessage::Query {
                     opcode: 2,
                     body: query_str.to_string(),
                     keyspace_name: keyspace_name.clone(),
+                    trace_id: trace_id.clone(),
                 };
-                let mut nodes_to_resend_query =
-                    self.get_nodes_for_insert(&keyspace_name, table_name, &rows_to_insert[0]);
+                let (mut nodes_to_resend_query, dead_replicas, required) = self.live_replicas(
+                    self.get_nodes_for_insert(&keyspace_name, table_name, &rows_to_insert[0]),
+                    &consistency_level,
+                )?;
+                let required =
+                    self.hint_dead_replicas(&dead_replicas, &to_send, &consistency_level, required);
 
                 let _ = self
                     .logger
-                    .log(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
+                    .debug(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
 
                 let (tx, rx) = mpsc::sync_channel(1);
                 let number_of_nodes_to_resend = nodes_to_resend_query.len();
@@ -1213,13 +2977,15 @@ impl Node {
                     nodes_to_resend_query.remove(pos);
                 }
 
+                let counts_hints = consistency_level.counts_hints();
                 for node_id in nodes_to_resend_query {
-                    let self_cloned = Arc::new(self.clone());
+                    let internode = Arc::clone(&self.internode);
                     let to_send = to_send.clone();
                     let tx = tx.clone();
 
-                    std::thread::spawn(move || {
-                        let response = self_cloned.resend(&to_send, &node_id);
+                    self.run_in_background(move || {
+                        let response =
+                            write_response(internode.send_write(&to_send, &node_id), counts_hints);
 
                         match response {
                             Ok(response) => match tx.send(Ok(response)) {
@@ -1243,13 +3009,14 @@ impl Node {
                                 }
                             },
                         }
-                    });
+                    })?;
                 }
                 drop(tx);
 
-                match consistency_level.check_consistency_level(&rx, number_of_nodes_to_resend) {
+                self.inflight.set_state(&trace_id, "waiting for replicas");
+                match consistency_level.check_consistency_level(&rx, number_of_nodes_to_resend, required, self.timeouts.write_request()) {
                     Ok(_) => {
-                        let _ = self.logger.log(
+                        let _ = self.logger.debug(
                             format!(
                                 "Consistency level {:?} checked on: {:?}",
                                 consistency_level,
@@ -1260,16 +3027,16 @@ impl Node {
 
                         Ok(QueryResult::Void)
                     }
-                    Err(_) => {
-                        let _ = self.logger.log(
+                    Err(e) => {
+                        let _ = self.logger.warn(
                             format!(
-                                "Insert didn't meet consistency level on: {:?}",
-                                nodes_to_check
+                                "Insert didn't meet consistency level on: {:?} ({:?})",
+                                nodes_to_check, e
                             )
                             .as_str(),
                         );
 
-                        Err(ErrorCode::UnavailableException)
+                        Err(consistency_error_code(e, &consistency_level, false))
                     }
                 }
             }
@@ -1277,87 +3044,170 @@ impl Node {
                 condition,
                 //columns,
                 table_name,
+                order_by,
+                limit,
                 ..
             } => {
-                let to_send = InternalMessage::Query {
-                    opcode: 3,
-                    body: query_str.to_string(),
-                    keyspace_name: keyspace_name.clone().to_string(),
+                // Sin ORDER BY cada réplica devuelve solo la página, desde la posición de la
+                // última fila de la anterior: las filas están en el mismo orden en todas.
+                let position_paging = match page_size {
+                    Some(page_size) if order_by.is_empty() => {
+                        let state = match paging_state.as_deref() {
+                            Some(bytes) => {
+                                Some(PagingState::from_bytes(bytes).ok_or(ErrorCode::ProtocolError)?)
+                            }
+                            None => None,
+                        };
+                        let returned = state.as_ref().map_or(0, |state| state.returned as usize);
+                        let page_size =
+                            usize::try_from(page_size).map_err(|_| ErrorCode::ProtocolError)?.max(1);
+                        let page_size = match limit {
+                            Some(limit) => page_size.min(limit.saturating_sub(returned)),
+                            None => page_size,
+                        };
+                        // El estado de la última página del LIMIT no se devuelve
+                        if page_size == 0 {
+                            return Err(ErrorCode::ProtocolError);
+                        }
+                        Some((returned, page_size, state.map(|state| state.position)))
+                    }
+                    _ => None,
+                };
+                let to_send = match &position_paging {
+                    Some((_, page_size, position)) => InternalMessage::Query {
+                        opcode: SELECT_PAGE_OPCODE,
+                        body: serde_json::to_string(&PageRequest {
+                            query: query_str.to_string(),
+                            page_size: *page_size,
+                            position: position.clone(),
+                        })
+                        .map_err(|_| ErrorCode::ServerError)?,
+                        keyspace_name: keyspace_name.clone(),
+                        trace_id: trace_id.clone(),
+                    },
+                    None => InternalMessage::Query {
+                        opcode: 3,
+                        body: query_str.to_string(),
+                        keyspace_name: keyspace_name.clone().to_string(),
+                        trace_id: trace_id.clone(),
+                    },
                 };
-                let table_name_to_find = format!("{}.{}", keyspace_name, table_name);
-
-                let data = match self.data.read() {
-                    Ok(data) => data.clone(),
 
-                    Err(_) => {
-                        return Err(ErrorCode::UnavailableException);
-                    }
+                let unavailable = ErrorCode::UnavailableException {
+                    consistency: consistency_level.to_consistency_level(),
+                    required: 0,
+                    alive: 0,
+                };
+                let (partition_key_columns, primary_key_columns, counter_columns) = match self.get_shared_table(&keyspace_name, table_name) {
+                    Ok(table) => match table.read() {
+                        Ok(table) => {
+                            let partition_key_columns = table.get_partition_key_columns();
+                            let mut columns = partition_key_columns.clone();
+                            columns.extend(table.get_clustering_key_columns());
+                            (partition_key_columns, columns, counter_columns(&table.get_columns()))
+                        }
+                        Err(_) => return Err(unavailable),
+                    },
+                    // Table not found
+                    Err(_) => return Err(unavailable),
                 };
 
-                if !data.contains_key(&table_name_to_find) {
-                    // println!("Table not found");
-                    return Err(ErrorCode::UnavailableException); // Table not found
+                // Si la condición no fija la partition key, se lee cada rango de tokens de sus
+                // réplicas, con el consistency level del cliente en cada rango.
+                let partition_replicas =
+                    self.get_nodes_for_condition(&keyspace_name, table_name, condition);
+                let scatter = partition_replicas.is_empty();
+                let (ranges, consistency_level) = match scatter {
+                    false => (
+                        vec![partition_replicas],
+                        consistency_level.for_reads(&self.default_consistency),
+                    ),
+                    true => match self.get_nodes_for_ranges(&keyspace_name) {
+                        Some(ranges) => (ranges, consistency_level.for_reads(&self.default_consistency)),
+                        // Con RandomStrategy cualquier nodo puede tener cualquier partición
+                        None => (vec![self.get_all_nodes()], Consistency::All),
+                    },
+                };
+                let mut range_replicas = vec![];
+                for replicas in ranges {
+                    let (live, _, required) = self.live_replicas(replicas, &consistency_level)?;
+                    range_replicas.push((live, required));
                 }
 
-                let mut nodes_to_resend_query =
-                    self.get_nodes_for_condition(&keyspace_name, condition);
+                // Cada nodo recibe la query una vez y su respuesta cuenta para todos sus rangos
+                let mut nodes_to_resend_query: Vec<String> = vec![];
+                for node_id in range_replicas.iter().flat_map(|(live, _)| live) {
+                    if !nodes_to_resend_query.contains(node_id) {
+                        nodes_to_resend_query.push(node_id.clone());
+                    }
+                }
 
                 let _ = self
                     .logger
-                    .log(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
-
-                let (tx, rx) = mpsc::channel();
-                let number_of_nodes_to_resend = nodes_to_resend_query.len();
+                    .debug(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
+
+                let (senders, receivers): (Vec<_>, Vec<_>) =
+                    range_replicas.iter().map(|_| mpsc::channel()).unzip();
+                let senders_of = |node_id: &String| -> Vec<mpsc::Sender<Result<String, String>>> {
+                    range_replicas
+                        .iter()
+                        .zip(&senders)
+                        .filter(|((live, _), _)| live.contains(node_id))
+                        .map(|(_, tx)| tx.clone())
+                        .collect()
+                };
 
                 let nodes_to_check = nodes_to_resend_query.clone();
 
-                if let Some(pos) = nodes_to_resend_query.iter().position(|x| *x == self.id) {
-                    let response = self.receive_internal_message(&to_send);
-                    tx.send(response).unwrap();
+                let local_position = nodes_to_resend_query.iter().position(|x| *x == self.id);
+                if let Some(pos) = local_position {
                     nodes_to_resend_query.remove(pos);
                 }
 
+                // Las consultas remotas salen en paralelo antes de resolver la local.
                 for node_id in nodes_to_resend_query {
                     let to_send = to_send.clone();
-                    let tx = tx.clone();
-                    let cloned_gossip_table = local_gossip_table.clone();
-
-                    std::thread::spawn(move || {
-                        let response =
-                            resend_without_storing_hint(&cloned_gossip_table, &to_send, &node_id);
-                        match response {
-                            Ok(response) => match tx.send(Ok(response)) {
-                                Ok(_) => {
-                                    println!("Sent OK response to rx successfully");
-                                    drop(tx);
-                                }
-                                Err(_) => {
-                                    println!("Consistency level already met");
-                                    drop(tx);
-                                }
-                            },
-                            Err(e) => match tx.send(Err(e)) {
-                                Ok(_) => {
-                                    println!("Sent Error response to rx successfully");
-                                    drop(tx);
-                                }
-                                Err(_) => {
-                                    println!("Consistency level already met");
-                                    drop(tx);
-                                }
-                            },
+                    let txs = senders_of(&node_id);
+                    let internode = Arc::clone(&self.internode);
+
+                    self.run_in_background(move || {
+                        let response = internode.send_without_hint(&to_send, &node_id);
+                        for tx in txs {
+                            if tx.send(response.clone()).is_err() {
+                                println!("Consistency level already met");
+                            }
                         }
-                    });
+                    })?;
                 }
 
-                match consistency_level.check_consistency_level(&rx, number_of_nodes_to_resend) {
+                if local_position.is_some() {
+                    let response = self.receive_internal_message(&to_send);
+                    for tx in senders_of(&self.id) {
+                        if let Err(e) = tx.send(response.clone()) {
+                            eprintln!("Error sending response to rx: {}", e);
+                        }
+                    }
+                }
+                drop(senders);
+
+                self.inflight.set_state(&trace_id, "waiting for replicas");
+                let responses = range_replicas.iter().zip(&receivers).try_fold(
+                    Vec::new(),
+                    |mut responses: Vec<String>, ((live, required), rx)| {
+                        for response in
+                            consistency_level.check_consistency_level(rx, live.len(), *required, self.timeouts.read_request())?
+                        {
+                            // Una réplica de varios rangos manda la misma respuesta a cada uno
+                            if !responses.contains(&response) {
+                                responses.push(response);
+                            }
+                        }
+                        Ok(responses)
+                    },
+                );
+                match responses {
                     Ok(responses) => {
-                        // Vamos a comparar la columna timestamp de las responses para ver si hay que hacer read repair
-                        // Si coinciden, devolvemos la respuesta 0
-                        // Si no coinciden, vamos a hacer read repair
-                        //     Vamos a ver cual es la respuesta con el timestamp mas grande
-                        //     Luego enviamos insert a todos los nodos
-                        let _ = self.logger.log(
+                        let _ = self.logger.debug(
                             format!(
                                 "Consistency level {:?} checked on: {:?}",
                                 consistency_level,
@@ -1365,41 +3215,98 @@ impl Node {
                             )
                             .as_str(),
                         );
+                        let responses: Vec<RowSet> = responses
+                            .iter()
+                            .filter_map(|response| match serde_json::from_str(response) {
+                                Ok(rows) => Some(rows),
+                                Err(e) => {
+                                    eprintln!("Error deserializing response: {}", e);
+                                    None
+                                }
+                            })
+                            .collect();
+                        // Cada réplica devuelve sus shards de los contadores: se suman los más nuevos.
+                        let responses = merge_counter_responses(
+                            responses,
+                            &primary_key_columns,
+                            &counter_columns,
+                        );
 
-                        let final_response =
-                            self.read_repair(&responses, &keyspace_name, table_name);
+                        // Las réplicas de una misma partición pueden diferir: se repara la más vieja.
+                        if !scatter {
+                            self.inflight.set_state(&trace_id, "read repair");
+                            let _ = self.read_repair(&responses, &keyspace_name, table_name, &trace_id);
+                        }
 
-                        Ok(QueryResult::parse_json_to_rows(&final_response))
+                        let (rows, next_page) = match (position_paging, page_size) {
+                            (Some((returned, page_size, _)), _) => {
+                                // Una réplica que llenó la página puede tener más filas
+                                let replica_filled =
+                                    responses.iter().any(|rows| rows.rows.len() >= page_size);
+                                let rows = merge_select_responses(
+                                    &responses,
+                                    &primary_key_columns,
+                                    order_by,
+                                    None,
+                                );
+                                ring_page(
+                                    rows,
+                                    &partition_key_columns,
+                                    &primary_key_columns,
+                                    (returned, page_size),
+                                    replica_filled,
+                                    *limit,
+                                )
+                            }
+                            (None, Some(page_size)) => page_of(
+                                merge_select_responses(&responses, &primary_key_columns, order_by, *limit),
+                                page_size,
+                                paging_state.as_deref(),
+                            )?,
+                            (None, None) => (
+                                merge_select_responses(&responses, &primary_key_columns, order_by, *limit),
+                                None,
+                            ),
+                        };
+                        Ok(QueryResult::from_row_set(&rows, next_page))
                     }
-                    Err(_) => {
-                        let _ = self.logger.log(
+                    Err(e) => {
+                        let _ = self.logger.warn(
                             format!(
-                                "Select didn't meet consistency level on: {:?}",
-                                nodes_to_check
+                                "Select didn't meet consistency level on: {:?} ({:?})",
+                                nodes_to_check, e
                             )
                             .as_str(),
                         );
-                        // println!("Error checking consistency level");
-                        Err(ErrorCode::UnavailableException)
+                        Err(consistency_error_code(e, &consistency_level, true))
                     }
                 }
             }
 
-            ParsedQuery::Update { condition, .. } => {
+            ParsedQuery::Update {
+                condition,
+                table_name,
+                ..
+            } => {
                 let query_str = add_timestamp_to_update_message(&query_str);
 
                 let to_send = InternalMessage::Query {
                     opcode: 4,
                     body: query_str.to_string(),
                     keyspace_name: keyspace_name.clone(),
+                    trace_id: trace_id.clone(),
                 };
 
-                let mut nodes_to_resend_query =
-                    self.get_nodes_for_condition(keyspace_name.as_str(), condition);
+                let (mut nodes_to_resend_query, dead_replicas, required) = self.live_replicas(
+                    self.get_nodes_for_mutation(keyspace_name.as_str(), table_name, condition),
+                    &consistency_level,
+                )?;
+                let required =
+                    self.hint_dead_replicas(&dead_replicas, &to_send, &consistency_level, required);
 
                 let _ = self
                     .logger
-                    .log(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
+                    .debug(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
 
                 let (tx, rx) = mpsc::channel();
                 let number_of_nodes_to_resend = nodes_to_resend_query.len();
@@ -1413,13 +3320,15 @@ impl Node {
                     nodes_to_resend_query.remove(pos);
                 }
 
+                let counts_hints = consistency_level.counts_hints();
                 for node_id in nodes_to_resend_query {
-                    let self_cloned = Arc::new(self.clone());
+                    let internode = Arc::clone(&self.internode);
                     let to_send = to_send.clone();
                     let tx = tx.clone();
 
-                    std::thread::spawn(move || {
-                        let response = self_cloned.resend(&to_send, &node_id);
+                    self.run_in_background(move || {
+                        let response =
+                            write_response(internode.send_write(&to_send, &node_id), counts_hints);
                         match response {
                             Ok(response) => match tx.send(Ok(response)) {
                                 Ok(_) => {
@@ -1442,12 +3351,13 @@ impl Node {
                                 }
                             },
                         }
-                    });
+                    })?;
                 }
 
-                match consistency_level.check_consistency_level(&rx, number_of_nodes_to_resend) {
+                self.inflight.set_state(&trace_id, "waiting for replicas");
+                match consistency_level.check_consistency_level(&rx, number_of_nodes_to_resend, required, self.timeouts.write_request()) {
                     Ok(_) => {
-                        let _ = self.logger.log(
+                        let _ = self.logger.debug(
                             format!(
                                 "Consistency level {:?} checked on: {:?}",
                                 consistency_level,
@@ -1458,30 +3368,38 @@ impl Node {
                         Ok(QueryResult::Void)
                     }
 
-                    Err(_) => {
-                        let _ = self.logger.log(
+                    Err(e) => {
+                        let _ = self.logger.warn(
                             format!(
-                                "Update didn't meet consistency level on: {:?}, returning Err",
-                                nodes_to_check
+                                "Update didn't meet consistency level on: {:?} ({:?}), returning Err",
+                                nodes_to_check, e
                             )
                             .as_str(),
                         );
-                        Err(ErrorCode::UnavailableException)
+                        Err(consistency_error_code(e, &consistency_level, false))
                     }
                 }
             }
-            ParsedQuery::Delete { condition, .. } => {
+            ParsedQuery::Delete {
+                condition,
+                table_name,
+            } => {
                 let to_send = InternalMessage::Query {
                     opcode: 5,
                     body: query_str.to_string(),
                     keyspace_name: keyspace_name.clone(),
+                    trace_id: trace_id.clone(),
                 };
-                let mut nodes_to_resend_query =
-                    self.get_nodes_for_condition(keyspace_name.as_str(), condition);
+                let (mut nodes_to_resend_query, dead_replicas, required) = self.live_replicas(
+                    self.get_nodes_for_mutation(keyspace_name.as_str(), table_name, condition),
+                    &consistency_level,
+                )?;
+                let required =
+                    self.hint_dead_replicas(&dead_replicas, &to_send, &consistency_level, required);
 
                 let _ = self
                     .logger
-                    .log(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
+                    .debug(format!("Nodes to resend query: {:?}", nodes_to_resend_query).as_str());
 
                 let (tx, rx) = mpsc::channel();
                 let number_of_nodes_to_resend = nodes_to_resend_query.len();
@@ -1493,12 +3411,14 @@ impl Node {
                     nodes_to_resend_query.remove(pos);
                 }
 
+                let counts_hints = consistency_level.counts_hints();
                 for node_id in nodes_to_resend_query {
-                    let self_cloned = Arc::new(self.clone());
+                    let internode = Arc::clone(&self.internode);
                     let to_send = to_send.clone();
                     let tx = tx.clone();
-                    std::thread::spawn(move || {
-                        let response = self_cloned.resend(&to_send, &node_id);
+                    self.run_in_background(move || {
+                        let response =
+                            write_response(internode.send_write(&to_send, &node_id), counts_hints);
                         match response {
                             Ok(response) => match tx.send(Ok(response)) {
                                 Ok(_) => {
@@ -1521,12 +3441,13 @@ impl Node {
                                 }
                             },
                         }
-                    });
+                    })?;
                 }
 
-                match consistency_level.check_consistency_level(&rx, number_of_nodes_to_resend) {
+                self.inflight.set_state(&trace_id, "waiting for replicas");
+                match consistency_level.check_consistency_level(&rx, number_of_nodes_to_resend, required, self.timeouts.write_request()) {
                     Ok(_) => {
-                        let _ = self.logger.log(
+                        let _ = self.logger.debug(
                             format!(
                                 "Consistency level {:?} checked on: {:?}",
                                 consistency_level,
@@ -1537,15 +3458,15 @@ impl Node {
                         Ok(QueryResult::Void)
                     }
 
-                    Err(_) => {
-                        let _ = self.logger.log(
+                    Err(e) => {
+                        let _ = self.logger.warn(
                             format!(
-                                "Delete didn't meet consistency level on: {:?}, returning Err",
-                                nodes_to_check
+                                "Delete didn't meet consistency level on: {:?} ({:?}), returning Err",
+                                nodes_to_check, e
                             )
                             .as_str(),
                         );
-                        Err(ErrorCode::UnavailableException)
+                        Err(consistency_error_code(e, &consistency_level, false))
                     }
                 }
             }
@@ -1578,94 +3499,294 @@ impl Node {
         if node_id == self.id {
             return self.receive_internal_message(to_send);
         }
-        let mut port = "";
-        let mut ip = "";
+        self.internode.send(to_send, node_id)
+    }
 
-        let gossip_table = match self.get_gossip_table() {
-            Ok(table) => table,
-            Err(e) => {
-                return Err(e);
+    // ------------------------ Admin ------------------------
+
+    /// Returns a version of the schema known by the node: the same keyspaces and tables give the
+    /// same version, so nodes that disagree about the schema can be spotted.
+    pub fn schema_version(&self) -> Result<String, String> {
+        let mut statements: Vec<String> = self
+            .get_keyspaces()?
+            .into_iter()
+            .map(|(keyspace_name, replication_strategy)| {
+                create_keyspace_query(&keyspace_name, replication_strategy)
+            })
+            .collect();
+        for (_, table) in self.get_tables()? {
+            if let Ok(table) = table.read() {
+                statements.push(create_table_query(&table.get_table()));
             }
-        };
+        }
+        statements.sort();
 
-        for gossip_info in &gossip_table {
-            if gossip_info.node_id == node_id {
-                ip = &gossip_info.ip;
-                port = &gossip_info.port_gossip_query;
-                break;
-            }
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        statements.hash(&mut hasher);
+        Ok(format!("{:016x}", hasher.finish()))
+    }
+
+    /// Returns the number of hints waiting to be delivered to each node.
+    fn pending_hints(&self) -> Result<BTreeMap<String, usize>, String> {
+        match self.hints.read() {
+            Ok(hints) => Ok(hints
+                .iter()
+                .filter(|(_, hints)| !hints.is_empty())
+                .map(|(node_id, hints)| (node_id.clone(), hints.len()))
+                .collect()),
+            Err(_) => Err("Failed locking hints".to_string()),
         }
+    }
+
+    /// Returns the status of the node and of the nodes it knows, answering the STATUS admin request.
+    pub fn status(&self) -> Result<NodeStatus, String> {
+        Ok(NodeStatus {
+            node_id: self.id.clone(),
+            ip: self.ip.clone(),
+            port_native_protocol: self.port_native_protocol,
+            port_gossip_query: self.port_gossip_query,
+            schema_version: self.schema_version()?,
+            nodes: self.get_gossip_table()?,
+            pending_hints: self.pending_hints()?,
+        })
+    }
 
-        if port.is_empty() {
-            return Err("Node not found".to_string());
+    /// Returns the metrics of the node in the Prometheus text format: the latencies and
+    /// failures it recorded, plus the state of its peers, its pending hints and the size
+    /// of its tables.
+    pub fn prometheus_metrics(&self) -> Result<String, String> {
+        let mut writer = PrometheusWriter::new();
+        self.metrics.snapshot().write_prometheus(&mut writer);
+
+        let peer_up = "aero_peer_up";
+        writer.family(peer_up, "gauge", "Whether each node of the gossip table is Live.");
+        for peer in self.get_gossip_table()? {
+            let up = if peer.status == "Live" { 1.0 } else { 0.0 };
+            writer.sample(
+                peer_up,
+                &[("peer", &peer.node_id), ("status", &peer.status)],
+                up,
+            );
         }
 
-        let destination = format!("{}:{}", ip, port);
+        let hints = "aero_pending_hints";
+        writer.family(hints, "gauge", "Hints waiting to be delivered to each node.");
+        for (node_id, pending) in self.pending_hints()? {
+            writer.sample(hints, &[("peer", &node_id)], pending as f64);
+        }
 
-        let _ = self
-            .logger
-            .log(format!("Attempting resend to {}", &destination).as_str());
+        let partitions = "aero_table_partitions";
+        writer.family(partitions, "gauge", "Partitions stored in each table.");
+        let mut tables = self.get_tables()?;
+        tables.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, table) in tables {
+            let count = match table.read() {
+                Ok(table) => table.partition_count(),
+                Err(_) => continue,
+            };
+            writer.sample(partitions, &[("table", &name)], count as f64);
+        }
 
-        if let Ok(mut stream) = TcpStream::connect(&destination) {
-            if let Err(e) = to_send.write_to_stream(&mut stream) {
-                let _ = self
-                    .logger
-                    .log(format!("Error writing to stream while resending to node {}, storing query for hinted-handoff", &destination).as_str());
-                let mut hints_for_all_nodes = match self.hints.write() {
-                    Ok(hints) => hints,
-                    Err(_) => {
-                        return Err("Error locking hints".to_string());
-                    }
-                };
-                if let Some(hints) = hints_for_all_nodes.get_mut(node_id) {
-                    hints.push(to_send.clone());
-                } else {
-                    hints_for_all_nodes.insert(node_id.to_string(), vec![to_send.clone()]);
+        Ok(writer.finish())
+    }
+
+    /// Returns the range of tokens each node is the primary replica of, answering the RING
+    /// admin request.
+    pub fn ring(&self) -> Result<Vec<TokenRange>, String> {
+        let gossip_table = self.get_gossip_table()?;
+        Ok(self
+            .consistent_hash
+            .token_ranges(&gossip_table)
+            .into_iter()
+            .map(|(node_id, start, end)| TokenRange {
+                node_id,
+                start,
+                end,
+                ownership: (end - start) as f64 / u64::MAX as f64,
+            })
+            .collect())
+    }
+
+    /// Returns the schema and the nodes known by the node, answering the DESCRIBE_CLUSTER admin
+    /// request.
+    pub fn describe_cluster(&self) -> Result<ClusterDescription, String> {
+        let mut keyspaces: BTreeMap<String, KeyspaceDescription> = self
+            .get_keyspaces()?
+            .into_iter()
+            .map(|(keyspace_name, replication_strategy)| {
+                (
+                    keyspace_name,
+                    KeyspaceDescription {
+                        replication_strategy: replication_strategy.get_name(),
+                        replication_factor: replication_strategy.get_replication_factor(),
+                        tables: vec![],
+                    },
+                )
+            })
+            .collect();
+        for (full_name, _) in self.get_tables()? {
+            if let Some((keyspace_name, table_name)) = full_name.split_once('.') {
+                if let Some(keyspace) = keyspaces.get_mut(keyspace_name) {
+                    keyspace.tables.push(table_name.to_string());
                 }
-                return Err(format!("Error resending query: {}", e));
             }
-            let _ = self
-                .logger
-                .log(format!("Query resent to {}", &destination).as_str());
+        }
+        for keyspace in keyspaces.values_mut() {
+            keyspace.tables.sort();
+        }
 
-            let response = InternalMessage::deserialize_from_stream(&mut stream);
+        Ok(ClusterDescription {
+            node_id: self.id.clone(),
+            schema_version: self.schema_version()?,
+            nodes: self
+                .get_gossip_table()?
+                .into_iter()
+                .map(|gossip_info| (gossip_info.node_id, gossip_info.status))
+                .collect(),
+            keyspaces,
+            pending_hints: self.pending_hints()?.values().sum(),
+            warnings: self.schema_warnings()?,
+        })
+    }
 
-            if let Ok(response) = response {
-                match response {
-                    InternalMessage::Response { opcode, body } => {
-                        if opcode == 0 {
-                            Ok(body)
+    /// Returns the rows of a table of `system_schema`, filtered by `condition`.
+    ///
+    /// # Parameters
+    /// - `table_name`: The table, `keyspaces`, `tables` or `columns`.
+    /// - `condition`: The `WHERE` of the query.
+    ///
+    /// # Returns
+    /// The rows, or an error if the table doesn't exist or the condition can't be evaluated.
+    pub fn system_schema_rows(
+        &self,
+        table_name: &str,
+        condition: &Expression,
+    ) -> Result<RowSet, String> {
+        let mut rows = vec![];
+        match table_name {
+            "keyspaces" => {
+                for (keyspace_name, replication_strategy) in self.get_keyspaces()? {
+                    rows.push(HashMap::from([
+                        ("keyspace_name".to_string(), keyspace_name),
+                        ("replication".to_string(), replication_strategy.get_name()),
+                        (
+                            "replication_factor".to_string(),
+                            replication_strategy.get_replication_factor().to_string(),
+                        ),
+                    ]));
+                }
+            }
+            "tables" | "columns" => {
+                for (full_name, table) in self.get_tables()? {
+                    let Some((keyspace_name, name)) = full_name.split_once('.') else {
+                        continue;
+                    };
+                    let table_row = HashMap::from([
+                        ("keyspace_name".to_string(), keyspace_name.to_string()),
+                        ("table_name".to_string(), name.to_string()),
+                    ]);
+                    if table_name == "tables" {
+                        rows.push(table_row);
+                        continue;
+                    }
+                    let table = table.read().map_err(|_| "Failed locking table".to_string())?;
+                    let partition_key = table.get_partition_key_columns();
+                    let clustering_key = table.get_clustering_key_columns();
+                    // Las columnas internas, como _timestamp, no son parte del esquema
+                    for (column_name, column_type) in table
+                        .get_columns()
+                        .into_iter()
+                        .filter(|(column_name, _)| !column_name.starts_with('_'))
+                    {
+                        let (kind, position) = if let Some(position) =
+                            partition_key.iter().position(|key| *key == column_name)
+                        {
+                            ("partition_key", position as i64)
+                        } else if let Some(position) =
+                            clustering_key.iter().position(|key| *key == column_name)
+                        {
+                            ("clustering", position as i64)
                         } else {
-                            Err(body)
-                        }
+                            ("regular", -1)
+                        };
+                        let mut row = table_row.clone();
+                        row.insert("column_name".to_string(), column_name);
+                        row.insert("type".to_string(), column_type.to_lowercase());
+                        row.insert("kind".to_string(), kind.to_string());
+                        row.insert("position".to_string(), position.to_string());
+                        rows.push(row);
                     }
-                    _ => Err("Invalid response".to_string()),
                 }
-            } else {
-                Err("Error deserializing response".to_string())
             }
-        } else {
-            let _ = self.logger.log(
-                format!(
-                    "Error connecting to node {}, storing query for hinted-handoff",
-                    &destination
-                )
-                .as_str(),
-            );
+            _ => return Err(format!("Table {}.{} not found", SYSTEM_SCHEMA, table_name)),
+        }
 
-            let mut hints_for_all_nodes = match self.hints.write() {
-                Ok(hints) => hints,
-                Err(_) => {
-                    return Err("Error locking hints".to_string());
-                }
+        let mut selected = vec![];
+        for row in rows {
+            if evaluate_expression(condition, &row).map_err(|e| e.to_string())? {
+                selected.push(row);
+            }
+        }
+        selected.sort_by(|a, b| {
+            let key = |row: &HashMap<String, String>| {
+                ["keyspace_name", "table_name", "column_name"]
+                    .map(|column| row.get(column).cloned().unwrap_or_default())
             };
-            if let Some(hints) = hints_for_all_nodes.get_mut(node_id) {
-                hints.push(to_send.clone());
-            } else {
-                hints_for_all_nodes.insert(node_id.to_string(), vec![to_send.clone()]);
+            key(a).cmp(&key(b))
+        });
+        let mut rows = RowSet::new(SYSTEM_SCHEMA, table_name, system_columns(table_name));
+        rows.extend_from_text(&selected);
+        Ok(rows)
+    }
+
+    /// Returns the rows of a table of `system`, filtered by `condition`. `peers` has a row
+    /// per node of the gossip table, the coordinator included, with its address, status, the
+    /// fraction of the ring it owns and the hints the coordinator keeps for it.
+    ///
+    /// # Parameters
+    /// - `table_name`: The table, `peers`.
+    /// - `condition`: The `WHERE` of the query.
+    ///
+    /// # Returns
+    /// The rows, or an error if the table doesn't exist or the condition can't be evaluated.
+    pub fn system_rows(
+        &self,
+        table_name: &str,
+        condition: &Expression,
+    ) -> Result<RowSet, String> {
+        if table_name != "peers" {
+            return Err(format!("Table {}.{} not found", SYSTEM, table_name));
+        }
+        let mut ownership: HashMap<String, f64> = HashMap::new();
+        for range in self.ring()? {
+            *ownership.entry(range.node_id).or_default() += range.ownership;
+        }
+        let pending_hints = self.pending_hints()?;
+
+        let mut selected = vec![];
+        for peer in self.get_gossip_table()? {
+            let row = HashMap::from([
+                ("peer".to_string(), peer.node_id.clone()),
+                ("rpc_address".to_string(), peer.native_address()),
+                ("status".to_string(), peer.status.clone()),
+                ("last_heartbeat".to_string(), peer.last_heartbeat.to_string()),
+                (
+                    "ownership".to_string(),
+                    ownership.get(&peer.node_id).copied().unwrap_or_default().to_string(),
+                ),
+                (
+                    "pending_hints".to_string(),
+                    pending_hints.get(&peer.node_id).copied().unwrap_or_default().to_string(),
+                ),
+            ]);
+            if evaluate_expression(condition, &row).map_err(|e| e.to_string())? {
+                selected.push(row);
             }
-            Err("Error connecting to node".to_string())
         }
+        selected.sort_by(|a, b| a.get("peer").cmp(&b.get("peer")));
+        let mut rows = RowSet::new(SYSTEM, table_name, system_columns(table_name));
+        rows.extend_from_text(&selected);
+        Ok(rows)
     }
 
     // ------------------------ Receive and Execute Query ------------------------
@@ -1678,8 +3799,65 @@ impl Node {
     /// # Returns
     /// Ok(String) on success, or a descriptive Err(String) on failure.
     pub fn receive_internal_message(&self, message: &InternalMessage) -> Result<String, String> {
+        // Las escrituras se agregan al commit log antes de aplicarse en memoria
+        if is_logged_write(message) {
+            return self
+                .commit_log
+                .record(message, || self.apply_internal_message(message));
+        }
+        self.apply_internal_message(message)
+    }
+
+    /// Processes an internal message without adding it to the commit log.
+    fn apply_internal_message(&self, message: &InternalMessage) -> Result<String, String> {
+        let span = match message {
+            InternalMessage::Query {
+                opcode,
+                keyspace_name,
+                trace_id,
+                ..
+            } => info_span!(
+                "replica",
+                node = %self.id,
+                opcode,
+                keyspace = %keyspace_name,
+                query_id = %trace_id
+            ),
+            InternalMessage::Gossip { opcode, .. } => {
+                debug_span!("internal", node = %self.id, opcode)
+            }
+            InternalMessage::Response { .. } => Span::none(),
+        };
+        let _span = span.entered();
         match message {
             InternalMessage::Gossip { opcode, body } => {
+                match opcode {
+                    // METRICS: el body se ignora
+                    2 => return self.metrics.snapshot_json(),
+                    // PEER_FAILURES: el body se ignora
+                    3 => {
+                        return serde_json::to_string(&self.metrics.peers_snapshot())
+                            .map_err(|e| format!("Error serializing peer failures: {}", e))
+                    }
+                    // STATUS, RING y DESCRIBE_CLUSTER: el body se ignora
+                    4 => return to_admin_response(&self.status()?),
+                    5 => return to_admin_response(&self.ring()?),
+                    6 => return to_admin_response(&self.describe_cluster()?),
+                    // LOG_LEVEL: el body es el nuevo nivel, o vacio para consultarlo
+                    7 => {
+                        if !body.is_empty() {
+                            log::set_level(LogLevel::from_str_to_enum(body)?);
+                        }
+                        return to_admin_response(&log::level().as_str());
+                    }
+                    // HEALTH: el body se ignora
+                    8 => return to_admin_response(&self.health()),
+                    // QUERIES: el body se ignora
+                    9 => return to_admin_response(&self.inflight_queries()),
+                    // KILL_QUERY: el body es el id de la query
+                    10 => return to_admin_response(&self.kill_query(body)?),
+                    _ => {}
+                }
                 let gossip_table: Vec<GossipInformation> = match serde_json::from_str(body) {
                     Ok(table) => table,
                     Err(e) => return Err(format!("Error deserializing gossip table: {}", e)),
@@ -1714,10 +3892,36 @@ impl Node {
                 opcode,
                 body,
                 keyspace_name,
+                trace_id,
             } => {
                 let _ = self
                     .logger
-                    .log(format!("Received query internally: {}", body).as_str());
+                    .log_traced(trace_id, format!("Received query internally: {}", body).as_str());
+
+                match opcode {
+                    // PAXOS_PREPARE, PAXOS_PROPOSE y PAXOS_COMMIT: el body es JSON
+                    7 => return self.paxos_prepare(keyspace_name, body),
+                    8 => return self.paxos_propose(body),
+                    9 => return self.paxos_commit(keyspace_name, body, trace_id),
+                    // COUNTER: el body es JSON
+                    10 => return self.apply_counter_mutation(keyspace_name, body, trace_id),
+                    // HINT: el body es JSON
+                    11 => return self.apply_hint(keyspace_name, body, trace_id),
+                    // SELECT_PAGE: el body es JSON
+                    12 => return self.select_page(keyspace_name, body, trace_id),
+                    _ => {}
+                }
+
+                if *opcode == 6 {
+                    // BATCH: el body no es una query, sino una lista de queries
+                    let table_name = self.apply_internal_batch(keyspace_name, body)?;
+                    let _ = self.logger.log_with(
+                        LogLevel::Info,
+                        "Batch applied",
+                        &[("query_id", trace_id), ("table", &table_name)],
+                    );
+                    return Ok("Batch applied successfully".to_string());
+                }
 
                 let parsed_query = match parse_instruction(body) {
                     Ok(parsed_query) => parsed_query,
@@ -1742,7 +3946,7 @@ impl Node {
                                 if let Err(e) = result {
                                     Err(e)
                                 } else {
-                                    let _ = self.logger.log(
+                                    let _ = self.logger.log_traced(trace_id, 
                                         format!("Keyspace created: {}", keyspace_name).as_str(),
                                     );
                                     Ok("Keyspace created successfully".to_string())
@@ -1767,9 +3971,11 @@ impl Node {
                                     clustering_key_columns,
                                     columns,
                                 );
-                                let _ = self
-                                    .logger
-                                    .log(format!("Table created: {}", table_name).as_str());
+                                let _ = self.logger.log_with(
+                                    LogLevel::Info,
+                                    "Table created",
+                                    &[("query_id", trace_id), ("table", &table_name)],
+                                );
                                 Ok("Table created successfully".to_string())
                             }
                             _ => Err("Opcode doesn't match query".to_string()),
@@ -1783,19 +3989,21 @@ impl Node {
                                 rows_to_insert,
                                 ..
                             } => {
-                                if let Some(row) = rows_to_insert.into_iter().next() {
-                                    let result = self.insert_row(keyspace_name, &table_name, row);
-                                    if let Err(e) = result {
-                                        return Err(e);
-                                    } else {
-                                        let _ = self.logger.log(
-                                            format!("Row inserted in table: {}", table_name)
-                                                .as_str(),
-                                        );
-                                        return Ok("Row inserted successfully".to_string());
-                                    }
+                                if rows_to_insert.is_empty() {
+                                    return Err("No rows to insert".to_string());
+                                }
+                                // Todas las filas de un INSERT se aplican juntas o ninguna.
+                                let mutations =
+                                    rows_to_insert.into_iter().map(Mutation::Insert).collect();
+                                let result = self.apply_batch(keyspace_name, &table_name, mutations);
+                                if let Err(e) = result {
+                                    Err(e)
+                                } else {
+                                    let _ = self.logger.log_traced(trace_id, 
+                                        format!("Row inserted in table: {}", table_name).as_str(),
+                                    );
+                                    Ok("Row inserted successfully".to_string())
                                 }
-                                Err("No rows to insert".to_string())
                             }
                             _ => Err("Opcode doesn't match query".to_string()),
                         }
@@ -1808,32 +4016,16 @@ impl Node {
                                 columns: _,
                                 condition,
                                 order_by: _,
+                                limit: _,
                             } => {
-                                let table = match self.get_table(keyspace_name, &table_name) {
-                                    Some(table) => table,
-                                    None => return Err("Table not found".to_string()),
-                                };
+                                // Solo se desencripta la partición si la condición la fija
+                                let table = self.get_shared_table(keyspace_name, &table_name)?;
+                                let table = table
+                                    .read()
+                                    .map_err(|_| "Error locking table".to_string())?;
 
                                 let rows = table.select_if(&condition);
-                                let mut response = vec![];
-
-                                for row in rows {
-                                    response.push(row.clone());
-                                }
-
-                                match serde_json::to_string(&response) {
-                                    Ok(json) => {
-                                        let _ = self.logger.log(
-                                            format!(
-                                                "Returning select values from table: {}",
-                                                table_name
-                                            )
-                                            .as_str(),
-                                        );
-                                        Ok(json)
-                                    }
-                                    Err(e) => Err(format!("Error serializing response: {}", e)),
-                                }
+                                self.select_response(keyspace_name, &table_name, &table, rows, trace_id)
                             }
                             _ => Err("Opcode doesn't match query".to_string()),
                         }
@@ -1845,6 +4037,7 @@ impl Node {
                                 table_name,
                                 values_to_update,
                                 condition,
+                                ..
                             } => {
                                 let result = self.update_row(
                                     keyspace_name,
@@ -1855,7 +4048,7 @@ impl Node {
                                 if let Err(e) = result {
                                     Err(e)
                                 } else {
-                                    let _ = self.logger.log(
+                                    let _ = self.logger.log_traced(trace_id, 
                                         format!("Row updated in table: {}", table_name).as_str(),
                                     );
                                     Ok("Row updated successfully".to_string())
@@ -1876,7 +4069,7 @@ impl Node {
                                 if let Err(e) = result {
                                     Err(e)
                                 } else {
-                                    let _ = self.logger.log(
+                                    let _ = self.logger.log_traced(trace_id, 
                                         format!("Row deleted in table: {}", table_name).as_str(),
                                     );
                                     Ok("Row deleted successfully".to_string())
@@ -1933,24 +4126,19 @@ impl Node {
         table_name: &str,
         row_values: &HashMap<String, String>,
     ) -> Vec<String> {
-        let data = match self.data.read() {
-            Ok(data) => data.clone(),
+        let partition_key_columns = match self.get_shared_table(keyspace_name, table_name) {
+            Ok(table) => match table.read() {
+                Ok(table) => table.get_partition_key_columns(),
+                Err(_) => return Vec::new(),
+            },
             Err(_) => {
+                eprintln!(
+                    "No se encontró la tabla: keyspace_name: {}, table_name: {}",
+                    keyspace_name, table_name
+                );
                 return Vec::new();
             }
         };
-
-        let partition_key_columns =
-            match data.get(format!("{}.{}", keyspace_name, table_name).as_str()) {
-                Some(table) => table.get_partition_key_columns(),
-                None => {
-                    eprintln!(
-                        "No se encontró la tabla: keyspace_name: {}, table_name: {}",
-                        keyspace_name, table_name
-                    );
-                    return Vec::new();
-                }
-            };
         let mut partition_keys = Vec::new();
 
         for key_column in partition_key_columns {
@@ -1962,156 +4150,400 @@ impl Node {
             }
         }
 
-        let keyspaces = match self.keyspaces.read() {
-            Ok(keyspaces) => keyspaces.clone(),
-
-            Err(_) => {
-                return Vec::new();
-            }
-        };
-
-        let gossip_table = match self.gossip_table.read() {
-            Ok(gossip_table) => gossip_table.clone(),
+        self.get_nodes_for_partition(keyspace_name, &partition_keys)
+    }
 
-            Err(_) => {
-                return Vec::new();
+    /// Groups the rows of an insert by the nodes they should be inserted in, keeping their order.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The name of the keyspace.
+    /// - `table_name`: The name of the table.
+    /// - `rows`: The rows to be inserted.
+    ///
+    /// # Returns
+    /// - `Vec<Vec<Row>>` with the rows of each group of nodes, a single group if all of them go
+    ///   to the same nodes.
+    fn group_rows_by_replicas(
+        &self,
+        keyspace_name: &str,
+        table_name: &str,
+        rows: &[Row],
+    ) -> Vec<Vec<Row>> {
+        let mut groups: Vec<(Vec<String>, Vec<Row>)> = Vec::new();
+        for row in rows {
+            let nodes = self.get_nodes_for_insert(keyspace_name, table_name, row);
+            match groups.iter_mut().find(|(group_nodes, _)| *group_nodes == nodes) {
+                Some((_, group_rows)) => group_rows.push(row.clone()),
+                None => groups.push((nodes, vec![row.clone()])),
             }
-        };
-
-        if let Some(replication_strategy) = keyspaces.get(keyspace_name) {
-            replication_strategy.get_replica_nodes(
-                &partition_keys,
-                &gossip_table,
-                &self.consistent_hash,
-            )
-        } else {
-            eprintln!("No se encontró el keyspace: {}", keyspace_name);
-            Vec::new()
         }
+        groups.into_iter().map(|(_, group_rows)| group_rows).collect()
     }
 
     /// Retrieves the nodes responsible for the partition key based on a condition.
+    /// Every partition key column of the table must be restricted by an equality in the
+    /// condition, so composite keys are hashed exactly like inserts do.
     ///
     /// # Parameters
     /// - `keyspace_name`: The name of the keyspace.
+    /// - `table_name`: The name of the table.
     /// - `condition`: The condition to be evaluated.
     ///
     /// # Returns
     /// - vector of node IDs that are responsible for the given partition key in the condition or
     ///   an empty vector if the operation failed.
-    fn get_nodes_for_condition(&self, keyspace_name: &str, condition: &Expression) -> Vec<String> {
-        // Se asume que la condicion es sobre la partition key, que a su vez es la unica key
-        let partition_key = extract_value_supposing_column_equals_value(condition);
-
-        let partition_keys = match partition_key {
-            Some(key) => vec![key],
-            None => {
-                eprintln!("La suposicion condition: 'column = value' no se cumplio");
+    fn get_nodes_for_condition(
+        &self,
+        keyspace_name: &str,
+        table_name: &str,
+        condition: &Expression,
+    ) -> Vec<String> {
+        let partition_key_columns = match self.get_shared_table(keyspace_name, table_name) {
+            Ok(table) => match table.read() {
+                Ok(table) => table.get_partition_key_columns(),
+                Err(_) => return Vec::new(),
+            },
+            Err(_) => {
+                eprintln!(
+                    "No se encontró la tabla: keyspace_name: {}, table_name: {}",
+                    keyspace_name, table_name
+                );
                 return Vec::new();
             }
         };
 
-        let keyspaces = match self.keyspaces.read() {
-            Ok(keyspaces) => keyspaces.clone(),
-
-            Err(_) => {
+        let equalities = extract_equalities(condition);
+        let partition_keys = match partition_key_columns
+            .iter()
+            .map(|column| equalities.get(column).cloned())
+            .collect::<Option<Vec<String>>>()
+        {
+            Some(partition_keys) => partition_keys,
+            None => {
+                eprintln!(
+                    "La condición no restringe todas las partition keys: {:?}",
+                    partition_key_columns
+                );
                 return Vec::new();
             }
         };
 
-        let gossip_table = match self.gossip_table.read() {
-            Ok(gossip_table) => gossip_table.clone(),
+        self.get_nodes_for_partition(keyspace_name, &partition_keys)
+    }
 
-            Err(_) => {
+    /// Retrieves the nodes an UPDATE or DELETE has to be sent to: the replicas of the partition
+    /// restricted by the condition or, if the partition key can't be extracted from it, every
+    /// node the gossip table has as live. The dead ones are left out, so the coordinator
+    /// neither waits for them nor keeps hints for them.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The name of the keyspace.
+    /// - `table_name`: The name of the table.
+    /// - `condition`: The WHERE clause of the query.
+    ///
+    /// # Returns
+    /// - vector of node IDs the query has to be sent to.
+    fn get_nodes_for_mutation(
+        &self,
+        keyspace_name: &str,
+        table_name: &str,
+        condition: &Expression,
+    ) -> Vec<String> {
+        let nodes = self.get_nodes_for_condition(keyspace_name, table_name, condition);
+        if nodes.is_empty() {
+            let _ = self.logger.log(
+                format!(
+                    "Partition key of {}.{} not found in condition, sending to every node",
+                    keyspace_name, table_name
+                )
+                .as_str(),
+            );
+            return self.get_all_nodes();
+        }
+        nodes
+    }
+
+    /// Retrieves the replicas of a partition according to the replication strategy of its keyspace.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The name of the keyspace.
+    /// - `partition_keys`: The values of the partition key columns, in the order they were declared.
+    ///
+    /// # Returns
+    /// - vector of node IDs that are responsible for the partition or an empty vector if the
+    ///   operation failed.
+    fn get_nodes_for_partition(&self, keyspace_name: &str, partition_keys: &[String]) -> Vec<String> {
+        let keyspaces = match self.keyspaces.read() {
+            Ok(keyspaces) => keyspaces,
+
+            Err(_) => {
+                return Vec::new();
+            }
+        };
+
+        // Se mantiene el lock mientras se calculan las réplicas: quien cambie la tabla
+        // vacía el cache después, así nunca queda guardada una ubicación vieja
+        let gossip_table = match self.gossip_table.read() {
+            Ok(gossip_table) => gossip_table,
+
+            Err(_) => {
                 return Vec::new();
             }
         };
 
         if let Some(replication_strategy) = keyspaces.get(keyspace_name) {
-            replication_strategy.get_replica_nodes(
-                &partition_keys,
+            // Solo SimpleStrategy elige siempre las mismas réplicas para un rango
+            let range = match replication_strategy {
+                ReplicationStrategy::SimpleStrategy { .. } => self
+                    .consistent_hash
+                    .range_index(partition_keys, gossip_table.len()),
+                ReplicationStrategy::RandomStrategy { .. } => None,
+            };
+            if let Some(replicas) = range.and_then(|range| self.placement.get(keyspace_name, range)) {
+                return replicas;
+            }
+            let replicas = replication_strategy.get_replica_nodes(
+                partition_keys,
                 &gossip_table,
                 &self.consistent_hash,
-            )
+            );
+            if let Some(range) = range {
+                self.placement.insert(keyspace_name, range, replicas.clone());
+            }
+            replicas
         } else {
             eprintln!("No se encontró el keyspace: {}", keyspace_name);
             Vec::new()
         }
     }
 
+    /// Retrieves the replicas of each token range of a keyspace, for the reads that don't
+    /// restrict the partition key and have to go over every partition.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The name of the keyspace.
+    ///
+    /// # Returns
+    /// - The replicas of each range, or `None` if the keyspace doesn't exist or its strategy
+    ///   doesn't keep each range on the same replicas.
+    fn get_nodes_for_ranges(&self, keyspace_name: &str) -> Option<Vec<Vec<String>>> {
+        let keyspaces = self.keyspaces.read().ok()?;
+        let gossip_table = self.gossip_table.read().ok()?;
+        keyspaces.get(keyspace_name)?.range_replicas(&gossip_table)
+    }
+
+    /// Leaves out the replicas of a statement that gossip marks as dead, so the coordinator
+    /// doesn't wait for them, and checks that enough of the others are alive to meet the
+    /// consistency level.
+    ///
+    /// # Parameters
+    /// - `replicas`: The replicas of the statement.
+    /// - `consistency_level`: The consistency level of the statement.
+    ///
+    /// # Returns
+    /// The live replicas, the dead ones and the number of responses the consistency level
+    /// requires, or `UnavailableException` with the required and live replicas if they aren't
+    /// enough.
+    fn live_replicas(
+        &self,
+        replicas: Vec<String>,
+        consistency_level: &Consistency,
+    ) -> Result<(Vec<String>, Vec<String>, usize), ErrorCode> {
+        let gossip_table = match self.gossip_table.read() {
+            Ok(gossip_table) => gossip_table,
+            Err(_) => return Err(ErrorCode::ServerError),
+        };
+        let number_of_replicas = replicas.len();
+        let (live, dead): (Vec<String>, Vec<String>) = replicas.into_iter().partition(|node_id| {
+            !gossip_table
+                .iter()
+                .any(|gossip_info| gossip_info.node_id == *node_id && gossip_info.status == "Dead")
+        });
+        let required = consistency_level
+            .check_alive_replicas(number_of_replicas, live.len())
+            .map_err(|e| consistency_error_code(e, consistency_level, false))?;
+        Ok((live, dead, required))
+    }
+
+    /// Stores the write of a statement as a hint for each of its dead replicas, to be
+    /// delivered when they come back.
+    ///
+    /// # Parameters
+    /// - `dead_replicas`: The replicas gossip marks as dead.
+    /// - `to_send`: The write sent to the live replicas.
+    /// - `consistency_level`: The consistency level of the statement.
+    /// - `required`: The responses the consistency level requires.
+    ///
+    /// # Returns
+    /// The responses still required from the live replicas: at ANY each hint counts as one.
+    fn hint_dead_replicas(
+        &self,
+        dead_replicas: &[String],
+        to_send: &InternalMessage,
+        consistency_level: &Consistency,
+        required: usize,
+    ) -> usize {
+        let sent_at = Utc::now();
+        let hinted = dead_replicas
+            .iter()
+            .filter(|node_id| self.internode.store_hint(node_id, to_send, sent_at).is_ok())
+            .count();
+        consistency_level.required_after_hints(required, hinted)
+    }
+
     // ------------------------  Disk ------------------------
 
     /// Flushes the in-memory data and keyspace information to disk.
     ///
     pub fn flush(&self) {
-        self.flush_keyspaces();
-        self.flush_data();
+        let _span = debug_span!("flush", node = %self.id).entered();
+        let start = Instant::now();
+        // Las escrituras que llegan durante el flush van a un segmento nuevo del commit log
+        let segment = self.commit_log.start_segment();
+        let keyspaces_flushed = self.flush_keyspaces();
+        let flushed = self.flush_data() && keyspaces_flushed;
+        // Los segmentos anteriores solo se descartan si todo quedó en disco
+        if let (Ok(segment), true) = (segment, flushed) {
+            if let Err(e) = self.commit_log.discard_before(segment) {
+                eprintln!("Error discarding the flushed commit log: {}", e);
+            }
+        }
+        self.metrics.record(Operation::Flush, start.elapsed(), flushed);
     }
 
-    fn flush_keyspaces(&self) {
-        let keyspaces = match self.keyspaces.read() {
-            Ok(keyspaces) => keyspaces.clone(),
-            Err(_) => {
-                return;
-            }
+    /// Drops from every table the partitions that deletes left without rows. The tables that
+    /// change are written to disk on the next flush.
+    pub fn compact(&self) {
+        let _span = debug_span!("compact", node = %self.id).entered();
+        let Ok(data) = self.get_tables() else {
+            return;
         };
+        for (table_name, encrypted_table) in data.iter() {
+            // Cada tabla se bloquea solo mientras se compacta.
+            let Ok(mut encrypted_table) = encrypted_table.write() else {
+                continue;
+            };
+            let dropped = encrypted_table.compact();
+            if dropped > 0 {
+                tracing::debug!(table = %table_name, dropped, "compacted table");
+            }
+        }
+    }
+
+    /// Returns the metrics of the node, to be exported.
+    pub fn get_metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
 
-        let dir = format!("./data/{}", self.id);
-        let file = format!("{}/keyspaces", dir);
+    /// Injects the faults of a `[chaos]` section in the messages this node sends from now on,
+    /// for instance to make a test cluster unreliable after it formed.
+    pub fn set_faults(&self, chaos: &ChaosConfig) {
+        self.internode.set_faults(FaultInjector::new(&self.id, chaos));
+    }
 
-        if let Err(e) = fs::create_dir_all(&dir) {
-            eprintln!("Failed to create directory {}: {}", dir, e);
+    fn flush_keyspaces(&self) -> bool {
+        // Solo se reescriben si cambiaron desde el último flush.
+        if !self.keyspaces_dirty.swap(false, Ordering::SeqCst) {
+            return true;
         }
 
-        for (keyspace_name, replication_strategy) in keyspaces.iter() {
-            let write = format!(
-                "{},{},{}",
-                keyspace_name,
-                replication_strategy.get_name(),
-                replication_strategy.get_replication_factor()
-            );
-            if let Err(e) = fs::write(&file, write) {
-                eprintln!("Failed to write to file {}: {}", file, e);
+        let document = match self.keyspaces.read() {
+            Ok(keyspaces) => KeyspacesDocument::new(&keyspaces),
+            Err(_) => {
+                self.keyspaces_dirty.store(true, Ordering::SeqCst);
+                return false;
             }
+        };
+
+        let file = format!("{}/keyspaces", self.data_dir());
+
+        // Todos los keyspaces van en un solo documento, escrito de una vez.
+        let result = document
+            .to_bytes()
+            .and_then(|payload| {
+                storage_format::write_file(&file, FileKind::Keyspaces, &payload)
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            eprintln!("Failed to write to file {}: {}", file, e);
+            // Se reintenta en el próximo flush.
+            self.keyspaces_dirty.store(true, Ordering::SeqCst);
+            return false;
         }
+        true
     }
 
-    fn flush_data(&self) {
-        let data = match self.data.read() {
-            Ok(data) => data.clone(),
+    fn flush_data(&self) -> bool {
+        let data = match self.get_tables() {
+            Ok(data) => data,
             Err(_) => {
-                return;
+                return false;
             }
         };
 
-        for (_, encrypted_table) in data.iter() {
-            let table = encrypted_table.get_table();
+        let mut flushed = true;
+        for (table_name, encrypted_table) in data.iter() {
+            // Cada tabla se bloquea solo mientras se escribe.
+            let encrypted_table = match encrypted_table.read() {
+                Ok(encrypted_table) => encrypted_table,
+                Err(_) => {
+                    flushed = false;
+                    continue;
+                }
+            };
+            // Solo se reescriben las tablas que cambiaron desde el último flush.
+            if !encrypted_table.is_dirty() {
+                continue;
+            }
 
-            let dir = format!("./data/{}", self.id);
+            let dir = self.data_dir();
             //let file = format!("{}/{}", dir, table_name);
 
             if let Err(e) = fs::create_dir_all(&dir) {
                 eprintln!("Failed to create directory {}: {}", dir, e);
+                flushed = false;
                 continue;
             }
 
             // Escribe la tabla en el archivo.
-            if let Err(e) = encrypted_table.write_to_disk(&dir, table.get_name()) {
+            tracing::debug!(table = %table_name, "flushing table");
+            if let Err(e) = encrypted_table.write_to_disk(&dir, table_name) {
+                tracing::error!(table = %table_name, error = %e, "failed to flush table");
                 eprintln!("Failed to write to file {}: {}", dir, e);
+                flushed = false;
             }
         }
+        flushed
     }
 
     /// Loads the in-memory data and keyspace information from disk.
     ///
     fn load_data(&self) {
+        let _span = info_span!("load_data", node = %self.id).entered();
         self.load_keyspaces();
         self.load_tables();
         self.load_gossip_table();
     }
 
+    /// Applies again the writes of the commit log that weren't flushed before the node stopped.
+    /// They are written to disk, and their segments discarded, on the next flush.
+    fn replay_commit_log(&self) {
+        let writes = self.commit_log.replay();
+        if writes.is_empty() {
+            return;
+        }
+        let _span =
+            info_span!("replay_commit_log", node = %self.id, writes = writes.len()).entered();
+        for write in &writes {
+            // Las escrituras que fallaron al recibirse vuelven a fallar, no se reintentan
+            if let Err(e) = self.apply_internal_message(write) {
+                tracing::debug!(error = %e, "write of the commit log not applied");
+            }
+        }
+    }
+
     fn load_keyspaces(&self) {
-        let keyspaces_data = match load_keyspaces(&self.id) {
+        let keyspaces_data = match load_keyspaces(&self.data_dir()) {
             Ok(keyspaces_data) => keyspaces_data,
             Err(e) => {
                 eprintln!("Error loading keyspaces: {}", e);
@@ -2127,319 +4559,2238 @@ impl Node {
             }
         };
 
-        for keyspace_data in keyspaces_data {
-            let keyspace_name = keyspace_data.0;
-            let replication_strategy_name = keyspace_data.1;
-            let replication_factor = keyspace_data.2;
+        keyspaces.extend(keyspaces_data);
+        self.placement.invalidate();
+    }
+
+    fn load_tables(&self) {
+        let tables_path = match load_tables_path(&self.data_dir()) {
+            Ok(tables_path) => tables_path,
+            Err(e) => {
+                eprintln!("Error loading table names: {}", e);
+                return;
+            }
+        };
+
+        let mut data = match self.data.write() {
+            Ok(data) => data,
+            Err(_) => {
+                eprintln!("Error locking data");
+                return;
+            }
+        };
+
+        for table_path in tables_path {
+            let encrypted_table = match EncryptedTable::load_table(&self.data_dir(), &table_path) {
+                Ok(table) => table,
+                Err(e) => {
+                    // Una tabla corrupta no debe impedir que el nodo arranque.
+                    let _ = self
+                        .logger
+                        .log(format!("Error loading table {}: {}", table_path, e).as_str());
+                    match quarantine_file(&self.data_dir(), &table_path) {
+                        Ok(destination) => {
+                            let _ = self.logger.log(
+                                format!("Table {} quarantined at {}", table_path, destination)
+                                    .as_str(),
+                            );
+                        }
+                        Err(e) => {
+                            let _ = self.logger.log(e.as_str());
+                        }
+                    }
+                    continue;
+                }
+            };
+            let name = encrypted_table.get_name();
+            data.insert(name, Arc::new(RwLock::new(encrypted_table)));
+        }
+    }
+
+    fn load_gossip_table(&self) {
+        let loaded_gossip_table = match load_gossip_table(&self.data_dir()) {
+            Ok(gossip_table) => gossip_table,
+            Err(e) => {
+                eprintln!("Error loading gossip table: {}", e);
+                return;
+            }
+        };
+
+        if loaded_gossip_table.is_empty() {
+            return;
+        }
+
+        let mut gossip_table = match self.gossip_table.write() {
+            Ok(gossip_table) => gossip_table,
+            Err(_) => {
+                eprintln!("Error locking gossip table");
+                return;
+            }
+        };
+        // print for debugging
+        for gossip_info in &loaded_gossip_table {
+            println!("Node: {}, IP: {}, Port: {}", gossip_info.node_id, gossip_info.ip, gossip_info.port_gossip_query);
+        }
+
+        // Solo se persisten los pares que cambiaron, así que la entrada propia puede no estar
+        let mut loaded_gossip_table = loaded_gossip_table;
+        if !loaded_gossip_table.iter().any(|gossip_info| gossip_info.node_id == self.id) {
+            loaded_gossip_table.extend(
+                gossip_table
+                    .iter()
+                    .filter(|gossip_info| gossip_info.node_id == self.id)
+                    .cloned(),
+            );
+            loaded_gossip_table.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        }
+        *gossip_table = loaded_gossip_table;
+        self.placement.invalidate();
+    }
+}
+
+// ------------------------  JSON / Format ------------------------
+
+///  Serializes the given gossip table into a JSON string.
+///
+/// # Parameters
+/// - `gossip_table`: A vector of `GossipInformation` containing the gossip table to be serialized.
+///
+/// # Returns
+/// Ok(string) on success, or an Err(string) on failure.
+///
+pub fn gossip_table_to_json(gossip_table: &Vec<GossipInformation>) -> Result<String, String> {
+    match serde_json::to_string(gossip_table) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Error serializing gossip table: {}", e)),
+    }
+}
+
+/// Generates a CQL `INSERT` statement for a given table and data.
+///
+/// # Parameters
+/// - `table_name`: The name of the table.
+/// - `data`: A `HashMap<String, String>` containing the data to be inserted.
+///
+/// # Returns
+/// A `String` containing the generated CQL statement.
+///
+
+fn generate_insert_cql(table_name: &str, data: HashMap<String, String>) -> String {
+    let columns: Vec<String> = data.keys().cloned().collect();
+    let values: Vec<String> = data.values().cloned().collect();
+
+    let columns_str = columns.join(", ");
+    let values_str = values
+        .iter()
+        .map(|v| quote(v))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    format!(
+        "INSERT INTO {} ({}) VALUES ({});",
+        table_name, columns_str, values_str
+    )
+}
+
+// ------------------------  Auxiliar ------------------------
+
+/// Builds the condition that selects a row by its primary key: an equality on each of
+/// `key_columns`, or `None` if the row lacks any of them.
+fn key_condition(key_columns: &[String], row: &HashMap<String, String>) -> Option<Expression> {
+    let mut condition: Option<Expression> = None;
+    for column in key_columns {
+        let value = row.get(column)?;
+        let equality = Expression::Comparison {
+            left: Operand::Column(column.to_string()),
+            operator: "=".to_string(),
+            right: Operand::String(value.to_string()),
+        };
+        condition = Some(match condition {
+            Some(left) => Expression::And {
+                left: Box::new(left),
+                right: Box::new(equality),
+            },
+            None => equality,
+        });
+    }
+    condition
+}
+
+/// Converts an INSERT, UPDATE or DELETE into the mutations it applies to its table.
+///
+/// # Parameters
+/// - `parsed_query`: The parsed query.
+///
+/// # Returns
+/// The name of the table and its mutations, or an error if the query doesn't modify rows.
+fn mutations_from_parsed_query(parsed_query: ParsedQuery) -> Result<(String, Vec<Mutation>), String> {
+    match parsed_query {
+        ParsedQuery::Insert {
+            table_name,
+            rows_to_insert,
+            ..
+        } => Ok((
+            table_name,
+            rows_to_insert.into_iter().map(Mutation::Insert).collect(),
+        )),
+        ParsedQuery::Update {
+            table_name,
+            values_to_update,
+            condition,
+            ..
+        } => Ok((
+            table_name,
+            vec![Mutation::Update {
+                values_to_update,
+                condition,
+            }],
+        )),
+        ParsedQuery::Delete {
+            table_name,
+            condition,
+        } => Ok((table_name, vec![Mutation::Delete(condition)])),
+        _ => Err("Only INSERT, UPDATE and DELETE can be batched".to_string()),
+    }
+}
+
+/// Merges the rows returned by every node for a SELECT: rows with the same primary key are
+/// deduplicated keeping the one that wins the conflict (see `wins_conflict`), then ORDER BY
+/// and LIMIT are applied.
+///
+/// # Parameters
+/// - `responses`: The rows returned by each node.
+/// - `primary_key_columns`: Partition key columns followed by clustering key columns.
+/// - `order_by`: Columns to sort by, with `ASC` or `DESC`.
+/// - `limit`: Maximum number of rows to return.
+///
+/// # Returns
+/// The merged rows, with the columns of the first response.
+fn merge_select_responses(
+    responses: &[RowSet],
+    primary_key_columns: &[String],
+    order_by: &[(String, String)],
+    limit: Option<usize>,
+) -> RowSet {
+    let mut merged_rows = responses
+        .first()
+        .map(|rows| RowSet::new(&rows.keyspace, &rows.table, rows.columns.clone()))
+        .unwrap_or_default();
+    let mut merged: HashMap<Vec<String>, Vec<Value>> = HashMap::new();
+    for response in responses {
+        for row in &response.rows {
+            let row: Vec<Value> = merged_rows
+                .columns
+                .iter()
+                .map(|column| response.value(row, &column.name).clone())
+                .collect();
+            let key: Vec<String> = primary_key_columns
+                .iter()
+                .map(|column| merged_rows.value(&row, column).to_text().unwrap_or_default())
+                .collect();
+            let is_newer = match merged.get(&key) {
+                Some(current) => wins_conflict(&merged_rows, &row, current),
+                None => true,
+            };
+            if is_newer {
+                merged.insert(key, row);
+            }
+        }
+    }
+
+    let mut rows: Vec<Vec<Value>> = merged.into_values().collect();
+    rows.sort_by(|row_a, row_b| {
+        for (column, direction) in order_by {
+            let ordering = compare_values(
+                merged_rows.value(row_a, column),
+                merged_rows.value(row_b, column),
+            );
+            let ordering = if direction == "DESC" {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            if ordering != std::cmp::Ordering::Equal {
+                return ordering;
+            }
+        }
+        // Sin ORDER BY se devuelven ordenadas por primary key con el tipo de cada columna,
+        // igual en todas las consultas.
+        primary_key_columns
+            .iter()
+            .map(|column| {
+                compare_values(merged_rows.value(row_a, column), merged_rows.value(row_b, column))
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let rows = rows.into_iter();
+    merged_rows.rows = match limit {
+        Some(limit) => rows.take(limit).collect(),
+        None => rows.collect(),
+    };
+    merged_rows
+}
+
+/// Returns whether a row answered by a replica replaces the one with the same primary key
+/// answered by another: the newest `_timestamp` wins and, if both were written in the same
+/// second, the one with the greatest values, so the result doesn't depend on the order the
+/// replicas answer in.
+///
+/// # Parameters
+/// - `rows`: The set the rows belong to, to find their columns.
+/// - `row`: The row answered last.
+/// - `current`: The row kept so far.
+fn wins_conflict(rows: &RowSet, row: &[Value], current: &[Value]) -> bool {
+    let timestamp = |row: &[Value]| {
+        let text = rows.value(row, "_timestamp").to_text().unwrap_or_default();
+        ColumnType::Timestamp.value_of(&text)
+    };
+    let (timestamp_a, timestamp_b) = (timestamp(row), timestamp(current));
+    // Una fila sin timestamp es más vieja que cualquiera que lo tenga
+    let ordering = timestamp_a
+        .compare(&timestamp_b)
+        .unwrap_or_else(|| (timestamp_a != Value::Null).cmp(&(timestamp_b != Value::Null)));
+    ordering
+        .then_with(|| {
+            row.iter()
+                .zip(current)
+                .map(|(a, b)| compare_values(a, b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .is_gt()
+}
+
+/// Takes a page of the merged rows of a SELECT with ORDER BY, which the replicas can't read
+/// from a position. The paging state is the number of rows already returned: the rows are
+/// merged in the same order on every query, so any node can answer the next page.
+///
+/// # Parameters
+/// - `rows`: The merged rows.
+/// - `page_size`: Maximum number of rows of the page.
+/// - `paging_state`: The state returned with the previous page, `None` for the first one.
+///
+/// # Returns
+/// The rows of the page and, if there are more, the state to request the next one.
+fn page_of(
+    mut rows: RowSet,
+    page_size: i32,
+    paging_state: Option<&[u8]>,
+) -> Result<(RowSet, Option<Vec<u8>>), ErrorCode> {
+    let offset = match paging_state {
+        Some(state) => u64::from_be_bytes(state.try_into().map_err(|_| ErrorCode::ProtocolError)?)
+            as usize,
+        None => 0,
+    };
+    let page_size = usize::try_from(page_size).map_err(|_| ErrorCode::ProtocolError)?.max(1);
+
+    let end = offset.saturating_add(page_size);
+    let next_page = (end < rows.rows.len()).then(|| (end as u64).to_be_bytes().to_vec());
+    rows.rows = rows.rows.into_iter().skip(offset).take(page_size).collect();
+    Ok((rows, next_page))
+}
+
+/// Takes a page of the merged rows the replicas answered to a SELECT_PAGE. They are put in
+/// the order the replicas read them, the order of the ring, so the position of the last row
+/// of the page is where every replica resumes the next one.
+///
+/// # Parameters
+/// - `rows`: The merged rows, sorted by primary key.
+/// - `partition_key_columns`: The partition key columns of the table.
+/// - `primary_key_columns`: The partition and clustering key columns of the table.
+/// - `(returned, page_size)`: The rows returned in the previous pages and the size of this one.
+/// - `more`: Whether a replica filled its page, so it may have more rows.
+/// - `limit`: The LIMIT of the query, counting the rows of every page.
+///
+/// # Returns
+/// The rows of the page and, if there may be more, the paging state to request the next one.
+fn ring_page(
+    mut rows: RowSet,
+    partition_key_columns: &[String],
+    primary_key_columns: &[String],
+    (returned, page_size): (usize, usize),
+    more: bool,
+    limit: Option<usize>,
+) -> (RowSet, Option<Vec<u8>>) {
+    let texts = |rows: &RowSet, row: &[Value], columns: &[String]| -> Vec<String> {
+        columns
+            .iter()
+            .map(|column| rows.value(row, column).to_text().unwrap_or_default())
+            .collect()
+    };
+    // Ordenadas por primary key: basta un sort estable por token para llevarlas al del anillo
+    let mut keyed: Vec<(u64, Vec<String>, Vec<Value>)> = std::mem::take(&mut rows.rows)
+        .into_iter()
+        .map(|row| {
+            let partition_key = texts(&rows, &row, partition_key_columns);
+            (partition_token(&partition_key), partition_key, row)
+        })
+        .collect();
+    keyed.sort_by(|(token_a, key_a, _), (token_b, key_b, _)| (token_a, key_a).cmp(&(token_b, key_b)));
+
+    let more = more || keyed.len() > page_size;
+    keyed.truncate(page_size);
+    rows.rows = keyed.into_iter().map(|(_, _, row)| row).collect();
+
+    let returned = returned + rows.rows.len();
+    let next_page = match rows.rows.last() {
+        Some(last) if more && limit.is_none_or(|limit| returned < limit) => {
+            let position = RowPosition::new(
+                texts(&rows, last, partition_key_columns),
+                texts(&rows, last, &primary_key_columns[partition_key_columns.len()..]),
+            );
+            Some(
+                PagingState {
+                    returned: returned as u64,
+                    position: position.to_bytes(),
+                }
+                .to_bytes(),
+            )
+        }
+        _ => None,
+    };
+    (rows, next_page)
+}
+
+/// Compares two values of a column: numbers, timestamps, uuids and booleans by their value,
+/// and any other pair by their text, numerically if both are numbers.
+fn compare_values(a: &Value, b: &Value) -> std::cmp::Ordering {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => a.cmp(b),
+        (Value::Bigint(a), Value::Bigint(b)) => a.cmp(b),
+        (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+        (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+        (Value::Uuid(a), Value::Uuid(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        _ => {
+            let (a, b) = (a.to_text().unwrap_or_default(), b.to_text().unwrap_or_default());
+            match (a.parse::<f64>(), b.parse::<f64>()) {
+                (Ok(a), Ok(b)) => a.total_cmp(&b),
+                _ => a.cmp(&b),
+            }
+        }
+    }
+}
+
+/// Returns a set without rows with the columns of a table, in the order they were declared.
+fn table_row_set(keyspace_name: &str, table_name: &str, columns: &[(String, String)]) -> RowSet {
+    let columns = columns
+        .iter()
+        .map(|(name, column_type)| Column::new(name, ColumnType::parse(column_type)))
+        .collect();
+    RowSet::new(keyspace_name, table_name, columns)
+}
+
+/// Returns the columns of a table of `system_schema` or `system`.
+fn system_columns(table_name: &str) -> Vec<Column> {
+    let columns: &[(&str, ColumnType)] = match table_name {
+        "keyspaces" => &[
+            ("keyspace_name", ColumnType::Text),
+            ("replication", ColumnType::Text),
+            ("replication_factor", ColumnType::Int),
+        ],
+        "tables" => &[("keyspace_name", ColumnType::Text), ("table_name", ColumnType::Text)],
+        "columns" => &[
+            ("keyspace_name", ColumnType::Text),
+            ("table_name", ColumnType::Text),
+            ("column_name", ColumnType::Text),
+            ("type", ColumnType::Text),
+            ("kind", ColumnType::Text),
+            ("position", ColumnType::Int),
+        ],
+        "peers" => &[
+            ("peer", ColumnType::Text),
+            ("rpc_address", ColumnType::Text),
+            ("status", ColumnType::Text),
+            ("last_heartbeat", ColumnType::Bigint),
+            ("ownership", ColumnType::Float),
+            ("pending_hints", ColumnType::Int),
+        ],
+        _ => &[],
+    };
+    columns
+        .iter()
+        .map(|(name, column_type)| Column::new(name, *column_type))
+        .collect()
+}
+
+/// Returns the counter columns among the columns of a table.
+fn counter_columns(columns: &[(String, String)]) -> Vec<String> {
+    columns
+        .iter()
+        .filter(|(_, column_type)| column_type.eq_ignore_ascii_case("counter"))
+        .map(|(column, _)| column.clone())
+        .collect()
+}
+
+/// Returns the key the counters of a row are stored with.
+fn counter_key(keyspace_name: &str, table_name: &str, row_key: &HashMap<String, String>) -> String {
+    let row_key: BTreeMap<&String, &String> = row_key.iter().collect();
+    format!(
+        "{}.{}:{}",
+        keyspace_name,
+        table_name,
+        serde_json::to_string(&row_key).unwrap_or_default()
+    )
+}
+
+/// Merges the counter shards returned by each replica: every row gets, in each counter
+/// column, the sum of the newest version of every shard found in any replica.
+///
+/// # Parameters
+/// - `responses`: The rows returned by each node, with the shards of the counters as text.
+/// - `primary_key_columns`: Partition key columns followed by clustering key columns.
+/// - `counter_columns`: The counter columns of the table.
+///
+/// # Returns
+/// The responses with the value of each counter.
+fn merge_counter_responses(
+    mut responses: Vec<RowSet>,
+    primary_key_columns: &[String],
+    counter_columns: &[String],
+) -> Vec<RowSet> {
+    if counter_columns.is_empty() {
+        return responses;
+    }
+    let row_key = |rows: &RowSet, row: &[Value]| -> Vec<String> {
+        primary_key_columns
+            .iter()
+            .map(|column| rows.value(row, column).to_text().unwrap_or_default())
+            .collect()
+    };
+
+    let mut merged: HashMap<(Vec<String>, &String), Shards> = HashMap::new();
+    for rows in &responses {
+        for row in &rows.rows {
+            for column in counter_columns {
+                let shards: Shards = rows
+                    .value(row, column)
+                    .to_text()
+                    .and_then(|shards| serde_json::from_str(&shards).ok())
+                    .unwrap_or_default();
+                merge_shards(merged.entry((row_key(rows, row), column)).or_default(), &shards);
+            }
+        }
+    }
+
+    for rows in &mut responses {
+        let counters: Vec<(usize, &String)> = counter_columns
+            .iter()
+            .filter_map(|column| Some((rows.column_index(column)?, column)))
+            .collect();
+        for i in 0..rows.rows.len() {
+            let key = row_key(rows, &rows.rows[i]);
+            for (index, column) in &counters {
+                if let Some(shards) = merged.get(&(key.clone(), *column)) {
+                    rows.rows[i][*index] = Value::Bigint(total(shards));
+                }
+            }
+        }
+    }
+    responses
+}
+
+/// Serializes the body of a Paxos message.
+fn to_paxos_body<T: Serialize>(message: &T) -> Result<String, ErrorCode> {
+    serde_json::to_string(message).map_err(|_| ErrorCode::ServerError)
+}
+
+/// Builds the answer to a conditional statement: a row with the `[applied]` column, followed
+/// by the current rows when it wasn't applied.
+fn cas_result(applied: bool, mut rows: RowSet) -> QueryResult {
+    rows.columns.insert(0, Column::new("[applied]", ColumnType::Boolean));
+    for row in &mut rows.rows {
+        row.insert(0, Value::Bool(applied));
+    }
+    if rows.rows.is_empty() {
+        let mut row = vec![Value::Null; rows.columns.len()];
+        row[0] = Value::Bool(applied);
+        rows.rows.push(row);
+    }
+    QueryResult::from_row_set(&rows, None)
+}
+
+/// Waits a random time, longer on each attempt, before retrying a Paxos round that lost
+/// against another coordinator, so competing coordinators don't keep preempting each other.
+fn contention_backoff(attempt: u32) {
+    let max_millis = CAS_BACKOFF_MILLIS << attempt;
+    thread::sleep(Duration::from_millis(rng().random_range(0..max_millis)));
+}
+
+/// Serializes the answer to an admin request.
+fn to_admin_response<T: Serialize>(response: &T) -> Result<String, String> {
+    serde_json::to_string(response).map_err(|e| format!("Error serializing admin response: {}", e))
+}
+
+/// Returns the operation recorded in the metrics for a query coordinated by the node, by its
+/// first keyword. Schema changes and USE aren't recorded.
+fn operation_of(query_str: &str) -> Option<Operation> {
+    let keyword = query_str.split_whitespace().next()?.to_uppercase();
+    match keyword.as_str() {
+        "SELECT" => Some(Operation::Read),
+        "INSERT" | "UPDATE" | "DELETE" => Some(Operation::Write),
+        _ => None,
+    }
+}
+
+/// Converts a consistency level that wasn't met into the error returned to the client.
+///
+/// # Parameters
+/// - `error`: Why the consistency level wasn't met.
+/// - `consistency_level`: The consistency level of the query.
+/// - `read`: Whether the query was a read.
+///
+/// # Returns
+/// `ReadTimeout` or `WriteTimeout` with the received and required responses if the replicas
+/// didn't answer in time, `ServerError` if the query was killed, or `UnavailableException`
+/// with the required and live replicas otherwise.
+fn consistency_error_code(
+    error: ConsistencyError,
+    consistency_level: &Consistency,
+    read: bool,
+) -> ErrorCode {
+    match error {
+        ConsistencyError::Timeout { received, required } if read => ErrorCode::ReadTimeout {
+            consistency: consistency_level.to_consistency_level(),
+            received: received as i32,
+            block_for: required as i32,
+            data_present: received > 0,
+        },
+        ConsistencyError::Timeout { received, required } => ErrorCode::WriteTimeout {
+            consistency: consistency_level.to_consistency_level(),
+            received: received as i32,
+            block_for: required as i32,
+            write_type: WriteType::Simple,
+        },
+        ConsistencyError::Unavailable { received, required } => ErrorCode::UnavailableException {
+            consistency: consistency_level.to_consistency_level(),
+            required: required as i32,
+            alive: received as i32,
+        },
+        ConsistencyError::Cancelled => ErrorCode::ServerError,
+    }
+}
+
+/// Returns the response of a replica to a write. A hint stored for the replica counts as its
+/// answer if `counts_hints`, as at the ANY consistency level.
+fn write_response(delivery: Result<Delivery, String>, counts_hints: bool) -> Result<String, String> {
+    match delivery? {
+        Delivery::Answered(response) => Ok(response),
+        Delivery::Hinted(_) if counts_hints => Ok("Write stored as a hint".to_string()),
+        Delivery::Hinted(e) => Err(e),
+    }
+}
+
+/// Returns the event clients are notified of when a node goes from `previous_status` to the
+/// status of `gossip_info`, `None` if it doesn't go up or down.
+fn status_event(previous_status: &str, gossip_info: &GossipInformation) -> Option<ClusterEvent> {
+    match (previous_status == "Live", gossip_info.status == "Live") {
+        (false, true) => Some(ClusterEvent::Up(gossip_info.native_address())),
+        (true, false) => Some(ClusterEvent::Down(gossip_info.native_address())),
+        _ => None,
+    }
+}
+
+/// Returns whether a message changes the schema or the rows of a table, so it has to be in the
+/// commit log: CREATE KEYSPACE, CREATE TABLE, INSERT, UPDATE, DELETE and BATCH. The Paxos
+/// commits and the hints apply their writes through one of these.
+fn is_logged_write(message: &InternalMessage) -> bool {
+    matches!(
+        message,
+        InternalMessage::Query {
+            opcode: 0 | 1 | 2 | 4 | 5 | 6,
+            ..
+        }
+    )
+}
+
+/// Returns the gossip table without heartbeats and sorted by node id, so two tables can be
+/// compared to know whether a node joined, changed its address or changed its status.
+fn gossip_membership(gossip_table: &[GossipInformation]) -> Vec<GossipInformation> {
+    let mut membership: Vec<GossipInformation> = gossip_table
+        .iter()
+        .map(|gossip_info| GossipInformation {
+            last_heartbeat: 0,
+            ..gossip_info.clone()
+        })
+        .collect();
+    membership.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+    membership
+}
+
+/// Retrieves all the live nodes from the given gossip table.
+///
+/// # Parameters
+/// - `gossip_table`: A vector of `GossipInformation` containing the gossip table.
+///
+/// # Returns
+/// A vector of `GossipInformation` containing the live nodes.
+fn get_live_nodes(gossip_table: &Vec<GossipInformation>) -> Vec<GossipInformation> {
+    let mut nodes = vec![];
+    for node_info in gossip_table {
+        if node_info.status == "Live" {
+            nodes.push(node_info.clone());
+        }
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hints::hint_message;
+    use crate::query_parser::expression::Operand;
+    use crate::test_cluster::temp_storage;
+    use common::frame::messages::consistency_level::ConsistencyLevel;
+    use std::net::TcpListener;
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_create_keyspace() {
+        let node = Node::new_with_storage(
+            "node1",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("create_keyspace"),
+        );
+        node.create_keyspace("test_keyspace", "SimpleStrategy", "3")
+            .unwrap();
+        assert!(node.keyspace_exists("test_keyspace"));
+    }
+
+    #[test]
+    fn test_create_table() {
+        let node = Node::new_with_storage(
+            "node1",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("create_table"),
+        );
+        let _ = node.create_keyspace("test_keyspace", "SimpleStrategy", "3");
+        node.create_encrypted_table(
+            "test_keyspace",
+            "test_table",
+            vec!["id".to_string()],
+            vec!["name".to_string()],
+            vec![
+                ("id".to_string(), "int".to_string()),
+                ("name".to_string(), "text".to_string()),
+            ],
+        );
+
+        let data = match node.data.read() {
+            Ok(data) => data.clone(),
+            Err(_) => {
+                return;
+            }
+        };
+
+        assert!(data.contains_key("test_keyspace.test_table"));
+    }
+
+    #[test]
+    fn test_insert_row() {
+        let node = Node::new_with_storage(
+            "node1",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("insert_row"),
+        );
+        let _ = node.create_keyspace("test_keyspace", "SimpleStrategy", "3");
+        node.create_encrypted_table(
+            "test_keyspace",
+            "test_table",
+            vec!["id".to_string()],
+            vec!["name".to_string()],
+            vec![
+                ("id".to_string(), "int".to_string()),
+                ("name".to_string(), "text".to_string()),
+            ],
+        );
+        let mut values = HashMap::new();
+        values.insert("id".to_string(), "1".to_string());
+        values.insert("name".to_string(), "Alice".to_string());
+        let result = node.insert_row("test_keyspace", "test_table", values);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_corrupt_table_is_quarantined_on_load() {
+        let storage = temp_storage("corrupt_table_is_quarantined_on_load");
+        let dir = storage.node_data_dir("node_quarantine");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(format!("{}/ks.corrupt", dir), b"not a table").unwrap();
+
+        let node = Node::new_with_storage("node_quarantine", "localhost", 9042, 7000, storage);
+
+        assert!(!node.data.read().unwrap().contains_key("ks.corrupt"));
+        assert!(!Path::new(&format!("{}/ks.corrupt", dir)).exists());
+        let quarantined = fs::read_dir(format!("{}/quarantine", dir)).unwrap().count();
+        assert_eq!(quarantined, 1);
+    }
+
+    #[test]
+    fn test_data_is_stored_in_configured_data_dir() {
+        let storage = StorageConfig {
+            data_dir: "./data/custom_storage".to_string(),
+            ..StorageConfig::default()
+        };
+        let _ = fs::remove_dir_all(&storage.data_dir);
+
+        let node = Node::new_with_storage("node_storage", "localhost", 9042, 7000, storage.clone());
+        node.create_keyspace("storage_keyspace", "SimpleStrategy", "1")
+            .unwrap();
+        node.create_encrypted_table(
+            "storage_keyspace",
+            "storage_table",
+            vec!["id".to_string()],
+            vec![],
+            vec![("id".to_string(), "int".to_string())],
+        );
+        node.flush();
+
+        assert_eq!(node.data_dir(), "./data/custom_storage/node_storage");
+        assert!(Path::new("./data/custom_storage/node_storage/keyspaces").exists());
+
+        let restarted = Node::new_with_storage("node_storage", "localhost", 9042, 7000, storage);
+        assert!(restarted.keyspace_exists("storage_keyspace"));
+        assert!(restarted
+            .data
+            .read()
+            .unwrap()
+            .contains_key("storage_keyspace.storage_table"));
+    }
+
+    #[test]
+    fn test_saturated_workers_shed_requests() {
+        let overload = OverloadConfig {
+            worker_threads: 1,
+            worker_queue_capacity: 1,
+            ..OverloadConfig::default()
+        };
+        let node = Node::new_with_config(
+            "node_overload",
+            "localhost",
+            9042,
+            7000,
+            NodeSettings {
+                overload,
+                storage: temp_storage("saturated_workers_shed_requests"),
+                ..NodeSettings::default()
+            },
+        );
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        node.run_in_background(move || {
+            started_tx.send(()).unwrap();
+            let _ = release_rx.recv_timeout(std::time::Duration::from_secs(5));
+        })
+        .unwrap();
+        started_rx
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap();
+
+        assert!(node.run_in_background(|| {}).is_ok());
+        assert_eq!(node.run_in_background(|| {}), Err(ErrorCode::Overloaded));
+        assert_eq!(node.get_metrics().snapshot().shed_requests, 1);
+        release_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_metrics_opcode_returns_snapshot() {
+        let node = Node::new_with_storage(
+            "node_metrics",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("metrics_opcode_returns_snapshot"),
+        );
+        node.flush();
+        let message = InternalMessage::Gossip {
+            opcode: 2,
+            body: "".to_string(),
+        };
+
+        let response = node.receive_internal_message(&message).unwrap();
+        let metrics: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(metrics["flush"]["count"], 1);
+        assert_eq!(metrics["read"]["count"], 0);
+        assert_eq!(operation_of("  select * FROM t;"), Some(Operation::Read));
+
+        node.metrics
+            .record_peer_failure("node_down", crate::metrics::PeerFailure::Timeout);
+        let message = InternalMessage::Gossip {
+            opcode: 3,
+            body: "".to_string(),
+        };
+        let response = node.receive_internal_message(&message).unwrap();
+        let peers: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(peers["node_down"]["timeouts"], 1);
+        assert_eq!(operation_of("CREATE TABLE t (id int);"), None);
+    }
+
+    #[test]
+    fn test_admin_requests() {
+        let storage = temp_storage("admin_requests");
+        let node = Node::new_with_storage("node_admin", "localhost", 9042, 7000, storage.clone());
+        let other = Node::new_with_storage("node_admin_2", "localhost", 9043, 7001, storage);
+        for node in [&node, &other] {
+            let _ = node.create_keyspace("admin_keyspace", "SimpleStrategy", "1");
+            node.create_encrypted_table(
+                "admin_keyspace",
+                "flights",
+                vec!["id".to_string()],
+                vec![],
+                vec![("id".to_string(), "int".to_string())],
+            );
+        }
+        assert_eq!(node.schema_version(), other.schema_version());
+
+        let admin_request = |opcode| {
+            let response = node
+                .receive_internal_message(&InternalMessage::Gossip {
+                    opcode,
+                    body: "".to_string(),
+                })
+                .unwrap();
+            serde_json::from_str::<serde_json::Value>(&response).unwrap()
+        };
+
+        let status = admin_request(4);
+        assert_eq!(status["node_id"], "node_admin");
+        assert_eq!(status["nodes"].as_array().unwrap().len(), 1);
+
+        let ring = admin_request(5);
+        assert_eq!(ring[0]["node_id"], "node_admin");
+        assert_eq!(ring[0]["start"], 0);
+
+        let description = admin_request(6);
+        assert_eq!(
+            description["keyspaces"]["admin_keyspace"]["tables"],
+            serde_json::json!(["flights"])
+        );
+        assert_eq!(description["schema_version"], status["schema_version"]);
+        assert_eq!(description["pending_hints"], 0);
+
+        let set_level = |level: &str| {
+            node.receive_internal_message(&InternalMessage::Gossip {
+                opcode: 7,
+                body: level.to_string(),
+            })
+        };
+        assert_eq!(set_level("debug").unwrap(), "\"DEBUG\"");
+        assert_eq!(log::level(), LogLevel::Debug);
+        assert!(set_level("verbose").is_err());
+        assert_eq!(admin_request(7), "DEBUG");
+        set_level("info").unwrap();
+    }
+
+    #[test]
+    fn test_prometheus_metrics() {
+        let node = Node::new_with_storage(
+            "node_prometheus",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("prometheus_metrics"),
+        );
+        let _ = node.create_keyspace("prometheus_keyspace", "SimpleStrategy", "1");
+        node.create_encrypted_table(
+            "prometheus_keyspace",
+            "flights",
+            vec!["id".to_string()],
+            vec![],
+            vec![("id".to_string(), "int".to_string())],
+        );
+        node.metrics.record(Operation::Flush, Duration::from_millis(2), true);
+
+        let page = node.prometheus_metrics().unwrap();
+        assert!(page.contains("aero_operation_duration_seconds_count{operation=\"flush\"} 1\n"));
+        assert!(page.contains("aero_peer_up{peer=\"node_prometheus\",status=\"Live\"} 1\n"));
+        assert!(page.contains("aero_table_partitions{table=\"prometheus_keyspace.flights\"} 0\n"));
+    }
+
+    #[test]
+    fn test_receive_internal_batch() {
+        let node = Node::new_with_storage(
+            "node_batch",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("receive_internal_batch"),
+        );
+        let _ = node.create_keyspace("batch_keyspace", "SimpleStrategy", "1");
+        node.create_encrypted_table(
+            "batch_keyspace",
+            "batch_table",
+            vec!["id".to_string()],
+            vec!["name".to_string()],
+            vec![
+                ("id".to_string(), "int".to_string()),
+                ("name".to_string(), "text".to_string()),
+            ],
+        );
+        let statements = vec![
+            "INSERT INTO batch_table (id, name) VALUES (1, 'Alice');".to_string(),
+            "INSERT INTO batch_table (id, name) VALUES (1, 'Bob');".to_string(),
+            "DELETE FROM batch_table WHERE name = 'Alice';".to_string(),
+        ];
+        let message = InternalMessage::Query {
+            opcode: 6,
+            body: serde_json::to_string(&statements).unwrap(),
+            keyspace_name: "batch_keyspace".to_string(),
+            trace_id: new_trace_id(),
+        };
+
+        assert!(node.receive_internal_message(&message).is_ok());
+        let table = node.get_table("batch_keyspace", "batch_table").unwrap();
+        assert_eq!(table.len(), 1);
+
+        let invalid = InternalMessage::Query {
+            opcode: 6,
+            body: serde_json::to_string(&vec![
+                "INSERT INTO batch_table (id, name) VALUES (2, 'Carol');",
+                "INSERT INTO batch_table (id, unknown) VALUES (3, 'Dave');",
+            ])
+            .unwrap(),
+            keyspace_name: "batch_keyspace".to_string(),
+            trace_id: new_trace_id(),
+        };
+        assert!(node.receive_internal_message(&invalid).is_err());
+        let table = node.get_table("batch_keyspace", "batch_table").unwrap();
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_locked_table_does_not_block_other_tables() {
+        let node = Node::new_with_storage(
+            "node_locks",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("locked_table_does_not_block_other_tables"),
+        );
+        let _ = node.create_keyspace("locks_keyspace", "SimpleStrategy", "1");
+        for table_name in ["table_a", "table_b"] {
+            node.create_encrypted_table(
+                "locks_keyspace",
+                table_name,
+                vec!["id".to_string()],
+                vec![],
+                vec![("id".to_string(), "int".to_string())],
+            );
+        }
+        let mut values = HashMap::new();
+        values.insert("id".to_string(), "1".to_string());
+
+        let table_a = node.get_shared_table("locks_keyspace", "table_a").unwrap();
+        let _guard = table_a.write().unwrap();
+
+        assert!(node.insert_row("locks_keyspace", "table_b", values).is_ok());
+        assert_eq!(node.get_table("locks_keyspace", "table_b").unwrap().len(), 1);
+    }
+
+    fn create_node_with_composite_partition_key(node_id: &str) -> Node {
+        let node = Node::new_with_storage(node_id, "localhost", 9042, 7000, temp_storage(node_id));
+        let peers: Vec<GossipInformation> = (0..4)
+            .map(|i| GossipInformation {
+                node_id: format!("{}_{}", node_id, i),
+                ip: "localhost".to_string(),
+                port_native_protocol: "9042".to_string(),
+                port_gossip_query: "7000".to_string(),
+                last_heartbeat: 0,
+                status: "Live".to_string(),
+            })
+            .collect();
+        node.update_gossip_table(&peers);
+        let _ = node.create_keyspace("composite_keyspace", "SimpleStrategy", "2");
+        node.create_encrypted_table(
+            "composite_keyspace",
+            "flights",
+            vec!["origin".to_string(), "year".to_string()],
+            vec!["id".to_string()],
+            vec![
+                ("origin".to_string(), "text".to_string()),
+                ("year".to_string(), "int".to_string()),
+                ("id".to_string(), "int".to_string()),
+            ],
+        );
+        node
+    }
+
+    #[test]
+    fn test_condition_on_composite_partition_key_routes_like_insert() {
+        let node = create_node_with_composite_partition_key("node_composite_pk");
+
+        let mut row = HashMap::new();
+        row.insert("origin".to_string(), "EZE".to_string());
+        row.insert("year".to_string(), "2024".to_string());
+        row.insert("id".to_string(), "1".to_string());
+        let insert_nodes = node.get_nodes_for_insert("composite_keyspace", "flights", &row);
+        assert_eq!(insert_nodes.len(), 2);
+
+        // Las columnas de la partition key en distinto orden que en la tabla.
+        let condition = Expression::And {
+            left: Box::new(Expression::Comparison {
+                left: Operand::Column("year".to_string()),
+                operator: "=".to_string(),
+                right: Operand::Integer("2024".to_string()),
+            }),
+            right: Box::new(Expression::Comparison {
+                left: Operand::Column("origin".to_string()),
+                operator: "=".to_string(),
+                right: Operand::String("EZE".to_string()),
+            }),
+        };
+        let condition_nodes =
+            node.get_nodes_for_condition("composite_keyspace", "flights", &condition);
+        assert_eq!(condition_nodes, insert_nodes);
+
+        let partial_condition = Expression::Comparison {
+            left: Operand::Column("origin".to_string()),
+            operator: "=".to_string(),
+            right: Operand::String("EZE".to_string()),
+        };
+        assert!(node
+            .get_nodes_for_condition("composite_keyspace", "flights", &partial_condition)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_mutations_without_partition_key_skip_the_dead_nodes() {
+        let node = create_node_with_composite_partition_key("node_mutation_broadcast");
+        node.update_gossip_table(&[GossipInformation {
+            node_id: "node_mutation_broadcast_3".to_string(),
+            ip: "localhost".to_string(),
+            port_native_protocol: "9042".to_string(),
+            port_gossip_query: "7000".to_string(),
+            last_heartbeat: 1,
+            status: "Dead".to_string(),
+        }]);
+
+        let partial_condition = Expression::Comparison {
+            left: Operand::Column("origin".to_string()),
+            operator: "=".to_string(),
+            right: Operand::String("EZE".to_string()),
+        };
+        let nodes =
+            node.get_nodes_for_mutation("composite_keyspace", "flights", &partial_condition);
+        assert_eq!(
+            nodes,
+            [
+                "node_mutation_broadcast",
+                "node_mutation_broadcast_0",
+                "node_mutation_broadcast_1",
+                "node_mutation_broadcast_2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replica_placement_is_cached_until_the_ring_changes() {
+        let node = create_node_with_composite_partition_key("node_placement");
+        let partition_keys = vec!["EZE".to_string(), "2024".to_string()];
+        let replicas = node.get_nodes_for_partition("composite_keyspace", &partition_keys);
+        assert_eq!(replicas.len(), 2);
+
+        // El nodo y sus cuatro pares, sin los que hayan dejado corridas anteriores
+        let num_nodes = node.get_gossip_table().unwrap().len();
+        assert_eq!(num_nodes, 5);
+        let range = node
+            .consistent_hash
+            .range_index(&partition_keys, num_nodes)
+            .unwrap();
+        assert_eq!(
+            node.placement.get("composite_keyspace", range),
+            Some(replicas.clone())
+        );
+
+        // Un cambio de estado de un nodo vacía el cache
+        node.update_gossip_table(&[GossipInformation {
+            node_id: "node_placement_1".to_string(),
+            ip: "localhost".to_string(),
+            port_native_protocol: "9042".to_string(),
+            port_gossip_query: "7000".to_string(),
+            last_heartbeat: 1,
+            status: "Dead".to_string(),
+        }]);
+        assert_eq!(node.placement.get("composite_keyspace", range), None);
+        assert_eq!(
+            node.get_nodes_for_partition("composite_keyspace", &partition_keys),
+            replicas
+        );
+
+        // Igual que volver a crear el keyspace con otra replicación
+        let _ = node.create_keyspace("composite_keyspace", "SimpleStrategy", "3");
+        assert_eq!(node.placement.get("composite_keyspace", range), None);
+        assert_eq!(
+            node.get_nodes_for_partition("composite_keyspace", &partition_keys).len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_dead_replicas_make_the_coordinator_answer_unavailable() {
+        let node = create_node_with_composite_partition_key("node_dead_replicas");
+        let partition_keys = vec!["EZE".to_string(), "2024".to_string()];
+        let replicas = node.get_nodes_for_partition("composite_keyspace", &partition_keys);
+        let dead: Vec<GossipInformation> = replicas
+            .iter()
+            .filter(|node_id| *node_id != node.get_id())
+            .map(|node_id| GossipInformation {
+                node_id: node_id.to_string(),
+                ip: "localhost".to_string(),
+                port_native_protocol: "9042".to_string(),
+                port_gossip_query: "7000".to_string(),
+                last_heartbeat: 1,
+                status: "Dead".to_string(),
+            })
+            .collect();
+        node.update_gossip_table(&dead);
+
+        let alive = replicas.len() - dead.len();
+        let unavailable = ErrorCode::UnavailableException {
+            consistency: ConsistencyLevel::All,
+            required: 2,
+            alive: alive as i32,
+        };
+        assert_eq!(
+            node.live_replicas(replicas, &Consistency::All),
+            Err(unavailable)
+        );
+
+        // Se responde sin esperar a que las réplicas caídas no contesten
+        let start = Instant::now();
+        let query = Query::default(
+            "INSERT INTO flights (origin, year, id) VALUES ('EZE', 2024, 1);".to_string(),
+            ConsistencyLevel::All,
+        );
+        assert_eq!(
+            node.resend_query_as_internal_message(query, Some("composite_keyspace".to_string()))
+                .err(),
+            Some(unavailable)
+        );
+        assert!(start.elapsed() < TimeoutConfig::default().write_request());
+    }
+
+    #[test]
+    fn test_oversized_replication_is_rejected_or_clamped() {
+        let create = |node: &Node, keyspace_name: &str| {
+            let query = Query::default(
+                format!(
+                    "CREATE KEYSPACE {} WITH REPLICATION = {{'class': 'SimpleStrategy', 'replication_factor': 3}};",
+                    keyspace_name
+                ),
+                ConsistencyLevel::All,
+            );
+            node.resend_query_as_internal_message(query, None).err()
+        };
+
+        let storage = temp_storage("oversized_replication_is_rejected_or_clamped");
+        let rejecting = Node::new_with_storage(
+            "node_oversized_reject",
+            "localhost",
+            9042,
+            7000,
+            storage.clone(),
+        );
+        assert_eq!(
+            create(&rejecting, "oversized_keyspace"),
+            Some(ErrorCode::ConfigError)
+        );
+        assert!(!rejecting.keyspace_exists("oversized_keyspace"));
+
+        let mut settings = NodeSettings {
+            storage,
+            ..NodeSettings::default()
+        };
+        settings.cluster.oversized_replication = "clamp".to_string();
+        let clamping =
+            Node::new_with_config("node_oversized_clamp", "localhost", 9042, 7000, settings);
+        assert_eq!(create(&clamping, "oversized_keyspace"), None);
+        let keyspaces = clamping.describe_cluster().unwrap().keyspaces;
+        assert_eq!(keyspaces["oversized_keyspace"].replication_factor, 1);
+    }
+
+    #[test]
+    fn test_replication_counts_the_nodes_that_are_down() {
+        let node = Node::new_with_storage(
+            "node_replication_down_peer",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("replication_counts_the_nodes_that_are_down"),
+        );
+        node.update_gossip_table(&[GossipInformation {
+            node_id: "node_replication_down_peer_2".to_string(),
+            ip: "127.0.0.1".to_string(),
+            port_native_protocol: "1".to_string(),
+            port_gossip_query: "1".to_string(),
+            last_heartbeat: 1,
+            status: "Dead".to_string(),
+        }]);
+        let query = "CREATE KEYSPACE down_peer_keyspace WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 2};";
+
+        // Un nodo caído sigue siendo parte del cluster: el keyspace no se rechaza ni se recorta
+        assert_eq!(
+            node.check_replication(query, "down_peer_keyspace", "SimpleStrategy", "2"),
+            Ok(query.to_string())
+        );
+        assert!(node
+            .check_replication(query, "down_peer_keyspace", "SimpleStrategy", "3")
+            .is_err());
+    }
+
+    #[test]
+    fn test_dead_nodes_leave_keyspaces_with_a_schema_warning() {
+        let node = Node::new_with_storage(
+            "node_schema_warnings",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("dead_nodes_leave_keyspaces_with_a_schema_warning"),
+        );
+        let mut peer = GossipInformation {
+            node_id: "node_schema_warnings_peer".to_string(),
+            ip: "127.0.0.1".to_string(),
+            port_native_protocol: "1".to_string(),
+            port_gossip_query: "1".to_string(),
+            last_heartbeat: 1,
+            status: "Live".to_string(),
+        };
+        node.update_gossip_table(&[peer.clone()]);
+        node.create_keyspace("replicated_keyspace", "SimpleStrategy", "2")
+            .unwrap();
+        assert!(node.describe_cluster().unwrap().warnings.is_empty());
+
+        peer.last_heartbeat = 2;
+        peer.status = "Dead".to_string();
+        node.update_gossip_table(&[peer]);
+        assert_eq!(
+            node.describe_cluster().unwrap().warnings,
+            vec!["Keyspace replicated_keyspace has replication factor 2 but only 1 nodes are alive"]
+        );
+    }
+
+    #[test]
+    fn test_writes_at_any_succeed_with_hints_for_the_dead_replicas() {
+        let node = create_node_with_composite_partition_key("node_any");
+        let partition_keys = vec!["EZE".to_string(), "2024".to_string()];
+        let replicas = node.get_nodes_for_partition("composite_keyspace", &partition_keys);
+        let dead: Vec<GossipInformation> = replicas
+            .iter()
+            .filter(|node_id| *node_id != node.get_id())
+            .map(|node_id| GossipInformation {
+                node_id: node_id.to_string(),
+                ip: "localhost".to_string(),
+                port_native_protocol: "9042".to_string(),
+                port_gossip_query: "7000".to_string(),
+                last_heartbeat: 1,
+                status: "Dead".to_string(),
+            })
+            .collect();
+        node.update_gossip_table(&dead);
+
+        let query = Query::default(
+            "INSERT INTO flights (origin, year, id) VALUES ('EZE', 2024, 1);".to_string(),
+            ConsistencyLevel::Any,
+        );
+        assert!(node
+            .resend_query_as_internal_message(query, Some("composite_keyspace".to_string()))
+            .is_ok());
+        let hinted: Vec<String> = node.pending_hints().unwrap().into_keys().collect();
+        let mut dead: Vec<String> = dead.into_iter().map(|gossip_info| gossip_info.node_id).collect();
+        dead.sort();
+        assert_eq!(hinted, dead);
+    }
+
+    #[test]
+    fn test_hints_do_not_overwrite_rows_written_after_them() {
+        let node = Node::new_with_storage(
+            "node_late_hints",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("hints_do_not_overwrite_rows_written_after_them"),
+        );
+        let _ = node.create_keyspace("hints_keyspace", "SimpleStrategy", "1");
+        node.create_encrypted_table(
+            "hints_keyspace",
+            "flights",
+            vec!["id".to_string()],
+            vec![],
+            vec![
+                ("id".to_string(), "int".to_string()),
+                ("origin".to_string(), "text".to_string()),
+            ],
+        );
+        let write = |body: &str| InternalMessage::Query {
+            opcode: 2,
+            body: body.to_string(),
+            keyspace_name: "hints_keyspace".to_string(),
+            trace_id: new_trace_id(),
+        };
+        node.receive_internal_message(&write(
+            "INSERT INTO flights (id, origin, _timestamp) VALUES (1, 'EZE', '2024-05-01 11:00:00');",
+        ))
+        .unwrap();
+        let origin = || {
+            let condition = Expression::Comparison {
+                left: Operand::Column("id".to_string()),
+                operator: "=".to_string(),
+                right: Operand::Integer("1".to_string()),
+            };
+            let table = node.get_table("hints_keyspace", "flights").unwrap();
+            table.select_if(&condition)[0]["origin"].clone()
+        };
+
+        // Un hint de antes de la última escritura de la fila no la pisa
+        let late = write(
+            "INSERT INTO flights (id, origin, _timestamp) VALUES (1, 'AEP', '2024-05-01 10:00:00');",
+        );
+        let sent_at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        node.receive_internal_message(&hint_message(&late, sent_at)).unwrap();
+        assert_eq!(origin(), "EZE");
+
+        let newer = write(
+            "INSERT INTO flights (id, origin, _timestamp) VALUES (1, 'COR', '2024-05-01 12:00:00');",
+        );
+        let sent_at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        node.receive_internal_message(&hint_message(&newer, sent_at)).unwrap();
+        assert_eq!(origin(), "COR");
+
+        // Dentro del mismo segundo gana la escritura más reciente
+        node.receive_internal_message(&write(
+            "INSERT INTO flights (id, origin, _timestamp) VALUES (1, 'MDZ', '2024-05-01 13:00:00.500000');",
+        ))
+        .unwrap();
+        let same_second = write(
+            "INSERT INTO flights (id, origin, _timestamp) VALUES (1, 'ROS', '2024-05-01 13:00:00.250000');",
+        );
+        // Se envió después que la escritura, pero se hizo antes
+        let sent_at = Utc.with_ymd_and_hms(2024, 5, 1, 13, 0, 1).unwrap();
+        node.receive_internal_message(&hint_message(&same_second, sent_at)).unwrap();
+        assert_eq!(origin(), "MDZ");
+    }
+
+    #[test]
+    fn test_hints_only_leave_out_the_rows_written_after_them() {
+        let node = Node::new_with_storage(
+            "node_partly_late_hints",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("hints_only_leave_out_the_rows_written_after_them"),
+        );
+        let _ = node.create_keyspace("partial_hints_keyspace", "SimpleStrategy", "1");
+        node.create_encrypted_table(
+            "partial_hints_keyspace",
+            "flights",
+            vec!["id".to_string()],
+            vec![],
+            vec![
+                ("id".to_string(), "int".to_string()),
+                ("origin".to_string(), "text".to_string()),
+            ],
+        );
+        let write = |opcode: u8, body: &str| InternalMessage::Query {
+            opcode,
+            body: body.to_string(),
+            keyspace_name: "partial_hints_keyspace".to_string(),
+            trace_id: new_trace_id(),
+        };
+        node.receive_internal_message(&write(
+            2,
+            "INSERT INTO flights (id, origin, _timestamp) VALUES (1, 'EZE', '2024-05-01 11:00:00');",
+        ))
+        .unwrap();
+        let origins = || {
+            let table = node.get_table("partial_hints_keyspace", "flights").unwrap();
+            let mut origins: Vec<(String, String)> = table
+                .select_if(&Expression::True)
+                .into_iter()
+                .map(|row| (row["id"].clone(), row["origin"].clone()))
+                .collect();
+            origins.sort();
+            origins
+        };
+        let origin = |id: &str, origin: &str| (id.to_string(), origin.to_string());
+
+        // Solo la fila 1 se escribió después del hint: las otras dos se escriben igual
+        let late = write(
+            2,
+            "INSERT INTO flights (id, origin, _timestamp) VALUES (1, 'AEP', '2024-05-01 10:00:00'), (2, 'AEP', '2024-05-01 10:00:00'), (3, 'AEP', '2024-05-01 10:00:00');",
+        );
+        let sent_at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        node.receive_internal_message(&hint_message(&late, sent_at)).unwrap();
+        assert_eq!(
+            origins(),
+            vec![origin("1", "EZE"), origin("2", "AEP"), origin("3", "AEP")]
+        );
+
+        let late = write(
+            4,
+            "UPDATE flights SET origin = 'COR', _timestamp = '2024-05-01 10:30:00' WHERE id > 0;",
+        );
+        let sent_at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+        node.receive_internal_message(&hint_message(&late, sent_at)).unwrap();
+        assert_eq!(
+            origins(),
+            vec![origin("1", "EZE"), origin("2", "COR"), origin("3", "COR")]
+        );
+
+        let late = write(5, "DELETE FROM flights WHERE id > 1;");
+        let sent_at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 15, 0).unwrap();
+        node.receive_internal_message(&hint_message(&late, sent_at)).unwrap();
+        assert_eq!(
+            origins(),
+            vec![origin("1", "EZE"), origin("2", "COR"), origin("3", "COR")]
+        );
+        let sent_at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 45, 0).unwrap();
+        node.receive_internal_message(&hint_message(&late, sent_at)).unwrap();
+        assert_eq!(origins(), vec![origin("1", "EZE")]);
+    }
+
+    #[test]
+    fn test_reassigned_partitions_are_deleted_once_their_replicas_confirm() {
+        let storage = temp_storage("reassigned_partitions_are_deleted_once_their_replicas_confirm");
+        let source = Node::new_with_storage(
+            "node_cleanup_source",
+            "127.0.0.1",
+            9042,
+            7000,
+            storage.clone(),
+        );
+        let _ = source.create_keyspace("cleanup_keyspace", "SimpleStrategy", "1");
+        source.create_encrypted_table(
+            "cleanup_keyspace",
+            "flights",
+            vec!["id".to_string()],
+            vec!["origin".to_string()],
+            vec![
+                ("id".to_string(), "int".to_string()),
+                ("origin".to_string(), "text".to_string()),
+            ],
+        );
+        for id in 0..20 {
+            let insert = InternalMessage::Query {
+                opcode: 2,
+                body: format!(
+                    "INSERT INTO flights (id, origin, _timestamp) VALUES ({}, 'EZE', '2024-05-01 10:00:00');",
+                    id
+                ),
+                keyspace_name: "cleanup_keyspace".to_string(),
+                trace_id: new_trace_id(),
+            };
+            source.receive_internal_message(&insert).unwrap();
+        }
+        let partitions = |node: &Node| match node.get_table("cleanup_keyspace", "flights") {
+            Some(table) => table.get_partitions().len(),
+            None => 0,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let target = Arc::new(Node::new_with_storage(
+            "node_cleanup_target",
+            "127.0.0.1",
+            0,
+            port,
+            storage,
+        ));
+        let target_listener = Arc::clone(&target);
+        thread::spawn(move || {
+            crate::handler_nodes::serve_node_gossip_query_protocol(target_listener, listener)
+        });
+        let mut target_info = GossipInformation {
+            node_id: "node_cleanup_target".to_string(),
+            ip: "127.0.0.1".to_string(),
+            port_native_protocol: "0".to_string(),
+            port_gossip_query: port.to_string(),
+            last_heartbeat: 1,
+            status: "Dead".to_string(),
+        };
+
+        // Mientras la réplica nueva está caída, las particiones no se borran
+        source.update_gossip_table(&[target_info.clone()]);
+        let moved = source.cleanups.pending().len();
+        assert!(moved > 0);
+        assert_eq!(partitions(&source), 20);
+        assert_eq!(partitions(&target), 0);
+
+        // Cuando vuelve, se le envían y se borran cuando confirma que las recibió
+        target_info.last_heartbeat = 2;
+        target_info.status = "Live".to_string();
+        source.update_gossip_table(&[target_info]);
+        assert!(source.cleanups.pending().is_empty());
+        assert_eq!(partitions(&source), 20 - moved);
+        assert_eq!(partitions(&target), moved);
+    }
+
+    #[test]
+    fn test_mutations_only_broadcast_when_partition_key_is_missing() {
+        let node = create_node_with_composite_partition_key("node_mutation_routing");
+
+        let full_key = Expression::And {
+            left: Box::new(Expression::Comparison {
+                left: Operand::Column("origin".to_string()),
+                operator: "=".to_string(),
+                right: Operand::String("EZE".to_string()),
+            }),
+            right: Box::new(Expression::Comparison {
+                left: Operand::Column("year".to_string()),
+                operator: "=".to_string(),
+                right: Operand::Integer("2024".to_string()),
+            }),
+        };
+        assert_eq!(
+            node.get_nodes_for_mutation("composite_keyspace", "flights", &full_key)
+                .len(),
+            2
+        );
+
+        let without_key = Expression::Comparison {
+            left: Operand::Column("id".to_string()),
+            operator: "=".to_string(),
+            right: Operand::Integer("1".to_string()),
+        };
+        assert_eq!(
+            node.get_nodes_for_mutation("composite_keyspace", "flights", &without_key)
+                .len(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_multi_row_insert_is_grouped_by_replicas() {
+        let node = create_node_with_composite_partition_key("node_multi_row_insert");
+        let columns = vec!["origin".to_string(), "year".to_string(), "id".to_string()];
+        let origins = ["EZE", "MAD", "JFK", "GRU", "SCL", "EZE", "LIM", "BOG"];
+        let rows: Vec<HashMap<String, String>> = origins
+            .iter()
+            .enumerate()
+            .map(|(i, origin)| {
+                let mut row = HashMap::new();
+                row.insert("origin".to_string(), origin.to_string());
+                row.insert("year".to_string(), "2024".to_string());
+                row.insert("id".to_string(), i.to_string());
+                row
+            })
+            .collect();
+
+        let query = insert_message_from_rows("flights", &columns, &rows);
+        assert!(query.starts_with(
+            "INSERT INTO flights (origin, year, id) VALUES ('EZE', '2024', '0'), ('MAD'"
+        ));
+        let parsed = parse_instruction(&query).unwrap();
+        assert_eq!(parsed.get_rows_to_insert().unwrap(), rows);
+
+        let groups = node.group_rows_by_replicas("composite_keyspace", "flights", &rows);
+        assert!(groups.len() > 1);
+        assert_eq!(groups.iter().map(Vec::len).sum::<usize>(), rows.len());
+        for group in &groups {
+            let nodes = node.get_nodes_for_insert("composite_keyspace", "flights", &group[0]);
+            assert!(group.iter().all(|row| {
+                node.get_nodes_for_insert("composite_keyspace", "flights", row) == nodes
+            }));
+        }
+        // Las filas de una misma particion quedan juntas y en orden
+        let eze = groups.iter().find(|group| group[0]["origin"] == "EZE").unwrap();
+        assert_eq!(eze.iter().filter(|row| row["origin"] == "EZE").count(), 2);
+    }
+
+    /// Builds the rows a replica answers, from their values as text.
+    fn row_set(columns: &[(&str, &str)], rows: &[&[(&str, &str)]]) -> RowSet {
+        let columns: Vec<(String, String)> = columns
+            .iter()
+            .map(|(name, column_type)| (name.to_string(), column_type.to_string()))
+            .collect();
+        let mut row_set = table_row_set("keyspace", "flights", &columns);
+        let rows: Vec<HashMap<String, String>> = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|(column, value)| (column.to_string(), value.to_string()))
+                    .collect()
+            })
+            .collect();
+        row_set.extend_from_text(&rows);
+        row_set
+    }
+
+    #[test]
+    fn test_merge_select_responses_deduplicates_by_timestamp() {
+        let columns = [("id", "int"), ("name", "text"), ("_timestamp", "String")];
+        let old = row_set(
+            &columns,
+            &[
+                &[("id", "1"), ("name", "old"), ("_timestamp", "2024-01-01 10:00:00")],
+                &[("id", "3"), ("name", "c"), ("_timestamp", "2024-01-01 10:00:00")],
+            ],
+        );
+        // Otra réplica puede responder las columnas en otro orden
+        let new = row_set(
+            &[("name", "text"), ("_timestamp", "String"), ("id", "int")],
+            &[
+                &[("id", "1"), ("name", "new"), ("_timestamp", "2024-01-01 11:00:00")],
+                &[("id", "20"), ("name", "b"), ("_timestamp", "2024-01-01 10:00:00")],
+            ],
+        );
+        let responses = vec![old, new];
+        let primary_key = vec!["id".to_string()];
+
+        let rows = merge_select_responses(&responses, &primary_key, &[], None);
+        assert_eq!(rows.rows.len(), 3);
+        assert_eq!(rows.value(&rows.rows[0], "name"), &Value::Text("new".to_string()));
+        assert_eq!(rows.columns, responses[0].columns);
+
+        let order_by = vec![("id".to_string(), "DESC".to_string())];
+        let rows = merge_select_responses(&responses, &primary_key, &order_by, Some(2));
+        let ids: Vec<&Value> = rows.rows.iter().map(|row| rows.value(row, "id")).collect();
+        assert_eq!(ids, vec![&Value::Int(20), &Value::Int(3)]);
+    }
+
+    #[test]
+    fn test_merge_select_responses_does_not_depend_on_the_order_of_the_replicas() {
+        let columns = [("id", "int"), ("name", "text"), ("_timestamp", "String")];
+        let first = row_set(
+            &columns,
+            &[&[("id", "1"), ("name", "a"), ("_timestamp", "2024-01-01 10:00:00")]],
+        );
+        // Escrita en el mismo segundo en otra réplica
+        let second = row_set(
+            &columns,
+            &[&[("id", "1"), ("name", "b"), ("_timestamp", "2024-01-01 10:00:00")]],
+        );
+        let without_timestamp = row_set(&columns, &[&[("id", "1"), ("name", "z")]]);
+        let primary_key = vec!["id".to_string()];
+
+        let responses = [first.clone(), second.clone(), without_timestamp.clone()];
+        let rows = merge_select_responses(&responses, &primary_key, &[], None);
+        assert_eq!(rows.rows.len(), 1);
+        assert_eq!(rows.value(&rows.rows[0], "name"), &Value::Text("b".to_string()));
+
+        let responses = [without_timestamp, second, first];
+        assert_eq!(merge_select_responses(&responses, &primary_key, &[], None), rows);
+    }
+
+    #[test]
+    fn test_page_of_resumes_after_the_previous_page() {
+        let ids: Vec<String> = (0..5).map(|id| id.to_string()).collect();
+        let rows: Vec<[(&str, &str); 1]> = ids.iter().map(|id| [("id", id.as_str())]).collect();
+        let rows: Vec<&[(&str, &str)]> = rows.iter().map(|row| row.as_slice()).collect();
+        let rows = row_set(&[("id", "int")], &rows);
+
+        let (page, paging_state) = page_of(rows.clone(), 2, None).unwrap();
+        assert_eq!(page.rows, rows.rows[..2]);
+        let (page, paging_state) = page_of(rows.clone(), 2, paging_state.as_deref()).unwrap();
+        assert_eq!(page.rows, rows.rows[2..4]);
+        let (page, paging_state) = page_of(rows.clone(), 2, paging_state.as_deref()).unwrap();
+        assert_eq!(page.rows, rows.rows[4..]);
+        assert!(paging_state.is_none());
+
+        assert!(page_of(rows, 2, Some(&[1, 2])).is_err());
+    }
+
+    #[test]
+    fn test_select_without_partition_key_gathers_every_partition() {
+        let node = Node::new_with_storage(
+            "node_scatter_select",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("select_without_partition_key_gathers_every_partition"),
+        );
+        let _ = node.create_keyspace("scatter_keyspace", "SimpleStrategy", "1");
+        node.create_encrypted_table(
+            "scatter_keyspace",
+            "flights",
+            vec!["id".to_string()],
+            vec![],
+            vec![
+                ("id".to_string(), "int".to_string()),
+                ("origin".to_string(), "text".to_string()),
+            ],
+        );
+        for (id, origin) in [("1", "EZE"), ("2", "AEP"), ("3", "EZE"), ("4", "EZE")] {
+            let mut values = HashMap::new();
+            values.insert("id".to_string(), id.to_string());
+            values.insert("origin".to_string(), origin.to_string());
+            node.insert_row("scatter_keyspace", "flights", values).unwrap();
+        }
+
+        let query = Query::default(
+            "SELECT * FROM flights WHERE origin = 'EZE' ORDER BY id DESC LIMIT 2;".to_string(),
+            ConsistencyLevel::One,
+        );
+        let result = node
+            .resend_query_as_internal_message(query, Some("scatter_keyspace".to_string()))
+            .unwrap();
+        let rows: Vec<HashMap<String, String>> = serde_json::from_str(&result.to_string()).unwrap();
+        let ids: Vec<&String> = rows.iter().map(|row| row.get("id").unwrap()).collect();
+        assert_eq!(ids, vec!["4", "3"]);
+        // El resultado conserva el tipo de cada columna
+        let rows = result.row_set().unwrap();
+        assert_eq!(rows.columns[0], Column::new("id", ColumnType::Int));
+        assert_eq!(rows.rows[0][0], Value::Int(4));
+    }
 
-            match replication_strategy_name.as_str() {
-                "SimpleStrategy" => {
-                    keyspaces.insert(
-                        keyspace_name.to_string(),
-                        ReplicationStrategy::new_simple(replication_factor.to_string()),
-                    );
-                }
-                _ => {
-                    eprintln!(
-                        "Invalid replication strategy: {}",
-                        replication_strategy_name
-                    );
-                    return;
-                }
-            }
-        }
+    #[test]
+    fn test_system_peers_describes_the_nodes() {
+        let node = Node::new_with_storage(
+            "node_peers",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("system_peers_describes_the_nodes"),
+        );
+        let query = Query::default("SELECT * FROM system.peers;".to_string(), ConsistencyLevel::One);
+        let result = node.resend_query_as_internal_message(query, None).unwrap();
+        let peers: Vec<HashMap<String, String>> =
+            serde_json::from_str(&result.to_string()).unwrap();
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0]["peer"], "node_peers");
+        assert_eq!(peers[0]["rpc_address"], "localhost:9042");
+        assert_eq!(peers[0]["pending_hints"], "0");
+        // Un solo nodo es duenio de todo el anillo
+        let ownership: f64 = peers[0]["ownership"].parse().unwrap();
+        assert!((ownership - 1.0).abs() < 1e-6);
+
+        let query = Query::default("SELECT * FROM system.hints;".to_string(), ConsistencyLevel::One);
+        assert!(node.resend_query_as_internal_message(query, None).is_err());
     }
 
-    fn load_tables(&self) {
-        let tables_path = match load_tables_path(&self.id) {
-            Ok(tables_path) => tables_path,
-            Err(e) => {
-                eprintln!("Error loading table names: {}", e);
-                return;
-            }
+    #[test]
+    fn test_system_schema_describes_the_tables() {
+        let node = Node::new_with_storage(
+            "node_schema",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("system_schema_describes_the_tables"),
+        );
+        let _ = node.create_keyspace("schema_keyspace", "SimpleStrategy", "1");
+        node.create_encrypted_table(
+            "schema_keyspace",
+            "positions",
+            vec!["flight".to_string()],
+            vec!["time".to_string()],
+            vec![
+                ("flight".to_string(), "int".to_string()),
+                ("time".to_string(), "text".to_string()),
+                ("altitude".to_string(), "int".to_string()),
+            ],
+        );
+        let select = |query: &str| {
+            let query = Query::default(query.to_string(), ConsistencyLevel::One);
+            let result = node.resend_query_as_internal_message(query, None).unwrap();
+            serde_json::from_str::<Vec<HashMap<String, String>>>(&result.to_string()).unwrap()
         };
 
-        let mut data = match self.data.write() {
-            Ok(data) => data,
-            Err(_) => {
-                eprintln!("Error locking data");
-                return;
-            }
-        };
+        let keyspaces = select(
+            "SELECT * FROM system_schema.keyspaces WHERE keyspace_name = 'schema_keyspace';",
+        );
+        assert_eq!(keyspaces.len(), 1);
+        assert_eq!(keyspaces[0]["replication_factor"], "1");
 
-        for table_path in tables_path {
-            let encrypted_table = match EncryptedTable::load_table(&self.id, &table_path) {
-                Ok(table) => table,
-                Err(e) => {
-                    eprintln!("Error loading table: {}", e);
-                    return;
-                }
-            };
-            let table = encrypted_table.get_table();
-            let name = table.get_name().clone();
-            data.insert(name, encrypted_table);
-        }
+        let columns = select(
+            "SELECT * FROM system_schema.columns WHERE keyspace_name = 'schema_keyspace';",
+        );
+        let kinds: Vec<(&str, &str, &str)> = columns
+            .iter()
+            .map(|row| {
+                (
+                    row["column_name"].as_str(),
+                    row["kind"].as_str(),
+                    row["type"].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("altitude", "regular", "int"),
+                ("flight", "partition_key", "int"),
+                ("time", "clustering", "text"),
+            ]
+        );
     }
 
-    fn load_gossip_table(&self) {
-        let loaded_gossip_table = match load_gossip_table(&self.id) {
-            Ok(gossip_table) => gossip_table,
-            Err(e) => {
-                eprintln!("Error loading gossip table: {}", e);
-                return;
-            }
+    #[test]
+    fn test_conditional_statements_go_through_paxos() {
+        let node = Node::new_with_storage(
+            "node_cas",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("conditional_statements_go_through_paxos"),
+        );
+        let _ = node.create_keyspace("cas_keyspace", "SimpleStrategy", "1");
+        node.create_encrypted_table(
+            "cas_keyspace",
+            "seats",
+            vec!["flight".to_string()],
+            vec!["seat".to_string()],
+            vec![
+                ("flight".to_string(), "int".to_string()),
+                ("seat".to_string(), "text".to_string()),
+                ("passenger".to_string(), "text".to_string()),
+            ],
+        );
+        let execute = |query: &str| {
+            let query = Query::default(query.to_string(), ConsistencyLevel::One);
+            let result = node
+                .resend_query_as_internal_message(query, Some("cas_keyspace".to_string()))
+                .unwrap();
+            serde_json::from_str::<Vec<HashMap<String, String>>>(&result.to_string()).unwrap()
         };
 
-        if loaded_gossip_table.is_empty() {
-            return;
-        }
+        let insert = "INSERT INTO seats (flight, seat, passenger) VALUES (1, '4A', 'Ana') IF NOT EXISTS;";
+        assert_eq!(execute(insert)[0]["[applied]"], "true");
+        let rows = execute(
+            "INSERT INTO seats (flight, seat, passenger) VALUES (1, '4A', 'Juan') IF NOT EXISTS;",
+        );
+        assert_eq!(rows[0]["[applied]"], "false");
+        assert_eq!(rows[0]["passenger"], "Ana");
 
-        let mut gossip_table = match self.gossip_table.write() {
-            Ok(gossip_table) => gossip_table,
-            Err(_) => {
-                eprintln!("Error locking gossip table");
-                return;
-            }
-        };
-        // print for debugging
-        for gossip_info in &loaded_gossip_table {
-            println!("Node: {}, IP: {}, Port: {}", gossip_info.node_id, gossip_info.ip, gossip_info.port_gossip_query);
-        }
+        let rows = execute(
+            "UPDATE seats SET passenger = 'Juan' WHERE flight = 1 AND seat = '4A' IF passenger = 'Juan';",
+        );
+        assert_eq!(rows[0]["[applied]"], "false");
+        let rows = execute(
+            "UPDATE seats SET passenger = 'Juan' WHERE flight = 1 AND seat = '4A' IF passenger = 'Ana';",
+        );
+        assert_eq!(rows[0]["[applied]"], "true");
+        let rows = execute(
+            "UPDATE seats SET passenger = 'Ana' WHERE flight = 1 AND seat = '4B' IF EXISTS;",
+        );
+        assert_eq!(rows[0]["[applied]"], "false");
 
-        *gossip_table = loaded_gossip_table;
+        let table = node.get_table("cas_keyspace", "seats").unwrap();
+        let rows = table.select_if(&Expression::True);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["passenger"], "Juan");
     }
-}
-
-// ------------------------  JSON / Format ------------------------
 
-///  Serializes the given gossip table into a JSON string.
-///
-/// # Parameters
-/// - `gossip_table`: A vector of `GossipInformation` containing the gossip table to be serialized.
-///
-/// # Returns
-/// Ok(string) on success, or an Err(string) on failure.
-///
-pub fn gossip_table_to_json(gossip_table: &Vec<GossipInformation>) -> Result<String, String> {
-    match serde_json::to_string(gossip_table) {
-        Ok(json) => Ok(json),
-        Err(e) => Err(format!("Error serializing gossip table: {}", e)),
+    #[test]
+    fn test_merge_counter_responses_sums_the_newest_shards() {
+        let primary_key = vec!["id".to_string()];
+        let counters = vec!["seats".to_string()];
+        let columns = [("id", "int"), ("seats", "counter")];
+        let responses = vec![
+            row_set(&columns, &[&[("id", "1"), ("seats", r#"{"a":{"value":5,"clock":2},"b":{"value":1,"clock":1}}"#)]]),
+            row_set(&columns, &[&[("id", "1"), ("seats", r#"{"a":{"value":3,"clock":1},"b":{"value":4,"clock":3}}"#)]]),
+        ];
+        let merged = merge_counter_responses(responses, &primary_key, &counters);
+        for response in merged {
+            assert_eq!(response.value(&response.rows[0], "seats"), &Value::Bigint(9));
+        }
     }
-}
 
-/// Generates a CQL `INSERT` statement for a given table and data.
-///
-/// # Parameters
-/// - `table_name`: The name of the table.
-/// - `data`: A `HashMap<String, String>` containing the data to be inserted.
-///
-/// # Returns
-/// A `String` containing the generated CQL statement.
-///
+    #[test]
+    fn test_counter_updates_are_merged_on_read() {
+        let node = Node::new_with_storage(
+            "node_counters",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("counter_updates_are_merged_on_read"),
+        );
+        let _ = node.create_keyspace("counter_keyspace", "SimpleStrategy", "1");
+        node.create_encrypted_table(
+            "counter_keyspace",
+            "sold_seats",
+            vec!["flight".to_string()],
+            vec![],
+            vec![
+                ("flight".to_string(), "int".to_string()),
+                ("sold".to_string(), "counter".to_string()),
+            ],
+        );
+        let execute = |query: &str| {
+            let query = Query::default(query.to_string(), ConsistencyLevel::One);
+            node.resend_query_as_internal_message(query, Some("counter_keyspace".to_string()))
+        };
 
-fn generate_insert_cql(table_name: &str, data: HashMap<String, String>) -> String {
-    let columns: Vec<String> = data.keys().cloned().collect();
-    let values: Vec<String> = data.values().cloned().collect();
+        execute("UPDATE sold_seats SET sold = sold + 5 WHERE flight = 1;").unwrap();
+        execute("UPDATE sold_seats SET sold = sold - 2 WHERE flight = 1;").unwrap();
+        assert!(execute("UPDATE sold_seats SET flight = flight + 1 WHERE flight = 1;").is_err());
 
-    let columns_str = columns.join(", ");
-    let values_str = values
-        .iter()
-        .map(|v| format!("'{}'", v))
-        .collect::<Vec<String>>()
-        .join(", ");
+        let result = execute("SELECT * FROM sold_seats WHERE flight = 1;").unwrap();
+        let rows: Vec<HashMap<String, String>> = serde_json::from_str(&result.to_string()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["sold"], "3");
+    }
 
-    format!(
-        "INSERT INTO {} ({}) VALUES ({});",
-        table_name, columns_str, values_str
-    )
-}
+    #[test]
+    fn test_node_gossips_joining_until_warmed_up() {
+        let node = Node::new_with_storage(
+            "node_warm_up",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("node_gossips_joining_until_warmed_up"),
+        );
+        assert!(node.is_ready());
 
-// ------------------------  Auxiliar ------------------------
+        node.ready.store(false, Ordering::SeqCst);
+        node.set_own_status();
+        assert_eq!(node.get_gossip_table().unwrap()[0].status, "Joining");
 
-/// Sends an internal message to a specified node in the gossip table and waits for a response.
-/// 
-/// # Parameters
-/// - `message`: The `InternalMessage` to be sent.
-/// - `ip`: The IP address of the node.
-/// - `port`: The port of the node.
-/// 
-/// # Returns
-/// Ok(InternalMessage::Response) if a response was received, or an Err(String).
-///  
-fn send_internal_message_and_return_response(
-    message: &InternalMessage,
-    ip: &str,
-    port: &str,
-) -> Result<InternalMessage, String> {
-    let destination = format!("{}:{}", ip, port);
-    match TcpStream::connect(destination) {
-        Ok(mut stream) => {
-            if let Err(e) = message.write_to_stream(&mut stream) {
-                eprintln!("Error sending message: {}", e);
-                return Err(format!("Error sending message: {}", e));
-            }
-
-            let response = InternalMessage::deserialize_from_stream(&mut stream);
-
-            if let Ok(response) = response {
-                match response {
-                    InternalMessage::Response { .. } => {
-                        Ok(response)
-                    }
-                    _ => {
-                        eprintln!("Received invalid response");
-                        Err("Invalid response".to_string())
-                    },
-                }
-            } else {
-                eprintln!("Error deserializing response");
-                Err("Error deserializing response".to_string())
-            }
-        }
-        Err(e) => {
-            eprintln!("Error connecting to node: {}", e);
-            Err(format!("Error connecting to node: {}", e))
-        }
+        node.start_warm_up().join().unwrap();
+        assert!(node.is_ready());
+        assert_eq!(node.get_gossip_table().unwrap()[0].status, "Live");
     }
-}
-
-/// Resends an `InternalMessage` to a specified node in the gossip table and waits for a response. Used for query types that don't require storing hints, as SELECT.
-///
-/// # Parameters
-/// - `gossip_table`: A vector of `GossipInformation` containing the gossip table.
-/// - `to_send`: The `InternalMessage` to be resent.
-/// - `node_id`: The id of the node to which the message will be sent.
-///
-/// # Returns
-/// Ok(String) on success, or a descriptive Err(String) on failure.
-fn resend_without_storing_hint(
-    gossip_table: &Vec<GossipInformation>,
-    to_send: &InternalMessage,
-    node_id: &str,
-) -> Result<String, String> {
-    let mut port = "";
-    let mut ip = "";
 
-    for gossip_info in gossip_table {
-        if gossip_info.node_id == node_id {
-            ip = &gossip_info.ip;
-            port = &gossip_info.port_gossip_query;
-            break;
-        }
+    #[test]
+    fn test_node_is_ready_once_loaded_and_joined() {
+        let storage = temp_storage("node_is_ready_once_loaded_and_joined");
+        let node = Node::new_with_storage("node_health", "localhost", 9042, 7000, storage.clone());
+        let other = Node::new_with_storage("node_health_2", "localhost", 9043, 7001, storage);
+        assert!(node.health().ready);
+
+        node.start_joining();
+        let health = node.health();
+        assert!(health.live && health.data_loaded);
+        assert!(!health.gossip_joined && !health.ready);
+
+        node.update_gossip_table(&other.get_gossip_table().unwrap());
+        assert!(node.health().ready);
+
+        let message = InternalMessage::Gossip {
+            opcode: 8,
+            body: String::new(),
+        };
+        let response = node.receive_internal_message(&message).unwrap();
+        assert!(response.contains("\"ready\":true"));
     }
 
-    if port.is_empty() {
-        return Err("Node not found".to_string());
+    #[test]
+    fn test_queries_admin_requests() {
+        let node = Node::new_with_storage(
+            "node_queries",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("queries_admin_requests"),
+        );
+        let queries = InternalMessage::Gossip {
+            opcode: 9,
+            body: String::new(),
+        };
+        assert_eq!(node.receive_internal_message(&queries).unwrap(), "[]");
+
+        let _guard = node.inflight.register("query_lenta", "SELECT * FROM flights", "airports");
+        let response = node.receive_internal_message(&queries).unwrap();
+        assert!(response.contains("\"query_id\":\"query_lenta\""));
+
+        let kill = |query_id: &str| InternalMessage::Gossip {
+            opcode: 10,
+            body: query_id.to_string(),
+        };
+        assert!(node.receive_internal_message(&kill("query_lenta")).is_ok());
+        assert!(node.receive_internal_message(&kill("otra")).is_err());
+        assert_eq!(node.inflight_queries()[0].state, "killed");
     }
 
-    let destination = format!("{}:{}", ip, port);
+    #[test]
+    fn test_node_advertises_its_broadcast_address() {
+        let node = Node::new_with_storage(
+            "node_listen",
+            "203.0.113.7",
+            9042,
+            7000,
+            temp_storage("node_advertises_its_broadcast_address"),
+        );
+        assert_eq!(node.get_listen_address(), "0.0.0.0");
 
-    if let Ok(mut stream) = TcpStream::connect(destination) {
-        if let Err(e) = to_send.write_to_stream(&mut stream) {
-            return Err(format!("Error resending query: {}", e));
-        }
+        let node = node.with_listen_address("10.0.0.5");
+        assert_eq!(node.get_listen_address(), "10.0.0.5");
+        assert_eq!(node.get_gossip_table().unwrap()[0].ip, "203.0.113.7");
+    }
 
-        let response = InternalMessage::deserialize_from_stream(&mut stream);
+    #[test]
+    fn test_every_keyspace_survives_a_restart() {
+        let node = Node::new_with_storage(
+            "node_keyspaces_document",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("every_keyspace_survives_a_restart"),
+        );
 
-        if let Ok(response) = response {
-            match response {
-                InternalMessage::Response { opcode, body } => {
-                    if opcode == 0 {
-                        Ok(body)
-                    } else {
-                        Err(body)
-                    }
-                }
-                _ => Err("Invalid response".to_string()),
-            }
-        } else {
-            Err("Error deserializing response".to_string())
-        }
-    } else {
-        Err("Error connecting to node".to_string())
+        node.create_keyspace("first_keyspace", "SimpleStrategy", "2")
+            .unwrap();
+        node.create_keyspace("second_keyspace", "SimpleStrategy", "3")
+            .unwrap();
+        node.keyspaces.write().unwrap().insert(
+            "random_keyspace".to_string(),
+            ReplicationStrategy::new_random("2".to_string()),
+        );
+        node.flush();
+
+        let restarted = Node::new_with_storage(
+            "node_keyspaces_document",
+            "localhost",
+            9042,
+            7000,
+            node.get_storage().clone(),
+        );
+        assert_eq!(
+            restarted.get_keyspaces().unwrap(),
+            node.get_keyspaces().unwrap()
+        );
     }
-}
 
-/// Retrieves all the live nodes from the given gossip table.
-///
-/// # Parameters
-/// - `gossip_table`: A vector of `GossipInformation` containing the gossip table.
-///
-/// # Returns
-/// A vector of `GossipInformation` containing the live nodes.
-fn get_live_nodes(gossip_table: &Vec<GossipInformation>) -> Vec<GossipInformation> {
-    let mut nodes = vec![];
-    for node_info in gossip_table {
-        if node_info.status == "Live" {
-            nodes.push(node_info.clone());
+    fn gossip_peer(node_id: &str, status: &str) -> GossipInformation {
+        GossipInformation {
+            node_id: node_id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port_native_protocol: "9042".to_string(),
+            port_gossip_query: "7000".to_string(),
+            last_heartbeat: Utc::now().timestamp(),
+            status: status.to_string(),
         }
     }
-    nodes
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::query_parser::expression::Operand;
 
-    use super::*;
+    #[test]
+    fn test_gossip_flush_writes_only_the_changed_peers() {
+        let node = Node::new_with_storage(
+            "node_gossip_peers",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("gossip_flush_writes_only_the_changed_peers"),
+        );
+        let peer_file = |id: &str| format!("{}/gossip/{}", node.data_dir(), id);
+
+        node.update_gossip_table(&[gossip_peer("peer_a", "Live"), gossip_peer("peer_b", "Live")]);
+        assert!(Path::new(&peer_file("peer_a")).exists());
+        assert!(Path::new(&peer_file("peer_b")).exists());
+
+        fs::remove_file(peer_file("peer_a")).unwrap();
+        let mut dead_peer = gossip_peer("peer_b", "Dead");
+        dead_peer.last_heartbeat += 1;
+        node.update_gossip_table(&[dead_peer]);
+        assert!(!Path::new(&peer_file("peer_a")).exists());
+        assert!(Path::new(&peer_file("peer_b")).exists());
+
+        let restarted = Node::new_with_storage(
+            "node_gossip_peers",
+            "localhost",
+            9042,
+            7000,
+            node.get_storage().clone(),
+        );
+        let statuses: Vec<(String, String)> = restarted
+            .get_gossip_table()
+            .unwrap()
+            .into_iter()
+            .map(|peer| (peer.node_id, peer.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("node_gossip_peers".to_string(), "Live".to_string()),
+                ("peer_b".to_string(), "Dead".to_string()),
+            ]
+        );
+    }
 
     #[test]
-    fn test_create_keyspace() {
-        let node = Node::new("node1", "localhost", 9042, 7000);
-        node.create_keyspace("test_keyspace", "SimpleStrategy", "3")
-            .unwrap();
-        assert!(node.keyspace_exists("test_keyspace"));
+    fn test_gossip_table_of_the_previous_layout_is_split_per_peer() {
+        let storage = temp_storage("gossip_table_of_the_previous_layout_is_split_per_peer");
+        let dir = storage.node_data_dir("node_gossip_legacy");
+        let legacy = vec![
+            gossip_peer("node_gossip_legacy", "Live"),
+            gossip_peer("peer_c", "Live"),
+        ];
+        storage_format::write_file(
+            &format!("{}/gossip_table", dir),
+            FileKind::Gossip,
+            &serde_json::to_vec(&legacy).unwrap(),
+        )
+        .unwrap();
+
+        let node = Node::new_with_storage("node_gossip_legacy", "localhost", 9042, 7000, storage);
+        assert_eq!(node.get_gossip_table().unwrap().len(), 2);
+        assert!(!Path::new(&format!("{}/gossip_table", dir)).exists());
+        assert!(Path::new(&format!("{}/gossip/peer_c", dir)).exists());
     }
 
     #[test]
-    fn test_create_table() {
-        let node = Node::new("node1", "localhost", 9042, 7000);
-        let _ = node.create_keyspace("test_keyspace", "SimpleStrategy", "3");
-        node.create_encrypted_table(
-            "test_keyspace",
-            "test_table",
-            vec!["id".to_string()],
-            vec!["name".to_string()],
-            vec![
-                ("id".to_string(), "int".to_string()),
-                ("name".to_string(), "text".to_string()),
-            ],
+    fn test_flush_skips_unchanged_keyspaces_and_gossip() {
+        let node = Node::new_with_storage(
+            "node_flush_dirty",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("flush_skips_unchanged_keyspaces_and_gossip"),
         );
+        let keyspaces_file = format!("{}/keyspaces", node.data_dir());
+        let gossip_file = format!("{}/gossip/node_flush_dirty", node.data_dir());
 
-        let data = match node.data.read() {
-            Ok(data) => data.clone(),
-            Err(_) => {
-                return;
-            }
-        };
-
-        assert!(data.contains_key("test_keyspace.test_table"));
+        node.create_keyspace("dirty_keyspace", "SimpleStrategy", "1")
+            .unwrap();
+        node.flush();
+        assert!(Path::new(&keyspaces_file).exists());
+
+        fs::remove_file(&keyspaces_file).unwrap();
+        node.flush();
+        assert!(!Path::new(&keyspaces_file).exists());
+
+        let mut gossip_table = node.get_gossip_table().unwrap();
+        gossip_table[0].last_heartbeat += 1;
+        node.update_gossip_table(&gossip_table);
+        assert!(!Path::new(&gossip_file).exists());
+
+        gossip_table[0].status = "Dead".to_string();
+        gossip_table[0].last_heartbeat += 1;
+        node.update_gossip_table(&gossip_table);
+        assert!(Path::new(&gossip_file).exists());
     }
 
     #[test]
-    fn test_insert_row() {
-        let node = Node::new("node1", "localhost", 9042, 7000);
-        let _ = node.create_keyspace("test_keyspace", "SimpleStrategy", "3");
-        node.create_encrypted_table(
-            "test_keyspace",
-            "test_table",
-            vec!["id".to_string()],
-            vec!["name".to_string()],
-            vec![
-                ("id".to_string(), "int".to_string()),
-                ("name".to_string(), "text".to_string()),
-            ],
+    fn test_every_keyspace_is_persisted() {
+        let node = Node::new_with_storage(
+            "node_keyspaces",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("every_keyspace_is_persisted"),
         );
-        let mut values = HashMap::new();
-        values.insert("id".to_string(), "1".to_string());
-        values.insert("name".to_string(), "Alice".to_string());
-        let result = node.insert_row("test_keyspace", "test_table", values);
-        assert!(result.is_ok());
+        for keyspace in ["keyspace_a", "keyspace_b", "keyspace_c"] {
+            node.create_keyspace(keyspace, "SimpleStrategy", "2").unwrap();
+        }
+        node.flush();
+
+        let restarted = Node::new_with_storage(
+            "node_keyspaces",
+            "localhost",
+            9042,
+            7000,
+            node.get_storage().clone(),
+        );
+        for keyspace in ["keyspace_a", "keyspace_b", "keyspace_c"] {
+            assert!(restarted.keyspace_exists(keyspace));
+        }
     }
 
     #[test]
     fn test_update_gossip_table() {
-        let node = Node::new("node1", "localhost", 9042, 7000);
+        let node = Node::new_with_storage(
+            "node1",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("update_gossip_table"),
+        );
         let gossip_info = GossipInformation {
             node_id: "node2".to_string(),
             ip: "localhost".to_string(),
@@ -2461,9 +6812,54 @@ mod tests {
         assert_eq!(gossip_table[1], gossip_info);
     }
 
+    #[test]
+    fn test_update_gossip_table_publishes_events() {
+        let node = Node::new_with_storage(
+            "node_events",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("update_gossip_table_publishes_events"),
+        );
+        let events = node.subscribe_events();
+        let mut gossip_info = GossipInformation {
+            node_id: "node_events_peer".to_string(),
+            ip: "127.0.0.2".to_string(),
+            port_native_protocol: "9043".to_string(),
+            port_gossip_query: "7001".to_string(),
+            last_heartbeat: 1,
+            status: "Live".to_string(),
+        };
+        node.update_gossip_table(&[gossip_info.clone()]);
+
+        gossip_info.last_heartbeat = 2;
+        gossip_info.status = "Dead".to_string();
+        node.update_gossip_table(&[gossip_info.clone()]);
+
+        gossip_info.last_heartbeat = 3;
+        gossip_info.status = "Live".to_string();
+        node.update_gossip_table(&[gossip_info]);
+
+        let address = "127.0.0.2:9043".to_string();
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                ClusterEvent::NewNode(address.clone()),
+                ClusterEvent::Down(address.clone()),
+                ClusterEvent::Up(address),
+            ]
+        );
+    }
+
     #[test]
     fn test_update_row() {
-        let node = Node::new("node1", "localhost", 9042, 7000);
+        let node = Node::new_with_storage(
+            "node1",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("update_row"),
+        );
         let _ = node.create_keyspace("test_keyspace", "SimpleStrategy", "3");
         node.create_encrypted_table(
             "test_keyspace",
@@ -2511,12 +6907,20 @@ mod tests {
         assert!(data
             .get("test_keyspace.test_table")
             .unwrap()
+            .read()
+            .unwrap()
             .contains_row(&expected_values));
     }
 
     #[test]
     fn test_delete_row() {
-        let node = Node::new("node1", "localhost", 9042, 7000);
+        let node = Node::new_with_storage(
+            "node1",
+            "localhost",
+            9042,
+            7000,
+            temp_storage("delete_row"),
+        );
         let _ = node.create_keyspace("test_keyspace", "SimpleStrategy", "3");
         node.create_encrypted_table(
             "test_keyspace",
@@ -2553,6 +6957,8 @@ mod tests {
         assert!(!data
             .get("test_keyspace.test_table")
             .unwrap()
+            .read()
+            .unwrap()
             .contains_row(&values));
     }
     #[test]
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+/// Opcode of the SELECT_PAGE query messages.
+pub const SELECT_PAGE_OPCODE: u8 = 12;
+
+/// Body of a SELECT_PAGE message: a coordinator asks a replica for a page of the rows of a
+/// SELECT, resuming after the position of the last row of the previous page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageRequest {
+    pub query: String,
+    /// Maximum number of rows of the page.
+    pub page_size: usize,
+    /// The `RowPosition` of the last row already returned, as its bytes, or `None` for the
+    /// first page.
+    pub position: Option<Vec<u8>>,
+}
+
+/// The paging state a coordinator hands to the client with each page: how many rows it
+/// already returned, for the `LIMIT` of the query, and the `RowPosition` to resume from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PagingState {
+    pub returned: u64,
+    pub position: Vec<u8>,
+}
+
+impl PagingState {
+    /// Serializes the state as `[u64 returned][position]`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.returned.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.position);
+        bytes
+    }
+
+    /// Deserializes a state written by `to_bytes`.
+    ///
+    /// # Returns
+    /// The state, or `None` if the bytes are too short to be one.
+    pub fn from_bytes(bytes: &[u8]) -> Option<PagingState> {
+        if bytes.len() <= 8 {
+            return None;
+        }
+        let (returned, position) = bytes.split_at(8);
+        Some(PagingState {
+            returned: u64::from_be_bytes(returned.try_into().ok()?),
+            position: position.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paging_states_roundtrip() {
+        let state = PagingState {
+            returned: 40,
+            position: vec![0, 0, 0, 1, 0, 3, b'E', b'Z', b'E'],
+        };
+        assert_eq!(PagingState::from_bytes(&state.to_bytes()), Some(state));
+        assert_eq!(PagingState::from_bytes(&[0, 0, 0, 1]), None);
+    }
+}
@@ -0,0 +1,313 @@
+use crate::encrypted_table::{open_at_rest, seal_at_rest};
+use crate::query_parser::expression::Expression;
+use crate::storage_format::{self, FileKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the file, inside the data directory of a node, where the Paxos state is persisted.
+pub const PAXOS_STATE_FILE: &str = "paxos_state";
+
+/// Identifies a Paxos round. Ballots are ordered by time and then by the id of the
+/// coordinator, so two coordinators never use the same ballot.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Ballot {
+    pub micros: u64,
+    pub node_id: String,
+}
+
+impl Ballot {
+    /// Creates a ballot for a coordinator, newer than the current time and than `after`.
+    ///
+    /// # Parameters
+    /// - `node_id`: The id of the coordinator.
+    /// - `after`: The newest ballot seen by the coordinator, if any.
+    pub fn new(node_id: &str, after: Option<&Ballot>) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_micros() as u64)
+            .unwrap_or(0);
+        let micros = match after {
+            Some(after) => now.max(after.micros + 1),
+            None => now,
+        };
+        Ballot {
+            micros,
+            node_id: node_id.to_string(),
+        }
+    }
+}
+
+/// A write proposed in a Paxos round: the statement and the internal opcode used to apply it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub ballot: Ballot,
+    pub opcode: u8,
+    pub statement: String,
+}
+
+/// Body of a PAXOS_PREPARE message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prepare {
+    /// The partition the round is about.
+    pub key: String,
+    pub ballot: Ballot,
+    /// The table and condition of the row the statement depends on, read by the replica
+    /// along with the promise.
+    pub table_name: String,
+    pub read_condition: Expression,
+}
+
+/// Answer of a replica to a PAXOS_PREPARE message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Promise {
+    /// Whether the replica promised not to accept older ballots.
+    pub promised: bool,
+    /// The newest ballot the replica has promised.
+    pub highest_promised: Ballot,
+    /// A proposal the replica accepted but that may not have been committed.
+    pub accepted: Option<Proposal>,
+    /// The ballot of the last proposal committed on the partition.
+    pub most_recent_commit: Option<Ballot>,
+    /// The rows of the replica that match the read condition.
+    pub rows: Vec<HashMap<String, String>>,
+}
+
+/// Body of the PAXOS_PROPOSE and PAXOS_COMMIT messages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProposalMessage {
+    pub key: String,
+    pub proposal: Proposal,
+}
+
+/// The Paxos state of a partition on a replica.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct PaxosState {
+    promised: Option<Ballot>,
+    accepted: Option<Proposal>,
+    most_recent_commit: Option<Ballot>,
+}
+
+/// The Paxos state of every partition a replica took part in a lightweight transaction for.
+///
+/// Every change is written to disk before it is answered, so a replica that restarts never
+/// breaks a promise it made.
+#[derive(Debug)]
+pub struct PaxosStore {
+    path: String,
+    states: Mutex<HashMap<String, PaxosState>>,
+}
+
+impl PaxosStore {
+    /// Loads the Paxos state stored in `path`, sealed with the at-rest key, or starts empty if
+    /// there is none. A state written in clear by an older version is still read.
+    ///
+    /// # Parameters
+    /// - `path`: The file where the state is persisted.
+    pub fn load(path: &str) -> Self {
+        let states = storage_format::read_file(path, FileKind::Paxos)
+            .ok()
+            .and_then(|payload| {
+                // Los estados anteriores a sellarlos están en claro
+                let payload = open_at_rest(&payload).unwrap_or(payload);
+                serde_json::from_slice(&payload).ok()
+            })
+            .unwrap_or_default();
+        PaxosStore {
+            path: path.to_string(),
+            states: Mutex::new(states),
+        }
+    }
+
+    /// Handles the prepare phase: promises not to accept proposals older than `ballot`.
+    ///
+    /// # Parameters
+    /// - `key`: The partition.
+    /// - `ballot`: The ballot of the round.
+    ///
+    /// # Returns
+    /// Whether it was promised, the newest promised ballot, the accepted proposal and the last
+    /// committed ballot; or Err(String) if the state couldn't be persisted.
+    pub fn prepare(
+        &self,
+        key: &str,
+        ballot: &Ballot,
+    ) -> Result<(bool, Ballot, Option<Proposal>, Option<Ballot>), String> {
+        self.update(key, |state| {
+            let promised = state
+                .promised
+                .as_ref()
+                .is_none_or(|promised| ballot > promised);
+            if promised {
+                state.promised = Some(ballot.clone());
+            }
+            let highest_promised = state.promised.clone().unwrap_or_else(|| ballot.clone());
+            (
+                promised,
+                highest_promised,
+                state.accepted.clone(),
+                state.most_recent_commit.clone(),
+            )
+        })
+    }
+
+    /// Handles the propose phase: accepts the proposal unless a newer ballot was promised.
+    ///
+    /// # Returns
+    /// Whether the proposal was accepted, or Err(String) if the state couldn't be persisted.
+    pub fn propose(&self, key: &str, proposal: &Proposal) -> Result<bool, String> {
+        self.update(key, |state| {
+            let accepted = state
+                .promised
+                .as_ref()
+                .is_none_or(|promised| proposal.ballot >= *promised);
+            if accepted {
+                state.promised = Some(proposal.ballot.clone());
+                state.accepted = Some(proposal.clone());
+            }
+            accepted
+        })
+    }
+
+    /// Handles the commit phase: records the proposal as committed.
+    ///
+    /// # Returns
+    /// Whether the proposal is newer than the last one committed (and has to be applied), or
+    /// Err(String) if the state couldn't be persisted.
+    pub fn commit(&self, key: &str, proposal: &Proposal) -> Result<bool, String> {
+        self.update(key, |state| {
+            let is_newer = state
+                .most_recent_commit
+                .as_ref()
+                .is_none_or(|committed| proposal.ballot > *committed);
+            if is_newer {
+                state.most_recent_commit = Some(proposal.ballot.clone());
+            }
+            if state
+                .accepted
+                .as_ref()
+                .is_some_and(|accepted| accepted.ballot <= proposal.ballot)
+            {
+                state.accepted = None;
+            }
+            is_newer
+        })
+    }
+
+    /// Applies `f` to the state of a partition and persists the result, sealed with the at-rest
+    /// key.
+    fn update<T>(&self, key: &str, f: impl FnOnce(&mut PaxosState) -> T) -> Result<T, String> {
+        let mut states = self
+            .states
+            .lock()
+            .map_err(|_| "Error locking Paxos state".to_string())?;
+        let result = f(states.entry(key.to_string()).or_default());
+        let payload = serde_json::to_vec(&*states)
+            .map_err(|e| format!("Error serializing Paxos state: {}", e))?;
+        storage_format::write_file(&self.path, FileKind::Paxos, &seal_at_rest(&payload))
+            .map_err(|e| format!("Error writing Paxos state: {}", e))?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ballot(micros: u64, node_id: &str) -> Ballot {
+        Ballot {
+            micros,
+            node_id: node_id.to_string(),
+        }
+    }
+
+    fn empty_store(name: &str) -> PaxosStore {
+        let path = format!("./data/paxos_test/{}", name);
+        let _ = std::fs::remove_file(&path);
+        PaxosStore::load(&path)
+    }
+
+    fn proposal(ballot: Ballot) -> Proposal {
+        Proposal {
+            ballot,
+            opcode: 2,
+            statement: "INSERT INTO flights (id) VALUES (1);".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ballots_are_ordered_by_time_and_node() {
+        assert!(ballot(1, "b") > ballot(1, "a"));
+        assert!(ballot(2, "a") > ballot(1, "b"));
+        let newer = Ballot::new("a", Some(&ballot(u64::MAX - 1, "b")));
+        assert_eq!(newer.micros, u64::MAX);
+    }
+
+    #[test]
+    fn test_prepare_rejects_older_ballots() {
+        let store = empty_store("prepare");
+        assert!(store.prepare("k", &ballot(2, "a")).unwrap().0);
+        let (promised, highest, _, _) = store.prepare("k", &ballot(1, "b")).unwrap();
+        assert!(!promised);
+        assert_eq!(highest, ballot(2, "a"));
+        // Otra partición no se ve afectada
+        assert!(store.prepare("other", &ballot(1, "b")).unwrap().0);
+    }
+
+    #[test]
+    fn test_propose_and_commit() {
+        let store = empty_store("propose");
+        store.prepare("k", &ballot(2, "a")).unwrap();
+        assert!(!store.propose("k", &proposal(ballot(1, "b"))).unwrap());
+        assert!(store.propose("k", &proposal(ballot(2, "a"))).unwrap());
+
+        // Un nuevo coordinador ve la propuesta aceptada
+        let (_, _, accepted, _) = store.prepare("k", &ballot(3, "b")).unwrap();
+        assert_eq!(accepted, Some(proposal(ballot(2, "a"))));
+
+        assert!(store.commit("k", &proposal(ballot(2, "a"))).unwrap());
+        assert!(!store.commit("k", &proposal(ballot(2, "a"))).unwrap());
+        let (_, _, accepted, committed) = store.prepare("k", &ballot(4, "b")).unwrap();
+        assert_eq!(accepted, None);
+        assert_eq!(committed, Some(ballot(2, "a")));
+    }
+
+    #[test]
+    fn test_state_survives_a_restart() {
+        let store = empty_store("restart");
+        store.prepare("k", &ballot(5, "a")).unwrap();
+
+        let reloaded = PaxosStore::load("./data/paxos_test/restart");
+        assert!(!reloaded.prepare("k", &ballot(4, "b")).unwrap().0);
+    }
+
+    #[test]
+    fn test_state_is_sealed_on_disk() {
+        let store = empty_store("sealed");
+        store.prepare("vuelo-secreto", &ballot(5, "a")).unwrap();
+
+        let bytes = std::fs::read("./data/paxos_test/sealed").unwrap();
+        assert!(!bytes.windows(13).any(|w| w == b"vuelo-secreto"));
+        let reloaded = PaxosStore::load("./data/paxos_test/sealed");
+        assert!(!reloaded.prepare("vuelo-secreto", &ballot(4, "b")).unwrap().0);
+    }
+
+    #[test]
+    fn test_state_in_clear_is_still_loaded() {
+        let path = "./data/paxos_test/clear";
+        let mut states = HashMap::new();
+        states.insert(
+            "k".to_string(),
+            PaxosState {
+                promised: Some(ballot(5, "a")),
+                ..PaxosState::default()
+            },
+        );
+        let payload = serde_json::to_vec(&states).unwrap();
+        storage_format::write_file(path, FileKind::Paxos, &payload).unwrap();
+
+        let reloaded = PaxosStore::load(path);
+        assert!(!reloaded.prepare("k", &ballot(4, "b")).unwrap().0);
+    }
+}
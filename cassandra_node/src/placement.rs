@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// The replicas of each token range of each keyspace, so the placement of a partition isn't
+/// computed from the gossip table on every statement.
+///
+/// The replicas only change when the membership or the status of the nodes changes, or when a
+/// keyspace is created again with another replication, so the node empties the cache on those
+/// changes while it holds the lock of what changed.
+#[derive(Debug, Default)]
+pub struct PlacementCache {
+    replicas: RwLock<HashMap<(String, usize), Vec<String>>>,
+}
+
+impl PlacementCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the replicas of a token range of a keyspace, `None` if they aren't cached.
+    ///
+    /// # Parameters
+    /// - `keyspace`: The keyspace of the partition.
+    /// - `range`: The position, in the ring, of the range the partition belongs to.
+    pub fn get(&self, keyspace: &str, range: usize) -> Option<Vec<String>> {
+        self.replicas
+            .read()
+            .ok()?
+            .get(&(keyspace.to_string(), range))
+            .cloned()
+    }
+
+    /// Caches the replicas of a token range of a keyspace.
+    pub fn insert(&self, keyspace: &str, range: usize, replicas: Vec<String>) {
+        if let Ok(mut cache) = self.replicas.write() {
+            cache.insert((keyspace.to_string(), range), replicas);
+        }
+    }
+
+    /// Forgets the replicas of every keyspace, after a change of the nodes of the ring.
+    pub fn invalidate(&self) {
+        if let Ok(mut cache) = self.replicas.write() {
+            cache.clear();
+        }
+    }
+
+    /// Forgets the replicas of a keyspace, after a change of its replication.
+    pub fn invalidate_keyspace(&self, keyspace: &str) {
+        if let Ok(mut cache) = self.replicas.write() {
+            cache.retain(|(cached_keyspace, _), _| cached_keyspace != keyspace);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicas_are_forgotten_by_keyspace_or_all_at_once() {
+        let cache = PlacementCache::new();
+        cache.insert("airports", 0, vec!["node0".to_string()]);
+        cache.insert("airports", 1, vec!["node1".to_string()]);
+        cache.insert("flights", 0, vec!["node0".to_string()]);
+        assert_eq!(cache.get("airports", 1), Some(vec!["node1".to_string()]));
+        assert_eq!(cache.get("airports", 2), None);
+
+        cache.invalidate_keyspace("airports");
+        assert_eq!(cache.get("airports", 0), None);
+        assert_eq!(cache.get("flights", 0), Some(vec!["node0".to_string()]));
+
+        cache.invalidate();
+        assert_eq!(cache.get("flights", 0), None);
+    }
+}
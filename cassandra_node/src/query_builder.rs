@@ -1,8 +1,10 @@
 use std::collections::HashMap;
 
 use chrono::Utc;
+use common::frame::messages::prepared::{quote, WRITE_TIMESTAMP_FORMAT};
 
 use crate::encrypted_table::table::Table;
+use crate::query_parser::expression::{Expression, Operand};
 use crate::replication_strategy::ReplicationStrategy;
 
 pub fn create_keyspace_query(
@@ -29,30 +31,20 @@ pub fn create_table_query(table: &Table) -> String {
     let partition_key_columns = table.get_partition_key_columns();
     let clustering_key_columns = table.get_clustering_key_columns();
 
-    for (column_name, column_type) in columns {
-        query.push_str(&format!("{} {},", column_name, column_type));
+    // La columna _timestamp la agrega la tabla al crearse
+    for (column_name, column_type) in columns.iter().filter(|(name, _)| name != "_timestamp") {
+        query.push_str(&format!("{} {}, ", column_name, column_type));
     }
 
-    query.push_str(" PRIMARY KEY (");
-    
-    for (i, column_name) in partition_key_columns.iter().enumerate() {
-        query.push_str(column_name);
-        if i != partition_key_columns.len() - 1 {
-            query.push_str(", ");
-        } else {
-            query.push_str("), ");
-        }
+    // PRIMARY KEY ((partition key), clustering key)
+    query.push_str(&format!(
+        "PRIMARY KEY (({})",
+        partition_key_columns.join(", ")
+    ));
+    for column_name in clustering_key_columns.iter() {
+        query.push_str(&format!(", {}", column_name));
     }
-
-    for (i, column_name) in clustering_key_columns.iter().enumerate() {
-        query.push_str(column_name);
-        if i != clustering_key_columns.len() - 1 {
-            query.push_str(", ");
-        } else {
-            query.push_str(")");
-        }
-    }
-    query.push_str(");");
+    query.push_str("));");
 
     query
 }
@@ -66,9 +58,7 @@ pub fn insert_message_from_row_and_tablename(
 
     for (i, (column, value)) in row.iter().enumerate() {
         insert_str.push_str(column);
-        values_str.push('\'');
-        values_str.push_str(value);
-        values_str.push('\'');
+        values_str.push_str(&quote(value));
         if i < row.len() - 1 {
             insert_str.push_str(", ");
             values_str.push_str(", ");
@@ -81,6 +71,41 @@ pub fn insert_message_from_row_and_tablename(
     insert_str
 }
 
+/// Builds an `INSERT` statement of several rows with the same columns.
+///
+/// # Parameters
+/// - `table_name`: The name of the table.
+/// - `columns`: The columns, in the order of the values.
+/// - `rows`: The rows to insert.
+///
+/// # Returns
+/// A `String` containing the `INSERT` statement, with every value quoted.
+pub fn insert_message_from_rows(
+    table_name: &str,
+    columns: &[String],
+    rows: &[HashMap<String, String>],
+) -> String {
+    let values: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let row_values: Vec<String> = columns
+                .iter()
+                .map(|column| {
+                    quote(row.get(column).map(String::as_str).unwrap_or_default())
+                })
+                .collect();
+            format!("({})", row_values.join(", "))
+        })
+        .collect();
+
+    format!(
+        "INSERT INTO {} ({}) VALUES {};",
+        table_name,
+        columns.join(", "),
+        values.join(", ")
+    )
+}
+
 /// Extracts  the columns (in order) from an `INSERT` statement string in CQL.
 ///
 /// # Parameters
@@ -90,7 +115,7 @@ pub fn insert_message_from_row_and_tablename(
 /// A `Vec<String>` containing the columns in order.
 ///
 pub fn add_timestamp_to_insert_message(insert_str: &str) -> String {
-    let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let timestamp = Utc::now().format(WRITE_TIMESTAMP_FORMAT).to_string();
     let insert_str_before_first_closing_parenthesis =
         insert_str.split(")").collect::<Vec<&str>>()[0];
     let insert_str_after_values = insert_str.split("VALUES").collect::<Vec<&str>>()[1];
@@ -120,7 +145,7 @@ pub fn add_timestamp_to_insert_message(insert_str: &str) -> String {
 /// A `String` containing the `UPDATE` statement with the `_timestamp` field added.
 ///
 pub fn add_timestamp_to_update_message(update_str: &str) -> String {
-    let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let timestamp = Utc::now().format(WRITE_TIMESTAMP_FORMAT).to_string();
     let update_str_before_set = update_str.split("SET").collect::<Vec<&str>>()[0];
     let update_str_after_set = update_str.split("SET").collect::<Vec<&str>>()[1];
 
@@ -131,4 +156,84 @@ pub fn add_timestamp_to_update_message(update_str: &str) -> String {
     update_str_with_timestamp.push_str("',");
     update_str_with_timestamp.push_str(update_str_after_set);
     update_str_with_timestamp
-}
\ No newline at end of file
+}
+
+/// Builds an `UPDATE` statement that sets `values_to_update` on the rows matching `condition`.
+///
+/// # Parameters
+/// - `table_name`: The name of the table.
+/// - `values_to_update`: The value of each column to set.
+/// - `condition`: The condition of the rows to update.
+///
+/// # Returns
+/// A `String` containing the `UPDATE` statement, with every value quoted.
+pub fn update_message(
+    table_name: &str,
+    values_to_update: &HashMap<String, String>,
+    condition: &Expression,
+) -> String {
+    let assignments: Vec<String> = values_to_update
+        .iter()
+        .map(|(column, value)| format!("{} = {}", column, quote(value)))
+        .collect();
+    format!(
+        "UPDATE {} SET {}{};",
+        table_name,
+        assignments.join(", "),
+        where_clause(condition)
+    )
+}
+
+/// Builds a `DELETE` statement of the rows matching `condition`.
+///
+/// # Parameters
+/// - `table_name`: The name of the table.
+/// - `condition`: The condition of the rows to delete.
+pub fn delete_message(table_name: &str, condition: &Expression) -> String {
+    format!("DELETE FROM {}{};", table_name, where_clause(condition))
+}
+
+/// Returns the WHERE clause of a condition, or nothing if every row matches it.
+fn where_clause(condition: &Expression) -> String {
+    match condition {
+        Expression::True => String::new(),
+        condition => format!(" WHERE {}", expression_to_cql(condition)),
+    }
+}
+
+/// Writes a condition back as CQL, with every AND and OR between parentheses so it parses
+/// into the same tree.
+fn expression_to_cql(expression: &Expression) -> String {
+    match expression {
+        // No hay literal para TRUE: una comparación que vale siempre
+        Expression::True => "1 = 1".to_string(),
+        Expression::And { left, right } => format!(
+            "({} AND {})",
+            expression_to_cql(left),
+            expression_to_cql(right)
+        ),
+        Expression::Or { left, right } => format!(
+            "({} OR {})",
+            expression_to_cql(left),
+            expression_to_cql(right)
+        ),
+        Expression::Not { right } => format!("NOT ({})", expression_to_cql(right)),
+        Expression::Comparison {
+            left,
+            operator,
+            right,
+        } => format!(
+            "{} {} {}",
+            operand_to_cql(left),
+            operator,
+            operand_to_cql(right)
+        ),
+    }
+}
+
+fn operand_to_cql(operand: &Operand) -> String {
+    match operand {
+        Operand::Column(text) | Operand::Integer(text) => text.to_string(),
+        Operand::String(text) => quote(text),
+    }
+}
@@ -1,4 +1,5 @@
 use super::custom_error::CustomError;
+use common::frame::messages::query_result::row_set::ColumnType;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -40,21 +41,42 @@ pub enum Operand {
 pub fn evaluate_expression(
     expression: &Expression,
     row: &HashMap<String, String>,
+) -> Result<bool, CustomError> {
+    evaluate_expression_with_types(expression, row, &HashMap::new())
+}
+
+/// Evaluates an expression on a row, comparing the values of each column with the type it
+/// was declared with, so `speed > 900` holds for `1000` and timestamps are compared by their
+/// instant. A column without a type, or a value that isn't of the type of its column, is
+/// compared as a number if both sides are integers and as a text otherwise.
+///
+/// #Parameters
+/// - `expression`: Contains the expression to evaluate.
+/// - `row`: Contains the values of the columns of the row.
+/// - `column_types`: Contains the type of each column of the table.
+///
+/// #Returns
+/// - Whether the row satisfies the expression.
+///
+pub fn evaluate_expression_with_types(
+    expression: &Expression,
+    row: &HashMap<String, String>,
+    column_types: &HashMap<String, ColumnType>,
 ) -> Result<bool, CustomError> {
     match expression {
         Expression::True => Ok(true),
         Expression::And { left, right } => {
-            let left_result = evaluate_expression(left, row)?;
-            let right_result = evaluate_expression(right, row)?;
+            let left_result = evaluate_expression_with_types(left, row, column_types)?;
+            let right_result = evaluate_expression_with_types(right, row, column_types)?;
             Ok(left_result && right_result)
         }
         Expression::Or { left, right } => {
-            let left_result = evaluate_expression(left, row)?;
-            let right_result = evaluate_expression(right, row)?;
+            let left_result = evaluate_expression_with_types(left, row, column_types)?;
+            let right_result = evaluate_expression_with_types(right, row, column_types)?;
             Ok(left_result || right_result)
         }
         Expression::Not { right } => {
-            let right_result = evaluate_expression(right, row)?;
+            let right_result = evaluate_expression_with_types(right, row, column_types)?;
             Ok(!right_result)
         }
         Expression::Comparison {
@@ -64,26 +86,31 @@ pub fn evaluate_expression(
         } => {
             let left_value = evaluate_operand(left, row)?;
             let right_value = evaluate_operand(right, row)?;
-            if let Ok(left_number) = str_to_number(&left_value) {
-                if let Ok(right_number) = str_to_number(&right_value) {
-                    return match operator.as_str() {
-                        "=" => Ok(left_number == right_number),
-                        ">" => Ok(left_number > right_number),
-                        "<" => Ok(left_number < right_number),
-                        ">=" => Ok(left_number >= right_number),
-                        "<=" => Ok(left_number <= right_number),
-                        _ => Err(CustomError::GenericError {
-                            message: format!("Invalid operator: {}", operator),
-                        }),
-                    };
-                }
-            }
+            // El tipo de la comparación es el de la columna comparada
+            let typed_ordering = [left, right]
+                .into_iter()
+                .find_map(|operand| match operand {
+                    Operand::Column(column) => column_types.get(column),
+                    _ => None,
+                })
+                .and_then(|column_type| {
+                    column_type
+                        .value_of(&left_value)
+                        .compare(&column_type.value_of(&right_value))
+                });
+            let ordering = match typed_ordering {
+                Some(ordering) => ordering,
+                None => match (str_to_number(&left_value), str_to_number(&right_value)) {
+                    (Ok(left_number), Ok(right_number)) => left_number.cmp(&right_number),
+                    _ => left_value.cmp(&right_value),
+                },
+            };
             match operator.as_str() {
-                "=" => Ok(left_value == right_value),
-                ">" => Ok(left_value > right_value),
-                "<" => Ok(left_value < right_value),
-                ">=" => Ok(left_value >= right_value),
-                "<=" => Ok(left_value <= right_value),
+                "=" => Ok(ordering.is_eq()),
+                ">" => Ok(ordering.is_gt()),
+                "<" => Ok(ordering.is_lt()),
+                ">=" => Ok(ordering.is_ge()),
+                "<=" => Ok(ordering.is_le()),
                 _ => Err(CustomError::GenericError {
                     message: format!("Invalid operator: {}", operator),
                 }),
@@ -92,59 +119,40 @@ pub fn evaluate_expression(
     }
 }
 
-
-/// Returns value given an expression "column = value" or "column = value AND ~".
+/// Returns the values of every "column = value" comparison joined by AND in an expression,
+/// e.g. the partition key columns of a WHERE clause.
 ///
 /// #Parameters
-/// - `expression`: Contains the expression with the comparison.
+/// - `expression`: Contains the expression with the comparisons.
 ///
 /// #Returns
-/// - Value
+/// - HashMap from column name to the value it must be equal to
 ///
-pub fn extract_value_supposing_column_equals_value(expression: &Expression) -> Option<String> {
+pub fn extract_equalities(expression: &Expression) -> HashMap<String, String> {
+    let mut equalities = HashMap::new();
+    collect_equalities(expression, &mut equalities);
+    equalities
+}
+
+fn collect_equalities(expression: &Expression, equalities: &mut HashMap<String, String>) {
     match expression {
-        Expression::Comparison {
-            left: Operand::Column(_column_name),
-            operator,
-            right: Operand::String(value),
-        } => {
-            if operator == "=" {
-                return Some(value.clone());
-            }
+        Expression::And { left, right } => {
+            collect_equalities(left, equalities);
+            collect_equalities(right, equalities);
         }
         Expression::Comparison {
-            left: Operand::Column(_column_name),
+            left,
             operator,
-            right: Operand::Integer(value),
-        } => {
-            if operator == "=" {
-                return Some(value.clone());
-            }
-        }
-        Expression::And { left, .. } => match &**left {
-            Expression::Comparison {
-                left: Operand::Column(_column_name),
-                operator,
-                right: Operand::String(value),
-            } => {
-                if operator == "=" {
-                    return Some(value.clone());
-                }
-            }
-            Expression::Comparison {
-                left: Operand::Column(_column_name),
-                operator,
-                right: Operand::Integer(value),
-            } => {
-                if operator == "=" {
-                    return Some(value.clone());
-                }
+            right,
+        } if operator == "=" => match (left, right) {
+            (Operand::Column(column), Operand::String(value) | Operand::Integer(value))
+            | (Operand::String(value) | Operand::Integer(value), Operand::Column(column)) => {
+                equalities.insert(column.clone(), value.clone());
             }
             _ => {}
         },
         _ => {}
     }
-    None
 }
 
 fn str_to_number(s: &str) -> Result<i32, CustomError> {
@@ -227,4 +235,68 @@ mod tests {
         };
         assert!(evaluate_expression(&expression, &row).unwrap());
     }
+
+    #[test]
+    fn test_evaluate_expression_with_the_types_of_the_columns() {
+        let mut row = HashMap::new();
+        row.insert("speed".to_string(), "1000".to_string());
+        row.insert("departure".to_string(), "2024-05-01 10:30:00".to_string());
+        row.insert("gate".to_string(), "10".to_string());
+        let column_types = HashMap::from([
+            ("speed".to_string(), ColumnType::Bigint),
+            ("departure".to_string(), ColumnType::Timestamp),
+            ("gate".to_string(), ColumnType::Text),
+        ]);
+        let comparison = |column: &str, operator: &str, value: &str| Expression::Comparison {
+            left: Operand::Column(column.to_string()),
+            operator: operator.to_string(),
+            right: Operand::String(value.to_string()),
+        };
+
+        let faster = comparison("speed", ">", "900");
+        assert!(evaluate_expression_with_types(&faster, &row, &column_types).unwrap());
+        let faster = comparison("speed", ">=", "5000000000");
+        assert!(!evaluate_expression_with_types(&faster, &row, &column_types).unwrap());
+        // Sin el tipo, un número que no entra en un INT se compara como texto
+        let mut fast_row = row.clone();
+        fast_row.insert("speed".to_string(), "10000000000".to_string());
+        assert!(evaluate_expression_with_types(&faster, &fast_row, &column_types).unwrap());
+        assert!(!evaluate_expression(&faster, &fast_row).unwrap());
+
+        let earlier = comparison("departure", "<", "2024-05-01T11:00:00Z");
+        assert!(evaluate_expression_with_types(&earlier, &row, &column_types).unwrap());
+
+        // Una columna de texto se compara como texto
+        let gate = comparison("gate", "<", "9");
+        assert!(evaluate_expression_with_types(&gate, &row, &column_types).unwrap());
+        assert!(!evaluate_expression(&gate, &row).unwrap());
+    }
+
+    #[test]
+    fn test_extract_equalities_of_composite_key() {
+        let expression = Expression::And {
+            left: Box::new(Expression::Comparison {
+                left: Operand::Column("origin".to_string()),
+                operator: "=".to_string(),
+                right: Operand::String("EZE".to_string()),
+            }),
+            right: Box::new(Expression::And {
+                left: Box::new(Expression::Comparison {
+                    left: Operand::Integer("2024".to_string()),
+                    operator: "=".to_string(),
+                    right: Operand::Column("year".to_string()),
+                }),
+                right: Box::new(Expression::Comparison {
+                    left: Operand::Column("seat".to_string()),
+                    operator: ">".to_string(),
+                    right: Operand::Integer("10".to_string()),
+                }),
+            }),
+        };
+
+        let equalities = extract_equalities(&expression);
+        assert_eq!(equalities.len(), 2);
+        assert_eq!(equalities.get("origin"), Some(&"EZE".to_string()));
+        assert_eq!(equalities.get("year"), Some(&"2024".to_string()));
+    }
 }
@@ -29,11 +29,18 @@ pub enum ParsedQuery {
         table_name: String,
         columns_in_order: Vec<String>,
         rows_to_insert: Vec<HashMap<String, String>>,
+        /// INSERT ... IF NOT EXISTS: only applied if the row doesn't exist (lightweight transaction)
+        if_not_exists: bool,
     },
     Update {
         table_name: String,
         values_to_update: HashMap<String, String>,
         condition: Expression,
+        /// UPDATE ... IF <condition>: only applied if the current row matches it (lightweight
+        /// transaction). IF EXISTS is `Expression::True`, applied if the row exists.
+        if_condition: Option<Expression>,
+        /// SET c = c + n (or c - n) on counter columns: the amount each counter changes
+        counter_increments: HashMap<String, i64>,
     },
     Delete {
         table_name: String,
@@ -44,6 +51,7 @@ pub enum ParsedQuery {
         columns: Vec<String>,
         condition: Expression,
         order_by: Vec<(String, String)>,
+        limit: Option<usize>,
     },
     UseKeyspace {
         keyspace_name: String,
@@ -158,6 +166,23 @@ impl ParsedQuery {
         }
     }
 
+    /// Returns whether the query is a lightweight transaction, that has to be applied with Paxos
+    pub fn is_conditional(&self) -> bool {
+        match self {
+            Self::Insert { if_not_exists, .. } => *if_not_exists,
+            Self::Update { if_condition, .. } => if_condition.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Returns the limit of the query
+    pub fn get_limit(&self) -> Result<Option<usize>, String> {
+        match self {
+            Self::Select { limit, .. } => Ok(*limit),
+            _ => Err("No limit found".to_string()),
+        }
+    }
+
     /// Returns the rows to insert of the query
     pub fn get_rows_to_insert(&self) -> Result<Vec<HashMap<String, String>>, String> {
         match self {
@@ -170,6 +195,15 @@ impl ParsedQuery {
     }
 }
 
+/// Parses a query from its bytes, as they arrive from a client or another node. Malformed
+/// queries, including bytes that are not UTF-8, are returned as errors and never panic.
+pub fn try_parse(bytes: &[u8]) -> Result<ParsedQuery, CustomError> {
+    let query_string = std::str::from_utf8(bytes).map_err(|e| CustomError::InvalidSyntax {
+        message: format!("The query is not valid UTF-8: {}", e),
+    })?;
+    parse_instruction(query_string)
+}
+
 // Given a string, returns a vector of exploded instructions
 pub fn parse_instruction(query_string: &str) -> Result<ParsedQuery, CustomError> {
     let tokens = tokenize(query_string)?;
@@ -230,14 +264,15 @@ fn parse_use(tokens: &[Token]) -> Result<ParsedQuery, CustomError> {
 // Functions used to parse INSERT
 
 fn parse_insert(tokens: &[Token]) -> Result<ParsedQuery, CustomError> {
-    let (table_name, columns_in_order, rows) = parse_insert_variables(tokens)?;
+    let (table_name, columns_in_order, rows, if_not_exists) = parse_insert_variables(tokens)?;
     Ok(ParsedQuery::Insert {
         table_name: table_name.clone(),
         columns_in_order,
         rows_to_insert: rows,
+        if_not_exists,
     })
 }
-type QueryResult = Result<(String, Vec<String>, Vec<HashMap<String, String>>), CustomError>;
+type QueryResult = Result<(String, Vec<String>, Vec<HashMap<String, String>>, bool), CustomError>;
 
 fn parse_insert_variables(
     tokens: &[Token],
@@ -247,8 +282,26 @@ fn parse_insert_variables(
     let table_name = parse_insert_into(&mut iter)?;
     let columns = parse_insert_columns(&mut iter)?;
     let rows = parse_insert_values(&mut iter, &columns)?;
+    let if_not_exists = parse_if_not_exists(&mut iter)?;
+    if if_not_exists && rows.len() > 1 {
+        CustomError::error_invalid_syntax("IF NOT EXISTS only supports a single row")?;
+    }
     check_ending_with_semicolon(&mut iter)?;
-    Ok((table_name, columns, rows))
+    Ok((table_name, columns, rows, if_not_exists))
+}
+
+fn parse_if_not_exists(iter: &mut Peekable<Iter<Token>>) -> Result<bool, CustomError> {
+    if !matches!(iter.peek(), Some(Token::Keyword(keyword)) if keyword.as_str() == "IF") {
+        return Ok(false);
+    }
+    iter.next();
+    if !matches!(iter.next(), Some(Token::LogicalOperator(operator)) if operator.as_str() == "NOT") {
+        CustomError::error_invalid_syntax("Expected NOT after IF")?;
+    }
+    if !matches!(iter.next(), Some(Token::Keyword(keyword)) if keyword.as_str() == "EXISTS") {
+        CustomError::error_invalid_syntax("Expected EXISTS after IF NOT")?;
+    }
+    Ok(true)
 }
 
 fn parse_insert_into(iter: &mut Peekable<Iter<Token>>) -> Result<String, CustomError> {
@@ -544,7 +597,7 @@ fn parse_create_table_columns(
                     // Sino debería ser nombre de columna
                     if let Some(Token::Identifier(column_type)) = iter.next() {
                         // Verifico que haya tipo de dato
-                        if ["TEXT", "BIGINT", "INT", "UUID", "TIMESTAMP", "FLOAT"]
+                        if ["TEXT", "BIGINT", "INT", "UUID", "TIMEUUID", "TIMESTAMP", "FLOAT", "COUNTER"]
                             .contains(&column_type.to_uppercase().as_str())
                         {
                             columns.push((name.to_string(), column_type.to_string()));
@@ -556,7 +609,7 @@ fn parse_create_table_columns(
                                 )?;
                             }
                         } else {
-                            CustomError::error_invalid_syntax(format!("Expected data type after column name, supported data types are: TEXT, BIGINT, INT, UUID, TIMESTAMP, FLOAT. Found: {}", column_type).as_str())?;
+                            CustomError::error_invalid_syntax(format!("Expected data type after column name, supported data types are: TEXT, BIGINT, INT, UUID, TIMEUUID, TIMESTAMP, FLOAT, COUNTER. Found: {}", column_type).as_str())?;
                         }
                     } else {
                         CustomError::error_invalid_syntax("Expected data type after column name")?;
@@ -681,18 +734,22 @@ pub fn parse_create_table_primary_key(
 // Functions used to parse UPDATE
 
 fn parse_update(tokens: &[Token]) -> Result<ParsedQuery, CustomError> {
-    let (table_name, set_values, condition) = parse_update_variables(tokens)?;
+    let (table_name, (set_values, counter_increments), condition, if_condition) =
+        parse_update_variables(tokens)?;
     let query = ParsedQuery::Update {
         table_name: table_name.clone(),
         values_to_update: set_values.clone(),
         condition,
+        if_condition,
+        counter_increments,
     };
     Ok(query)
 }
 
+#[allow(clippy::type_complexity)]
 fn parse_update_variables(
     tokens: &[Token],
-) -> Result<(String, HashMap<String, String>, Expression), CustomError> {
+) -> Result<(String, SetValues, Expression, Option<Expression>), CustomError> {
     let mut table_name = String::new();
 
     let mut iter = tokens.iter().peekable();
@@ -705,34 +762,67 @@ fn parse_update_variables(
     }
     let set_values = parse_update_set_values(&mut iter)?;
     let condition = parse_condition(&mut iter)?;
+    let if_condition = parse_if_condition(&mut iter)?;
     check_ending_with_semicolon(&mut iter)?;
-    Ok((table_name, set_values, condition))
+    Ok((table_name, set_values, condition, if_condition))
 }
 
-fn parse_update_set_values(
-    iter: &mut Peekable<Iter<Token>>,
-) -> Result<HashMap<String, String>, CustomError> {
+fn parse_if_condition(iter: &mut Peekable<Iter<Token>>) -> Result<Option<Expression>, CustomError> {
+    if !matches!(iter.peek(), Some(Token::Keyword(keyword)) if keyword.as_str() == "IF") {
+        return Ok(None);
+    }
+    iter.next();
+    if matches!(iter.peek(), Some(Token::Keyword(keyword)) if keyword.as_str() == "EXISTS") {
+        iter.next();
+        return Ok(Some(Expression::True));
+    }
+    Ok(Some(parse_expression(iter)?))
+}
+
+/// The values set by an UPDATE: the new values of regular columns and the increments of
+/// counter columns.
+type SetValues = (HashMap<String, String>, HashMap<String, i64>);
+
+/// A value in the SET clause of an UPDATE.
+enum SetValue {
+    Value(String),
+    Increment(i64),
+}
+
+fn parse_update_set_values(iter: &mut Peekable<Iter<Token>>) -> Result<SetValues, CustomError> {
     let mut set_values: HashMap<String, String> = HashMap::new();
+    let mut counter_increments: HashMap<String, i64> = HashMap::new();
     if !matches!(iter.next(), Some(Token::Keyword(keyword)) if keyword.as_str() == "SET") {
         // Verifico que haya SET
         CustomError::error_invalid_syntax("Expected SET after table name")?;
     }
-    let (column, value) = parse_update_set_value(iter)?; // Parseo el primer valor
-    set_values.insert(column, value);
-    while let Some(Token::Symbol(',')) = iter.peek() {
-        // Si lo sigue una coma, parseo otro valor
-        iter.next();
-        let (column, value) = parse_update_set_value(iter)?;
-        set_values.insert(column, value);
+    loop {
+        match parse_update_set_value(iter)? {
+            (column, SetValue::Value(value)) => {
+                set_values.insert(column, value);
+            }
+            (column, SetValue::Increment(increment)) => {
+                counter_increments.insert(column, increment);
+            }
+        }
+        if let Some(Token::Symbol(',')) = iter.peek() {
+            // Si lo sigue una coma, parseo otro valor
+            iter.next();
+        } else {
+            break;
+        }
+    }
+    if !set_values.is_empty() && !counter_increments.is_empty() {
+        CustomError::error_invalid_syntax("Counter and regular columns can't be set together")?;
     }
-    Ok(set_values)
+    Ok((set_values, counter_increments))
 }
 
 fn parse_update_set_value(
     iter: &mut Peekable<Iter<Token>>,
-) -> Result<(String, String), CustomError> {
+) -> Result<(String, SetValue), CustomError> {
     let mut column: String = "".to_string();
-    let mut value: String = "".to_string();
+    let mut value = SetValue::Value("".to_string());
     if let Some(Token::Identifier(name)) | Some(Token::String(name)) = iter.next() {
         // Verifico que haya nombre de columna
         column = name.to_string();
@@ -741,11 +831,16 @@ fn parse_update_set_value(
     }
     if matches!(iter.next(), Some(Token::ComparisonOperator(keyword)) if keyword.as_str() == "=") {
         // Verifico que haya '='
-        if let Some(Token::Integer(string)) | Some(Token::String(string)) = iter.next() {
-            // Verifico que haya valor
-            value = string.to_string();
-        } else {
-            CustomError::error_invalid_syntax("Expected value after '='")?;
+        match iter.next() {
+            Some(Token::Integer(string)) | Some(Token::String(string)) => {
+                // Verifico que haya valor
+                value = SetValue::Value(string.to_string());
+            }
+            Some(Token::Identifier(name)) if *name == column => {
+                // Un contador: c = c + n o c = c - n
+                value = SetValue::Increment(parse_counter_increment(iter)?);
+            }
+            _ => CustomError::error_invalid_syntax("Expected value after '='")?,
         }
     } else {
         CustomError::error_invalid_syntax("Expected '=' after column name")?;
@@ -753,6 +848,31 @@ fn parse_update_set_value(
     Ok((column, value))
 }
 
+fn parse_counter_increment(iter: &mut Peekable<Iter<Token>>) -> Result<i64, CustomError> {
+    // El tokenizer toma el '-' como parte de una palabra: "- 3" o "-3"
+    let (sign, amount) = match iter.next() {
+        Some(Token::Symbol('+')) => (1, None),
+        Some(Token::Identifier(word)) if word == "-" => (-1, None),
+        Some(Token::Identifier(word)) if word.starts_with('-') => (-1, Some(&word[1..])),
+        _ => return Err(CustomError::InvalidSyntax {
+            message: "Expected + or - after counter column".to_string(),
+        }),
+    };
+    let amount = match amount {
+        Some(amount) => amount,
+        None => match iter.next() {
+            Some(Token::Integer(amount)) => amount.as_str(),
+            _ => "",
+        },
+    };
+    match amount.parse::<i64>() {
+        Ok(amount) => Ok(sign * amount),
+        Err(_) => Err(CustomError::InvalidSyntax {
+            message: format!("Invalid counter increment: {}", amount),
+        }),
+    }
+}
+
 // Functions used to parse DELETE
 
 fn parse_delete(tokens: &[Token]) -> Result<ParsedQuery, CustomError> {
@@ -785,27 +905,29 @@ fn parse_delete_variables(tokens: &[Token]) -> Result<(String, Expression), Cust
 // Functions used to parse SELECT
 
 fn parse_select(tokens: &[Token]) -> Result<ParsedQuery, CustomError> {
-    let (table_name, columns, condition, order_by) = parse_select_variables(tokens)?;
+    let (table_name, columns, condition, order_by, limit) = parse_select_variables(tokens)?;
     Ok(ParsedQuery::Select {
         table_name,
         columns,
         condition,
         order_by,
+        limit,
     })
 }
 
 #[allow(clippy::type_complexity)]
 fn parse_select_variables(
     tokens: &[Token],
-) -> Result<(String, Vec<String>, Expression, Vec<(String, String)>), CustomError> {
+) -> Result<(String, Vec<String>, Expression, Vec<(String, String)>, Option<usize>), CustomError> {
     let mut iter = tokens.iter().peekable();
     iter.next(); // salteo el SELECT
     let columns = parse_select_columns(&mut iter)?;
     let table_name = parse_select_from(&mut iter)?;
     let condition = parse_condition(&mut iter)?;
     let order_by = parse_order_by(&mut iter)?;
+    let limit = parse_limit(&mut iter)?;
     check_ending_with_semicolon(&mut iter)?;
-    Ok((table_name, columns, condition, order_by))
+    Ok((table_name, columns, condition, order_by, limit))
 }
 
 fn parse_select_columns(iter: &mut Peekable<Iter<Token>>) -> Result<Vec<String>, CustomError> {
@@ -885,6 +1007,23 @@ fn parse_order_by(iter: &mut Peekable<Iter<Token>>) -> Result<Vec<(String, Strin
     Ok(order_by)
 }
 
+fn parse_limit(iter: &mut Peekable<Iter<Token>>) -> Result<Option<usize>, CustomError> {
+    if !matches!(iter.peek(), Some(Token::Keyword(keyword)) if keyword.as_str() == "LIMIT") {
+        // Si no hay LIMIT, se devuelven todas las filas
+        return Ok(None);
+    }
+    iter.next();
+    if let Some(Token::Integer(value)) = iter.next() {
+        if let Ok(limit) = value.parse::<usize>() {
+            if limit > 0 {
+                return Ok(Some(limit));
+            }
+        }
+    }
+    CustomError::error_invalid_syntax("Expected a positive integer after LIMIT")?;
+    Ok(None)
+}
+
 fn parse_order_by_column(
     iter: &mut Peekable<Iter<Token>>,
 ) -> Result<(String, String), CustomError> {
@@ -944,7 +1083,7 @@ fn parse_condition(iter: &mut Peekable<Iter<Token>>) -> Result<Expression, Custo
 
 #[cfg(test)]
 mod tests {
-    use expression::Operand;
+    use expression::{extract_equalities, Operand};
 
     //use super::expression::Operand;
     use super::*;
@@ -957,6 +1096,7 @@ mod tests {
             table_name,
             columns_in_order,
             rows_to_insert,
+            ..
         } = &instruction
         {
             assert_eq!(table_name, "table1");
@@ -977,6 +1117,66 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_insert_if_not_exists() {
+        let query = "INSERT INTO table1 (column1) VALUES (1) IF NOT EXISTS;";
+        let instruction = parse_instruction(query).unwrap();
+        assert!(instruction.is_conditional());
+        assert!(matches!(instruction, ParsedQuery::Insert { if_not_exists: true, .. }));
+
+        let query = "INSERT INTO table1 (column1) VALUES (1), (2) IF NOT EXISTS;";
+        assert!(parse_instruction(query).is_err());
+        let query = "INSERT INTO table1 (column1) VALUES (1) IF EXISTS;";
+        assert!(parse_instruction(query).is_err());
+    }
+
+    #[test]
+    fn test_parse_update_if_condition() {
+        let query = "UPDATE table1 SET column2 = 'b' WHERE column1 = 1 IF column2 = 'a';";
+        if let ParsedQuery::Update {
+            condition,
+            if_condition,
+            ..
+        } = parse_instruction(query).unwrap()
+        {
+            assert_eq!(extract_equalities(&condition)["column1"], "1");
+            assert_eq!(extract_equalities(&if_condition.unwrap())["column2"], "a");
+        } else {
+            panic!("Expected Update instruction");
+        }
+
+        let query = "UPDATE table1 SET column2 = 'b' WHERE column1 = 1 IF EXISTS;";
+        assert!(matches!(
+            parse_instruction(query).unwrap(),
+            ParsedQuery::Update { if_condition: Some(Expression::True), .. }
+        ));
+        let query = "UPDATE table1 SET column2 = 'b' WHERE column1 = 1;";
+        assert!(!parse_instruction(query).unwrap().is_conditional());
+    }
+
+    #[test]
+    fn test_parse_update_counter_increments() {
+        let query = "UPDATE table1 SET seats = seats + 3, sold = sold - 2, left = left -1 WHERE id = 1;";
+        if let ParsedQuery::Update {
+            values_to_update,
+            counter_increments,
+            ..
+        } = parse_instruction(query).unwrap()
+        {
+            assert!(values_to_update.is_empty());
+            assert_eq!(counter_increments["seats"], 3);
+            assert_eq!(counter_increments["sold"], -2);
+            assert_eq!(counter_increments["left"], -1);
+        } else {
+            panic!("Expected Update instruction");
+        }
+
+        let query = "UPDATE table1 SET seats = seats + 3, name = 'a' WHERE id = 1;";
+        assert!(parse_instruction(query).is_err());
+        let query = "UPDATE table1 SET seats = other + 3 WHERE id = 1;";
+        assert!(parse_instruction(query).is_err());
+    }
+
     #[test]
     fn test_parse_insert_invalid_syntax() {
         let query = "INSERT INTO table1 (column1, column2) VALUES (1, 'value1', 2, 'value2');";
@@ -1036,4 +1236,75 @@ mod tests {
             panic!("Expected Delete instruction");
         }
     }
+
+    #[test]
+    fn test_parse_select_with_order_by_and_limit() {
+        let query = "SELECT * FROM table1 WHERE column1 = 1 ORDER BY column2 DESC LIMIT 10;";
+        let instruction = parse_instruction(query).unwrap();
+        assert_eq!(
+            instruction.get_order_by().unwrap(),
+            vec![("column2".to_string(), "DESC".to_string())]
+        );
+        assert_eq!(instruction.get_limit().unwrap(), Some(10));
+
+        assert!(parse_instruction("SELECT * FROM table1 LIMIT 0;").is_err());
+        assert!(parse_instruction("SELECT * FROM table1 LIMIT;").is_err());
+    }
+
+    #[test]
+    fn test_try_parse_rejects_malformed_bytes() {
+        let query = "SELECT * FROM flights WHERE id = 1;";
+        assert_eq!(try_parse(query.as_bytes()), parse_instruction(query));
+        assert!(matches!(
+            try_parse(b"SELECT * FROM \xFF;"),
+            Err(CustomError::InvalidSyntax { .. })
+        ));
+        assert!(try_parse(b"SELECT * FROM flights WHERE (").is_err());
+        assert!(try_parse(b"").is_err());
+    }
+
+    #[test]
+    fn test_parse_create_table_with_timeuuid() {
+        let query = "CREATE TABLE flight_events (flight_id INT, at TEXT, event_id TIMEUUID, kind TEXT, PRIMARY KEY ((flight_id), at, event_id));";
+        let instruction = parse_instruction(query).unwrap();
+        if let ParsedQuery::CreateTable {
+            columns,
+            clustering_key_columns,
+            ..
+        } = &instruction
+        {
+            assert!(columns.contains(&("event_id".to_string(), "TIMEUUID".to_string())));
+            assert_eq!(clustering_key_columns, &vec!["at".to_string(), "event_id".to_string()]);
+        } else {
+            panic!("Expected CreateTable instruction");
+        }
+    }
+
+    #[test]
+    fn test_parse_create_table_with_counter() {
+        let query = "CREATE TABLE sold_seats (flight_id INT, class TEXT, sold COUNTER, PRIMARY KEY ((flight_id), class));";
+        let Ok(ParsedQuery::CreateTable { columns, .. }) = parse_instruction(query) else {
+            panic!("Expected CreateTable instruction");
+        };
+        assert!(columns.contains(&("sold".to_string(), "COUNTER".to_string())));
+    }
+
+    #[test]
+    fn test_parse_the_tables_of_the_boards() {
+        let tables = common::models::board::TABLES
+            .iter()
+            .chain(common::models::flight_filter::TABLES.iter());
+        for query in tables {
+            let Ok(ParsedQuery::CreateTable {
+                partition_key_columns,
+                clustering_key_columns,
+                ..
+            }) = parse_instruction(query)
+            else {
+                panic!("Expected CreateTable instruction: {}", query);
+            };
+            assert_eq!(partition_key_columns.len(), 2);
+            assert_eq!(clustering_key_columns, vec!["departure", "flight_id"]);
+        }
+    }
 }
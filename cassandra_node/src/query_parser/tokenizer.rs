@@ -6,7 +6,7 @@ use std::str::Chars;
 /// Los Tokens son la unidad mínima de un comando SQL que existen para facilitar su parseo.
 pub enum Token {
     /// Los Keywords son palabras clave de un comando SQL, esta implementación incluye:
-    /// INSERT, UPDATE, DELETE, SELECT, FROM, WHERE, SET, INTO, VALUES, ORDER, BY, DESC, ASC, CREATE, TABLE, WITH, REPLICATION, KEYSPACE
+    /// INSERT, UPDATE, DELETE, SELECT, FROM, WHERE, SET, INTO, VALUES, ORDER, BY, LIMIT, DESC, ASC, CREATE, TABLE, WITH, REPLICATION, KEYSPACE
     Keyword(String),
     /// Los LogicalOperators son operadores lógicos, en esta implementación incluye:
     /// AND, OR, NOT
@@ -21,7 +21,7 @@ pub enum Token {
     /// Los Integers son números enteros.
     Integer(String),
     /// Los Symbols son caracteres especiales, en esta implementación incluye:
-    /// , ( ) : ; * { } =
+    /// , ( ) : ; * { } = +
     Symbol(char),
 }
 
@@ -57,8 +57,8 @@ fn tokenize_integer_or_identifier_starting_with_integer(chars: &mut Peekable<Cha
 fn tokenize_word(chars: &mut Peekable<Chars>) -> Token {
     let mut word = String::new();
     while let Some(&ch) = chars.peek() {
-        // se agrega al string hasta que no sea alfanumérico
-        if ch.is_alphanumeric() || ch == '_' || ch == '-' {
+        // se agrega al string hasta que no sea alfanumérico; el punto separa keyspace y tabla
+        if ch.is_alphanumeric() || ch == '_' || ch == '-' || ch == '.' {
             word.push(ch);
             chars.next();
         } else {
@@ -78,6 +78,7 @@ fn tokenize_word(chars: &mut Peekable<Chars>) -> Token {
         "VALUES",
         "ORDER",
         "BY",
+        "LIMIT",
         "CREATE",
         "TABLE",
         "DESC",
@@ -86,6 +87,8 @@ fn tokenize_word(chars: &mut Peekable<Chars>) -> Token {
         "REPLICATION",
         "KEYSPACE",
         "USE",
+        "IF",
+        "EXISTS",
     ]
     .contains(&word_upper.as_str())
     // si es una palabra clave se retorna un Token::Keyword
@@ -110,6 +113,12 @@ fn tokenize_string(chars: &mut Peekable<Chars>) -> Token {
             chars.next();
         } else {
             chars.next();
+            // dos comillas seguidas son una comilla dentro del string
+            if chars.peek() == Some(&'\'') {
+                string.push(ch);
+                chars.next();
+                continue;
+            }
             break;
         }
     }
@@ -163,7 +172,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, CustomError> {
             tokens.push(tokenize_string(&mut chars)); // strings
         } else if ['=', '>', '<'].contains(&ch) {
             tokens.push(tokenize_comparison_operator(&mut chars)); // operadores de comparacion
-        } else if [',', '(', ')', ';', '*', '{', '}', ':'].contains(&ch) {
+        } else if [',', '(', ')', ';', '*', '{', '}', ':', '+'].contains(&ch) {
             tokens.push(Token::Symbol(ch)); // símbolos especiales
             chars.next();
         } else {
@@ -194,6 +203,15 @@ mod tests {
         assert_eq!(tokenize(input).unwrap(), expected_output);
     }
 
+    #[test]
+    fn test_tokenize_string_with_escaped_quotes() {
+        let input = "INSERT INTO airports (name) VALUES ('O''Higgins'), ('''');";
+        let tokens = tokenize(input).unwrap();
+        assert_eq!(tokens[8], Token::String("O'Higgins".to_string()));
+        assert_eq!(tokens[12], Token::String("'".to_string()));
+        assert_eq!(tokens.len(), 15);
+    }
+
     #[test]
     fn test_tokenize_with_identifies_starting_with_number() {
         let input = "SELECT * FROM table1 WHERE column1 = 'value1' AND 1column = 'value2';";
@@ -214,4 +232,17 @@ mod tests {
         ];
         assert_eq!(tokenize(input).unwrap(), expected_output);
     }
+
+    #[test]
+    fn test_tokenize_table_name_with_keyspace() {
+        let input = "SELECT * FROM system_schema.columns;";
+        let expected_output = vec![
+            Token::Keyword("SELECT".to_string()),
+            Token::Symbol('*'),
+            Token::Keyword("FROM".to_string()),
+            Token::Identifier("system_schema.columns".to_string()),
+            Token::Symbol(';'),
+        ];
+        assert_eq!(tokenize(input).unwrap(), expected_output);
+    }
 }
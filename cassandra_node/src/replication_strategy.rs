@@ -3,11 +3,13 @@ use std::cmp::min;
 use crate::consistent_hashing::ConsistentHash;
 use crate::node::GossipInformation;
 use rand::{rng, Rng};
+use serde::{Deserialize, Serialize};
 
 /// This enum has simple and random replication strategies.
 /// 
 /// 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "class")]
 pub enum ReplicationStrategy {
     /// SimpleStrategy repilca la query al nodo correspondiente a la partición y a los siguientes nodos en el hash.
     SimpleStrategy { replication_factor: usize },
@@ -15,6 +17,27 @@ pub enum ReplicationStrategy {
     RandomStrategy { replication_factor: usize },
 }
 
+/// What the coordinator of a `CREATE KEYSPACE` does when its replication factor is larger
+/// than the nodes of the cluster.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OversizedReplication {
+    /// La query se rechaza con un error de configuración.
+    Reject,
+    /// El factor de replicación se reduce a la cantidad de nodos del cluster.
+    Clamp,
+}
+
+impl OversizedReplication {
+    /// Parses the policy by its name: REJECT or CLAMP.
+    pub fn from_str_to_enum(s: &str) -> Result<Self, String> {
+        match s.to_uppercase().as_str() {
+            "REJECT" => Ok(OversizedReplication::Reject),
+            "CLAMP" => Ok(OversizedReplication::Clamp),
+            _ => Err("Política de replicación inválida".to_string()),
+        }
+    }
+}
+
 fn replication_factor_string_to_usize(replication_factor: String) -> usize {
     match replication_factor.as_str() {
         "1" | "ONE" => 1,
@@ -77,7 +100,7 @@ impl ReplicationStrategy {
     /// A vector of node ids.
     pub fn get_replica_nodes(
         &self,
-        partition_keys: &Vec<String>,
+        partition_keys: &[String],
         gossip_table: &[GossipInformation],
         hash: &ConsistentHash,
     ) -> Vec<String> {
@@ -125,7 +148,35 @@ impl ReplicationStrategy {
         }
     }
 
-   
+    /// Gets the replicas of each token range, in the order of the ranges in the ring.
+    ///
+    /// #Parameters
+    /// - `gossip_table`: Contains gossip information of nodes, one range each.
+    ///
+    /// #Returns
+    /// The replicas of each range, or `None` with RandomStrategy, whose replicas change with
+    /// each query, so any node can hold any partition.
+    pub fn range_replicas(&self, gossip_table: &[GossipInformation]) -> Option<Vec<Vec<String>>> {
+        match self {
+            Self::SimpleStrategy { replication_factor } => {
+                let number_of_replicas = min(*replication_factor, gossip_table.len());
+                Some(
+                    (0..gossip_table.len())
+                        .map(|range| {
+                            (0..number_of_replicas)
+                                .map(|offset| {
+                                    gossip_table[(range + offset) % gossip_table.len()]
+                                        .node_id
+                                        .clone()
+                                })
+                                .collect()
+                        })
+                        .collect(),
+                )
+            }
+            Self::RandomStrategy { .. } => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -142,12 +193,20 @@ mod tests_rf {
     use crate::internal_protocol::InternalMessage;
 
     use crate::node::{GossipInformation, Node};
+    use crate::test_cluster::temp_storage;
 
     #[test]
     // testeo el aramado de un simple strategy con rf = 1
     fn creo_2_nodos_con_un_rf_de_1_y_envio_una_query() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 1198, 1199));
-        let nodo2 = Arc::new(Node::new("Nodo2", "localhost", 2298, 2299));
+        let storage = temp_storage("creo_2_nodos_con_un_rf_de_1_y_envio_una_query");
+        let nodo1 = Arc::new(Node::new_with_storage(
+            "Nodo1",
+            "localhost",
+            1198,
+            1199,
+            storage.clone(),
+        ));
+        let nodo2 = Arc::new(Node::new_with_storage("Nodo2", "localhost", 2298, 2299, storage));
 
         let node1_clone = Arc::clone(&nodo1);
 
@@ -202,7 +261,7 @@ mod tests_rf {
         queries.push("CREATE KEYSPACE keyspace1 WITH REPLICATION = { 'class' : 'SimpleStrategy', 'replication_factor' : 1};");
         queries.push("USE keyspace1;");
         queries.push("CREATE TABLE vuelos (id_flight INT, from_city TEXT, to_city TEXT, departure_time TEXT, PRIMARY KEY ((from_city), departure_time));");
-        queries.push("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Rio', 'Catamarca', '21');");
+        queries.push("INSERT INTO vuelos (id_flight, from_city, to_city, departure_time) VALUES (1010, 'Cordoba', 'Catamarca', '21');");
 
         for query in queries {
             if let Ok(mut stream) = TcpStream::connect("localhost:2298") {
@@ -218,7 +277,7 @@ mod tests_rf {
 
         let mut values_vuelos1 = HashMap::new();
         values_vuelos1.insert("id_flight".to_string(), "1010".to_string());
-        values_vuelos1.insert("from_city".to_string(), "Rio".to_string());
+        values_vuelos1.insert("from_city".to_string(), "Cordoba".to_string());
         values_vuelos1.insert("to_city".to_string(), "Catamarca".to_string());
         values_vuelos1.insert("departure_time".to_string(), "21".to_string());
 
@@ -239,8 +298,15 @@ mod tests_rf {
     #[test]
     // testeo el aramado de un simple strategy con rf = 2
     fn creo_2_nodos_con_un_rf_de_2_y_envio_varios_inserts() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 8001, 8002));
-        let nodo2 = Arc::new(Node::new("Nodo2", "localhost", 9001, 9002));
+        let storage = temp_storage("creo_2_nodos_con_un_rf_de_2_y_envio_varios_inserts");
+        let nodo1 = Arc::new(Node::new_with_storage(
+            "Nodo1",
+            "localhost",
+            8001,
+            8002,
+            storage.clone(),
+        ));
+        let nodo2 = Arc::new(Node::new_with_storage("Nodo2", "localhost", 9001, 9002, storage));
 
         let node1_clone = Arc::clone(&nodo1);
 
@@ -352,11 +418,36 @@ mod tests_rf {
     // testeo el aramado de un random strategy con rf = 5
 
     fn creo_5_nodos_con_un_rf_de_5_y_envio_varios_inserts() {
-        let nodo1 = Arc::new(Node::new("Nodo1", "localhost", 11112, 11113));
-        let nodo2 = Arc::new(Node::new("Nodo2", "localhost", 22223, 22224));
-        let nodo3 = Arc::new(Node::new("Nodo3", "localhost", 33334, 33335));
-        let nodo4 = Arc::new(Node::new("Nodo4", "localhost", 44445, 44449));
-        let nodo5 = Arc::new(Node::new("Nodo5", "localhost", 55556, 55557));
+        let storage = temp_storage("creo_5_nodos_con_un_rf_de_5_y_envio_varios_inserts");
+        let nodo1 = Arc::new(Node::new_with_storage(
+            "Nodo1",
+            "localhost",
+            11112,
+            11113,
+            storage.clone(),
+        ));
+        let nodo2 = Arc::new(Node::new_with_storage(
+            "Nodo2",
+            "localhost",
+            22223,
+            22224,
+            storage.clone(),
+        ));
+        let nodo3 = Arc::new(Node::new_with_storage(
+            "Nodo3",
+            "localhost",
+            33334,
+            33335,
+            storage.clone(),
+        ));
+        let nodo4 = Arc::new(Node::new_with_storage(
+            "Nodo4",
+            "localhost",
+            44445,
+            44449,
+            storage.clone(),
+        ));
+        let nodo5 = Arc::new(Node::new_with_storage("Nodo5", "localhost", 55556, 55557, storage));
 
         let node1_clone = Arc::clone(&nodo1);
 
@@ -0,0 +1,504 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use common::config::FsyncPolicy;
+
+/// Magic number that starts every file persisted by a node.
+pub const MAGIC: [u8; 4] = *b"RCDB";
+
+/// Current version of the storage format. Bump it and register a migration in
+/// `MIGRATIONS` whenever the layout of a persisted file changes.
+pub const CURRENT_VERSION: u16 = 4;
+
+/// Length of the header: magic number, file kind and version.
+const HEADER_LEN: usize = MAGIC.len() + 1 + 2;
+
+/// When `write_file` syncs the files, set at startup from the `[storage]` section.
+static FSYNC_POLICY: Mutex<FsyncPolicy> = Mutex::new(FsyncPolicy::Always);
+
+/// Files written with the periodic policy that weren't synced yet.
+static UNSYNCED_FILES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// The kinds of files a node persists in its data directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Table = 1,
+    Keyspaces = 2,
+    Gossip = 3,
+    Hints = 4,
+    Paxos = 5,
+    Counters = 6,
+    Cleanups = 7,
+    CommitLog = 8,
+}
+
+impl FileKind {
+    fn from_byte(byte: u8) -> Option<FileKind> {
+        match byte {
+            1 => Some(FileKind::Table),
+            2 => Some(FileKind::Keyspaces),
+            3 => Some(FileKind::Gossip),
+            4 => Some(FileKind::Hints),
+            5 => Some(FileKind::Paxos),
+            6 => Some(FileKind::Counters),
+            7 => Some(FileKind::Cleanups),
+            8 => Some(FileKind::CommitLog),
+            _ => None,
+        }
+    }
+}
+
+/// A migration upgrades the payload of a file of the given kind from `from_version`
+/// to `to_version`.
+struct Migration {
+    kind: Option<FileKind>, // None aplica a todos los tipos de archivo
+    from_version: u16,
+    to_version: u16,
+    migrate: fn(Vec<u8>) -> Result<Vec<u8>, String>,
+}
+
+/// Registered migrations, applied in order until the payload reaches `CURRENT_VERSION`.
+const MIGRATIONS: &[Migration] = &[
+    // Version 0 de las tablas: encriptadas con el cifrado anterior a AES-GCM.
+    Migration {
+        kind: Some(FileKind::Table),
+        from_version: 0,
+        to_version: 2,
+        migrate: crate::encrypted_table::migrate_legacy_table,
+    },
+    // Version 0 del resto: archivos sin header, escritos antes de versionar el formato.
+    // El contenido no cambió, solo se le agrega el header.
+    Migration {
+        kind: None,
+        from_version: 0,
+        to_version: 1,
+        migrate: Ok,
+    },
+    // Version 1 de las tablas: checksum sin clave y particiones sin atar a su id.
+    Migration {
+        kind: Some(FileKind::Table),
+        from_version: 1,
+        to_version: 2,
+        migrate: crate::encrypted_table::migrate_v1_table,
+    },
+    // Version 1 del resto: el contenido no cambió.
+    Migration {
+        kind: None,
+        from_version: 1,
+        to_version: 2,
+        migrate: Ok,
+    },
+    // Version 2 de los keyspaces: una línea `nombre,estrategia,rf` por keyspace.
+    Migration {
+        kind: Some(FileKind::Keyspaces),
+        from_version: 2,
+        to_version: 3,
+        migrate: crate::data_parser::migrate_csv_keyspaces,
+    },
+    // Version 2 del resto: el contenido no cambió.
+    Migration {
+        kind: None,
+        from_version: 2,
+        to_version: 3,
+        migrate: Ok,
+    },
+    // Version 3 de los hints: todos sellados juntos en vez de un registro por hint.
+    Migration {
+        kind: Some(FileKind::Hints),
+        from_version: 3,
+        to_version: 4,
+        migrate: crate::hints::migrate_sealed_hints,
+    },
+    // Version 3 del resto: el contenido no cambió.
+    Migration {
+        kind: None,
+        from_version: 3,
+        to_version: 4,
+        migrate: Ok,
+    },
+];
+
+/// Prepends the header of the current version to `payload`.
+///
+/// # Parameters
+/// - `kind`: The kind of file.
+/// - `payload`: The content of the file.
+///
+/// # Returns
+/// The bytes to write to disk.
+pub fn encode(kind: FileKind, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(kind as u8);
+    bytes.extend_from_slice(&CURRENT_VERSION.to_be_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Reads the header of a file and upgrades its payload to the current version.
+/// Files without a header are treated as version 0.
+///
+/// # Parameters
+/// - `kind`: The kind of file expected.
+/// - `bytes`: The content of the file.
+///
+/// # Returns
+/// - `Ok((payload, migrated))` with the payload in the current format and whether it had to be
+///   migrated, or `Err(String)` if the file is of another kind, is from a newer version or a
+///   migration failed.
+pub fn decode(kind: FileKind, bytes: &[u8]) -> Result<(Vec<u8>, bool), String> {
+    let (version, payload) = if bytes.len() >= HEADER_LEN && bytes[..MAGIC.len()] == MAGIC {
+        let file_kind = FileKind::from_byte(bytes[MAGIC.len()]);
+        if file_kind != Some(kind) {
+            return Err(format!(
+                "Expected a {:?} file, found kind {}",
+                kind,
+                bytes[MAGIC.len()]
+            ));
+        }
+        let version = u16::from_be_bytes([bytes[MAGIC.len() + 1], bytes[MAGIC.len() + 2]]);
+        (version, bytes[HEADER_LEN..].to_vec())
+    } else {
+        (0, bytes.to_vec())
+    };
+
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "Storage format version {} is newer than the supported version {}",
+            version, CURRENT_VERSION
+        ));
+    }
+
+    let migrated = version < CURRENT_VERSION;
+    let mut payload = payload;
+    let mut version = version;
+    while version < CURRENT_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| {
+                migration.from_version == version
+                    && migration.kind.is_none_or(|migration_kind| migration_kind == kind)
+            })
+            .ok_or(format!(
+                "No migration for {:?} files from version {}",
+                kind, version
+            ))?;
+        payload = (migration.migrate)(payload)?;
+        version = migration.to_version;
+    }
+    Ok((payload, migrated))
+}
+
+/// Sets when the files written by every node of the process are synced to disk.
+pub fn set_fsync_policy(policy: FsyncPolicy) {
+    if let Ok(mut fsync_policy) = FSYNC_POLICY.lock() {
+        *fsync_policy = policy;
+    }
+}
+
+fn fsync_policy() -> FsyncPolicy {
+    FSYNC_POLICY
+        .lock()
+        .map_or(FsyncPolicy::Always, |policy| *policy)
+}
+
+/// Writes `payload` with the current header through a temporary file renamed over `path`.
+/// With the `always` fsync policy the temporary file is synced before the rename, so a crash
+/// never leaves a half written file behind; with `periodic` it is synced by
+/// `sync_written_files`, and with `never` it is left to the OS.
+///
+/// # Parameters
+/// - `path`: The path of the file.
+/// - `kind`: The kind of file.
+/// - `payload`: The content of the file.
+///
+/// # Returns
+/// An `io::Result` indicating the success of the operation.
+pub fn write_file(path: &str, kind: FileKind, payload: &[u8]) -> io::Result<()> {
+    write_file_with(path, kind, payload, fsync_policy())
+}
+
+fn write_file_with(
+    path: &str,
+    kind: FileKind,
+    payload: &[u8],
+    fsync: FsyncPolicy,
+) -> io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = format!("{}.tmp", path);
+    let mut temp = File::create(&temp_path)?;
+    temp.write_all(&encode(kind, payload))?;
+    if fsync == FsyncPolicy::Always {
+        temp.sync_all()?;
+    }
+    fs::rename(temp_path, path)?;
+    if fsync == FsyncPolicy::Periodic {
+        if let Ok(mut unsynced) = UNSYNCED_FILES.lock() {
+            unsynced.insert(path.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Encodes `records` as the payload of a log file: each one prefixed by its length.
+pub fn encode_records(records: &[Vec<u8>]) -> Vec<u8> {
+    let mut payload = vec![];
+    for record in records {
+        payload.extend_from_slice(&(record.len() as u32).to_be_bytes());
+        payload.extend_from_slice(record);
+    }
+    payload
+}
+
+/// Splits the payload of a log file into its records. A last record cut short, left by a
+/// crash while it was appended, is dropped.
+fn decode_records(payload: &[u8]) -> Vec<Vec<u8>> {
+    let mut records = vec![];
+    let mut rest = payload;
+    while rest.len() >= 4 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if rest.len() - 4 < len {
+            break;
+        }
+        records.push(rest[4..4 + len].to_vec());
+        rest = &rest[4 + len..];
+    }
+    records
+}
+
+/// Appends a record to a log file, creating it with the current header if it doesn't exist.
+/// A file of an older version is migrated before appending to it. The record is synced
+/// according to the fsync policy.
+///
+/// # Parameters
+/// - `path`: The path of the file.
+/// - `kind`: The kind of file.
+/// - `record`: The record to append.
+///
+/// # Returns
+/// An `io::Result` indicating the success of the operation.
+pub fn append_record(path: &str, kind: FileKind, record: &[u8]) -> io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let header = encode(kind, &[]);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    let mut current_header = vec![];
+    (&mut file)
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut current_header)?;
+    if current_header.is_empty() {
+        file.write_all(&header)?;
+    } else if current_header != header {
+        // Se reescribe con el formato actual antes de agregarle registros
+        drop(file);
+        read_file(path, kind)?;
+        file = OpenOptions::new().append(true).open(path)?;
+    }
+    file.write_all(&encode_records(&[record.to_vec()]))?;
+
+    match fsync_policy() {
+        FsyncPolicy::Always => file.sync_data(),
+        FsyncPolicy::Periodic => {
+            if let Ok(mut unsynced) = UNSYNCED_FILES.lock() {
+                unsynced.insert(path.to_string());
+            }
+            Ok(())
+        }
+        FsyncPolicy::Never => Ok(()),
+    }
+}
+
+/// Reads the records of a log file written by `append_record`. If its last record was cut
+/// short, the file is rewritten without it so the next records are appended after the
+/// complete ones.
+///
+/// # Parameters
+/// - `path`: The path of the file.
+/// - `kind`: The kind of file expected.
+///
+/// # Returns
+/// An `io::Result` with the records, or an `InvalidData` error if the file can't be decoded.
+pub fn read_records(path: &str, kind: FileKind) -> io::Result<Vec<Vec<u8>>> {
+    let payload = read_file(path, kind)?;
+    let records = decode_records(&payload);
+    let complete = encode_records(&records);
+    if complete.len() != payload.len() {
+        write_file(path, kind, &complete)?;
+    }
+    Ok(records)
+}
+
+/// Syncs to disk the files written with the periodic policy since the last call. The files
+/// that fail are kept to be synced in the next call.
+///
+/// # Returns
+/// The last error found, if any.
+pub fn sync_written_files() -> io::Result<()> {
+    let paths = match UNSYNCED_FILES.lock() {
+        Ok(mut unsynced) => std::mem::take(&mut *unsynced),
+        Err(_) => return Ok(()),
+    };
+
+    let mut result = Ok(());
+    for path in paths {
+        match File::open(&path).and_then(|file| file.sync_all()) {
+            Ok(()) => {}
+            // Un archivo borrado desde que se escribió ya no hace falta sincronizarlo
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                if let Ok(mut unsynced) = UNSYNCED_FILES.lock() {
+                    unsynced.insert(path);
+                }
+                result = Err(e);
+            }
+        }
+    }
+    result
+}
+
+/// Reads a file and returns its payload in the current format. If the file was written
+/// with an older version, it is rewritten in place with the current one.
+///
+/// # Parameters
+/// - `path`: The path of the file.
+/// - `kind`: The kind of file expected.
+///
+/// # Returns
+/// An `io::Result` with the payload, or an `InvalidData` error if it can't be decoded.
+pub fn read_file(path: &str, kind: FileKind) -> io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    let (payload, migrated) = decode(kind, &bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))?;
+    if migrated {
+        write_file(path, kind, &payload)?;
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replication_strategy::ReplicationStrategy;
+
+    #[test]
+    fn test_encode_and_decode() {
+        let bytes = encode(FileKind::Keyspaces, b"ks,SimpleStrategy,1");
+        assert_eq!(&bytes[..4], b"RCDB");
+        let (payload, migrated) = decode(FileKind::Keyspaces, &bytes).unwrap();
+        assert_eq!(payload, b"ks,SimpleStrategy,1");
+        assert!(!migrated);
+    }
+
+    #[test]
+    fn test_files_without_header_are_migrated() {
+        let (payload, migrated) = decode(FileKind::Gossip, b"[]").unwrap();
+        assert_eq!(payload, b"[]");
+        assert!(migrated);
+    }
+
+    #[test]
+    fn test_files_of_a_previous_version_are_migrated() {
+        let mut bytes = encode(FileKind::Gossip, b"[]");
+        bytes[5..7].copy_from_slice(&1u16.to_be_bytes());
+        assert_eq!(decode(FileKind::Gossip, &bytes).unwrap(), (b"[]".to_vec(), true));
+    }
+
+    #[test]
+    fn test_wrong_kind_is_rejected() {
+        let bytes = encode(FileKind::Table, b"data");
+        assert!(decode(FileKind::Gossip, &bytes).is_err());
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let mut bytes = encode(FileKind::Hints, b"data");
+        bytes[5..7].copy_from_slice(&(CURRENT_VERSION + 1).to_be_bytes());
+        assert!(decode(FileKind::Hints, &bytes).is_err());
+    }
+
+    #[test]
+    fn test_read_file_upgrades_in_place() {
+        let path = "./data/storage_format_test/gossip_table";
+        fs::create_dir_all("./data/storage_format_test").unwrap();
+        fs::write(path, b"[]").unwrap();
+
+        let payload = read_file(path, FileKind::Gossip).unwrap();
+        assert_eq!(payload, b"[]");
+        assert_eq!(fs::read(path).unwrap(), encode(FileKind::Gossip, &payload));
+    }
+
+    #[test]
+    fn test_periodic_fsync_syncs_the_written_files_later() {
+        let path = "./data/storage_format_test/periodic_hints";
+        write_file_with(path, FileKind::Hints, b"[]", FsyncPolicy::Periodic).unwrap();
+        assert!(UNSYNCED_FILES.lock().unwrap().contains(path));
+
+        sync_written_files().unwrap();
+        assert!(!UNSYNCED_FILES.lock().unwrap().contains(path));
+        assert_eq!(read_file(path, FileKind::Hints).unwrap(), b"[]");
+
+        let never = "./data/storage_format_test/never_hints";
+        write_file_with(never, FileKind::Hints, b"[]", FsyncPolicy::Never).unwrap();
+        assert!(!UNSYNCED_FILES.lock().unwrap().contains(never));
+    }
+
+    #[test]
+    fn test_records_are_appended_and_a_cut_record_is_dropped() {
+        let path = "./data/storage_format_test/appended_log";
+        let _ = fs::remove_file(path);
+        append_record(path, FileKind::CommitLog, b"first").unwrap();
+        append_record(path, FileKind::CommitLog, b"second").unwrap();
+        assert_eq!(
+            read_records(path, FileKind::CommitLog).unwrap(),
+            vec![b"first".to_vec(), b"second".to_vec()]
+        );
+
+        // Un registro cortado por una caída se descarta
+        let mut bytes = fs::read(path).unwrap();
+        bytes.extend_from_slice(&100u32.to_be_bytes());
+        bytes.extend_from_slice(b"cut");
+        fs::write(path, bytes).unwrap();
+        assert_eq!(read_records(path, FileKind::CommitLog).unwrap().len(), 2);
+
+        append_record(path, FileKind::CommitLog, b"third").unwrap();
+        assert_eq!(
+            read_records(path, FileKind::CommitLog).unwrap(),
+            vec![b"first".to_vec(), b"second".to_vec(), b"third".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_csv_keyspaces_are_migrated_to_a_document() {
+        let csv = b"flights,SimpleStrategy,3\nair,ports,RandomStrategy,2";
+        for version in [0u16, 2] {
+            let mut bytes = encode(FileKind::Keyspaces, csv);
+            bytes[5..7].copy_from_slice(&version.to_be_bytes());
+            let (payload, migrated) = decode(FileKind::Keyspaces, &bytes).unwrap();
+            assert!(migrated);
+
+            let document: crate::data_parser::KeyspacesDocument =
+                serde_json::from_slice(&payload).unwrap();
+            assert_eq!(document.keyspaces.len(), 2);
+            assert_eq!(
+                document.keyspaces["flights"],
+                ReplicationStrategy::SimpleStrategy { replication_factor: 3 }
+            );
+            assert_eq!(
+                document.keyspaces["air,ports"],
+                ReplicationStrategy::RandomStrategy { replication_factor: 2 }
+            );
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::client_manager::ClientManager;
+use common::config::StorageConfig;
+
+use crate::connect_to_first_node;
+use crate::handler_nodes::{
+    serve_node_gossip_query_protocol, serve_node_native_protocol, start_gossip,
+};
+use crate::node::Node;
+
+/// Interval of the gossip rounds of the nodes of a test cluster, shorter than that of a real
+/// cluster so the tests don't wait for the nodes to find each other.
+const GOSSIP_INTERVAL_MS: u64 = 100;
+
+/// How long `eventually` waits for a condition before failing the test.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(15);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Numbers the clusters of the process, so each one has its own node ids and directories.
+static CLUSTERS: AtomicUsize = AtomicUsize::new(0);
+
+/// A cluster of nodes running inside the test process, each on ephemeral ports and with its
+/// own directories, so tests can run in parallel without agreeing on ports or sharing data.
+/// The nodes join the first one, as they do with a seed, and the cluster is returned once
+/// every node knows every other. The directories are deleted when the cluster is dropped.
+///
+/// The listeners of the nodes can't be stopped, so their threads run until the end of the
+/// tests.
+pub struct TestCluster {
+    nodes: Vec<Arc<Node>>,
+    dir: PathBuf,
+}
+
+impl TestCluster {
+    /// Starts a cluster of `size` nodes and waits until they gossiped with each other.
+    ///
+    /// #Parameters
+    /// - `size`: The number of nodes, at least one.
+    ///
+    pub fn start(size: usize) -> Self {
+        let number = CLUSTERS.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "aero_test_cluster_{}_{}",
+            std::process::id(),
+            number
+        ));
+        let storage = storage_in(&dir);
+
+        let mut nodes: Vec<Arc<Node>> = Vec::with_capacity(size);
+        for i in 1..=size.max(1) {
+            // Los puertos se eligen al vincularlos, asi que no chocan con otros tests
+            let native = bind_ephemeral();
+            let gossip = bind_ephemeral();
+            let node = Arc::new(Node::new_with_storage(
+                &format!("Cluster{}Node{}", number, i),
+                "127.0.0.1",
+                port_of(&native),
+                port_of(&gossip),
+                storage.clone(),
+            ));
+            let node_native = Arc::clone(&node);
+            thread::spawn(move || serve_node_native_protocol(node_native, native));
+            let node_gossip = Arc::clone(&node);
+            thread::spawn(move || serve_node_gossip_query_protocol(node_gossip, gossip));
+
+            if let Some(seed) = nodes.first() {
+                connect_to_first_node(
+                    &node,
+                    &format!("127.0.0.1:{}", seed.get_port_gossip_query()),
+                );
+            }
+            nodes.push(node);
+        }
+        for node in &nodes {
+            start_gossip(Arc::clone(node), GOSSIP_INTERVAL_MS);
+        }
+
+        let cluster = Self { nodes, dir };
+        eventually("every node to know every other", || {
+            cluster.nodes.iter().all(|node| {
+                node.get_gossip_table()
+                    .is_ok_and(|table| table.len() == cluster.nodes.len())
+            })
+        });
+        cluster
+    }
+
+    /// Returns the `i`-th node, counting from zero.
+    pub fn node(&self, i: usize) -> &Arc<Node> {
+        &self.nodes[i]
+    }
+
+    pub fn nodes(&self) -> &[Arc<Node>] {
+        &self.nodes
+    }
+
+    /// Returns the addresses of the native protocol ports of the nodes.
+    pub fn native_addresses(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .map(|node| format!("127.0.0.1:{}", node.get_port_native_protocol()))
+            .collect()
+    }
+
+    /// Returns a client connected to every node of the cluster.
+    pub fn client(&self) -> ClientManager {
+        ClientManager::connect_when_ready(&self.native_addresses(), WAIT_TIMEOUT)
+            .expect("Error al conectar al cluster de prueba")
+    }
+}
+
+impl Drop for TestCluster {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Returns the directories of the nodes of a test, emptied and in the temp directory of the
+/// system, so the nodes start without the data, hints or gossip peers that earlier runs left.
+///
+/// #Parameters
+/// - `name`: Names the directories. Each test uses its own, as the tests run in parallel.
+pub fn temp_storage(name: &str) -> StorageConfig {
+    storage_in(&std::env::temp_dir().join(format!("aero_test_storage_{}", name)))
+}
+
+fn storage_in(dir: &Path) -> StorageConfig {
+    let _ = fs::remove_dir_all(dir);
+    StorageConfig {
+        data_dir: dir.join("data").to_string_lossy().to_string(),
+        hints_dir: dir.join("hints").to_string_lossy().to_string(),
+        commitlog_dir: dir.join("commitlog").to_string_lossy().to_string(),
+        ..StorageConfig::default()
+    }
+}
+
+/// Waits until `condition` holds, instead of sleeping for a fixed time.
+///
+/// #Parameters
+/// - `description`: What is waited for, shown if it doesn't happen.
+/// - `condition`: Checked every few milliseconds.
+///
+/// #Panics
+/// If the condition doesn't hold after `WAIT_TIMEOUT`.
+pub fn eventually(description: &str, condition: impl Fn() -> bool) {
+    let started = Instant::now();
+    while !condition() {
+        assert!(
+            started.elapsed() < WAIT_TIMEOUT,
+            "Timed out after {:?} waiting for {}",
+            WAIT_TIMEOUT,
+            description
+        );
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn bind_ephemeral() -> TcpListener {
+    TcpListener::bind("127.0.0.1:0").expect("Error al vincular un puerto efimero")
+}
+
+fn port_of(listener: &TcpListener) -> u16 {
+    listener
+        .local_addr()
+        .expect("Error al leer el puerto vinculado")
+        .port()
+}
@@ -0,0 +1,214 @@
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Why a job couldn't be queued without blocking.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PoolError {
+    /// Every worker is busy and the queue is full.
+    Saturated,
+    /// The pool has no workers left.
+    ShutDown,
+}
+
+/// A fixed set of worker threads that run jobs taken from a bounded queue.
+///
+/// When the queue is full, `execute` blocks until a worker frees a slot, so a burst of
+/// requests slows down the caller instead of spawning an unbounded number of threads.
+pub struct ThreadPool {
+    name: String,
+    sender: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    /// Creates a pool and starts its workers.
+    ///
+    /// # Parameters
+    /// - `name`: Name of the pool, used to name its threads.
+    /// - `size`: Number of worker threads.
+    /// - `queue_capacity`: Maximum number of jobs waiting for a free worker.
+    ///
+    /// # Returns
+    /// The new `ThreadPool`.
+    pub fn new(name: &str, size: usize, queue_capacity: usize) -> Self {
+        let (sender, receiver) = sync_channel::<Job>(queue_capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut workers = Vec::with_capacity(size);
+        for i in 0..size.max(1) {
+            let receiver = Arc::clone(&receiver);
+            let worker = thread::Builder::new()
+                .name(format!("{}-{}", name, i))
+                .spawn(move || run_worker(receiver));
+            match worker {
+                Ok(worker) => workers.push(worker),
+                Err(e) => eprintln!("Error starting worker {} of {}: {}", i, name, e),
+            }
+        }
+
+        ThreadPool {
+            name: name.to_string(),
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Queues a job to be run by the next free worker, blocking while the queue is full.
+    ///
+    /// # Parameters
+    /// - `job`: The closure to run.
+    ///
+    /// # Returns
+    /// `Ok(())` if the job was queued, or `Err(String)` if the pool has no workers left.
+    pub fn execute<F>(&self, job: F) -> Result<(), String>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match &self.sender {
+            Some(sender) => sender
+                .send(Box::new(job))
+                .map_err(|_| format!("Thread pool {} is shut down", self.name)),
+            None => Err(format!("Thread pool {} is shut down", self.name)),
+        }
+    }
+
+    /// Queues a job to be run by the next free worker, failing instead of blocking when the
+    /// queue is full so the caller can shed the request.
+    ///
+    /// # Parameters
+    /// - `job`: The closure to run.
+    ///
+    /// # Returns
+    /// `Ok(())` if the job was queued, or the `PoolError` that prevented it.
+    pub fn try_execute<F>(&self, job: F) -> Result<(), PoolError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match &self.sender {
+            Some(sender) => sender.try_send(Box::new(job)).map_err(|e| match e {
+                TrySendError::Full(_) => PoolError::Saturated,
+                TrySendError::Disconnected(_) => PoolError::ShutDown,
+            }),
+            None => Err(PoolError::ShutDown),
+        }
+    }
+}
+
+/// Runs jobs until the pool is dropped. A panicking job doesn't take the worker down with it.
+fn run_worker(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        match job {
+            Ok(job) => {
+                if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                    eprintln!("A job panicked in a worker thread");
+                }
+            }
+            // El pool se destruyó
+            Err(_) => return,
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Cerrar el canal hace que cada worker termine después de vaciar la cola. No se
+        // esperan los workers: el último handle al pool puede soltarse desde un job.
+        self.sender.take();
+    }
+}
+
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadPool")
+            .field("name", &self.name)
+            .field("workers", &self.workers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_runs_every_job() {
+        let pool = ThreadPool::new("test", 4, 2);
+        let counter = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = channel();
+        for _ in 0..20 {
+            let counter = Arc::clone(&counter);
+            let tx = tx.clone();
+            pool.execute(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tx.send(()).unwrap();
+            })
+            .unwrap();
+        }
+        for _ in 0..20 {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 20);
+    }
+
+    #[test]
+    fn test_runs_jobs_in_parallel() {
+        let pool = ThreadPool::new("test", 2, 2);
+        let (tx, rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        let release_rx = Arc::new(Mutex::new(release_rx));
+        for _ in 0..2 {
+            let tx = tx.clone();
+            let release_rx = Arc::clone(&release_rx);
+            pool.execute(move || {
+                tx.send(()).unwrap();
+                let _ = release_rx.lock().unwrap().recv_timeout(Duration::from_secs(5));
+            })
+            .unwrap();
+        }
+        // Los dos jobs arrancan aunque ninguno haya terminado.
+        for _ in 0..2 {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        release_tx.send(()).unwrap();
+        release_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_try_execute_rejects_jobs_when_saturated() {
+        let pool = ThreadPool::new("test", 1, 1);
+        let (started_tx, started_rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        pool.try_execute(move || {
+            started_tx.send(()).unwrap();
+            let _ = release_rx.recv_timeout(Duration::from_secs(5));
+        })
+        .unwrap();
+        started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // El worker está ocupado: entra un job en la cola y el siguiente se rechaza.
+        assert!(pool.try_execute(|| {}).is_ok());
+        assert_eq!(pool.try_execute(|| {}), Err(PoolError::Saturated));
+        release_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_survives_panicking_jobs() {
+        let pool = ThreadPool::new("test", 1, 1);
+        pool.execute(|| panic!("job failed")).unwrap();
+        let (tx, rx) = channel();
+        pool.execute(move || tx.send(()).unwrap()).unwrap();
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
@@ -0,0 +1,259 @@
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::policy::{LoadBalancingPolicy, RandomPolicy};
+use super::{is_idempotent, RETRIES, UNANSWERED_QUERY};
+use crate::frame::messages::authentication::{plain_token, AuthChallenge, AuthResponse};
+use crate::frame::messages::consistency_level::ConsistencyLevel;
+use crate::frame::{Frame, HEADER_SIZE};
+use crate::security::EncryptionHandler;
+
+/// Asynchronous version of `ClientManager`, on a tokio `TcpStream`: a single runtime thread
+/// can drive many connections issuing queries concurrently.
+#[derive(Debug)]
+pub struct AsyncClientManager {
+    addresses: Vec<String>,
+    stream: TcpStream,
+    stream_id: i16,
+    /// The user the client identifies itself as, `None` for an anonymous client.
+    user: Option<String>,
+    encryption_handler: EncryptionHandler,
+    current_keyspace: String,
+    /// Whether the last request was sent and its response never arrived.
+    unanswered: bool,
+}
+
+impl AsyncClientManager {
+    /// Creates a new `AsyncClientManager` by connecting to the first available address and authenticating.
+    pub async fn new(addresses: &[String]) -> io::Result<AsyncClientManager> {
+        Self::connect(addresses, None).await
+    }
+
+    /// Creates a new `AsyncClientManager` that identifies itself as `user`, so that the
+    /// nodes record it in their audit log.
+    pub async fn new_as_user(addresses: &[String], user: &str) -> io::Result<AsyncClientManager> {
+        Self::connect(addresses, Some(user)).await
+    }
+
+    async fn connect(addresses: &[String], user: Option<&str>) -> io::Result<AsyncClientManager> {
+        let mut stream = connect_to_first_available(addresses).await?;
+        let (encryption_handler, stream_id) = authenticate_to_server(&mut stream, user).await?;
+
+        Ok(AsyncClientManager {
+            addresses: addresses.to_vec(),
+            stream,
+            stream_id,
+            user: user.map(str::to_string),
+            encryption_handler,
+            current_keyspace: String::new(),
+            unanswered: false,
+        })
+    }
+
+    /// Sets the current keyspace for the client.
+    pub async fn use_keyspace(&mut self, keyspace: &str) -> Result<(), String> {
+        self.query(format!("USE {};", keyspace), None).await?;
+        self.current_keyspace = keyspace.to_string();
+        Ok(())
+    }
+
+    /// Executes a query with the given consistency level, or the default one of the protocol
+    /// if it is `None`.
+    pub async fn query(
+        &mut self,
+        query_string: String,
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
+    ) -> Result<String, String> {
+        let consistency_level = consistency_level.into().unwrap_or_default();
+        let idempotent = is_idempotent(&query_string);
+
+        let query = Frame::new_query(query_string, consistency_level, self.stream_id);
+
+        let response = self
+            .execute_query(&query, idempotent)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        response.handle_response(query)
+    }
+
+    /// Sends a query and reads its response. If the connection fails, the client reconnects
+    /// to any of the nodes and sends the query again, up to `RETRIES` times. A query that
+    /// isn't idempotent is only sent again if the node never got it.
+    async fn execute_query(&mut self, query: &Frame, idempotent: bool) -> io::Result<Frame> {
+        let mut attempts = 0;
+        loop {
+            match self.exchange(query).await {
+                Ok(response) => return Ok(response),
+                Err(e) if attempts >= RETRIES => return Err(e),
+                Err(e) => {
+                    attempts += 1;
+                    eprintln!(
+                        "({}) Attempt {} failed ({}), reconnecting...",
+                        self.stream_id, attempts, e
+                    );
+                    let unanswered = self.unanswered;
+                    self.reconnect().await?;
+                    if unanswered && !idempotent {
+                        return Err(io::Error::other(format!("{}: {}", UNANSWERED_QUERY, e)));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn exchange(&mut self, frame: &Frame) -> io::Result<Frame> {
+        let bytes = self.encryption_handler.encrypt_frame(frame);
+        self.stream.write_all(&bytes).await?;
+        self.unanswered = true;
+        let encryption_handler = &self.encryption_handler;
+        let response =
+            read_frame(&mut self.stream, &|bytes| encryption_handler.decrypt(bytes)).await?;
+        self.unanswered = false;
+        Ok(response)
+    }
+
+    /// Connects again to any of the nodes, keeping the keyspace in use.
+    async fn reconnect(&mut self) -> io::Result<()> {
+        let manager = AsyncClientManager::connect(&self.addresses, self.user.as_deref()).await?;
+
+        self.stream = manager.stream;
+        self.stream_id = manager.stream_id;
+        self.encryption_handler = manager.encryption_handler;
+        self.unanswered = false;
+
+        if self.current_keyspace.is_empty() {
+            return Ok(());
+        }
+        let use_keyspace = Frame::new_query(
+            format!("USE {};", self.current_keyspace),
+            ConsistencyLevel::default(),
+            self.stream_id,
+        );
+        let response = self.exchange(&use_keyspace).await?;
+        response
+            .handle_response(use_keyspace)
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+}
+
+/// Connects to the first available address of a random plan.
+async fn connect_to_first_available(addresses: &[String]) -> io::Result<TcpStream> {
+    for address in &RandomPolicy.query_plan(addresses, None) {
+        match TcpStream::connect(address).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => eprintln!("Failed to connect to {}: {}", address, e),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        format!(
+            "Could not connect to any of the specified addresses: {:?}",
+            addresses
+        ),
+    ))
+}
+
+/// Same handshake as the blocking client: STARTUP, then the PLAIN authentication in which
+/// both sides agree on the key that encrypts the rest of the connection.
+async fn authenticate_to_server(
+    stream: &mut TcpStream,
+    user: Option<&str>,
+) -> io::Result<(EncryptionHandler, i16)> {
+    let startup = Frame::new_startup();
+    stream.write_all(&startup.serialize()).await?;
+
+    let server_response = read_frame(stream, &|bytes| bytes.to_vec()).await?;
+    let (authentication, stream_id) = server_response.get_authenticator()?;
+    if authentication != "PLAIN" {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "Unsupported authentication method",
+        ));
+    }
+
+    let auth_response =
+        server_response.new_auth_response(user.map(plain_token).unwrap_or_default());
+    stream.write_all(&auth_response.serialize()).await?;
+
+    let server_response = read_frame(stream, &|bytes| bytes.to_vec()).await?;
+    let challenge = AuthChallenge::deserialize(&server_response.get_auth_challenge()?);
+
+    let (encryption_handler, public_key, shared_secret) =
+        EncryptionHandler::new_initialized(challenge.prime, challenge.base, challenge.public_key);
+
+    let auth_response = AuthResponse::new(public_key, shared_secret);
+    let auth_response = server_response.new_auth_response(auth_response.serialize());
+    stream.write_all(&auth_response.serialize()).await?;
+
+    // El nodo ya encripta el AUTH_SUCCESS
+    let response = read_frame(stream, &|bytes| encryption_handler.decrypt(bytes)).await?;
+    match response.is_success() {
+        true => Ok((encryption_handler, stream_id)),
+        false => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "Authentication failed",
+        )),
+    }
+}
+
+/// Reads a frame, decrypting its header and body with `decryptor`. The asynchronous client
+/// doesn't ask for compression, so the bodies are never compressed.
+async fn read_frame(
+    stream: &mut TcpStream,
+    decryptor: &(dyn Fn(&[u8]) -> Vec<u8> + Sync),
+) -> io::Result<Frame> {
+    let mut encrypted_header = [0u8; HEADER_SIZE];
+    stream.read_exact(&mut encrypted_header).await?;
+    let header = decryptor(&encrypted_header);
+
+    let length = match Frame::body_length(&header) {
+        Ok(length) => length,
+        Err(stream_id) => return Ok(Frame::new_protocol_error(stream_id)),
+    };
+
+    let mut encrypted_body = vec![0u8; length];
+    stream.read_exact(&mut encrypted_body).await?;
+    Ok(Frame::from_parts(&header, decryptor(&encrypted_body), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_manager::mock_server::start_mock_server;
+
+    #[tokio::test]
+    async fn concurrent_queries_on_one_thread() {
+        let addresses = vec![start_mock_server().0];
+        let mut first = AsyncClientManager::new(&addresses).await.unwrap();
+        let mut second = AsyncClientManager::new_as_user(&addresses, "simulator")
+            .await
+            .unwrap();
+
+        let (first_result, second_result) = tokio::join!(
+            async {
+                first.use_keyspace("flights").await?;
+                first
+                    .query("SELECT * FROM flights".to_string(), ConsistencyLevel::One)
+                    .await
+            },
+            second.query(
+                "INSERT INTO airports.flights (id) VALUES (1)".to_string(),
+                ConsistencyLevel::Quorum
+            )
+        );
+        assert!(first_result.is_ok());
+        assert!(second_result.is_ok());
+        assert_eq!(first.current_keyspace, "flights");
+    }
+
+    #[tokio::test]
+    async fn no_node_available() {
+        let addresses = vec!["127.0.0.1:1".to_string()];
+        let error = AsyncClientManager::new(&addresses).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
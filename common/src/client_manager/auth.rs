@@ -1,6 +1,84 @@
-use std::{io::{self, Write}, net::TcpStream};
+use std::{fmt, io::{self, Write}, net::TcpStream};
 
-use crate::{frame::{messages::authentication::{AuthChallenge, AuthResponse}, Frame}, security::EncryptionHandler};
+use crate::{frame::{compression::Compression, messages::authentication::{plain_credentials, plain_token, AuthChallenge, AuthResponse}, Frame}, security::EncryptionHandler};
+
+/// Builds the responses of a client to the authentication of a node. The first one goes
+/// before the keys of the connection are agreed on, so it must not carry secrets; a node that
+/// requires credentials asks for them with a challenge once the connection is encrypted.
+pub trait Authenticator: fmt::Debug + Send + Sync {
+    /// Returns the token sent to a node that asked for the given authenticator.
+    ///
+    /// # Parameters
+    /// - `mechanism`: The authenticator of the node, `PLAIN` for the nodes of the cluster.
+    ///
+    /// # Returns
+    /// The token, or an error if the mechanism is not supported.
+    fn initial_response(&self, mechanism: &str) -> io::Result<Vec<u8>>;
+
+    /// Returns the response to the challenge a node sends on the encrypted connection when it
+    /// requires credentials. By default the client has none to send.
+    ///
+    /// # Parameters
+    /// - `challenge`: The challenge of the node.
+    fn evaluate_challenge(&self, _challenge: &[u8]) -> io::Result<Vec<u8>> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "The node requires credentials",
+        ))
+    }
+}
+
+/// Authenticates with the PLAIN mechanism. The first response only carries the user, or
+/// nothing for an anonymous client; the password is sent once the connection is encrypted,
+/// if the node asks for it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlainTextAuthenticator {
+    user: Option<String>,
+    password: Option<String>,
+}
+
+impl PlainTextAuthenticator {
+    /// Creates an authenticator with a user and its password.
+    pub fn new(user: &str, password: &str) -> Self {
+        PlainTextAuthenticator {
+            user: Some(user.to_string()),
+            password: Some(password.to_string()),
+        }
+    }
+
+    /// Creates an authenticator that only identifies the client as `user`, anonymous if `None`.
+    pub fn identified_as(user: Option<&str>) -> Self {
+        PlainTextAuthenticator {
+            user: user.map(str::to_string),
+            password: None,
+        }
+    }
+}
+
+impl Authenticator for PlainTextAuthenticator {
+    fn initial_response(&self, mechanism: &str) -> io::Result<Vec<u8>> {
+        if mechanism != "PLAIN" {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "Unsupported authentication method",
+            ));
+        }
+        Ok(match &self.user {
+            Some(user) => plain_token(user),
+            None => vec![],
+        })
+    }
+
+    fn evaluate_challenge(&self, _challenge: &[u8]) -> io::Result<Vec<u8>> {
+        match (&self.user, &self.password) {
+            (Some(user), Some(password)) => Ok(plain_credentials(user, password)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "The node requires a user and its password",
+            )),
+        }
+    }
+}
 
 /// Initializes a new `EncryptionHandler` with the given parameters and returns a tuple containing:
 /// - `encryption_handler`: An instance of `EncryptionHandler` initialized with the provided parameters.
@@ -12,30 +90,39 @@ use crate::{frame::{messages::authentication::{AuthChallenge, AuthResponse}, Fra
 /// - `challenge.base`: The base number used for encryption.
 /// - `challenge.public_key`: The public key provided for the challenge.
 ///
+/// - `authenticator`: Builds the responses to the node: the user the client identifies
+///   itself as, recorded in the audit log of the nodes, and its password if the node asks
+///   for it once the connection is encrypted.
+/// - `compression`: The compression the client asks for, used only if the node supports it.
+///
 /// # Returns
 /// A tuple containing the initialized `EncryptionHandler`, the generated public key, and the shared secret.
 
-pub fn authenticate_to_server(stream: &mut TcpStream) -> io::Result<(EncryptionHandler, i16)> {
-    let startup = Frame::new_startup();
+pub fn authenticate_to_server(
+    stream: &mut TcpStream,
+    authenticator: &dyn Authenticator,
+    compression: Option<Compression>,
+) -> io::Result<(EncryptionHandler, i16)> {
+    let compression = match compression {
+        Some(compression) => negotiate_compression(stream, compression)?,
+        None => None,
+    };
+    let startup = Frame::new_startup_with_compression(compression);
     stream.write_all(&startup.serialize())?;
 
     let server_response = read_non_encrypted_frame(stream)?;
     let (authentication, stream_id) = server_response.get_authenticator()?;
-    if authentication != "PLAIN" {
-        return Err(io::Error::new(
-            io::ErrorKind::ConnectionRefused,
-            "Unsupported authentication method",
-        ));
-    }
+    let token = authenticator.initial_response(&authentication)?;
 
-    let auth_response = server_response.new_auth_response(vec![]);
+    let auth_response = server_response.new_auth_response(token);
     stream.write_all(&auth_response.serialize())?;
 
     let server_response = read_non_encrypted_frame(stream)?;
     let challenge = AuthChallenge::deserialize(&server_response.get_auth_challenge()?);
 
-    let (encryption_handler, public_key, shared_secret) =
+    let (mut encryption_handler, public_key, shared_secret) =
         EncryptionHandler::new_initialized(challenge.prime, challenge.base, challenge.public_key);
+    encryption_handler.set_compression(compression);
 
     let auth_response = AuthResponse::new(public_key, shared_secret);
     let auth_response = server_response.new_auth_response(auth_response.serialize());
@@ -47,7 +134,13 @@ pub fn authenticate_to_server(stream: &mut TcpStream) -> io::Result<(EncryptionH
 
     incluyendo el de auth success, por lo que habia un bug si no leia el auth success con el decryptor */
 
-    let response = encryption_handler.read(stream)?;
+    let mut response = encryption_handler.read(stream)?;
+    if let Ok(challenge) = response.get_auth_challenge() {
+        // El nodo pide las credenciales, que ya viajan encriptadas
+        let credentials = authenticator.evaluate_challenge(&challenge)?;
+        encryption_handler.write(stream, &response.new_auth_response(credentials))?;
+        response = encryption_handler.read(stream)?;
+    }
     match response.is_success() {
         true => Ok((encryption_handler, stream_id)),
         false => Err(io::Error::new(
@@ -57,6 +150,19 @@ pub fn authenticate_to_server(stream: &mut TcpStream) -> io::Result<(EncryptionH
     }
 }
 
+/// Asks the node with an OPTIONS whether it supports `compression`.
+///
+/// # Returns
+/// The compression, or `None` if the node doesn't support it.
+fn negotiate_compression(
+    stream: &mut TcpStream,
+    compression: Compression,
+) -> io::Result<Option<Compression>> {
+    stream.write_all(&Frame::new_options().serialize())?;
+    let supported = read_non_encrypted_frame(stream)?.supported_compressions();
+    Ok(supported.contains(&compression).then_some(compression))
+}
+
 fn read_non_encrypted_frame(stream: &mut TcpStream) -> io::Result<Frame> {
-    Frame::deserialize_from_stream(stream, &|bytes| bytes.to_vec())
+    Frame::deserialize_from_stream(stream, &|bytes| bytes.to_vec(), None)
 }
\ No newline at end of file
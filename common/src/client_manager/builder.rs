@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::auth::{Authenticator, PlainTextAuthenticator};
+use super::policy::RoundRobinPolicy;
+use super::pool::{ConnectionPool, ConnectionSettings, DEFAULT_CONNECTIONS_PER_NODE};
+use super::profile::ExecutionProfile;
+use super::ClientManager;
+use crate::frame::compression::Compression;
+use crate::frame::messages::consistency_level::ConsistencyLevel;
+
+/// The settings of a `ClientManager`, set before it connects to the cluster. By default the
+/// client is anonymous, waits as long as the nodes take to connect, waits
+/// `DEFAULT_REQUEST_TIMEOUT` for each response and uses the default consistency level of
+/// the protocol.
+#[derive(Debug, Clone)]
+pub struct ClusterBuilder {
+    contact_points: Vec<String>,
+    settings: ConnectionSettings,
+    connections_per_node: usize,
+    default_consistency: ConsistencyLevel,
+    keyspace: Option<String>,
+    profiles: HashMap<String, ExecutionProfile>,
+}
+
+impl Default for ClusterBuilder {
+    fn default() -> Self {
+        ClusterBuilder {
+            contact_points: vec![],
+            settings: ConnectionSettings::new(Arc::new(PlainTextAuthenticator::default())),
+            connections_per_node: DEFAULT_CONNECTIONS_PER_NODE,
+            default_consistency: ConsistencyLevel::default(),
+            keyspace: None,
+            profiles: HashMap::new(),
+        }
+    }
+}
+
+impl ClusterBuilder {
+    /// Creates a builder without contact points.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node the client connects to first, `ip:port`. The rest of the cluster is
+    /// found out from its events.
+    pub fn contact_point(mut self, address: &str) -> Self {
+        self.contact_points.push(address.to_string());
+        self
+    }
+
+    /// Adds several contact points.
+    pub fn contact_points(mut self, addresses: &[String]) -> Self {
+        self.contact_points.extend_from_slice(addresses);
+        self
+    }
+
+    /// Authenticates with a user and its password with the PLAIN mechanism. The password is
+    /// only sent once the connection is encrypted, to the nodes that ask for it.
+    pub fn credentials(self, user: &str, password: &str) -> Self {
+        self.authenticator(PlainTextAuthenticator::new(user, password))
+    }
+
+    /// Identifies the client as `user` with the PLAIN mechanism, without a password. The
+    /// nodes record it in their audit log; those that require credentials refuse it.
+    pub fn user(self, user: &str) -> Self {
+        self.authenticator(PlainTextAuthenticator::identified_as(Some(user)))
+    }
+
+    /// Authenticates with a custom authenticator, which builds the responses to the nodes.
+    pub fn authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.settings.authenticator = Arc::new(authenticator);
+        self
+    }
+
+    /// Sets the maximum number of connections open to each node.
+    pub fn connections_per_node(mut self, connections_per_node: usize) -> Self {
+        self.connections_per_node = connections_per_node;
+        self
+    }
+
+    /// Sets the time to connect to a node and authenticate, after which the node is
+    /// considered down. It must not be zero.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the time to wait for the response of a request. A request without response in
+    /// time fails like one whose connection was closed: the node is considered down and the
+    /// request is retried on another one. It must not be zero.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Asks the nodes to compress the frames with `compression`, which pays off for large
+    /// results like the tracks of past flights. Nodes that don't support it answer
+    /// uncompressed.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.settings.compression = Some(compression);
+        self
+    }
+
+    /// Sets the consistency level of the queries sent without one (`None`).
+    pub fn default_consistency(mut self, consistency_level: ConsistencyLevel) -> Self {
+        self.default_consistency = consistency_level;
+        self
+    }
+
+    /// Sets the keyspace the client uses from the start, as with `USE`.
+    pub fn keyspace(mut self, keyspace: &str) -> Self {
+        self.keyspace = Some(keyspace.to_string());
+        self
+    }
+
+    /// Adds an execution profile, which queries choose by its name with
+    /// `ClientManager::query_with_profile`. A profile with the same name is replaced.
+    pub fn execution_profile(mut self, name: &str, profile: ExecutionProfile) -> Self {
+        self.profiles.insert(name.to_string(), profile);
+        self
+    }
+
+    /// Opens the pool of connections, authenticating each one, and starts following the
+    /// changes of the cluster.
+    ///
+    /// # Returns
+    /// The client, or an error if no contact point accepted the connection or the keyspace
+    /// can't be used.
+    pub fn build(self) -> io::Result<ClientManager> {
+        let profile_timeouts = self
+            .profiles
+            .values()
+            .map(|profile| profile.request_timeout);
+        for timeout in [self.settings.connect_timeout, self.settings.request_timeout]
+            .into_iter()
+            .chain(profile_timeouts)
+        {
+            if timeout == Some(Duration::ZERO) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Timeouts must not be zero",
+                ));
+            }
+        }
+
+        let pool = Arc::new(ConnectionPool::with_settings(
+            &self.contact_points,
+            self.connections_per_node,
+            self.settings,
+            Box::new(RoundRobinPolicy::default()),
+        )?);
+        // Los nodos que se unan despues se agregan con los eventos del cluster
+        pool.watch_topology();
+
+        let mut manager = ClientManager::with_pool(pool);
+        manager.set_default_consistency(self.default_consistency);
+        for (name, profile) in self.profiles {
+            manager.add_execution_profile(&name, profile);
+        }
+        if let Some(keyspace) = &self.keyspace {
+            manager
+                .use_keyspace(keyspace)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        }
+        Ok(manager)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_manager::mock_server::{
+        start_mock_server, start_mock_server_with_users, start_slow_mock_server,
+    };
+    use crate::config::AuthConfig;
+    use crate::security::credentials::hash_password;
+    use crate::frame::messages::authentication::plain_token;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct CountingAuthenticator {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Authenticator for CountingAuthenticator {
+        fn initial_response(&self, mechanism: &str) -> io::Result<Vec<u8>> {
+            assert_eq!(mechanism, "PLAIN");
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(plain_token("simulator"))
+        }
+    }
+
+    #[test]
+    fn every_connection_authenticates_with_the_authenticator() {
+        let (first, _) = start_mock_server();
+        let (second, _) = start_mock_server();
+        let authenticator = CountingAuthenticator::default();
+        let calls = Arc::clone(&authenticator.calls);
+
+        let mut manager = ClientManager::builder(&[first, second])
+            .authenticator(authenticator)
+            .build()
+            .unwrap();
+        manager
+            .query(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One,
+            )
+            .unwrap();
+
+        // Una conexion por nodo, mas la registrada para eventos si ya se abrio
+        assert!(calls.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[test]
+    fn the_user_is_sent_with_the_plain_mechanism() {
+        let authenticator = PlainTextAuthenticator::identified_as(Some("admin"));
+        assert_eq!(
+            authenticator.initial_response("PLAIN").unwrap(),
+            b"\0admin\0"
+        );
+        assert!(PlainTextAuthenticator::default()
+            .initial_response("PLAIN")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            authenticator
+                .initial_response("Kerberos")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+
+        let (address, _) = start_mock_server();
+        let manager = ClientManager::builder(&[address])
+            .user("admin")
+            .build();
+        assert!(manager.is_ok());
+    }
+
+    #[test]
+    fn nodes_with_users_only_accept_their_passwords() {
+        let mut auth = AuthConfig::default();
+        auth.users
+            .insert("admin".to_string(), hash_password("secret"));
+        let (address, _) = start_mock_server_with_users(auth);
+        let connect = |builder: ClusterBuilder| builder.contact_point(&address).build();
+
+        let mut manager = connect(ClusterBuilder::new().credentials("admin", "secret")).unwrap();
+        assert!(manager
+            .query(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One,
+            )
+            .is_ok());
+        assert!(connect(ClusterBuilder::new().credentials("admin", "wrong")).is_err());
+        assert!(connect(ClusterBuilder::new().credentials("ops", "secret")).is_err());
+        assert!(connect(ClusterBuilder::new().user("admin")).is_err());
+        assert!(connect(ClusterBuilder::new()).is_err());
+
+        // La contraseña no va en la primera respuesta, que viaja sin encriptar
+        let authenticator = PlainTextAuthenticator::new("admin", "secret");
+        assert_eq!(authenticator.initial_response("PLAIN").unwrap(), b"\0admin\0");
+        assert_eq!(
+            authenticator.evaluate_challenge(&[]).unwrap(),
+            b"\0admin\0secret"
+        );
+    }
+
+    #[test]
+    fn the_session_starts_with_the_keyspace() {
+        let (address, _) = start_mock_server();
+        let mut manager = ClusterBuilder::new()
+            .contact_point(&address)
+            .keyspace("airports")
+            .default_consistency(ConsistencyLevel::Quorum)
+            .build()
+            .unwrap();
+
+        // Sin keyspace el nodo no encuentra la tabla
+        assert!(manager
+            .query("SELECT * FROM flights".to_string(), None)
+            .is_ok());
+    }
+
+    #[test]
+    fn requests_without_response_in_time_fail() {
+        let (address, _) = start_slow_mock_server(Duration::from_millis(500));
+        let mut manager = ClusterBuilder::new()
+            .contact_point(&address)
+            .connect_timeout(Duration::from_secs(1))
+            .request_timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        assert!(manager
+            .query(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One
+            )
+            .is_err());
+
+        let error = ClusterBuilder::new()
+            .contact_point(&address)
+            .request_timeout(Duration::ZERO)
+            .build()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn queries_use_the_settings_of_their_profile() {
+        let (address, _) = start_slow_mock_server(Duration::from_millis(300));
+        let mut manager = ClusterBuilder::new()
+            .contact_point(&address)
+            .execution_profile(
+                "telemetry-writes",
+                ExecutionProfile::new()
+                    .consistency(ConsistencyLevel::One)
+                    .request_timeout(Duration::from_millis(50)),
+            )
+            .execution_profile(
+                "dashboard-reads",
+                ExecutionProfile::new()
+                    .consistency(ConsistencyLevel::Quorum)
+                    .request_timeout(Duration::from_secs(2)),
+            )
+            .build()
+            .unwrap();
+
+        assert!(manager
+            .query_with_profile(
+                "SELECT * FROM airports.flights".to_string(),
+                "dashboard-reads"
+            )
+            .is_ok());
+        assert!(manager
+            .query_with_profile(
+                "SELECT * FROM airports.flights".to_string(),
+                "telemetry-writes"
+            )
+            .is_err());
+        assert!(manager
+            .query_with_profile("SELECT * FROM airports.flights".to_string(), "unknown")
+            .unwrap_err()
+            .contains("Unknown execution profile"));
+
+        let error = ClusterBuilder::new()
+            .contact_point(&address)
+            .execution_profile(
+                "telemetry-writes",
+                ExecutionProfile::new().request_timeout(Duration::ZERO),
+            )
+            .build()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn large_results_are_read_with_the_compression_of_the_session() {
+        let (address, _) = start_mock_server();
+        for compression in Compression::ALL {
+            let mut manager = ClusterBuilder::new()
+                .contact_point(&address)
+                .compression(compression)
+                .build()
+                .unwrap();
+
+            let rows = manager
+                .query_rows(
+                    "SELECT * FROM airports.tracks".to_string(),
+                    ConsistencyLevel::One,
+                )
+                .unwrap();
+            assert_eq!(rows.len(), 200);
+            assert_eq!(rows[199].get::<u32>("id"), Ok(199));
+        }
+    }
+}
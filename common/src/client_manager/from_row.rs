@@ -0,0 +1,72 @@
+use super::row::{parse_rows, Row};
+
+/// A type built from a row of the result of a query, as returned by
+/// `ClientManager::query_as`. Usually derived with `#[derive(FromRow)]`.
+pub trait FromRow: Sized {
+    /// Builds the value from the columns of a row.
+    ///
+    /// # Returns
+    /// The value, or an error if a column is missing or can't be parsed.
+    fn from_row(row: &Row) -> Result<Self, String>;
+}
+
+/// Parses the rows answered by a node and builds a `T` from each one.
+pub(crate) fn rows_as<T: FromRow>(json: &str) -> Result<Vec<T>, String> {
+    parse_rows(json)?.iter().map(T::from_row).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_manager::FromRow;
+
+    #[derive(Debug, PartialEq, FromRow)]
+    struct FlightStatus {
+        flight_id: u32,
+        #[row(rename = "status")]
+        description: String,
+        latitude: Option<f64>,
+        altitude: Option<u16>,
+    }
+
+    #[test]
+    fn rows_are_mapped_to_structs() {
+        let json = r#"[
+            {"flight_id":"20","status":"On Air","latitude":"-34.8","altitude":"9000"},
+            {"flight_id":"21","status":"Delayed","latitude":"","_timestamp":"2024-01-01 10:00:00"}
+        ]"#;
+
+        let rows: Vec<FlightStatus> = rows_as(json).unwrap();
+        assert_eq!(
+            rows,
+            [
+                FlightStatus {
+                    flight_id: 20,
+                    description: "On Air".to_string(),
+                    latitude: Some(-34.8),
+                    altitude: Some(9000),
+                },
+                FlightStatus {
+                    flight_id: 21,
+                    description: "Delayed".to_string(),
+                    latitude: None,
+                    altitude: None,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_and_invalid_columns_are_errors() {
+        let missing = r#"[{"flight_id":"20"}]"#;
+        assert_eq!(
+            rows_as::<FlightStatus>(missing),
+            Err("Missing value in column status".to_string())
+        );
+
+        let invalid = r#"[{"flight_id":"20","status":"On Air","altitude":"high"}]"#;
+        assert!(rows_as::<FlightStatus>(invalid)
+            .unwrap_err()
+            .contains("column altitude"));
+    }
+}
@@ -0,0 +1,181 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::frame::messages::consistency_level::ConsistencyLevel;
+
+/// Something that happened to the connections of a client, as passed to its listeners.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// A request got its response, or failed, after `latency`.
+    Request {
+        address: String,
+        latency: Duration,
+        success: bool,
+    },
+    /// A connection to the node failed, so it is skipped for a while.
+    NodeDown { address: String, error: String },
+    /// A connection was opened again to a node that had failed.
+    Reconnected { address: String },
+}
+
+/// A callback run on every `ClientEvent`, from the thread that caused it.
+pub type Listener = Arc<dyn Fn(&ClientEvent) + Send + Sync>;
+
+/// The requests sent to a single node.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HostSnapshot {
+    pub requests: u64,
+    /// Requests that failed, either answered with an error or without response.
+    pub errors: u64,
+    /// Connections that failed, marking the node down.
+    pub connection_failures: u64,
+    /// Connections opened again after the node was down.
+    pub reconnections: u64,
+    pub sum_latency_micros: u64,
+    pub max_latency_micros: u64,
+}
+
+impl HostSnapshot {
+    /// Returns the mean latency of the requests, zero if there were none.
+    pub fn mean_latency(&self) -> Duration {
+        match self.requests {
+            0 => Duration::ZERO,
+            requests => Duration::from_micros(self.sum_latency_micros / requests),
+        }
+    }
+}
+
+/// The last query a client sent: the node that coordinated it, how long it took, retries
+/// included, and the consistency level it was sent with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastRequest {
+    pub coordinator: String,
+    pub latency: Duration,
+    pub consistency: ConsistencyLevel,
+    pub success: bool,
+}
+
+/// The requests of a client to each node, by address, at a point in time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    pub hosts: BTreeMap<String, HostSnapshot>,
+}
+
+/// Counts of the requests of a client to each node, shared by every `ClientManager` of a
+/// `ConnectionPool`, and the listeners notified of each event.
+#[derive(Default)]
+pub struct ClientMetrics {
+    hosts: Mutex<HashMap<String, HostSnapshot>>,
+    listeners: Mutex<Vec<Listener>>,
+}
+
+impl fmt::Debug for ClientMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientMetrics")
+            .field("hosts", &self.hosts)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClientMetrics {
+    /// Adds a callback run on every event from now on.
+    pub fn add_listener(&self, listener: Listener) {
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.push(listener);
+        }
+    }
+
+    /// Returns the current counts of every node.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let hosts = match self.hosts.lock() {
+            Ok(hosts) => hosts
+                .iter()
+                .map(|(address, host)| (address.clone(), host.clone()))
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        };
+        MetricsSnapshot { hosts }
+    }
+
+    /// Records an event and notifies the listeners.
+    pub(crate) fn record(&self, event: ClientEvent) {
+        if let Ok(mut hosts) = self.hosts.lock() {
+            match &event {
+                ClientEvent::Request {
+                    address,
+                    latency,
+                    success,
+                } => {
+                    let host = hosts.entry(address.clone()).or_default();
+                    let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+                    host.requests += 1;
+                    if !success {
+                        host.errors += 1;
+                    }
+                    host.sum_latency_micros = host.sum_latency_micros.saturating_add(micros);
+                    host.max_latency_micros = host.max_latency_micros.max(micros);
+                }
+                ClientEvent::NodeDown { address, .. } => {
+                    hosts
+                        .entry(address.clone())
+                        .or_default()
+                        .connection_failures += 1;
+                }
+                ClientEvent::Reconnected { address } => {
+                    hosts.entry(address.clone()).or_default().reconnections += 1;
+                }
+            }
+        }
+
+        // Se copian para no tener el lock mientras corren
+        let listeners = match self.listeners.lock() {
+            Ok(listeners) => listeners.clone(),
+            Err(_) => return,
+        };
+        for listener in listeners {
+            listener(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn events_are_counted_by_host_and_passed_to_the_listeners() {
+        let metrics = ClientMetrics::default();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&seen);
+        metrics.add_listener(Arc::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        for (latency, success) in [(10, true), (30, false)] {
+            metrics.record(ClientEvent::Request {
+                address: "a:1".to_string(),
+                latency: Duration::from_micros(latency),
+                success,
+            });
+        }
+        metrics.record(ClientEvent::NodeDown {
+            address: "b:1".to_string(),
+            error: "Connection refused".to_string(),
+        });
+        metrics.record(ClientEvent::Reconnected {
+            address: "b:1".to_string(),
+        });
+
+        let snapshot = metrics.snapshot();
+        let a = &snapshot.hosts["a:1"];
+        assert_eq!((a.requests, a.errors, a.max_latency_micros), (2, 1, 30));
+        assert_eq!(a.mean_latency(), Duration::from_micros(20));
+        let b = &snapshot.hosts["b:1"];
+        assert_eq!((b.connection_failures, b.reconnections), (1, 1));
+        assert_eq!(seen.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::config::AuthConfig;
+use crate::frame::messages::error::ErrorCode;
+use crate::frame::messages::event::ClusterEvent;
+use crate::frame::messages::prepared::prepared_id;
+use crate::frame::messages::query::Query;
+use crate::frame::messages::query_result::row_set::{Column, ColumnType, RowSet};
+use crate::frame::messages::query_result::QueryResult;
+use crate::frame::server_handle::{ConnectionState, Node};
+use crate::frame::Frame;
+use crate::security::EncryptionHandler;
+use std::collections::{BTreeSet, HashMap};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Rows of a `pages` table, which the node answers in pages.
+const PAGED_ROWS: usize = 5;
+/// Rows of a `tracks` table, large enough for its result to be compressed.
+const TRACK_ROWS: usize = 200;
+
+struct MockNode {
+    /// Time the node takes to answer each query other than `USE`.
+    delay: Duration,
+    prepared: Mutex<HashMap<Vec<u8>, String>>,
+}
+
+impl Node for MockNode {
+    fn resend_query_as_internal_message(
+        &self,
+        query: Query,
+        keyspace: Option<String>,
+    ) -> Result<QueryResult, ErrorCode> {
+        if let Some(keyspace) = query.query_string.strip_prefix("USE ") {
+            return Ok(QueryResult::SetKeyspace(
+                keyspace.trim_end_matches(';').to_string(),
+            ));
+        }
+        thread::sleep(self.delay);
+        if query.query_string.contains(".pages") {
+            return Ok(page_of_rows(&query));
+        }
+        if query.query_string.contains(".tracks") {
+            let rows: Vec<String> = (0..TRACK_ROWS)
+                .map(|id| {
+                    format!(
+                        r#"{{"id":"{}","latitude":"-34.8","longitude":"-58.5"}}"#,
+                        id
+                    )
+                })
+                .collect();
+            return Ok(rows_of(&format!("[{}]", rows.join(",")), None));
+        }
+        if let Some(rows) = system_schema_rows(&query.query_string) {
+            return Ok(rows_of(rows, None));
+        }
+        // Una tabla sin keyspace necesita el de la conexion
+        if query.query_string.contains("FROM flights") && keyspace.is_none() {
+            return Err(ErrorCode::Invalid);
+        }
+        Ok(QueryResult::Void)
+    }
+
+    fn prepare(&self, query_string: &str) -> Vec<u8> {
+        let id = prepared_id(query_string);
+        self.prepared
+            .lock()
+            .unwrap()
+            .insert(id.clone(), query_string.to_string());
+        id
+    }
+
+    fn prepared_statement(&self, id: &[u8]) -> Option<String> {
+        self.prepared.lock().unwrap().get(id).cloned()
+    }
+}
+
+/// Answers the `system_schema` tables describing an `airports.flights` table, and
+/// `system.peers` with a live node and a dead one.
+fn system_schema_rows(query_string: &str) -> Option<&'static str> {
+    if query_string.contains("system.peers") {
+        Some(
+            r#"[{"peer":"node1","rpc_address":"127.0.0.1:9042","status":"Live","last_heartbeat":"1730786400","ownership":"0.5","pending_hints":"0"},
+                {"peer":"node2","rpc_address":"127.0.0.2:9042","status":"Dead","last_heartbeat":"1730786300","ownership":"0.5","pending_hints":"4"}]"#,
+        )
+    } else if query_string.contains("system_schema.keyspaces") {
+        Some(
+            r#"[{"keyspace_name":"airports","replication":"SimpleStrategy","replication_factor":"3"}]"#,
+        )
+    } else if query_string.contains("system_schema.tables") {
+        Some(r#"[{"keyspace_name":"airports","table_name":"flights"}]"#)
+    } else if query_string.contains("system_schema.columns") {
+        Some(
+            r#"[{"keyspace_name":"airports","table_name":"flights","column_name":"origin","type":"text","kind":"partition_key","position":"0"},
+                {"keyspace_name":"airports","table_name":"flights","column_name":"departure","type":"timestamp","kind":"clustering","position":"0"},
+                {"keyspace_name":"airports","table_name":"flights","column_name":"status","type":"text","kind":"regular","position":"-1"}]"#,
+        )
+    } else {
+        None
+    }
+}
+
+/// Answers a page of the `PAGED_ROWS` rows of a `pages` table, keeping the offset of the
+/// next page in the paging state.
+fn page_of_rows(query: &Query) -> QueryResult {
+    let offset = query
+        .paging_state
+        .as_deref()
+        .and_then(|state| state.try_into().ok())
+        .map(u32::from_be_bytes)
+        .unwrap_or_default() as usize;
+    let page_size = query.result_page_size.unwrap_or(PAGED_ROWS as i32) as usize;
+
+    let end = (offset + page_size).min(PAGED_ROWS);
+    let rows: Vec<String> = (offset..end)
+        .map(|id| format!(r#"{{"id":"{}"}}"#, id))
+        .collect();
+    let paging_state = (end < PAGED_ROWS).then(|| (end as u32).to_be_bytes().to_vec());
+    rows_of(&format!("[{}]", rows.join(",")), paging_state)
+}
+
+/// Builds the result of a query from its rows as JSON, with a text column per column of
+/// the rows.
+fn rows_of(json: &str, paging_state: Option<Vec<u8>>) -> QueryResult {
+    let rows: Vec<HashMap<String, String>> = serde_json::from_str(json).unwrap();
+    let columns: BTreeSet<&String> = rows.iter().flat_map(HashMap::keys).collect();
+    let mut row_set = RowSet::new(
+        "",
+        "",
+        columns
+            .into_iter()
+            .map(|name| Column::new(name, ColumnType::Text))
+            .collect(),
+    );
+    row_set.extend_from_text(&rows);
+    QueryResult::from_row_set(&row_set, paging_state)
+}
+
+/// Starts a node that answers every query, serving each connection in its own thread as
+/// the nodes do.
+///
+/// # Returns
+/// The address of the node and the number of queries it has answered.
+pub fn start_mock_server() -> (String, Arc<AtomicUsize>) {
+    start_slow_mock_server(Duration::ZERO)
+}
+
+/// Starts a node that takes `delay` to answer each query.
+pub fn start_slow_mock_server(delay: Duration) -> (String, Arc<AtomicUsize>) {
+    start_node(delay, vec![], AuthConfig::default())
+}
+
+/// Starts a node that pushes `events` to each connection that registers for them.
+pub fn start_mock_server_with_events(events: Vec<ClusterEvent>) -> (String, Arc<AtomicUsize>) {
+    start_node(Duration::ZERO, events, AuthConfig::default())
+}
+
+/// Starts a node that only lets connect the users of `auth`, with their password.
+pub fn start_mock_server_with_users(auth: AuthConfig) -> (String, Arc<AtomicUsize>) {
+    start_node(Duration::ZERO, vec![], auth)
+}
+
+fn start_node(
+    delay: Duration,
+    events: Vec<ClusterEvent>,
+    auth: AuthConfig,
+) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let answered = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&answered);
+    let node = Arc::new(MockNode {
+        delay,
+        prepared: Mutex::new(HashMap::new()),
+    });
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let counter = Arc::clone(&counter);
+            let node = Arc::clone(&node);
+            let events = events.clone();
+            let auth = auth.clone();
+            thread::spawn(move || {
+                let mut state = ConnectionState::Uninitialized;
+                let mut encryption_handler = EncryptionHandler::new(23, 5);
+                let mut keyspace = None;
+                while let Ok(request) = encryption_handler.read(&mut stream) {
+                    let response = match state {
+                        ConnectionState::Uninitialized => {
+                            request.handle_uninitialized(&mut state, &mut encryption_handler)
+                        }
+                        ConnectionState::Ready => {
+                            if request.registered_events().is_none() {
+                                counter.fetch_add(1, Ordering::SeqCst);
+                            }
+                            request.generate_response(Arc::clone(&node) as _, &mut keyspace)
+                        }
+                        _ => request.handle_authentication(
+                            &mut state,
+                            &mut encryption_handler,
+                            &auth,
+                        ),
+                    };
+                    if encryption_handler.write(&mut stream, &response).is_err() {
+                        return;
+                    }
+                    if request.registered_events().is_some() {
+                        for event in &events {
+                            let frame = Frame::new_event(event.clone());
+                            if encryption_handler.write(&mut stream, &frame).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    });
+    (address, answered)
+}
@@ -1,176 +1,1098 @@
+mod async_manager;
 mod auth;
+mod builder;
+mod from_row;
+mod metrics;
+#[cfg(test)]
+mod mock_server;
+mod paging;
+mod peers;
+mod policy;
+mod pool;
+mod profile;
+mod row;
+mod schema;
+mod token;
 
-use auth::authenticate_to_server;
-use rand::rng;
-use rand::seq::SliceRandom;
+pub use crate::frame::compression::Compression;
+pub use crate::frame::messages::consistency_level::ConsistencyLevel;
+pub use crate::frame::messages::prepared::Value;
+pub use async_manager::AsyncClientManager;
+pub use auth::{Authenticator, PlainTextAuthenticator};
+pub use builder::ClusterBuilder;
+pub use common_derive::FromRow;
+pub use from_row::FromRow;
+pub use metrics::{
+    ClientEvent, ClientMetrics, HostSnapshot, LastRequest, Listener, MetricsSnapshot,
+};
+pub use paging::QueryIter;
+pub use peers::PeerStatus;
+pub use policy::{
+    DcAwarePolicy, LoadBalancingPolicy, RandomPolicy, RoundRobinPolicy, TokenAwarePolicy,
+};
+pub use pool::{ConnectionPool, DEFAULT_CONNECTIONS_PER_NODE, DEFAULT_REQUEST_TIMEOUT};
+pub use profile::ExecutionProfile;
+pub use row::{FromColumn, Row};
+pub use schema::{ColumnKind, ColumnMetadata, KeyspaceMetadata, SchemaMetadata, TableMetadata};
+pub use token::{partition_token, token};
+pub use uuid::Uuid;
 
-use crate::frame::messages::consistency_level::ConsistencyLevel;
 use crate::frame::Frame;
-use crate::security::EncryptionHandler;
+use pool::Connection;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io::{self};
-use std::net::TcpStream;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 const RETRIES: u8 = 3;
+/// Time between attempts while waiting for the cluster to be ready.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Start of the error of a query that isn't idempotent whose connection failed after sending
+/// it. The node may have applied it, so it isn't sent again and the caller decides.
+pub const UNANSWERED_QUERY: &str = "The query was sent but not answered";
+const SELECT_KEYSPACES: &str = "SELECT * FROM system_schema.keyspaces;";
+const SELECT_TABLES: &str = "SELECT * FROM system_schema.tables;";
+const SELECT_COLUMNS: &str = "SELECT * FROM system_schema.columns;";
+const SELECT_PEERS: &str = "SELECT * FROM system.peers;";
 
+/// A client of the cluster. Each query is sent on a connection checked out from a
+/// `ConnectionPool`, so the load is spread over the live nodes.
 #[derive(Debug)]
 pub struct ClientManager {
-    addresses: Vec<String>,
-    stream: TcpStream,
-    stream_id: i16,
-    encryption_handler: EncryptionHandler,
+    pool: Arc<ConnectionPool>,
     current_keyspace: String,
-    unanswered_queries: Vec<Frame>,
+    /// Time without response after which an idempotent query is also sent to another node,
+    /// `None` to never do it.
+    speculative_delay: Option<Duration>,
+    /// The statements prepared by the client, by their text.
+    prepared: HashMap<String, PreparedStatement>,
+    /// Consistency level of the queries sent without one.
+    default_consistency: ConsistencyLevel,
+    /// The execution profiles of the client, by name.
+    profiles: HashMap<String, ExecutionProfile>,
+    /// The last query sent, `None` before the first one.
+    last_request: Option<LastRequest>,
+}
+
+/// A statement prepared in the cluster, to run it with `ClientManager::execute`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedStatement {
+    id: Vec<u8>,
+    query_string: String,
+    idempotent: bool,
+}
+
+impl PreparedStatement {
+    /// Returns the text of the statement, with its `?` markers.
+    pub fn query_string(&self) -> &str {
+        &self.query_string
+    }
+
+    /// Marks whether the statement can be applied more than once with the same result, so
+    /// that it is sent again if its connection fails before the response. Only reads are
+    /// considered idempotent by default.
+    pub fn set_idempotent(&mut self, idempotent: bool) {
+        self.idempotent = idempotent;
+    }
 }
 
 impl ClientManager {
-    /// Creates a new `ClientManager` by connecting to the first available address and authenticating.
+    /// Creates a new `ClientManager` with a pool of connections to the given addresses and
+    /// the default settings of `ClusterBuilder`.
     pub fn new(addresses: &[String]) -> io::Result<ClientManager> {
-        let mut stream = connect_to_first_available(addresses)?;
-        println!("Connected to {:?}", stream.peer_addr());
+        Self::connect(addresses, None)
+    }
 
-        let (encryption_handler, stream_id) = authenticate_to_server(&mut stream)?;
+    /// Creates a new `ClientManager` that identifies itself as `user`, so that the nodes
+    /// record it in their audit log.
+    pub fn new_as_user(addresses: &[String], user: &str) -> io::Result<ClientManager> {
+        Self::connect(addresses, Some(user))
+    }
 
-        Ok(ClientManager {
-            addresses: addresses.to_vec(),
-            stream,
-            stream_id,
-            encryption_handler,
+    /// Creates a new `ClientManager` on a pool shared with other clients, for instance one
+    /// per thread.
+    pub fn with_pool(pool: Arc<ConnectionPool>) -> ClientManager {
+        ClientManager {
+            pool,
             current_keyspace: String::new(),
-            unanswered_queries: Vec::new(),
-        })
+            speculative_delay: None,
+            prepared: HashMap::new(),
+            default_consistency: ConsistencyLevel::default(),
+            profiles: HashMap::new(),
+            last_request: None,
+        }
+    }
+
+    /// Returns a builder to set the credentials, timeouts and defaults of the client before
+    /// connecting to the given addresses.
+    pub fn builder(addresses: &[String]) -> ClusterBuilder {
+        ClusterBuilder::new().contact_points(addresses)
+    }
+
+    fn connect(addresses: &[String], user: Option<&str>) -> io::Result<ClientManager> {
+        Self::builder(addresses)
+            .authenticator(PlainTextAuthenticator::identified_as(user))
+            .build()
+    }
+
+    /// Creates a new `ClientManager` once a node of the cluster is ready. Nodes only open
+    /// their client port after loading their data, so this retries until one accepts the
+    /// connection or `timeout` elapses.
+    pub fn connect_when_ready(
+        addresses: &[String],
+        timeout: Duration,
+    ) -> io::Result<ClientManager> {
+        let started = Instant::now();
+        loop {
+            match ClientManager::new(addresses) {
+                Ok(manager) => return Ok(manager),
+                Err(e) if started.elapsed() >= timeout => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("The cluster wasn't ready after {:?}: {}", timeout, e),
+                    ))
+                }
+                Err(_) => thread::sleep(READY_POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Sets the consistency level of the queries sent without one (`None`).
+    pub fn set_default_consistency(&mut self, consistency_level: ConsistencyLevel) {
+        self.default_consistency = consistency_level;
     }
 
-    /// Sets the current keyspace for the client.
+    /// Returns the consistency level of the queries sent without one.
+    pub fn default_consistency(&self) -> ConsistencyLevel {
+        self.default_consistency
+    }
+
+    /// Returns the node that coordinated the last query sent with `query` and its variants,
+    /// how long it took and its consistency level, `None` before the first one.
+    pub fn last_request(&self) -> Option<&LastRequest> {
+        self.last_request.as_ref()
+    }
+
+    /// Adds an execution profile, replacing the one with the same name.
+    pub(crate) fn add_execution_profile(&mut self, name: &str, profile: ExecutionProfile) {
+        self.profiles.insert(name.to_string(), profile);
+    }
+
+    /// Returns `consistency_level`, or the default one of the client if it is `None`.
+    fn consistency(&self, consistency_level: Option<ConsistencyLevel>) -> ConsistencyLevel {
+        consistency_level.unwrap_or(self.default_consistency)
+    }
+
+    /// Sets the current keyspace for the client. The pool connections switch to it the next
+    /// time they are checked out.
     pub fn use_keyspace(&mut self, keyspace: &str) -> Result<(), String> {
-        let query = format!("USE {};", keyspace);
-        let res_ks = self.query(query, "")?;
-        self.current_keyspace = res_ks;
-        Ok(())
+        self.query(format!("USE {};", keyspace), None).map(|_| ())
     }
 
-    /// Executes a query with the given consistency level.
+    /// Executes a query with the given consistency level. A `USE` changes the keyspace of
+    /// the client, not only that of the connection it is sent on.
     pub fn query(
         &mut self,
         query_string: String,
-        consistency_level: &str,
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
     ) -> Result<String, String> {
-        let consistency_level = ConsistencyLevel::from_str_to_enum(consistency_level);
+        let consistency_level = self.consistency(consistency_level.into());
+        let idempotent = is_idempotent(&query_string);
+        self.send_query(query_string, consistency_level, None, idempotent)
+    }
 
-        let query = Frame::new_query(query_string, consistency_level, self.stream_id);
+    /// Executes a query waiting up to `timeout` for each response, instead of the request
+    /// timeout of the client. A query without response in time fails with a timeout error
+    /// and, if it is idempotent, is retried on another connection.
+    ///
+    /// # Parameters
+    /// - `query_string`: The query.
+    /// - `consistency_level`: The consistency level of the query.
+    /// - `timeout`: The time to wait for each response. It must not be zero.
+    pub fn query_with_timeout(
+        &mut self,
+        query_string: String,
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
+        timeout: Duration,
+    ) -> Result<String, String> {
+        if timeout.is_zero() {
+            return Err("Timeouts must not be zero".to_string());
+        }
+        let consistency_level = self.consistency(consistency_level.into());
+        let idempotent = is_idempotent(&query_string);
+        self.send_query_with(
+            query_string,
+            consistency_level,
+            None,
+            idempotent,
+            Some(timeout),
+        )
+    }
 
-        let response = self.execute_query(&query).map_err(|e| e.to_string())?;
+    /// Executes a query with the settings of an execution profile of the client.
+    ///
+    /// # Parameters
+    /// - `query_string`: The query.
+    /// - `profile`: The name of the profile, as given to `ClusterBuilder::execution_profile`.
+    ///
+    /// # Returns
+    /// The result of the query, or an error if it fails or the profile doesn't exist.
+    pub fn query_with_profile(
+        &mut self,
+        query_string: String,
+        profile: &str,
+    ) -> Result<String, String> {
+        let profile = *self
+            .profiles
+            .get(profile)
+            .ok_or_else(|| format!("Unknown execution profile {}", profile))?;
+        let consistency_level = profile.consistency.unwrap_or(self.default_consistency);
+        let idempotent = is_idempotent(&query_string);
+        self.send_query_with(
+            query_string,
+            consistency_level,
+            None,
+            idempotent,
+            profile.request_timeout,
+        )
+    }
 
-        response.handle_response(query)
+    /// Executes a query on the partition with the given key values, so that a
+    /// `TokenAwarePolicy` can send it to one of its replicas.
+    ///
+    /// # Parameters
+    /// - `query_string`: The query.
+    /// - `consistency_level`: The consistency level of the query.
+    /// - `routing_key`: The values of the partition key columns, in the order they were declared.
+    pub fn query_with_routing_key(
+        &mut self,
+        query_string: String,
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
+        routing_key: &[String],
+    ) -> Result<String, String> {
+        let consistency_level = self.consistency(consistency_level.into());
+        let idempotent = is_idempotent(&query_string);
+        self.send_query(
+            query_string,
+            consistency_level,
+            Some(routing_key),
+            idempotent,
+        )
     }
 
-    /// Writes a frame to the server, with support for retries and reconnection.
-    pub fn write(&mut self, frame: &Frame) -> io::Result<()> {
-        loop {
-            let operation = |manager: &mut ClientManager| {
-                manager.encryption_handler.write(&mut manager.stream, frame)
-            };
+    /// Executes a query and returns the rows of its result.
+    pub fn query_rows(
+        &mut self,
+        query_string: String,
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
+    ) -> Result<Vec<Row>, String> {
+        let json = self.query(query_string, consistency_level)?;
+        row::parse_rows(&json)
+    }
 
-            if self.retries(operation).is_ok() {
-                self.unanswered_queries.push(frame.clone());
-                return Ok(());
-            }
+    /// Executes a query and builds a `T` from each row of its result.
+    ///
+    /// # Parameters
+    /// - `query_string`: The query, usually a `SELECT`.
+    /// - `consistency_level`: The consistency level of the query.
+    ///
+    /// # Returns
+    /// The rows, or an error if the query fails or a row doesn't match `T`.
+    pub fn query_as<T: FromRow>(
+        &mut self,
+        query_string: String,
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
+    ) -> Result<Vec<T>, String> {
+        let json = self.query(query_string, consistency_level)?;
+        from_row::rows_as(&json)
+    }
+
+    /// Returns the schema of the cluster, read from the `system_schema` tables of a node.
+    /// Nodes that haven't received a schema change yet may answer an older schema.
+    ///
+    /// # Returns
+    /// The keyspaces, with their tables and columns, or an error if the query fails.
+    pub fn metadata(&mut self) -> Result<SchemaMetadata, String> {
+        let keyspaces = self.query_as(SELECT_KEYSPACES.to_string(), None)?;
+        let tables = self.query_as(SELECT_TABLES.to_string(), None)?;
+        let columns = self.query_as(SELECT_COLUMNS.to_string(), None)?;
+        Ok(schema::build_schema(keyspaces, tables, columns))
+    }
+
+    /// Reads the nodes of the cluster from `system.peers`, as the node that answers sees
+    /// them, for example to show the health of the cluster every so often.
+    ///
+    /// # Returns
+    /// Each node with its status, ownership of the ring and pending hints, in order of id, or
+    /// an error if the query fails.
+    pub fn peers(&mut self) -> Result<Vec<PeerStatus>, String> {
+        self.query_as(SELECT_PEERS.to_string(), None)
+    }
 
-            self.reconnect()?;
+    /// Executes a query and returns an iterator over its rows, which fetches them from the
+    /// nodes `page_size` at a time as it advances. Each page may be answered by a different
+    /// node.
+    ///
+    /// # Parameters
+    /// - `query_string`: The query, usually a `SELECT`.
+    /// - `consistency_level`: The consistency level of each page.
+    /// - `page_size`: The number of rows fetched on each request.
+    pub fn query_iter(
+        &mut self,
+        query_string: String,
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
+        page_size: usize,
+    ) -> QueryIter<'_> {
+        let consistency_level = self.consistency(consistency_level.into());
+        QueryIter::new(self, query_string, consistency_level, page_size)
+    }
+
+    /// Prepares a statement with `?` markers for its values. Statements already prepared by
+    /// the client are not sent again.
+    pub fn prepare(&mut self, query_string: &str) -> Result<PreparedStatement, String> {
+        if let Some(statement) = self.prepared.get(query_string) {
+            return Ok(statement.clone());
         }
+        let id = self.run(None, true, |connection| {
+            prepare_on(connection, query_string)
+        })?;
+
+        let statement = PreparedStatement {
+            id,
+            query_string: query_string.to_string(),
+            idempotent: is_idempotent(query_string),
+        };
+        self.prepared
+            .insert(query_string.to_string(), statement.clone());
+        Ok(statement)
     }
 
-    /// Reads a frame from the server, with support for retries and reconnection.
-    pub fn read(&mut self) -> io::Result<Frame> {
-        let operation =
-            |manager: &mut ClientManager| manager.encryption_handler.read(&mut manager.stream);
+    /// Runs a prepared statement with the given values. If the node doesn't know the
+    /// statement, because it restarted or it was prepared on another node, it is prepared
+    /// again on it.
+    ///
+    /// # Parameters
+    /// - `statement`: The statement returned by `prepare`.
+    /// - `values`: The values of the markers, in order.
+    /// - `consistency_level`: The consistency level of the query.
+    pub fn execute(
+        &mut self,
+        statement: &PreparedStatement,
+        values: &[Value],
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
+    ) -> Result<String, String> {
+        let consistency_level = self.consistency(consistency_level.into());
 
-        loop {
-            match self.retries(operation) {
-                Ok(frame) => {
-                    self.unanswered_queries.pop();
-                    return Ok(frame);
-                }
-                Err(_) => {
-                    self.reconnect()?;
-                    self.retry_pending_query()?;
+        self.run(None, statement.idempotent, |connection| {
+            let execute = |connection: &mut Connection| {
+                let stream_id = connection.next_stream_id();
+                Frame::new_execute(statement.id.clone(), values, consistency_level, stream_id)
+            };
+            let mut request = execute(connection);
+            let mut response = connection.exchange(&request)?;
+            if response.is_unprepared() {
+                if let Err(e) = prepare_on(connection, &statement.query_string)? {
+                    return Ok(Err(e));
                 }
+                request = execute(connection);
+                response = connection.exchange(&request)?;
             }
-        }
+            Ok(response.handle_response(request))
+        })
+    }
+
+    /// Returns the requests, errors, latencies and reconnections of each node, counted over
+    /// every client that shares the pool.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.pool.metrics().snapshot()
+    }
+
+    /// Adds a callback run on every request and every node that goes down or comes back,
+    /// for example to show the health of the connections.
+    pub fn add_listener<F>(&self, listener: F)
+    where
+        F: Fn(&ClientEvent) + Send + Sync + 'static,
+    {
+        self.pool.metrics().add_listener(Arc::new(listener));
+    }
+
+    /// Enables speculative execution: an idempotent query that gets no response within
+    /// `delay` is sent to a second node as well, and the first response is used. `None`
+    /// disables it.
+    pub fn set_speculative_execution(&mut self, delay: Option<Duration>) {
+        self.speculative_delay = delay;
+    }
+
+    /// Executes a query that can be applied more than once with the same result, like a
+    /// read or an `INSERT` of fixed values. With speculative execution enabled it may run on
+    /// two nodes.
+    ///
+    /// # Parameters
+    /// - `query_string`: The query.
+    /// - `consistency_level`: The consistency level of the query.
+    /// - `routing_key`: The values of the partition key columns, if known.
+    pub fn query_idempotent(
+        &mut self,
+        query_string: String,
+        consistency_level: impl Into<Option<ConsistencyLevel>>,
+        routing_key: Option<&[String]>,
+    ) -> Result<String, String> {
+        let consistency_level = self.consistency(consistency_level.into());
+        self.send_query(query_string, consistency_level, routing_key, true)
     }
 }
 
 impl ClientManager {
-    fn execute_query(&mut self, query: &Frame) -> io::Result<Frame> {
-        self.write(query)?;
-        self.read()
+    fn send_query(
+        &mut self,
+        query_string: String,
+        consistency_level: ConsistencyLevel,
+        routing_key: Option<&[String]>,
+        idempotent: bool,
+    ) -> Result<String, String> {
+        self.send_query_with(
+            query_string,
+            consistency_level,
+            routing_key,
+            idempotent,
+            None,
+        )
     }
 
-    fn retry_pending_query(&mut self) -> io::Result<()> {
-        match self.unanswered_queries.pop() {
-            Some(frame) => self.write(&frame),
-            None => Ok(()),
+    /// Sends a query, waiting up to `timeout` for each response if it is set. With speculative
+    /// execution enabled an idempotent query may also be sent to a second node.
+    fn send_query_with(
+        &mut self,
+        query_string: String,
+        consistency_level: ConsistencyLevel,
+        routing_key: Option<&[String]>,
+        idempotent: bool,
+        timeout: Option<Duration>,
+    ) -> Result<String, String> {
+        match self.speculative_delay {
+            Some(delay) if idempotent && used_keyspace(&query_string).is_none() => self.speculate(
+                query_string,
+                consistency_level,
+                routing_key,
+                delay,
+                timeout,
+            ),
+            _ => self.send_once(query_string, consistency_level, routing_key, idempotent, timeout),
         }
     }
 
-    /// Handles retries for a given operation.
-    fn retries<F, T>(&mut self, mut operation: F) -> io::Result<T>
+    /// Sends a query to a single node at a time, waiting up to `timeout` for each response if
+    /// it is set.
+    fn send_once(
+        &mut self,
+        query_string: String,
+        consistency_level: ConsistencyLevel,
+        routing_key: Option<&[String]>,
+        idempotent: bool,
+        timeout: Option<Duration>,
+    ) -> Result<String, String> {
+        let keyspace = used_keyspace(&query_string);
+
+        let start = Instant::now();
+        let coordinator = RefCell::new(None);
+        let result = self.run(routing_key, idempotent, |connection| {
+            // Se queda con el nodo del ultimo intento
+            coordinator.replace(Some(connection.address().to_string()));
+            let query = Frame::new_query(
+                query_string.clone(),
+                consistency_level,
+                connection.next_stream_id(),
+            );
+            let response = connection.exchange_within(&query, timeout)?;
+            let result = response.handle_response(query);
+            if let (Ok(_), Some(keyspace)) = (&result, &keyspace) {
+                connection.keyspace = keyspace.clone();
+            }
+            Ok(result)
+        });
+        if let Some(coordinator) = coordinator.into_inner() {
+            self.last_request = Some(LastRequest {
+                coordinator,
+                latency: start.elapsed(),
+                consistency: consistency_level,
+                success: result.is_ok(),
+            });
+        }
+        let result = result?;
+
+        if let Some(keyspace) = keyspace {
+            self.current_keyspace = keyspace;
+        }
+        Ok(result)
+    }
+
+    /// Fetches a page of the rows of a query.
+    ///
+    /// # Returns
+    /// The rows of the page and the state to request the next one, `None` on the last page.
+    fn query_page(
+        &self,
+        query_string: &str,
+        consistency_level: ConsistencyLevel,
+        page_size: i32,
+        paging_state: Option<Vec<u8>>,
+    ) -> Result<(String, Option<Vec<u8>>), String> {
+        self.run(None, true, |connection| {
+            let query = Frame::new_paged_query(
+                query_string.to_string(),
+                consistency_level,
+                page_size,
+                paging_state.clone(),
+                connection.next_stream_id(),
+            );
+            let response = connection.exchange(&query)?;
+            Ok(response.handle_page_response(query))
+        })
+    }
+
+    /// Runs a request on a connection of the pool with the current keyspace in use. If the
+    /// connection fails, it is discarded and the request runs on another one, up to
+    /// `RETRIES` times. A request that isn't idempotent is only sent again if the node
+    /// never got it; otherwise it fails with an `UNANSWERED_QUERY` error.
+    fn run<T, F>(
+        &self,
+        routing_key: Option<&[String]>,
+        idempotent: bool,
+        request: F,
+    ) -> Result<T, String>
     where
-        F: FnMut(&mut Self) -> io::Result<T>,
+        F: Fn(&mut Connection) -> io::Result<Result<T, String>>,
     {
         let mut attempts = 0;
+        loop {
+            let mut connection = self.pool.checkout(routing_key).map_err(|e| e.to_string())?;
 
-        while attempts < RETRIES {
-            match operation(self) {
-                Ok(result) => return Ok(result),
-                Err(_) => {
+            // Si falla el USE previo, el request nunca llego al nodo
+            let mut sent = false;
+            let result =
+                set_keyspace(&mut connection, &self.current_keyspace).and_then(|keyspace_set| {
+                    match keyspace_set {
+                        Ok(()) => {
+                            sent = true;
+                            request(&mut connection)
+                        }
+                        Err(e) => Ok(Err(e)),
+                    }
+                });
+
+            match result {
+                Ok(result) => {
+                    self.pool.checkin(connection);
+                    return result;
+                }
+                Err(e) => {
+                    let unanswered = sent && connection.is_unanswered();
+                    self.pool.discard(connection, &e);
+                    if unanswered && !idempotent {
+                        return Err(format!("{}: {}", UNANSWERED_QUERY, e));
+                    }
+                    if attempts >= RETRIES {
+                        return Err(format!("Failed after {} attempts: {}", RETRIES, e));
+                    }
                     attempts += 1;
-                    eprintln!(
-                        "({}) Attempt {} failed, retrying...",
-                        self.stream_id, attempts
-                    );
+                    eprintln!("Attempt {} failed ({}), retrying...", attempts, e);
                 }
             }
         }
+    }
+
+    /// Sends an idempotent query and, if it gets no response within `delay`, sends it to
+    /// another node too. Each attempt waits up to `timeout` for its response if it is set.
+    /// Returns the first response, recording its node as the coordinator of the request. If
+    /// every attempt fails, the query is retried as any other.
+    fn speculate(
+        &mut self,
+        query_string: String,
+        consistency_level: ConsistencyLevel,
+        routing_key: Option<&[String]>,
+        delay: Duration,
+        timeout: Option<Duration>,
+    ) -> Result<String, String> {
+        let (sender, receiver) = mpsc::channel();
+
+        let start = Instant::now();
+        let first = self.pool.checkout(routing_key).map_err(|e| e.to_string())?;
+        let first_node = first.address().to_string();
+        self.spawn_attempt(first, &query_string, consistency_level, timeout, sender.clone());
+        let mut pending = 1;
+
+        let mut answer = receiver.recv_timeout(delay).ok();
+        if answer.is_none() {
+            // El primer nodo tarda: se manda la misma query a otro
+            if let Some(second) = self.pool.try_checkout(routing_key, &[first_node]) {
+                self.spawn_attempt(second, &query_string, consistency_level, timeout, sender);
+                pending += 1;
+            }
+        }
 
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed after {} attempts", RETRIES),
-        ))
+        while pending > 0 {
+            let (coordinator, result) = match answer.take() {
+                Some(answer) => answer,
+                None => match receiver.recv() {
+                    Ok(answer) => answer,
+                    Err(_) => break,
+                },
+            };
+            pending -= 1;
+            if let Ok(result) = result {
+                self.last_request = Some(LastRequest {
+                    coordinator,
+                    latency: start.elapsed(),
+                    consistency: consistency_level,
+                    success: result.is_ok(),
+                });
+                return result;
+            }
+        }
+        self.send_once(query_string, consistency_level, routing_key, true, timeout)
     }
 
-    /// Attempts to reconnect the client manager to a server.
-    fn reconnect(&mut self) -> io::Result<()> {
-        eprintln!("Failed after {} attempts, reconnecting...", RETRIES);
+    /// Sends a query on its own thread, waiting up to `timeout` for the response if it is
+    /// set, and returns the connection to the pool when it's done. The node of the connection
+    /// is sent along with the result.
+    fn spawn_attempt(
+        &self,
+        mut connection: Connection,
+        query_string: &str,
+        consistency: ConsistencyLevel,
+        timeout: Option<Duration>,
+        sender: Sender<(String, io::Result<Result<String, String>>)>,
+    ) {
+        let pool = Arc::clone(&self.pool);
+        let keyspace = self.current_keyspace.clone();
+        let query_string = query_string.to_string();
+        thread::spawn(move || {
+            let result =
+                set_keyspace(&mut connection, &keyspace).and_then(
+                    |keyspace_set| match keyspace_set {
+                        Ok(()) => {
+                            let query = Frame::new_query(
+                                query_string,
+                                consistency,
+                                connection.next_stream_id(),
+                            );
+                            let response = connection.exchange_within(&query, timeout)?;
+                            Ok(response.handle_response(query))
+                        }
+                        Err(e) => Ok(Err(e)),
+                    },
+                );
+            let coordinator = connection.address().to_string();
+            match &result {
+                Ok(_) => pool.checkin(connection),
+                Err(e) => pool.discard(connection, e),
+            }
+            // Si ya respondio el otro nodo nadie espera esta respuesta
+            let _ = sender.send((coordinator, result));
+        });
+    }
+}
 
-        let mock_manager = ClientManager::new(&self.addresses)?;
+/// Prepares a statement on the node of a connection.
+fn prepare_on(
+    connection: &mut Connection,
+    query_string: &str,
+) -> io::Result<Result<Vec<u8>, String>> {
+    let prepare = Frame::new_prepare(query_string.to_string(), connection.next_stream_id());
+    let response = connection.exchange(&prepare)?;
+    Ok(response.prepared_id())
+}
 
-        self.stream = mock_manager.stream;
-        self.stream_id = mock_manager.stream_id;
-        self.encryption_handler = mock_manager.encryption_handler;
+/// Switches a connection to `keyspace` if it uses another one. An empty keyspace leaves the
+/// connection as it is.
+fn set_keyspace(connection: &mut Connection, keyspace: &str) -> io::Result<Result<(), String>> {
+    if keyspace.is_empty() || connection.keyspace == keyspace {
+        return Ok(Ok(()));
+    }
+    let use_keyspace = Frame::new_query(
+        format!("USE {};", keyspace),
+        ConsistencyLevel::default(),
+        connection.next_stream_id(),
+    );
+    let response = connection.exchange(&use_keyspace)?;
+    Ok(response.handle_response(use_keyspace).map(|_| {
+        connection.keyspace = keyspace.to_string();
+    }))
+}
+
+/// Returns whether a statement can be sent again after its connection failed without risk
+/// of applying it twice: reads and `USE`.
+pub(crate) fn is_idempotent(query_string: &str) -> bool {
+    query_string
+        .split_whitespace()
+        .next()
+        .is_some_and(|keyword| {
+            keyword.eq_ignore_ascii_case("SELECT") || keyword.eq_ignore_ascii_case("USE")
+        })
+}
 
-        let current_keyspace = self.current_keyspace.clone();
-        self.use_keyspace(&current_keyspace)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+/// Returns the keyspace of a `USE` statement, `None` for any other statement.
+fn used_keyspace(query_string: &str) -> Option<String> {
+    let mut words = query_string.split_whitespace();
+    match words.next() {
+        Some(keyword) if keyword.eq_ignore_ascii_case("USE") => words
+            .next()
+            .map(|keyspace| keyspace.trim_end_matches(';').to_string()),
+        _ => None,
     }
 }
 
-/// Connects to the first available address from the given list.
-fn connect_to_first_available(addresses: &[String]) -> io::Result<TcpStream> {
-    let mut shuffle = addresses.to_vec();
-    shuffle.shuffle(&mut rng());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connect_when_ready_times_out() {
+        let addresses = vec!["127.0.0.1:1".to_string()];
+        let error = ClientManager::connect_when_ready(&addresses, Duration::ZERO).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn queries_are_spread_over_the_nodes() {
+        let (first, first_answered) = mock_server::start_mock_server();
+        let (second, second_answered) = mock_server::start_mock_server();
+        let pool = Arc::new(ConnectionPool::new(&[first, second], 2, None).unwrap());
 
-    for address in &shuffle {
-        match TcpStream::connect(address) {
-            Ok(stream) => return Ok(stream),
-            Err(e) => eprintln!("Failed to connect to {}: {}", address, e),
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let mut manager = ClientManager::with_pool(Arc::clone(&pool));
+                thread::spawn(move || {
+                    manager.use_keyspace("airports")?;
+                    for _ in 0..5 {
+                        manager
+                            .query("SELECT * FROM flights".to_string(), ConsistencyLevel::One)?;
+                    }
+                    Ok::<(), String>(())
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Ok(()));
         }
+
+        assert!(first_answered.load(std::sync::atomic::Ordering::SeqCst) > 0);
+        assert!(second_answered.load(std::sync::atomic::Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn prepared_statements_are_prepared_again_on_other_nodes() {
+        let (first, first_answered) = mock_server::start_mock_server();
+        let (second, second_answered) = mock_server::start_mock_server();
+        let pool = ConnectionPool::new(&[first, second], 1, None).unwrap();
+        let mut manager = ClientManager::with_pool(Arc::new(pool));
+
+        let statement = manager
+            .prepare("INSERT INTO airports.flights (id, status) VALUES (?, ?)")
+            .unwrap();
+        assert_eq!(
+            manager.prepare(statement.query_string()),
+            Ok(statement.clone())
+        );
+
+        for id in 0..4 {
+            let values = [Value::Int(id), Value::Text("on time".to_string())];
+            assert_eq!(
+                manager.execute(&statement, &values, ConsistencyLevel::One),
+                Ok(String::new())
+            );
+        }
+        // Falta un valor
+        assert!(manager
+            .execute(&statement, &[Value::Int(5)], ConsistencyLevel::One)
+            .is_err());
+
+        // Cada nodo preparo la query y ejecuto la mitad
+        assert!(first_answered.load(std::sync::atomic::Ordering::SeqCst) >= 3);
+        assert!(second_answered.load(std::sync::atomic::Ordering::SeqCst) >= 3);
     }
 
-    Err(io::Error::new(
-        io::ErrorKind::ConnectionRefused,
-        format!(
-            "Could not connect to any of the specified addresses: {:?}",
-            addresses
-        ),
-    ))
+    #[test]
+    fn query_iter_fetches_the_rows_page_by_page() {
+        let (address, answered) = mock_server::start_mock_server();
+        let mut manager = ClientManager::new(&[address]).unwrap();
+
+        let rows: Result<Vec<_>, String> = manager
+            .query_iter(
+                "SELECT * FROM airports.pages".to_string(),
+                ConsistencyLevel::One,
+                2,
+            )
+            .collect();
+        let ids: Vec<String> = rows
+            .unwrap()
+            .into_iter()
+            .map(|row| row.get("id").unwrap())
+            .collect();
+        assert_eq!(ids, ["0", "1", "2", "3", "4"]);
+        // Tres paginas: 2, 2 y 1 filas
+        assert_eq!(answered.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let mut rows = manager.query_iter(
+            "SELECT * FROM flights".to_string(),
+            ConsistencyLevel::One,
+            2,
+        );
+        assert!(matches!(rows.next(), Some(Err(_))));
+        assert!(rows.next().is_none());
+    }
+
+    #[test]
+    fn query_as_builds_a_struct_from_each_row() {
+        #[derive(Debug, FromRow)]
+        struct Page {
+            id: u32,
+        }
+
+        let (address, _) = mock_server::start_mock_server();
+        let mut manager = ClientManager::new(&[address]).unwrap();
+
+        let pages: Vec<Page> = manager
+            .query_as(
+                "SELECT id FROM airports.pages".to_string(),
+                ConsistencyLevel::One,
+            )
+            .unwrap();
+        let ids: Vec<u32> = pages.iter().map(|page| page.id).collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn queries_without_response_in_time_fail_with_a_timeout() {
+        let (address, answered) = mock_server::start_slow_mock_server(Duration::from_millis(300));
+        let mut manager = ClientManager::new(&[address]).unwrap();
+
+        let error = manager
+            .query_with_timeout(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One,
+                Duration::from_millis(50),
+            )
+            .unwrap_err();
+        assert!(error.contains("No response from"));
+        // La lectura es idempotente, asi que se reintenta
+        assert_eq!(
+            answered.load(std::sync::atomic::Ordering::SeqCst),
+            1 + RETRIES as usize
+        );
+
+        assert!(manager
+            .query_with_timeout(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One,
+                Duration::from_secs(2),
+            )
+            .is_ok());
+        assert!(manager
+            .query_with_timeout(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One,
+                Duration::ZERO
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn metadata_describes_the_tables_of_the_cluster() {
+        let (address, _) = mock_server::start_mock_server();
+        let mut manager = ClientManager::new(&[address]).unwrap();
+
+        let schema = manager.metadata().unwrap();
+        let flights = schema.table("airports", "flights").unwrap();
+        assert_eq!(flights.partition_key, ["origin"]);
+        assert_eq!(flights.clustering_key, ["departure"]);
+        assert_eq!(flights.column("status").unwrap().column_type, "text");
+        assert_eq!(
+            schema.keyspace("airports").unwrap().replication,
+            "SimpleStrategy"
+        );
+    }
+
+    #[test]
+    fn the_last_request_names_its_coordinator_and_consistency() {
+        let (address, _) = mock_server::start_mock_server();
+        let mut manager = ClientManager::new(std::slice::from_ref(&address)).unwrap();
+        assert_eq!(manager.last_request(), None);
+
+        manager.set_default_consistency(ConsistencyLevel::Quorum);
+        assert_eq!(manager.default_consistency(), ConsistencyLevel::Quorum);
+        manager.use_keyspace("airports").unwrap();
+        manager.query("SELECT * FROM flights".to_string(), None).unwrap();
+        let last = manager.last_request().unwrap();
+        assert_eq!(last.coordinator, address);
+        assert_eq!(last.consistency, ConsistencyLevel::Quorum);
+        assert!(last.success);
+
+        manager
+            .query("SELECT * FROM flights".to_string(), ConsistencyLevel::All)
+            .unwrap();
+        assert_eq!(
+            manager.last_request().unwrap().consistency,
+            ConsistencyLevel::All
+        );
+    }
+
+    #[test]
+    fn peers_are_read_from_the_system_table() {
+        let (address, _) = mock_server::start_mock_server();
+        let mut manager = ClientManager::new(&[address]).unwrap();
+
+        let peers = manager.peers().unwrap();
+        let states: Vec<(&str, bool, u32)> = peers
+            .iter()
+            .map(|peer| (peer.node_id.as_str(), peer.is_up(), peer.pending_hints))
+            .collect();
+        assert_eq!(states, [("node1", true, 0), ("node2", false, 4)]);
+        assert_eq!(peers[1].ownership, 0.5);
+    }
+
+    #[test]
+    fn requests_are_recorded_by_node() {
+        let (address, _) = mock_server::start_mock_server();
+        let mut manager = ClientManager::new(std::slice::from_ref(&address)).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        manager.add_listener(move |event| {
+            let _ = sender.send(event.clone());
+        });
+
+        // Sin keyspace el nodo responde con un error
+        assert!(manager
+            .query("SELECT * FROM flights".to_string(), ConsistencyLevel::One)
+            .is_err());
+        manager.use_keyspace("airports").unwrap();
+        manager
+            .query("SELECT * FROM flights".to_string(), ConsistencyLevel::One)
+            .unwrap();
+
+        let host = &manager.metrics().hosts[&address];
+        assert_eq!((host.requests, host.errors), (3, 1));
+        let events: Vec<ClientEvent> = receiver.try_iter().collect();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            &events[0],
+            ClientEvent::Request { success: false, .. }
+        ));
+        assert!(matches!(
+            &events[2],
+            ClientEvent::Request { success: true, .. }
+        ));
+    }
+
+    #[test]
+    fn slow_idempotent_queries_are_also_sent_to_another_node() {
+        let (slow, _) = mock_server::start_slow_mock_server(Duration::from_secs(3));
+        let (fast, fast_answered) = mock_server::start_mock_server();
+        // Round-robin empieza por el nodo lento
+        let pool = ConnectionPool::new(&[slow, fast.clone()], 1, None).unwrap();
+        let mut manager = ClientManager::with_pool(Arc::new(pool));
+        manager.set_speculative_execution(Some(Duration::from_millis(50)));
+
+        let started = Instant::now();
+        manager
+            .query_idempotent(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One,
+                None,
+            )
+            .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(fast_answered.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(manager.last_request().unwrap().coordinator, fast);
+    }
+
+    #[test]
+    fn speculative_attempts_wait_only_for_the_timeout_of_the_query() {
+        let (first, _) = mock_server::start_slow_mock_server(Duration::from_secs(3));
+        let (second, _) = mock_server::start_slow_mock_server(Duration::from_secs(3));
+        let pool = ConnectionPool::new(&[first, second], 1, None).unwrap();
+        let mut manager = ClientManager::with_pool(Arc::new(pool));
+        manager.set_speculative_execution(Some(Duration::from_millis(20)));
+
+        let started = Instant::now();
+        assert!(manager
+            .query_with_timeout(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One,
+                Duration::from_millis(100),
+            )
+            .is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(!manager.last_request().unwrap().success);
+    }
+
+    #[test]
+    fn only_idempotent_queries_are_sent_again_without_response() {
+        use std::sync::atomic::Ordering;
+
+        let (address, answered) = mock_server::start_slow_mock_server(Duration::from_millis(300));
+        let mut manager = ClientManager::builder(&[address])
+            .request_timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        // El nodo recibio el INSERT y puede haberlo aplicado
+        let error = manager
+            .query(
+                "INSERT INTO airports.flights (id) VALUES (1)".to_string(),
+                ConsistencyLevel::One,
+            )
+            .unwrap_err();
+        assert!(error.starts_with(UNANSWERED_QUERY));
+        assert_eq!(answered.load(Ordering::SeqCst), 1);
+
+        let error = manager
+            .query(
+                "SELECT * FROM airports.flights".to_string(),
+                ConsistencyLevel::One,
+            )
+            .unwrap_err();
+        assert!(!error.starts_with(UNANSWERED_QUERY));
+        assert_eq!(answered.load(Ordering::SeqCst), 2 + RETRIES as usize);
+
+        assert!(is_idempotent("  select * FROM flights"));
+        assert!(!is_idempotent("UPDATE flights SET status = 'landed'"));
+    }
+
+    #[test]
+    fn routed_queries_go_to_the_owner_of_the_partition() {
+        let (first, first_answered) = mock_server::start_mock_server();
+        let (second, second_answered) = mock_server::start_mock_server();
+        let routing_key = vec!["20".to_string()];
+        let ring = match partition_token(&routing_key) <= u64::MAX / 2 {
+            true => vec![
+                (first.clone(), 0, u64::MAX / 2),
+                (second.clone(), u64::MAX / 2 + 1, u64::MAX),
+            ],
+            false => vec![
+                (second.clone(), 0, u64::MAX / 2),
+                (first.clone(), u64::MAX / 2 + 1, u64::MAX),
+            ],
+        };
+        let policy = TokenAwarePolicy::new(ring, Box::new(RoundRobinPolicy::default()));
+        let pool =
+            ConnectionPool::with_policy(&[first, second], 2, None, Box::new(policy)).unwrap();
+        let mut manager = ClientManager::with_pool(Arc::new(pool));
+
+        for _ in 0..5 {
+            manager
+                .query_with_routing_key(
+                    "SELECT * FROM airports.flights WHERE origin = 20".to_string(),
+                    ConsistencyLevel::One,
+                    &routing_key,
+                )
+                .unwrap();
+        }
+
+        assert_eq!(first_answered.load(std::sync::atomic::Ordering::SeqCst), 5);
+        assert_eq!(second_answered.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
 }
@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+
+use super::row::{parse_rows, Row};
+use super::ClientManager;
+use crate::frame::messages::consistency_level::ConsistencyLevel;
+
+/// The rows of a query, fetched one page at a time: the next page is requested with the
+/// paging state of the previous one when the rows already fetched run out, so only one page
+/// is kept in memory. Returned by `ClientManager::query_iter`.
+#[derive(Debug)]
+pub struct QueryIter<'a> {
+    client: &'a mut ClientManager,
+    query_string: String,
+    consistency_level: ConsistencyLevel,
+    page_size: i32,
+    rows: VecDeque<Row>,
+    /// The state to request the next page, `None` after the last one.
+    paging_state: Option<Vec<u8>>,
+    first_page: bool,
+}
+
+impl<'a> QueryIter<'a> {
+    pub(super) fn new(
+        client: &'a mut ClientManager,
+        query_string: String,
+        consistency_level: ConsistencyLevel,
+        page_size: usize,
+    ) -> Self {
+        Self {
+            client,
+            query_string,
+            consistency_level,
+            page_size: page_size.clamp(1, i32::MAX as usize) as i32,
+            rows: VecDeque::new(),
+            paging_state: None,
+            first_page: true,
+        }
+    }
+
+    fn has_more_pages(&self) -> bool {
+        self.first_page || self.paging_state.is_some()
+    }
+
+    /// Fetches the next page. If it fails the iteration ends, since the paging state was
+    /// consumed.
+    fn fetch_page(&mut self) -> Result<(), String> {
+        self.first_page = false;
+        let (json, paging_state) = self.client.query_page(
+            &self.query_string,
+            self.consistency_level,
+            self.page_size,
+            self.paging_state.take(),
+        )?;
+        self.paging_state = paging_state;
+
+        self.rows.extend(parse_rows(&json)?);
+        Ok(())
+    }
+}
+
+impl Iterator for QueryIter<'_> {
+    type Item = Result<Row, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Una pagina puede venir vacia sin ser la ultima
+        while self.rows.is_empty() && self.has_more_pages() {
+            if let Err(e) = self.fetch_page() {
+                return Some(Err(e));
+            }
+        }
+        self.rows.pop_front().map(Ok)
+    }
+}
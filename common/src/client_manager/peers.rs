@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+use crate::client_manager::FromRow;
+
+/// A node of the cluster as seen by the node that answered, a row of `system.peers`, as
+/// returned by `ClientManager::peers`.
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct PeerStatus {
+    #[row(rename = "peer")]
+    pub node_id: String,
+    /// The address clients connect to the node at, `ip:port`.
+    pub rpc_address: String,
+    /// The status of the node in the gossip table, e.g. `Live` or `Dead`.
+    pub status: String,
+    pub last_heartbeat: i64,
+    /// Fraction of the token ring whose primary replica is the node.
+    pub ownership: f64,
+    /// Hints the node that answered keeps for the node.
+    pub pending_hints: u32,
+}
+
+impl PeerStatus {
+    /// Returns whether the node is up.
+    pub fn is_up(&self) -> bool {
+        self.status == "Live"
+    }
+}
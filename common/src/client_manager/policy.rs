@@ -0,0 +1,208 @@
+use super::token::partition_token;
+use rand::rng;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Decides in which order a client tries the nodes for each query.
+pub trait LoadBalancingPolicy: Debug + Send + Sync {
+    /// Returns the query plan: the nodes to try, the preferred one first.
+    ///
+    /// # Parameters
+    /// - `nodes`: The addresses of the live nodes.
+    /// - `routing_key`: The values of the partition key of the query, if the client knows them.
+    fn query_plan(&self, nodes: &[String], routing_key: Option<&[String]>) -> Vec<String>;
+}
+
+/// Starts each plan on the node after the one the previous plan started on.
+#[derive(Debug, Default)]
+pub struct RoundRobinPolicy {
+    next: AtomicUsize,
+}
+
+impl LoadBalancingPolicy for RoundRobinPolicy {
+    fn query_plan(&self, nodes: &[String], _routing_key: Option<&[String]>) -> Vec<String> {
+        if nodes.is_empty() {
+            return vec![];
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % nodes.len();
+        nodes[start..]
+            .iter()
+            .chain(&nodes[..start])
+            .cloned()
+            .collect()
+    }
+}
+
+/// Tries the nodes in a random order.
+#[derive(Debug, Default)]
+pub struct RandomPolicy;
+
+impl LoadBalancingPolicy for RandomPolicy {
+    fn query_plan(&self, nodes: &[String], _routing_key: Option<&[String]>) -> Vec<String> {
+        let mut plan = nodes.to_vec();
+        plan.shuffle(&mut rng());
+        plan
+    }
+}
+
+/// Tries the nodes of the local datacenter first, in round-robin, and the remote ones
+/// only after them.
+#[derive(Debug)]
+pub struct DcAwarePolicy {
+    local_dc: String,
+    /// The datacenter of each node address. Nodes not listed are considered remote.
+    datacenters: HashMap<String, String>,
+    local: RoundRobinPolicy,
+    remote: RoundRobinPolicy,
+}
+
+impl DcAwarePolicy {
+    /// Creates the policy of a client that runs in `local_dc`.
+    ///
+    /// # Parameters
+    /// - `local_dc`: The datacenter of the client.
+    /// - `datacenters`: The datacenter of each node address.
+    pub fn new(local_dc: &str, datacenters: HashMap<String, String>) -> Self {
+        Self {
+            local_dc: local_dc.to_string(),
+            datacenters,
+            local: RoundRobinPolicy::default(),
+            remote: RoundRobinPolicy::default(),
+        }
+    }
+}
+
+impl LoadBalancingPolicy for DcAwarePolicy {
+    fn query_plan(&self, nodes: &[String], routing_key: Option<&[String]>) -> Vec<String> {
+        let (local, remote): (Vec<String>, Vec<String>) = nodes
+            .iter()
+            .cloned()
+            .partition(|node| self.datacenters.get(node) == Some(&self.local_dc));
+
+        let mut plan = self.local.query_plan(&local, routing_key);
+        plan.extend(self.remote.query_plan(&remote, routing_key));
+        plan
+    }
+}
+
+/// Sends the queries with a routing key to the node that owns the partition, followed by
+/// the next nodes of the ring, where the replicas are. The other queries follow the plan of
+/// `child`.
+#[derive(Debug)]
+pub struct TokenAwarePolicy {
+    /// The address of each node with the first and last token of its range, both
+    /// inclusive, in ring order.
+    ring: Vec<(String, u64, u64)>,
+    child: Box<dyn LoadBalancingPolicy>,
+}
+
+impl TokenAwarePolicy {
+    /// Creates the policy from the token ranges of the nodes, as answered by the RING admin
+    /// request with each node id replaced by its address.
+    ///
+    /// # Parameters
+    /// - `ring`: The address and token range of each node, in ring order.
+    /// - `child`: The policy for the queries without routing key.
+    pub fn new(ring: Vec<(String, u64, u64)>, child: Box<dyn LoadBalancingPolicy>) -> Self {
+        Self { ring, child }
+    }
+}
+
+impl LoadBalancingPolicy for TokenAwarePolicy {
+    fn query_plan(&self, nodes: &[String], routing_key: Option<&[String]>) -> Vec<String> {
+        let child_plan = self.child.query_plan(nodes, routing_key);
+        let Some(routing_key) = routing_key else {
+            return child_plan;
+        };
+        let token = partition_token(routing_key);
+        let Some(owner) = self
+            .ring
+            .iter()
+            .position(|(_, start, end)| (*start..=*end).contains(&token))
+        else {
+            return child_plan;
+        };
+
+        let mut plan: Vec<String> = self.ring[owner..]
+            .iter()
+            .chain(&self.ring[..owner])
+            .map(|(address, _, _)| address.clone())
+            .filter(|address| nodes.contains(address))
+            .collect();
+        let others: Vec<String> = child_plan
+            .into_iter()
+            .filter(|node| !plan.contains(node))
+            .collect();
+        plan.extend(others);
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes() -> Vec<String> {
+        vec!["a:1".to_string(), "b:1".to_string(), "c:1".to_string()]
+    }
+
+    #[test]
+    fn round_robin_starts_each_plan_on_the_next_node() {
+        let policy = RoundRobinPolicy::default();
+        assert_eq!(policy.query_plan(&nodes(), None), nodes());
+        assert_eq!(policy.query_plan(&nodes(), None), ["b:1", "c:1", "a:1"]);
+        assert!(policy.query_plan(&[], None).is_empty());
+    }
+
+    #[test]
+    fn dc_aware_prefers_the_local_nodes() {
+        let datacenters = HashMap::from([
+            ("a:1".to_string(), "west".to_string()),
+            ("b:1".to_string(), "east".to_string()),
+            ("c:1".to_string(), "east".to_string()),
+        ]);
+        let policy = DcAwarePolicy::new("east", datacenters);
+        assert_eq!(policy.query_plan(&nodes(), None), ["b:1", "c:1", "a:1"]);
+        assert_eq!(policy.query_plan(&nodes(), None), ["c:1", "b:1", "a:1"]);
+    }
+
+    #[test]
+    fn token_aware_starts_on_the_owner_of_the_partition() {
+        let third = u64::MAX / 3;
+        let ring = vec![
+            ("a:1".to_string(), 0, third),
+            ("b:1".to_string(), third + 1, 2 * third),
+            ("c:1".to_string(), 2 * third + 1, u64::MAX),
+        ];
+        let policy = TokenAwarePolicy::new(ring.clone(), Box::new(RoundRobinPolicy::default()));
+
+        let routing_key = vec!["20".to_string()];
+        let token = partition_token(&routing_key);
+        let owner = ring
+            .iter()
+            .position(|(_, start, end)| (*start..=*end).contains(&token))
+            .unwrap();
+
+        let plan = policy.query_plan(&nodes(), Some(&routing_key));
+        assert_eq!(plan[0], ring[owner].0);
+        assert_eq!(plan[1], ring[(owner + 1) % 3].0);
+
+        // Sin el dueño vivo, empieza por la siguiente replica
+        let live: Vec<String> = nodes()
+            .into_iter()
+            .filter(|node| *node != ring[owner].0)
+            .collect();
+        let plan = policy.query_plan(&live, Some(&routing_key));
+        assert_eq!(
+            plan,
+            [
+                ring[(owner + 1) % 3].0.clone(),
+                ring[(owner + 2) % 3].0.clone()
+            ]
+        );
+
+        assert_eq!(policy.query_plan(&nodes(), None).len(), 3);
+    }
+}
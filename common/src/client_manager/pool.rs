@@ -0,0 +1,780 @@
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::auth::{authenticate_to_server, Authenticator, PlainTextAuthenticator};
+use super::metrics::{ClientEvent, ClientMetrics};
+use super::policy::{LoadBalancingPolicy, RoundRobinPolicy};
+use crate::frame::compression::Compression;
+use crate::frame::messages::event::{ClusterEvent, STATUS_CHANGE, TOPOLOGY_CHANGE};
+use crate::frame::Frame;
+use crate::security::EncryptionHandler;
+
+/// Connections a client opens at most to each node by default.
+pub const DEFAULT_CONNECTIONS_PER_NODE: usize = 2;
+/// Time a client waits by default for the response of a request, so that a node that
+/// stopped answering doesn't block it forever.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(12);
+/// Time a node that failed is skipped before connecting to it again.
+const DOWN_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Time the connection registered for events waits for one before checking whether the pool
+/// was dropped.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How the connections of a pool are opened.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionSettings {
+    pub(crate) authenticator: Arc<dyn Authenticator>,
+    /// Time to connect to a node and authenticate, `None` to wait as long as it takes.
+    pub(crate) connect_timeout: Option<Duration>,
+    /// Time to send a request and read its response, `None` to wait as long as it takes.
+    pub(crate) request_timeout: Option<Duration>,
+    /// The compression asked for to the nodes, `None` to send the frames uncompressed.
+    pub(crate) compression: Option<Compression>,
+}
+
+impl ConnectionSettings {
+    /// Settings without a connect timeout and with the default request timeout.
+    pub(crate) fn new(authenticator: Arc<dyn Authenticator>) -> Self {
+        ConnectionSettings {
+            authenticator,
+            connect_timeout: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+            compression: None,
+        }
+    }
+}
+
+/// An authenticated connection to a node.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    address: String,
+    stream: TcpStream,
+    stream_id: i16,
+    encryption_handler: EncryptionHandler,
+    /// The keyspace set with `USE` on this connection, empty if none.
+    pub(crate) keyspace: String,
+    metrics: Arc<ClientMetrics>,
+    /// Time to wait for each response unless the request sets its own.
+    request_timeout: Option<Duration>,
+    /// The stream id of the request sent without a response yet, if any.
+    unanswered: Option<i16>,
+}
+
+impl Connection {
+    fn open(
+        address: &str,
+        settings: &ConnectionSettings,
+        metrics: &Arc<ClientMetrics>,
+    ) -> io::Result<Connection> {
+        let mut stream = match settings.connect_timeout {
+            Some(timeout) => connect_timeout(address, timeout)?,
+            None => TcpStream::connect(address)?,
+        };
+        // El handshake tambien cuenta como parte de la conexion
+        stream.set_read_timeout(settings.connect_timeout)?;
+        let (encryption_handler, stream_id) = authenticate_to_server(
+            &mut stream,
+            settings.authenticator.as_ref(),
+            settings.compression,
+        )?;
+        stream.set_read_timeout(settings.request_timeout)?;
+        stream.set_write_timeout(settings.request_timeout)?;
+
+        Ok(Connection {
+            address: address.to_string(),
+            stream,
+            stream_id,
+            encryption_handler,
+            keyspace: String::new(),
+            metrics: Arc::clone(metrics),
+            request_timeout: settings.request_timeout,
+            unanswered: None,
+        })
+    }
+
+    /// Returns the stream id for a new request. Each request gets its own, so a response
+    /// can't be taken for that of another request.
+    pub(crate) fn next_stream_id(&mut self) -> i16 {
+        // Los ids negativos son de los eventos que manda el nodo
+        self.stream_id = self.stream_id.wrapping_add(1).max(0);
+        self.stream_id
+    }
+
+    /// Returns whether a request was sent on the connection and its response never arrived,
+    /// so the node may have applied it.
+    pub(crate) fn is_unanswered(&self) -> bool {
+        self.unanswered.is_some()
+    }
+
+    pub(crate) fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Sends a frame and reads the response of the node, recording the request in the
+    /// metrics of the pool.
+    pub(crate) fn exchange(&mut self, frame: &Frame) -> io::Result<Frame> {
+        let start = Instant::now();
+        let response = self.send(frame).and_then(|_| self.receive());
+
+        self.metrics.record(ClientEvent::Request {
+            address: self.address.clone(),
+            latency: start.elapsed(),
+            success: response.as_ref().is_ok_and(|response| !response.is_error()),
+        });
+        response
+    }
+
+    fn send(&mut self, frame: &Frame) -> io::Result<()> {
+        self.encryption_handler.write(&mut self.stream, frame)?;
+        self.unanswered = Some(frame.stream_id());
+        Ok(())
+    }
+
+    /// Reads the response of the request sent last, failing if it answers another one or
+    /// it doesn't arrive within the read timeout of the socket.
+    fn receive(&mut self) -> io::Result<Frame> {
+        let response = self
+            .encryption_handler
+            .read(&mut self.stream)
+            .map_err(|e| match e.kind() {
+                // Segun la plataforma, un read que vence devuelve uno u otro
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "No response from {} within {:?}",
+                        self.address,
+                        self.stream
+                            .read_timeout()
+                            .ok()
+                            .flatten()
+                            .unwrap_or_default()
+                    ),
+                ),
+                _ => e,
+            })?;
+        match self.unanswered {
+            Some(stream_id) if stream_id != response.stream_id() => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Received the response of stream {} while waiting for stream {}",
+                    response.stream_id(),
+                    stream_id
+                ),
+            )),
+            _ => {
+                self.unanswered = None;
+                Ok(response)
+            }
+        }
+    }
+
+    /// Sends a frame and waits up to `timeout` for the response, or as long as the
+    /// connection was opened with if it is `None`.
+    pub(crate) fn exchange_within(
+        &mut self,
+        frame: &Frame,
+        timeout: Option<Duration>,
+    ) -> io::Result<Frame> {
+        let Some(timeout) = timeout else {
+            return self.exchange(frame);
+        };
+        self.stream.set_read_timeout(Some(timeout))?;
+        let response = self.exchange(frame);
+        self.stream.set_read_timeout(self.request_timeout)?;
+        response
+    }
+
+    /// Asks the node to push the changes of the topology and the status of the cluster on
+    /// this connection, which is then only used to read them.
+    fn register_for_events(&mut self) -> io::Result<()> {
+        let event_types = vec![TOPOLOGY_CHANGE.to_string(), STATUS_CHANGE.to_string()];
+        let register = Frame::new_register(event_types, self.next_stream_id());
+        self.encryption_handler.write(&mut self.stream, &register)?;
+        let response = self.encryption_handler.read(&mut self.stream)?;
+        if !response.is_ready() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} did not accept the registration for events",
+                    self.address
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for an event pushed by the node.
+    ///
+    /// # Returns
+    /// The event, `None` if none arrived in time, or an error if the connection was closed.
+    fn next_event(&mut self, timeout: Duration) -> io::Result<Option<ClusterEvent>> {
+        // Solo se espera con timeout a que llegue algo, para no cortar un frame a la mitad
+        self.stream.set_read_timeout(Some(timeout))?;
+        let arrived = self.stream.peek(&mut [0u8]);
+        self.stream.set_read_timeout(None)?;
+        match arrived {
+            Ok(0) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(_) => {
+                let frame = self.encryption_handler.read(&mut self.stream)?;
+                Ok(frame.cluster_event().cloned())
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Connects to the first address `address` resolves to that accepts within `timeout`.
+fn connect_timeout(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} does not resolve to any address", address),
+    );
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+#[derive(Debug)]
+struct NodeConnections {
+    address: String,
+    idle: Vec<Connection>,
+    /// Connections open to the node, idle or checked out.
+    open: usize,
+    /// When the node last failed, `None` while it answers.
+    down_since: Option<Instant>,
+    /// Whether the cluster reported the node down. It is skipped until it is reported up.
+    reported_down: bool,
+}
+
+impl NodeConnections {
+    /// A node without connections, as reported by the cluster when it joins.
+    fn new(address: &str) -> Self {
+        NodeConnections {
+            address: address.to_string(),
+            idle: vec![],
+            open: 0,
+            down_since: None,
+            reported_down: false,
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        !self.reported_down
+            && self
+                .down_since
+                .is_none_or(|since| since.elapsed() >= DOWN_RETRY_INTERVAL)
+    }
+}
+
+/// What a checkout can do next.
+enum Slot {
+    Idle(Connection),
+    /// Open a new connection to the address, whose slot is already reserved.
+    New(String),
+    /// Every connection is checked out: wait for one to be returned.
+    Busy,
+    /// No node is left to try.
+    Unavailable,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    nodes: Vec<NodeConnections>,
+}
+
+impl PoolState {
+    /// Returns the addresses of the live nodes, or of every node if they are all down so
+    /// that they are tried again.
+    fn live_nodes(&self, failed: &[String]) -> Vec<String> {
+        let candidates: Vec<&NodeConnections> = self
+            .nodes
+            .iter()
+            .filter(|node| !failed.contains(&node.address))
+            .collect();
+        let everyone_down = candidates.iter().all(|node| !node.is_live());
+        candidates
+            .into_iter()
+            .filter(|node| everyone_down || node.is_live())
+            .map(|node| node.address.clone())
+            .collect()
+    }
+
+    /// Takes a connection to the first node of the plan that has one idle or can open one.
+    fn next_slot(&mut self, plan: &[String], connections_per_node: usize) -> Slot {
+        if plan.is_empty() {
+            return Slot::Unavailable;
+        }
+        for address in plan {
+            let Some(node) = self.node_mut(address) else {
+                continue;
+            };
+            if let Some(connection) = node.idle.pop() {
+                return Slot::Idle(connection);
+            }
+            if node.open < connections_per_node {
+                node.open += 1;
+                return Slot::New(node.address.clone());
+            }
+        }
+        Slot::Busy
+    }
+
+    fn node_mut(&mut self, address: &str) -> Option<&mut NodeConnections> {
+        self.nodes.iter_mut().find(|node| node.address == address)
+    }
+
+    /// Releases the slot of a connection that failed and marks its node down, closing the
+    /// idle connections to it.
+    fn node_failed(&mut self, address: &str) {
+        if let Some(node) = self.node_mut(address) {
+            node.open = node.open.saturating_sub(1 + node.idle.len());
+            node.idle.clear();
+            node.down_since = Some(Instant::now());
+        }
+    }
+
+    /// Updates the nodes with a change reported by the cluster.
+    fn apply_event(&mut self, event: &ClusterEvent) {
+        match event {
+            ClusterEvent::NewNode(address) | ClusterEvent::Up(address) => {
+                match self.node_mut(address) {
+                    Some(node) => {
+                        node.reported_down = false;
+                        node.down_since = None;
+                    }
+                    None => self.nodes.push(NodeConnections::new(address)),
+                }
+            }
+            ClusterEvent::Down(address) => {
+                if let Some(node) = self.node_mut(address) {
+                    node.open = node.open.saturating_sub(node.idle.len());
+                    node.idle.clear();
+                    node.reported_down = true;
+                }
+            }
+            ClusterEvent::RemovedNode(address) => {
+                self.nodes.retain(|node| &node.address != address);
+            }
+        }
+    }
+}
+
+/// Authenticated connections to every node of the cluster, up to `connections_per_node`
+/// each. Requests check out a connection to the first node of the plan of the
+/// `LoadBalancingPolicy` that has one available, and return it when they get their
+/// response. It can be shared between threads.
+#[derive(Debug)]
+pub struct ConnectionPool {
+    settings: ConnectionSettings,
+    connections_per_node: usize,
+    policy: Box<dyn LoadBalancingPolicy>,
+    state: Mutex<PoolState>,
+    /// Notified when a connection is returned or a slot released.
+    available: Condvar,
+    metrics: Arc<ClientMetrics>,
+}
+
+impl ConnectionPool {
+    /// Creates a pool that rotates over the nodes with a `RoundRobinPolicy`.
+    ///
+    /// # Parameters
+    /// - `addresses`: The addresses of the nodes.
+    /// - `connections_per_node`: The maximum number of connections open to each node.
+    /// - `user`: The user the client identifies itself as, `None` for an anonymous client.
+    pub fn new(
+        addresses: &[String],
+        connections_per_node: usize,
+        user: Option<&str>,
+    ) -> io::Result<ConnectionPool> {
+        Self::with_policy(
+            addresses,
+            connections_per_node,
+            user,
+            Box::new(RoundRobinPolicy::default()),
+        )
+    }
+
+    /// Creates a pool whose connections identify the client as `user`.
+    ///
+    /// # Parameters
+    /// - `addresses`: The addresses of the nodes.
+    /// - `connections_per_node`: The maximum number of connections open to each node.
+    /// - `user`: The user the client identifies itself as, `None` for an anonymous client.
+    /// - `policy`: Decides which node each query is sent to.
+    pub fn with_policy(
+        addresses: &[String],
+        connections_per_node: usize,
+        user: Option<&str>,
+        policy: Box<dyn LoadBalancingPolicy>,
+    ) -> io::Result<ConnectionPool> {
+        Self::with_authenticator(
+            addresses,
+            connections_per_node,
+            Arc::new(PlainTextAuthenticator::identified_as(user)),
+            policy,
+        )
+    }
+
+    /// Creates a pool whose connections authenticate with `authenticator`.
+    ///
+    /// # Parameters
+    /// - `addresses`: The addresses of the nodes.
+    /// - `connections_per_node`: The maximum number of connections open to each node.
+    /// - `authenticator`: Builds the credentials each connection authenticates with.
+    /// - `policy`: Decides which node each query is sent to.
+    pub fn with_authenticator(
+        addresses: &[String],
+        connections_per_node: usize,
+        authenticator: Arc<dyn Authenticator>,
+        policy: Box<dyn LoadBalancingPolicy>,
+    ) -> io::Result<ConnectionPool> {
+        Self::with_settings(
+            addresses,
+            connections_per_node,
+            ConnectionSettings::new(authenticator),
+            policy,
+        )
+    }
+
+    /// Creates a pool, opening a first connection to each node to find out which ones are
+    /// up.
+    ///
+    /// # Parameters
+    /// - `addresses`: The addresses of the nodes.
+    /// - `connections_per_node`: The maximum number of connections open to each node.
+    /// - `settings`: How each connection is opened.
+    /// - `policy`: Decides which node each query is sent to.
+    ///
+    /// # Returns
+    /// The pool, or a `ConnectionRefused` error if no node accepted the connection.
+    pub(crate) fn with_settings(
+        addresses: &[String],
+        connections_per_node: usize,
+        settings: ConnectionSettings,
+        policy: Box<dyn LoadBalancingPolicy>,
+    ) -> io::Result<ConnectionPool> {
+        let metrics = Arc::new(ClientMetrics::default());
+        let nodes: Vec<NodeConnections> = addresses
+            .iter()
+            .map(
+                |address| match Connection::open(address, &settings, &metrics) {
+                    Ok(connection) => NodeConnections {
+                        idle: vec![connection],
+                        open: 1,
+                        ..NodeConnections::new(address)
+                    },
+                    Err(e) => {
+                        eprintln!("Failed to connect to {}: {}", address, e);
+                        metrics.record(ClientEvent::NodeDown {
+                            address: address.clone(),
+                            error: e.to_string(),
+                        });
+                        NodeConnections {
+                            down_since: Some(Instant::now()),
+                            ..NodeConnections::new(address)
+                        }
+                    }
+                },
+            )
+            .collect();
+
+        if nodes.iter().all(|node| node.down_since.is_some()) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!(
+                    "Could not connect to any of the specified addresses: {:?}",
+                    addresses
+                ),
+            ));
+        }
+
+        Ok(ConnectionPool {
+            settings,
+            connections_per_node: connections_per_node.max(1),
+            policy,
+            state: Mutex::new(PoolState { nodes }),
+            available: Condvar::new(),
+            metrics,
+        })
+    }
+
+    /// Returns the metrics of the requests sent through the pool, where listeners can be
+    /// added.
+    pub fn metrics(&self) -> &ClientMetrics {
+        &self.metrics
+    }
+
+    /// Returns the addresses of the nodes the pool sends requests to.
+    pub fn live_nodes(&self) -> Vec<String> {
+        self.lock()
+            .nodes
+            .iter()
+            .filter(|node| node.is_live())
+            .map(|node| node.address.clone())
+            .collect()
+    }
+
+    /// Keeps the nodes of the pool up to date with the changes of the cluster: a thread keeps
+    /// a connection to a live node registered for its events, adding the nodes that join and
+    /// skipping those reported down. The thread stops once the pool is dropped.
+    pub fn watch_topology(self: &Arc<Self>) {
+        let pool = Arc::downgrade(self);
+        thread::spawn(move || {
+            while let Some(mut connection) = Self::open_event_connection(&pool) {
+                loop {
+                    let event = connection.next_event(EVENT_POLL_INTERVAL);
+                    let Some(pool) = pool.upgrade() else {
+                        return;
+                    };
+                    match event {
+                        Ok(Some(event)) => pool.apply_event(&event),
+                        Ok(None) => {}
+                        // Se registra en otro nodo
+                        Err(_) => break,
+                    }
+                }
+            }
+        });
+    }
+
+    /// Opens a connection registered for the events of the cluster to a live node, trying
+    /// again every `DOWN_RETRY_INTERVAL` while none accepts it.
+    ///
+    /// # Returns
+    /// The connection, or `None` once the pool is dropped.
+    fn open_event_connection(pool: &Weak<Self>) -> Option<Connection> {
+        loop {
+            let current = pool.upgrade()?;
+            for address in current.live_nodes() {
+                let connection = Connection::open(&address, &current.settings, &current.metrics)
+                    .and_then(|mut connection| {
+                        connection.register_for_events()?;
+                        Ok(connection)
+                    });
+                if let Ok(connection) = connection {
+                    return Some(connection);
+                }
+            }
+            drop(current);
+            thread::sleep(DOWN_RETRY_INTERVAL);
+        }
+    }
+
+    /// Updates the nodes of the pool with a change reported by the cluster.
+    pub(crate) fn apply_event(&self, event: &ClusterEvent) {
+        self.lock().apply_event(event);
+        // Un nodo nuevo puede tener conexiones disponibles
+        self.available.notify_all();
+    }
+
+    /// Checks out a connection following the plan of the policy, opening it if the node has
+    /// none idle. Blocks while every connection is checked out.
+    ///
+    /// # Parameters
+    /// - `routing_key`: The values of the partition key of the query, if known.
+    pub(crate) fn checkout(&self, routing_key: Option<&[String]>) -> io::Result<Connection> {
+        self.take(routing_key, &[], true)
+    }
+
+    /// Checks out a connection to a node other than those in `exclude`, without waiting if
+    /// every connection to them is checked out.
+    ///
+    /// # Returns
+    /// The connection, or `None` if no other node has one available.
+    pub(crate) fn try_checkout(
+        &self,
+        routing_key: Option<&[String]>,
+        exclude: &[String],
+    ) -> Option<Connection> {
+        self.take(routing_key, exclude, false).ok()
+    }
+
+    fn take(
+        &self,
+        routing_key: Option<&[String]>,
+        exclude: &[String],
+        wait: bool,
+    ) -> io::Result<Connection> {
+        let mut failed = exclude.to_vec();
+        let mut state = self.lock();
+        loop {
+            let plan = self
+                .policy
+                .query_plan(&state.live_nodes(&failed), routing_key);
+            match state.next_slot(&plan, self.connections_per_node) {
+                Slot::Idle(connection) => return Ok(connection),
+                Slot::New(address) => {
+                    let reconnecting = state
+                        .node_mut(&address)
+                        .is_some_and(|node| node.down_since.is_some());
+                    drop(state);
+                    match Connection::open(&address, &self.settings, &self.metrics) {
+                        Ok(connection) => {
+                            if reconnecting {
+                                self.metrics.record(ClientEvent::Reconnected { address });
+                            }
+                            return Ok(connection);
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to connect to {}: {}", address, e);
+                            self.metrics.record(ClientEvent::NodeDown {
+                                address: address.clone(),
+                                error: e.to_string(),
+                            });
+                            state = self.lock();
+                            state.node_failed(&address);
+                            failed.push(address);
+                        }
+                    }
+                }
+                Slot::Busy if !wait => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "Every connection is checked out",
+                    ))
+                }
+                Slot::Busy => {
+                    state = self
+                        .available
+                        .wait(state)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+                Slot::Unavailable => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        "Could not connect to any node",
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Returns a connection that got its response, so that other requests can use it.
+    pub(crate) fn checkin(&self, connection: Connection) {
+        let mut state = self.lock();
+        if let Some(node) = state.node_mut(&connection.address) {
+            node.down_since = None;
+            // La conexion a un nodo reportado caido se cierra
+            if node.reported_down {
+                node.open = node.open.saturating_sub(1);
+            } else {
+                node.idle.push(connection);
+            }
+        }
+        self.available.notify_one();
+    }
+
+    /// Closes a connection that failed, marking its node down.
+    pub(crate) fn discard(&self, connection: Connection, error: &io::Error) {
+        self.lock().node_failed(&connection.address);
+        self.available.notify_all();
+        self.metrics.record(ClientEvent::NodeDown {
+            address: connection.address,
+            error: error.to_string(),
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_manager::mock_server::{start_mock_server, start_mock_server_with_events};
+
+    #[test]
+    fn connections_rotate_over_the_live_nodes() {
+        let (first, _) = start_mock_server();
+        let (second, _) = start_mock_server();
+        let addresses = vec![first.clone(), second.clone(), "127.0.0.1:1".to_string()];
+        let pool = ConnectionPool::new(&addresses, 1, None).unwrap();
+
+        let mut live = pool.live_nodes();
+        live.sort();
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_eq!(live, expected);
+
+        let a = pool.checkout(None).unwrap();
+        let b = pool.checkout(None).unwrap();
+        assert_ne!(a.address, b.address);
+
+        pool.checkin(a);
+        let c = pool.checkout(None).unwrap();
+        pool.discard(b, &io::Error::from(io::ErrorKind::ConnectionReset));
+        pool.checkin(c);
+        assert_eq!(pool.live_nodes().len(), 1);
+
+        let hosts = pool.metrics().snapshot().hosts;
+        assert_eq!(hosts["127.0.0.1:1"].connection_failures, 1);
+        assert_eq!(
+            hosts
+                .values()
+                .map(|host| host.connection_failures)
+                .sum::<u64>(),
+            2
+        );
+    }
+
+    #[test]
+    fn nodes_follow_the_events_of_the_cluster() {
+        let (first, _) = start_mock_server();
+        let pool = ConnectionPool::new(std::slice::from_ref(&first), 1, None).unwrap();
+
+        pool.apply_event(&ClusterEvent::NewNode("127.0.0.1:9043".to_string()));
+        assert_eq!(
+            pool.live_nodes(),
+            [first.clone(), "127.0.0.1:9043".to_string()]
+        );
+
+        pool.apply_event(&ClusterEvent::Down(first.clone()));
+        assert_eq!(pool.live_nodes(), ["127.0.0.1:9043"]);
+
+        pool.apply_event(&ClusterEvent::Up(first.clone()));
+        pool.apply_event(&ClusterEvent::RemovedNode("127.0.0.1:9043".to_string()));
+        assert_eq!(pool.live_nodes(), [first]);
+    }
+
+    #[test]
+    fn the_watcher_adds_the_nodes_that_join() {
+        let (second, _) = start_mock_server();
+        let (first, answered) =
+            start_mock_server_with_events(vec![ClusterEvent::NewNode(second.clone())]);
+        let pool = Arc::new(ConnectionPool::new(std::slice::from_ref(&first), 1, None).unwrap());
+        pool.watch_topology();
+
+        let started = Instant::now();
+        while pool.live_nodes().len() < 2 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pool.live_nodes(), [first, second]);
+        assert_eq!(answered.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn no_node_available() {
+        let addresses = vec!["127.0.0.1:1".to_string()];
+        let error = ConnectionPool::new(&addresses, 1, None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
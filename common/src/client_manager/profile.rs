@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use crate::frame::messages::consistency_level::ConsistencyLevel;
+
+/// Settings shared by the queries of a workload, for instance `ONE` and a short timeout for
+/// the positions the simulator writes, or `QUORUM` and a longer one for the reads of a
+/// dashboard. Profiles are added to the client with `ClusterBuilder::execution_profile` and
+/// chosen by name with `ClientManager::query_with_profile`. A setting left unset falls back
+/// to that of the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExecutionProfile {
+    pub(crate) consistency: Option<ConsistencyLevel>,
+    pub(crate) request_timeout: Option<Duration>,
+}
+
+impl ExecutionProfile {
+    /// Creates a profile that uses the settings of the client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the consistency level of the queries.
+    pub fn consistency(mut self, consistency_level: ConsistencyLevel) -> Self {
+        self.consistency = Some(consistency_level);
+        self
+    }
+
+    /// Sets the time to wait for the response of each query. It must not be zero.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+}
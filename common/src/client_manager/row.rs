@@ -0,0 +1,151 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::frame::messages::prepared::TIMESTAMP_FORMAT;
+
+/// A row of the result of a query. The nodes answer every column as text; `get` parses it
+/// into the type the caller expects.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Row {
+    columns: HashMap<String, String>,
+}
+
+impl Row {
+    /// Returns the value of a column.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the column.
+    ///
+    /// # Returns
+    /// The value, or an error if the column is missing (unless `T` is an `Option`) or its
+    /// value can't be parsed as a `T`.
+    pub fn get<T: FromColumn>(&self, name: &str) -> Result<T, String> {
+        let value = self.columns.get(name).map(String::as_str);
+        T::from_column(value).map_err(|e| format!("{} in column {}", e, name))
+    }
+
+    /// Returns the names of the columns of the row.
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.columns.keys().map(String::as_str)
+    }
+}
+
+impl From<HashMap<String, String>> for Row {
+    fn from(columns: HashMap<String, String>) -> Self {
+        Row { columns }
+    }
+}
+
+/// Parses the rows answered by a node for a query.
+pub(crate) fn parse_rows(json: &str) -> Result<Vec<Row>, String> {
+    serde_json::from_str(json).map_err(|e| format!("Invalid rows: {}", e))
+}
+
+/// A type a column of a `Row` can be read as.
+pub trait FromColumn: Sized {
+    /// Parses the value of a column, `None` if the row doesn't have it.
+    fn from_column(value: Option<&str>) -> Result<Self, String>;
+}
+
+/// Returns the value of a column that must be present.
+fn required(value: Option<&str>) -> Result<&str, String> {
+    value.ok_or_else(|| "Missing value".to_string())
+}
+
+macro_rules! from_column_with_from_str {
+    ($($type:ty),*) => {
+        $(
+            impl FromColumn for $type {
+                fn from_column(value: Option<&str>) -> Result<Self, String> {
+                    let value = required(value)?;
+                    value
+                        .parse()
+                        .map_err(|e| format!("Invalid value {:?} ({})", value, e))
+                }
+            }
+        )*
+    };
+}
+
+from_column_with_from_str!(i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, bool, String);
+
+impl FromColumn for DateTime<Utc> {
+    fn from_column(value: Option<&str>) -> Result<Self, String> {
+        let value = required(value)?;
+        // Los nodos escriben los timestamps sin zona horaria, en UTC
+        NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT)
+            .map(|timestamp| timestamp.and_utc())
+            .or_else(|_| DateTime::parse_from_rfc3339(value).map(|date| date.to_utc()))
+            .map_err(|e| format!("Invalid value {:?} ({})", value, e))
+    }
+}
+
+impl FromColumn for Uuid {
+    fn from_column(value: Option<&str>) -> Result<Self, String> {
+        let value = required(value)?;
+        Uuid::parse_str(value).map_err(|e| format!("Invalid value {:?} ({})", value, e))
+    }
+}
+
+/// A missing, empty or `null` column is `None`.
+impl<T: FromColumn> FromColumn for Option<T> {
+    fn from_column(value: Option<&str>) -> Result<Self, String> {
+        match value {
+            None | Some("") | Some("null") => Ok(None),
+            value => T::from_column(value).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row() -> Row {
+        Row::from(HashMap::from([
+            ("flight_id".to_string(), "20".to_string()),
+            ("latitude".to_string(), "-34.8".to_string()),
+            ("on_time".to_string(), "true".to_string()),
+            ("altitude".to_string(), "".to_string()),
+            ("_timestamp".to_string(), "2024-05-01 10:30:00".to_string()),
+            (
+                "id".to_string(),
+                "67e55044-10b1-426f-9247-bb680e5fe0c8".to_string(),
+            ),
+        ]))
+    }
+
+    #[test]
+    fn columns_are_parsed_into_the_expected_type() {
+        let row = row();
+        assert_eq!(row.get::<u32>("flight_id"), Ok(20));
+        assert_eq!(row.get::<f64>("latitude"), Ok(-34.8));
+        assert_eq!(row.get::<bool>("on_time"), Ok(true));
+        assert_eq!(row.get::<Option<u16>>("altitude"), Ok(None));
+        assert_eq!(row.get::<Option<u16>>("speed"), Ok(None));
+        assert_eq!(
+            row.get::<DateTime<Utc>>("_timestamp").unwrap().to_rfc3339(),
+            "2024-05-01T10:30:00+00:00"
+        );
+        assert_eq!(
+            row.get::<Uuid>("id"),
+            Ok(Uuid::from_u128(0x67e55044_10b1_426f_9247_bb680e5fe0c8))
+        );
+    }
+
+    #[test]
+    fn missing_and_invalid_columns_are_errors() {
+        let row = row();
+        assert_eq!(
+            row.get::<String>("status"),
+            Err("Missing value in column status".to_string())
+        );
+        assert!(row
+            .get::<u32>("latitude")
+            .unwrap_err()
+            .ends_with("in column latitude"));
+    }
+}
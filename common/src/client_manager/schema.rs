@@ -0,0 +1,204 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::client_manager::FromRow;
+
+/// The schema of the cluster: its keyspaces, their tables and the columns of each one, as
+/// returned by `ClientManager::metadata`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SchemaMetadata {
+    pub keyspaces: BTreeMap<String, KeyspaceMetadata>,
+}
+
+impl SchemaMetadata {
+    /// Returns a keyspace, `None` if it doesn't exist.
+    pub fn keyspace(&self, keyspace: &str) -> Option<&KeyspaceMetadata> {
+        self.keyspaces.get(keyspace)
+    }
+
+    /// Returns a table of a keyspace, `None` if it doesn't exist.
+    pub fn table(&self, keyspace: &str, table: &str) -> Option<&TableMetadata> {
+        self.keyspace(keyspace)?.tables.get(table)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct KeyspaceMetadata {
+    pub name: String,
+    /// The replication strategy, `SimpleStrategy` or `NetworkTopologyStrategy`.
+    pub replication: String,
+    pub replication_factor: u32,
+    pub tables: BTreeMap<String, TableMetadata>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TableMetadata {
+    pub keyspace: String,
+    pub name: String,
+    /// The columns of the partition key, in order.
+    pub partition_key: Vec<String>,
+    /// The columns of the clustering key, in order.
+    pub clustering_key: Vec<String>,
+    /// Every column of the table, by name.
+    pub columns: Vec<ColumnMetadata>,
+}
+
+impl TableMetadata {
+    /// Returns a column of the table, `None` if it doesn't exist.
+    pub fn column(&self, name: &str) -> Option<&ColumnMetadata> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnMetadata {
+    pub name: String,
+    /// The type of the column, in lowercase, e.g. `int` or `text`.
+    pub column_type: String,
+    pub kind: ColumnKind,
+}
+
+/// The part of the primary key a column is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ColumnKind {
+    PartitionKey,
+    Clustering,
+    Regular,
+}
+
+/// A row of `system_schema.keyspaces`.
+#[derive(Debug, FromRow)]
+pub(crate) struct KeyspaceRow {
+    keyspace_name: String,
+    replication: String,
+    replication_factor: u32,
+}
+
+/// A row of `system_schema.tables`.
+#[derive(Debug, FromRow)]
+pub(crate) struct TableRow {
+    keyspace_name: String,
+    table_name: String,
+}
+
+/// A row of `system_schema.columns`.
+#[derive(Debug, FromRow)]
+pub(crate) struct ColumnRow {
+    keyspace_name: String,
+    table_name: String,
+    column_name: String,
+    #[row(rename = "type")]
+    column_type: String,
+    kind: String,
+    /// Position of the column in its key, -1 for regular columns.
+    position: i32,
+}
+
+/// Builds the schema from the rows of the `system_schema` tables. Tables and columns of
+/// unknown keyspaces or tables are ignored.
+pub(crate) fn build_schema(
+    keyspaces: Vec<KeyspaceRow>,
+    tables: Vec<TableRow>,
+    mut columns: Vec<ColumnRow>,
+) -> SchemaMetadata {
+    let mut schema = SchemaMetadata::default();
+    for row in keyspaces {
+        schema.keyspaces.insert(
+            row.keyspace_name.clone(),
+            KeyspaceMetadata {
+                name: row.keyspace_name,
+                replication: row.replication,
+                replication_factor: row.replication_factor,
+                tables: BTreeMap::new(),
+            },
+        );
+    }
+    for row in tables {
+        if let Some(keyspace) = schema.keyspaces.get_mut(&row.keyspace_name) {
+            keyspace.tables.insert(
+                row.table_name.clone(),
+                TableMetadata {
+                    keyspace: row.keyspace_name,
+                    name: row.table_name,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    // Las claves quedan en el orden de su posicion
+    columns.sort_by_key(|row| row.position);
+    for row in columns {
+        let Some(table) = schema
+            .keyspaces
+            .get_mut(&row.keyspace_name)
+            .and_then(|keyspace| keyspace.tables.get_mut(&row.table_name))
+        else {
+            continue;
+        };
+        let kind = match row.kind.as_str() {
+            "partition_key" => {
+                table.partition_key.push(row.column_name.clone());
+                ColumnKind::PartitionKey
+            }
+            "clustering" => {
+                table.clustering_key.push(row.column_name.clone());
+                ColumnKind::Clustering
+            }
+            _ => ColumnKind::Regular,
+        };
+        table.columns.push(ColumnMetadata {
+            name: row.column_name,
+            column_type: row.column_type,
+            kind,
+        });
+    }
+    for table in schema
+        .keyspaces
+        .values_mut()
+        .flat_map(|keyspace| keyspace.tables.values_mut())
+    {
+        table.columns.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_manager::from_row::rows_as;
+
+    #[test]
+    fn schema_is_built_from_the_system_tables() {
+        let keyspaces = rows_as(
+            r#"[{"keyspace_name":"airports","replication":"SimpleStrategy","replication_factor":"3"}]"#,
+        )
+        .unwrap();
+        let tables = rows_as(
+            r#"[{"keyspace_name":"airports","table_name":"positions"},
+                {"keyspace_name":"unknown","table_name":"flights"}]"#,
+        )
+        .unwrap();
+        let columns = rows_as(
+            r#"[{"keyspace_name":"airports","table_name":"positions","column_name":"time","type":"timestamp","kind":"clustering","position":"0"},
+                {"keyspace_name":"airports","table_name":"positions","column_name":"flight_id","type":"int","kind":"partition_key","position":"1"},
+                {"keyspace_name":"airports","table_name":"positions","column_name":"airline","type":"text","kind":"partition_key","position":"0"},
+                {"keyspace_name":"airports","table_name":"positions","column_name":"altitude","type":"int","kind":"regular","position":"-1"}]"#,
+        )
+        .unwrap();
+
+        let schema = build_schema(keyspaces, tables, columns);
+        assert_eq!(schema.keyspaces.len(), 1);
+        assert_eq!(schema.keyspace("airports").unwrap().replication_factor, 3);
+
+        let positions = schema.table("airports", "positions").unwrap();
+        assert_eq!(positions.partition_key, ["airline", "flight_id"]);
+        assert_eq!(positions.clustering_key, ["time"]);
+        assert_eq!(
+            positions.column("altitude").unwrap().kind,
+            ColumnKind::Regular
+        );
+        assert_eq!(positions.column("time").unwrap().column_type, "timestamp");
+        assert!(schema.table("airports", "flights").is_none());
+    }
+}
@@ -0,0 +1,136 @@
+/// Returns the token of a partition as Cassandra's `Murmur3Partitioner` computes it: the
+/// first half of the 128 bits Murmur3 hash of the serialized partition key. The values are
+/// serialized as the nodes store them, as texts.
+///
+/// # Parameters
+/// - `partition_keys`: The values of the partition key columns, in order.
+pub fn token(partition_keys: &[String]) -> i64 {
+    let token = murmur3_x64_128(&serialize_partition_key(partition_keys)) as i64;
+    // Cassandra reserva el token mínimo para el inicio del anillo
+    match token {
+        i64::MIN => i64::MAX,
+        token => token,
+    }
+}
+
+/// Returns the token of a partition, the position in the ring that decides which nodes
+/// store it. It is its Murmur3 `token` moved to start at 0, so tokens keep their order. The
+/// nodes compute the position of the partitions the same way.
+pub fn partition_token(partition_keys: &[String]) -> u64 {
+    (token(partition_keys) as u64) ^ (1 << 63)
+}
+
+/// Serializes a partition key like Cassandra: a single value as is, and each value of a
+/// composite key behind its length and followed by a zero byte.
+fn serialize_partition_key(partition_keys: &[String]) -> Vec<u8> {
+    if let [key] = partition_keys {
+        return key.as_bytes().to_vec();
+    }
+    let mut bytes = vec![];
+    for key in partition_keys {
+        bytes.extend_from_slice(&(key.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
+const C1: u64 = 0x87c3_7b91_1142_53d5;
+const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+/// Returns the first 64 bits of the Murmur3 x64 128 bits hash of `data` with seed 0.
+fn murmur3_x64_128(data: &[u8]) -> u64 {
+    let (mut h1, mut h2) = (0u64, 0u64);
+    let blocks = data.chunks_exact(16);
+    let tail = blocks.remainder();
+    for block in blocks {
+        let (k1, k2) = block.split_at(8);
+        h1 ^= mix_k1(u64::from_le_bytes(k1.try_into().unwrap_or_default()));
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dc_e729);
+        h2 ^= mix_k2(u64::from_le_bytes(k2.try_into().unwrap_or_default()));
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5ab5);
+    }
+
+    let (mut k1, mut k2) = (0u64, 0u64);
+    for (i, byte) in tail.iter().enumerate() {
+        // Como Cassandra, que lee los bytes del final con signo
+        let byte = *byte as i8 as i64 as u64;
+        match i {
+            0..8 => k1 ^= byte << (8 * i),
+            _ => k2 ^= byte << (8 * (i - 8)),
+        }
+    }
+    h1 ^= mix_k1(k1);
+    h2 ^= mix_k2(k2);
+
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix(h1);
+    h2 = fmix(h2);
+    h1.wrapping_add(h2)
+}
+
+fn mix_k1(k1: u64) -> u64 {
+    k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2)
+}
+
+fn mix_k2(k2: u64) -> u64 {
+    k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1)
+}
+
+fn fmix(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^ (k >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_the_murmur3_hash_of_the_partition_key() {
+        assert_eq!(murmur3_x64_128(b""), 0);
+        assert_eq!(murmur3_x64_128(b"hello"), 0xcbd8_a7b3_41bd_9b02);
+        assert_eq!(token(&["hello".to_string()]), -3758069500696749310);
+        let fox = "The quick brown fox jumps over the lazy dog".to_string();
+        assert_eq!(token(&[fox]), -2068352364225029268);
+        // Los bytes del final que no son ASCII se leen con signo
+        let airport = "Aeroparque Jorge Newbery ñ".to_string();
+        assert_eq!(token(&[airport]), 8303049488280820251);
+        assert_eq!(token(&["hello".to_string()]), token(&["hello".to_string()]));
+        assert_ne!(token(&["EZE".to_string()]), token(&["AEP".to_string()]));
+    }
+
+    #[test]
+    fn composite_keys_are_serialized_with_the_length_of_each_value() {
+        let key = ["EZE".to_string(), "2024".to_string()];
+        assert_eq!(
+            serialize_partition_key(&key),
+            [&[0, 3][..], b"EZE", &[0, 0, 4], b"2024", &[0]].concat()
+        );
+        assert_ne!(token(&key), token(&["EZE2".to_string(), "024".to_string()]));
+    }
+
+    #[test]
+    fn positions_in_the_ring_keep_the_order_of_the_tokens() {
+        let keys: Vec<Vec<String>> = (0..100).map(|id| vec![id.to_string()]).collect();
+        let mut by_token = keys.clone();
+        by_token.sort_by_key(|key| token(key));
+        let mut by_position = keys;
+        by_position.sort_by_key(|key| partition_token(key));
+        assert_eq!(by_token, by_position);
+    }
+}
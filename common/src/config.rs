@@ -1,9 +1,24 @@
 use serde::de::DeserializeOwned;
-use std::{fs::read_to_string, io};
+use std::{collections::{BTreeMap, HashSet}, env, fs::read_to_string, io, path::Path, time::Duration};
+use toml::{Table, Value};
 
-use crate::models::{airplane::Airplane, airport::Airport};
+use crate::models::{
+    aircraft_model::AircraftModel, airplane::Airplane, airport::Airport, fleet::FleetConfig,
+    proximity::SeparationConfig, replay::ReplayConfig, schedule::Timetable,
+    tile_cache::TileCacheConfig, weather::Weather,
+};
+use crate::security::credentials::{is_password_hash, verify_password};
 
-const CONFIG_PATH: &str = "Config.toml"; // ahora este en el root del proyecto
+// ahora este en el root del proyecto; se usa el primero que exista
+const CONFIG_PATHS: [&str; 3] = ["Config.toml", "Config.yaml", "Config.yml"];
+// las variables AERO_<SECCION>_<CLAVE> pisan los valores del archivo
+const ENV_PREFIX: &str = "AERO_";
+const DEFAULT_LISTEN_ADDRESS: &str = "0.0.0.0";
+const CONSISTENCY_LEVELS: [&str; 3] = ["ONE", "QUORUM", "ALL"];
+const OVERSIZED_REPLICATIONS: [&str; 2] = ["REJECT", "CLAMP"];
+const LOG_LEVELS: [&str; 5] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+const LOG_FORMATS: [&str; 2] = ["text", "json"];
+const FSYNC_POLICIES: [&str; 3] = ["always", "periodic", "never"];
 
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct NodeConfig {
@@ -11,6 +26,33 @@ pub struct NodeConfig {
     pub address: String,
     pub private_port: u16,
     pub public_port: u16,
+    /// Address the node binds its ports to. Defaults to all the interfaces.
+    #[serde(default)]
+    pub listen_address: Option<String>,
+    /// Address the other nodes dial, advertised through gossip. Defaults to `address`.
+    #[serde(default)]
+    pub broadcast_address: Option<String>,
+    /// Port of the HTTP endpoint that serves the Prometheus metrics, 0 to disable it.
+    #[serde(default)]
+    pub metrics_port: u16,
+    /// Port of the HTTP endpoint that serves the liveness and readiness of the node, 0 to
+    /// disable it.
+    #[serde(default)]
+    pub health_port: u16,
+}
+
+impl NodeConfig {
+    /// Returns the address the node binds its ports to.
+    pub fn listen_address(&self) -> &str {
+        self.listen_address
+            .as_deref()
+            .unwrap_or(DEFAULT_LISTEN_ADDRESS)
+    }
+
+    /// Returns the address the other nodes use to reach this node.
+    pub fn broadcast_address(&self) -> &str {
+        self.broadcast_address.as_deref().unwrap_or(&self.address)
+    }
 }
 
 #[derive(Debug, serde::Deserialize, Clone)]
@@ -19,28 +61,848 @@ pub struct UiConfig {
     pub map_path: String,
     pub status_update_interval_in_ms: u64,
     pub tracking_update_interval_in_ms: u64,
+    /// Where the map tiles come from and how many are kept on disk.
+    #[serde(default)]
+    pub tiles: TileCacheConfig,
+}
+
+/// Where each node keeps its files. Every directory holds one subdirectory per node id.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub data_dir: String,
+    pub hints_dir: String,
+    pub commitlog_dir: String,
+    /// When the files a node persists are synced to disk: always, periodic or never.
+    pub fsync: String,
+    /// How often the files written since the last sync are synced, with the periodic policy.
+    pub fsync_interval_ms: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: "./data".to_string(),
+            hints_dir: "./data/hints".to_string(),
+            commitlog_dir: "./data/commitlog".to_string(),
+            fsync: "always".to_string(),
+            fsync_interval_ms: 1000,
+        }
+    }
+}
+
+/// When the files a node persists are synced to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Each file is synced before it replaces the previous one.
+    Always,
+    /// The files written since the last sync are synced every `fsync_interval_ms`.
+    Periodic,
+    /// The files are left in the page cache until the OS writes them.
+    Never,
+}
+
+impl StorageConfig {
+    /// Directory holding the tables, keyspaces and gossip table of a node.
+    pub fn node_data_dir(&self, node_id: &str) -> String {
+        format!("{}/{}", self.data_dir, node_id)
+    }
+
+    /// Directory holding the hints a node keeps for the nodes it couldn't reach.
+    pub fn node_hints_dir(&self, node_id: &str) -> String {
+        format!("{}/{}", self.hints_dir, node_id)
+    }
+
+    /// Directory holding the commit log of a node: the writes it didn't flush yet.
+    pub fn node_commitlog_dir(&self, node_id: &str) -> String {
+        format!("{}/{}", self.commitlog_dir, node_id)
+    }
+
+    /// Returns the fsync policy, `Always` if it isn't one of the known ones.
+    pub fn fsync_policy(&self) -> FsyncPolicy {
+        match self.fsync.to_lowercase().as_str() {
+            "periodic" => FsyncPolicy::Periodic,
+            "never" => FsyncPolicy::Never,
+            _ => FsyncPolicy::Always,
+        }
+    }
+}
+
+/// Sizes of the worker pools of each node. When the queue of a pool is full, new requests
+/// are rejected with an `Overloaded` error instead of piling up.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct OverloadConfig {
+    /// Threads that send requests to other nodes (replica fan-out, read repair and hints).
+    pub worker_threads: usize,
+    /// Requests to other nodes that can wait for a free worker.
+    pub worker_queue_capacity: usize,
+    /// Threads that handle the requests received from other nodes.
+    pub internal_worker_threads: usize,
+    /// Requests from other nodes that can wait for a free worker.
+    pub internal_queue_capacity: usize,
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        Self {
+            worker_threads: 8,
+            worker_queue_capacity: 256,
+            internal_worker_threads: 8,
+            internal_queue_capacity: 256,
+        }
+    }
+}
+
+/// How the nodes find each other and how often they run their periodic tasks.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ClusterConfig {
+    /// Ids of the nodes a starting node contacts to join the cluster. If empty, the first
+    /// node of `nodes` is the only seed.
+    pub seeds: Vec<String>,
+    pub gossip_interval_ms: u64,
+    pub flush_interval_ms: u64,
+    /// Consistency level used when a client asks for one the nodes don't support: ONE,
+    /// QUORUM or ALL.
+    pub default_consistency: String,
+    /// What the nodes do with a `CREATE KEYSPACE` whose replication factor is larger than the
+    /// nodes of the cluster: REJECT it, or CLAMP the factor to the number of nodes.
+    pub oversized_replication: String,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            seeds: vec![],
+            gossip_interval_ms: 1000,
+            flush_interval_ms: 10000,
+            default_consistency: "ONE".to_string(),
+            oversized_replication: "REJECT".to_string(),
+        }
+    }
+}
+
+impl ClusterConfig {
+    /// Returns the ids of the seed nodes.
+    ///
+    /// # Parameters
+    /// - `nodes`: The nodes of the cluster, the first one is the seed if none is configured.
+    pub fn seed_ids(&self, nodes: &[NodeConfig]) -> Vec<String> {
+        if self.seeds.is_empty() {
+            nodes.iter().take(1).map(|node| node.id.clone()).collect()
+        } else {
+            self.seeds.clone()
+        }
+    }
+}
+
+/// How long a node waits for the other nodes, in milliseconds.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// How long a coordinator waits for the replicas of a SELECT.
+    pub read_request_ms: u64,
+    /// How long a coordinator waits for the replicas of an INSERT, UPDATE or DELETE.
+    pub write_request_ms: u64,
+    /// Read and write timeout of a request to another node. Below the coordinator timeouts,
+    /// so a replica that doesn't answer fails before the coordinator gives up.
+    pub internal_request_ms: u64,
+    /// Timeout of each phase of the Paxos round of a lightweight transaction.
+    pub paxos_phase_ms: u64,
+    /// How long the leader of a counter update waits for the other replicas to apply it.
+    pub counter_replication_ms: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            read_request_ms: 5000,
+            write_request_ms: 4000,
+            internal_request_ms: 2000,
+            paxos_phase_ms: 2000,
+            counter_replication_ms: 2000,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    pub fn read_request(&self) -> Duration {
+        Duration::from_millis(self.read_request_ms)
+    }
+
+    pub fn write_request(&self) -> Duration {
+        Duration::from_millis(self.write_request_ms)
+    }
+
+    pub fn internal_request(&self) -> Duration {
+        Duration::from_millis(self.internal_request_ms)
+    }
+
+    pub fn paxos_phase(&self) -> Duration {
+        Duration::from_millis(self.paxos_phase_ms)
+    }
+
+    pub fn counter_replication(&self) -> Duration {
+        Duration::from_millis(self.counter_replication_ms)
+    }
+
+    /// Returns the problems of the timeouts: a request to another node must time out before
+    /// the coordinator stops waiting for it.
+    pub fn problems(&self) -> Vec<String> {
+        [
+            ("read_request_ms", self.read_request_ms),
+            ("write_request_ms", self.write_request_ms),
+        ]
+        .iter()
+        .filter(|(_, timeout)| self.internal_request_ms >= *timeout)
+        .map(|(name, timeout)| {
+            format!(
+                "timeouts internal_request_ms {} must be below {} {}",
+                self.internal_request_ms, name, timeout
+            )
+        })
+        .collect()
+    }
+}
+
+/// How the nodes drop the partitions that deletes left without rows.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CompactionConfig {
+    pub enabled: bool,
+    /// How often the tables are compacted.
+    pub interval_ms: u64,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_ms: 60000,
+        }
+    }
+}
+
+/// Where the data-at-rest keys of the nodes are kept. Without a keystore the key is derived
+/// from the `DB_KEY` environment variable.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SecurityConfig {
+    /// Keystore file with the keys, written by `cassandra_node keystore generate`.
+    pub keystore_path: String,
+    /// File holding the passphrase of the keystore, e.g. a Docker secret.
+    pub passphrase_file: String,
+}
+
+impl SecurityConfig {
+    /// Returns the paths of the keystore and of its passphrase, if a keystore is configured.
+    pub fn keystore(&self) -> Option<(&str, &str)> {
+        if self.keystore_path.is_empty() {
+            None
+        } else {
+            Some((&self.keystore_path, &self.passphrase_file))
+        }
+    }
+}
+
+/// The users the nodes let connect, each with the hash of its password made by
+/// `cassandra_node password`. Without users every client connects, anonymous or as the user it
+/// claims to be; with them a client must send the password of its user once the connection is
+/// encrypted.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub users: BTreeMap<String, String>,
+}
+
+impl AuthConfig {
+    /// Returns whether the clients must authenticate with a user and its password.
+    pub fn requires_credentials(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    /// Returns whether `password` is the password of `user`.
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        self.users
+            .get(user)
+            .is_some_and(|hash| verify_password(password, hash))
+    }
+
+    /// Returns the problems of the section, if any.
+    pub fn problems(&self) -> Vec<String> {
+        self.users
+            .iter()
+            .filter(|(_, hash)| !is_password_hash(hash))
+            .map(|(user, _)| {
+                format!(
+                    "auth user {} has no password hash, generate it with cassandra_node password",
+                    user
+                )
+            })
+            .collect()
+    }
+}
+
+/// How the nodes write their logs.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Lowest level that is logged: TRACE, DEBUG, INFO, WARN or ERROR.
+    pub level: String,
+    /// `text` for one line per entry, or `json` for one JSON object per entry.
+    pub format: String,
+    /// Size past which a log file is rotated.
+    pub max_file_size_mb: u64,
+    /// Age past which a log file is rotated, 0 to rotate only by size.
+    pub max_file_age_hours: u64,
+    /// How many rotated files of each node are kept, the oldest are deleted.
+    pub retained_files: usize,
+    /// Whether the rotated files are compressed with gzip.
+    pub compress: bool,
+    /// Whether the `tracing` spans of the coordinator, storage, gossip and hints are printed.
+    pub tracing: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "INFO".to_string(),
+            format: "text".to_string(),
+            max_file_size_mb: 10,
+            max_file_age_hours: 24,
+            retained_files: 5,
+            compress: true,
+            tracing: false,
+        }
+    }
+}
+
+/// Which statements the nodes record in their audit log.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Whether the schema changes and data mutations of the clients are audited.
+    pub enabled: bool,
+    /// Directory of the audit files, one per node.
+    pub dir: String,
+    /// Keyspaces whose statements are audited, every keyspace if empty.
+    pub keyspaces: Vec<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "./logs/audit".to_string(),
+            keyspaces: Vec::new(),
+        }
+    }
+}
+
+impl AuditConfig {
+    /// Returns whether the statements on `keyspace` are audited.
+    pub fn audits(&self, keyspace: &str) -> bool {
+        self.enabled && (self.keyspaces.is_empty() || self.keyspaces.iter().any(|k| k == keyspace))
+    }
+}
+
+/// Faults injected in the messages between the nodes, to test hinted handoff, read repair
+/// and the consistency levels against an unreliable network. Nothing is injected unless
+/// `enabled`; it is meant for test clusters only.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    /// Seed of the random numbers of the faults, to replay a run. Random if unset.
+    pub seed: Option<u64>,
+    pub faults: Vec<FaultRule>,
+}
+
+impl ChaosConfig {
+    /// Returns the problems of the section, if any.
+    pub fn problems(&self) -> Vec<String> {
+        self.faults.iter().flat_map(FaultRule::problems).collect()
+    }
+}
+
+/// The faults of the messages a node sends to another, each one happening to a message with
+/// its probability, from 0 to 1.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct FaultRule {
+    /// Id of the node that sends the messages, `*` for every node.
+    pub from: String,
+    /// Id of the node the messages are sent to, `*` for every node.
+    pub to: String,
+    /// The message is lost before reaching the node, as if it were down.
+    pub drop: f64,
+    /// The message is sent `delay_ms` late.
+    pub delay: f64,
+    pub delay_ms: u64,
+    /// The message is delivered twice.
+    pub duplicate: f64,
+    /// A byte of the message is changed on the way.
+    pub corrupt: f64,
+}
+
+impl Default for FaultRule {
+    fn default() -> Self {
+        Self {
+            from: "*".to_string(),
+            to: "*".to_string(),
+            drop: 0.0,
+            delay: 0.0,
+            delay_ms: 500,
+            duplicate: 0.0,
+            corrupt: 0.0,
+        }
+    }
+}
+
+impl FaultRule {
+    /// Returns whether the rule applies to the messages from `from` to `to`.
+    pub fn matches(&self, from: &str, to: &str) -> bool {
+        [(&self.from, from), (&self.to, to)]
+            .iter()
+            .all(|(pattern, id)| *pattern == "*" || pattern == id)
+    }
+
+    /// Returns the problems of the rule, if any.
+    pub fn problems(&self) -> Vec<String> {
+        [
+            ("drop", self.drop),
+            ("delay", self.delay),
+            ("duplicate", self.duplicate),
+            ("corrupt", self.corrupt),
+        ]
+        .iter()
+        .filter(|(_, probability)| !(0.0..=1.0).contains(probability))
+        .map(|(name, probability)| {
+            format!(
+                "chaos fault {} from {} to {} is {}, it must be between 0 and 1",
+                name, self.from, self.to, probability
+            )
+        })
+        .collect()
+    }
+}
+
+/// The settings a node runs with, taken from the sections of the config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeSettings {
+    pub storage: StorageConfig,
+    pub overload: OverloadConfig,
+    pub cluster: ClusterConfig,
+    pub timeouts: TimeoutConfig,
+    pub audit: AuditConfig,
+    pub auth: AuthConfig,
+    pub chaos: ChaosConfig,
 }
 
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct Config {
     pub replication_factor: u64,
     pub simulation_thread_sleep_ms: u64,
+    /// Seed of the random numbers of the simulation, to replay a run. Random if unset.
+    #[serde(default)]
+    pub simulation_seed: Option<u64>,
     pub nodes_gateway_address: String,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub overload: OverloadConfig,
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+    #[serde(default)]
+    pub compaction: CompactionConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// Faults injected in the messages between the nodes, for tests.
+    #[serde(default)]
+    pub chaos: ChaosConfig,
+    /// The wind and storms of the simulation.
+    #[serde(default)]
+    pub weather: Weather,
+    /// How many flights the simulator keeps in the air and how fast it adds them.
+    #[serde(default)]
+    pub fleet: FleetConfig,
+    /// How close the flights in the air may get before an alert is raised.
+    #[serde(default)]
+    pub separation: SeparationConfig,
     pub ui: UiConfig,
     pub nodes: Vec<NodeConfig>,
+    /// CSV or JSON file with the catalog of airports, added to those written in `airports`.
+    #[serde(default)]
+    pub airports_file: Option<String>,
+    #[serde(default)]
     pub airports: Vec<Airport>,
     pub airplanes: Vec<Airplane>,
+    /// The performance of the models of the airplanes.
+    #[serde(default)]
+    pub aircraft_models: Vec<AircraftModel>,
+    /// The recurring flights the simulator creates.
+    #[serde(default)]
+    pub timetables: Vec<Timetable>,
+    /// Stored tracks the simulator replays instead of simulating flights, if set.
+    #[serde(default)]
+    pub replay: Option<ReplayConfig>,
 }
 
 impl Config {
+    /// Loads the config file of the project root: `Config.toml`, or `Config.yaml` if there is
+    /// no TOML file.
     pub fn new() -> io::Result<Self> {
-        deserialize_toml(CONFIG_PATH)
+        let path = CONFIG_PATHS
+            .iter()
+            .find(|path| Path::new(path).exists())
+            .unwrap_or(&CONFIG_PATHS[0]);
+        Self::from_file(path)
+    }
+
+    /// Loads a config file, as YAML if its extension is `.yaml` or `.yml` and as TOML otherwise.
+    /// The `AERO_<SECTION>_<KEY>` environment variables override the values of the file.
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        let mut config: Value = deserialize_file(path)?;
+        apply_env_overrides(&mut config, env::vars())?;
+        let mut config: Self = config
+            .try_into()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(catalog) = &config.airports_file {
+            config.airports.extend(Airport::load_catalog(catalog)?);
+        }
+        Ok(config)
+    }
+
+    /// Checks the whole config, so that every problem is reported at startup instead of
+    /// failing once the nodes are running.
+    ///
+    /// # Returns
+    /// The description of every problem found, if any.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = vec![];
+
+        if self.nodes.is_empty() {
+            problems.push("no nodes are configured".to_string());
+        }
+        let mut ids = HashSet::new();
+        let mut ports = HashSet::new();
+        for node in &self.nodes {
+            if !ids.insert(&node.id) {
+                problems.push(format!("node id {} is used by more than one node", node.id));
+            }
+            let http_ports = [node.metrics_port, node.health_port]
+                .into_iter()
+                .filter(|port| *port != 0);
+            for port in [node.private_port, node.public_port]
+                .into_iter()
+                .chain(http_ports)
+            {
+                if !ports.insert((node.broadcast_address(), port)) {
+                    problems.push(format!(
+                        "port {} of node {} is already used by another port on {}",
+                        port,
+                        node.id,
+                        node.broadcast_address()
+                    ));
+                }
+            }
+        }
+
+        if self.replication_factor == 0 {
+            problems.push("replication_factor must be at least 1".to_string());
+        } else if self.replication_factor as usize > self.nodes.len() {
+            problems.push(format!(
+                "replication_factor {} is larger than the {} nodes of the cluster",
+                self.replication_factor,
+                self.nodes.len()
+            ));
+        }
+        for seed in &self.cluster.seeds {
+            if !ids.contains(seed) {
+                problems.push(format!("seed {} is not the id of any node", seed));
+            }
+        }
+        if !CONSISTENCY_LEVELS.contains(&self.cluster.default_consistency.to_uppercase().as_str()) {
+            problems.push(format!(
+                "default_consistency {} must be one of {}",
+                self.cluster.default_consistency,
+                CONSISTENCY_LEVELS.join(", ")
+            ));
+        }
+        if !OVERSIZED_REPLICATIONS
+            .contains(&self.cluster.oversized_replication.to_uppercase().as_str())
+        {
+            problems.push(format!(
+                "oversized_replication {} must be one of {}",
+                self.cluster.oversized_replication,
+                OVERSIZED_REPLICATIONS.join(", ")
+            ));
+        }
+
+        if !LOG_LEVELS.contains(&self.logging.level.to_uppercase().as_str()) {
+            problems.push(format!(
+                "logging level {} must be one of {}",
+                self.logging.level,
+                LOG_LEVELS.join(", ")
+            ));
+        }
+        if !LOG_FORMATS.contains(&self.logging.format.to_lowercase().as_str()) {
+            problems.push(format!(
+                "logging format {} must be one of {}",
+                self.logging.format,
+                LOG_FORMATS.join(", ")
+            ));
+        }
+        if self.logging.max_file_size_mb == 0 {
+            problems.push("logging max_file_size_mb must be at least 1".to_string());
+        }
+        if self.security.keystore().is_some() && self.security.passphrase_file.is_empty() {
+            problems.push("keystore_path is set but passphrase_file is not".to_string());
+        }
+        if self.compaction.enabled && self.compaction.interval_ms == 0 {
+            problems.push("compaction interval_ms must be at least 1".to_string());
+        }
+        if !FSYNC_POLICIES.contains(&self.storage.fsync.to_lowercase().as_str()) {
+            problems.push(format!(
+                "storage fsync {} must be one of {}",
+                self.storage.fsync,
+                FSYNC_POLICIES.join(", ")
+            ));
+        } else if self.storage.fsync_policy() == FsyncPolicy::Periodic
+            && self.storage.fsync_interval_ms == 0
+        {
+            problems.push("storage fsync_interval_ms must be at least 1".to_string());
+        }
+        problems.extend(self.timeouts.problems());
+        problems.extend(self.auth.problems());
+        problems.extend(self.chaos.problems());
+        problems.extend(self.weather.problems());
+        problems.extend(self.fleet.problems());
+        problems.extend(self.separation.problems());
+        problems.extend(self.ui.tiles.problems());
+        for timetable in &self.timetables {
+            problems.extend(timetable.problems(&self.airports, &self.airplanes));
+        }
+        let mut model_names = HashSet::new();
+        for model in &self.aircraft_models {
+            if !model_names.insert(&model.name) {
+                problems.push(format!(
+                    "aircraft model {} is defined more than once",
+                    model.name
+                ));
+            }
+            problems.extend(model.problems());
+        }
+        if let Some(replay) = &self.replay {
+            problems.extend(replay.problems());
+        }
+        let mut airport_ids = HashSet::new();
+        let mut iata_codes = HashSet::new();
+        for airport in &self.airports {
+            if !airport_ids.insert(airport.id) {
+                problems.push(format!(
+                    "airport id {} is used by more than one airport",
+                    airport.id
+                ));
+            }
+            if !airport.iata.is_empty() && !iata_codes.insert(airport.iata.to_uppercase()) {
+                problems.push(format!(
+                    "IATA code {} is used by more than one airport",
+                    airport.iata
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Returns the settings the nodes run with.
+    pub fn node_settings(&self) -> NodeSettings {
+        NodeSettings {
+            storage: self.storage.clone(),
+            overload: self.overload.clone(),
+            cluster: self.cluster.clone(),
+            timeouts: self.timeouts.clone(),
+            audit: self.audit.clone(),
+            auth: self.auth.clone(),
+            chaos: self.chaos.clone(),
+        }
+    }
+}
+
+fn deserialize_file<T: DeserializeOwned>(path: &str) -> io::Result<T> {
+    let contents = read_to_string(path)?;
+    match Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        _ => toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+/// Overrides the options of a config with the `AERO_` variables. The rest of the name is the
+/// path to the option in lowercase, e.g. `AERO_CLUSTER_GOSSIP_INTERVAL_MS` or
+/// `AERO_NODES_0_PUBLIC_PORT`. Lists, like the seeds, are separated by commas.
+///
+/// # Parameters
+/// - `config`: The config read from the file.
+/// - `vars`: The environment variables.
+///
+/// # Returns
+/// An error if a variable doesn't match any option or its value has the wrong type.
+fn apply_env_overrides(
+    config: &mut Value,
+    vars: impl Iterator<Item = (String, String)>,
+) -> io::Result<()> {
+    let Value::Table(table) = config else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "config is not a table",
+        ));
+    };
+    // las secciones opcionales tienen que estar para poder pisar sus claves
+    insert_defaults(table, "storage", &StorageConfig::default())?;
+    insert_defaults(table, "overload", &OverloadConfig::default())?;
+    insert_defaults(table, "cluster", &ClusterConfig::default())?;
+    insert_defaults(table, "timeouts", &TimeoutConfig::default())?;
+    insert_defaults(table, "compaction", &CompactionConfig::default())?;
+    insert_defaults(table, "security", &SecurityConfig::default())?;
+    insert_defaults(table, "logging", &LoggingConfig::default())?;
+    insert_defaults(table, "audit", &AuditConfig::default())?;
+    insert_defaults(table, "chaos", &ChaosConfig::default())?;
+    insert_defaults(table, "weather", &Weather::default())?;
+    insert_defaults(table, "fleet", &FleetConfig::default())?;
+    insert_defaults(table, "separation", &SeparationConfig::default())?;
+    if let Some(Value::Array(nodes)) = table.get_mut("nodes") {
+        for node in nodes.iter_mut().filter_map(Value::as_table_mut) {
+            let address = node.get("address").cloned();
+            node.entry("metrics_port").or_insert(Value::Integer(0));
+            node.entry("health_port").or_insert(Value::Integer(0));
+            node.entry("listen_address")
+                .or_insert_with(|| Value::String(DEFAULT_LISTEN_ADDRESS.to_string()));
+            if let Some(address) = address {
+                node.entry("broadcast_address").or_insert(address);
+            }
+        }
+    }
+
+    for (name, raw) in vars {
+        let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let option = find_option(config, &path.to_lowercase()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown config option {}", name),
+            )
+        })?;
+        *option = parse_override(option, &raw).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid value for {}: {}", name, raw),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+fn insert_defaults<T: serde::Serialize>(
+    table: &mut Table,
+    section: &str,
+    defaults: &T,
+) -> io::Result<()> {
+    let Value::Table(defaults) =
+        Value::try_from(defaults).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    else {
+        return Ok(());
+    };
+    if let Value::Table(current) = table
+        .entry(section)
+        .or_insert_with(|| Value::Table(Table::new()))
+    {
+        for (key, value) in defaults {
+            current.entry(key).or_insert(value);
+        }
     }
+    Ok(())
 }
 
-fn deserialize_toml<T: DeserializeOwned>(path: &str) -> io::Result<T> {
-    toml::from_str(&read_to_string(path)?)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+/// Finds the option named by `path`, where the keys and list indexes are joined by `_`.
+fn find_option<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    option_keys(value, path)?
+        .into_iter()
+        .try_fold(value, |value, key| match value {
+            Value::Table(table) => table.get_mut(&key),
+            Value::Array(items) => items.get_mut(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/// Splits `path` into the keys that lead to an existing option.
+fn option_keys(value: &Value, path: &str) -> Option<Vec<String>> {
+    match value {
+        Value::Table(table) => {
+            if table.contains_key(path) {
+                return Some(vec![path.to_string()]);
+            }
+            // las claves tambien tienen `_`, se prueba cada una que sea prefijo
+            table.iter().find_map(|(key, nested)| {
+                let rest = path.strip_prefix(key.as_str())?.strip_prefix('_')?;
+                let mut keys = option_keys(nested, rest)?;
+                keys.insert(0, key.clone());
+                Some(keys)
+            })
+        }
+        Value::Array(items) => {
+            let (index, rest) = path.split_once('_').unwrap_or((path, ""));
+            let item = items.get(index.parse::<usize>().ok()?)?;
+            let mut keys = if rest.is_empty() {
+                vec![]
+            } else {
+                option_keys(item, rest)?
+            };
+            keys.insert(0, index.to_string());
+            Some(keys)
+        }
+        _ => None,
+    }
+}
+
+/// Parses the value of a variable with the type of the option it overrides.
+fn parse_override(current: &Value, raw: &str) -> Option<Value> {
+    match current {
+        Value::Integer(_) => raw.trim().parse().ok().map(Value::Integer),
+        Value::Float(_) => raw.trim().parse().ok().map(Value::Float),
+        Value::Boolean(_) => raw.trim().parse().ok().map(Value::Boolean),
+        Value::Array(items) => {
+            let element = items
+                .first()
+                .cloned()
+                .unwrap_or(Value::String(String::new()));
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| parse_override(&element, item))
+                .collect::<Option<Vec<_>>>()
+                .map(Value::Array)
+        }
+        _ => Some(Value::String(raw.to_string())),
+    }
 }
 
 /*
@@ -61,3 +923,467 @@ pub fn gather_public_addresses(config: &Config) -> Vec<String> {
         .map(|node| format!("{}:{}", address, node.public_port))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_defaults_to_local_data_dir() {
+        let storage: StorageConfig = toml::from_str("").unwrap();
+        assert_eq!(storage, StorageConfig::default());
+        assert_eq!(storage.node_data_dir("0"), "./data/0");
+    }
+
+    #[test]
+    fn storage_dirs_can_be_overridden() {
+        let storage: StorageConfig =
+            toml::from_str("data_dir = \"/var/lib/cassandra\"\nhints_dir = \"/hints\"").unwrap();
+        assert_eq!(storage.node_data_dir("1"), "/var/lib/cassandra/1");
+        assert_eq!(storage.node_hints_dir("1"), "/hints/1");
+        assert_eq!(storage.node_commitlog_dir("1"), "./data/commitlog/1");
+    }
+
+    #[test]
+    fn fsync_policy_defaults_to_always_and_is_validated() {
+        let storage: StorageConfig = toml::from_str("fsync = \"Periodic\"").unwrap();
+        assert_eq!(storage.fsync_policy(), FsyncPolicy::Periodic);
+        assert_eq!(StorageConfig::default().fsync_policy(), FsyncPolicy::Always);
+
+        let mut config = config(vec![
+            node("0", "localhost", 7000, 9042),
+            node("1", "localhost", 7001, 9043),
+        ]);
+        config.storage.fsync = "sometimes".to_string();
+        assert!(config.validate().unwrap_err()[0].contains("storage fsync sometimes"));
+
+        config.storage.fsync = "periodic".to_string();
+        config.storage.fsync_interval_ms = 0;
+        assert!(config.validate().unwrap_err()[0].contains("fsync_interval_ms"));
+    }
+
+    #[test]
+    fn cluster_settings_default_to_the_first_node_as_seed() {
+        let cluster: ClusterConfig = toml::from_str("gossip_interval_ms = 500").unwrap();
+        assert_eq!(cluster.gossip_interval_ms, 500);
+        assert_eq!(cluster.flush_interval_ms, 10000);
+        let nodes = vec![
+            NodeConfig {
+                id: "0".to_string(),
+                address: "node-0".to_string(),
+                private_port: 7000,
+                public_port: 9042,
+                listen_address: None,
+                broadcast_address: None,
+                metrics_port: 0,
+                health_port: 0,
+            },
+            NodeConfig {
+                id: "1".to_string(),
+                address: "node-1".to_string(),
+                private_port: 7001,
+                public_port: 9043,
+                listen_address: None,
+                broadcast_address: None,
+                metrics_port: 0,
+                health_port: 0,
+            },
+        ];
+        assert_eq!(cluster.seed_ids(&nodes), vec!["0".to_string()]);
+
+        let cluster: ClusterConfig = toml::from_str("seeds = [\"0\", \"1\"]").unwrap();
+        assert_eq!(cluster.seed_ids(&nodes).len(), 2);
+    }
+
+    #[test]
+    fn config_can_be_written_in_yaml() {
+        let path = std::env::temp_dir().join("aero_config_test.yaml");
+        let yaml = r#"
+replication_factor: 3
+simulation_thread_sleep_ms: 100
+nodes_gateway_address: localhost
+timeouts:
+  paxos_phase_ms: 500
+ui:
+  gatherer: localhost
+  map_path: map.png
+  status_update_interval_in_ms: 1000
+  tracking_update_interval_in_ms: 1000
+nodes:
+  - id: "0"
+    address: node-0
+    private_port: 7000
+    public_port: 9042
+airports: []
+airplanes: []
+"#;
+        std::fs::write(&path, yaml).unwrap();
+
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        let settings = config.node_settings();
+        assert_eq!(settings.timeouts.paxos_phase(), Duration::from_millis(500));
+        assert_eq!(settings.timeouts.internal_request_ms, 2000);
+        assert_eq!(config.compaction, CompactionConfig::default());
+        assert_eq!(settings.cluster, ClusterConfig::default());
+        assert_eq!(config.nodes[0].public_port, 9042);
+    }
+
+    #[test]
+    fn weather_is_read_from_its_section() {
+        let weather: Weather = toml::from_str(
+            r#"
+[[regions]]
+name = "atlantico sur"
+min_latitude = -60.0
+max_latitude = 0.0
+min_longitude = -70.0
+max_longitude = 0.0
+wind = { direction = 90.0, speed = 60 }
+
+[[storms]]
+name = "sudestada"
+latitude = -35.0
+longitude = -57.0
+radius_km = 0.0
+wind = { direction = 0.0, speed = 80 }
+"#,
+        )
+        .unwrap();
+        assert_eq!(weather.wind_at(-20.0, -40.0).speed, 60);
+
+        let mut config = config(vec![
+            node("0", "localhost", 7000, 9042),
+            node("1", "localhost", 7001, 9043),
+        ]);
+        assert_eq!(config.weather, Weather::default());
+        config.weather = weather;
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("storm sudestada"));
+    }
+
+    #[test]
+    fn airports_are_added_from_the_catalog_file() {
+        let dir = std::env::temp_dir();
+        let catalog = dir.join("aero_config_airports.csv");
+        std::fs::write(
+            &catalog,
+            "id,iata,name,latitude,longitude,city,country,capacity
+             2,MAD,Barajas,40.47,-3.56,Madrid,Spain,60
+             3,EZE,Pistarini,-34.82,-58.54,Buenos Aires,Argentina,40
+",
+        )
+        .unwrap();
+        let path = dir.join("aero_config_airports.yaml");
+        let yaml = format!(
+            r#"
+replication_factor: 1
+simulation_thread_sleep_ms: 100
+nodes_gateway_address: localhost
+ui:
+  gatherer: localhost
+  map_path: map.png
+  status_update_interval_in_ms: 1000
+  tracking_update_interval_in_ms: 1000
+nodes:
+  - id: "0"
+    address: node-0
+    private_port: 7000
+    public_port: 9042
+airports_file: {}
+airports:
+  - id: 1
+    iata: EZE
+    name: Ministro Pistarini
+    latitude: -34.82
+    longitude: -58.54
+    city: Buenos Aires
+    country: Argentina
+airplanes: []
+"#,
+            catalog.to_str().unwrap()
+        );
+        std::fs::write(&path, yaml).unwrap();
+
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(config.airports.len(), 3);
+        assert_eq!(
+            Airport::by_iata(&config.airports, "MAD").unwrap().capacity,
+            60
+        );
+        let problems = config.validate().unwrap_err();
+        assert_eq!(
+            problems,
+            vec!["IATA code EZE is used by more than one airport"]
+        );
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn audit_can_be_limited_to_some_keyspaces() {
+        let mut audit = AuditConfig::default();
+        assert!(!audit.audits("flights"));
+
+        audit.enabled = true;
+        assert!(audit.audits("flights"));
+
+        audit.keyspaces = vec!["flights".to_string()];
+        assert!(audit.audits("flights"));
+        assert!(!audit.audits("airports"));
+    }
+
+    #[test]
+    fn auth_users_need_a_password_hash() {
+        let auth: AuthConfig = toml::from_str(&format!(
+            "[users]\nsimulator = \"{}\"\nops = \"secret\"\n",
+            crate::security::credentials::hash_password("secret")
+        ))
+        .unwrap();
+        assert!(auth.requires_credentials());
+        assert!(auth.verify("simulator", "secret"));
+        assert!(!auth.verify("ops", "secret"));
+        assert_eq!(
+            auth.problems(),
+            ["auth user ops has no password hash, generate it with cassandra_node password"]
+        );
+        assert!(!AuthConfig::default().requires_credentials());
+    }
+
+    #[test]
+    fn chaos_faults_apply_to_their_pairs_of_nodes() {
+        let chaos: ChaosConfig = toml::from_str(
+            r#"
+enabled = true
+[[faults]]
+to = "node2"
+drop = 0.5
+[[faults]]
+from = "node1"
+to = "node3"
+corrupt = 1.5
+"#,
+        )
+        .unwrap();
+        assert!(chaos.faults[0].matches("node1", "node2"));
+        assert!(chaos.faults[0].matches("node3", "node2"));
+        assert!(!chaos.faults[0].matches("node2", "node1"));
+        assert!(!chaos.faults[1].matches("node2", "node3"));
+        assert_eq!(chaos.faults[0].delay_ms, 500);
+        assert_eq!(
+            chaos.problems(),
+            ["chaos fault corrupt from node1 to node3 is 1.5, it must be between 0 and 1"]
+        );
+    }
+
+    #[test]
+    fn env_variables_override_the_config_file() {
+        let mut config: Value = toml::from_str(
+            r#"
+replication_factor = 3
+nodes_gateway_address = "localhost"
+
+[cluster]
+gossip_interval_ms = 500
+
+[[nodes]]
+id = "0"
+address = "node-0"
+private_port = 7000
+public_port = 9042
+"#,
+        )
+        .unwrap();
+
+        apply_env_overrides(
+            &mut config,
+            vars(&[
+                ("PATH", "/usr/bin"),
+                ("AERO_REPLICATION_FACTOR", "2"),
+                ("AERO_NODES_GATEWAY_ADDRESS", "gateway"),
+                ("AERO_CLUSTER_SEEDS", "0, 1"),
+                ("AERO_TIMEOUTS_PAXOS_PHASE_MS", "100"),
+                ("AERO_NODES_0_PUBLIC_PORT", "9050"),
+                ("AERO_FLEET_MAX_FLIGHTS", "20"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config["replication_factor"].as_integer(), Some(2));
+        assert_eq!(config["nodes_gateway_address"].as_str(), Some("gateway"));
+        let cluster: ClusterConfig = config["cluster"].clone().try_into().unwrap();
+        assert_eq!(cluster.seeds, vec!["0".to_string(), "1".to_string()]);
+        assert_eq!(cluster.gossip_interval_ms, 500);
+        let timeouts: TimeoutConfig = config["timeouts"].clone().try_into().unwrap();
+        assert_eq!(timeouts.paxos_phase_ms, 100);
+        let fleet: FleetConfig = config["fleet"].clone().try_into().unwrap();
+        assert_eq!(fleet.max_flights, 20);
+        assert_eq!(fleet.spawn_per_minute, 10.0);
+        let node: NodeConfig = config["nodes"][0].clone().try_into().unwrap();
+        assert_eq!(node.public_port, 9050);
+        assert_eq!(node.listen_address(), "0.0.0.0");
+        assert_eq!(node.broadcast_address(), "node-0");
+    }
+
+    #[test]
+    fn nodes_listen_and_broadcast_on_different_addresses() {
+        let node: NodeConfig = toml::from_str(
+            "id = \"0\"\naddress = \"node-0\"\nprivate_port = 7000\npublic_port = 9042",
+        )
+        .unwrap();
+        assert_eq!(node.listen_address(), "0.0.0.0");
+        assert_eq!(node.broadcast_address(), "node-0");
+
+        let mut config: Value = toml::from_str(
+            "[[nodes]]\nid = \"0\"\naddress = \"node-0\"\nprivate_port = 7000\npublic_port = 9042",
+        )
+        .unwrap();
+        apply_env_overrides(
+            &mut config,
+            vars(&[
+                ("AERO_NODES_0_LISTEN_ADDRESS", "10.0.0.5"),
+                ("AERO_NODES_0_BROADCAST_ADDRESS", "203.0.113.7"),
+            ]),
+        )
+        .unwrap();
+        let node: NodeConfig = config["nodes"][0].clone().try_into().unwrap();
+        assert_eq!(node.listen_address(), "10.0.0.5");
+        assert_eq!(node.broadcast_address(), "203.0.113.7");
+    }
+
+    #[test]
+    fn unknown_or_mistyped_env_overrides_are_rejected() {
+        let mut config: Value = toml::from_str("replication_factor = 3").unwrap();
+        assert!(apply_env_overrides(&mut config, vars(&[("AERO_CLUSTER_SEDS", "0")])).is_err());
+        assert!(
+            apply_env_overrides(&mut config, vars(&[("AERO_REPLICATION_FACTOR", "three")]))
+                .is_err()
+        );
+        assert!(
+            apply_env_overrides(&mut config, vars(&[("AERO_NODES_0_PUBLIC_PORT", "1")])).is_err()
+        );
+    }
+
+    fn node(id: &str, address: &str, private_port: u16, public_port: u16) -> NodeConfig {
+        NodeConfig {
+            id: id.to_string(),
+            address: address.to_string(),
+            private_port,
+            public_port,
+            listen_address: None,
+            broadcast_address: None,
+            metrics_port: 0,
+            health_port: 0,
+        }
+    }
+
+    fn config(nodes: Vec<NodeConfig>) -> Config {
+        Config {
+            replication_factor: 2,
+            simulation_thread_sleep_ms: 100,
+            simulation_seed: None,
+            nodes_gateway_address: "localhost".to_string(),
+            storage: StorageConfig::default(),
+            overload: OverloadConfig::default(),
+            cluster: ClusterConfig::default(),
+            timeouts: TimeoutConfig::default(),
+            compaction: CompactionConfig::default(),
+            security: SecurityConfig::default(),
+            logging: LoggingConfig::default(),
+            audit: AuditConfig::default(),
+            auth: AuthConfig::default(),
+            chaos: ChaosConfig::default(),
+            weather: Weather::default(),
+            fleet: FleetConfig::default(),
+            separation: SeparationConfig::default(),
+            ui: UiConfig {
+                gatherer: "localhost".to_string(),
+                map_path: "map.png".to_string(),
+                status_update_interval_in_ms: 1000,
+                tracking_update_interval_in_ms: 1000,
+                tiles: TileCacheConfig::default(),
+            },
+            nodes,
+            airports_file: None,
+            airports: vec![],
+            airplanes: vec![],
+            aircraft_models: vec![],
+            timetables: vec![],
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        let config = config(vec![
+            node("0", "localhost", 7000, 9042),
+            node("1", "localhost", 7001, 9043),
+        ]);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validation_reports_every_problem_at_once() {
+        let mut config = config(vec![
+            node("0", "localhost", 7000, 9042),
+            node("0", "localhost", 7000, 9043),
+        ]);
+        config.replication_factor = 3;
+        config.cluster.seeds = vec!["5".to_string()];
+        config.cluster.default_consistency = "TWO".to_string();
+        config.cluster.oversized_replication = "IGNORE".to_string();
+        config.logging.format = "xml".to_string();
+        config.timeouts.internal_request_ms = 4500;
+
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.len(), 8);
+        assert!(problems[0].contains("node id 0"));
+        assert!(problems[1].contains("port 7000"));
+        assert!(problems[2].contains("replication_factor 3"));
+        assert!(problems[3].contains("seed 5"));
+        assert!(problems[4].contains("default_consistency TWO"));
+        assert!(problems[5].contains("oversized_replication IGNORE"));
+        assert!(problems[6].contains("logging format xml"));
+        assert!(problems[7].contains("below write_request_ms 4000"));
+    }
+
+    #[test]
+    fn metrics_ports_are_checked_for_collisions() {
+        let mut nodes = vec![
+            node("0", "localhost", 7000, 9042),
+            node("1", "localhost", 7001, 9043),
+        ];
+        nodes[1].metrics_port = 9042;
+        let problems = config(nodes.clone()).validate().unwrap_err();
+        assert!(problems[0].contains("port 9042 of node 1"));
+
+        nodes[1].metrics_port = 9100;
+        nodes[1].health_port = 9100;
+        let problems = config(nodes).validate().unwrap_err();
+        assert!(problems[0].contains("port 9100 of node 1"));
+    }
+
+    #[test]
+    fn nodes_on_different_hosts_can_share_ports() {
+        let config = config(vec![
+            node("0", "node-0", 7000, 9042),
+            node("1", "node-1", 7000, 9042),
+        ]);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn overload_thresholds_can_be_overridden() {
+        let overload: OverloadConfig = toml::from_str("internal_queue_capacity = 16").unwrap();
+        assert_eq!(overload.internal_queue_capacity, 16);
+        assert_eq!(
+            overload.worker_queue_capacity,
+            OverloadConfig::default().worker_queue_capacity
+        );
+    }
+}
@@ -1,19 +1,50 @@
 use std::io;
 
 use crate::frame::messages::consistency_level::ConsistencyLevel;
-use crate::frame::messages::startup_options::default_startup;
+use crate::frame::messages::error::ErrorCode;
+use crate::frame::messages::event::ClusterEvent;
+use crate::frame::messages::prepared::Value;
+use crate::frame::messages::query_result::QueryResult;
+use crate::frame::compression::Compression;
+use crate::frame::messages::startup_options::{
+    startup_with_compression, supported_compressions,
+};
 use crate::frame::messages::{query, Message};
 use crate::frame::version::Version;
 use crate::frame::Frame;
 
 impl Frame {
     pub fn new_startup() -> Self {
+        Self::new_startup_with_compression(None)
+    }
+
+    /// Creates a STARTUP that asks the node to compress the frames with `compression`.
+    pub fn new_startup_with_compression(compression: Option<Compression>) -> Self {
+        Self {
+            version: Version::RequestV3,
+            compression: false,
+            tracing: false,
+            stream: rand::random(),
+            body: Message::Startup(startup_with_compression(compression)),
+        }
+    }
+
+    /// Creates an OPTIONS, which asks the node which options of the STARTUP it supports.
+    pub fn new_options() -> Self {
         Self {
             version: Version::RequestV3,
             compression: false,
             tracing: false,
             stream: rand::random(),
-            body: Message::Startup(default_startup()),
+            body: Message::Options,
+        }
+    }
+
+    /// Returns the compressions a SUPPORTED offers, empty for any other frame.
+    pub fn supported_compressions(&self) -> Vec<Compression> {
+        match &self.body {
+            Message::Supported(options) => supported_compressions(options),
+            _ => vec![],
         }
     }
 
@@ -44,6 +75,121 @@ impl Frame {
         }
     }
 
+    /// Creates a QUERY that asks for a page of at most `page_size` rows.
+    ///
+    /// # Parameters
+    /// - `query_string`: The query.
+    /// - `consistency_level`: The consistency level of the query.
+    /// - `page_size`: The maximum number of rows of the page.
+    /// - `paging_state`: The state returned with the previous page, `None` for the first one.
+    /// - `stream_id`: The stream of the connection.
+    pub fn new_paged_query(
+        query_string: String,
+        consistency_level: ConsistencyLevel,
+        page_size: i32,
+        paging_state: Option<Vec<u8>>,
+        stream_id: i16,
+    ) -> Self {
+        let mut query = query::Query::default(query_string, consistency_level);
+        query.result_page_size = Some(page_size);
+        query.paging_state = paging_state;
+        Self {
+            version: Version::RequestV3,
+            compression: false,
+            tracing: false,
+            stream: stream_id,
+            body: Message::Query(query),
+        }
+    }
+
+    /// Creates a PREPARE of a statement with `?` markers.
+    pub fn new_prepare(query_string: String, stream_id: i16) -> Self {
+        Self {
+            version: Version::RequestV3,
+            compression: false,
+            tracing: false,
+            stream: stream_id,
+            body: Message::Prepare(query_string),
+        }
+    }
+
+    /// Creates an EXECUTE of a prepared statement with the values of its markers.
+    pub fn new_execute(
+        id: Vec<u8>,
+        values: &[Value],
+        consistency_level: ConsistencyLevel,
+        stream_id: i16,
+    ) -> Self {
+        let mut parameters = query::Query::default(String::new(), consistency_level);
+        parameters.values = Some(
+            values
+                .iter()
+                .map(|value| (None, value.serialize()))
+                .collect(),
+        );
+        Self {
+            version: Version::RequestV3,
+            compression: false,
+            tracing: false,
+            stream: stream_id,
+            body: Message::Execute(id, parameters),
+        }
+    }
+
+    /// Creates a REGISTER for the events of the given types.
+    pub fn new_register(event_types: Vec<String>, stream_id: i16) -> Self {
+        Self {
+            version: Version::RequestV3,
+            compression: false,
+            tracing: false,
+            stream: stream_id,
+            body: Message::Register(event_types),
+        }
+    }
+
+    /// Returns the stream id of the frame, which a response shares with its request.
+    pub fn stream_id(&self) -> i16 {
+        self.stream
+    }
+
+    /// Returns the event pushed by a node, `None` if the frame isn't an EVENT.
+    pub fn cluster_event(&self) -> Option<&ClusterEvent> {
+        match &self.body {
+            Message::Event(event) => Some(event),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the frame is a READY, as answered to a REGISTER.
+    pub fn is_ready(&self) -> bool {
+        matches!(&self.body, Message::Ready)
+    }
+
+    /// Returns the id of the statement a PREPARED result answers, or the error of the node.
+    pub fn prepared_id(&self) -> Result<Vec<u8>, String> {
+        match &self.body {
+            Message::Result(QueryResult::Prepared { id, .. }) => Ok(id.clone()),
+            Message::Error(error_code) => Err(error_code.message().to_string()),
+            _ => Err("Unknown protocol error.".to_string()),
+        }
+    }
+
+    /// Returns whether the node didn't know the statement of an EXECUTE, as happens after
+    /// it restarts.
+    pub fn is_unprepared(&self) -> bool {
+        matches!(&self.body, Message::Error(ErrorCode::Unprepared))
+    }
+
+    /// Same as `handle_response`, also returning the state to request the next page of the
+    /// rows, `None` on the last page.
+    pub fn handle_page_response(&self, query: Self) -> Result<(String, Option<Vec<u8>>), String> {
+        let rows = self.handle_response(query)?;
+        match &self.body {
+            Message::Result(result) => Ok((rows, result.paging_state())),
+            _ => Ok((rows, None)),
+        }
+    }
+
     pub fn handle_response(&self, query: Self) -> Result<String, String> {
         if self.version != Version::ResponseV3 {
             return Err("Invalid version".to_string());
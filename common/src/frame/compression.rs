@@ -0,0 +1,133 @@
+use std::io;
+use std::str::FromStr;
+
+use super::MAX_FRAME_SIZE;
+
+/// Bodies smaller than this are sent uncompressed, since compressing them saves little.
+pub(crate) const MIN_COMPRESSED_SIZE: usize = 512;
+
+/// An algorithm the bodies of the frames of a connection can be compressed with, agreed on
+/// in its STARTUP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// An LZ4 block preceded by the length of the uncompressed body, as a big-endian `int`.
+    Lz4,
+    /// A raw Snappy block.
+    Snappy,
+}
+
+impl Compression {
+    /// Every algorithm, in the order the nodes prefer them.
+    pub const ALL: [Compression; 2] = [Compression::Lz4, Compression::Snappy];
+
+    /// Returns the name of the algorithm in the STARTUP and SUPPORTED messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Lz4 => "lz4",
+            Compression::Snappy => "snappy",
+        }
+    }
+
+    pub fn compress(self, body: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Lz4 => {
+                let mut compressed = (body.len() as u32).to_be_bytes().to_vec();
+                compressed.extend(lz4_flex::block::compress(body));
+                compressed
+            }
+            Compression::Snappy => snap::raw::Encoder::new()
+                .compress_vec(body)
+                .unwrap_or_else(|_| body.to_vec()),
+        }
+    }
+
+    /// Decompresses a body, rejecting those that would be larger than a frame.
+    pub fn decompress(self, body: &[u8]) -> io::Result<Vec<u8>> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        match self {
+            Compression::Lz4 => {
+                let (length, block) = body
+                    .split_first_chunk::<4>()
+                    .ok_or_else(|| invalid("Missing the length of the LZ4 body".to_string()))?;
+                let length = u32::from_be_bytes(*length) as usize;
+                if length > MAX_FRAME_SIZE {
+                    return Err(invalid(format!(
+                        "LZ4 body of {} bytes is too large",
+                        length
+                    )));
+                }
+                lz4_flex::block::decompress(block, length).map_err(|e| invalid(e.to_string()))
+            }
+            Compression::Snappy => {
+                let length = snap::raw::decompress_len(body).map_err(|e| invalid(e.to_string()))?;
+                if length > MAX_FRAME_SIZE {
+                    return Err(invalid(format!(
+                        "Snappy body of {} bytes is too large",
+                        length
+                    )));
+                }
+                snap::raw::Decoder::new()
+                    .decompress_vec(body)
+                    .map_err(|e| invalid(e.to_string()))
+            }
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Compression::ALL
+            .into_iter()
+            .find(|compression| compression.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown compression {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::messages::consistency_level::ConsistencyLevel;
+    use crate::frame::{Frame, HEADER_SIZE};
+
+    #[test]
+    fn bodies_roundtrip() {
+        let body = "{\"flight_id\":\"1\",\"latitude\":\"-34.8\"}".repeat(50);
+        for compression in Compression::ALL {
+            let compressed = compression.compress(body.as_bytes());
+            assert!(compressed.len() < body.len());
+            assert_eq!(
+                compression.decompress(&compressed).unwrap(),
+                body.as_bytes()
+            );
+            assert_eq!(compression.name().parse(), Ok(compression));
+        }
+        assert!(Compression::Lz4.decompress(&[0, 0]).is_err());
+        assert!("gzip".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn large_frames_are_sent_compressed() {
+        let query_string = format!(
+            "SELECT * FROM tracks WHERE flight_id IN ({})",
+            "1,".repeat(400)
+        );
+        let frame = Frame::new_query(query_string.clone(), ConsistencyLevel::One, 7);
+
+        for compression in Compression::ALL {
+            let bytes = frame.serialize_compressed(Some(compression));
+            assert_eq!(bytes[1], 0x01);
+            assert!(bytes.len() < frame.serialize().len());
+
+            let (header, body) = bytes.split_at(HEADER_SIZE);
+            let received = Frame::from_parts(header, body.to_vec(), Some(compression));
+            assert_eq!(received.query_string(), Some(query_string.as_str()));
+            // Sin compresion acordada el cuerpo no se puede leer
+            assert!(Frame::from_parts(header, body.to_vec(), None).is_error());
+        }
+
+        let small = Frame::new_query("SELECT * FROM tracks".to_string(), ConsistencyLevel::One, 7);
+        assert_eq!(small.serialize_compressed(Some(Compression::Lz4))[1], 0x00);
+    }
+}
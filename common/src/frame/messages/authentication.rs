@@ -1,5 +1,5 @@
 use super::notation::{read_string, write_string};
-use std::io::Cursor;
+use std::io::{self, Cursor};
 
 pub fn serialize_authenticate(iauthenticator: &str) -> Vec<u8> {
     let mut buffer = vec![];
@@ -7,9 +7,50 @@ pub fn serialize_authenticate(iauthenticator: &str) -> Vec<u8> {
     buffer
 }
 
-pub fn deserialize_authenticate(buffer: &[u8]) -> String {
+pub fn deserialize_authenticate(buffer: &[u8]) -> io::Result<String> {
     let mut cursor = Cursor::new(buffer);
-    read_string(&mut cursor).unwrap()
+    read_string(&mut cursor)
+}
+
+/// Builds the first token of the PLAIN authentication, `\0user\0`, without a password:
+/// it is sent before the connection is encrypted. The user only identifies the client, the
+/// keys are agreed on in the challenge that follows.
+pub fn plain_token(user: &str) -> Vec<u8> {
+    plain_credentials(user, "")
+}
+
+/// Builds the PLAIN token with a password, `\0user\0password`. It is only sent once the
+/// connection is encrypted, when the node asks for the credentials of the client.
+pub fn plain_credentials(user: &str, password: &str) -> Vec<u8> {
+    let mut token = vec![0];
+    token.extend_from_slice(user.as_bytes());
+    token.push(0);
+    token.extend_from_slice(password.as_bytes());
+    token
+}
+
+/// Reads the user of a PLAIN token, `authzid\0user\0password`.
+///
+/// # Returns
+/// The user, or `None` if the token is empty or has no user.
+pub fn plain_user(token: &[u8]) -> Option<String> {
+    let user = token.split(|byte| *byte == 0).nth(1)?;
+    if user.is_empty() {
+        return None;
+    }
+    String::from_utf8(user.to_vec()).ok()
+}
+
+/// Reads the password of a PLAIN token, `authzid\0user\0password`.
+///
+/// # Returns
+/// The password, or `None` if the token has none.
+pub fn plain_password(token: &[u8]) -> Option<String> {
+    let password = token.splitn(3, |byte| *byte == 0).nth(2)?;
+    if password.is_empty() {
+        return None;
+    }
+    String::from_utf8(password.to_vec()).ok()
 }
 
 #[derive(Debug)]
@@ -84,4 +125,25 @@ impl AuthResponse {
         bytes.extend_from_slice(&self.shared_secret.to_be_bytes());
         bytes
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_token_carries_the_user() {
+        assert_eq!(plain_user(&plain_token("flights")), Some("flights".to_string()));
+        assert_eq!(plain_user(b"\0admin\0secret"), Some("admin".to_string()));
+        assert_eq!(plain_user(&[]), None);
+        assert_eq!(plain_token("flights"), b"\0flights\0");
+    }
+
+    #[test]
+    fn plain_credentials_carry_the_password() {
+        let token = plain_credentials("admin", "se\0cret");
+        assert_eq!(plain_user(&token), Some("admin".to_string()));
+        assert_eq!(plain_password(&token), Some("se\0cret".to_string()));
+        assert_eq!(plain_password(&plain_token("admin")), None);
+    }
+}
@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ConsistencyLevel {
     #[default]
@@ -36,20 +38,32 @@ impl ConsistencyLevel {
         }
     }
 
+    /// Parses a consistency level, returning the default one if the name is not recognized.
+    /// Use `str::parse` to reject unknown names instead.
     pub fn from_str_to_enum(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "any" => ConsistencyLevel::Any,
-            "one" => ConsistencyLevel::One,
-            "two" => ConsistencyLevel::Two,
-            "three" => ConsistencyLevel::Three,
-            "quorum" => ConsistencyLevel::Quorum,
-            "all" => ConsistencyLevel::All,
-            "localquorum" => ConsistencyLevel::LocalQuorum,
-            "eachquorum" => ConsistencyLevel::EachQuorum,
-            "serial" => ConsistencyLevel::Serial,
-            "localserial" => ConsistencyLevel::LocalSerial,
-            "localone" => ConsistencyLevel::LocalOne,
-            _ => Default::default(), // Returns the default value if the input is not recognized
+        s.parse().unwrap_or_default()
+    }
+}
+
+impl FromStr for ConsistencyLevel {
+    type Err = String;
+
+    /// Parses the name of a consistency level, ignoring case and underscores, so that both
+    /// `LOCAL_QUORUM` and `localquorum` are accepted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "").as_str() {
+            "any" => Ok(ConsistencyLevel::Any),
+            "one" => Ok(ConsistencyLevel::One),
+            "two" => Ok(ConsistencyLevel::Two),
+            "three" => Ok(ConsistencyLevel::Three),
+            "quorum" => Ok(ConsistencyLevel::Quorum),
+            "all" => Ok(ConsistencyLevel::All),
+            "localquorum" => Ok(ConsistencyLevel::LocalQuorum),
+            "eachquorum" => Ok(ConsistencyLevel::EachQuorum),
+            "serial" => Ok(ConsistencyLevel::Serial),
+            "localserial" => Ok(ConsistencyLevel::LocalSerial),
+            "localone" => Ok(ConsistencyLevel::LocalOne),
+            _ => Err(format!("Unknown consistency level {}", s)),
         }
     }
 }
@@ -72,3 +86,19 @@ impl std::fmt::Display for ConsistencyLevel {
         write!(f, "{}", s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_parsed_and_unknown_ones_rejected() {
+        assert_eq!("QUORUM".parse(), Ok(ConsistencyLevel::Quorum));
+        assert_eq!("local_one".parse(), Ok(ConsistencyLevel::LocalOne));
+        assert!("QUORUMM".parse::<ConsistencyLevel>().is_err());
+
+        for level in [ConsistencyLevel::One, ConsistencyLevel::EachQuorum] {
+            assert_eq!(level.to_string().parse(), Ok(level));
+        }
+    }
+}
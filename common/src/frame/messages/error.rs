@@ -1,7 +1,47 @@
-use crate::frame::messages::notation::{read_int, write_string};
+use crate::frame::messages::consistency_level::ConsistencyLevel;
+use crate::frame::messages::notation::{
+    read_byte, read_consistency, read_int, read_string, write_byte, write_consistency, write_int,
+    write_string,
+};
 use std::io;
 use std::io::Cursor;
 
+/// The type of write that timed out, sent in the body of a `WriteTimeout` error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum WriteType {
+    #[default]
+    Simple,
+    Batch,
+    UnloggedBatch,
+    Counter,
+    BatchLog,
+    Cas,
+}
+
+impl WriteType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WriteType::Simple => "SIMPLE",
+            WriteType::Batch => "BATCH",
+            WriteType::UnloggedBatch => "UNLOGGED_BATCH",
+            WriteType::Counter => "COUNTER",
+            WriteType::BatchLog => "BATCH_LOG",
+            WriteType::Cas => "CAS",
+        }
+    }
+
+    pub fn from_str_to_enum(s: &str) -> Self {
+        match s {
+            "BATCH" => WriteType::Batch,
+            "UNLOGGED_BATCH" => WriteType::UnloggedBatch,
+            "COUNTER" => WriteType::Counter,
+            "BATCH_LOG" => WriteType::BatchLog,
+            "CAS" => WriteType::Cas,
+            _ => WriteType::Simple,
+        }
+    }
+}
+
 /// ```ignore
 /// 9. Error codes
 ///
@@ -96,20 +136,33 @@ use std::io::Cursor;
 ///               this host. The rest of the ERROR message body will be [short
 ///               bytes] representing the unknown ID.
 /// ```
-
 //const UNKNOWN: &str = "Unknown error code";
 #[repr(i32)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ErrorCode {
     ServerError = 0x0000,
     ProtocolError = 0x000A,
     BadCredentials = 0x0100,
-    UnavailableException = 0x1000,
+    UnavailableException {
+        consistency: ConsistencyLevel,
+        required: i32,
+        alive: i32,
+    } = 0x1000,
     Overloaded = 0x1001,
     IsBootstrapping = 0x1002,
     TruncateError = 0x1003,
-    WriteTimeout = 0x1100,
-    ReadTimeout = 0x1200,
+    WriteTimeout {
+        consistency: ConsistencyLevel,
+        received: i32,
+        block_for: i32,
+        write_type: WriteType,
+    } = 0x1100,
+    ReadTimeout {
+        consistency: ConsistencyLevel,
+        received: i32,
+        block_for: i32,
+        data_present: bool,
+    } = 0x1200,
     SyntaxError = 0x2000,
     Unauthorized = 0x2100,
     Invalid = 0x2200,
@@ -126,12 +179,26 @@ impl TryFrom<i32> for ErrorCode {
             0x0000 => Ok(ErrorCode::ServerError),
             0x000A => Ok(ErrorCode::ProtocolError),
             0x0100 => Ok(ErrorCode::BadCredentials),
-            0x1000 => Ok(ErrorCode::UnavailableException),
+            0x1000 => Ok(ErrorCode::UnavailableException {
+                consistency: ConsistencyLevel::default(),
+                required: 0,
+                alive: 0,
+            }),
             0x1001 => Ok(ErrorCode::Overloaded),
             0x1002 => Ok(ErrorCode::IsBootstrapping),
             0x1003 => Ok(ErrorCode::TruncateError),
-            0x1100 => Ok(ErrorCode::WriteTimeout),
-            0x1200 => Ok(ErrorCode::ReadTimeout),
+            0x1100 => Ok(ErrorCode::WriteTimeout {
+                consistency: ConsistencyLevel::default(),
+                received: 0,
+                block_for: 0,
+                write_type: WriteType::default(),
+            }),
+            0x1200 => Ok(ErrorCode::ReadTimeout {
+                consistency: ConsistencyLevel::default(),
+                received: 0,
+                block_for: 0,
+                data_present: false,
+            }),
             0x2000 => Ok(ErrorCode::SyntaxError),
             0x2100 => Ok(ErrorCode::Unauthorized),
             0x2200 => Ok(ErrorCode::Invalid),
@@ -151,13 +218,75 @@ impl ErrorCode {
         let mut body = Vec::new();
         body.extend_from_slice(&i32::from(*self).to_be_bytes());
         write_string(&mut body, self.message());
+        match self {
+            ErrorCode::UnavailableException {
+                consistency,
+                required,
+                alive,
+            } => {
+                write_consistency(&mut body, *consistency);
+                write_int(&mut body, *required);
+                write_int(&mut body, *alive);
+            }
+            ErrorCode::WriteTimeout {
+                consistency,
+                received,
+                block_for,
+                write_type,
+            } => {
+                write_consistency(&mut body, *consistency);
+                write_int(&mut body, *received);
+                write_int(&mut body, *block_for);
+                write_string(&mut body, write_type.as_str());
+            }
+            ErrorCode::ReadTimeout {
+                consistency,
+                received,
+                block_for,
+                data_present,
+            } => {
+                write_consistency(&mut body, *consistency);
+                write_int(&mut body, *received);
+                write_int(&mut body, *block_for);
+                write_byte(&mut body, *data_present as u8);
+            }
+            _ => {}
+        }
         body
     }
 
     pub fn deserialize_to_code(body: &[u8]) -> io::Result<Self> {
         let mut cursor = Cursor::new(body);
         let code = read_int(&mut cursor)?;
-        ErrorCode::try_from(code)
+        match ErrorCode::try_from(code)? {
+            ErrorCode::UnavailableException { .. } => {
+                read_string(&mut cursor)?;
+                Ok(ErrorCode::UnavailableException {
+                    consistency: read_consistency(&mut cursor)?,
+                    required: read_int(&mut cursor)?,
+                    alive: read_int(&mut cursor)?,
+                })
+            }
+            ErrorCode::WriteTimeout { .. } => {
+                read_string(&mut cursor)?;
+                Ok(ErrorCode::WriteTimeout {
+                    consistency: read_consistency(&mut cursor)?,
+                    received: read_int(&mut cursor)?,
+                    block_for: read_int(&mut cursor)?,
+                    write_type: WriteType::from_str_to_enum(&read_string(&mut cursor)?),
+                })
+            }
+            ErrorCode::ReadTimeout { .. } => {
+                read_string(&mut cursor)?;
+                Ok(ErrorCode::ReadTimeout {
+                    consistency: read_consistency(&mut cursor)?,
+                    received: read_int(&mut cursor)?,
+                    block_for: read_int(&mut cursor)?,
+                    data_present: read_byte(&mut cursor)? != 0,
+                })
+            }
+            code => Ok(code),
+        }
     }
 
     pub fn message(&self) -> &'static str {
@@ -165,12 +294,12 @@ impl ErrorCode {
             ErrorCode::ServerError => "A server error occurred.",
             ErrorCode::ProtocolError => "There was a protocol error.",
             ErrorCode::BadCredentials => "Invalid credentials provided.",
-            ErrorCode::UnavailableException => "The requested service is unavailable.",
+            ErrorCode::UnavailableException { .. } => "The requested service is unavailable.",
             ErrorCode::Overloaded => "The server is overloaded.",
             ErrorCode::IsBootstrapping => "The server is currently bootstrapping.",
             ErrorCode::TruncateError => "An error occurred while truncating data.",
-            ErrorCode::WriteTimeout => "A write timeout occurred.",
-            ErrorCode::ReadTimeout => "A read timeout occurred.",
+            ErrorCode::WriteTimeout { .. } => "A write timeout occurred.",
+            ErrorCode::ReadTimeout { .. } => "A read timeout occurred.",
             ErrorCode::SyntaxError => "There is a syntax error in the query.",
             ErrorCode::Unauthorized => "You are unauthorized to perform this action.",
             ErrorCode::Invalid => "The request was invalid.",
@@ -183,6 +312,65 @@ impl ErrorCode {
 
 impl From<ErrorCode> for i32 {
     fn from(code: ErrorCode) -> Self {
-        code as i32
+        match code {
+            ErrorCode::ServerError => 0x0000,
+            ErrorCode::ProtocolError => 0x000A,
+            ErrorCode::BadCredentials => 0x0100,
+            ErrorCode::UnavailableException { .. } => 0x1000,
+            ErrorCode::Overloaded => 0x1001,
+            ErrorCode::IsBootstrapping => 0x1002,
+            ErrorCode::TruncateError => 0x1003,
+            ErrorCode::WriteTimeout { .. } => 0x1100,
+            ErrorCode::ReadTimeout { .. } => 0x1200,
+            ErrorCode::SyntaxError => 0x2000,
+            ErrorCode::Unauthorized => 0x2100,
+            ErrorCode::Invalid => 0x2200,
+            ErrorCode::ConfigError => 0x2300,
+            ErrorCode::AlreadyExists => 0x2400,
+            ErrorCode::Unprepared => 0x2500,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeouts_roundtrip_with_their_body() {
+        let unavailable = ErrorCode::UnavailableException {
+            consistency: ConsistencyLevel::Quorum,
+            required: 2,
+            alive: 1,
+        };
+        let body = unavailable.serialize();
+        assert_eq!(ErrorCode::deserialize_to_code(&body).unwrap(), unavailable);
+
+        let write_timeout = ErrorCode::WriteTimeout {
+            consistency: ConsistencyLevel::Quorum,
+            received: 1,
+            block_for: 2,
+            write_type: WriteType::Batch,
+        };
+        let body = write_timeout.serialize();
+        assert_eq!(ErrorCode::deserialize_to_code(&body).unwrap(), write_timeout);
+
+        let read_timeout = ErrorCode::ReadTimeout {
+            consistency: ConsistencyLevel::All,
+            received: 2,
+            block_for: 3,
+            data_present: true,
+        };
+        let body = read_timeout.serialize();
+        assert_eq!(ErrorCode::deserialize_to_code(&body).unwrap(), read_timeout);
+    }
+
+    #[test]
+    fn test_codes_without_body_roundtrip() {
+        let body = ErrorCode::Overloaded.serialize();
+        assert_eq!(
+            ErrorCode::deserialize_to_code(&body).unwrap(),
+            ErrorCode::Overloaded
+        );
     }
 }
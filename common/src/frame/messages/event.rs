@@ -0,0 +1,102 @@
+use std::io::{self, Cursor};
+
+use crate::frame::messages::notation::{read_string, write_string};
+
+/// Events about the nodes that join or leave the cluster.
+pub const TOPOLOGY_CHANGE: &str = "TOPOLOGY_CHANGE";
+/// Events about the nodes that go down or come back up.
+pub const STATUS_CHANGE: &str = "STATUS_CHANGE";
+
+/// A change in the cluster that a node pushes to the clients registered for its type. Each
+/// node is identified by the address clients connect to, `ip:port`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClusterEvent {
+    NewNode(String),
+    RemovedNode(String),
+    Up(String),
+    Down(String),
+}
+
+impl ClusterEvent {
+    /// Returns the type clients register for to receive the event.
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            ClusterEvent::NewNode(_) | ClusterEvent::RemovedNode(_) => TOPOLOGY_CHANGE,
+            ClusterEvent::Up(_) | ClusterEvent::Down(_) => STATUS_CHANGE,
+        }
+    }
+
+    /// Returns the address of the node the event is about.
+    pub fn address(&self) -> &str {
+        match self {
+            ClusterEvent::NewNode(address)
+            | ClusterEvent::RemovedNode(address)
+            | ClusterEvent::Up(address)
+            | ClusterEvent::Down(address) => address,
+        }
+    }
+
+    fn change(&self) -> &'static str {
+        match self {
+            ClusterEvent::NewNode(_) => "NEW_NODE",
+            ClusterEvent::RemovedNode(_) => "REMOVED_NODE",
+            ClusterEvent::Up(_) => "UP",
+            ClusterEvent::Down(_) => "DOWN",
+        }
+    }
+
+    /// Writes the type of the event, the change and the address of the node.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        write_string(&mut body, self.event_type());
+        write_string(&mut body, self.change());
+        write_string(&mut body, self.address());
+        body
+    }
+
+    pub fn deserialize(body: &[u8]) -> io::Result<Self> {
+        let mut cursor = Cursor::new(body);
+        let event_type = read_string(&mut cursor)?;
+        let change = read_string(&mut cursor)?;
+        let address = read_string(&mut cursor)?;
+
+        match (event_type.as_str(), change.as_str()) {
+            (TOPOLOGY_CHANGE, "NEW_NODE") => Ok(ClusterEvent::NewNode(address)),
+            (TOPOLOGY_CHANGE, "REMOVED_NODE") => Ok(ClusterEvent::RemovedNode(address)),
+            (STATUS_CHANGE, "UP") => Ok(ClusterEvent::Up(address)),
+            (STATUS_CHANGE, "DOWN") => Ok(ClusterEvent::Down(address)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown event {} {}", event_type, change),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::messages::Message;
+
+    #[test]
+    fn events_roundtrip() {
+        for event in [
+            ClusterEvent::NewNode("127.0.0.1:9043".to_string()),
+            ClusterEvent::RemovedNode("127.0.0.1:9043".to_string()),
+            ClusterEvent::Up("127.0.0.1:9043".to_string()),
+            ClusterEvent::Down("127.0.0.1:9043".to_string()),
+        ] {
+            let body = Message::Event(event.clone()).serialize();
+            match Message::deserialize(0x0C, body).unwrap() {
+                Message::Event(received) => assert_eq!(received, event),
+                other => panic!("Expected an event, got {:?}", other),
+            }
+        }
+
+        let body = Message::Register(vec![TOPOLOGY_CHANGE.to_string()]).serialize();
+        match Message::deserialize(0x0B, body).unwrap() {
+            Message::Register(types) => assert_eq!(types, [TOPOLOGY_CHANGE]),
+            other => panic!("Expected a register, got {:?}", other),
+        }
+    }
+}
@@ -1,15 +1,23 @@
 use crate::frame::messages::error::ErrorCode;
+use crate::frame::messages::event::ClusterEvent;
 use crate::frame::messages::query::Query;
 use crate::frame::messages::query_result::QueryResult;
 use crate::frame::messages::startup_options::{
     deserialize_options, deserialize_startup, serialize_options, serialize_startup,
 };
+use notation::{
+    read_long_string, read_short_bytes, read_string_list, write_long_string, write_short_bytes,
+    write_string_list,
+};
 use std::io;
+use std::io::Cursor;
 
 pub mod authentication;
 pub mod consistency_level;
 pub mod error;
+pub mod event;
 mod notation;
+pub mod prepared;
 pub mod query;
 pub mod query_result;
 pub mod startup_options;
@@ -25,10 +33,13 @@ pub enum Message {
     Supported(Vec<(String, Vec<String>)>) = 0x06,
     Query(Query) = 0x07,
     Result(QueryResult) = 0x08,
-    Prepare = 0x09,
-    Execute = 0x0A,
-    Register = 0x0B,
-    Event = 0x0C,
+    Prepare(String) = 0x09,
+    /// The id of a prepared statement and the parameters to run it with. The `query_string`
+    /// of the parameters is empty.
+    Execute(Vec<u8>, Query) = 0x0A,
+    /// The types of events the client wants to be pushed, see `event`.
+    Register(Vec<String>) = 0x0B,
+    Event(ClusterEvent) = 0x0C,
     Batch = 0x0D,
     AuthChallenge(Vec<u8>) = 0x0E,
     AuthResponse(Vec<u8>) = 0x0F,
@@ -43,17 +54,22 @@ impl Message {
             0x02 => Ok(Message::Ready),
 
             0x03 => Ok(Message::Authenticate(
-                authentication::deserialize_authenticate(&body),
+                authentication::deserialize_authenticate(&body)?,
             )),
 
             0x05 => Ok(Message::Options),
             0x06 => Ok(Message::Supported(deserialize_options(&body)?)),
             0x07 => Ok(Message::Query(Query::deserialize(&body)?)),
             0x08 => Ok(Message::Result(QueryResult::deserialize(&body)?)),
-            0x09 => Ok(Message::Prepare),
-            0x0A => Ok(Message::Execute),
-            0x0B => Ok(Message::Register),
-            0x0C => Ok(Message::Event),
+            0x09 => Ok(Message::Prepare(read_long_string(&mut Cursor::new(&body))?)),
+            0x0A => {
+                let mut cursor = Cursor::new(body.as_slice());
+                let id = read_short_bytes(&mut cursor)?;
+                let parameters = Query::deserialize_parameters(String::new(), &mut cursor)?;
+                Ok(Message::Execute(id, parameters))
+            }
+            0x0B => Ok(Message::Register(read_string_list(&mut Cursor::new(&body))?)),
+            0x0C => Ok(Message::Event(ClusterEvent::deserialize(&body)?)),
             0x0D => Ok(Message::Batch),
             0x0E => Ok(Message::AuthChallenge(body)),
             0x0F => Ok(Message::AuthResponse(body)),
@@ -73,6 +89,24 @@ impl Message {
             Message::Query(query) => query.serialize(),
             Message::Result(query_result) => query_result.serialize(),
 
+            Message::Prepare(query_string) => {
+                let mut body = Vec::new();
+                write_long_string(&mut body, query_string);
+                body
+            }
+            Message::Execute(id, parameters) => {
+                let mut body = Vec::new();
+                write_short_bytes(&mut body, id);
+                parameters.serialize_parameters(&mut body);
+                body
+            }
+            Message::Register(event_types) => {
+                let mut body = Vec::new();
+                write_string_list(&mut body, event_types.iter().map(String::as_str).collect());
+                body
+            }
+            Message::Event(event) => event.serialize(),
+
             Message::Authenticate(iauthenticator) => {
                 authentication::serialize_authenticate(iauthenticator)
             }
@@ -93,10 +127,10 @@ impl Message {
             Message::Supported(_) => 0x06,
             Message::Query(_) => 0x07,
             Message::Result(_) => 0x08,
-            Message::Prepare => 0x09,
-            Message::Execute => 0x0A,
-            Message::Register => 0x0B,
-            Message::Event => 0x0C,
+            Message::Prepare(_) => 0x09,
+            Message::Execute(..) => 0x0A,
+            Message::Register(_) => 0x0B,
+            Message::Event(_) => 0x0C,
             Message::Batch => 0x0D,
             Message::AuthChallenge(_) => 0x0E,
             Message::AuthResponse(_) => 0x0F,
@@ -99,6 +99,14 @@ pub fn write_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
     buffer.extend_from_slice(bytes);
 }
 
+// Write [bytes] that may be null, as a negative length
+pub fn write_nullable_bytes(buffer: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => write_bytes(buffer, bytes),
+        None => write_int(buffer, -1),
+    }
+}
+
 // Write [short bytes]
 pub fn write_short_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
     write_short(buffer, bytes.len() as u16); // write [short] n
@@ -161,16 +169,43 @@ pub fn read_long(cursor: &mut Cursor<&[u8]>) -> io::Result<i64> {
 
 pub fn read_string(cursor: &mut Cursor<&[u8]>) -> io::Result<String> {
     let len = read_short(cursor)? as usize;
-    let mut buf = vec![0; len];
-    cursor.read_exact(&mut buf)?;
-    Ok(String::from_utf8(buf).unwrap())
+    utf8(read_exact_bytes(cursor, len)?)
 }
 
 pub fn read_long_string(cursor: &mut Cursor<&[u8]>) -> io::Result<String> {
-    let len = read_int(cursor)? as usize;
-    let mut buf = vec![0; len];
+    let len = read_length(cursor)?;
+    utf8(read_exact_bytes(cursor, len)?)
+}
+
+/// Reads an [int] length or count, rejecting negative ones.
+pub fn read_length(cursor: &mut Cursor<&[u8]>) -> io::Result<usize> {
+    let len = read_int(cursor)?;
+    usize::try_from(len).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Negative length {}", len))
+    })
+}
+
+/// Checks that `count` elements of at least `min_size` bytes each fit in the rest of the
+/// cursor, so a malformed count can't make the reader reserve memory the frame doesn't have.
+pub fn check_count(cursor: &Cursor<&[u8]>, count: usize, min_size: usize) -> io::Result<usize> {
+    let remaining = cursor.get_ref().len().saturating_sub(cursor.position() as usize);
+    if count.saturating_mul(min_size.max(1)) > remaining {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} elements don't fit in {} bytes", count, remaining),
+        ));
+    }
+    Ok(count)
+}
+
+fn read_exact_bytes(cursor: &mut Cursor<&[u8]>, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; check_count(cursor, len, 1)?];
     cursor.read_exact(&mut buf)?;
-    Ok(String::from_utf8(buf).unwrap())
+    Ok(buf)
+}
+
+fn utf8(bytes: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 /*
 fn read_uuid(cursor: &mut Cursor<&[u8]>) -> io::Result<[u8; 16]> {
@@ -179,7 +214,7 @@ fn read_uuid(cursor: &mut Cursor<&[u8]>) -> io::Result<[u8; 16]> {
     Ok(buf)
 }*/
 
-fn read_string_list(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<String>> {
+pub fn read_string_list(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<String>> {
     let len = read_short(cursor)?;
     let mut list = Vec::with_capacity(len as usize);
     for _ in 0..len {
@@ -189,17 +224,22 @@ fn read_string_list(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<String>> {
 }
 
 pub fn read_bytes(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<u8>> {
+    let len = read_length(cursor)?;
+    read_exact_bytes(cursor, len)
+}
+
+/// Reads [bytes] that may be null, `None` if its length is negative.
+pub fn read_nullable_bytes(cursor: &mut Cursor<&[u8]>) -> io::Result<Option<Vec<u8>>> {
     let len = read_int(cursor)?;
-    let mut buf = vec![0; len as usize];
-    cursor.read_exact(&mut buf)?;
-    Ok(buf)
+    match usize::try_from(len) {
+        Ok(len) => read_exact_bytes(cursor, len).map(Some),
+        Err(_) => Ok(None),
+    }
 }
 
 pub fn read_short_bytes(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<u8>> {
     let len = read_short(cursor)? as usize;
-    let mut buf = vec![0; len];
-    cursor.read_exact(&mut buf)?;
-    Ok(buf)
+    read_exact_bytes(cursor, len)
 }
 
 pub fn read_byte(cursor: &mut Cursor<&[u8]>) -> io::Result<u8> {
@@ -0,0 +1,244 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use uuid::Uuid;
+
+use crate::frame::messages::error::ErrorCode;
+
+/// Length in bytes of the id of a prepared statement.
+const PREPARED_ID_LEN: usize = 16;
+
+/// Format in which the nodes write timestamps.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Format of the `_timestamp` the coordinators write with each mutation. It goes down to the
+/// microsecond, so two writes of the same second are still ordered.
+pub const WRITE_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.6f";
+
+/// A value bound to a `?` marker of a prepared statement, or of a column of a `RowSet`. The
+/// nodes parse the text of the queries, so bound values travel as their CQL literal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    Null,
+    Int(i32),
+    Bigint(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    Timestamp(DateTime<Utc>),
+    Uuid(Uuid),
+}
+
+impl Value {
+    /// Returns the CQL literal of the value. The node only reads non-negative integers
+    /// unquoted, so the other numbers, timestamps and uuids are quoted like texts.
+    pub fn to_literal(&self) -> String {
+        match self {
+            Value::Null => "NULL".to_string(),
+            Value::Int(value) => integer_literal(*value as i64),
+            Value::Bigint(value) => integer_literal(*value),
+            Value::Float(value) => format!("'{}'", value),
+            Value::Bool(value) => value.to_string(),
+            Value::Text(value) => quote(value),
+            Value::Timestamp(value) => format!("'{}'", value.format(TIMESTAMP_FORMAT)),
+            Value::Uuid(value) => format!("'{}'", value.hyphenated()),
+        }
+    }
+
+    /// Serializes the value to send it in an EXECUTE.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.to_literal().into_bytes()
+    }
+
+    /// Returns the value as the nodes store it, without quotes, or `None` if it is `Null`.
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            Value::Null => None,
+            Value::Int(value) => Some(value.to_string()),
+            Value::Bigint(value) => Some(value.to_string()),
+            Value::Float(value) => Some(value.to_string()),
+            Value::Bool(value) => Some(value.to_string()),
+            Value::Text(value) => Some(value.clone()),
+            Value::Timestamp(value) => Some(value.format(TIMESTAMP_FORMAT).to_string()),
+            Value::Uuid(value) => Some(value.hyphenated().to_string()),
+        }
+    }
+
+    /// Compares two values of the same type: numbers, timestamps and texts by their value,
+    /// timeuuids by their time and other uuids by their bytes. Returns `None` if the values
+    /// are of different types or any of them is `Null`.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        let ordering = match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Bigint(a), Value::Bigint(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+            (Value::Uuid(a), Value::Uuid(b)) => match (a.get_timestamp(), b.get_timestamp()) {
+                (Some(time_a), Some(time_b)) => time_a
+                    .to_gregorian()
+                    .cmp(&time_b.to_gregorian())
+                    .then_with(|| a.cmp(b)),
+                _ => a.cmp(b),
+            },
+            _ => return None,
+        };
+        Some(ordering)
+    }
+}
+
+/// Returns the CQL literal of a text, with its quotes doubled so that a text like `O'Higgins`
+/// can't end the literal early.
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn integer_literal(value: i64) -> String {
+    match value < 0 {
+        true => format!("'{}'", value),
+        false => value.to_string(),
+    }
+}
+
+/// Returns the id of a prepared statement. It only depends on the text of the statement, so
+/// every node gives a statement the same id.
+pub fn prepared_id(query_string: &str) -> Vec<u8> {
+    Sha256::digest(query_string.as_bytes())[..PREPARED_ID_LEN].to_vec()
+}
+
+/// Replaces the `?` markers of a prepared statement with the values of an EXECUTE, in order.
+/// Question marks inside string literals are kept.
+///
+/// # Parameters
+/// - `query_string`: The prepared statement.
+/// - `values`: The serialized values.
+///
+/// # Returns
+/// The statement to run, or `Invalid` if the number of values doesn't match the markers.
+pub fn bind(query_string: &str, values: &[Vec<u8>]) -> Result<String, ErrorCode> {
+    let mut bound = String::with_capacity(query_string.len());
+    let mut values = values.iter();
+    let mut in_literal = false;
+
+    for c in query_string.chars() {
+        match c {
+            '\'' => {
+                in_literal = !in_literal;
+                bound.push(c);
+            }
+            '?' if !in_literal => {
+                let value = values.next().ok_or(ErrorCode::Invalid)?;
+                bound.push_str(&String::from_utf8_lossy(value));
+            }
+            _ => bound.push(c),
+        }
+    }
+
+    match values.next() {
+        Some(_) => Err(ErrorCode::Invalid),
+        None => Ok(bound),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::messages::consistency_level::ConsistencyLevel;
+    use crate::frame::messages::query::Query;
+    use crate::frame::messages::Message;
+
+    #[test]
+    fn values_are_bound_in_order() {
+        let statement = "INSERT INTO flights (id, origin, status) VALUES (?, ?, 'on time?')";
+        let values = [
+            Value::Int(10001).serialize(),
+            Value::Text("Ezeiza's".to_string()).serialize(),
+        ];
+        assert_eq!(
+            bind(statement, &values),
+            Ok(
+                "INSERT INTO flights (id, origin, status) VALUES (10001, 'Ezeiza''s', 'on time?')"
+                    .to_string()
+            )
+        );
+        assert_eq!(bind(statement, &values[..1]), Err(ErrorCode::Invalid));
+    }
+
+    #[test]
+    fn values_are_written_as_literals_the_node_reads() {
+        let departure = DateTime::parse_from_rfc3339("2024-05-01T10:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let id = Uuid::from_u128(0x67e55044_10b1_426f_9247_bb680e5fe0c8);
+
+        assert_eq!(Value::Bigint(-3).to_literal(), "'-3'");
+        assert_eq!(Value::Float(-34.8).to_literal(), "'-34.8'");
+        assert_eq!(Value::Bool(true).to_literal(), "true");
+        assert_eq!(quote("Martin's'); DROP"), "'Martin''s''); DROP'");
+        assert_eq!(
+            Value::Timestamp(departure).to_literal(),
+            "'2024-05-01 10:30:00'"
+        );
+        assert_eq!(
+            Value::Uuid(id).to_literal(),
+            "'67e55044-10b1-426f-9247-bb680e5fe0c8'"
+        );
+    }
+
+    #[test]
+    fn execute_roundtrip() {
+        let mut parameters = Query::default(String::new(), ConsistencyLevel::Quorum);
+        parameters.values = Some(vec![(None, Value::Int(20).serialize())]);
+        let execute = Message::Execute(vec![1, 2, 3], parameters);
+
+        let Message::Execute(id, parameters) =
+            Message::deserialize(0x0A, execute.serialize()).unwrap()
+        else {
+            panic!("Expected Message::Execute");
+        };
+        assert_eq!(id, vec![1, 2, 3]);
+        assert_eq!(parameters.consistency_level, ConsistencyLevel::Quorum);
+        assert_eq!(parameters.values, Some(vec![(None, b"20".to_vec())]));
+
+        let prepare = Message::Prepare("SELECT * FROM flights WHERE id = ?".to_string());
+        let Message::Prepare(query_string) =
+            Message::deserialize(0x09, prepare.serialize()).unwrap()
+        else {
+            panic!("Expected Message::Prepare");
+        };
+        assert_eq!(query_string, "SELECT * FROM flights WHERE id = ?");
+    }
+
+    #[test]
+    fn the_id_only_depends_on_the_statement() {
+        let statement = "SELECT * FROM flights WHERE id = ?";
+        assert_eq!(prepared_id(statement), prepared_id(statement));
+        assert_ne!(prepared_id(statement), prepared_id("SELECT * FROM flights"));
+        assert_eq!(prepared_id(statement).len(), PREPARED_ID_LEN);
+    }
+
+    #[test]
+    fn values_of_the_same_type_are_compared_by_their_value() {
+        assert_eq!(Value::Int(9).compare(&Value::Int(10)), Some(Ordering::Less));
+        assert_eq!(
+            Value::Float(-1.5).compare(&Value::Float(-2.0)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            Value::Text("10".to_string()).compare(&Value::Text("9".to_string())),
+            Some(Ordering::Less)
+        );
+        assert_eq!(Value::Int(1).compare(&Value::Bigint(1)), None);
+        assert_eq!(Value::Null.compare(&Value::Null), None);
+
+        // Los timeuuid se comparan por su tiempo y no por sus bytes
+        let earlier = Uuid::parse_str("ffffffff-0000-11ef-8000-000000000000").unwrap();
+        let later = Uuid::parse_str("00000000-0001-11ef-8000-000000000000").unwrap();
+        assert_eq!(
+            Value::Uuid(earlier).compare(&Value::Uuid(later)),
+            Some(Ordering::Less)
+        );
+    }
+}
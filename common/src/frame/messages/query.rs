@@ -54,13 +54,21 @@ impl Query {
         let mut cursor = Cursor::new(body);
 
         let query_string = read_long_string(&mut cursor)?;
-        let consistency = read_consistency(&mut cursor)?;
+        Self::deserialize_parameters(query_string, &mut cursor)
+    }
+
+    /// Reads the parameters that follow the statement of a QUERY, or the id of an EXECUTE.
+    pub(crate) fn deserialize_parameters(
+        query_string: String,
+        cursor: &mut Cursor<&[u8]>,
+    ) -> io::Result<Self> {
+        let consistency = read_consistency(cursor)?;
 
-        let flags = read_byte(&mut cursor)?;
+        let flags = read_byte(cursor)?;
 
         let values = if QueryFlag::Values.is_set(flags) {
             Some(deserialize_values(
-                &mut cursor,
+                cursor,
                 QueryFlag::WithNamesForValues.is_set(flags),
             )?)
         } else {
@@ -70,19 +78,19 @@ impl Query {
         let skip_metadata = QueryFlag::SkipMetadata.is_set(flags);
 
         let result_page_size =
-            read_optional_value(&mut cursor, QueryFlag::PageSize.is_set(flags), read_int)?;
+            read_optional_value(cursor, QueryFlag::PageSize.is_set(flags), read_int)?;
         let paging_state = read_optional_value(
-            &mut cursor,
+            cursor,
             QueryFlag::WithPagingState.is_set(flags),
             read_bytes,
         )?;
         let serial_consistency = read_optional_value(
-            &mut cursor,
+            cursor,
             QueryFlag::WithSerialConsistency.is_set(flags),
             read_consistency,
         )?;
         let time_stamp = read_optional_value(
-            &mut cursor,
+            cursor,
             QueryFlag::WithDefaultTimestamp.is_set(flags),
             read_long,
         )?;
@@ -103,33 +111,37 @@ impl Query {
         let mut body = Vec::new();
 
         write_long_string(&mut body, &self.query_string);
-        write_consistency(&mut body, self.consistency_level);
-        write_byte(&mut body, self.serialize_flags());
+        self.serialize_parameters(&mut body);
+        body
+    }
+
+    /// Writes the parameters that follow the statement of a QUERY, or the id of an EXECUTE.
+    pub(crate) fn serialize_parameters(&self, body: &mut Vec<u8>) {
+        write_consistency(body, self.consistency_level);
+        write_byte(body, self.serialize_flags());
 
         if let Some(values) = &self.values {
-            write_short(&mut body, values.len() as u16);
+            write_short(body, values.len() as u16);
             for (name, value) in values {
                 if let Some(name_str) = name {
-                    write_string(&mut body, name_str);
+                    write_string(body, name_str);
                 }
-                write_bytes(&mut body, value);
+                write_bytes(body, value);
             }
         }
 
         if let Some(page_size) = self.result_page_size {
-            write_int(&mut body, page_size);
+            write_int(body, page_size);
         }
         if let Some(paging_state) = &self.paging_state {
-            write_bytes(&mut body, paging_state);
+            write_bytes(body, paging_state);
         }
         if let Some(serial_consistency) = self.serial_consistency {
-            write_consistency(&mut body, serial_consistency);
+            write_consistency(body, serial_consistency);
         }
         if let Some(time_stamp) = self.time_stamp {
-            write_long(&mut body, time_stamp);
+            write_long(body, time_stamp);
         }
-
-        body
     }
 
     fn serialize_flags(&self) -> u8 {
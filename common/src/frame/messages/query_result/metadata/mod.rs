@@ -4,7 +4,8 @@ mod spec;
 use crate::frame::messages::notation::{
     read_bytes, read_int, write_bytes, write_int,
 };
-use crate::frame::messages::query_result::metadata::spec::Spec;
+use crate::frame::messages::query_result::metadata::spec::{GlobalSpec, Spec};
+use crate::frame::messages::query_result::row_set::{ColumnType, RowSet};
 use std::io::{self, Cursor};
 
 #[repr(i32)]
@@ -82,6 +83,40 @@ impl Metadata {
     pub fn get_columns_count(&self) -> i32 {
         self.columns_count
     }
+
+    /// Creates the metadata of rows of a table, with the name and type of each column.
+    ///
+    /// # Parameters
+    /// - `keyspace_name`: The keyspace of the table.
+    /// - `table_name`: The table of the rows.
+    /// - `columns`: The name and type of each column, in the order of the values of the rows.
+    /// - `paging_state`: The state to request the next page, `None` on the last page.
+    pub fn with_columns(
+        keyspace_name: &str,
+        table_name: &str,
+        columns: Vec<(String, ColumnType)>,
+        paging_state: Option<Vec<u8>>,
+    ) -> Self {
+        let cols = columns
+            .into_iter()
+            .map(|(name, column_type)| (name, column_type.into()))
+            .collect::<Vec<_>>();
+        Metadata {
+            columns_count: cols.len() as i32,
+            paging_state,
+            spec: Some(Spec::Global(GlobalSpec::new(keyspace_name, table_name, cols))),
+        }
+    }
+
+    /// Returns a set without rows with the columns of the metadata, `None` if it has none.
+    pub fn row_set(&self) -> io::Result<Option<RowSet>> {
+        self.spec.as_ref().map(Spec::row_set).transpose()
+    }
+
+    /// Returns the state to request the next page, `None` on the last page.
+    pub fn paging_state(&self) -> Option<&[u8]> {
+        self.paging_state.as_deref()
+    }
 }
 
 impl Default for Metadata {
@@ -1,6 +1,7 @@
 use crate::frame::messages::notation::{
     read_int, read_short, read_string, write_int, write_short, write_string,
 };
+use crate::frame::messages::query_result::row_set::ColumnType;
 use std::io;
 use std::io::Cursor;
 
@@ -137,6 +138,44 @@ impl Option {
     }
 }
 
+impl From<ColumnType> for Option {
+    fn from(column_type: ColumnType) -> Self {
+        match column_type {
+            ColumnType::Text => Option::Varchar,
+            ColumnType::Int => Option::Int,
+            ColumnType::Bigint => Option::Bigint,
+            ColumnType::Float => Option::Double,
+            ColumnType::Boolean => Option::Boolean,
+            ColumnType::Uuid => Option::Uuid,
+            ColumnType::Timeuuid => Option::Timeuuid,
+            ColumnType::Timestamp => Option::Timestamp,
+            ColumnType::Counter => Option::Counter,
+        }
+    }
+}
+
+impl Option {
+    /// Returns the type of the values of a column of this type, or an error for the types
+    /// the nodes don't have.
+    pub(crate) fn column_type(&self) -> io::Result<ColumnType> {
+        match self {
+            Option::Ascii | Option::Varchar => Ok(ColumnType::Text),
+            Option::Int => Ok(ColumnType::Int),
+            Option::Bigint => Ok(ColumnType::Bigint),
+            Option::Float | Option::Double => Ok(ColumnType::Float),
+            Option::Boolean => Ok(ColumnType::Boolean),
+            Option::Uuid => Ok(ColumnType::Uuid),
+            Option::Timeuuid => Ok(ColumnType::Timeuuid),
+            Option::Timestamp => Ok(ColumnType::Timestamp),
+            Option::Counter => Ok(ColumnType::Counter),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported column type {:?}", self),
+            )),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,5 +1,6 @@
-use crate::frame::messages::notation::{read_string, write_string};
+use crate::frame::messages::notation::{check_count, read_string, write_string};
 use crate::frame::messages::query_result::metadata::option::Option;
+use crate::frame::messages::query_result::row_set::{Column, RowSet};
 use std::io;
 use std::io::Cursor;
 
@@ -45,6 +46,7 @@ impl GlobalSpec {
         let keyspace_name = read_string(cursor)?;
         let table_name = read_string(cursor)?;
 
+        let column_count = check_count(cursor, column_count, 4)?;
         let mut cols = Vec::with_capacity(column_count);
         for _ in 0..column_count {
             let col_name = read_string(cursor)?;
@@ -69,6 +71,17 @@ impl GlobalSpec {
     }
 }
 
+impl GlobalSpec {
+    /// Creates the spec of columns of a single table.
+    pub(crate) fn new(keyspace_name: &str, table_name: &str, cols: Vec<(String, Option)>) -> Self {
+        GlobalSpec {
+            keyspace_name: keyspace_name.to_string(),
+            table_name: table_name.to_string(),
+            cols,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Spec {
     Global(GlobalSpec),
@@ -88,6 +101,7 @@ impl Spec {
             )?));
         }
 
+        let columns_count = check_count(cursor, columns_count, 4)?;
         let mut columns = Vec::with_capacity(columns_count);
         for _ in 0..columns_count {
             columns.push(NotGlobalCol::parse(cursor)?);
@@ -95,6 +109,28 @@ impl Spec {
         Ok(Self::NotGlobal(columns))
     }
 
+    /// Returns a set without rows with the table and the columns of the spec. Columns of
+    /// different tables are taken as columns of the table of the first one.
+    pub(crate) fn row_set(&self) -> io::Result<RowSet> {
+        let (keyspace_name, table_name, cols) = match self {
+            Spec::Global(spec) => (
+                spec.keyspace_name.as_str(),
+                spec.table_name.as_str(),
+                spec.cols.iter().map(|(name, option)| (name, option)).collect(),
+            ),
+            Spec::NotGlobal(cols) => (
+                cols.first().map_or("", |col| col.keyspace_name.as_str()),
+                cols.first().map_or("", |col| col.table_name.as_str()),
+                cols.iter().map(|col| (&col.name, &col.option)).collect::<Vec<_>>(),
+            ),
+        };
+        let columns = cols
+            .into_iter()
+            .map(|(name, option)| Ok(Column::new(name, option.column_type()?)))
+            .collect::<io::Result<_>>()?;
+        Ok(RowSet::new(keyspace_name, table_name, columns))
+    }
+
     pub(crate) fn serialize(&self, buffer: &mut Vec<u8>) {
         match self {
             Spec::Global(ref global_spec) => global_spec.serialize(buffer),
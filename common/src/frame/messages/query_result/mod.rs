@@ -1,13 +1,16 @@
 mod metadata;
 pub(crate) mod row;
+pub mod row_set;
 
 use crate::frame::messages::notation::{
-    read_int, read_short_bytes, read_string, write_int, write_short_bytes, write_string,
+    check_count, read_int, read_length, read_short_bytes, read_string, write_int,
+    write_short_bytes, write_string,
 };
 use metadata::Metadata;
 use row::Row;
+use row_set::RowSet;
 use std::io::Cursor;
-use std::{io, vec};
+use std::io;
 
 #[repr(i32)]
 #[derive(Debug, Clone)]
@@ -67,12 +70,19 @@ impl QueryResult {
 
     fn deserialize_rows(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
         let metadata = Metadata::deserialize(cursor)?;
-        let rows_count = read_int(cursor)? as usize;
+        let columns_count = metadata.get_columns_count() as usize;
+        // Cada valor de una fila ocupa al menos su largo
+        let rows_count = read_length(cursor)?;
+        let rows_count = check_count(cursor, rows_count, columns_count.saturating_mul(4))?;
 
+        // Con los tipos de las columnas se rechazan los valores que no son de su tipo
+        let columns = metadata.row_set()?.map(|row_set| row_set.columns);
         let mut rows = Vec::with_capacity(rows_count);
-        let columns_count = metadata.get_columns_count() as usize;
         for _ in 0..rows_count {
             let row_i = Row::deserialize(columns_count, cursor)?;
+            for (column, value) in columns.iter().flatten().zip(&row_i.values) {
+                column.column_type.decode(value.as_deref())?;
+            }
             rows.push(row_i);
         }
         Ok(Self::Rows(metadata, rows))
@@ -120,23 +130,72 @@ impl QueryResult {
         bytes
     }
 
-    pub fn parse_json_to_rows(json: &str) -> QueryResult {
-        let serialized_json = Vec::from(json.as_bytes());
-        let row = Row {
-            values: vec![serialized_json],
+    /// Builds the result of a SELECT: the metadata has the name and type of each column, and
+    /// each row its values in the binary format of their type.
+    ///
+    /// # Parameters
+    /// - `row_set`: The rows of the result, or of a page of it.
+    /// - `paging_state`: The state to request the next page, `None` on the last page.
+    pub fn from_row_set(row_set: &RowSet, paging_state: Option<Vec<u8>>) -> QueryResult {
+        let columns = row_set
+            .columns
+            .iter()
+            .map(|column| (column.name.clone(), column.column_type))
+            .collect();
+        let metadata =
+            Metadata::with_columns(&row_set.keyspace, &row_set.table, columns, paging_state);
+        let rows = row_set
+            .rows
+            .iter()
+            .map(|values| Row {
+                values: row_set
+                    .columns
+                    .iter()
+                    .zip(values)
+                    .map(|(column, value)| column.column_type.encode(value))
+                    .collect(),
+            })
+            .collect();
+        QueryResult::Rows(metadata, rows)
+    }
+
+    /// Returns the rows of a result with their types, `None` if it isn't a `Rows` result with
+    /// the types of its columns.
+    pub fn row_set(&self) -> Option<RowSet> {
+        let QueryResult::Rows(metadata, rows) = self else {
+            return None;
         };
-        QueryResult::Rows(Metadata::default(), vec![row])
+        let mut row_set = metadata.row_set().ok()??;
+        for row in rows {
+            let values = row_set
+                .columns
+                .iter()
+                .zip(&row.values)
+                .map(|(column, value)| column.column_type.decode(value.as_deref()))
+                .collect::<io::Result<_>>()
+                .ok()?;
+            row_set.rows.push(values);
+        }
+        Some(row_set)
+    }
+
+    /// Returns the state to request the next page of a result, `None` if it has no more.
+    pub fn paging_state(&self) -> Option<Vec<u8>> {
+        match self {
+            QueryResult::Rows(metadata, _) => metadata.paging_state().map(<[u8]>::to_vec),
+            _ => None,
+        }
     }
 
     #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         match self {
             QueryResult::Void => String::new(),
-            QueryResult::Rows(_metadata, rows) => {
-                let row = &rows[0];
-                let json = String::from_utf8_lossy(&row.values[0]);
-                json.to_string()
-            }
+            // Las filas se muestran como JSON, con sus valores como texto
+            QueryResult::Rows(..) => self
+                .row_set()
+                .and_then(|row_set| serde_json::to_string(&row_set.text_rows()).ok())
+                .unwrap_or_default(),
             QueryResult::SetKeyspace(name) => name.to_owned(),
             QueryResult::Prepared { .. } => String::new(),
             QueryResult::SchemaChange { change_type, .. } => change_type.to_owned(),
@@ -148,6 +207,9 @@ impl QueryResult {
 mod tests {
     use super::*;
     use crate::frame::messages::notation::write_bytes;
+    use crate::frame::messages::prepared::Value;
+    use row_set::{Column, ColumnType};
+    use std::collections::HashMap;
 
     fn row(values: &[&str]) -> io::Result<Row> {
         let mut vec: Vec<u8> = Vec::new();
@@ -275,4 +337,70 @@ mod tests {
             panic!("Expected QueryResult::Prepared");
         }
     }
+
+    fn flights() -> RowSet {
+        let mut flights = RowSet::new(
+            "airports",
+            "flights",
+            vec![
+                Column::new("id", ColumnType::Int),
+                Column::new("status", ColumnType::Text),
+            ],
+        );
+        flights.extend_from_text(&[HashMap::from([("id".to_string(), "1".to_string())])]);
+        flights
+    }
+
+    #[test]
+    fn test_queryresult_page_keeps_the_paging_state() {
+        let page = QueryResult::from_row_set(&flights(), Some(vec![0, 2]));
+
+        let deserialized = QueryResult::deserialize(&page.serialize()).unwrap();
+        assert_eq!(deserialized.paging_state(), Some(vec![0, 2]));
+        assert_eq!(deserialized.to_string(), r#"[{"id":"1"}]"#);
+
+        let last = QueryResult::from_row_set(&RowSet::default(), None);
+        let deserialized = QueryResult::deserialize(&last.serialize()).unwrap();
+        assert_eq!(deserialized.paging_state(), None);
+        assert_eq!(deserialized.to_string(), "[]");
+    }
+
+    #[test]
+    fn test_queryresult_rows_keep_their_types() {
+        let mut flights = flights();
+        flights.extend_from_text(&[HashMap::from([
+            ("id".to_string(), "2".to_string()),
+            ("status".to_string(), "on time".to_string()),
+        ])]);
+
+        let result = QueryResult::from_row_set(&flights, None);
+        let QueryResult::Rows(_, rows) = &result else {
+            panic!("Expected QueryResult::Rows");
+        };
+        assert_eq!(rows[1].values[0], Some(2i32.to_be_bytes().to_vec()));
+        assert_eq!(rows[0].values[1], None);
+
+        let deserialized = QueryResult::deserialize(&result.serialize()).unwrap();
+        assert_eq!(deserialized.row_set(), Some(flights));
+
+        // Un int de 3 bytes no es valido
+        let mut bytes = result.serialize();
+        let len = bytes.len();
+        bytes.splice(len - 15..len - 11, 3i32.to_be_bytes());
+        bytes.remove(len - 11);
+        assert!(QueryResult::deserialize(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_queryresult_sends_unparseable_values_as_null() {
+        let mut flights = flights();
+        flights.extend_from_text(&[HashMap::from([("id".to_string(), "unknown".to_string())])]);
+
+        let result = QueryResult::from_row_set(&flights, None);
+        let deserialized = QueryResult::deserialize(&result.serialize()).unwrap();
+        let row_set = deserialized.row_set().unwrap();
+        assert_eq!(row_set.columns[0].column_type, ColumnType::Int);
+        assert_eq!(row_set.rows[0][0], Value::Int(1));
+        assert_eq!(row_set.rows[1][0], Value::Null);
+    }
 }
@@ -1,17 +1,18 @@
-use crate::frame::messages::notation::{read_bytes, write_bytes};
+use crate::frame::messages::notation::{check_count, read_nullable_bytes, write_nullable_bytes};
 use std::io;
 use std::io::Cursor;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Row {
-    pub values: Vec<Vec<u8>>, // vector de bytes (Vec<u8>)
+    pub values: Vec<Option<Vec<u8>>>, // vector de bytes (Vec<u8>), None si el valor es null
 }
 
 impl Row {
     pub fn deserialize(columns_count: usize, cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        let columns_count = check_count(cursor, columns_count, 4)?;
         let mut values = Vec::with_capacity(columns_count);
         for _ in 0..columns_count {
-            let value = read_bytes(cursor)?;
+            let value = read_nullable_bytes(cursor)?;
             values.push(value);
         }
         //println!("values: {:?}", values);
@@ -20,7 +21,7 @@ impl Row {
 
     pub fn serialize(&self, buffer: &mut Vec<u8>) {
         for value in &self.values {
-            write_bytes(buffer, value);
+            write_nullable_bytes(buffer, value.as_deref());
         }
     }
 }
@@ -30,6 +31,7 @@ mod tests {
     //use chrono::naive::serde::ts_microseconds::deserialize;
 
     use super::*;
+    use crate::frame::messages::notation::write_bytes;
     /*
     fn dummy(values: &[&str]) -> Vec<u8> {
         let mut vec: Vec<u8> = Vec::new();
@@ -47,14 +49,14 @@ mod tests {
         let row = Row::deserialize(2, &mut Cursor::new(&data)).unwrap();
 
         assert_eq!(row.values.len(), 2);
-        assert_eq!(row.values[0], b"val1".to_vec());
-        assert_eq!(row.values[1], b"val2".to_vec());
+        assert_eq!(row.values[0], Some(b"val1".to_vec()));
+        assert_eq!(row.values[1], Some(b"val2".to_vec()));
     }
 
     #[test]
     fn test_serialize() {
         let row = Row {
-            values: vec![b"val1".to_vec(), b"val2".to_vec()],
+            values: vec![Some(b"val1".to_vec()), Some(b"val2".to_vec())],
         };
 
         let mut buffer = Vec::new();
@@ -70,6 +72,18 @@ mod tests {
         assert_eq!(buffer, expected);
     }
 
+    #[test]
+    fn test_null_values() {
+        let row = Row {
+            values: vec![None, Some(vec![])],
+        };
+
+        let mut buffer = Vec::new();
+        row.serialize(&mut buffer);
+        assert_eq!(buffer, [0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+        assert_eq!(Row::deserialize(2, &mut Cursor::new(&buffer)).unwrap(), row);
+    }
+
     #[test]
     fn test_empty_row() {
         let row = Row { values: vec![] };
@@ -0,0 +1,303 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use uuid::Uuid;
+
+use crate::frame::messages::prepared::Value;
+
+/// The type a column was declared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColumnType {
+    Text,
+    Int,
+    Bigint,
+    Float,
+    Boolean,
+    Uuid,
+    Timeuuid,
+    Timestamp,
+    Counter,
+}
+
+impl ColumnType {
+    /// Returns the type of a column declared as `name`, ignoring case. Other types, like the
+    /// one of the internal `_timestamp` column, are texts.
+    pub fn parse(name: &str) -> Self {
+        match name.to_uppercase().as_str() {
+            "INT" => ColumnType::Int,
+            "BIGINT" => ColumnType::Bigint,
+            "FLOAT" | "DOUBLE" => ColumnType::Float,
+            "BOOLEAN" => ColumnType::Boolean,
+            "UUID" => ColumnType::Uuid,
+            "TIMEUUID" => ColumnType::Timeuuid,
+            "TIMESTAMP" => ColumnType::Timestamp,
+            "COUNTER" => ColumnType::Counter,
+            _ => ColumnType::Text,
+        }
+    }
+
+    /// Parses a value as the nodes store it. An empty text is `Null` in a column that isn't a
+    /// text, and a text that isn't of the type of the column is kept as a text. Timestamps
+    /// may have fractions of a second, as the `_timestamp` of the mutations does.
+    pub fn value_of(&self, text: &str) -> Value {
+        if text.is_empty() && *self != ColumnType::Text {
+            return Value::Null;
+        }
+        let value = match self {
+            ColumnType::Text => None,
+            ColumnType::Int => text.parse().ok().map(Value::Int),
+            ColumnType::Bigint | ColumnType::Counter => text.parse().ok().map(Value::Bigint),
+            ColumnType::Float => text.parse().ok().map(Value::Float),
+            ColumnType::Boolean => text.parse().ok().map(Value::Bool),
+            ColumnType::Uuid | ColumnType::Timeuuid => Uuid::parse_str(text).ok().map(Value::Uuid),
+            ColumnType::Timestamp => NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+                .map(|timestamp| timestamp.and_utc())
+                .or_else(|_| DateTime::parse_from_rfc3339(text).map(|date| date.to_utc()))
+                .ok()
+                .map(Value::Timestamp),
+        };
+        value.unwrap_or_else(|| Value::Text(text.to_string()))
+    }
+
+    /// Serializes a value of this type as a value of a row of a result, `None` for `Null`.
+    /// A text column holds the text of any value, and a value that isn't of any other type,
+    /// like a text that couldn't be parsed with it, is sent as `Null`.
+    pub(crate) fn encode(&self, value: &Value) -> Option<Vec<u8>> {
+        let bytes = match (self, value) {
+            (_, Value::Null) => return None,
+            (ColumnType::Text, value) => value.to_text().unwrap_or_default().into_bytes(),
+            (ColumnType::Int, Value::Int(value)) => value.to_be_bytes().to_vec(),
+            (ColumnType::Bigint | ColumnType::Counter, Value::Bigint(value)) => {
+                value.to_be_bytes().to_vec()
+            }
+            (ColumnType::Float, Value::Float(value)) => value.to_be_bytes().to_vec(),
+            (ColumnType::Boolean, Value::Bool(value)) => vec![u8::from(*value)],
+            (ColumnType::Uuid | ColumnType::Timeuuid, Value::Uuid(value)) => {
+                value.as_bytes().to_vec()
+            }
+            (ColumnType::Timestamp, Value::Timestamp(value)) => {
+                value.timestamp_millis().to_be_bytes().to_vec()
+            }
+            // El cliente recibe siempre el tipo declarado de la columna
+            _ => return None,
+        };
+        Some(bytes)
+    }
+
+    /// Deserializes a value of a row of a result written by `encode`.
+    pub(crate) fn decode(&self, bytes: Option<&[u8]>) -> io::Result<Value> {
+        let Some(bytes) = bytes else {
+            return Ok(Value::Null);
+        };
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid {:?} value of {} bytes", self, bytes.len()),
+            )
+        };
+        let value = match self {
+            ColumnType::Text => {
+                Value::Text(String::from_utf8(bytes.to_vec()).map_err(|_| invalid())?)
+            }
+            ColumnType::Int => {
+                Value::Int(i32::from_be_bytes(bytes.try_into().map_err(|_| invalid())?))
+            }
+            ColumnType::Bigint | ColumnType::Counter => {
+                Value::Bigint(i64::from_be_bytes(bytes.try_into().map_err(|_| invalid())?))
+            }
+            ColumnType::Float => match bytes.len() {
+                4 => {
+                    Value::Float(f32::from_be_bytes(bytes.try_into().map_err(|_| invalid())?) as f64)
+                }
+                _ => Value::Float(f64::from_be_bytes(bytes.try_into().map_err(|_| invalid())?)),
+            },
+            ColumnType::Boolean => match bytes {
+                [byte] => Value::Bool(*byte != 0),
+                _ => return Err(invalid()),
+            },
+            ColumnType::Uuid | ColumnType::Timeuuid => {
+                Value::Uuid(Uuid::from_slice(bytes).map_err(|_| invalid())?)
+            }
+            ColumnType::Timestamp => {
+                let millis = i64::from_be_bytes(bytes.try_into().map_err(|_| invalid())?);
+                Value::Timestamp(
+                    DateTime::<Utc>::from_timestamp_millis(millis).ok_or_else(invalid)?,
+                )
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// A column of a `RowSet`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Column {
+    pub name: String,
+    pub column_type: ColumnType,
+}
+
+impl Column {
+    pub fn new(name: &str, column_type: ColumnType) -> Self {
+        Column {
+            name: name.to_string(),
+            column_type,
+        }
+    }
+}
+
+/// The rows of a SELECT with their types: each row has a value per column, in the order of
+/// the columns. The replicas answer them to the coordinator, which merges them and sends them
+/// to the client as a `QueryResult::Rows`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct RowSet {
+    pub keyspace: String,
+    pub table: String,
+    pub columns: Vec<Column>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl RowSet {
+    /// Creates a set without rows.
+    ///
+    /// # Parameters
+    /// - `keyspace`: The keyspace of the table of the rows.
+    /// - `table`: The table of the rows.
+    /// - `columns`: The columns of the rows.
+    pub fn new(keyspace: &str, table: &str, columns: Vec<Column>) -> Self {
+        RowSet {
+            keyspace: keyspace.to_string(),
+            table: table.to_string(),
+            columns,
+            rows: vec![],
+        }
+    }
+
+    /// Adds the rows as the nodes store them, parsing each value with the type of its column.
+    /// A column the row doesn't have is `Null`, and a column of the row that isn't in the set
+    /// is left out.
+    pub fn extend_from_text(&mut self, rows: &[HashMap<String, String>]) {
+        for row in rows {
+            let values = self
+                .columns
+                .iter()
+                .map(|column| match row.get(&column.name) {
+                    Some(text) => column.column_type.value_of(text),
+                    None => Value::Null,
+                })
+                .collect();
+            self.rows.push(values);
+        }
+    }
+
+    /// Returns the position of a column, `None` if the set doesn't have it.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    /// Returns the value of a column of a row of the set, `Null` if the set doesn't have the
+    /// column.
+    pub fn value<'a>(&self, row: &'a [Value], column: &str) -> &'a Value {
+        self.column_index(column)
+            .and_then(|index| row.get(index))
+            .unwrap_or(&Value::Null)
+    }
+
+    /// Returns the rows as the nodes store them, without the `Null` values.
+    pub fn text_rows(&self) -> Vec<HashMap<String, String>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .zip(row)
+                    .filter_map(|(column, value)| Some((column.name.clone(), value.to_text()?)))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_rows_are_parsed_with_the_types_of_their_columns() {
+        let mut rows = RowSet::new(
+            "airports",
+            "flights",
+            vec![
+                Column::new("id", ColumnType::parse("int")),
+                Column::new("departure", ColumnType::parse("TIMESTAMP")),
+                Column::new("delay", ColumnType::parse("float")),
+                Column::new("status", ColumnType::parse("text")),
+            ],
+        );
+        rows.extend_from_text(&[
+            HashMap::from([
+                ("id".to_string(), "20".to_string()),
+                ("departure".to_string(), "2024-05-01 10:30:00".to_string()),
+                ("delay".to_string(), "1.5".to_string()),
+                ("gate".to_string(), "A1".to_string()),
+            ]),
+            HashMap::from([
+                ("id".to_string(), "unknown".to_string()),
+                ("status".to_string(), "".to_string()),
+            ]),
+        ]);
+
+        assert_eq!(rows.value(&rows.rows[0], "id"), &Value::Int(20));
+        assert_eq!(rows.value(&rows.rows[0], "delay"), &Value::Float(1.5));
+        assert_eq!(rows.value(&rows.rows[0], "status"), &Value::Null);
+        assert_eq!(rows.value(&rows.rows[0], "gate"), &Value::Null);
+        assert_eq!(rows.value(&rows.rows[1], "departure"), &Value::Null);
+        assert_eq!(
+            rows.value(&rows.rows[1], "id"),
+            &Value::Text("unknown".to_string())
+        );
+        assert_eq!(
+            rows.value(&rows.rows[1], "status"),
+            &Value::Text(String::new())
+        );
+
+        // Un valor que no es del tipo de su columna se manda como null
+        assert_eq!(ColumnType::Int.encode(&rows.rows[1][0]), None);
+        assert_eq!(
+            ColumnType::Int.encode(&rows.rows[0][0]),
+            Some(vec![0, 0, 0, 20])
+        );
+
+        let text = rows.text_rows();
+        assert_eq!(text[0]["departure"], "2024-05-01 10:30:00");
+        assert!(!text[0].contains_key("status"));
+        assert_eq!(text[1]["id"], "unknown");
+    }
+
+    #[test]
+    fn values_are_decoded_as_they_are_encoded() {
+        let values = [
+            (ColumnType::Int, Value::Int(-7)),
+            (ColumnType::Counter, Value::Bigint(1 << 40)),
+            (ColumnType::Float, Value::Float(-34.8)),
+            (ColumnType::Boolean, Value::Bool(true)),
+            (ColumnType::Uuid, Value::Uuid(Uuid::from_u128(42))),
+            (
+                ColumnType::Timestamp,
+                ColumnType::Timestamp.value_of("2024-05-01 10:30:00"),
+            ),
+            (
+                ColumnType::Timestamp,
+                ColumnType::Timestamp.value_of("2024-05-01 10:30:00.250"),
+            ),
+            (ColumnType::Text, Value::Text("Ezeiza".to_string())),
+            (ColumnType::Text, Value::Null),
+        ];
+        for (column_type, value) in values {
+            let bytes = column_type.encode(&value);
+            assert_eq!(column_type.decode(bytes.as_deref()).unwrap(), value);
+        }
+        assert!(ColumnType::Int.decode(Some(&[0, 1, 2])).is_err());
+        assert!(ColumnType::Text.decode(Some(&[0xFF])).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::frame::compression::Compression;
 use crate::frame::messages::notation::{
     read_string_map, read_string_multimap, write_string_map, write_string_multimap,
 };
@@ -6,7 +7,6 @@ use std::io::Cursor;
 const CQL_VERSION_KEY: &str = "CQL_VERSION";
 const CQL_VERSION_VALUE: &str = "3.0.0";
 const COMPRESSION_KEY: &str = "COMPRESSION";
-const COMPRESSION_VALUE: &str = "";
 
 pub fn deserialize_startup(body: &[u8]) -> std::io::Result<Vec<(String, String)>> {
     read_string_map(&mut Cursor::new(body))
@@ -40,6 +40,33 @@ pub fn default_startup() -> Vec<(String, String)> {
     vec![(CQL_VERSION_KEY.to_string(), CQL_VERSION_VALUE.to_string())]
 }
 
+/// The options of a STARTUP that asks for the frames to be compressed with `compression`,
+/// or for no compression if it is `None`.
+pub fn startup_with_compression(compression: Option<Compression>) -> Vec<(String, String)> {
+    let mut options = default_startup();
+    if let Some(compression) = compression {
+        options.push((COMPRESSION_KEY.to_string(), compression.name().to_string()));
+    }
+    options
+}
+
+/// Returns the compression a STARTUP asks for, `None` if it asks for none.
+pub fn startup_compression(options: &[(String, String)]) -> Option<Compression> {
+    options
+        .iter()
+        .find(|(key, _)| key == COMPRESSION_KEY)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// Returns the compressions offered in a SUPPORTED.
+pub fn supported_compressions(options: &[(String, Vec<String>)]) -> Vec<Compression> {
+    options
+        .iter()
+        .filter(|(key, _)| key == COMPRESSION_KEY)
+        .flat_map(|(_, values)| values.iter().filter_map(|value| value.parse().ok()))
+        .collect()
+}
+
 pub fn default_supported() -> Vec<(String, Vec<String>)> {
     vec![
         (
@@ -48,7 +75,10 @@ pub fn default_supported() -> Vec<(String, Vec<String>)> {
         ),
         (
             COMPRESSION_KEY.to_string(),
-            vec![COMPRESSION_VALUE.to_string()],
+            Compression::ALL
+                .iter()
+                .map(|compression| compression.name().to_string())
+                .collect(),
         ),
     ]
 }
@@ -64,7 +94,7 @@ pub fn validate_options(options: &Vec<(String, String)>) -> bool {
                 explicit_version = true;
             }
             COMPRESSION_KEY => {
-                if value.is_empty() {
+                if value.parse::<Compression>().is_err() {
                     return false;
                 }
             }
@@ -78,6 +108,8 @@ pub fn validate_options(options: &Vec<(String, String)>) -> bool {
 mod tests {
     use super::*;
 
+    const COMPRESSION_VALUE: &str = "lz4";
+
     #[test]
     fn test_default_startup() {
         let startup_options = default_startup();
@@ -93,7 +125,25 @@ mod tests {
         assert_eq!(supported_options[0].0, CQL_VERSION_KEY);
         assert_eq!(supported_options[0].1, vec![CQL_VERSION_VALUE]);
         assert_eq!(supported_options[1].0, COMPRESSION_KEY);
-        assert_eq!(supported_options[1].1, vec![COMPRESSION_VALUE]);
+        assert_eq!(supported_options[1].1, vec!["lz4", "snappy"]);
+    }
+
+    #[test]
+    fn test_compression_is_negotiated() {
+        let startup = startup_with_compression(Some(Compression::Snappy));
+        assert!(validate_options(&startup));
+        assert_eq!(startup_compression(&startup), Some(Compression::Snappy));
+        assert_eq!(startup_compression(&default_startup()), None);
+        assert_eq!(
+            supported_compressions(&default_supported()),
+            Compression::ALL
+        );
+
+        let unknown = vec![
+            (CQL_VERSION_KEY.to_string(), CQL_VERSION_VALUE.to_string()),
+            (COMPRESSION_KEY.to_string(), "gzip".to_string()),
+        ];
+        assert!(!validate_options(&unknown));
     }
 
     #[test]
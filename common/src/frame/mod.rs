@@ -1,3 +1,4 @@
+use crate::frame::compression::{Compression, MIN_COMPRESSED_SIZE};
 use crate::frame::messages::error::ErrorCode;
 use crate::frame::messages::Message;
 use crate::frame::version::Version;
@@ -7,10 +8,11 @@ use std::net::TcpStream;
 
 mod version;
 mod client_handle;
+pub mod compression;
 pub mod server_handle;
 pub mod messages;
 
-const HEADER_SIZE: usize = 9; // 9 BYTES
+pub(crate) const HEADER_SIZE: usize = 9; // 9 BYTES
 const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024; // 256 MB
 
 #[derive(Debug, Clone)]
@@ -43,32 +45,100 @@ impl Frame {
     pub fn deserialize_from_stream(
         stream: &mut TcpStream,
         decryptor: &dyn Fn(&[u8]) -> Vec<u8>,
+        compression: Option<Compression>,
     ) -> io::Result<Self> {
         let mut encrypted_header = [0u8; HEADER_SIZE];
         stream.read_exact(&mut encrypted_header)?;
         let header = decryptor(&encrypted_header);
 
-        let stream_id = i16::from_be_bytes([header[2], header[3]]);
+        let length = match Self::body_length(&header) {
+            Ok(length) => length,
+            Err(stream_id) => return Ok(Self::new_protocol_error(stream_id)),
+        };
 
-        let Ok(version) = Version::try_from(header[0]) else {
-            return Ok(Frame::new_protocol_error(stream_id));
+        // Se lee de a partes para no reservar el largo que dice un header malformado
+        let mut encrypted_body = Vec::new();
+        stream.take(length as u64).read_to_end(&mut encrypted_body)?;
+        if encrypted_body.len() < length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let body = decryptor(&encrypted_body);
+
+        Ok(Self::from_parts(&header, body, compression))
+    }
+
+    /// Deserializes a frame from its bytes, already decrypted. Malformed frames never panic:
+    /// an invalid header, body or compression is answered with a protocol error frame, as
+    /// from a stream, and bytes that are not a single whole frame are an error.
+    ///
+    /// # Parameters
+    /// - `bytes`: The header and body of the frame.
+    /// - `compression`: The compression agreed in the STARTUP, if any.
+    pub fn try_deserialize(bytes: &[u8], compression: Option<Compression>) -> io::Result<Self> {
+        let Some((header, body)) = bytes.split_at_checked(HEADER_SIZE) else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        let length = match Self::body_length(header) {
+            Ok(length) => length,
+            Err(stream_id) => return Ok(Self::new_protocol_error(stream_id)),
         };
+        if body.len() != length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Expected a body of {} bytes, found {}", length, body.len()),
+            ));
+        }
+        Ok(Self::from_parts(header, body.to_vec(), compression))
+    }
+
+    /// Reads the length of the body from a decrypted header.
+    ///
+    /// # Returns
+    /// The length, or the stream id of the protocol error to answer with if the header is
+    /// invalid.
+    pub(crate) fn body_length(header: &[u8]) -> Result<usize, i16> {
+        let stream_id = i16::from_be_bytes([header[2], header[3]]);
+
+        if Version::try_from(header[0]).is_err() {
+            return Err(stream_id);
+        }
 
         let length = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
         if length > (MAX_FRAME_SIZE - HEADER_SIZE) as u32 {
-            return Ok(Self::new_protocol_error(stream_id));
+            return Err(stream_id);
         }
+        Ok(length as usize)
+    }
 
-        let mut encrypted_body = vec![0u8; length as usize];
-        stream.read_exact(&mut encrypted_body)?;
-        let body = decryptor(&encrypted_body);
+    /// Builds a frame from its decrypted header and body, decompressing the body with
+    /// `compression` if the header says it is compressed.
+    pub(crate) fn from_parts(
+        header: &[u8],
+        body: Vec<u8>,
+        compression: Option<Compression>,
+    ) -> Self {
+        let stream_id = i16::from_be_bytes([header[2], header[3]]);
+
+        let Ok(version) = Version::try_from(header[0]) else {
+            return Frame::new_protocol_error(stream_id);
+        };
+
+        let flags = header[1];
+        let body = match (flags & 0x01 != 0, compression) {
+            (false, _) => body,
+            (true, Some(compression)) => match compression.decompress(&body) {
+                Ok(body) => body,
+                Err(_) => return Self::new_protocol_error(stream_id),
+            },
+            // No se acordo ninguna compresion en el STARTUP
+            (true, None) => return Self::new_protocol_error(stream_id),
+        };
 
         let op_code = header[4];
         let Ok(body) = Message::deserialize(op_code, body) else {
-            return Ok(Self::new_protocol_error(stream_id));
+            return Self::new_protocol_error(stream_id);
         };
 
-        let flags = header[1];
         let (compression, tracing) = match flags {
             0x01 => (true, false),
             0x02 => (false, true),
@@ -76,21 +146,36 @@ impl Frame {
             _ => (false, false),
         };
 
-        Ok(Frame {
+        Frame {
             version,
             compression,
             tracing,
             stream: stream_id,
             body,
-        })
+        }
     }
 
     pub fn serialize(&self) -> Vec<u8> {
+        self.serialize_compressed(None)
+    }
+
+    /// Serializes the frame, compressing its body with `compression` if it is large enough
+    /// to be worth it.
+    pub fn serialize_compressed(&self, compression: Option<Compression>) -> Vec<u8> {
         let mut bytes: Vec<u8> = Vec::new();
 
         bytes.push(u8::from(self.version));
 
-        let flags: u8 = match (self.compression, self.tracing) {
+        let mut body_bytes = self.body.serialize();
+        let compressed = match compression {
+            Some(compression) if body_bytes.len() >= MIN_COMPRESSED_SIZE => {
+                body_bytes = compression.compress(&body_bytes);
+                true
+            }
+            _ => false,
+        };
+
+        let flags: u8 = match (compressed, self.tracing) {
             (true, false) => 0x01,
             (false, true) => 0x02,
             (true, true) => 0x03,
@@ -101,7 +186,6 @@ impl Frame {
         bytes.extend_from_slice(&self.stream.to_be_bytes());
         bytes.push(self.body.to_op_code());
 
-        let body_bytes = self.body.serialize();
         let length = body_bytes.len() as i32;
         bytes.extend_from_slice(&length.to_be_bytes());
         bytes.extend_from_slice(&body_bytes);
@@ -123,3 +207,54 @@ impl Frame {
         Frame::new_error(ErrorCode::ProtocolError, stream)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::messages::consistency_level::ConsistencyLevel;
+
+    fn frame(version: u8, op_code: u8, body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![version, 0, 0, 7, op_code];
+        bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn is_protocol_error(frame: &Frame) -> bool {
+        matches!(frame.body, Message::Error(ErrorCode::ProtocolError)) && frame.stream == 7
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected_without_panicking() {
+        let query = Frame::new_query("USE flights;".to_string(), ConsistencyLevel::One, 7);
+        let bytes = query.serialize();
+        let decoded = Frame::try_deserialize(&bytes, None).unwrap();
+        assert_eq!(decoded.query_string(), Some("USE flights;"));
+
+        assert!(Frame::try_deserialize(&bytes[..HEADER_SIZE - 1], None).is_err());
+        assert!(Frame::try_deserialize(&bytes[..bytes.len() - 1], None).is_err());
+        assert!(Frame::try_deserialize(&[bytes.clone(), vec![0]].concat(), None).is_err());
+
+        for malformed in [
+            // Una version desconocida
+            frame(0x7F, 0x07, &[]),
+            // Un REGISTER con un [string] que no es UTF-8
+            frame(0x03, 0x0B, &[0, 1, 0, 2, 0xC3, 0x28]),
+            // Un PREPARE con un [long string] de largo negativo
+            frame(0x03, 0x09, &[0xFF, 0xFF, 0xFF, 0xFF]),
+            // Un RESULT de filas que dice tener mas filas de las que trae
+            frame(0x83, 0x08, &[0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 1, 0x7F, 0xFF, 0xFF, 0xFF]),
+        ] {
+            let decoded = Frame::try_deserialize(&malformed, None).unwrap();
+            assert!(is_protocol_error(&decoded), "{:?}", decoded);
+        }
+
+        // Un cuerpo LZ4 que dice descomprimirse en 2 GB
+        let mut compressed = frame(0x03, 0x07, &[0x7F, 0xFF, 0xFF, 0xFF, 0]);
+        compressed[1] = 0x01;
+        for compression in [None, Some(Compression::Lz4)] {
+            let decoded = Frame::try_deserialize(&compressed, compression).unwrap();
+            assert!(is_protocol_error(&decoded));
+        }
+    }
+}
@@ -1,13 +1,16 @@
+use crate::config::AuthConfig;
 use crate::frame::messages::error::ErrorCode;
+use crate::frame::messages::event::{ClusterEvent, STATUS_CHANGE, TOPOLOGY_CHANGE};
 use crate::frame::messages::startup_options::{
-    default_supported, validate_options,
+    default_supported, startup_compression, validate_options,
 };
 use crate::frame::messages::Message;
 use crate::frame::version::Version;
 use crate::security::EncryptionHandler;
 use std::sync::Arc;
 
-use super::messages::authentication::{AuthChallenge, AuthResponse};
+use super::messages::authentication::{plain_password, plain_user, AuthChallenge, AuthResponse};
+use super::messages::prepared::bind;
 use super::messages::query::Query;
 use super::messages::query_result::QueryResult;
 use super::Frame;
@@ -17,6 +20,8 @@ pub enum ConnectionState {
     Uninitialized,
     UnAuthenticated,
     Authenticating,
+    /// The connection is encrypted and the node waits for the password of the client.
+    VerifyingCredentials,
     Ready,
 }
 
@@ -26,6 +31,12 @@ pub trait Node {
         query: Query,
         keyspace: Option<String>,
     ) -> Result<QueryResult, ErrorCode>;
+
+    /// Stores a prepared statement and returns its id.
+    fn prepare(&self, query_string: &str) -> Vec<u8>;
+
+    /// Returns the statement prepared with the given id, `None` if the node doesn't know it.
+    fn prepared_statement(&self, id: &[u8]) -> Option<String>;
 }
 
 impl Frame {
@@ -33,21 +44,92 @@ impl Frame {
         Frame::new_error(ErrorCode::ServerError, 0)
     }
 
+    /// Returns the user a client identified itself as in an AUTH_RESPONSE, if any.
+    pub fn auth_user(&self) -> Option<String> {
+        match &self.body {
+            Message::AuthResponse(token) => plain_user(token),
+            _ => None,
+        }
+    }
+
+    /// Returns the statement of a QUERY.
+    pub fn query_string(&self) -> Option<&str> {
+        match &self.body {
+            Message::Query(query) => Some(&query.query_string),
+            _ => None,
+        }
+    }
+
+    /// Returns the id of the statement an EXECUTE runs.
+    pub fn prepared_statement_id(&self) -> Option<&[u8]> {
+        match &self.body {
+            Message::Execute(id, _) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Creates an EVENT pushed to a client that registered for its type. Events don't answer
+    /// a request, so they are sent on stream -1.
+    pub fn new_event(event: ClusterEvent) -> Self {
+        Self {
+            version: Version::ResponseV3,
+            compression: false,
+            tracing: false,
+            stream: -1,
+            body: Message::Event(event),
+        }
+    }
+
+    /// Returns the types of events a client asks for in a REGISTER.
+    pub fn registered_events(&self) -> Option<&[String]> {
+        match &self.body {
+            Message::Register(event_types) => Some(event_types),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the frame is an ERROR.
+    pub fn is_error(&self) -> bool {
+        matches!(&self.body, Message::Error(_))
+    }
+
     pub fn generate_response(&self, node: Arc<dyn Node>, keyspace: &mut Option<String>) -> Self {
         let body = match &self.body {
-            Message::Query(query) => {
-                let response =
-                    node.resend_query_as_internal_message(query.clone(), keyspace.clone());
-                match response {
-                    Ok(query_result) => {
-                        if let QueryResult::SetKeyspace(keyspace_name) = &query_result {
-                            *keyspace = Some(keyspace_name.clone());
-
-                            println!("Keyspace set to: {}", keyspace_name);
+            Message::Query(query) => run_query(&node, query.clone(), keyspace),
+            Message::Prepare(query_string) => Message::Result(QueryResult::Prepared {
+                id: node.prepare(query_string),
+                metadata: Default::default(),
+                result_metadata: Default::default(),
+            }),
+            Message::Execute(id, parameters) => match node.prepared_statement(id) {
+                Some(statement) => {
+                    let values: Vec<Vec<u8>> = parameters
+                        .values
+                        .iter()
+                        .flatten()
+                        .map(|(_, value)| value.clone())
+                        .collect();
+                    match bind(&statement, &values) {
+                        Ok(query_string) => {
+                            let mut query = parameters.clone();
+                            query.query_string = query_string;
+                            query.values = None;
+                            run_query(&node, query, keyspace)
                         }
-                        Message::Result(query_result)
+                        Err(error_code) => Message::Error(error_code),
                     }
-                    Err(error_code) => Message::Error(error_code),
+                }
+                None => Message::Error(ErrorCode::Unprepared),
+            },
+            Message::Register(event_types) => {
+                let known = [TOPOLOGY_CHANGE, STATUS_CHANGE];
+                if event_types
+                    .iter()
+                    .all(|event_type| known.contains(&event_type.as_str()))
+                {
+                    Message::Ready
+                } else {
+                    Message::Error(ErrorCode::ProtocolError)
                 }
             }
             Message::Error(error) => Message::Error(*error),
@@ -63,12 +145,19 @@ impl Frame {
         }
     }
 
-    pub fn handle_uninitialized(&self, conncection_state: &mut ConnectionState) -> Self {
+    /// Answers the OPTIONS and STARTUP of a new connection. The compression the STARTUP asks
+    /// for is used from the moment the connection is encrypted.
+    pub fn handle_uninitialized(
+        &self,
+        conncection_state: &mut ConnectionState,
+        encryption_handler: &mut EncryptionHandler,
+    ) -> Self {
         let body = match &self.body {
             Message::Startup(selected_options) => {
                 if !validate_options(selected_options) {
                     Message::Error(ErrorCode::ProtocolError)
                 } else {
+                    encryption_handler.set_compression(startup_compression(selected_options));
                     *conncection_state = ConnectionState::UnAuthenticated;
                     Message::Authenticate("PLAIN".to_string())
                 }
@@ -86,14 +175,19 @@ impl Frame {
         }
     }
 
+    /// Answers the AUTH_RESPONSEs of a connection: the first one with the challenge to agree
+    /// on the keys, and the one with the keys with AUTH_SUCCESS. If `auth` has users, the
+    /// node then asks for the credentials of the client on the encrypted connection and checks
+    /// them before the AUTH_SUCCESS.
     pub fn handle_authentication(
         &self,
         connection_state: &mut ConnectionState,
         encryption_handler: &mut EncryptionHandler,
+        auth: &AuthConfig,
     ) -> Self {
         let body = match &self.body {
             Message::AuthResponse(response) => {
-                authenticate_client(response, connection_state, encryption_handler)
+                authenticate_client(response, connection_state, encryption_handler, auth)
             }
 
             _ => Message::Error(ErrorCode::ProtocolError),
@@ -109,10 +203,25 @@ impl Frame {
     }
 }
 
+fn run_query(node: &Arc<dyn Node>, query: Query, keyspace: &mut Option<String>) -> Message {
+    match node.resend_query_as_internal_message(query, keyspace.clone()) {
+        Ok(query_result) => {
+            if let QueryResult::SetKeyspace(keyspace_name) = &query_result {
+                *keyspace = Some(keyspace_name.clone());
+
+                println!("Keyspace set to: {}", keyspace_name);
+            }
+            Message::Result(query_result)
+        }
+        Err(error_code) => Message::Error(error_code),
+    }
+}
+
 fn authenticate_client(
     auth_response: &[u8],
     connection_state: &mut ConnectionState,
     encryption_handler: &mut EncryptionHandler,
+    auth: &AuthConfig,
 ) -> Message {
     match *connection_state {
         ConnectionState::UnAuthenticated => {
@@ -129,6 +238,27 @@ fn authenticate_client(
 
             match encryption_handler.attempt_initialize(response.public_key, response.shared_secret)
             {
+                // La contraseña se pide recién con la conexión encriptada
+                true if auth.requires_credentials() => {
+                    *connection_state = ConnectionState::VerifyingCredentials;
+                    Message::AuthChallenge(vec![])
+                }
+                true => {
+                    *connection_state = ConnectionState::Ready;
+                    Message::AuthSuccess
+                }
+                false => {
+                    *connection_state = ConnectionState::Uninitialized;
+                    Message::Error(ErrorCode::BadCredentials)
+                }
+            }
+        }
+        ConnectionState::VerifyingCredentials => {
+            let verified = match (plain_user(auth_response), plain_password(auth_response)) {
+                (Some(user), Some(password)) => auth.verify(&user, &password),
+                _ => false,
+            };
+            match verified {
                 true => {
                     *connection_state = ConnectionState::Ready;
                     Message::AuthSuccess
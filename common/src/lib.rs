@@ -1,3 +1,6 @@
+// El codigo de #[derive(FromRow)] nombra al crate como `common`, tambien desde adentro
+extern crate self as common;
+
 pub mod config;
 pub mod security;
 pub mod models;
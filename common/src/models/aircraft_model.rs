@@ -0,0 +1,91 @@
+use std::ops::Range;
+
+use serde::Deserialize;
+
+use super::tracking_data::{KmH, Liters, Meters};
+
+/// The performance of an aircraft model, set in the `aircraft_models` of the config and
+/// named by the `model` of the airplanes.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AircraftModel {
+    pub name: String,
+    pub cruise_speed: KmH,
+    /// Highest altitude the model flies at.
+    pub ceiling: Meters,
+    pub fuel_capacity: Liters,
+    /// Meters climbed per minute.
+    pub climb_rate: Meters,
+}
+
+impl AircraftModel {
+    /// A generic airliner, for the airplanes whose model has no profile: cruises between 9,000
+    /// and 12,000 m at 600 to 900 km/h.
+    pub fn generic() -> Self {
+        Self {
+            name: "generic".to_string(),
+            cruise_speed: 750,
+            ceiling: 12_000,
+            fuel_capacity: 100_000,
+            climb_rate: 1_500,
+        }
+    }
+
+    /// Finds the profile of a model, the generic one if it has none.
+    pub fn by_name(models: &[AircraftModel], name: &str) -> AircraftModel {
+        models
+            .iter()
+            .find(|model| model.name == name)
+            .cloned()
+            .unwrap_or_else(Self::generic)
+    }
+
+    /// Returns the lowest cruising altitude, three quarters of the ceiling.
+    pub fn cruise_floor(&self) -> Meters {
+        (self.ceiling as u32 * 3 / 4) as Meters
+    }
+
+    /// Returns the speeds the model cruises at, 20% around its cruise speed.
+    pub fn cruise_speeds(&self) -> Range<KmH> {
+        self.cruise_speed - self.cruise_speed / 5..self.cruise_speed + self.cruise_speed / 5
+    }
+
+    /// Returns the problems of the profile, if any.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for (field, value) in [
+            ("cruise_speed", self.cruise_speed as u32),
+            ("ceiling", self.ceiling as u32),
+            ("fuel_capacity", self.fuel_capacity),
+            ("climb_rate", self.climb_rate as u32),
+        ] {
+            if value == 0 {
+                problems.push(format!(
+                    "aircraft model {} must have a {} of at least 1",
+                    self.name, field
+                ));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_generic_profile_keeps_the_previous_ranges() {
+        let generic = AircraftModel::generic();
+        assert_eq!(generic.cruise_floor(), 9_000);
+        assert_eq!(generic.cruise_speeds(), 600..900);
+        assert!(generic.problems().is_empty());
+
+        let models = [AircraftModel {
+            name: "E190".to_string(),
+            cruise_speed: 0,
+            ..AircraftModel::generic()
+        }];
+        assert_eq!(AircraftModel::by_name(&models, "E190").problems().len(), 1);
+        assert_eq!(AircraftModel::by_name(&models, "A330"), generic);
+    }
+}
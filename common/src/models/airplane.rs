@@ -1,8 +1,23 @@
+use super::aircraft_model::AircraftModel;
+use super::tracking_data::Liters;
+
 pub type Id = u16;
 
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct Airplane {
     pub id: Id,
     pub model: String,
-    pub max_fuel: super::tracking_data::Liters,
+    /// Fuel capacity of the airplane, 0 for that of its model.
+    #[serde(default)]
+    pub max_fuel: Liters,
+}
+
+impl Airplane {
+    /// Returns the fuel capacity of the airplane, or that of its model if it has none.
+    pub fn fuel_capacity(&self, model: &AircraftModel) -> Liters {
+        match self.max_fuel {
+            0 => model.fuel_capacity,
+            max_fuel => max_fuel,
+        }
+    }
 }
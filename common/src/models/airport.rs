@@ -1,26 +1,178 @@
+use std::{collections::HashMap, fs::read_to_string, io, path::Path, str::FromStr};
+
 use super::tracking_data::Degrees;
+use crate::frame::messages::prepared::quote;
 
 pub type Id = u16;
 
-#[derive(Debug, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Deserialize, Clone, PartialEq)]
 pub struct Airport {
     pub id: Id,
+    /// Three-letter IATA code, e.g. `EZE`.
+    #[serde(default)]
+    pub iata: String,
     pub name: String,
     pub latitude: Degrees,
     pub longitude: Degrees,
     pub city: String,
     pub country: String,
+    /// Flights the airport can handle at the same time, 0 if unknown.
+    #[serde(default)]
+    pub capacity: u32,
 }
 
 impl Airport {
     pub fn unknown() -> Self {
         Self {
             id: 0,
+            iata: String::new(),
             name: "Unknown Airport".to_string(),
             latitude: 0.0,
             longitude: 0.0,
             city: "Unknown".to_string(),
             country: "Unknown".to_string(),
+            capacity: 0,
+        }
+    }
+
+    pub fn generate_query(&self) -> String {
+        format!(
+            "INSERT INTO airports (id, iata, name, latitude, longitude, city, country, capacity) \
+                VALUES ({}, {}, {}, '{}', '{}', {}, {}, '{}');",
+            self.id,
+            quote(&self.iata),
+            quote(&self.name),
+            self.latitude,
+            self.longitude,
+            quote(&self.city),
+            quote(&self.country),
+            self.capacity
+        )
+    }
+
+    /// Finds an airport of the catalog by its IATA code, ignoring case.
+    pub fn by_iata<'a>(airports: &'a [Airport], iata: &str) -> Option<&'a Airport> {
+        airports
+            .iter()
+            .find(|airport| airport.iata.eq_ignore_ascii_case(iata))
+    }
+
+    /// Loads a catalog of airports, as JSON if the extension of the file is `.json` and as CSV
+    /// otherwise. A CSV file starts with a header naming its columns: `id`, `iata`, `name`,
+    /// `latitude`, `longitude`, `city`, `country` and `capacity`, in any order. Its values
+    /// can't have commas.
+    ///
+    /// # Returns
+    /// The airports, or an error if the file can't be read or a row is invalid.
+    pub fn load_catalog(path: &str) -> io::Result<Vec<Airport>> {
+        let contents = read_to_string(path)?;
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("json") => serde_json::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            _ => parse_csv(&contents),
         }
     }
 }
+
+fn parse_csv(contents: &str) -> io::Result<Vec<Airport>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or_else(|| invalid("the airport catalog is empty".to_string()))?
+        .split(',')
+        .map(str::trim)
+        .collect();
+
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            if values.len() != header.len() {
+                return Err(invalid(format!(
+                    "row {} of the airport catalog has {} values instead of {}",
+                    i + 1,
+                    values.len(),
+                    header.len()
+                )));
+            }
+            let row: HashMap<&str, &str> = header.iter().copied().zip(values).collect();
+            let text = |column: &str| row.get(column).copied().unwrap_or_default();
+            Ok(Airport {
+                id: parse_value(&row, "id", i + 1)?,
+                iata: text("iata").to_string(),
+                name: text("name").to_string(),
+                latitude: parse_value(&row, "latitude", i + 1)?,
+                longitude: parse_value(&row, "longitude", i + 1)?,
+                city: text("city").to_string(),
+                country: text("country").to_string(),
+                // La capacidad es opcional, como en el config
+                capacity: match text("capacity") {
+                    "" => 0,
+                    _ => parse_value(&row, "capacity", i + 1)?,
+                },
+            })
+        })
+        .collect()
+}
+
+fn parse_value<T: FromStr>(row: &HashMap<&str, &str>, column: &str, line: usize) -> io::Result<T> {
+    row.get(column)
+        .copied()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("row {} has an invalid {}", line, column),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_catalog_is_read_from_csv_or_json() {
+        let dir = std::env::temp_dir();
+        let csv = dir.join("aero_airports_test.csv");
+        std::fs::write(
+            &csv,
+            "iata,id,name,latitude,longitude,city,country,capacity\n\
+             EZE,1,Ministro Pistarini,-34.82,-58.54,Buenos Aires,Argentina,40\n\
+             MAD,2,Barajas,40.47,-3.56,Madrid,Spain,\n",
+        )
+        .unwrap();
+        let json = dir.join("aero_airports_test.json");
+        std::fs::write(
+            &json,
+            r#"[{"id":1,"iata":"EZE","name":"Ministro Pistarini","latitude":-34.82,"longitude":-58.54,"city":"Buenos Aires","country":"Argentina","capacity":40},
+                {"id":2,"iata":"MAD","name":"Barajas","latitude":40.47,"longitude":-3.56,"city":"Madrid","country":"Spain"}]"#,
+        )
+        .unwrap();
+
+        let from_csv = Airport::load_catalog(csv.to_str().unwrap()).unwrap();
+        let from_json = Airport::load_catalog(json.to_str().unwrap()).unwrap();
+        assert_eq!(from_csv, from_json);
+        let madrid = Airport::by_iata(&from_csv, "mad").unwrap();
+        assert_eq!((madrid.latitude, madrid.capacity), (40.47, 0));
+        assert!(madrid.generate_query().starts_with("INSERT INTO airports"));
+
+        // Una comilla en el nombre no corta el texto
+        let ohiggins = Airport {
+            name: "Aeródromo O'Higgins".to_string(),
+            ..madrid.clone()
+        };
+        assert!(ohiggins
+            .generate_query()
+            .contains("'MAD', 'Aeródromo O''Higgins', '40.47'"));
+
+        std::fs::write(&csv, "id,iata,latitude\n3,COR,muy al sur\n").unwrap();
+        let error = Airport::load_catalog(csv.to_str().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("invalid latitude"));
+    }
+}
@@ -0,0 +1,242 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::tracking_data::mode::Mode;
+use super::FlightId;
+use crate::client_manager::{ClientManager, FromRow};
+use crate::frame::messages::prepared::quote;
+
+/// The side of the board of an airport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardKind {
+    Departures,
+    Arrivals,
+}
+
+impl BoardKind {
+    /// Returns the table the `BoardJob` keeps for this side of the boards.
+    fn table(&self) -> &'static str {
+        match self {
+            BoardKind::Departures => "departures_by_airport",
+            BoardKind::Arrivals => "arrivals_by_airport",
+        }
+    }
+}
+
+/// How a flight stands out on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Highlight {
+    Normal,
+    Delayed,
+    Sos,
+}
+
+/// A flight on the board of an airport.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoardEntry {
+    pub flight_id: FlightId,
+    /// The destination of a departure or the origin of an arrival.
+    pub airport: String,
+    pub departure: DateTime<Utc>,
+    pub status: String,
+    pub mode: String,
+    /// Only known for the arrivals of flights in the air.
+    pub eta: Option<DateTime<Utc>>,
+    /// The flights don't record a gate yet, so it is empty unless the row has one.
+    pub gate: Option<String>,
+}
+
+impl BoardEntry {
+    /// Returns how the flight stands out: flights in emergency before delayed ones.
+    pub fn highlight(&self) -> Highlight {
+        if self.mode == Mode::Sos.to_string() {
+            Highlight::Sos
+        } else if self.status == "Delayed" {
+            Highlight::Delayed
+        } else {
+            Highlight::Normal
+        }
+    }
+}
+
+/// A row of `departures_by_airport` or `arrivals_by_airport`.
+#[derive(Debug, Clone, FromRow)]
+struct BoardRow {
+    flight_id: FlightId,
+    origin: Option<String>,
+    destination: Option<String>,
+    departure: DateTime<Utc>,
+    status: String,
+    mode: String,
+    eta: Option<DateTime<Utc>>,
+    gate: Option<String>,
+}
+
+/// The departures and arrivals of an airport on a day, for its screen in the interface. The
+/// board reads the partition of the airport that the `BoardJob` keeps, a page at a time, and
+/// reads it again every `every` so the screen stays up to date.
+#[derive(Debug, Clone)]
+pub struct AirportBoard {
+    pub airport: String,
+    every: Duration,
+    page_size: usize,
+    next_refresh: Option<Instant>,
+    departures: Vec<BoardEntry>,
+    arrivals: Vec<BoardEntry>,
+}
+
+impl AirportBoard {
+    /// # Parameters
+    /// - `every`: Time between refreshes, e.g. the `status_update_interval_in_ms` of the
+    ///   interface.
+    /// - `page_size`: Rows fetched on each request to the nodes.
+    pub fn new(airport: &str, every: Duration, page_size: usize) -> Self {
+        Self {
+            airport: airport.to_string(),
+            every,
+            page_size,
+            next_refresh: None,
+            departures: vec![],
+            arrivals: vec![],
+        }
+    }
+
+    /// Builds the query that selects a side of the board of the airport on `date`.
+    pub fn select_query(&self, kind: BoardKind, date: NaiveDate) -> String {
+        format!(
+            "SELECT * FROM {} WHERE airport = {} AND date = '{}';",
+            kind.table(),
+            quote(&self.airport),
+            date.format("%Y-%m-%d")
+        )
+    }
+
+    /// Reads the board again if `every` passed since the last time.
+    ///
+    /// # Returns
+    /// Whether the board was read, or an error if a query fails.
+    pub fn refresh_if_due(
+        &mut self,
+        client: &mut ClientManager,
+        date: NaiveDate,
+        now: Instant,
+    ) -> Result<bool, String> {
+        if self
+            .next_refresh
+            .is_some_and(|next_refresh| now < next_refresh)
+        {
+            return Ok(false);
+        }
+        self.next_refresh = Some(now + self.every);
+        self.refresh(client, date).map(|_| true)
+    }
+
+    /// Reads the departures and arrivals of the airport on `date`. If a query fails the board
+    /// keeps the flights it had.
+    ///
+    /// # Returns
+    /// An error if a query fails or a row is invalid.
+    pub fn refresh(&mut self, client: &mut ClientManager, date: NaiveDate) -> Result<(), String> {
+        let mut read = |kind: BoardKind| -> Result<Vec<BoardEntry>, String> {
+            let rows = client
+                .query_iter(self.select_query(kind, date), None, self.page_size)
+                .map(|row| BoardRow::from_row(&row?))
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(entries(kind, rows))
+        };
+        let departures = read(BoardKind::Departures)?;
+        let arrivals = read(BoardKind::Arrivals)?;
+        (self.departures, self.arrivals) = (departures, arrivals);
+        Ok(())
+    }
+
+    /// Returns the departures, in order of departure.
+    pub fn departures(&self) -> &[BoardEntry] {
+        &self.departures
+    }
+
+    /// Returns the arrivals, in order of arrival; those without an ETA last, by departure.
+    pub fn arrivals(&self) -> &[BoardEntry] {
+        &self.arrivals
+    }
+}
+
+/// Builds the entries of a side of the board from its rows, in the order the board shows them.
+fn entries(kind: BoardKind, rows: Vec<BoardRow>) -> Vec<BoardEntry> {
+    let mut entries: Vec<BoardEntry> = rows
+        .into_iter()
+        .map(|row| BoardEntry {
+            flight_id: row.flight_id,
+            airport: match kind {
+                BoardKind::Departures => row.destination,
+                BoardKind::Arrivals => row.origin,
+            }
+            .unwrap_or_default(),
+            departure: row.departure,
+            status: row.status,
+            mode: row.mode,
+            eta: row.eta,
+            gate: row.gate,
+        })
+        .collect();
+    match kind {
+        BoardKind::Departures => {
+            entries.sort_by_key(|entry| (entry.departure, entry.flight_id));
+        }
+        BoardKind::Arrivals => entries.sort_by_key(|entry| {
+            (
+                entry.eta.is_none(),
+                entry.eta,
+                entry.departure,
+                entry.flight_id,
+            )
+        }),
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(flight_id: FlightId, eta: Option<&str>, status: &str, mode: &str) -> BoardRow {
+        BoardRow {
+            flight_id,
+            origin: Some("EZE".to_string()),
+            destination: None,
+            departure: format!("2024-11-05T0{}:00:00Z", flight_id).parse().unwrap(),
+            status: status.to_string(),
+            mode: mode.to_string(),
+            eta: eta.map(|eta| eta.parse().unwrap()),
+            gate: None,
+        }
+    }
+
+    #[test]
+    fn arrivals_are_ordered_by_eta_and_highlighted() {
+        let board = AirportBoard::new("MAD", Duration::from_secs(5), 50);
+        let date = NaiveDate::from_ymd_opt(2024, 11, 5).unwrap();
+        assert_eq!(
+            board.select_query(BoardKind::Arrivals, date),
+            "SELECT * FROM arrivals_by_airport WHERE airport = 'MAD' AND date = '2024-11-05';"
+        );
+
+        let arrivals = entries(
+            BoardKind::Arrivals,
+            vec![
+                row(1, None, "Delayed", "Unknown"),
+                row(2, Some("2024-11-05T18:00:00Z"), "On Air", "cruising"),
+                row(3, Some("2024-11-05T17:00:00Z"), "On Air", "sos"),
+            ],
+        );
+        let ids: Vec<_> = arrivals.iter().map(|entry| entry.flight_id).collect();
+        assert_eq!(ids, [3, 2, 1]);
+        assert_eq!(arrivals[0].airport, "EZE");
+        let highlights: Vec<_> = arrivals.iter().map(BoardEntry::highlight).collect();
+        assert_eq!(
+            highlights,
+            [Highlight::Sos, Highlight::Normal, Highlight::Delayed]
+        );
+    }
+}
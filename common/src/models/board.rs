@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+
+use super::flight_filter::FiledFlights;
+use super::FlightId;
+use crate::client_manager::{ClientManager, FromRow};
+use crate::frame::messages::prepared::quote;
+
+/// The tables of the boards, partitioned by airport and day of departure and clustered by
+/// departure, which doesn't change while the status of the flight does.
+pub const TABLES: [&str; 2] = [
+    "CREATE TABLE departures_by_airport (airport TEXT, date TEXT, departure TEXT, flight_id INT, \
+        destination TEXT, status TEXT, mode TEXT, PRIMARY KEY ((airport, date), departure, flight_id));",
+    "CREATE TABLE arrivals_by_airport (airport TEXT, date TEXT, departure TEXT, flight_id INT, \
+        origin TEXT, status TEXT, mode TEXT, eta TEXT, PRIMARY KEY ((airport, date), departure, flight_id));",
+];
+
+const DEPARTURES_INSERT: &str = "INSERT INTO departures_by_airport \
+    (airport, date, departure, flight_id, destination, status, mode) VALUES";
+const ARRIVALS_INSERT: &str = "INSERT INTO arrivals_by_airport \
+    (airport, date, departure, flight_id, origin, status, mode, eta) VALUES";
+
+/// A flight of the `flights` table, as the boards read it.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct BoardFlight {
+    pub flight_id: FlightId,
+    pub origin: String,
+    pub destination: String,
+    pub departure: DateTime<Utc>,
+}
+
+/// What the `status` table knows of a flight, written by `Status` and `TrackingData`.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct BoardStatus {
+    pub flight_id: FlightId,
+    pub status: Option<String>,
+    pub mode: Option<String>,
+    pub eta: Option<DateTime<Utc>>,
+}
+
+/// Keeps the `departures_by_airport` and `arrivals_by_airport` tables up to date from the
+/// `flights` and `status` tables, so the board of an airport reads a single partition instead
+/// of every flight. The rows are partitioned by airport and day of departure, and clustered by
+/// departure, which doesn't change while the status of the flight does. It also files each
+/// flight under its status and mode, for the filters of the interface. Only the flights whose
+/// rows changed since the last run are written.
+#[derive(Debug, Clone)]
+pub struct BoardJob {
+    every: Duration,
+    next_run: Option<DateTime<Utc>>,
+    /// The rows of each flight as written on the boards by the last run.
+    written: HashMap<FlightId, BoardRows>,
+    filed: FiledFlights,
+}
+
+impl BoardJob {
+    pub fn new(every: Duration) -> Self {
+        Self {
+            every,
+            next_run: None,
+            written: HashMap::new(),
+            filed: FiledFlights::default(),
+        }
+    }
+
+    /// Refreshes the boards if `every` passed since the last time, counted in the time of
+    /// `now`, usually that of the simulation.
+    ///
+    /// # Returns
+    /// The queries run, `None` if the job wasn't due, or an error if a query fails.
+    pub fn run_if_due(
+        &mut self,
+        client: &mut ClientManager,
+        now: DateTime<Utc>,
+    ) -> Result<Option<usize>, String> {
+        if self.next_run.is_some_and(|next_run| now < next_run) {
+            return Ok(None);
+        }
+        self.next_run = Some(now + self.every);
+        self.refresh(client).map(Some)
+    }
+
+    /// Reads every flight and its status and writes the rows of the flights that changed in the
+    /// board of their origin and in that of their destination, and in the partitions of their
+    /// status and mode. If a query fails, the next run writes those flights again.
+    ///
+    /// # Returns
+    /// The queries run, or an error if a query fails or a row is invalid.
+    pub fn refresh(&mut self, client: &mut ClientManager) -> Result<usize, String> {
+        let flights: Vec<BoardFlight> =
+            client.query_as("SELECT * FROM flights;".to_string(), None)?;
+        let statuses: Vec<BoardStatus> =
+            client.query_as("SELECT * FROM status;".to_string(), None)?;
+        let changed: Vec<(FlightId, BoardRows)> = board_rows(&flights, &statuses)
+            .into_iter()
+            .filter(|(flight_id, rows)| self.written.get(flight_id) != Some(rows))
+            .collect();
+        let mut queries = board_queries(&changed);
+        // Se actualiza una copia, para no dar por escritos los vuelos si falla una query
+        let mut filed = self.filed.clone();
+        queries.extend(filed.queries(&flights, &statuses));
+        for query in &queries {
+            client.query(query.clone(), None)?;
+        }
+        self.written.extend(changed);
+        self.filed = filed;
+        Ok(queries.len())
+    }
+}
+
+/// The values of the rows of a flight in `departures_by_airport` and `arrivals_by_airport`.
+pub type BoardRows = (String, String);
+
+/// Builds the values of the rows of each flight in the departures of its origin and the
+/// arrivals of its destination, with its current status.
+///
+/// # Parameters
+/// - `flights`: The rows of `flights`.
+/// - `statuses`: The rows of `status`; flights without one are `Unknown`.
+pub fn board_rows(flights: &[BoardFlight], statuses: &[BoardStatus]) -> Vec<(FlightId, BoardRows)> {
+    let statuses: HashMap<FlightId, &BoardStatus> = statuses
+        .iter()
+        .map(|status| (status.flight_id, status))
+        .collect();
+    let mut rows = Vec::with_capacity(flights.len());
+    for flight in flights {
+        let status = statuses.get(&flight.flight_id);
+        let text = |value: Option<&Option<String>>| {
+            quote(value.and_then(Option::as_deref).unwrap_or("Unknown"))
+        };
+        let state = text(status.map(|status| &status.status));
+        let mode = text(status.map(|status| &status.mode));
+        let eta = status
+            .and_then(|status| status.eta)
+            .map(|eta| eta.to_rfc3339_opts(SecondsFormat::Millis, true))
+            .unwrap_or_default();
+        // La fecha y la hora de salida como texto de ancho fijo, que se ordena como el tiempo
+        let date = flight.departure.format("%Y-%m-%d");
+        let departure = flight
+            .departure
+            .to_rfc3339_opts(SecondsFormat::Millis, true);
+
+        let departures = format!(
+            "({}, '{}', '{}', {}, {}, {}, {})",
+            quote(&flight.origin),
+            date,
+            departure,
+            flight.flight_id,
+            quote(&flight.destination),
+            state,
+            mode
+        );
+        let arrivals = format!(
+            "({}, '{}', '{}', {}, {}, {}, {}, '{}')",
+            quote(&flight.destination),
+            date,
+            departure,
+            flight.flight_id,
+            quote(&flight.origin),
+            state,
+            mode,
+            eta
+        );
+        rows.push((flight.flight_id, (departures, arrivals)));
+    }
+    rows
+}
+
+/// Builds the queries that write the rows of several flights, a single multi-row INSERT for
+/// each board instead of one per flight.
+///
+/// # Returns
+/// The queries, none if there are no rows.
+pub fn board_queries(rows: &[(FlightId, BoardRows)]) -> Vec<String> {
+    if rows.is_empty() {
+        return vec![];
+    }
+    let (departures, arrivals): (Vec<&str>, Vec<&str>) = rows
+        .iter()
+        .map(|(_, (departures, arrivals))| (departures.as_str(), arrivals.as_str()))
+        .unzip();
+    vec![
+        format!("{} {};", DEPARTURES_INSERT, departures.join(", ")),
+        format!("{} {};", ARRIVALS_INSERT, arrivals.join(", ")),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_flight_is_on_the_board_of_both_airports() {
+        let departure = "2024-11-05T06:00:00Z".parse().unwrap();
+        let flights = [
+            BoardFlight {
+                flight_id: 3,
+                origin: "EZE".to_string(),
+                destination: "MAD".to_string(),
+                departure,
+            },
+            BoardFlight {
+                flight_id: 4,
+                origin: "MAD".to_string(),
+                destination: "EZE".to_string(),
+                departure,
+            },
+        ];
+        let statuses = [BoardStatus {
+            flight_id: 3,
+            status: Some("On Air".to_string()),
+            mode: Some("cruising".to_string()),
+            eta: Some("2024-11-05T18:00:00Z".parse().unwrap()),
+        }];
+
+        let queries = board_queries(&board_rows(&flights, &statuses));
+        assert_eq!(queries.len(), 2);
+        assert!(queries[0].ends_with(
+            "VALUES ('EZE', '2024-11-05', '2024-11-05T06:00:00.000Z', 3, 'MAD', 'On Air', 'cruising'), \
+                ('MAD', '2024-11-05', '2024-11-05T06:00:00.000Z', 4, 'EZE', 'Unknown', 'Unknown');"
+        ));
+        assert!(queries[1].starts_with("INSERT INTO arrivals_by_airport"));
+        assert!(queries[1].contains(
+            "VALUES ('MAD', '2024-11-05', '2024-11-05T06:00:00.000Z', 3, 'EZE', 'On Air', 'cruising', '2024-11-05T18:00:00.000Z'), "
+        ));
+        // Sin fila en status el vuelo figura como desconocido
+        assert!(queries[1].ends_with("'MAD', 'Unknown', 'Unknown', '');"));
+        assert!(board_queries(&[]).is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// A source of the current time for the models, so that their timestamps follow simulated time
+/// instead of the wall clock.
+pub trait SimClock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The wall clock, for the models outside of a simulation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WallClock;
+
+impl SimClock for WallClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A time that doesn't move, for instance the departure of a flight or the moment a replay
+/// starts from.
+impl SimClock for DateTime<Utc> {
+    fn now(&self) -> DateTime<Utc> {
+        *self
+    }
+}
+
+impl SimClock for SimulationClock {
+    fn now(&self) -> DateTime<Utc> {
+        SimulationClock::now(self)
+    }
+}
+
+/// The clock of the simulation. Simulated time runs `speed` times faster than real time and
+/// stops while paused, when it can be advanced by hand one tick at a time. It is shared by the
+/// simulator, which moves the flights once per tick, and the interface, which controls it.
+#[derive(Debug)]
+pub struct SimulationClock {
+    /// Real time between the ticks of the simulator.
+    tick_interval: std::time::Duration,
+    state: Mutex<ClockState>,
+}
+
+#[derive(Debug)]
+struct ClockState {
+    now: DateTime<Utc>,
+    speed: f64,
+    paused: bool,
+    /// Real time when `now` was last brought up to date.
+    synced_at: Instant,
+    /// Simulated time of the previous tick.
+    last_tick: DateTime<Utc>,
+    /// Simulated time added by `step` while paused, for the next tick.
+    pending: Duration,
+}
+
+impl ClockState {
+    fn sync(&mut self, real_now: Instant) {
+        if !self.paused {
+            let elapsed = real_now.saturating_duration_since(self.synced_at);
+            self.now += scale(elapsed, self.speed);
+        }
+        self.synced_at = real_now;
+    }
+}
+
+impl SimulationClock {
+    /// Creates a clock at `start`, running at real time.
+    pub fn new(start: DateTime<Utc>, tick_interval: std::time::Duration) -> Self {
+        Self {
+            tick_interval,
+            state: Mutex::new(ClockState {
+                now: start,
+                speed: 1.0,
+                paused: false,
+                synced_at: Instant::now(),
+                last_tick: start,
+                pending: Duration::zero(),
+            }),
+        }
+    }
+
+    /// Returns the simulated time.
+    pub fn now(&self) -> DateTime<Utc> {
+        let mut state = self.lock();
+        state.sync(Instant::now());
+        state.now
+    }
+
+    /// Returns how many times faster than real time the simulation runs.
+    pub fn speed(&self) -> f64 {
+        self.lock().speed
+    }
+
+    /// Sets how many times faster than real time the simulation runs, e.g. 60 to fly an hour
+    /// in a minute.
+    ///
+    /// # Returns
+    /// An error if the speed is not a positive number.
+    pub fn set_speed(&self, speed: f64) -> Result<(), String> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err(format!("Invalid simulation speed {}", speed));
+        }
+        let mut state = self.lock();
+        // El tiempo transcurrido hasta ahora corre a la velocidad anterior
+        state.sync(Instant::now());
+        state.speed = speed;
+        Ok(())
+    }
+
+    pub fn pause(&self) {
+        let mut state = self.lock();
+        state.sync(Instant::now());
+        state.paused = true;
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.lock();
+        state.sync(Instant::now());
+        state.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    /// Advances the simulated time of one tick while paused, which the next tick moves the
+    /// flights through.
+    pub fn step(&self) {
+        let mut state = self.lock();
+        if state.paused {
+            let tick = scale(self.tick_interval, state.speed);
+            state.pending += tick;
+        }
+    }
+
+    /// Waits for the next tick of the simulator.
+    ///
+    /// # Returns
+    /// The simulated time elapsed since the previous tick, zero while paused.
+    pub fn tick(&self) -> Duration {
+        thread::sleep(self.tick_interval);
+        self.advance(Instant::now())
+    }
+
+    fn advance(&self, real_now: Instant) -> Duration {
+        let mut state = self.lock();
+        state.sync(real_now);
+        let pending = std::mem::replace(&mut state.pending, Duration::zero());
+        state.now += pending;
+        let elapsed = state.now - state.last_tick;
+        state.last_tick = state.now;
+        elapsed
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ClockState> {
+        // El estado sigue siendo valido aunque otro hilo haya entrado en panico
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Multiplies a real duration by the speed of the simulation.
+fn scale(real: std::time::Duration, speed: f64) -> Duration {
+    Duration::microseconds((real.as_micros() as f64 * speed) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration as RealDuration;
+
+    #[test]
+    fn simulated_time_runs_at_the_speed_of_the_clock() {
+        let start = Utc::now();
+        let clock = SimulationClock::new(start, RealDuration::from_millis(100));
+        let real_start = clock.lock().synced_at;
+
+        clock.set_speed(60.0).unwrap();
+        let elapsed = clock.advance(real_start + RealDuration::from_secs(2));
+        assert!(elapsed >= Duration::seconds(119) && elapsed <= Duration::seconds(121));
+
+        clock.pause();
+        assert!(clock.is_paused());
+        let paused_at = clock.now();
+        assert_eq!(clock.advance(Instant::now()), paused_at - start - elapsed);
+        assert_eq!(
+            clock.advance(Instant::now() + RealDuration::from_secs(5)),
+            Duration::zero()
+        );
+
+        clock.step();
+        clock.step();
+        assert_eq!(clock.advance(Instant::now()), Duration::seconds(12));
+        assert_eq!(clock.now(), paused_at + Duration::seconds(12));
+
+        clock.resume();
+        assert!(clock.now() >= paused_at + Duration::seconds(12));
+        assert!(clock.set_speed(0.0).is_err());
+        assert!(clock.set_speed(f64::NAN).is_err());
+        assert_eq!(clock.speed(), 60.0);
+    }
+}
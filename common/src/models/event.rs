@@ -0,0 +1,202 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rand::Rng;
+use uuid::Uuid;
+
+use super::tracking_data::{mode::Mode, Degrees, Meters, TrackingData};
+use super::FlightId;
+
+/// 100 ns intervals between the start of the gregorian calendar, where the time of a timeuuid
+/// counts from, and the unix epoch.
+const GREGORIAN_OFFSET: u64 = 0x01B2_1DD2_1381_4000;
+
+/// Something that happened to a flight, for its timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlightEventKind {
+    Takeoff,
+    ReachedCruise,
+    DescentStarted,
+    Landed,
+    ArrivedAtGate,
+    Sos,
+    Diverted,
+    /// Another flight got closer than the separation.
+    ProximityAlert,
+}
+
+impl std::fmt::Display for FlightEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlightEventKind::Takeoff => write!(f, "takeoff"),
+            FlightEventKind::ReachedCruise => write!(f, "reached_cruise"),
+            FlightEventKind::DescentStarted => write!(f, "descent_started"),
+            FlightEventKind::Landed => write!(f, "landed"),
+            FlightEventKind::ArrivedAtGate => write!(f, "arrived_at_gate"),
+            FlightEventKind::Sos => write!(f, "sos"),
+            FlightEventKind::Diverted => write!(f, "diverted"),
+            FlightEventKind::ProximityAlert => write!(f, "proximity_alert"),
+        }
+    }
+}
+
+impl FlightEventKind {
+    /// Returns the event of a plane changing from the mode `before` to `after` in a step of
+    /// the simulation, `None` if the change is not one of the timeline.
+    pub fn from_transition(before: &Mode, after: &Mode) -> Option<Self> {
+        match (before, after) {
+            (before, after) if before == after => None,
+            (_, Mode::Sos) => Some(FlightEventKind::Sos),
+            (Mode::OnGround, Mode::Climbing) => Some(FlightEventKind::Takeoff),
+            (_, Mode::Cruising) => Some(FlightEventKind::ReachedCruise),
+            (Mode::Cruising, Mode::Descending) => Some(FlightEventKind::DescentStarted),
+            (Mode::Landing | Mode::Descending | Mode::Sos, Mode::OnGround) => {
+                Some(FlightEventKind::Landed)
+            }
+            (_, Mode::AtGate) => Some(FlightEventKind::ArrivedAtGate),
+            _ => None,
+        }
+    }
+}
+
+/// An event of a flight, where and when it happened, stored in the `flight_events` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightEvent {
+    pub flight_id: FlightId,
+    pub kind: FlightEventKind,
+    pub at: DateTime<Utc>,
+    pub latitude: Degrees,
+    pub longitude: Degrees,
+    pub altitude: Meters,
+    /// Timeuuid of the event, which tells apart the events of a flight at the same time.
+    pub event_id: Uuid,
+}
+
+impl FlightEvent {
+    /// Creates an event at the position and time of the last update of the plane.
+    pub fn new(
+        flight_id: FlightId,
+        kind: FlightEventKind,
+        tracking: &TrackingData,
+        rng: &mut impl Rng,
+    ) -> Self {
+        Self {
+            flight_id,
+            kind,
+            at: tracking.last_update,
+            latitude: tracking.latitude,
+            longitude: tracking.longitude,
+            altitude: tracking.altitude,
+            event_id: timeuuid(tracking.last_update, rng),
+        }
+    }
+
+    /// Builds the query that stores the event. The events of a flight are clustered by `at`
+    /// and then `event_id`; `at` goes first and with a fixed width because the nodes compare
+    /// the clustering columns as text, so the timeline reads in order.
+    pub fn generate_query(&self) -> String {
+        format!(
+            "INSERT INTO flight_events (flight_id, at, event_id, kind, latitude, longitude, altitude) \
+                VALUES ({}, '{}', '{}', '{}', '{}', '{}', '{}');",
+            self.flight_id,
+            self.at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.event_id,
+            self.kind,
+            self.latitude,
+            self.longitude,
+            self.altitude
+        )
+    }
+}
+
+/// Builds a version 1 uuid for `at`, with the clock sequence and node taken from `rng` so that
+/// runs with the same seed repeat them.
+pub fn timeuuid(at: DateTime<Utc>, rng: &mut impl Rng) -> Uuid {
+    let intervals = at
+        .timestamp_nanos_opt()
+        .map(|nanos| (nanos / 100) as u64)
+        .unwrap_or_default()
+        .wrapping_add(GREGORIAN_OFFSET);
+    let clock_sequence: u16 = rng.random();
+    let node: [u8; 6] = rng.random();
+
+    let mut rest = [0; 8];
+    // Variante RFC 4122 en los dos bits altos de la secuencia
+    rest[0] = ((clock_sequence >> 8) as u8 & 0x3f) | 0x80;
+    rest[1] = clock_sequence as u8;
+    rest[2..].copy_from_slice(&node);
+    Uuid::from_fields(
+        intervals as u32,
+        (intervals >> 32) as u16,
+        ((intervals >> 48) as u16 & 0x0fff) | 0x1000,
+        &rest,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::clock::WallClock;
+    use crate::models::rng::flight_rng;
+    use chrono::TimeZone;
+
+    #[test]
+    fn events_come_from_the_changes_of_mode() {
+        let event = |before, after| FlightEventKind::from_transition(&before, &after);
+        assert_eq!(
+            event(Mode::OnGround, Mode::Climbing),
+            Some(FlightEventKind::Takeoff)
+        );
+        assert_eq!(
+            event(Mode::Climbing, Mode::Cruising),
+            Some(FlightEventKind::ReachedCruise)
+        );
+        assert_eq!(
+            event(Mode::Cruising, Mode::Descending),
+            Some(FlightEventKind::DescentStarted)
+        );
+        assert_eq!(
+            event(Mode::Landing, Mode::OnGround),
+            Some(FlightEventKind::Landed)
+        );
+        assert_eq!(
+            event(Mode::Taxiing, Mode::AtGate),
+            Some(FlightEventKind::ArrivedAtGate)
+        );
+        assert_eq!(event(Mode::OnGround, Mode::Taxiing), None);
+        assert_eq!(event(Mode::Cruising, Mode::Sos), Some(FlightEventKind::Sos));
+        assert_eq!(event(Mode::Descending, Mode::Landing), None);
+        assert_eq!(event(Mode::Cruising, Mode::Cruising), None);
+    }
+
+    #[test]
+    fn events_are_stored_with_a_timeuuid() {
+        let mut tracking = TrackingData::empty(&WallClock);
+        tracking.last_update = Utc.with_ymd_and_hms(2024, 11, 5, 6, 0, 0).unwrap();
+        let event = FlightEvent::new(
+            7,
+            FlightEventKind::Takeoff,
+            &tracking,
+            &mut flight_rng(1, 7),
+        );
+
+        assert_eq!(event.event_id.get_version_num(), 1);
+        assert_eq!(event.event_id.get_variant(), uuid::Variant::RFC4122);
+        let (seconds, nanos) = event.event_id.get_timestamp().unwrap().to_unix();
+        assert_eq!(
+            (seconds as i64, nanos),
+            (tracking.last_update.timestamp(), 0)
+        );
+        assert_eq!(
+            event,
+            FlightEvent::new(
+                7,
+                FlightEventKind::Takeoff,
+                &tracking,
+                &mut flight_rng(1, 7)
+            )
+        );
+        assert!(event
+            .generate_query()
+            .contains("VALUES (7, '2024-11-05T06:00:00.000Z', '"));
+        assert!(event.generate_query().contains("'takeoff'"));
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::frame::messages::consistency_level::ConsistencyLevel;
+use crate::frame::messages::error::ErrorCode;
+
+/// The `[fleet]` section of the config: how many flights the simulator keeps in the air, how
+/// fast it adds new ones and how it backs off when the database can't keep up.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct FleetConfig {
+    /// Flights in the air at the same time, at most.
+    pub max_flights: usize,
+    /// New flights per minute of simulated time, at most.
+    pub spawn_per_minute: f64,
+    /// Wait before writing again after the database answers Overloaded or Unavailable. It
+    /// doubles while the database keeps answering so, up to `max_backoff_ms`.
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for FleetConfig {
+    fn default() -> Self {
+        Self {
+            max_flights: 100,
+            spawn_per_minute: 10.0,
+            backoff_ms: 100,
+            max_backoff_ms: 5_000,
+        }
+    }
+}
+
+impl FleetConfig {
+    /// Returns the problems of the section, if any.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.max_flights == 0 {
+            problems.push("fleet max_flights must be at least 1".to_string());
+        }
+        if !self.spawn_per_minute.is_finite() || self.spawn_per_minute <= 0.0 {
+            problems.push(format!(
+                "fleet spawn_per_minute {} must be positive",
+                self.spawn_per_minute
+            ));
+        }
+        if self.backoff_ms == 0 || self.max_backoff_ms < self.backoff_ms {
+            problems.push(format!(
+                "fleet backoff_ms {} must be at least 1 and at most max_backoff_ms {}",
+                self.backoff_ms, self.max_backoff_ms
+            ));
+        }
+        problems
+    }
+}
+
+/// Decides how many of the flights due may depart, keeping the fleet within its size and
+/// spawn rate. The flights that can't depart yet wait for the next tick.
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    config: FleetConfig,
+    /// Departures allowed by the spawn rate and not used yet.
+    allowance: f64,
+    last_admission: DateTime<Utc>,
+}
+
+impl Fleet {
+    pub fn new(config: FleetConfig, now: DateTime<Utc>) -> Self {
+        Self {
+            allowance: 1.0,
+            config,
+            last_admission: now,
+        }
+    }
+
+    /// Returns how many flights may depart at `now`.
+    ///
+    /// # Parameters
+    /// - `in_flight`: The flights being simulated.
+    /// - `backing_off`: Whether the writes are backing off, when no flight departs.
+    pub fn admit(&mut self, now: DateTime<Utc>, in_flight: usize, backing_off: bool) -> usize {
+        let minutes = (now - self.last_admission).num_milliseconds().max(0) as f64 / 60_000.0;
+        self.last_admission = now;
+        // Se acumulan a lo sumo las salidas de un minuto
+        let burst = self.config.spawn_per_minute.max(1.0);
+        self.allowance = (self.allowance + minutes * self.config.spawn_per_minute).min(burst);
+        if backing_off {
+            return 0;
+        }
+        let admitted = (self.allowance.floor() as usize)
+            .min(self.config.max_flights.saturating_sub(in_flight));
+        self.allowance -= admitted as f64;
+        admitted
+    }
+}
+
+/// Queues the writes of the simulator and sends them in order, waiting longer each time the
+/// database answers Overloaded or Unavailable, instead of dropping them.
+#[derive(Debug, Clone)]
+pub struct WriteThrottle {
+    pending: VecDeque<String>,
+    backoff: Duration,
+    max_backoff: Duration,
+    /// Wait of the next back off.
+    next_backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl WriteThrottle {
+    pub fn new(config: &FleetConfig) -> Self {
+        let backoff = Duration::from_millis(config.backoff_ms);
+        Self {
+            pending: VecDeque::new(),
+            backoff,
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            next_backoff: backoff,
+            retry_at: None,
+        }
+    }
+
+    /// Queues a write, sent by the next `flush`.
+    pub fn push(&mut self, query: String) {
+        self.pending.push_back(query);
+    }
+
+    /// Returns the writes waiting to be sent.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns whether the writes are waiting for the database to recover.
+    pub fn is_backing_off(&self, now: Instant) -> bool {
+        self.retry_at.is_some_and(|retry_at| now < retry_at)
+    }
+
+    /// Sends the queued writes in order with `send`, unless backing off. A write the database
+    /// rejects because it is overloaded or unavailable stays queued and the following ones wait
+    /// with it.
+    ///
+    /// # Returns
+    /// The writes sent, or the error of a write that failed for another reason, which is
+    /// taken out of the queue.
+    pub fn flush(
+        &mut self,
+        now: Instant,
+        mut send: impl FnMut(&str) -> Result<String, String>,
+    ) -> Result<usize, String> {
+        if self.is_backing_off(now) {
+            return Ok(0);
+        }
+        let mut sent = 0;
+        while let Some(query) = self.pending.front() {
+            match send(query) {
+                Ok(_) => {
+                    self.pending.pop_front();
+                    self.next_backoff = self.backoff;
+                    self.retry_at = None;
+                    sent += 1;
+                }
+                Err(e) if is_backpressure(&e) => {
+                    self.retry_at = Some(now + self.next_backoff);
+                    self.next_backoff = (self.next_backoff * 2).min(self.max_backoff);
+                    break;
+                }
+                Err(e) => {
+                    self.pending.pop_front();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(sent)
+    }
+}
+
+/// Returns whether an error answered by the database means that it can't keep up.
+fn is_backpressure(error: &str) -> bool {
+    // El mensaje no depende de las réplicas vivas
+    let unavailable = ErrorCode::UnavailableException {
+        consistency: ConsistencyLevel::default(),
+        required: 0,
+        alive: 0,
+    };
+    [ErrorCode::Overloaded, unavailable]
+        .iter()
+        .any(|code| error == code.message())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn flights_depart_within_the_size_and_rate_of_the_fleet() {
+        let start = Utc.with_ymd_and_hms(2024, 11, 5, 6, 0, 0).unwrap();
+        let mut fleet = Fleet::new(
+            FleetConfig {
+                max_flights: 3,
+                spawn_per_minute: 2.0,
+                ..FleetConfig::default()
+            },
+            start,
+        );
+        assert_eq!(fleet.admit(start, 0, false), 1);
+        assert_eq!(
+            fleet.admit(start + chrono::Duration::seconds(30), 1, false),
+            1
+        );
+        // Un rato largo no acumula mas que las salidas de un minuto
+        assert_eq!(fleet.admit(start + chrono::Duration::hours(1), 1, true), 0);
+        assert_eq!(fleet.admit(start + chrono::Duration::hours(1), 1, false), 2);
+        assert_eq!(fleet.admit(start + chrono::Duration::hours(2), 3, false), 0);
+        assert!(FleetConfig::default().problems().is_empty());
+    }
+
+    #[test]
+    fn writes_back_off_while_the_database_is_overloaded() {
+        let config = FleetConfig {
+            backoff_ms: 100,
+            max_backoff_ms: 150,
+            ..FleetConfig::default()
+        };
+        let mut throttle = WriteThrottle::new(&config);
+        throttle.push("first".to_string());
+        throttle.push("second".to_string());
+        let overloaded = |_: &str| Err(ErrorCode::Overloaded.message().to_string());
+
+        let start = Instant::now();
+        assert_eq!(throttle.flush(start, overloaded), Ok(0));
+        assert!(throttle.is_backing_off(start + Duration::from_millis(99)));
+        assert_eq!(
+            throttle.flush(start, |_| panic!("sent while backing off")),
+            Ok(0)
+        );
+
+        let later = start + Duration::from_millis(100);
+        assert_eq!(throttle.flush(later, overloaded), Ok(0));
+        // La espera se duplica hasta el maximo
+        assert!(throttle.is_backing_off(later + Duration::from_millis(149)));
+        assert_eq!(throttle.pending(), 2);
+
+        let mut sent = vec![];
+        let recovered = later + Duration::from_millis(150);
+        let result = throttle.flush(recovered, |query| {
+            sent.push(query.to_string());
+            Ok(String::new())
+        });
+        assert_eq!(result, Ok(2));
+        assert_eq!(sent, ["first", "second"]);
+
+        throttle.push("invalid".to_string());
+        let invalid = |_: &str| Err(ErrorCode::Invalid.message().to_string());
+        assert!(throttle.flush(recovered, invalid).is_err());
+        assert_eq!(throttle.pending(), 0);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+
+use super::board::{BoardFlight, BoardStatus};
+use super::tracking_data::mode::Mode;
+use super::FlightId;
+use crate::client_manager::{ClientManager, FromRow};
+use crate::frame::messages::prepared::quote;
+
+/// The tables that file the flights under their status and under their mode, partitioned by
+/// either and the day of departure.
+pub const TABLES: [&str; 2] = [
+    "CREATE TABLE flights_by_status (status TEXT, date TEXT, departure TEXT, flight_id INT, \
+        mode TEXT, origin TEXT, destination TEXT, PRIMARY KEY ((status, date), departure, flight_id));",
+    "CREATE TABLE flights_by_mode (mode TEXT, date TEXT, departure TEXT, flight_id INT, \
+        status TEXT, origin TEXT, destination TEXT, PRIMARY KEY ((mode, date), departure, flight_id));",
+];
+
+/// The flights the interface shows, on a day of departure. Each filter is answered by the
+/// table partitioned by it, so the nodes read a single partition instead of every flight:
+/// `departures_by_airport` for the origin, `arrivals_by_airport` for the destination,
+/// `flights_by_mode` for the mode and `flights_by_status` for the status. The other filters
+/// are checked by the nodes on the rows of that partition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightFilter {
+    pub date: NaiveDate,
+    pub origin: Option<String>,
+    pub destination: Option<String>,
+    /// As stored in the `status` column, e.g. `On Air`.
+    pub status: Option<String>,
+    pub mode: Option<Mode>,
+}
+
+/// A flight that passed a `FlightFilter`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilteredFlight {
+    pub flight_id: FlightId,
+    pub origin: String,
+    pub destination: String,
+    pub departure: DateTime<Utc>,
+    pub status: String,
+    pub mode: String,
+}
+
+/// A row of any of the tables of the filters, which name the airport of the partition
+/// `airport`.
+#[derive(Debug, Clone, FromRow)]
+struct FilterRow {
+    flight_id: FlightId,
+    airport: Option<String>,
+    origin: Option<String>,
+    destination: Option<String>,
+    departure: DateTime<Utc>,
+    status: String,
+    mode: String,
+}
+
+impl FlightFilter {
+    /// A filter that lets every flight of `date` through.
+    pub fn on(date: NaiveDate) -> Self {
+        Self {
+            date,
+            origin: None,
+            destination: None,
+            status: None,
+            mode: None,
+        }
+    }
+
+    /// Builds the query of the filter, on the table partitioned by its first filter.
+    ///
+    /// # Returns
+    /// The query, or `None` if nothing is filtered and every flight is shown.
+    pub fn select_query(&self) -> Option<String> {
+        let mode = self.mode.as_ref().map(Mode::to_string);
+        let filters = [
+            ("origin", self.origin.as_deref()),
+            ("destination", self.destination.as_deref()),
+            ("mode", mode.as_deref()),
+            ("status", self.status.as_deref()),
+        ];
+        let first = filters.iter().position(|(_, value)| value.is_some())?;
+        let (table, key) = match filters[first].0 {
+            "origin" => ("departures_by_airport", "airport"),
+            "destination" => ("arrivals_by_airport", "airport"),
+            "mode" => ("flights_by_mode", "mode"),
+            _ => ("flights_by_status", "status"),
+        };
+        let mut conditions = vec![
+            format!("{} = {}", key, quote(filters[first].1?)),
+            format!("date = '{}'", self.date.format("%Y-%m-%d")),
+        ];
+        // Los filtros que siguen son columnas de las filas de la particion
+        for (column, value) in &filters[first + 1..] {
+            if let Some(value) = value {
+                conditions.push(format!("{} = {}", column, quote(value)));
+            }
+        }
+        Some(format!(
+            "SELECT * FROM {} WHERE {};",
+            table,
+            conditions.join(" AND ")
+        ))
+    }
+
+    /// Reads the flights that pass the filter.
+    ///
+    /// # Returns
+    /// The flights in order of departure, `None` if nothing is filtered, or an error if the
+    /// query fails or a row is invalid.
+    pub fn fetch(&self, client: &mut ClientManager) -> Result<Option<Vec<FilteredFlight>>, String> {
+        let Some(query) = self.select_query() else {
+            return Ok(None);
+        };
+        let rows: Vec<FilterRow> = client.query_as(query, None)?;
+        let mut flights: Vec<FilteredFlight> = rows
+            .into_iter()
+            .map(|row| FilteredFlight {
+                flight_id: row.flight_id,
+                origin: row.origin.or(row.airport.clone()).unwrap_or_default(),
+                destination: row.destination.or(row.airport).unwrap_or_default(),
+                departure: row.departure,
+                status: row.status,
+                mode: row.mode,
+            })
+            .collect();
+        flights.sort_by_key(|flight| (flight.departure, flight.flight_id));
+        Ok(Some(flights))
+    }
+}
+
+/// The status and mode each flight is filed under in `flights_by_status` and
+/// `flights_by_mode`, kept by the `BoardJob` to move the flights whose status or mode changed.
+#[derive(Debug, Clone, Default)]
+pub struct FiledFlights {
+    filed: HashMap<FlightId, (String, String)>,
+}
+
+impl FiledFlights {
+    /// Builds the queries that file the flights whose status or mode changed since the previous
+    /// run under the current ones, and take them out of the partitions of those they had. The
+    /// rows of each table go in a single multi-row INSERT.
+    ///
+    /// # Parameters
+    /// - `flights`: The rows of `flights`.
+    /// - `statuses`: The rows of `status`; flights without one are `Unknown`.
+    pub fn queries(&mut self, flights: &[BoardFlight], statuses: &[BoardStatus]) -> Vec<String> {
+        let statuses: HashMap<FlightId, &BoardStatus> = statuses
+            .iter()
+            .map(|status| (status.flight_id, status))
+            .collect();
+        let mut queries = vec![];
+        let mut rows = vec![];
+        for flight in flights {
+            let status = statuses.get(&flight.flight_id);
+            let text = |value: Option<&Option<String>>| {
+                value
+                    .and_then(Option::as_deref)
+                    .unwrap_or("Unknown")
+                    .to_string()
+            };
+            let state = (
+                text(status.map(|status| &status.status)),
+                text(status.map(|status| &status.mode)),
+            );
+            let date = flight.departure.format("%Y-%m-%d");
+            let departure = flight
+                .departure
+                .to_rfc3339_opts(SecondsFormat::Millis, true);
+            let key = format!(
+                "date = '{}' AND departure = '{}' AND flight_id = {}",
+                date, departure, flight.flight_id
+            );
+
+            let previous = self.filed.insert(flight.flight_id, state.clone());
+            if previous.as_ref() == Some(&state) {
+                continue;
+            }
+            if let Some((status, mode)) = &previous {
+                if *status != state.0 {
+                    queries.push(format!(
+                        "DELETE FROM flights_by_status WHERE status = {} AND {};",
+                        quote(status),
+                        key
+                    ));
+                }
+                if *mode != state.1 {
+                    queries.push(format!(
+                        "DELETE FROM flights_by_mode WHERE mode = {} AND {};",
+                        quote(mode),
+                        key
+                    ));
+                }
+            }
+            rows.push(format!(
+                "({}, {}, '{}', '{}', {}, {}, {})",
+                quote(&state.0),
+                quote(&state.1),
+                date,
+                departure,
+                flight.flight_id,
+                quote(&flight.origin),
+                quote(&flight.destination)
+            ));
+        }
+        if !rows.is_empty() {
+            for table in ["flights_by_status", "flights_by_mode"] {
+                queries.push(format!(
+                    "INSERT INTO {} (status, mode, date, departure, flight_id, origin, destination) VALUES {};",
+                    table,
+                    rows.join(", ")
+                ));
+            }
+        }
+        queries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_filter_reads_the_partition_of_its_table() {
+        let date = NaiveDate::from_ymd_opt(2024, 11, 5).unwrap();
+        assert_eq!(FlightFilter::on(date).select_query(), None);
+
+        let sos = FlightFilter {
+            mode: Some(Mode::Sos),
+            ..FlightFilter::on(date)
+        };
+        assert_eq!(
+            sos.select_query().unwrap(),
+            "SELECT * FROM flights_by_mode WHERE mode = 'sos' AND date = '2024-11-05';"
+        );
+
+        let from_eze = FlightFilter {
+            origin: Some("EZE".to_string()),
+            status: Some("On Air".to_string()),
+            ..FlightFilter::on(date)
+        };
+        assert_eq!(
+            from_eze.select_query().unwrap(),
+            "SELECT * FROM departures_by_airport WHERE airport = 'EZE' AND date = '2024-11-05' AND status = 'On Air';"
+        );
+
+        let to_mad = FlightFilter {
+            destination: Some("MAD".to_string()),
+            mode: Some(Mode::Cruising),
+            ..FlightFilter::on(date)
+        };
+        assert_eq!(
+            to_mad.select_query().unwrap(),
+            "SELECT * FROM arrivals_by_airport WHERE airport = 'MAD' AND date = '2024-11-05' AND mode = 'cruising';"
+        );
+    }
+
+    #[test]
+    fn flights_move_to_the_partition_of_their_new_mode() {
+        let flights = [BoardFlight {
+            flight_id: 3,
+            origin: "EZE".to_string(),
+            destination: "MAD".to_string(),
+            departure: "2024-11-05T06:00:00Z".parse().unwrap(),
+        }];
+        let status = |mode: &str| BoardStatus {
+            flight_id: 3,
+            status: Some("On Air".to_string()),
+            mode: Some(mode.to_string()),
+            eta: None,
+        };
+        let mut filed = FiledFlights::default();
+
+        let queries = filed.queries(&flights, &[status("cruising")]);
+        assert_eq!(queries.len(), 2);
+        assert!(queries[1].starts_with("INSERT INTO flights_by_mode"));
+        assert!(queries[1].ends_with(
+            "VALUES ('On Air', 'cruising', '2024-11-05', '2024-11-05T06:00:00.000Z', 3, 'EZE', 'MAD');"
+        ));
+
+        // Al pasar a SOS se borra de la particion de crucero
+        let queries = filed.queries(&flights, &[status("sos")]);
+        assert_eq!(queries.len(), 3);
+        assert_eq!(
+            queries[0],
+            "DELETE FROM flights_by_mode WHERE mode = 'cruising' AND date = '2024-11-05' \
+                AND departure = '2024-11-05T06:00:00.000Z' AND flight_id = 3;"
+        );
+        // Si no cambio nada no se vuelve a escribir
+        assert!(filed.queries(&flights, &[status("sos")]).is_empty());
+    }
+}
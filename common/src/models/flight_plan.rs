@@ -0,0 +1,186 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::tracking_data::{haversine_distance, Degrees, KmH, Meters};
+use super::FlightId;
+
+// Las tablas se guardan en CSV sin escapar, asi que la ruta no puede llevar comas
+const WAYPOINT_SEPARATOR: char = '|';
+
+/// A point of the route of a flight, with the altitude and speed the plane cruises at on
+/// its way to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub latitude: Degrees,
+    pub longitude: Degrees,
+    pub altitude: Meters,
+    pub speed: KmH,
+}
+
+impl Waypoint {
+    pub fn new(latitude: Degrees, longitude: Degrees, altitude: Meters, speed: KmH) -> Self {
+        Self {
+            latitude,
+            longitude,
+            altitude,
+            speed,
+        }
+    }
+}
+
+/// The route of a flight: its waypoints in order, the last one being the destination, and
+/// the leg being flown, the one towards the active waypoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightPlan {
+    waypoints: Vec<Waypoint>,
+    active_leg: usize,
+}
+
+impl FlightPlan {
+    /// Creates a plan through `waypoints`, starting with the leg towards the first one.
+    pub fn new(waypoints: Vec<Waypoint>) -> Self {
+        Self {
+            waypoints,
+            active_leg: 0,
+        }
+    }
+
+    /// Creates a plan straight to the destination.
+    pub fn direct(destination_lat: Degrees, destination_lon: Degrees) -> Self {
+        Self::new(vec![Waypoint::new(destination_lat, destination_lon, 0, 0)])
+    }
+
+    pub fn waypoints(&self) -> &[Waypoint] {
+        &self.waypoints
+    }
+
+    /// Returns the index of the leg being flown, which ends at the waypoint of that index.
+    pub fn active_leg(&self) -> usize {
+        self.active_leg
+    }
+
+    /// Returns the waypoint the plane is flying to, `None` once the destination is reached.
+    pub fn active_waypoint(&self) -> Option<&Waypoint> {
+        self.waypoints.get(self.active_leg)
+    }
+
+    /// Returns the destination, `None` if the plan has no waypoints.
+    pub fn destination(&self) -> Option<&Waypoint> {
+        self.waypoints.last()
+    }
+
+    /// Returns whether the active leg ends at the destination.
+    pub fn is_last_leg(&self) -> bool {
+        self.active_leg + 1 >= self.waypoints.len()
+    }
+
+    /// Moves on to the next leg, once the active waypoint is reached.
+    pub fn advance(&mut self) {
+        self.active_leg = (self.active_leg + 1).min(self.waypoints.len());
+    }
+
+    /// Returns the distance in km from a position to the destination along the rest of the
+    /// route.
+    pub fn remaining_distance(&self, latitude: Degrees, longitude: Degrees) -> f64 {
+        let mut distance = 0.0;
+        let mut from = (latitude, longitude);
+        for waypoint in &self.waypoints[self.active_leg.min(self.waypoints.len())..] {
+            distance += haversine_distance(from.0, from.1, waypoint.latitude, waypoint.longitude);
+            from = (waypoint.latitude, waypoint.longitude);
+        }
+        distance
+    }
+
+    /// Builds the query that stores the plan, so the route can be drawn on the map.
+    pub fn generate_query(&self, flight_id: FlightId) -> String {
+        format!(
+            "INSERT INTO flight_plans (flight_id, leg, route) \
+                VALUES ({}, '{}', '{}');",
+            flight_id, self.active_leg, self
+        )
+    }
+}
+
+/// Writes the route as stored in `flight_plans`: the waypoints separated by `|`, each one as
+/// `latitude longitude altitude speed`.
+impl fmt::Display for FlightPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, waypoint) in self.waypoints.iter().enumerate() {
+            if i > 0 {
+                write!(f, "{}", WAYPOINT_SEPARATOR)?;
+            }
+            write!(
+                f,
+                "{} {} {} {}",
+                waypoint.latitude, waypoint.longitude, waypoint.altitude, waypoint.speed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a route stored in `flight_plans`, starting from its first leg.
+impl FromStr for FlightPlan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid route {}", s);
+        let waypoints = s
+            .split(WAYPOINT_SEPARATOR)
+            .map(|waypoint| {
+                let fields: Vec<&str> = waypoint.split_whitespace().collect();
+                let [latitude, longitude, altitude, speed] = fields[..] else {
+                    return Err(invalid());
+                };
+                Ok(Waypoint::new(
+                    latitude.parse().map_err(|_| invalid())?,
+                    longitude.parse().map_err(|_| invalid())?,
+                    altitude.parse().map_err(|_| invalid())?,
+                    speed.parse().map_err(|_| invalid())?,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(waypoints))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_roundtrip_through_the_stored_text() {
+        let plan = FlightPlan::new(vec![
+            Waypoint::new(-23.4, -46.5, 10_000, 850),
+            Waypoint::new(40.47, -3.56, 0, 0),
+        ]);
+        assert_eq!(plan.to_string(), "-23.4 -46.5 10000 850|40.47 -3.56 0 0");
+        assert_eq!(plan.to_string().parse(), Ok(plan.clone()));
+        assert!(plan
+            .generate_query(7)
+            .contains("VALUES (7, '0', '-23.4 -46.5"));
+
+        assert!("-23.4 -46.5 10000".parse::<FlightPlan>().is_err());
+        assert!("-23.4 -46.5 alto 850".parse::<FlightPlan>().is_err());
+    }
+
+    #[test]
+    fn the_remaining_distance_follows_the_legs() {
+        let mut plan = FlightPlan::new(vec![
+            Waypoint::new(0.0, 10.0, 10_000, 850),
+            Waypoint::new(0.0, 20.0, 0, 0),
+        ]);
+        let leg = haversine_distance(0.0, 0.0, 0.0, 10.0);
+        assert!((plan.remaining_distance(0.0, 0.0) - 2.0 * leg).abs() < 1.0);
+        assert!(!plan.is_last_leg());
+
+        plan.advance();
+        assert!(plan.is_last_leg());
+        assert!((plan.remaining_distance(0.0, 10.0) - leg).abs() < 1.0);
+
+        plan.advance();
+        plan.advance();
+        assert!(plan.active_waypoint().is_none());
+        assert_eq!(plan.remaining_distance(0.0, 20.0), 0.0);
+    }
+}
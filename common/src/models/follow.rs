@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+use super::tracking_data::{great_circle_point, Degrees, TrackingData};
+use super::FlightId;
+
+/// Where the map draws a flight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    pub latitude: Degrees,
+    pub longitude: Degrees,
+    pub heading: Degrees,
+}
+
+impl From<&TrackingData> for Pose {
+    fn from(tracking: &TrackingData) -> Self {
+        Self {
+            latitude: tracking.latitude,
+            longitude: tracking.longitude,
+            heading: tracking.heading,
+        }
+    }
+}
+
+/// Keeps the map centered on a selected flight. The positions of the flight arrive once per
+/// update interval, so instead of jumping to each one the camera glides from where it is
+/// drawn to the new position over the next interval, along the great circle, turning the
+/// plane by the shortest way.
+#[derive(Debug, Clone)]
+pub struct FollowCamera {
+    flight_id: FlightId,
+    from: Pose,
+    to: Pose,
+    /// When the camera started moving towards `to`.
+    started: Instant,
+    interval: Duration,
+}
+
+impl FollowCamera {
+    /// Starts following a flight at its current position.
+    ///
+    /// # Parameters
+    /// - `interval`: Time between the positions of the flight, e.g. the
+    ///   `tracking_update_interval_in_ms` of the interface.
+    pub fn new(
+        flight_id: FlightId,
+        tracking: &TrackingData,
+        interval: Duration,
+        now: Instant,
+    ) -> Self {
+        let pose = Pose::from(tracking);
+        Self {
+            flight_id,
+            from: pose,
+            to: pose,
+            started: now,
+            interval,
+        }
+    }
+
+    /// Returns the flight followed.
+    pub fn flight_id(&self) -> FlightId {
+        self.flight_id
+    }
+
+    /// Takes a new position of the flight, which the camera reaches an interval after `now`.
+    pub fn update(&mut self, tracking: &TrackingData, now: Instant) {
+        self.from = self.pose(now);
+        self.to = Pose::from(tracking);
+        self.started = now;
+    }
+
+    /// Returns where the map draws the flight at `now`, the center of the map.
+    pub fn pose(&self, now: Instant) -> Pose {
+        let fraction = if self.interval.is_zero() {
+            1.0
+        } else {
+            (now.saturating_duration_since(self.started).as_secs_f64()
+                / self.interval.as_secs_f64())
+            .min(1.0)
+        };
+        let (latitude, longitude) = great_circle_point(
+            self.from.latitude,
+            self.from.longitude,
+            self.to.latitude,
+            self.to.longitude,
+            fraction,
+        );
+        // El giro mas corto, de -180 a 180 grados
+        let turn = (self.to.heading - self.from.heading + 540.0).rem_euclid(360.0) - 180.0;
+        Pose {
+            latitude,
+            longitude,
+            heading: (self.from.heading + turn * fraction).rem_euclid(360.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::clock::WallClock;
+
+    fn at(latitude: Degrees, longitude: Degrees, heading: Degrees) -> TrackingData {
+        let mut tracking = TrackingData::empty(&WallClock);
+        (tracking.latitude, tracking.longitude, tracking.heading) = (latitude, longitude, heading);
+        tracking
+    }
+
+    #[test]
+    fn the_camera_glides_between_the_positions() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(1);
+        let mut camera = FollowCamera::new(3, &at(0.0, 0.0, 350.0), interval, start);
+        assert_eq!(camera.pose(start).longitude, 0.0);
+
+        camera.update(&at(0.0, 1.0, 10.0), start);
+        let halfway = camera.pose(start + interval / 2);
+        assert!((halfway.longitude - 0.5).abs() < 1e-9);
+        // Gira por el norte, no por el sur
+        assert!(halfway.heading.abs() < 1e-9 || (halfway.heading - 360.0).abs() < 1e-9);
+        let arrived = camera.pose(start + interval * 3);
+        assert!((arrived.longitude - 1.0).abs() < 1e-9);
+        assert!((arrived.heading - 10.0).abs() < 1e-9);
+
+        // Una posicion nueva a mitad de camino sigue desde donde se dibuja
+        camera.update(&at(0.0, 2.0, 10.0), start + interval * 3);
+        let later = start + interval * 3 + interval / 4;
+        assert!((camera.pose(later).longitude - 1.25).abs() < 1e-9);
+        assert_eq!(camera.flight_id(), 3);
+    }
+}
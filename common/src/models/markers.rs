@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+use super::tracking_data::{Degrees, TrackingData};
+use super::FlightId;
+
+/// Pixels of a map tile, the world being a single tile at zoom 0.
+const TILE_SIZE: f64 = 256.0;
+
+/// Latitude where the Web Mercator projection of the map tiles ends.
+const MAX_LATITUDE: Degrees = 85.051_128_78;
+
+/// A flight and where it falls on the projected map.
+type Placed<'a> = (FlightId, &'a TrackingData, (f64, f64));
+
+/// How the map draws the flights at each zoom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkerSettings {
+    /// Flights closer than this on the screen are drawn as a single cluster.
+    pub cluster_radius_px: f64,
+    /// From this zoom on the flights are never clustered.
+    pub cluster_below_zoom: f64,
+    /// From this zoom on the flights are drawn with their sprite, turned to their heading,
+    /// instead of a dot.
+    pub sprite_zoom: f64,
+    /// From this zoom on the sprites also show the flight and its altitude.
+    pub label_zoom: f64,
+}
+
+impl Default for MarkerSettings {
+    fn default() -> Self {
+        Self {
+            cluster_radius_px: 40.0,
+            cluster_below_zoom: 8.0,
+            sprite_zoom: 5.0,
+            label_zoom: 7.0,
+        }
+    }
+}
+
+/// How much of a flight the map draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Detail {
+    Dot,
+    Sprite,
+    Labeled,
+}
+
+/// What the map draws for the flights at a zoom.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Marker {
+    Flight {
+        flight_id: FlightId,
+        latitude: Degrees,
+        longitude: Degrees,
+        heading: Degrees,
+        detail: Detail,
+    },
+    /// Flights too close on the screen to tell apart, drawn at their center with their count.
+    Cluster {
+        latitude: Degrees,
+        longitude: Degrees,
+        flights: Vec<FlightId>,
+    },
+}
+
+/// Groups the flights that would overlap on the screen at `zoom` and decides how much of each
+/// one to draw, so the map only draws a marker per group and sprites and labels when they fit.
+/// The flights are put in a grid over the projected map with cells of `cluster_radius_px`.
+///
+/// # Parameters
+/// - `flights`: The id and position of each flight.
+/// - `zoom`: Zoom of the map, 0 showing the whole world in a tile.
+///
+/// # Returns
+/// The markers to draw, clusters first and then flights in order of id.
+pub fn markers<'a>(
+    flights: impl IntoIterator<Item = (FlightId, &'a TrackingData)>,
+    zoom: f64,
+    settings: &MarkerSettings,
+) -> Vec<Marker> {
+    let detail = if zoom >= settings.label_zoom {
+        Detail::Labeled
+    } else if zoom >= settings.sprite_zoom {
+        Detail::Sprite
+    } else {
+        Detail::Dot
+    };
+    let flight = |flight_id: FlightId, tracking: &TrackingData| Marker::Flight {
+        flight_id,
+        latitude: tracking.latitude,
+        longitude: tracking.longitude,
+        heading: tracking.heading,
+        detail,
+    };
+    if zoom >= settings.cluster_below_zoom {
+        let mut markers: Vec<(FlightId, Marker)> = flights
+            .into_iter()
+            .map(|(flight_id, tracking)| (flight_id, flight(flight_id, tracking)))
+            .collect();
+        markers.sort_by_key(|(flight_id, _)| *flight_id);
+        return markers.into_iter().map(|(_, marker)| marker).collect();
+    }
+
+    let world = TILE_SIZE * zoom.exp2();
+    let cell = settings.cluster_radius_px.max(1.0);
+    let mut grid: BTreeMap<(i64, i64), Vec<Placed>> = BTreeMap::new();
+    for (flight_id, tracking) in flights {
+        let (x, y) = project(tracking.latitude, tracking.longitude, world);
+        let key = ((x / cell).floor() as i64, (y / cell).floor() as i64);
+        grid.entry(key)
+            .or_default()
+            .push((flight_id, tracking, (x, y)));
+    }
+
+    let (mut clusters, mut singles) = (vec![], vec![]);
+    for (_, mut members) in grid {
+        members.sort_by_key(|(flight_id, _, _)| *flight_id);
+        if let [(flight_id, tracking, _)] = members[..] {
+            singles.push((flight_id, flight(flight_id, tracking)));
+            continue;
+        }
+        // El centro se toma sobre el mapa proyectado, donde la celda es un cuadrado
+        let count = members.len() as f64;
+        let x = members.iter().map(|(_, _, (x, _))| x).sum::<f64>() / count;
+        let y = members.iter().map(|(_, _, (_, y))| y).sum::<f64>() / count;
+        let (latitude, longitude) = unproject(x, y, world);
+        clusters.push(Marker::Cluster {
+            latitude,
+            longitude,
+            flights: members.iter().map(|(flight_id, _, _)| *flight_id).collect(),
+        });
+    }
+    singles.sort_by_key(|(flight_id, _)| *flight_id);
+    clusters.extend(singles.into_iter().map(|(_, marker)| marker));
+    clusters
+}
+
+/// Returns where a point falls on a Web Mercator map `world` pixels wide.
+fn project(latitude: Degrees, longitude: Degrees, world: f64) -> (f64, f64) {
+    let latitude = latitude.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (longitude + 180.0) / 360.0 * world;
+    let y = (1.0 - (latitude.tan() + 1.0 / latitude.cos()).ln() / PI) / 2.0 * world;
+    (x, y)
+}
+
+/// Returns the latitude and longitude of a pixel of a Web Mercator map `world` pixels wide.
+fn unproject(x: f64, y: f64, world: f64) -> (Degrees, Degrees) {
+    let latitude = (PI * (1.0 - 2.0 * y / world)).sinh().atan().to_degrees();
+    (latitude, x / world * 360.0 - 180.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::clock::WallClock;
+
+    fn at(latitude: Degrees, longitude: Degrees) -> TrackingData {
+        let mut tracking = TrackingData::empty(&WallClock);
+        (tracking.latitude, tracking.longitude) = (latitude, longitude);
+        tracking
+    }
+
+    #[test]
+    fn nearby_flights_are_clustered_when_zoomed_out() {
+        let settings = MarkerSettings::default();
+        let flights = [
+            (3, at(-34.82, -58.54)),
+            (1, at(-34.84, -58.50)),
+            (2, at(40.47, -3.56)),
+        ];
+        let positions = || flights.iter().map(|(id, tracking)| (*id, tracking));
+
+        let zoomed_out = markers(positions(), 3.0, &settings);
+        assert_eq!(zoomed_out.len(), 2);
+        let Marker::Cluster {
+            latitude,
+            longitude,
+            flights,
+        } = &zoomed_out[0]
+        else {
+            panic!("expected a cluster, got {:?}", zoomed_out[0]);
+        };
+        assert_eq!(flights, &[1, 3]);
+        assert!((latitude + 34.83).abs() < 0.01 && (longitude + 58.52).abs() < 0.01);
+        assert!(matches!(
+            zoomed_out[1],
+            Marker::Flight {
+                flight_id: 2,
+                detail: Detail::Dot,
+                ..
+            }
+        ));
+
+        // De cerca cada vuelo tiene su marcador, con su etiqueta
+        let zoomed_in = markers(positions(), 10.0, &settings);
+        let ids: Vec<_> = zoomed_in
+            .iter()
+            .map(|marker| match marker {
+                Marker::Flight {
+                    flight_id, detail, ..
+                } => (*flight_id, *detail),
+                Marker::Cluster { .. } => panic!("clustered at zoom 10"),
+            })
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                (1, Detail::Labeled),
+                (2, Detail::Labeled),
+                (3, Detail::Labeled)
+            ]
+        );
+    }
+
+    #[test]
+    fn the_projection_goes_back_to_the_same_point() {
+        let world = TILE_SIZE * 4.0;
+        let (x, y) = project(-34.82, -58.54, world);
+        let (latitude, longitude) = unproject(x, y, world);
+        assert!((latitude + 34.82).abs() < 1e-9 && (longitude + 58.54).abs() < 1e-9);
+        assert_eq!(project(0.0, 0.0, world), (world / 2.0, world / 2.0));
+    }
+}
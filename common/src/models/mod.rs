@@ -1,6 +1,25 @@
+pub mod aircraft_model;
 pub mod airplane;
 pub mod airport;
+pub mod airport_board;
+pub mod board;
+pub mod clock;
+pub mod event;
+pub mod fleet;
+pub mod flight_filter;
+pub mod flight_plan;
+pub mod follow;
+pub mod markers;
+pub mod proximity;
+pub mod replay;
+pub mod rng;
+pub mod schedule;
+pub mod simulation_pool;
 pub mod tracking_data;
 pub mod status;
+pub mod telemetry;
+pub mod tile_cache;
+pub mod track;
+pub mod weather;
 
 pub type FlightId = u32;
\ No newline at end of file
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+
+use super::tracking_data::{haversine_distance, Degrees, Meters, TrackingData};
+use super::FlightId;
+
+/// Kilometers in a degree of latitude.
+const KM_PER_DEGREE: f64 = 111.2;
+
+/// The `[separation]` section of the config: how close two flights in the air may get before
+/// an alert is raised, and how often it is checked.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SeparationConfig {
+    /// Smallest distance over the ground, 5 nautical miles by default.
+    pub horizontal_km: f64,
+    /// Smallest difference of altitude, 1,000 feet by default.
+    pub vertical_m: Meters,
+    /// Seconds of simulated time between checks.
+    pub check_every_seconds: u32,
+}
+
+impl Default for SeparationConfig {
+    fn default() -> Self {
+        Self {
+            horizontal_km: 9.26,
+            vertical_m: 300,
+            check_every_seconds: 10,
+        }
+    }
+}
+
+impl SeparationConfig {
+    /// Returns the problems of the section, if any.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if !self.horizontal_km.is_finite() || self.horizontal_km <= 0.0 {
+            problems.push(format!(
+                "separation horizontal_km {} must be positive",
+                self.horizontal_km
+            ));
+        }
+        if self.check_every_seconds == 0 {
+            problems.push("separation check_every_seconds must be at least 1".to_string());
+        }
+        problems
+    }
+}
+
+/// Two flights in the air closer than the separation, stored in the `proximity_alerts` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProximityAlert {
+    /// The flight with the lower id.
+    pub flight_id: FlightId,
+    pub other_flight_id: FlightId,
+    pub at: DateTime<Utc>,
+    /// Distance over the ground, in kilometers.
+    pub horizontal_km: f64,
+    pub vertical_m: Meters,
+    /// Where the first flight was.
+    pub latitude: Degrees,
+    pub longitude: Degrees,
+}
+
+impl ProximityAlert {
+    /// Builds the query that stores the alert. The alerts are partitioned by day, so the
+    /// interface reads those of a day at once, and clustered by time and flights.
+    pub fn generate_query(&self) -> String {
+        format!(
+            "INSERT INTO proximity_alerts (date, at, flight_id, other_flight_id, horizontal_km, vertical_m, latitude, longitude) \
+                VALUES ('{}', '{}', {}, {}, '{}', '{}', '{}', '{}');",
+            self.at.format("%Y-%m-%d"),
+            self.at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.flight_id,
+            self.other_flight_id,
+            self.horizontal_km,
+            self.vertical_m,
+            self.latitude,
+            self.longitude
+        )
+    }
+}
+
+/// Checks every `check_every_seconds` of simulated time which flights in the air lost their
+/// separation. The flights are put in a grid over latitude and longitude with cells as wide as
+/// the separation, so each one is only compared with those of the cells around it.
+#[derive(Debug, Clone)]
+pub struct ProximityMonitor {
+    config: SeparationConfig,
+    next_check: Option<DateTime<Utc>>,
+}
+
+impl ProximityMonitor {
+    pub fn new(config: SeparationConfig) -> Self {
+        Self {
+            config,
+            next_check: None,
+        }
+    }
+
+    /// Returns the pairs of flights in the air that are closer than the separation, if a check
+    /// is due at `now`, each pair once and in order of their ids.
+    ///
+    /// # Parameters
+    /// - `flights`: The id and position of each flight.
+    pub fn check<'a>(
+        &mut self,
+        now: DateTime<Utc>,
+        flights: impl IntoIterator<Item = (FlightId, &'a TrackingData)>,
+    ) -> Vec<ProximityAlert> {
+        if self.next_check.is_some_and(|next_check| now < next_check) {
+            return vec![];
+        }
+        self.next_check = Some(now + Duration::seconds(self.config.check_every_seconds as i64));
+
+        let airborne: Vec<(FlightId, &TrackingData)> = flights
+            .into_iter()
+            .filter(|(_, tracking)| !tracking.landed())
+            .collect();
+        let cell = self.config.horizontal_km / KM_PER_DEGREE;
+        let columns = (360.0 / cell).ceil() as i64;
+        let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, (_, tracking)) in airborne.iter().enumerate() {
+            grid.entry(grid_cell(tracking, cell, columns))
+                .or_default()
+                .push(i);
+        }
+
+        let mut alerts = vec![];
+        for (flight_id, tracking) in &airborne {
+            let (row, column) = grid_cell(tracking, cell, columns);
+            // Cerca de los polos un grado de longitud mide menos, hay que mirar mas columnas
+            let widest = ((tracking.latitude.abs() + cell).min(89.9))
+                .to_radians()
+                .cos();
+            let reach = ((1.0 / widest).ceil() as i64).min(columns / 2 + 1);
+            for neighbor_row in row - 1..=row + 1 {
+                for neighbor_column in column - reach..=column + reach {
+                    let key = (neighbor_row, neighbor_column.rem_euclid(columns));
+                    for &j in grid.get(&key).into_iter().flatten() {
+                        let (other_flight_id, other) = airborne[j];
+                        if other_flight_id <= *flight_id {
+                            continue;
+                        }
+                        if let Some(alert) =
+                            self.alert(*flight_id, tracking, other_flight_id, other, now)
+                        {
+                            alerts.push(alert);
+                        }
+                    }
+                }
+            }
+        }
+        alerts.sort_by_key(|alert| (alert.flight_id, alert.other_flight_id));
+        alerts.dedup_by_key(|alert| (alert.flight_id, alert.other_flight_id));
+        alerts
+    }
+
+    fn alert(
+        &self,
+        flight_id: FlightId,
+        tracking: &TrackingData,
+        other_flight_id: FlightId,
+        other: &TrackingData,
+        now: DateTime<Utc>,
+    ) -> Option<ProximityAlert> {
+        let vertical_m = tracking.altitude.abs_diff(other.altitude);
+        if vertical_m >= self.config.vertical_m {
+            return None;
+        }
+        let horizontal_km = haversine_distance(
+            tracking.latitude,
+            tracking.longitude,
+            other.latitude,
+            other.longitude,
+        );
+        if horizontal_km >= self.config.horizontal_km {
+            return None;
+        }
+        Some(ProximityAlert {
+            flight_id,
+            other_flight_id,
+            at: now,
+            horizontal_km,
+            vertical_m,
+            latitude: tracking.latitude,
+            longitude: tracking.longitude,
+        })
+    }
+}
+
+/// Returns the row and column of the grid where a plane is, the columns going around the
+/// globe.
+fn grid_cell(tracking: &TrackingData, cell: Degrees, columns: i64) -> (i64, i64) {
+    (
+        ((tracking.latitude + 90.0) / cell).floor() as i64,
+        (((tracking.longitude + 180.0) / cell).floor() as i64).rem_euclid(columns),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::clock::WallClock;
+    use crate::models::tracking_data::mode::Mode;
+
+    fn flying(latitude: Degrees, longitude: Degrees, altitude: Meters) -> TrackingData {
+        let mut tracking = TrackingData::empty(&WallClock);
+        (tracking.latitude, tracking.longitude, tracking.altitude) =
+            (latitude, longitude, altitude);
+        tracking.current_mode = Mode::Cruising;
+        tracking
+    }
+
+    #[test]
+    fn flights_closer_than_the_separation_raise_an_alert() {
+        let now: DateTime<Utc> = "2024-11-05T06:00:00Z".parse().unwrap();
+        let mut monitor = ProximityMonitor::new(SeparationConfig::default());
+        let mut on_ground = flying(-34.82, -58.54, 0);
+        on_ground.current_mode = Mode::OnGround;
+        let flights = [
+            (7, flying(-34.80, -58.50, 10_000)),
+            (3, flying(-34.82, -58.54, 10_100)),
+            // Lejos en altura
+            (4, flying(-34.81, -58.52, 11_000)),
+            // Lejos sobre el suelo
+            (5, flying(-30.0, -58.54, 10_000)),
+            (6, on_ground.clone()),
+            (8, on_ground),
+            // Del otro lado del antimeridiano
+            (10, flying(0.0, 179.99, 10_000)),
+            (9, flying(0.0, -179.99, 10_000)),
+        ];
+        let positions = || flights.iter().map(|(id, tracking)| (*id, tracking));
+
+        let alerts = monitor.check(now, positions());
+        let pairs: Vec<_> = alerts
+            .iter()
+            .map(|alert| (alert.flight_id, alert.other_flight_id))
+            .collect();
+        assert_eq!(pairs, [(3, 7), (9, 10)]);
+        assert_eq!(alerts[0].vertical_m, 100);
+        assert!(alerts[0].horizontal_km < 9.26);
+        assert!(alerts[0]
+            .generate_query()
+            .contains("VALUES ('2024-11-05', '2024-11-05T06:00:00.000Z', 3, 7, '"));
+
+        // Hasta el proximo control no se vuelve a mirar
+        assert!(monitor
+            .check(now + Duration::seconds(5), positions())
+            .is_empty());
+        assert_eq!(
+            monitor
+                .check(now + Duration::seconds(10), positions())
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn planes_near_the_poles_are_compared_across_columns() {
+        let now: DateTime<Utc> = "2024-11-05T06:00:00Z".parse().unwrap();
+        let mut monitor = ProximityMonitor::new(SeparationConfig::default());
+        // A 85 grados un decimo de grado de longitud son menos de un kilometro
+        let (first, second) = (flying(85.0, 10.0, 10_000), flying(85.0, 10.4, 10_000));
+        let alerts = monitor.check(now, [(1, &first), (2, &second)]);
+        assert_eq!(alerts.len(), 1);
+        assert!(SeparationConfig::default().problems().is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+
+use super::track::{Track, TrackPoint};
+use super::tracking_data::{mode::Mode, Liters, TrackingData};
+use super::FlightId;
+use crate::client_manager::ClientManager;
+
+/// The `[replay]` section of the config: the simulator replays the tracks of `flights` stored
+/// in `status_history` between the days `from` and `to`, both included, instead of simulating
+/// new flights.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ReplayConfig {
+    pub flights: Vec<FlightId>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// How many times faster than they were recorded the positions are replayed.
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+impl ReplayConfig {
+    /// Returns the problems of the replay, if any.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.flights.is_empty() {
+            problems.push("replay has no flights".to_string());
+        }
+        if self.to < self.from {
+            problems.push(format!(
+                "replay ends on {} before it starts on {}",
+                self.to, self.from
+            ));
+        }
+        if !self.speed.is_finite() || self.speed <= 0.0 {
+            problems.push(format!("replay speed {} must be positive", self.speed));
+        }
+        problems
+    }
+}
+
+/// Replays stored tracks: hands out the positions of the flights as the simulated time reaches
+/// them, to be stored like those of the simulated flights.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    tracks: Vec<Track>,
+    /// The next position of each track to hand out, in the same order.
+    next: Vec<usize>,
+}
+
+impl Replay {
+    pub fn new(tracks: Vec<Track>) -> Self {
+        let next = vec![0; tracks.len()];
+        Self { tracks, next }
+    }
+
+    /// Reads the tracks to replay from `status_history`.
+    ///
+    /// # Returns
+    /// The replay, or an error if a query fails or a row is invalid.
+    pub fn fetch(client: &mut ClientManager, config: &ReplayConfig) -> Result<Self, String> {
+        let tracks = config
+            .flights
+            .iter()
+            .map(|flight_id| Track::fetch(client, *flight_id, config.from, config.to))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(tracks))
+    }
+
+    /// Returns the time of the first position replayed, where the clock of the replay starts,
+    /// `None` if there are no positions.
+    pub fn start(&self) -> Option<DateTime<Utc>> {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.points.first())
+            .map(|point| point.at)
+            .min()
+    }
+
+    /// Returns whether every position was handed out.
+    pub fn is_finished(&self) -> bool {
+        self.tracks
+            .iter()
+            .zip(&self.next)
+            .all(|(track, next)| *next >= track.points.len())
+    }
+
+    /// Hands out the positions reached since the previous call, up to `now`.
+    ///
+    /// # Returns
+    /// The id, last position reached and fuel capacity of each flight that moved, as taken by
+    /// `TrackingData::generate_batch_query`.
+    pub fn due(&mut self, now: DateTime<Utc>) -> Vec<(FlightId, TrackingData, Liters)> {
+        let mut due = vec![];
+        for (track, next) in self.tracks.iter().zip(self.next.iter_mut()) {
+            let reached = track.points[*next..]
+                .iter()
+                .take_while(|point| point.at <= now)
+                .count();
+            if reached == 0 {
+                continue;
+            }
+            *next += reached;
+            let (tracking, max_fuel) = tracking_data(&track.points[*next - 1]);
+            due.push((track.flight_id, tracking, max_fuel));
+        }
+        due
+    }
+}
+
+/// Rebuilds the position of a flight from a row of its track.
+fn tracking_data(point: &TrackPoint) -> (TrackingData, Liters) {
+    // El combustible se guarda como `restante/maximo`
+    let (fuel_remaining, max_fuel) = point
+        .fuel
+        .split_once('/')
+        .map(|(remaining, max)| {
+            (
+                remaining.parse().unwrap_or_default(),
+                max.parse().unwrap_or_default(),
+            )
+        })
+        .unwrap_or_default();
+    let tracking = TrackingData {
+        last_update: point.at,
+        fuel_remaining,
+        latitude: point.latitude,
+        longitude: point.longitude,
+        heading: point.heading,
+        altitude: point.altitude,
+        speed: point.speed,
+        current_mode: point.mode.parse().unwrap_or(Mode::Unknown),
+        // El recorrido no guarda la llegada estimada
+        eta: None,
+    };
+    (tracking, max_fuel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 11, 5, 6, minute, 0).unwrap()
+    }
+
+    fn point(minute: u32, latitude: f64) -> TrackPoint {
+        TrackPoint {
+            at: at(minute),
+            latitude,
+            longitude: -58.5,
+            altitude: 9000,
+            speed: 850,
+            heading: 45.0,
+            mode: "cruising".to_string(),
+            fuel: "900/1000".to_string(),
+        }
+    }
+
+    #[test]
+    fn positions_are_replayed_as_the_time_reaches_them() {
+        let mut replay = Replay::new(vec![
+            Track::new(3, vec![point(1, -34.8), point(2, -34.7), point(3, -34.6)]),
+            Track::new(4, vec![point(2, -20.0)]),
+        ]);
+        assert_eq!(replay.start(), Some(at(1)));
+
+        let due = replay.due(at(1));
+        assert_eq!(due.len(), 1);
+        let (flight_id, tracking, max_fuel) = &due[0];
+        assert_eq!((*flight_id, tracking.latitude, *max_fuel), (3, -34.8, 1000));
+        assert_eq!(tracking.fuel_remaining, 900);
+        assert_eq!(tracking.current_mode, Mode::Cruising);
+        assert_eq!(tracking.last_update, at(1));
+
+        // Si el reloj salta varias posiciones se entrega la ultima
+        let due = replay.due(at(3));
+        let positions: Vec<_> = due
+            .iter()
+            .map(|(flight_id, tracking, _)| (*flight_id, tracking.latitude))
+            .collect();
+        assert_eq!(positions, [(3, -34.6), (4, -20.0)]);
+        assert!(replay.is_finished());
+        assert!(replay.due(at(4)).is_empty());
+    }
+
+    #[test]
+    fn replays_need_flights_and_a_valid_range() {
+        let replay: ReplayConfig = toml::from_str(
+            r#"
+flights = [3]
+from = "2024-11-05"
+to = "2024-11-06"
+"#,
+        )
+        .unwrap();
+        assert_eq!(replay.speed, 1.0);
+        assert!(replay.problems().is_empty());
+
+        let config = ReplayConfig {
+            flights: vec![],
+            from: NaiveDate::from_ymd_opt(2024, 11, 5).unwrap(),
+            to: NaiveDate::from_ymd_opt(2024, 11, 4).unwrap(),
+            speed: 0.0,
+        };
+        assert_eq!(config.problems().len(), 3);
+    }
+}
@@ -0,0 +1,20 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::FlightId;
+
+/// The random number generator of the simulation. Runs started with the same seed replay the
+/// same scenario.
+pub type SimulationRng = StdRng;
+
+/// Returns the seed of a run: the one of the config, or a random one if there is none, which
+/// should be logged so that the run can be replayed.
+pub fn run_seed(configured: Option<u64>) -> u64 {
+    configured.unwrap_or_else(rand::random)
+}
+
+/// Returns the generator of a flight, derived from the seed of the run. Each flight has its
+/// own sequence, so the scenario doesn't depend on the order the flights are simulated in.
+pub fn flight_rng(run_seed: u64, flight_id: FlightId) -> SimulationRng {
+    // Mezcla el id para que los vuelos consecutivos no tengan semillas parecidas
+    SimulationRng::seed_from_u64(run_seed ^ (flight_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
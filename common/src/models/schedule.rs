@@ -0,0 +1,555 @@
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use serde::Deserialize;
+
+use super::aircraft_model::AircraftModel;
+use super::airplane::Airplane;
+use super::airport::Airport;
+use super::event::{FlightEvent, FlightEventKind};
+use super::flight_plan::FlightPlan;
+use super::rng::{flight_rng, SimulationRng};
+use super::status::Status;
+use super::tracking_data::{Liters, TrackingData};
+use super::weather::Weather;
+use super::FlightId;
+use crate::frame::messages::prepared::quote;
+
+const TIME_FORMAT: &str = "%H:%M";
+
+/// A recurring flight, set in the `timetables` of the config: every day from `origin` to
+/// `destination` at `first_departure`, UTC, and then every `every_minutes` until midnight,
+/// flown by an airplane of the `aircraft` model.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Timetable {
+    /// IATA code of the origin airport.
+    pub origin: String,
+    /// IATA code of the destination airport.
+    pub destination: String,
+    /// Time of the first departure of each day, as `HH:MM`.
+    pub first_departure: String,
+    pub every_minutes: u32,
+    pub aircraft: String,
+}
+
+impl Timetable {
+    /// Returns the first departure at or after `at`.
+    ///
+    /// # Returns
+    /// The departure, `None` if `first_departure` or `every_minutes` are invalid.
+    pub fn next_departure(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let first = NaiveTime::parse_from_str(&self.first_departure, TIME_FORMAT).ok()?;
+        if self.every_minutes == 0 {
+            return None;
+        }
+        let every = Duration::minutes(self.every_minutes as i64);
+
+        let today = at.date_naive();
+        let mut departure = today.and_time(first).and_utc();
+        while departure < at {
+            departure += every;
+            if departure.date_naive() != today {
+                // Los vuelos de hoy ya salieron, sigue el primero de manana
+                return Some((today + Duration::days(1)).and_time(first).and_utc());
+            }
+        }
+        Some(departure)
+    }
+
+    /// Checks the timetable against the airports and airplanes of the config.
+    ///
+    /// # Returns
+    /// The description of every problem found.
+    pub fn problems(&self, airports: &[Airport], airplanes: &[Airplane]) -> Vec<String> {
+        let mut problems = vec![];
+        let route = format!("{}-{}", self.origin, self.destination);
+        for iata in [&self.origin, &self.destination] {
+            if Airport::by_iata(airports, iata).is_none() {
+                problems.push(format!(
+                    "timetable {} uses the unknown airport {}",
+                    route, iata
+                ));
+            }
+        }
+        if !airplanes
+            .iter()
+            .any(|airplane| airplane.model == self.aircraft)
+        {
+            problems.push(format!(
+                "timetable {} uses the unknown aircraft {}",
+                route, self.aircraft
+            ));
+        }
+        if NaiveTime::parse_from_str(&self.first_departure, TIME_FORMAT).is_err() {
+            problems.push(format!(
+                "timetable {} has first_departure {} instead of HH:MM",
+                route, self.first_departure
+            ));
+        }
+        if self.every_minutes == 0 {
+            problems.push(format!(
+                "timetable {} must have every_minutes of at least 1",
+                route
+            ));
+        }
+        problems
+    }
+}
+
+/// A flight created from a timetable.
+#[derive(Debug, Clone)]
+pub struct ScheduledFlight {
+    pub flight_id: FlightId,
+    pub origin: Airport,
+    pub destination: Airport,
+    pub airplane: Airplane,
+    /// The profile of the model of the airplane.
+    pub aircraft: AircraftModel,
+    pub departure: DateTime<Utc>,
+}
+
+impl ScheduledFlight {
+    /// Builds the queries that add the flight to the `flights` and `status` tables.
+    pub fn generate_queries(&self) -> Vec<String> {
+        vec![
+            format!(
+                "INSERT INTO flights (flight_id, origin, destination, airplane, departure) \
+                    VALUES ({}, {}, {}, '{}', '{}');",
+                self.flight_id,
+                quote(&self.origin.iata),
+                quote(&self.destination.iata),
+                self.airplane.id,
+                self.departure.to_rfc3339()
+            ),
+            Status::Scheduled {
+                departing_time: self.departure,
+            }
+            .generate_query(self.flight_id),
+        ]
+    }
+
+    /// Starts the simulation of the flight, on the ground at its origin with the tank full and
+    /// a plan straight to its destination.
+    ///
+    /// # Parameters
+    /// - `run_seed`: The seed of the run, from which that of the flight is derived.
+    pub fn depart(&self, run_seed: u64) -> FlightSimulation {
+        let mut rng = flight_rng(run_seed, self.flight_id);
+        let max_fuel = self.airplane.fuel_capacity(&self.aircraft);
+        let tracking = TrackingData::random_init(
+            &Status::Boarding {
+                departing_time: self.departure,
+            },
+            &self.departure,
+            &mut rng,
+            max_fuel,
+            &self.origin,
+            &self.destination,
+            &self.aircraft,
+        );
+        let plan = FlightPlan::direct(self.destination.latitude, self.destination.longitude);
+        FlightSimulation {
+            flight_id: self.flight_id,
+            tracking,
+            plan,
+            aircraft: self.aircraft.clone(),
+            max_fuel,
+            rng,
+        }
+    }
+}
+
+/// A flight being simulated, with its own random number generator.
+#[derive(Debug, Clone)]
+pub struct FlightSimulation {
+    pub flight_id: FlightId,
+    pub tracking: TrackingData,
+    pub plan: FlightPlan,
+    pub aircraft: AircraftModel,
+    /// Fuel capacity of the airplane.
+    pub max_fuel: Liters,
+    rng: SimulationRng,
+}
+
+impl FlightSimulation {
+    /// Advances the flight by `elapsed` of simulated time, usually what `SimulationClock::tick`
+    /// returns.
+    ///
+    /// # Returns
+    /// The event of the flight in the step, if any, to store in `flight_events`.
+    pub fn step(&mut self, weather: &Weather, elapsed: Duration) -> Option<FlightEvent> {
+        let before = self.tracking.current_mode.clone();
+        self.tracking.simulate(
+            &mut self.plan,
+            weather,
+            &self.aircraft,
+            elapsed,
+            &mut self.rng,
+        );
+        let kind = FlightEventKind::from_transition(&before, &self.tracking.current_mode)?;
+        Some(FlightEvent::new(
+            self.flight_id,
+            kind,
+            &self.tracking,
+            &mut self.rng,
+        ))
+    }
+
+    /// Sends the flight straight to another airport from where it is.
+    ///
+    /// # Returns
+    /// The event of the diversion, to store in `flight_events`.
+    pub fn divert(&mut self, airport: &Airport) -> FlightEvent {
+        self.plan = FlightPlan::direct(airport.latitude, airport.longitude);
+        FlightEvent::new(
+            self.flight_id,
+            FlightEventKind::Diverted,
+            &self.tracking,
+            &mut self.rng,
+        )
+    }
+
+    /// Records that another flight got closer than the separation, as `ProximityMonitor::check`
+    /// found.
+    ///
+    /// # Returns
+    /// The event of the alert, to store in `flight_events`.
+    pub fn proximity_alert(&mut self) -> FlightEvent {
+        FlightEvent::new(
+            self.flight_id,
+            FlightEventKind::ProximityAlert,
+            &self.tracking,
+            &mut self.rng,
+        )
+    }
+}
+
+/// Creates the flights of the timetables as their departures come.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    timetables: Vec<Timetable>,
+    airports: Vec<Airport>,
+    airplanes: Vec<Airplane>,
+    aircraft_models: Vec<AircraftModel>,
+    /// The next departure of each timetable, in the same order.
+    next_departures: Vec<DateTime<Utc>>,
+    next_flight_id: FlightId,
+}
+
+impl Scheduler {
+    /// Creates a scheduler whose first departures are the ones at or after `now`.
+    ///
+    /// # Parameters
+    /// - `aircraft_models`: The profiles of the models, the airplanes of other models fly
+    ///   with the generic one.
+    /// - `first_flight_id`: The id of the first flight created, the next ones follow it.
+    ///
+    /// # Returns
+    /// The scheduler, or the problems of the timetables.
+    pub fn new(
+        timetables: Vec<Timetable>,
+        airports: Vec<Airport>,
+        airplanes: Vec<Airplane>,
+        aircraft_models: Vec<AircraftModel>,
+        now: DateTime<Utc>,
+        first_flight_id: FlightId,
+    ) -> Result<Self, Vec<String>> {
+        let problems: Vec<String> = timetables
+            .iter()
+            .flat_map(|timetable| timetable.problems(&airports, &airplanes))
+            .collect();
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        let next_departures = timetables
+            .iter()
+            .filter_map(|timetable| timetable.next_departure(now))
+            .collect();
+        Ok(Self {
+            timetables,
+            airports,
+            airplanes,
+            aircraft_models,
+            next_departures,
+            next_flight_id: first_flight_id,
+        })
+    }
+
+    /// Returns when the next flight departs, `None` without timetables.
+    pub fn next_departure(&self) -> Option<DateTime<Utc>> {
+        self.next_departures.iter().min().copied()
+    }
+
+    /// Creates the flights that depart at or before `now`, in order of departure, and moves
+    /// each timetable on to its next departure.
+    pub fn due(&mut self, now: DateTime<Utc>) -> Vec<ScheduledFlight> {
+        let mut flights = vec![];
+        for (timetable, next) in self.timetables.iter().zip(&mut self.next_departures) {
+            while *next <= now {
+                let airplane = self
+                    .airplanes
+                    .iter()
+                    .find(|airplane| airplane.model == timetable.aircraft);
+                let origin = Airport::by_iata(&self.airports, &timetable.origin);
+                let destination = Airport::by_iata(&self.airports, &timetable.destination);
+                if let (Some(airplane), Some(origin), Some(destination)) =
+                    (airplane, origin, destination)
+                {
+                    flights.push(ScheduledFlight {
+                        flight_id: 0,
+                        origin: origin.clone(),
+                        destination: destination.clone(),
+                        airplane: airplane.clone(),
+                        aircraft: AircraftModel::by_name(&self.aircraft_models, &airplane.model),
+                        departure: *next,
+                    });
+                }
+                match timetable.next_departure(*next + Duration::seconds(1)) {
+                    Some(departure) => *next = departure,
+                    None => break,
+                }
+            }
+        }
+
+        // Los ids se asignan en orden de salida
+        flights.sort_by_key(|flight| flight.departure);
+        for flight in &mut flights {
+            flight.flight_id = self.next_flight_id;
+            self.next_flight_id += 1;
+        }
+        flights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn airport(id: u16, iata: &str, latitude: f64, longitude: f64) -> Airport {
+        Airport {
+            id,
+            iata: iata.to_string(),
+            latitude,
+            longitude,
+            ..Airport::unknown()
+        }
+    }
+
+    fn timetable(first_departure: &str, every_minutes: u32) -> Timetable {
+        Timetable {
+            origin: "EZE".to_string(),
+            destination: "MAD".to_string(),
+            first_departure: first_departure.to_string(),
+            every_minutes,
+            aircraft: "A330".to_string(),
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 11, 5, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn departures_repeat_until_midnight() {
+        let timetable = timetable("06:00", 480);
+        assert_eq!(timetable.next_departure(at(0, 0)), Some(at(6, 0)));
+        assert_eq!(timetable.next_departure(at(6, 0)), Some(at(6, 0)));
+        assert_eq!(timetable.next_departure(at(6, 1)), Some(at(14, 0)));
+        assert_eq!(
+            timetable.next_departure(at(22, 1)),
+            Some(at(6, 0) + Duration::days(1))
+        );
+        assert_eq!(
+            Timetable {
+                every_minutes: 0,
+                ..timetable
+            }
+            .next_departure(at(0, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn the_scheduler_creates_the_flights_as_they_depart() {
+        let airports = vec![
+            airport(1, "EZE", -34.82, -58.54),
+            airport(2, "MAD", 40.47, -3.56),
+        ];
+        let airplanes = vec![Airplane {
+            id: 7,
+            model: "A330".to_string(),
+            max_fuel: 0,
+        }];
+        let a330 = AircraftModel {
+            name: "A330".to_string(),
+            fuel_capacity: 139_000,
+            ..AircraftModel::generic()
+        };
+        let mut scheduler = Scheduler::new(
+            vec![timetable("06:00", 480), timetable("10:00", 720)],
+            airports.clone(),
+            airplanes.clone(),
+            vec![a330.clone()],
+            at(5, 0),
+            100,
+        )
+        .unwrap();
+        assert_eq!(scheduler.next_departure(), Some(at(6, 0)));
+        assert!(scheduler.due(at(5, 59)).is_empty());
+
+        let flights = scheduler.due(at(15, 0));
+        let departures: Vec<_> = flights.iter().map(|flight| flight.departure).collect();
+        assert_eq!(departures, vec![at(6, 0), at(10, 0), at(14, 0)]);
+        assert_eq!(flights[2].flight_id, 102);
+        assert_eq!(scheduler.next_departure(), Some(at(22, 0)));
+
+        let queries = flights[0].generate_queries();
+        assert!(queries[0].starts_with("INSERT INTO flights"));
+        assert!(queries[0].contains("'EZE', 'MAD', '7'"));
+        let simulation = flights[0].depart(42);
+        assert_eq!(simulation.aircraft, a330);
+        assert_eq!(
+            (
+                simulation.tracking.latitude,
+                simulation.tracking.fuel_remaining
+            ),
+            (-34.82, 139_000)
+        );
+        assert_eq!(simulation.plan.destination().unwrap().latitude, 40.47);
+
+        let problems = Scheduler::new(
+            vec![Timetable {
+                destination: "COR".to_string(),
+                aircraft: "B747".to_string(),
+                ..timetable("6am", 60)
+            }],
+            airports,
+            airplanes,
+            vec![],
+            at(5, 0),
+            1,
+        )
+        .unwrap_err();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].contains("unknown airport COR"));
+    }
+
+    #[test]
+    fn runs_with_the_same_seed_replay_the_same_flights() {
+        let flight = ScheduledFlight {
+            flight_id: 3,
+            origin: airport(1, "EZE", -34.82, -58.54),
+            destination: airport(2, "MAD", 40.47, -3.56),
+            airplane: Airplane {
+                id: 7,
+                model: "A330".to_string(),
+                max_fuel: 139_000,
+            },
+            aircraft: AircraftModel::generic(),
+            departure: at(6, 0),
+        };
+        let run = |seed: u64| {
+            let mut simulation = flight.depart(seed);
+            (0..200)
+                .map(|_| {
+                    simulation.step(&Weather::default(), Duration::minutes(1));
+                    let tracking = &simulation.tracking;
+                    (
+                        tracking.latitude,
+                        tracking.longitude,
+                        tracking.altitude,
+                        tracking.fuel_remaining,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+
+        // Cada vuelo tiene su propia secuencia, no importa el orden en que se simulen
+        let other = ScheduledFlight {
+            flight_id: 4,
+            ..flight.clone()
+        };
+        let (mut first, mut second) = (flight.depart(42), other.depart(42));
+        second.step(&Weather::default(), Duration::minutes(1));
+        first.step(&Weather::default(), Duration::minutes(1));
+        let mut alone = flight.depart(42);
+        alone.step(&Weather::default(), Duration::minutes(1));
+        assert_eq!(first.tracking.altitude, alone.tracking.altitude);
+        assert_eq!(first.tracking.fuel_remaining, alone.tracking.fuel_remaining);
+    }
+
+    #[test]
+    fn flights_are_stamped_with_the_simulated_time() {
+        let flight = ScheduledFlight {
+            flight_id: 3,
+            origin: airport(1, "EZE", -34.82, -58.54),
+            destination: airport(2, "MAD", 40.47, -3.56),
+            airplane: Airplane {
+                id: 7,
+                model: "A330".to_string(),
+                max_fuel: 139_000,
+            },
+            aircraft: AircraftModel::generic(),
+            departure: at(6, 0),
+        };
+        let mut simulation = flight.depart(42);
+        assert_eq!(simulation.tracking.last_update, at(6, 0));
+        simulation.step(&Weather::default(), Duration::minutes(90));
+        assert_eq!(simulation.tracking.last_update, at(7, 30));
+        assert!(simulation
+            .tracking
+            .generate_query(3, 139_000)
+            .contains("'2024-11-05T07:30:00+00:00'"));
+
+        let mut rng = flight_rng(42, 3);
+        for _ in 0..20 {
+            match Status::random_init(&at(6, 0), &mut rng) {
+                Status::Landed { arrived_at } => assert!(arrived_at <= at(6, 0)),
+                Status::Scheduled { departing_time } | Status::Boarding { departing_time } => {
+                    assert!(departing_time >= at(6, 0))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn flights_leave_a_timeline_of_events() {
+        let flight = ScheduledFlight {
+            flight_id: 3,
+            origin: airport(1, "EZE", -34.82, -58.54),
+            destination: airport(2, "MVD", -34.84, -56.03),
+            airplane: Airplane {
+                id: 7,
+                model: "A330".to_string(),
+                max_fuel: 139_000,
+            },
+            aircraft: AircraftModel::generic(),
+            departure: at(6, 0),
+        };
+        let mut simulation = flight.depart(42);
+        let events: Vec<FlightEvent> = (0..600)
+            .filter_map(|_| simulation.step(&Weather::default(), Duration::minutes(1)))
+            .collect();
+        assert_eq!(
+            events.iter().map(|event| event.kind).collect::<Vec<_>>(),
+            vec![
+                FlightEventKind::Takeoff,
+                FlightEventKind::ReachedCruise,
+                FlightEventKind::DescentStarted,
+                FlightEventKind::Landed,
+                FlightEventKind::ArrivedAtGate
+            ]
+        );
+        assert!(events.windows(2).all(|pair| pair[0].at < pair[1].at));
+        assert!(events.iter().all(|event| event.flight_id == 3));
+
+        let mut simulation = flight.depart(42);
+        simulation.step(&Weather::default(), Duration::minutes(10));
+        let diverted = simulation.divert(&airport(3, "AEP", -34.56, -58.42));
+        assert_eq!(diverted.kind, FlightEventKind::Diverted);
+        assert_eq!(diverted.at, at(6, 10));
+        assert_eq!(simulation.plan.destination().unwrap().latitude, -34.56);
+    }
+}
@@ -0,0 +1,347 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chrono::Duration;
+
+use super::schedule::FlightSimulation;
+use super::tracking_data::{mode::Mode, TrackingData};
+use super::weather::Weather;
+use super::FlightId;
+
+/// How many statements the writers sent, failed to send, and didn't take because the queue
+/// was full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteStats {
+    pub written: usize,
+    pub failed: usize,
+    pub dropped: usize,
+}
+
+#[derive(Debug, Default)]
+struct WriteCounters {
+    written: AtomicUsize,
+    failed: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+/// A bounded queue of statements, written by a fixed set of writer threads. Pushing never
+/// blocks: when the database can't keep up and the queue is full, the statement is dropped and
+/// counted, so the simulation keeps its pace. The positions are written again on the next tick.
+#[derive(Debug, Clone)]
+pub struct WriteQueue {
+    sender: SyncSender<String>,
+    counters: Arc<WriteCounters>,
+}
+
+impl WriteQueue {
+    /// Starts the writers. They stop once every clone of the queue is dropped and the
+    /// statements queued are written.
+    ///
+    /// # Parameters
+    /// - `writers`: Number of writer threads.
+    /// - `capacity`: Statements that may wait to be written.
+    /// - `connect`: Called once by each writer to get what sends its statements, e.g. a
+    ///   `ClientManager::with_pool` on the pool of the app.
+    pub fn start<F, S>(writers: usize, capacity: usize, connect: F) -> Self
+    where
+        F: Fn() -> S,
+        S: FnMut(String) -> Result<String, String> + Send + 'static,
+    {
+        let (sender, receiver) = sync_channel::<String>(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let counters = Arc::new(WriteCounters::default());
+        for i in 0..writers.max(1) {
+            let receiver = Arc::clone(&receiver);
+            let counters = Arc::clone(&counters);
+            let mut send = connect();
+            let writer = thread::Builder::new()
+                .name(format!("writer-{}", i))
+                .spawn(move || loop {
+                    let statement = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    // La cola se cerro
+                    let Ok(statement) = statement else {
+                        return;
+                    };
+                    let counter = match send(statement) {
+                        Ok(_) => &counters.written,
+                        Err(_) => &counters.failed,
+                    };
+                    counter.fetch_add(1, Ordering::Relaxed);
+                });
+            if let Err(e) = writer {
+                eprintln!("Error starting writer {}: {}", i, e);
+            }
+        }
+        Self { sender, counters }
+    }
+
+    /// Queues a statement without waiting.
+    ///
+    /// # Returns
+    /// Whether it was queued, `false` if the queue is full or the writers stopped.
+    pub fn push(&self, statement: String) -> bool {
+        match self.sender.try_send(statement) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    pub fn stats(&self) -> WriteStats {
+        WriteStats {
+            written: self.counters.written.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+enum Command {
+    Depart(Box<FlightSimulation>),
+    Step(Arc<Weather>, Duration),
+}
+
+/// Simulates the flights on a fixed set of worker threads, each one with its share of the
+/// flights, and hands the statements of each step to a `WriteQueue`, so the simulation loop
+/// only waits for the physics and never for the database.
+#[derive(Debug)]
+pub struct SimulationPool {
+    workers: Vec<Sender<Command>>,
+    positions: Receiver<Vec<(FlightId, TrackingData)>>,
+}
+
+impl SimulationPool {
+    /// Starts the workers.
+    ///
+    /// # Parameters
+    /// - `size`: Number of worker threads.
+    /// - `writes`: Where the workers queue the statements of each step.
+    pub fn new(size: usize, writes: WriteQueue) -> Self {
+        let (positions_sender, positions) = channel();
+        let mut workers = Vec::with_capacity(size);
+        for i in 0..size.max(1) {
+            let (sender, commands) = channel();
+            let (writes, positions) = (writes.clone(), positions_sender.clone());
+            let worker = thread::Builder::new()
+                .name(format!("simulation-{}", i))
+                .spawn(move || run_worker(commands, writes, positions));
+            match worker {
+                Ok(_) => workers.push(sender),
+                Err(e) => eprintln!("Error starting simulation worker {}: {}", i, e),
+            }
+        }
+        Self { workers, positions }
+    }
+
+    /// Hands a flight that departs to the worker of its id.
+    pub fn depart(&self, simulation: FlightSimulation) -> Result<(), String> {
+        if self.workers.is_empty() {
+            return Err("The simulation pool has no workers".to_string());
+        }
+        let worker = &self.workers[simulation.flight_id as usize % self.workers.len()];
+        worker
+            .send(Command::Depart(Box::new(simulation)))
+            .map_err(|_| "A simulation worker stopped".to_string())
+    }
+
+    /// Advances every flight by `elapsed` of simulated time. Each worker queues the positions,
+    /// track and events of its flights, and stops simulating those that reached the gate.
+    ///
+    /// # Returns
+    /// The position of each flight after the step, in order of id, e.g. for
+    /// `ProximityMonitor::check`; an error if a worker stopped.
+    pub fn tick(
+        &self,
+        weather: &Weather,
+        elapsed: Duration,
+    ) -> Result<Vec<(FlightId, TrackingData)>, String> {
+        let weather = Arc::new(weather.clone());
+        for worker in &self.workers {
+            worker
+                .send(Command::Step(Arc::clone(&weather), elapsed))
+                .map_err(|_| "A simulation worker stopped".to_string())?;
+        }
+        let mut positions = Vec::new();
+        for _ in &self.workers {
+            let worker_positions = self
+                .positions
+                .recv()
+                .map_err(|_| "A simulation worker stopped".to_string())?;
+            positions.extend(worker_positions);
+        }
+        positions.sort_by_key(|(flight_id, _)| *flight_id);
+        Ok(positions)
+    }
+}
+
+/// Runs the commands of a worker until the pool is dropped.
+fn run_worker(
+    commands: Receiver<Command>,
+    writes: WriteQueue,
+    positions: Sender<Vec<(FlightId, TrackingData)>>,
+) {
+    let mut flights: Vec<FlightSimulation> = Vec::new();
+    while let Ok(command) = commands.recv() {
+        match command {
+            Command::Depart(simulation) => flights.push(*simulation),
+            Command::Step(weather, elapsed) => {
+                for simulation in flights.iter_mut() {
+                    if let Some(event) = simulation.step(&weather, elapsed) {
+                        writes.push(event.generate_query());
+                    }
+                }
+                let tick = || {
+                    flights.iter().map(|simulation| {
+                        (
+                            simulation.flight_id,
+                            &simulation.tracking,
+                            simulation.max_fuel,
+                        )
+                    })
+                };
+                for statement in TrackingData::generate_batch_query(tick())
+                    .into_iter()
+                    .chain(TrackingData::generate_history_query(tick()))
+                {
+                    writes.push(statement);
+                }
+                let step: Vec<_> = flights
+                    .iter()
+                    .map(|simulation| (simulation.flight_id, simulation.tracking.clone()))
+                    .collect();
+                // Los vuelos que llegaron a la puerta ya guardaron su ultima posicion
+                flights.retain(|simulation| simulation.tracking.current_mode != Mode::AtGate);
+                if positions.send(step).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::aircraft_model::AircraftModel;
+    use crate::models::airplane::Airplane;
+    use crate::models::airport::Airport;
+    use crate::models::schedule::ScheduledFlight;
+    use std::time::Duration as StdDuration;
+
+    fn flight(flight_id: FlightId) -> FlightSimulation {
+        ScheduledFlight {
+            flight_id,
+            origin: Airport {
+                latitude: -34.82,
+                longitude: -58.54,
+                ..Airport::unknown()
+            },
+            destination: Airport {
+                latitude: -34.84,
+                longitude: -56.03,
+                ..Airport::unknown()
+            },
+            airplane: Airplane {
+                id: 7,
+                model: "A330".to_string(),
+                max_fuel: 139_000,
+            },
+            aircraft: AircraftModel::generic(),
+            departure: "2024-11-05T06:00:00Z".parse().unwrap(),
+        }
+        .depart(42)
+    }
+
+    #[test]
+    fn workers_simulate_the_flights_like_a_single_loop() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let writes = {
+            let written = Arc::clone(&written);
+            WriteQueue::start(2, 100, move || {
+                let written = Arc::clone(&written);
+                move |statement: String| {
+                    written.lock().unwrap().push(statement);
+                    Ok(String::new())
+                }
+            })
+        };
+        let pool = SimulationPool::new(3, writes.clone());
+        let mut alone: Vec<FlightSimulation> = (1..=5).map(flight).collect();
+        for flight_id in 1..=5 {
+            pool.depart(flight(flight_id)).unwrap();
+        }
+
+        let positions = pool
+            .tick(&Weather::default(), Duration::minutes(1))
+            .unwrap();
+        for simulation in alone.iter_mut() {
+            simulation.step(&Weather::default(), Duration::minutes(1));
+        }
+        let ids: Vec<_> = positions.iter().map(|(flight_id, _)| *flight_id).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+        for ((_, tracking), simulation) in positions.iter().zip(&alone) {
+            assert_eq!(tracking.altitude, simulation.tracking.altitude);
+        }
+
+        // Cada worker guarda sus posiciones, su recorrido y el despegue de sus vuelos
+        let deadline = std::time::Instant::now() + StdDuration::from_secs(5);
+        while writes.stats().written < 3 * 2 + 5 && std::time::Instant::now() < deadline {
+            thread::sleep(StdDuration::from_millis(10));
+        }
+        assert_eq!(
+            writes.stats(),
+            WriteStats {
+                written: 11,
+                failed: 0,
+                dropped: 0
+            }
+        );
+        let written = written.lock().unwrap();
+        assert_eq!(
+            written
+                .iter()
+                .filter(|q| q.starts_with("INSERT INTO status "))
+                .count(),
+            3
+        );
+        assert_eq!(
+            written.iter().filter(|q| q.contains("'takeoff'")).count(),
+            5
+        );
+    }
+
+    #[test]
+    fn a_slow_database_does_not_stall_the_simulation() {
+        let (release, blocked) = channel::<()>();
+        let blocked = Arc::new(Mutex::new(blocked));
+        let writes = WriteQueue::start(1, 1, move || {
+            let blocked = Arc::clone(&blocked);
+            // El nodo no contesta hasta que se lo libera
+            move |_: String| {
+                let _ = blocked
+                    .lock()
+                    .unwrap()
+                    .recv_timeout(StdDuration::from_secs(5));
+                Ok(String::new())
+            }
+        });
+        let pool = SimulationPool::new(1, writes.clone());
+        pool.depart(flight(1)).unwrap();
+
+        let started = std::time::Instant::now();
+        for _ in 0..10 {
+            pool.tick(&Weather::default(), Duration::minutes(1))
+                .unwrap();
+        }
+        assert!(started.elapsed() < StdDuration::from_secs(2));
+        assert!(writes.stats().dropped > 0);
+        drop(release);
+    }
+}
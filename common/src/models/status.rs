@@ -1,7 +1,7 @@
 use chrono::{DateTime, Duration, Utc};
-use rand::{rng, Rng};
+use rand::Rng;
 
-use super::FlightId;
+use super::{clock::SimClock, FlightId};
 
 const STATUS_VARIANTS: usize = 6;
 
@@ -39,9 +39,8 @@ impl Status {
         )
     }
 
-    pub fn random_init() -> Self {
-        let mut rng = rng();
-
+    pub fn random_init(clock: &impl SimClock, rng: &mut impl Rng) -> Self {
+        let now = clock.now();
         // Calculate percentage ranges based on the number of variants
         let cancelled_range = 0..(100 / STATUS_VARIANTS); // E.g., 20% if there are 5 variants
         let delayed_range = cancelled_range.end..(cancelled_range.end + 100 / STATUS_VARIANTS);
@@ -58,13 +57,13 @@ impl Status {
             x if cancelled_range.contains(&x) => Status::Cancelled,
             x if delayed_range.contains(&x) => Status::Delayed,
             x if scheduled_range.contains(&x) => Status::Scheduled {
-                departing_time: Utc::now() + Duration::minutes(rng.random_range(30..=12000)),
+                departing_time: now + Duration::minutes(rng.random_range(30..=12000)),
             },
             x if boarding_range.contains(&x) => Status::Boarding {
-                departing_time: Utc::now() + Duration::minutes(rng.random_range(0..=30)),
+                departing_time: now + Duration::minutes(rng.random_range(0..=30)),
             },
             x if landed_range.contains(&x) => Status::Landed {
-                arrived_at: Utc::now() - Duration::minutes(rng.random_range(0..=60)),
+                arrived_at: now - Duration::minutes(rng.random_range(0..=60)),
             },
             _ => Status::OnAir,
         }
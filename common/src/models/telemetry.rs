@@ -0,0 +1,178 @@
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+
+use super::track::{Track, TrackPoint};
+use super::FlightId;
+use crate::client_manager::ClientManager;
+
+/// A measure of a flight plotted over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Measure {
+    /// Meters.
+    Altitude,
+    /// Km/h.
+    Speed,
+    /// Liters remaining.
+    Fuel,
+}
+
+/// The telemetry of a flight read so far from `status_history`, for the charts of its detail
+/// panel. Each `fetch` only reads the positions recorded after the last one it has, so the
+/// panel stays up to date without reading the whole track again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Telemetry {
+    pub flight_id: FlightId,
+    points: Vec<TrackPoint>,
+    /// First day to read while no position was read.
+    since: NaiveDate,
+}
+
+impl Telemetry {
+    /// # Parameters
+    /// - `since`: Day the flight departed, where its track starts.
+    pub fn new(flight_id: FlightId, since: NaiveDate) -> Self {
+        Self {
+            flight_id,
+            points: Vec::new(),
+            since,
+        }
+    }
+
+    /// Builds the queries that select the positions after the last one read, one per day of
+    /// `status_history` up to `today`.
+    pub fn select_queries(&self, today: NaiveDate) -> Vec<String> {
+        let Some(last) = self.points.last() else {
+            return days(self.since, today)
+                .map(|date| Track::select_query(self.flight_id, date))
+                .collect();
+        };
+        let after = last.at.to_rfc3339_opts(SecondsFormat::Millis, true);
+        days(last.at.date_naive(), today)
+            .map(|date| {
+                let query = Track::select_query(self.flight_id, date);
+                format!("{} AND at > '{}';", query.trim_end_matches(';'), after)
+            })
+            .collect()
+    }
+
+    /// Reads the positions recorded since the last `fetch` and adds them to the telemetry.
+    ///
+    /// # Returns
+    /// The positions added, or an error if a query fails or a row is invalid.
+    pub fn fetch(&mut self, client: &mut ClientManager, today: NaiveDate) -> Result<usize, String> {
+        let mut points = Vec::new();
+        for query in self.select_queries(today) {
+            points.extend(client.query_as::<TrackPoint>(query, None)?);
+        }
+        Ok(self.extend(points))
+    }
+
+    /// Adds positions to the telemetry, keeping it in order of time and leaving out those that
+    /// it already has.
+    ///
+    /// # Returns
+    /// The positions added.
+    pub fn extend(&mut self, mut points: Vec<TrackPoint>) -> usize {
+        let last = self.points.last().map(|point| point.at);
+        points.retain(|point| last.is_none_or(|last| point.at > last));
+        points.sort_by_key(|point| point.at);
+        points.dedup_by_key(|point| point.at);
+        let added = points.len();
+        self.points.extend(points);
+        added
+    }
+
+    /// Returns the time of the last position read, if any.
+    pub fn last_update(&self) -> Option<DateTime<Utc>> {
+        self.points.last().map(|point| point.at)
+    }
+
+    /// Returns the points of the chart of a measure: the seconds since the first position and
+    /// the value at each one. Positions with an unreadable fuel are left out of its chart.
+    pub fn series(&self, measure: Measure) -> Vec<[f64; 2]> {
+        let Some(first) = self.points.first().map(|point| point.at) else {
+            return vec![];
+        };
+        self.points
+            .iter()
+            .filter_map(|point| {
+                let value = match measure {
+                    Measure::Altitude => point.altitude as f64,
+                    Measure::Speed => point.speed as f64,
+                    Measure::Fuel => remaining_fuel(&point.fuel)?,
+                };
+                let seconds = (point.at - first).num_milliseconds() as f64 / 1000.0;
+                Some([seconds, value])
+            })
+            .collect()
+    }
+}
+
+/// Returns the days from `from` to `to`, both included.
+fn days(from: NaiveDate, to: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+    from.iter_days().take_while(move |date| *date <= to)
+}
+
+/// Returns the liters remaining of a fuel written as `remaining/maximum`.
+fn remaining_fuel(fuel: &str) -> Option<f64> {
+    fuel.split('/').next()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(at: &str, altitude: u16, fuel: &str) -> TrackPoint {
+        TrackPoint {
+            at: at.parse().unwrap(),
+            latitude: -34.8,
+            longitude: -58.5,
+            altitude,
+            speed: 800,
+            heading: 90.0,
+            mode: "cruising".to_string(),
+            fuel: fuel.to_string(),
+        }
+    }
+
+    #[test]
+    fn only_the_new_positions_are_read_and_plotted() {
+        let day = NaiveDate::from_ymd_opt(2024, 11, 5).unwrap();
+        let mut telemetry = Telemetry::new(3, day);
+        assert_eq!(
+            telemetry.select_queries(day.succ_opt().unwrap()),
+            [
+                "SELECT * FROM status_history WHERE flight_id = 3 AND date = '2024-11-05';",
+                "SELECT * FROM status_history WHERE flight_id = 3 AND date = '2024-11-06';",
+            ]
+        );
+
+        let added = telemetry.extend(vec![
+            point("2024-11-05T06:01:00Z", 1_500, "99000/100000"),
+            point("2024-11-05T06:00:00Z", 0, "100000/100000"),
+        ]);
+        assert_eq!(added, 2);
+        assert_eq!(
+            telemetry.select_queries(day),
+            ["SELECT * FROM status_history WHERE flight_id = 3 AND date = '2024-11-05' AND at > '2024-11-05T06:01:00.000Z';"]
+        );
+
+        // Una fila repetida no se agrega dos veces
+        let added = telemetry.extend(vec![
+            point("2024-11-05T06:01:00Z", 1_500, "99000/100000"),
+            point("2024-11-05T06:02:00Z", 3_000, "unknown"),
+        ]);
+        assert_eq!(added, 1);
+        assert_eq!(
+            telemetry.series(Measure::Altitude),
+            [[0.0, 0.0], [60.0, 1_500.0], [120.0, 3_000.0]]
+        );
+        assert_eq!(
+            telemetry.series(Measure::Fuel),
+            [[0.0, 100_000.0], [60.0, 99_000.0]]
+        );
+        assert_eq!(
+            telemetry.last_update(),
+            Some("2024-11-05T06:02:00Z".parse().unwrap())
+        );
+    }
+}
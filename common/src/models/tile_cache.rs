@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The `[ui.tiles]` section of the config: where the map tiles come from and how much of them
+/// is kept on disk.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TileCacheConfig {
+    /// URL of the tiles, with `{z}`, `{x}` and `{y}` in place of the zoom and coordinates.
+    pub url: String,
+    pub cache_dir: String,
+    /// Size of the tiles kept, at most. The least recently used are deleted first.
+    pub max_megabytes: u64,
+    /// Only use the tiles in the cache, without downloading any, e.g. for demos without
+    /// internet access.
+    pub offline: bool,
+}
+
+impl Default for TileCacheConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://tile.openstreetmap.org/{z}/{x}/{y}.png".to_string(),
+            cache_dir: "./tiles".to_string(),
+            max_megabytes: 200,
+            offline: false,
+        }
+    }
+}
+
+impl TileCacheConfig {
+    /// Returns the problems of the section, if any.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.max_megabytes == 0 {
+            problems.push("ui tiles max_megabytes must be at least 1".to_string());
+        }
+        if ["{z}", "{x}", "{y}"]
+            .iter()
+            .any(|placeholder| !self.url.contains(placeholder))
+        {
+            problems.push(format!(
+                "ui tiles url {} must have {{z}}, {{x}} and {{y}}",
+                self.url
+            ));
+        }
+        problems
+    }
+}
+
+/// A tile of the map: its zoom and its column and row at that zoom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TileId {
+    pub zoom: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileId {
+    /// Returns the URL of the tile in a `TileCacheConfig::url`.
+    pub fn url(&self, template: &str) -> String {
+        template
+            .replace("{z}", &self.zoom.to_string())
+            .replace("{x}", &self.x.to_string())
+            .replace("{y}", &self.y.to_string())
+    }
+
+    /// Returns the file of the tile in the cache, `<zoom>/<x>/<y>.tile`.
+    fn path(&self, dir: &Path) -> PathBuf {
+        dir.join(self.zoom.to_string())
+            .join(self.x.to_string())
+            .join(format!("{}.tile", self.y))
+    }
+
+    /// Reads the tile of a file of the cache, `None` if it isn't one.
+    fn from_path(dir: &Path, path: &Path) -> Option<Self> {
+        let mut parts = path.strip_prefix(dir).ok()?.iter();
+        let zoom = parts.next()?.to_str()?.parse().ok()?;
+        let x = parts.next()?.to_str()?.parse().ok()?;
+        let y = parts
+            .next()?
+            .to_str()?
+            .strip_suffix(".tile")?
+            .parse()
+            .ok()?;
+        Some(Self { zoom, x, y })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    size: u64,
+    /// Order of the last use, the lowest being the least recent.
+    last_use: u64,
+}
+
+/// The map tiles downloaded, kept on disk so the map doesn't download them again on every pan
+/// and zoom and still works without internet access. When the tiles take more than
+/// `max_megabytes`, the least recently used are deleted. A tile read from the cache has its
+/// file touched, so the order of use survives a restart.
+#[derive(Debug)]
+pub struct TileCache {
+    config: TileCacheConfig,
+    entries: HashMap<TileId, Entry>,
+    /// The tiles by their last use.
+    by_use: BTreeMap<u64, TileId>,
+    total: u64,
+    next_use: u64,
+}
+
+impl TileCache {
+    /// Opens the cache in `cache_dir`, creating it if needed, with the tiles already in it in
+    /// the order they were last used.
+    pub fn open(config: TileCacheConfig) -> io::Result<Self> {
+        let dir = PathBuf::from(&config.cache_dir);
+        fs::create_dir_all(&dir)?;
+        let mut found = vec![];
+        let mut pending = vec![dir.clone()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(&current)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    pending.push(entry.path());
+                } else if let Some(tile) = TileId::from_path(&dir, &entry.path()) {
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    found.push((modified, tile, metadata.len()));
+                }
+            }
+        }
+        found.sort();
+
+        let mut cache = Self {
+            config,
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+            total: 0,
+            next_use: 0,
+        };
+        for (_, tile, size) in found {
+            cache.track(tile, size);
+        }
+        cache.evict()?;
+        Ok(cache)
+    }
+
+    /// Returns a tile from the cache, or downloads it with `fetch` and keeps it.
+    ///
+    /// # Parameters
+    /// - `fetch`: Downloads the tile from its URL. It isn't called when offline.
+    ///
+    /// # Returns
+    /// The image of the tile, or an error if it isn't cached and can't be downloaded.
+    pub fn get_or_fetch(
+        &mut self,
+        tile: TileId,
+        fetch: impl FnOnce(&str) -> Result<Vec<u8>, String>,
+    ) -> Result<Vec<u8>, String> {
+        if let Some(image) = self.get(tile) {
+            return Ok(image);
+        }
+        if self.config.offline {
+            return Err(format!(
+                "Tile {}/{}/{} is not cached and the map is offline",
+                tile.zoom, tile.x, tile.y
+            ));
+        }
+        let image = fetch(&tile.url(&self.config.url))?;
+        // Si no se puede guardar se muestra igual
+        if let Err(e) = self.insert(tile, &image) {
+            eprintln!("Error caching tile: {}", e);
+        }
+        Ok(image)
+    }
+
+    /// Returns a tile from the cache, `None` if it isn't there.
+    pub fn get(&mut self, tile: TileId) -> Option<Vec<u8>> {
+        let entry = *self.entries.get(&tile)?;
+        let path = tile.path(Path::new(&self.config.cache_dir));
+        let Ok(image) = fs::read(&path) else {
+            self.forget(tile);
+            return None;
+        };
+        self.by_use.remove(&entry.last_use);
+        self.track(tile, entry.size);
+        if let Ok(file) = File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(image)
+    }
+
+    /// Keeps a tile, deleting the least recently used ones if the cache gets too big.
+    pub fn insert(&mut self, tile: TileId, image: &[u8]) -> io::Result<()> {
+        let path = tile.path(Path::new(&self.config.cache_dir));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, image)?;
+        self.forget(tile);
+        self.track(tile, image.len() as u64);
+        self.evict()
+    }
+
+    /// Returns the bytes taken by the tiles in the cache.
+    pub fn size(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the tiles in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records a tile as the most recently used.
+    fn track(&mut self, tile: TileId, size: u64) {
+        let last_use = self.next_use;
+        self.next_use += 1;
+        if self
+            .entries
+            .insert(tile, Entry { size, last_use })
+            .is_none()
+        {
+            self.total += size;
+        }
+        self.by_use.insert(last_use, tile);
+    }
+
+    fn forget(&mut self, tile: TileId) {
+        if let Some(entry) = self.entries.remove(&tile) {
+            self.by_use.remove(&entry.last_use);
+            self.total -= entry.size;
+        }
+    }
+
+    /// Deletes the least recently used tiles until the cache fits in `max_megabytes`.
+    fn evict(&mut self) -> io::Result<()> {
+        let max = self.config.max_megabytes.saturating_mul(1024 * 1024);
+        while self.total > max {
+            let Some((_, tile)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&tile) {
+                self.total -= entry.size;
+            }
+            match fs::remove_file(tile.path(Path::new(&self.config.cache_dir))) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(name: &str, max_megabytes: u64) -> TileCacheConfig {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        TileCacheConfig {
+            cache_dir: dir.to_string_lossy().to_string(),
+            max_megabytes,
+            ..TileCacheConfig::default()
+        }
+    }
+
+    fn tile(x: u32) -> TileId {
+        TileId { zoom: 5, x, y: 7 }
+    }
+
+    #[test]
+    fn tiles_are_downloaded_once_and_the_least_used_are_evicted() {
+        let config = config("aero_tile_cache_test", 1);
+        let mut cache = TileCache::open(config.clone()).unwrap();
+        let half = vec![0u8; 512 * 1024];
+        let mut urls = vec![];
+        let mut fetch = |url: &str| {
+            urls.push(url.to_string());
+            Ok(half.clone())
+        };
+
+        cache.get_or_fetch(tile(1), &mut fetch).unwrap();
+        cache.get_or_fetch(tile(2), &mut fetch).unwrap();
+        // La primera se vuelve a usar, asi que la que se borra es la segunda
+        cache.get_or_fetch(tile(1), &mut fetch).unwrap();
+        cache.get_or_fetch(tile(3), &mut fetch).unwrap();
+        assert_eq!(
+            urls,
+            [
+                "https://tile.openstreetmap.org/5/1/7.png",
+                "https://tile.openstreetmap.org/5/2/7.png",
+                "https://tile.openstreetmap.org/5/3/7.png",
+            ]
+        );
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size(), 1024 * 1024);
+        assert!(cache.get(tile(2)).is_none());
+        assert!(!tile(2).path(Path::new(&config.cache_dir)).exists());
+
+        // Sin conexion se usan las del disco
+        let mut offline = TileCache::open(TileCacheConfig {
+            offline: true,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(offline.len(), 2);
+        let unreachable = |_: &str| -> Result<Vec<u8>, String> { panic!("downloaded offline") };
+        assert_eq!(offline.get_or_fetch(tile(3), unreachable).unwrap(), half);
+        assert!(offline.get_or_fetch(tile(2), unreachable).is_err());
+        assert!(TileCacheConfig::default().problems().is_empty());
+    }
+}
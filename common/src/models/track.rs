@@ -0,0 +1,193 @@
+use std::{fs, io, path::Path};
+
+use chrono::{DateTime, Days, NaiveDate, SecondsFormat, Utc};
+use serde_json::json;
+
+use super::tracking_data::{Degrees, KmH, Meters};
+use super::FlightId;
+use crate::client_manager::{ClientManager, FromRow};
+
+/// A position of the track of a flight, a row of `status_history`.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct TrackPoint {
+    pub at: DateTime<Utc>,
+    pub latitude: Degrees,
+    pub longitude: Degrees,
+    pub altitude: Meters,
+    pub speed: KmH,
+    pub heading: Degrees,
+    pub mode: String,
+    /// Remaining and maximum fuel, as `remaining/maximum`.
+    pub fuel: String,
+}
+
+/// The positions of a flight over time, in order, to export them to other tools.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub flight_id: FlightId,
+    pub points: Vec<TrackPoint>,
+}
+
+impl Track {
+    pub fn new(flight_id: FlightId, mut points: Vec<TrackPoint>) -> Self {
+        points.sort_by_key(|point| point.at);
+        Self { flight_id, points }
+    }
+
+    /// Builds the query that selects the positions of a flight on a day, a partition of
+    /// `status_history`.
+    pub fn select_query(flight_id: FlightId, date: NaiveDate) -> String {
+        format!(
+            "SELECT * FROM status_history WHERE flight_id = {} AND date = '{}';",
+            flight_id,
+            date.format("%Y-%m-%d")
+        )
+    }
+
+    /// Reads the track of a flight between two days, both included, from `status_history`.
+    ///
+    /// # Returns
+    /// The track, or an error if a query fails or a row is invalid.
+    pub fn fetch(
+        client: &mut ClientManager,
+        flight_id: FlightId,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Self, String> {
+        let mut points = Vec::new();
+        let mut date = from;
+        while date <= to {
+            points
+                .extend(client.query_as::<TrackPoint>(Self::select_query(flight_id, date), None)?);
+            let Some(next) = date.checked_add_days(Days::new(1)) else {
+                break;
+            };
+            date = next;
+        }
+        Ok(Self::new(flight_id, points))
+    }
+
+    /// Writes the track as a GeoJSON feature with a `LineString` through its positions, each
+    /// one as `[longitude, latitude, altitude]`, and their times in the `times` property.
+    pub fn to_geojson(&self) -> String {
+        let coordinates: Vec<_> = self
+            .points
+            .iter()
+            .map(|point| json!([point.longitude, point.latitude, point.altitude]))
+            .collect();
+        let times: Vec<_> = self
+            .points
+            .iter()
+            .map(|point| timestamp(&point.at))
+            .collect();
+        json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": coordinates },
+            "properties": { "flight_id": self.flight_id, "times": times },
+        })
+        .to_string()
+    }
+
+    /// Writes the track as CSV, a row per position with a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "at,latitude,longitude,altitude,speed,heading,mode,fuel\n".to_string();
+        for point in &self.points {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                timestamp(&point.at),
+                point.latitude,
+                point.longitude,
+                point.altitude,
+                point.speed,
+                point.heading,
+                point.mode,
+                point.fuel
+            ));
+        }
+        csv
+    }
+
+    /// Writes the track to a file, as GeoJSON if the extension of the file is `.geojson` or
+    /// `.json` and as CSV otherwise.
+    pub fn export(&self, path: &str) -> io::Result<()> {
+        let contents = match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("geojson" | "json") => self.to_geojson(),
+            _ => self.to_csv(),
+        };
+        fs::write(path, contents)
+    }
+}
+
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_manager::Row;
+    use std::collections::HashMap;
+
+    fn row(at: &str, latitude: &str, longitude: &str) -> Row {
+        let mut columns = HashMap::new();
+        for (column, value) in [
+            ("flight_id", "3"),
+            ("date", "2024-11-05"),
+            ("at", at),
+            ("latitude", latitude),
+            ("longitude", longitude),
+            ("altitude", "9000"),
+            ("speed", "850"),
+            ("heading", "45"),
+            ("mode", "cruising"),
+            ("fuel", "900/1000"),
+            ("_timestamp", "2024-11-05 06:01:00"),
+        ] {
+            columns.insert(column.to_string(), value.to_string());
+        }
+        Row::from(columns)
+    }
+
+    #[test]
+    fn tracks_are_exported_to_geojson_and_csv() {
+        let points = [
+            row("2024-11-05T06:02:00.000Z", "-34.5", "-58.0"),
+            row("2024-11-05T06:01:00.000Z", "-34.8", "-58.5"),
+        ]
+        .iter()
+        .map(TrackPoint::from_row)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        let track = Track::new(3, points);
+        assert_eq!(
+            Track::select_query(3, NaiveDate::from_ymd_opt(2024, 11, 5).unwrap()),
+            "SELECT * FROM status_history WHERE flight_id = 3 AND date = '2024-11-05';"
+        );
+
+        let geojson: serde_json::Value = serde_json::from_str(&track.to_geojson()).unwrap();
+        assert_eq!(geojson["geometry"]["type"], "LineString");
+        assert_eq!(
+            geojson["geometry"]["coordinates"],
+            json!([[-58.5, -34.8, 9000], [-58.0, -34.5, 9000]])
+        );
+        assert_eq!(
+            geojson["properties"]["times"][0],
+            "2024-11-05T06:01:00.000Z"
+        );
+
+        let csv = track.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            "2024-11-05T06:01:00.000Z,-34.8,-58.5,9000,850,45,cruising,900/1000"
+        );
+
+        let path = std::env::temp_dir().join("aero_track_test.geojson");
+        track.export(path.to_str().unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), track.to_geojson());
+    }
+}
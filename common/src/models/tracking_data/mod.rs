@@ -1,7 +1,8 @@
-use chrono::{DateTime, Utc};
-use rand::{rngs::ThreadRng, Rng as _};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rand::Rng;
 
-use super::{FlightId, status::Status};
+use super::{FlightId, aircraft_model::AircraftModel, airport::Airport, clock::SimClock, flight_plan::{FlightPlan, Waypoint}, status::Status, weather::Weather};
+use crate::frame::messages::prepared::Value;
 
 pub mod mode;
 use mode::Mode;
@@ -9,11 +10,27 @@ use mode::Mode;
 pub type Meters = u16;
 pub type Liters = u32;
 pub type KmH = u16;
-pub type Degrees = f32;
+pub type Degrees = f64;
 
-const MAX_ALTITUDE: Meters = 12_000;
-const MIN_CRUISING: Meters = 9_000;
 const SEA_LEVEL: Meters = 0;
+/// Meters of altitude lost per kilometer flown on the descent, a glide slope of 3 degrees.
+const GLIDE_SLOPE: f64 = 52.0;
+/// Kilometers to the destination where the final approach starts.
+const FINAL_APPROACH: f64 = 10.0;
+/// Kilometers to the destination where the plane touches down.
+const RUNWAY: f64 = 1.0;
+const APPROACH_SPEED: KmH = 300;
+const TOUCHDOWN_SPEED: KmH = 250;
+const TAXI_SPEED: KmH = 30;
+/// Speed lost per minute while descending and while rolling out on the runway.
+const DESCENT_DECELERATION: KmH = 40;
+const ROLLOUT_DECELERATION: KmH = 200;
+const TAXI_TIME: Duration = Duration::minutes(5);
+
+const STATUS_INSERT: &str = "INSERT INTO status \
+    (flight_id, fuel, latitude, longitude, heading, altitude, speed, mode, last_update, eta) VALUES";
+const HISTORY_INSERT: &str = "INSERT INTO status_history \
+    (flight_id, date, at, fuel, latitude, longitude, heading, altitude, speed, mode) VALUES";
 
 #[derive(Debug, Clone)]
 pub struct TrackingData {
@@ -25,12 +42,15 @@ pub struct TrackingData {
     pub altitude: Meters,
     pub speed: KmH,
     pub current_mode: Mode,
+    /// Estimated time of arrival, `None` if the plane isn't flying towards its destination.
+    /// While taxiing, when it reaches the gate.
+    pub eta: Option<DateTime<Utc>>,
 }
 
 impl TrackingData {
-    pub fn empty() -> Self {
+    pub fn empty(clock: &impl SimClock) -> Self {
         Self {
-            last_update: Utc::now(),
+            last_update: clock.now(),
             fuel_remaining: 0,
             latitude: 0.0,
             longitude: 0.0,
@@ -38,47 +58,128 @@ impl TrackingData {
             altitude: 0,
             speed: 0,
             current_mode: Mode::OnGround,
+            eta: None,
         }
     }
 
     pub fn generate_query(&self, flight_id: FlightId, max_fuel: Liters) -> String {
-        format!(
-            "INSERT INTO status 
-            (flight_id, fuel, latitude, longitude, heading, altitude, speed, mode) \
-            VALUES ({}, '{}/{}', '{}', '{}', '{}', '{}', '{}', '{}');",
-            flight_id,
-            self.fuel_remaining,
-            max_fuel,
-            self.latitude,
-            self.longitude,
-            self.heading,
-            self.altitude,
-            self.speed,
-            self.current_mode
-        )
+        format!("{} {};", STATUS_INSERT, literals(&self.status_values(flight_id, max_fuel)))
+    }
+
+    /// Returns the statement that stores the position of a flight in `status`, with a `?` for
+    /// each value, to prepare once with `ClientManager::prepare` and run with the
+    /// `status_values` of each flight.
+    pub fn status_statement() -> String {
+        statement(STATUS_INSERT)
+    }
+
+    /// Returns the values of `status_statement` for the position of a flight.
+    pub fn status_values(&self, flight_id: FlightId, max_fuel: Liters) -> Vec<Value> {
+        let mut values = vec![Value::Bigint(flight_id.into())];
+        values.extend(self.measures(max_fuel));
+        // Hora simulada, no la del reloj de pared
+        values.push(Value::Text(self.last_update.to_rfc3339()));
+        // Vacia si no hay llegada estimada, que se lee como `None`
+        values.push(Value::Text(self.eta.map(|eta| eta.to_rfc3339()).unwrap_or_default()));
+        values
+    }
+
+    /// Builds a single query that stores the positions of several flights, e.g. all the flights
+    /// of a tick, instead of a query per flight.
+    ///
+    /// # Parameters
+    /// - `flights`: The id, position and fuel capacity of each flight.
+    ///
+    /// # Returns
+    /// The query, or `None` if there are no flights.
+    pub fn generate_batch_query<'a>(
+        flights: impl IntoIterator<Item = (FlightId, &'a TrackingData, Liters)>,
+    ) -> Option<String> {
+        let values: Vec<String> = flights
+            .into_iter()
+            .map(|(flight_id, tracking, max_fuel)| {
+                literals(&tracking.status_values(flight_id, max_fuel))
+            })
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(format!("{} {};", STATUS_INSERT, values.join(", ")))
+    }
+
+    /// Builds the query that adds the positions of several flights to their track in
+    /// `status_history`, partitioned by flight and day of the simulated time so that a
+    /// partition doesn't grow without bound, and clustered by the time of the position.
+    ///
+    /// # Parameters
+    /// - `flights`: The id, position and fuel capacity of each flight.
+    ///
+    /// # Returns
+    /// The query, or `None` if there are no flights.
+    pub fn generate_history_query<'a>(
+        flights: impl IntoIterator<Item = (FlightId, &'a TrackingData, Liters)>,
+    ) -> Option<String> {
+        let values: Vec<String> = flights
+            .into_iter()
+            .map(|(flight_id, tracking, max_fuel)| {
+                literals(&tracking.history_values(flight_id, max_fuel))
+            })
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(format!("{} {};", HISTORY_INSERT, values.join(", ")))
+    }
+
+    /// Returns the statement that adds a position to the track of a flight in
+    /// `status_history`, with a `?` for each value of `history_values`.
+    pub fn history_statement() -> String {
+        statement(HISTORY_INSERT)
+    }
+
+    /// Returns the values of `history_statement` for the position of a flight.
+    pub fn history_values(&self, flight_id: FlightId, max_fuel: Liters) -> Vec<Value> {
+        // La fecha y la hora como texto de ancho fijo, que se ordena como el tiempo
+        let mut values = vec![
+            Value::Bigint(flight_id.into()),
+            Value::Text(self.last_update.format("%Y-%m-%d").to_string()),
+            Value::Text(self.last_update.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        ];
+        values.extend(self.measures(max_fuel));
+        values
+    }
+
+    /// Values of the fuel, position, speed and mode columns.
+    fn measures(&self, max_fuel: Liters) -> [Value; 7] {
+        [
+            Value::Text(format!("{}/{}", self.fuel_remaining, max_fuel)),
+            Value::Float(self.latitude),
+            Value::Float(self.longitude),
+            Value::Float(self.heading),
+            Value::Int(self.altitude.into()),
+            Value::Int(self.speed.into()),
+            Value::Text(self.current_mode.to_string()),
+        ]
     }
 
     pub fn random_init(
         status: &Status,
+        clock: &impl SimClock,
+        rng: &mut impl Rng,
         max_fuel: Liters,
-        origin_lat: Degrees,
-        origin_lon: Degrees,
-        destination_lat: Degrees,
-        destination_lon: Degrees,
+        origin: &Airport,
+        destination: &Airport,
+        aircraft: &AircraftModel,
     ) -> Self {
+        let (origin_lat, origin_lon) = (origin.latitude, origin.longitude);
+        let (destination_lat, destination_lon) = (destination.latitude, destination.longitude);
         match status {
-            Status::OnAir => Self::random_on_air(
-                max_fuel,
-                origin_lat,
-                origin_lon,
-                destination_lat,
-                destination_lon,
-            ),
+            Status::OnAir => Self::random_on_air(clock, rng, max_fuel, origin, destination, aircraft),
             Status::Landed { .. } => Self {
-                last_update: Utc::now(),
-                fuel_remaining: (max_fuel * rand::rng().random_range(10..=40)) / 100,
+                last_update: clock.now(),
+                fuel_remaining: (max_fuel * rng.random_range(10..=40)) / 100,
                 latitude: destination_lat,
-                longitude: origin_lat,
+                longitude: destination_lon,
                 heading: calculate_heading(
                     origin_lat,
                     origin_lon,
@@ -88,9 +189,10 @@ impl TrackingData {
                 altitude: SEA_LEVEL,
                 speed: 0,
                 current_mode: Mode::OnGround,
+                eta: None,
             },
             _ => Self {
-                last_update: Utc::now(),
+                last_update: clock.now(),
                 fuel_remaining: max_fuel,
                 latitude: origin_lat,
                 longitude: origin_lon,
@@ -103,148 +205,269 @@ impl TrackingData {
                 altitude: SEA_LEVEL,
                 speed: 0,
                 current_mode: Mode::OnGround,
+                eta: None,
             },
         }
     }
 
     pub fn random_on_air(
+        clock: &impl SimClock,
+        rng: &mut impl Rng,
         max_fuel: Liters,
-        origin_lat: Degrees,
-        origin_lon: Degrees,
-        destination_lat: Degrees,
-        destination_lon: Degrees,
+        origin: &Airport,
+        destination: &Airport,
+        aircraft: &AircraftModel,
     ) -> Self {
-        let mut rng = rand::rng();
-
+        let (origin_lat, origin_lon) = (origin.latitude, origin.longitude);
+        let (destination_lat, destination_lon) = (destination.latitude, destination.longitude);
         let t = rng.random_range(0.0..=0.3);
-        let latitude = origin_lat + t * (destination_lat - origin_lat);
-        let longitude = origin_lon + t * (destination_lon - origin_lon);
+        let (latitude, longitude) =
+            great_circle_point(origin_lat, origin_lon, destination_lat, destination_lon, t);
         let (fuel_factor, altitude_range, speed_range, current_mode) =
-            Self::determine_by_flight_phase(t, &mut rng);
+            Self::determine_by_flight_phase(t, aircraft, rng);
 
+        let speed = rng.random_range(speed_range);
         Self {
-            last_update: Utc::now(),
+            last_update: clock.now(),
             fuel_remaining: (max_fuel * fuel_factor / 100).max(100),
             latitude,
             longitude,
             heading: calculate_heading(origin_lat, origin_lon, destination_lat, destination_lon),
             altitude: rng.random_range(altitude_range),
-            speed: rng.random_range(speed_range),
+            speed,
             current_mode,
+            eta: estimated_arrival(
+                clock.now(),
+                haversine_distance(latitude, longitude, destination_lat, destination_lon),
+                speed as f64,
+            ),
         }
     }
 
     fn determine_by_flight_phase(
-        t: f32,
-        rng: &mut rand::prelude::ThreadRng,
+        t: f64,
+        aircraft: &AircraftModel,
+        rng: &mut impl Rng,
     ) -> (u32, std::ops::Range<Meters>, std::ops::Range<KmH>, Mode) {
+        let cruise_floor = aircraft.cruise_floor();
         match t {
             0.0..=0.2 => (
                 90 - (t * 10.0) as u32,      // 90% to 100% fuel for climbing
-                SEA_LEVEL..MIN_CRUISING / 2, // Altitude for climbing should not go negative
+                SEA_LEVEL..cruise_floor / 2, // Altitude for climbing should not go negative
                 200..400,                    // Speed range
                 Mode::Climbing,
             ),
             0.8..=0.9 => (
                 rng.random_range(10..=50), // 10% to 50% fuel for descending
-                SEA_LEVEL..MIN_CRUISING,   // Altitude for descending should not go negative
+                SEA_LEVEL..cruise_floor,   // Altitude for descending should not go negative
                 200..400,                  // Speed range
                 Mode::Descending,
             ),
             _ => (
-                rng.random_range(40..=60),  // 40% to 60% fuel for cruising
-                MIN_CRUISING..MAX_ALTITUDE, // Higher altitude for cruising
-                600..900,                   // Higher speed range
+                rng.random_range(40..=60),      // 40% to 60% fuel for cruising
+                cruise_floor..aircraft.ceiling, // Cruising altitudes of the model
+                aircraft.cruise_speeds(),       // Cruising speeds of the model
                 Mode::Cruising,
             ),
         }
     }
 
-    pub fn simulate(&mut self, destination_lat: Degrees, destination_lon: Degrees) {
-        let mut rng = rand::rng();
+    /// Advances the flight one step along the active leg of its plan, as far as it flies in
+    /// `elapsed` of simulated time, moving on to the next leg when the waypoint is reached.
+    /// The wind changes the speed over the ground and the heading needed to keep on the
+    /// route, and storms make the plane burn more fuel.
+    pub fn simulate(
+        &mut self,
+        plan: &mut FlightPlan,
+        weather: &Weather,
+        aircraft: &AircraftModel,
+        elapsed: Duration,
+        rng: &mut impl Rng,
+    ) {
+        self.last_update += elapsed;
 
         // Check for landing completion and update the mode if necessary
 
-        if self.sos(&mut rng) {
+        if self.sos(rng) {
+            // En emergencia no hay llegada estimada
+            self.eta = None;
             return;
         }
 
-        // Update altitude, speed, and mode based on the distance to the destination
-        let distance_to_destination = haversine_distance(
-            self.latitude,
-            self.longitude,
-            destination_lat,
-            destination_lon,
-        );
+        // Update altitude, speed, and mode based on the distance to the destination along the route
+        let distance_to_destination = plan.remaining_distance(self.latitude, self.longitude);
+        if distance_to_destination < RUNWAY && self.landed() {
+            self.taxi(elapsed);
+            return;
+        }
+        let cruise_target = plan.active_waypoint().filter(|_| !plan.is_last_leg()).cloned();
         (self.altitude, self.speed, self.current_mode) =
-            self.update(distance_to_destination, &mut rng);
-
-        // Move the plane closer to the destination based on current speed
-        let t = self.speed as f32 / 32000.0; // Movement factor
-        self.latitude += t * (destination_lat - self.latitude);
-        self.longitude += t * (destination_lon - self.longitude);
-        
-        // Update heading to face the destination
-        self.heading = calculate_heading(
-            self.latitude,
-            self.longitude,
-            destination_lat,
-            destination_lon,
-        );
+            self.update(distance_to_destination, cruise_target.as_ref(), aircraft, elapsed, rng);
+
+        // Move the plane along the great circle of each leg based on its speed over the ground
+        let wind = weather.wind_at(self.latitude, self.longitude);
+        let ground_speed = match plan.active_waypoint() {
+            Some(waypoint) if self.speed > 0 => {
+                let course = calculate_heading(
+                    self.latitude,
+                    self.longitude,
+                    waypoint.latitude,
+                    waypoint.longitude,
+                );
+                (self.speed as f64 + wind.components(course).0).max(0.0)
+            }
+            _ => 0.0,
+        };
+        let hours = elapsed.num_milliseconds() as f64 / 3_600_000.0;
+        let mut step = (ground_speed * hours).min(distance_to_destination);
+        while let Some(waypoint) = plan.active_waypoint().cloned() {
+            let leg = haversine_distance(
+                self.latitude,
+                self.longitude,
+                waypoint.latitude,
+                waypoint.longitude,
+            );
+            if step < leg || plan.is_last_leg() {
+                if leg > 0.0 {
+                    (self.latitude, self.longitude) = great_circle_point(
+                        self.latitude,
+                        self.longitude,
+                        waypoint.latitude,
+                        waypoint.longitude,
+                        (step / leg).min(1.0),
+                    );
+                }
+                break;
+            }
+            // El resto del paso se vuela en el tramo siguiente
+            (self.latitude, self.longitude) = (waypoint.latitude, waypoint.longitude);
+            step -= leg;
+            plan.advance();
+        }
+
+        // Update heading to face the active waypoint, which changes along a great circle,
+        // turned into the crosswind so that the plane keeps on the route
+        if let Some(waypoint) = plan.active_waypoint() {
+            let course = calculate_heading(
+                self.latitude,
+                self.longitude,
+                waypoint.latitude,
+                waypoint.longitude,
+            );
+            let crosswind = wind.components(course).1;
+            let drift = if self.speed > 0 {
+                (crosswind / self.speed as f64).clamp(-1.0, 1.0).asin().to_degrees()
+            } else {
+                0.0
+            };
+            self.heading = (course - drift).rem_euclid(360.0);
+        }
 
         // Safely decrement fuel
-        self.fuel_remaining = self.fuel_remaining.saturating_sub(rng.random_range(1..=5));
+        let burned = rng.random_range(1..=5) as f64 * weather.fuel_burn_factor(self.latitude, self.longitude);
+        self.fuel_remaining = self.fuel_remaining.saturating_sub(burned.round() as Liters);
+
+        // Estimate the arrival from what is left of the route at the current speed over the ground
+        self.eta = if self.landed() {
+            None
+        } else {
+            estimated_arrival(
+                self.last_update,
+                plan.remaining_distance(self.latitude, self.longitude),
+                ground_speed,
+            )
+        };
     }
 
-    fn update(&self, distance_to_destination: f32, rng: &mut ThreadRng) -> (Meters, KmH, Mode) {
-        if distance_to_destination < 1.0 {
-            return (SEA_LEVEL, 0, Mode::OnGround);
+    fn update(
+        &self,
+        distance_to_destination: f64,
+        cruise_target: Option<&Waypoint>,
+        aircraft: &AircraftModel,
+        elapsed: Duration,
+        rng: &mut impl Rng,
+    ) -> (Meters, KmH, Mode) {
+        let (cruise_floor, ceiling) = (aircraft.cruise_floor(), aircraft.ceiling);
+        let cruise_speeds = aircraft.cruise_speeds();
+        if distance_to_destination < RUNWAY {
+            // Toca tierra y frena sobre la pista
+            return (SEA_LEVEL, self.speed.min(TOUCHDOWN_SPEED), Mode::OnGround);
         }
-        if distance_to_destination < 2.0 {
+        // Altitud de la senda de planeo a esta distancia de la pista
+        let glide_altitude = (distance_to_destination * GLIDE_SLOPE).min(Meters::MAX as f64) as Meters;
+        if distance_to_destination < FINAL_APPROACH {
             return (
-                self.altitude
-                    .saturating_sub(rng.random_range(100..=500))
-                    .max(100)
-                    .saturating_sub(rng.random_range(10..=30)),
-                self.speed
-                    .saturating_sub(rng.random_range(200..=1000))
-                    .max(300)
-                    .saturating_sub(rng.random_range(5..=10)),
+                self.altitude.min(glide_altitude),
+                approach(self.speed, TOUCHDOWN_SPEED, per_minute(DESCENT_DECELERATION, elapsed)),
                 Mode::Landing,
             );
         }
-        if distance_to_destination < 20.0 {
+        if self.altitude > SEA_LEVEL
+            && self.altitude >= glide_altitude
+            && self.current_mode != Mode::Climbing
+        {
+            // Baja a razon de ascenso del modelo hasta la senda, sin pasar por debajo
             return (
                 self.altitude
-                    .saturating_sub(rng.random_range(100..=700))
-                    .max(300)
-                    .saturating_sub(rng.random_range(1..=5)),
-                self.speed
-                    .saturating_sub(rng.random_range(10..=100))
-                    .max(400)
-                    .saturating_sub(rng.random_range(50..=100)),
+                    .saturating_sub(per_minute(aircraft.climb_rate, elapsed))
+                    .max(glide_altitude),
+                approach(self.speed, APPROACH_SPEED, per_minute(DESCENT_DECELERATION, elapsed)),
                 Mode::Descending,
             );
         }
-        if self.altitude < MIN_CRUISING {
+        if self.altitude < cruise_floor {
+            // Sube a razon de ascenso del modelo, con un tercio de variacion
+            let climb = aircraft.climb_rate as u32 * elapsed.num_seconds().max(0) as u32 / 60;
+            let altitude = (self.altitude as u32 + rng.random_range(climb * 2 / 3..=climb * 4 / 3))
+                .min(ceiling as u32) as Meters;
+            // Al llegar a la altitud de crucero se nivela
+            let mode = if altitude >= cruise_floor { Mode::Cruising } else { Mode::Climbing };
+            // Acelera sin pasar la velocidad de crucero, aunque suba lento
+            let speed = self.speed.saturating_add(rng.random_range(10..=80)).min(cruise_speeds.end);
+            return (altitude, speed, mode);
+        }
+        if let Some(target) = cruise_target {
+            // Se acerca a la altitud y velocidad del plan para este tramo
             return (
-                (self.altitude + rng.random_range(1000..=2000)),
-                (self.speed + rng.random_range(10..=80)),
-                Mode::Climbing,
+                approach(self.altitude, target.altitude, 500).clamp(cruise_floor, ceiling),
+                approach(self.speed, target.speed, 50),
+                Mode::Cruising,
             );
         }
         (
-            (self.altitude + rng.random_range(0..120) - 50).clamp(MIN_CRUISING, MAX_ALTITUDE),
-            (self.speed + rng.random_range(0..=110) - 50),
+            // Se resta despues de sumar sin bajar de cero, por si llego al crucero casi detenido
+            (self.altitude.saturating_add(rng.random_range(0..120)).saturating_sub(50))
+                .clamp(cruise_floor, ceiling),
+            (self.speed.saturating_add(rng.random_range(0..=110)).saturating_sub(50))
+                .clamp(cruise_speeds.start, cruise_speeds.end),
             Mode::Cruising,
         )
     }
 
+    /// Rolls out on the runway after touching down, taxis to the gate and parks there. While
+    /// taxiing, `eta` is when the plane reaches the gate.
+    fn taxi(&mut self, elapsed: Duration) {
+        match self.current_mode {
+            Mode::OnGround => {
+                self.speed = approach(self.speed, TAXI_SPEED, per_minute(ROLLOUT_DECELERATION, elapsed));
+                if self.speed <= TAXI_SPEED {
+                    self.current_mode = Mode::Taxiing;
+                    self.eta = Some(self.last_update + TAXI_TIME);
+                }
+            }
+            Mode::Taxiing if self.eta.is_none_or(|gate| self.last_update >= gate) => {
+                (self.current_mode, self.speed, self.eta) = (Mode::AtGate, 0, None);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns whether the plane is on the ground: rolling out, taxiing or at the gate.
     pub fn landed(&self) -> bool {
-        self.current_mode == Mode::OnGround
+        matches!(self.current_mode, Mode::OnGround | Mode::Taxiing | Mode::AtGate)
     }
 
-    pub fn sos(&mut self, rng: &mut ThreadRng) -> bool {
+    pub fn sos(&mut self, rng: &mut impl Rng) -> bool {
         if self.fuel_remaining > 0 {
             return false;
         }
@@ -259,10 +482,44 @@ impl TrackingData {
     }
 }
 
+/// Writes the values of a row of an INSERT as literals, with their texts escaped.
+fn literals(values: &[Value]) -> String {
+    let literals: Vec<String> = values.iter().map(Value::to_literal).collect();
+    format!("({})", literals.join(", "))
+}
+
+/// Builds the statement of an INSERT with a `?` marker for each of its columns.
+fn statement(insert: &str) -> String {
+    let columns = insert.matches(',').count() + 1;
+    format!("{} ({});", insert, vec!["?"; columns].join(", "))
+}
+
+/// Estimates when a plane arrives, flying the remaining distance at its current speed.
+///
+/// # Parameters
+/// - `now`: The time of the position.
+/// - `distance`: The kilometers left to the destination.
+/// - `speed`: The speed over the ground, in km/h.
+///
+/// # Returns
+/// The time of arrival, or `None` if the plane isn't moving.
+pub fn estimated_arrival(now: DateTime<Utc>, distance: f64, speed: f64) -> Option<DateTime<Utc>> {
+    if speed <= 0.0 {
+        return None;
+    }
+    let hours = distance.max(0.0) / speed;
+    Some(now + Duration::milliseconds((hours * 3_600_000.0).round() as i64))
+}
+
 /// Haversine formula to calculate the distance between two points on the globe
-pub fn haversine_distance(lat1: Degrees, lon1: Degrees, lat2: Degrees, lon2: Degrees) -> f32 {
+pub fn haversine_distance(lat1: Degrees, lon1: Degrees, lat2: Degrees, lon2: Degrees) -> f64 {
     let earth_radius_km = 6371.0;
 
+    earth_radius_km * angular_distance(lat1, lon1, lat2, lon2)
+}
+
+/// Angle between two points on the globe seen from its center, in radians
+fn angular_distance(lat1: Degrees, lon1: Degrees, lat2: Degrees, lon2: Degrees) -> f64 {
     let d_lat = (lat2 - lat1).to_radians();
     let d_lon = (lon2 - lon1).to_radians();
 
@@ -270,9 +527,54 @@ pub fn haversine_distance(lat1: Degrees, lon1: Degrees, lat2: Degrees, lon2: Deg
     let lat2 = lat2.to_radians();
 
     let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
-    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+    2.0 * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Point at `fraction` of the way along the great circle between two points, the shortest
+/// path between them on the globe.
+///
+/// # Returns
+/// The latitude and longitude of the point.
+pub fn great_circle_point(
+    origin_lat: Degrees,
+    origin_lon: Degrees,
+    destination_lat: Degrees,
+    destination_lon: Degrees,
+    fraction: f64,
+) -> (Degrees, Degrees) {
+    let delta = angular_distance(origin_lat, origin_lon, destination_lat, destination_lon);
+    if delta.sin().abs() < f64::EPSILON {
+        // Mismo punto (o antipodas): no hay un unico gran circulo
+        return (destination_lat, destination_lon);
+    }
+
+    let a = ((1.0 - fraction) * delta).sin() / delta.sin();
+    let b = (fraction * delta).sin() / delta.sin();
+
+    let (lat1, lon1) = (origin_lat.to_radians(), origin_lon.to_radians());
+    let (lat2, lon2) = (destination_lat.to_radians(), destination_lon.to_radians());
+    let x = a * lat1.cos() * lon1.cos() + b * lat2.cos() * lon2.cos();
+    let y = a * lat1.cos() * lon1.sin() + b * lat2.cos() * lon2.sin();
+    let z = a * lat1.sin() + b * lat2.sin();
 
-    earth_radius_km * c
+    (
+        z.atan2((x * x + y * y).sqrt()).to_degrees(),
+        y.atan2(x).to_degrees(),
+    )
+}
+
+/// Returns how much of a rate per minute is done in `elapsed`.
+fn per_minute(rate: u16, elapsed: Duration) -> u16 {
+    (rate as i64 * elapsed.num_milliseconds().max(0) / 60_000).min(u16::MAX as i64) as u16
+}
+
+/// Moves `current` towards `target` by at most `max_step`.
+fn approach(current: u16, target: u16, max_step: u16) -> u16 {
+    if current < target {
+        current.saturating_add(max_step).min(target)
+    } else {
+        current.saturating_sub(max_step).max(target)
+    }
 }
 
 fn calculate_heading(
@@ -300,3 +602,305 @@ fn calculate_heading(
 
     bearing
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::messages::prepared::bind;
+    use crate::models::clock::WallClock;
+    use crate::models::rng::flight_rng;
+    use crate::models::weather::{Wind, WindRegion};
+
+    #[test]
+    fn the_positions_of_a_tick_are_stored_in_one_query() {
+        let first = TrackingData::empty(&WallClock);
+        let mut second = first.clone();
+        second.latitude = -34.82;
+
+        let batch =
+            TrackingData::generate_batch_query([(1, &first, 1_000), (2, &second, 2_000)]).unwrap();
+        let single = first.generate_query(1, 1_000);
+        assert_eq!(batch.matches("INSERT INTO status").count(), 1);
+        assert!(batch.starts_with(single.trim_end_matches(';')));
+        assert!(batch.ends_with(&second.generate_query(2, 2_000)[STATUS_INSERT.len()..]));
+        assert_eq!(TrackingData::generate_batch_query([]), None);
+    }
+
+    #[test]
+    fn prepared_writes_store_the_same_values() {
+        let mut tracking = TrackingData::empty(&WallClock);
+        (tracking.latitude, tracking.altitude) = (-34.82, 9_000);
+        let bound = |statement: String, values: Vec<Value>| {
+            let values: Vec<Vec<u8>> = values.iter().map(Value::serialize).collect();
+            bind(&statement, &values).unwrap()
+        };
+
+        assert_eq!(
+            bound(TrackingData::status_statement(), tracking.status_values(4, 1_000)),
+            tracking.generate_query(4, 1_000)
+        );
+        assert_eq!(
+            bound(TrackingData::history_statement(), tracking.history_values(4, 1_000)),
+            TrackingData::generate_history_query([(4, &tracking, 1_000)]).unwrap()
+        );
+    }
+
+    #[test]
+    fn tracks_are_partitioned_by_flight_and_day() {
+        let mut before_midnight = TrackingData::empty(&WallClock);
+        before_midnight.last_update = "2024-11-05T23:59:30Z".parse().unwrap();
+        let mut after_midnight = before_midnight.clone();
+        after_midnight.last_update += Duration::minutes(1);
+
+        let query = TrackingData::generate_history_query([
+            (3, &before_midnight, 1_000),
+            (3, &after_midnight, 1_000),
+        ])
+        .unwrap();
+        assert!(query.starts_with("INSERT INTO status_history"));
+        assert!(query.contains("VALUES (3, '2024-11-05', '2024-11-05T23:59:30.000Z', '0/1000', "));
+        assert!(query.contains("(3, '2024-11-06', '2024-11-06T00:00:30.000Z', "));
+        assert_eq!(TrackingData::generate_history_query([]), None);
+    }
+
+    #[test]
+    fn planes_fly_along_the_great_circle() {
+        // Ezeiza a Madrid: por el gran circulo el punto medio queda en medio del Atlantico
+        let (lat, lon) = great_circle_point(-34.82, -58.54, 40.47, -3.56, 0.5);
+        assert!((lat - 3.18).abs() < 0.05, "latitude {}", lat);
+        assert!((lon + 32.18).abs() < 0.05, "longitude {}", lon);
+
+        let half = haversine_distance(-34.82, -58.54, lat, lon);
+        let total = haversine_distance(-34.82, -58.54, 40.47, -3.56);
+        assert!((half * 2.0 - total).abs() < 1.0);
+
+        assert_eq!(great_circle_point(10.0, 20.0, 10.0, 20.0, 0.3), (10.0, 20.0));
+    }
+
+    #[test]
+    fn the_heading_follows_the_path() {
+        let mut tracking = TrackingData::empty(&WallClock);
+        tracking.fuel_remaining = 1000;
+        (tracking.latitude, tracking.longitude) = (-34.82, -58.54);
+        tracking.altitude = AircraftModel::generic().cruise_floor();
+        tracking.speed = 800;
+        tracking.simulate(&mut FlightPlan::direct(40.47, -3.56), &Weather::default(), &AircraftModel::generic(), Duration::minutes(1), &mut rand::rng());
+
+        let expected = calculate_heading(tracking.latitude, tracking.longitude, 40.47, -3.56);
+        assert_eq!(tracking.heading, expected);
+        assert!(tracking.latitude > -34.82);
+    }
+
+    #[test]
+    fn planes_fly_within_the_profile_of_their_model() {
+        let turboprop = AircraftModel {
+            name: "ATR72".to_string(),
+            cruise_speed: 500,
+            ceiling: 7_600,
+            fuel_capacity: 6_400,
+            climb_rate: 600,
+        };
+        let mut tracking = TrackingData::empty(&WallClock);
+        tracking.fuel_remaining = 6_400;
+        (tracking.latitude, tracking.longitude) = (-34.82, -58.54);
+        tracking.speed = 300;
+        let mut plan = FlightPlan::direct(-31.31, -64.21);
+        let mut rng = flight_rng(7, 1);
+
+        tracking.simulate(&mut plan, &Weather::default(), &turboprop, Duration::minutes(1), &mut rng);
+        assert_eq!(tracking.current_mode, Mode::Climbing);
+        assert!((400..=800).contains(&tracking.altitude), "altitude {}", tracking.altitude);
+
+        for _ in 0..30 {
+            tracking.simulate(&mut plan, &Weather::default(), &turboprop, Duration::minutes(1), &mut rng);
+        }
+        assert_eq!(tracking.current_mode, Mode::Cruising);
+        assert!((turboprop.cruise_floor()..=turboprop.ceiling).contains(&tracking.altitude));
+        let speeds = turboprop.cruise_speeds();
+        assert!((speeds.start..=speeds.end).contains(&tracking.speed), "speed {}", tracking.speed);
+    }
+
+    #[test]
+    fn the_speed_stays_within_the_profile_of_slow_climbers() {
+        // Sube un metro por minuto: nunca llega a la altitud de crucero
+        let slow_climber = AircraftModel {
+            name: "slow".to_string(),
+            cruise_speed: 500,
+            ceiling: 7_600,
+            fuel_capacity: 100_000,
+            climb_rate: 1,
+        };
+        let mut tracking = TrackingData::empty(&WallClock);
+        tracking.fuel_remaining = 100_000;
+        let mut plan = FlightPlan::direct(0.0, 90.0);
+        let mut rng = flight_rng(7, 1);
+        for _ in 0..2_000 {
+            tracking.simulate(&mut plan, &Weather::default(), &slow_climber, Duration::seconds(1), &mut rng);
+        }
+        assert_eq!(tracking.current_mode, Mode::Climbing);
+        assert_eq!(tracking.speed, slow_climber.cruise_speeds().end);
+
+        // Llega al crucero en el primer minuto, todavia casi detenido
+        let low_ceiling = AircraftModel {
+            name: "low".to_string(),
+            cruise_speed: 100,
+            ceiling: 60,
+            fuel_capacity: 100_000,
+            climb_rate: 1_500,
+        };
+        let mut tracking = TrackingData::empty(&WallClock);
+        tracking.fuel_remaining = 100_000;
+        for _ in 0..10 {
+            tracking.simulate(&mut plan, &Weather::default(), &low_ceiling, Duration::minutes(1), &mut rng);
+        }
+        assert_eq!(tracking.current_mode, Mode::Cruising);
+        let speeds = low_ceiling.cruise_speeds();
+        assert!((speeds.start..=speeds.end).contains(&tracking.speed), "speed {}", tracking.speed);
+        assert!((low_ceiling.cruise_floor()..=low_ceiling.ceiling).contains(&tracking.altitude));
+    }
+
+    #[test]
+    fn planes_fly_the_legs_of_their_plan_in_order() {
+        // Ezeiza a Madrid pasando por Guarulhos
+        let mut plan = FlightPlan::new(vec![
+            Waypoint::new(-23.43, -46.47, 11_000, 850),
+            Waypoint::new(40.47, -3.56, 0, 0),
+        ]);
+        let mut tracking = TrackingData::empty(&WallClock);
+        tracking.fuel_remaining = 100_000;
+        (tracking.latitude, tracking.longitude) = (-34.82, -58.54);
+        tracking.altitude = AircraftModel::generic().cruise_floor();
+        tracking.speed = 800;
+
+        tracking.simulate(&mut plan, &Weather::default(), &AircraftModel::generic(), Duration::minutes(1), &mut rand::rng());
+        assert_eq!(plan.active_leg(), 0);
+        assert_eq!(tracking.altitude, AircraftModel::generic().cruise_floor() + 500);
+        assert_eq!(tracking.speed, 850);
+        let expected = calculate_heading(tracking.latitude, tracking.longitude, -23.43, -46.47);
+        assert_eq!(tracking.heading, expected);
+
+        while plan.active_leg() == 0 {
+            tracking.simulate(&mut plan, &Weather::default(), &AircraftModel::generic(), Duration::minutes(1), &mut rand::rng());
+        }
+        assert!(tracking.latitude > -23.43);
+        assert!(plan.remaining_distance(tracking.latitude, tracking.longitude) < 8400.0);
+    }
+
+    #[test]
+    fn landed_flights_start_at_their_destination() {
+        let (origin, destination) = (Airport::unknown(), Airport {
+            latitude: 40.471926,
+            longitude: -3.56264,
+            ..Airport::unknown()
+        });
+        let landed = Status::Landed { arrived_at: Utc::now() };
+        let tracking = TrackingData::random_init(&landed, &WallClock, &mut flight_rng(1, 1), 1_000, &origin, &destination, &AircraftModel::generic());
+        assert_eq!((tracking.latitude, tracking.longitude), (40.471926, -3.56264));
+        assert!(tracking.landed());
+
+        // Las coordenadas se guardan sin perder decimales
+        assert!(tracking.generate_query(1, 1_000).contains("'40.471926', '-3.56264'"));
+    }
+
+    #[test]
+    fn the_arrival_is_estimated_from_the_rest_of_the_route() {
+        let now: DateTime<Utc> = "2024-11-05T06:00:00Z".parse().unwrap();
+        assert_eq!(estimated_arrival(now, 400.0, 800.0), Some(now + Duration::minutes(30)));
+        assert_eq!(estimated_arrival(now, 400.0, 0.0), None);
+
+        let mut tracking = TrackingData::empty(&WallClock);
+        tracking.fuel_remaining = 1000;
+        (tracking.latitude, tracking.longitude) = (0.0, 0.0);
+        tracking.altitude = AircraftModel::generic().cruise_floor();
+        tracking.speed = 800;
+        let mut plan = FlightPlan::direct(0.0, 40.0);
+        tracking.simulate(&mut plan, &Weather::default(), &AircraftModel::generic(), Duration::minutes(1), &mut flight_rng(7, 1));
+        // Sin viento la velocidad sobre el suelo es la del avion
+        let eta = tracking.eta.unwrap();
+        let remaining = plan.remaining_distance(tracking.latitude, tracking.longitude);
+        assert_eq!(Some(eta), estimated_arrival(tracking.last_update, remaining, tracking.speed as f64));
+        assert!(tracking.generate_query(1, 1_000).ends_with(&format!("'{}');", eta.to_rfc3339())));
+
+        // En tierra no hay llegada estimada y se guarda vacia
+        tracking.simulate(&mut FlightPlan::direct(tracking.latitude, tracking.longitude), &Weather::default(), &AircraftModel::generic(), Duration::minutes(1), &mut flight_rng(7, 1));
+        assert!(tracking.landed());
+        assert_eq!(tracking.eta, None);
+        assert!(tracking.generate_query(1, 1_000).ends_with(", '');"));
+    }
+
+    #[test]
+    fn planes_glide_down_land_and_taxi_to_the_gate() {
+        let mut tracking = TrackingData::empty(&WallClock);
+        tracking.fuel_remaining = 100_000;
+        (tracking.latitude, tracking.longitude) = (0.0, 0.0);
+        (tracking.altitude, tracking.speed) = (9_000, 800);
+        tracking.current_mode = Mode::Cruising;
+        // A 120 km de la pista la senda de planeo ya quedo por debajo
+        let mut plan = FlightPlan::direct(0.0, 1.08);
+        let mut rng = flight_rng(7, 1);
+
+        let mut modes = vec![tracking.current_mode.clone()];
+        let mut previous = tracking.clone();
+        for _ in 0..60 {
+            tracking.simulate(&mut plan, &Weather::default(), &AircraftModel::generic(), Duration::minutes(1), &mut rng);
+            if !tracking.landed() {
+                // Baja y frena sin saltos
+                assert!(tracking.altitude <= previous.altitude);
+                assert!(previous.altitude - tracking.altitude <= AircraftModel::generic().climb_rate);
+                assert!(previous.speed.abs_diff(tracking.speed) <= DESCENT_DECELERATION);
+            }
+            if modes.last() != Some(&tracking.current_mode) {
+                if tracking.current_mode == Mode::Taxiing {
+                    assert_eq!(tracking.eta, Some(tracking.last_update + TAXI_TIME));
+                }
+                modes.push(tracking.current_mode.clone());
+            }
+            previous = tracking.clone();
+        }
+        assert_eq!(
+            modes,
+            [Mode::Cruising, Mode::Descending, Mode::Landing, Mode::OnGround, Mode::Taxiing, Mode::AtGate]
+        );
+        assert_eq!((tracking.altitude, tracking.speed, tracking.eta), (SEA_LEVEL, 0, None));
+        assert_eq!("at_gate".parse::<Mode>(), Ok(Mode::AtGate));
+    }
+
+    #[test]
+    fn the_wind_changes_the_ground_speed_and_the_heading() {
+        // Viento del oeste sobre todo el mapa
+        let weather = Weather {
+            regions: vec![WindRegion {
+                name: "global".to_string(),
+                min_latitude: -90.0,
+                max_latitude: 90.0,
+                min_longitude: -180.0,
+                max_longitude: 180.0,
+                wind: Wind {
+                    direction: 90.0,
+                    speed: 100,
+                },
+            }],
+            storms: vec![],
+        };
+        let start = |tracking: &mut TrackingData| {
+            tracking.fuel_remaining = 100_000;
+            (tracking.latitude, tracking.longitude) = (0.0, 0.0);
+            tracking.altitude = AircraftModel::generic().cruise_floor();
+            tracking.speed = 800;
+        };
+
+        // Con la misma semilla la unica diferencia es el viento
+        let (mut calm, mut windy) = (TrackingData::empty(&WallClock), TrackingData::empty(&WallClock));
+        start(&mut calm);
+        start(&mut windy);
+        calm.simulate(&mut FlightPlan::direct(0.0, 40.0), &Weather::default(), &AircraftModel::generic(), Duration::minutes(1), &mut flight_rng(7, 1));
+        windy.simulate(&mut FlightPlan::direct(0.0, 40.0), &weather, &AircraftModel::generic(), Duration::minutes(1), &mut flight_rng(7, 1));
+        assert!(windy.longitude > calm.longitude);
+
+        // Volando al norte el viento cruzado lo obliga a apuntar al oeste
+        let mut tracking = TrackingData::empty(&WallClock);
+        start(&mut tracking);
+        tracking.simulate(&mut FlightPlan::direct(40.0, 0.0), &weather, &AircraftModel::generic(), Duration::minutes(1), &mut rand::rng());
+        assert!(tracking.heading > 270.0 && tracking.heading < 360.0, "heading {}", tracking.heading);
+    }
+}
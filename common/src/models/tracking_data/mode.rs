@@ -5,6 +5,8 @@ pub enum Mode {
     Climbing,
     Descending,
     Landing,
+    Taxiing,
+    AtGate,
     Sos,
     Unknown,
 }
@@ -17,8 +19,30 @@ impl std::fmt::Display for Mode {
             Mode::Climbing => write!(f, "climbing"),
             Mode::Descending => write!(f, "descending"),
             Mode::Landing => write!(f, "landing"),
+            Mode::Taxiing => write!(f, "taxiing"),
+            Mode::AtGate => write!(f, "at_gate"),
             Mode::Sos => write!(f, "sos"),
             Mode::Unknown => write!(f, "unknown"),
         }
     }
 }
+
+/// Reads a mode as written by `Display`.
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "on_ground" => Ok(Mode::OnGround),
+            "cruising" => Ok(Mode::Cruising),
+            "climbing" => Ok(Mode::Climbing),
+            "descending" => Ok(Mode::Descending),
+            "landing" => Ok(Mode::Landing),
+            "taxiing" => Ok(Mode::Taxiing),
+            "at_gate" => Ok(Mode::AtGate),
+            "sos" => Ok(Mode::Sos),
+            "unknown" => Ok(Mode::Unknown),
+            _ => Err(format!("Invalid mode {}", s)),
+        }
+    }
+}
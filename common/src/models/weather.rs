@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+
+use super::tracking_data::{haversine_distance, Degrees, KmH};
+use crate::frame::messages::prepared::quote;
+
+/// How much more fuel the planes burn inside a storm.
+const STORM_FUEL_FACTOR: f64 = 2.0;
+
+/// Wind blowing towards `direction`, measured like the heading of the planes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct Wind {
+    pub direction: Degrees,
+    pub speed: KmH,
+}
+
+impl Wind {
+    /// Splits the wind into the component along `heading`, positive for a tailwind, and the
+    /// one across it, positive when it pushes the plane to its right.
+    pub fn components(&self, heading: Degrees) -> (f64, f64) {
+        let angle = (self.direction - heading).to_radians();
+        let speed = self.speed as f64;
+        (speed * angle.cos(), speed * angle.sin())
+    }
+}
+
+/// A part of the map where the wind is the same, between two latitudes and two longitudes.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WindRegion {
+    pub name: String,
+    pub min_latitude: Degrees,
+    pub max_latitude: Degrees,
+    pub min_longitude: Degrees,
+    pub max_longitude: Degrees,
+    pub wind: Wind,
+}
+
+impl WindRegion {
+    pub fn contains(&self, latitude: Degrees, longitude: Degrees) -> bool {
+        (self.min_latitude..=self.max_latitude).contains(&latitude)
+            && (self.min_longitude..=self.max_longitude).contains(&longitude)
+    }
+
+    pub fn generate_query(&self) -> String {
+        format!(
+            "INSERT INTO wind_regions \
+            (name, min_latitude, max_latitude, min_longitude, max_longitude, wind_direction, wind_speed) \
+            VALUES ({}, '{}', '{}', '{}', '{}', '{}', '{}');",
+            quote(&self.name),
+            self.min_latitude,
+            self.max_latitude,
+            self.min_longitude,
+            self.max_longitude,
+            self.wind.direction,
+            self.wind.speed
+        )
+    }
+}
+
+/// A storm: within `radius_km` of its center its wind adds to that of the region and the
+/// planes burn more fuel.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StormCell {
+    pub name: String,
+    pub latitude: Degrees,
+    pub longitude: Degrees,
+    pub radius_km: f64,
+    pub wind: Wind,
+}
+
+impl StormCell {
+    pub fn contains(&self, latitude: Degrees, longitude: Degrees) -> bool {
+        haversine_distance(self.latitude, self.longitude, latitude, longitude) <= self.radius_km
+    }
+
+    pub fn generate_query(&self) -> String {
+        format!(
+            "INSERT INTO storms (name, latitude, longitude, radius, wind_direction, wind_speed) \
+            VALUES ({}, '{}', '{}', '{}', '{}', '{}');",
+            quote(&self.name),
+            self.latitude,
+            self.longitude,
+            self.radius_km,
+            self.wind.direction,
+            self.wind.speed
+        )
+    }
+}
+
+/// The weather the flights are simulated in, set in the `weather` section of the config.
+/// Without regions nor storms there is no wind.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Weather {
+    /// The regions with wind. Where they overlap, the first one applies.
+    pub regions: Vec<WindRegion>,
+    pub storms: Vec<StormCell>,
+}
+
+impl Weather {
+    /// Returns the wind at a position: that of its region plus that of the storms it is in.
+    pub fn wind_at(&self, latitude: Degrees, longitude: Degrees) -> Wind {
+        let region = self
+            .regions
+            .iter()
+            .find(|region| region.contains(latitude, longitude))
+            .map(|region| region.wind);
+        let storms = self
+            .storms
+            .iter()
+            .filter(|storm| storm.contains(latitude, longitude))
+            .map(|storm| storm.wind);
+
+        // Los vientos se suman como vectores
+        let (x, y) = region
+            .into_iter()
+            .chain(storms)
+            .fold((0.0, 0.0), |(x, y), wind| {
+                let direction = wind.direction.to_radians();
+                let speed = wind.speed as f64;
+                (x + speed * direction.sin(), y + speed * direction.cos())
+            });
+        Wind {
+            direction: (x.atan2(y).to_degrees() + 360.0) % 360.0,
+            speed: (x * x + y * y).sqrt().round() as KmH,
+        }
+    }
+
+    pub fn in_storm(&self, latitude: Degrees, longitude: Degrees) -> bool {
+        self.storms
+            .iter()
+            .any(|storm| storm.contains(latitude, longitude))
+    }
+
+    /// Returns how many times the usual fuel a plane burns at a position.
+    pub fn fuel_burn_factor(&self, latitude: Degrees, longitude: Degrees) -> f64 {
+        if self.in_storm(latitude, longitude) {
+            STORM_FUEL_FACTOR
+        } else {
+            1.0
+        }
+    }
+
+    /// Builds the queries that store the regions and storms, so they can be drawn on the map.
+    pub fn generate_queries(&self) -> Vec<String> {
+        self.regions
+            .iter()
+            .map(WindRegion::generate_query)
+            .chain(self.storms.iter().map(StormCell::generate_query))
+            .collect()
+    }
+
+    /// Checks the regions and storms of the config.
+    ///
+    /// # Returns
+    /// The description of every problem found.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for region in &self.regions {
+            if region.min_latitude > region.max_latitude
+                || region.min_longitude > region.max_longitude
+            {
+                problems.push(format!(
+                    "wind region {} has a minimum larger than its maximum",
+                    region.name
+                ));
+            }
+        }
+        for storm in &self.storms {
+            if storm.radius_km <= 0.0 {
+                problems.push(format!("storm {} must have a positive radius", storm.name));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weather() -> Weather {
+        Weather {
+            regions: vec![WindRegion {
+                name: "atlantico sur".to_string(),
+                min_latitude: -60.0,
+                max_latitude: 0.0,
+                min_longitude: -70.0,
+                max_longitude: 0.0,
+                wind: Wind {
+                    direction: 90.0,
+                    speed: 60,
+                },
+            }],
+            storms: vec![StormCell {
+                name: "sudestada".to_string(),
+                latitude: -35.0,
+                longitude: -57.0,
+                radius_km: 200.0,
+                wind: Wind {
+                    direction: 0.0,
+                    speed: 80,
+                },
+            }],
+        }
+    }
+
+    #[test]
+    fn the_wind_of_the_storms_adds_to_that_of_the_region() {
+        let weather = weather();
+        assert_eq!(weather.wind_at(10.0, -40.0), Wind::default());
+        assert_eq!(
+            weather.wind_at(-20.0, -40.0),
+            Wind {
+                direction: 90.0,
+                speed: 60
+            }
+        );
+
+        let wind = weather.wind_at(-35.0, -57.5);
+        assert_eq!(wind.speed, 100);
+        assert!((wind.direction - 36.87).abs() < 0.01);
+        assert_eq!(weather.fuel_burn_factor(-35.0, -57.5), STORM_FUEL_FACTOR);
+        assert_eq!(weather.fuel_burn_factor(-20.0, -40.0), 1.0);
+        assert_eq!(weather.generate_queries().len(), 2);
+    }
+
+    #[test]
+    fn the_wind_splits_along_and_across_the_heading() {
+        let wind = Wind {
+            direction: 90.0,
+            speed: 50,
+        };
+        let (along, across) = wind.components(90.0);
+        assert!((along - 50.0).abs() < 0.01 && across.abs() < 0.01);
+        let (along, across) = wind.components(0.0);
+        assert!(along.abs() < 0.01 && (across - 50.0).abs() < 0.01);
+        let (along, _) = wind.components(270.0);
+        assert!((along + 50.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,188 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// Length in bytes of the random nonce prepended to every sealed blob.
+pub const NONCE_LEN: usize = 12;
+/// Length in bytes of the authentication tag appended by AES-GCM.
+pub const TAG_LEN: usize = 16;
+
+/// A 256-bit key used to encrypt data at rest.
+pub type AtRestKey = [u8; 32];
+
+/// Derives a 256-bit AES key from an arbitrary secret (e.g. the `DB_KEY` env var).
+///
+/// # Parameters
+/// - `secret`: The secret to derive the key from.
+///
+/// # Returns
+/// The SHA-256 digest of the secret, usable as an AES-256 key.
+pub fn derive_key(secret: &str) -> AtRestKey {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+/// Computes the SHA-256 checksum of `data`.
+///
+/// # Parameters
+/// - `data`: The bytes to checksum.
+///
+/// # Returns
+/// The 32-byte digest.
+pub fn checksum(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Computes the HMAC-SHA256 of `data` with `key`, so that equal inputs can be matched
+/// without storing them in plaintext, and data can't be changed without the key.
+///
+/// # Parameters
+/// - `data`: The bytes to authenticate.
+/// - `key`: The 256-bit key.
+///
+/// # Returns
+/// The 32-byte MAC.
+pub fn keyed_digest(data: &[u8], key: &AtRestKey) -> [u8; 32] {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Encrypts `data` with AES-256-GCM using a fresh random nonce.
+///
+/// # Parameters
+/// - `data`: The plaintext bytes.
+/// - `key`: The 256-bit key.
+///
+/// # Returns
+/// The nonce followed by the ciphertext and its authentication tag.
+pub fn seal(data: &[u8], key: &AtRestKey) -> Vec<u8> {
+    seal_with_aad(data, key, &[])
+}
+
+/// Same as [`seal`], but binds the blob to `aad`, e.g. the place where it is stored: it
+/// only opens with the same `aad`.
+///
+/// # Parameters
+/// - `data`: The plaintext bytes.
+/// - `key`: The 256-bit key.
+/// - `aad`: Data authenticated along with the blob but not stored in it.
+///
+/// # Returns
+/// The nonce followed by the ciphertext and its authentication tag.
+pub fn seal_with_aad(data: &[u8], key: &AtRestKey, aad: &[u8]) -> Vec<u8> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: data, aad })
+        .expect("AES-GCM encryption cannot fail for in-memory buffers");
+
+    let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    sealed
+}
+
+/// Decrypts a blob produced by [`seal`], verifying its authentication tag.
+///
+/// # Parameters
+/// - `sealed`: The nonce, ciphertext and tag.
+/// - `key`: The 256-bit key.
+///
+/// # Returns
+/// - `Ok(Vec<u8>)` with the plaintext, or `Err(String)` if the blob is truncated,
+///   was tampered with or was encrypted with a different key.
+pub fn open(sealed: &[u8], key: &AtRestKey) -> Result<Vec<u8>, String> {
+    open_with_aad(sealed, key, &[])
+}
+
+/// Decrypts a blob produced by [`seal_with_aad`], verifying its authentication tag and that
+/// it was sealed with the same `aad`.
+///
+/// # Returns
+/// - `Ok(Vec<u8>)` with the plaintext, or `Err(String)` if the blob is truncated, was
+///   tampered with, was encrypted with a different key or is bound to other data.
+pub fn open_with_aad(sealed: &[u8], key: &AtRestKey, aad: &[u8]) -> Result<Vec<u8>, String> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err("Encrypted data is truncated".to_string());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| "Encrypted data failed authentication".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open_roundtrip() {
+        let key = derive_key("82917");
+        let sealed = seal(b"hola mundo", &key);
+        assert_eq!(open(&sealed, &key).unwrap(), b"hola mundo");
+    }
+
+    #[test]
+    fn nonces_are_random() {
+        let key = derive_key("82917");
+        assert_ne!(seal(b"hola", &key), seal(b"hola", &key));
+    }
+
+    #[test]
+    fn tampered_data_is_rejected() {
+        let key = derive_key("82917");
+        let mut sealed = seal(b"hola mundo", &key);
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(open(&sealed, &key).is_err());
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let key = derive_key("82917");
+        let sealed = seal(b"hola mundo", &key);
+        assert!(open(&sealed[..NONCE_LEN + 3], &key).is_err());
+    }
+
+    #[test]
+    fn blobs_only_open_with_their_aad() {
+        let key = derive_key("82917");
+        let sealed = seal_with_aad(b"hola mundo", &key, b"particion 1");
+        assert_eq!(
+            open_with_aad(&sealed, &key, b"particion 1").unwrap(),
+            b"hola mundo"
+        );
+        assert!(open_with_aad(&sealed, &key, b"particion 2").is_err());
+        assert!(open(&sealed, &key).is_err());
+    }
+
+    #[test]
+    fn keyed_digest_is_hmac_sha256() {
+        // RFC 4231, caso 2, con la clave completada con ceros como pide HMAC
+        let mut key = [0; 32];
+        key[..4].copy_from_slice(b"Jefe");
+        let mac = keyed_digest(b"what do ya want for nothing?", &key);
+        assert_eq!(mac[..4], [0x5b, 0xdc, 0xc1, 0x46]);
+        assert_ne!(
+            mac,
+            keyed_digest(b"what do ya want for nothing?", &derive_key("82917"))
+        );
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        let sealed = seal(b"hola mundo", &derive_key("82917"));
+        assert!(open(&sealed, &derive_key("1234")).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use sha2::Sha256;
+
+/// Name of the scheme a password hash starts with.
+const SCHEME: &str = "pbkdf2-sha256";
+/// Rounds of PBKDF2 used to hash new passwords. A node checks a password on every connection
+/// that authenticates, so it is lower than that of the keystore.
+const PBKDF2_ROUNDS: u32 = 10_000;
+/// Length in bytes of the random salt of each password.
+const SALT_LEN: usize = 16;
+/// Length in bytes of the hash of a password.
+const HASH_LEN: usize = 32;
+
+/// Hashes a password to keep it in the `[auth]` section of the config file, as
+/// `pbkdf2-sha256$<rounds>$<salt>$<hash>` with the salt and the hash in hex.
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let hash = password_hash(password, &salt, PBKDF2_ROUNDS);
+    format!("{}${}${}${}", SCHEME, PBKDF2_ROUNDS, to_hex(&salt), to_hex(&hash))
+}
+
+/// Returns whether `password` is the one `hash` was made from with `hash_password`. A hash
+/// that isn't in that format matches no password.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    let Some((rounds, salt, expected)) = parse_hash(hash) else {
+        return false;
+    };
+    let hash = password_hash(password, &salt, rounds);
+    // Se comparan todos los bytes para no revelar cuántos coinciden
+    hash.iter()
+        .zip(expected.iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// Returns whether `hash` has the format of `hash_password`.
+pub fn is_password_hash(hash: &str) -> bool {
+    parse_hash(hash).is_some()
+}
+
+/// Reads the rounds, the salt and the hash of a password hash.
+fn parse_hash(hash: &str) -> Option<(u32, Vec<u8>, [u8; HASH_LEN])> {
+    let mut parts = hash.split('$');
+    if parts.next()? != SCHEME {
+        return None;
+    }
+    let rounds = parts.next()?.parse().ok().filter(|rounds| *rounds > 0)?;
+    let salt = from_hex(parts.next()?)?;
+    let expected: [u8; HASH_LEN] = from_hex(parts.next()?)?.try_into().ok()?;
+    match parts.next() {
+        Some(_) => None,
+        None => Some((rounds, salt, expected)),
+    }
+}
+
+fn password_hash(password: &str, salt: &[u8], rounds: u32) -> [u8; HASH_LEN] {
+    pbkdf2::pbkdf2_hmac_array::<Sha256, HASH_LEN>(password.as_bytes(), salt, rounds)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_hashed_password_matches_its_hash() {
+        let hash = hash_password("secret");
+        assert!(hash.starts_with("pbkdf2-sha256$"));
+        assert!(is_password_hash(&hash));
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("Secret", &hash));
+        // Cada hash tiene su propia sal
+        assert_ne!(hash, hash_password("secret"));
+
+        assert!(!is_password_hash("secret"));
+        assert!(!verify_password("secret", "secret"));
+        assert!(!verify_password("secret", "pbkdf2-sha256$0$00$00"));
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::Write;
+use std::{fs, io};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use sha2::Sha256;
+
+use super::at_rest::{open, seal, AtRestKey};
+use crate::config::FsyncPolicy;
+
+/// Bytes every keystore file starts with.
+const MAGIC: &[u8; 6] = b"AEROKS";
+/// Version of the keystore layout.
+const VERSION: u8 = 1;
+/// Rounds of PBKDF2 used to derive the key that seals the keystore from its passphrase.
+const PBKDF2_ROUNDS: u32 = 100_000;
+/// Length in bytes of the random salt of the passphrase.
+const SALT_LEN: usize = 16;
+/// Length in bytes of a data-at-rest key.
+const KEY_LEN: usize = 32;
+
+/// The data-at-rest keys of a node. The first one is the active key, used to encrypt; the
+/// others are the keys it replaced, kept so that data written before a rotation can be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keystore {
+    keys: Vec<AtRestKey>,
+}
+
+impl Keystore {
+    /// Creates a keystore with a single random key.
+    pub fn generate() -> Self {
+        Self {
+            keys: vec![random_key()],
+        }
+    }
+
+    /// Returns the key used to encrypt new data.
+    pub fn active_key(&self) -> AtRestKey {
+        self.keys[0]
+    }
+
+    /// Returns every key, the active one first, so that older data can still be read.
+    pub fn keys(&self) -> &[AtRestKey] {
+        &self.keys
+    }
+
+    /// Replaces the active key with a new random one, keeping the old keys for reading.
+    pub fn rotate(&mut self) {
+        self.keys.insert(0, random_key());
+    }
+
+    /// Writes the keystore to `path`, sealed with a key derived from `passphrase`.
+    ///
+    /// # Parameters
+    /// - `path`: The keystore file.
+    /// - `passphrase`: The passphrase that protects the keys.
+    /// - `fsync`: Whether the file is synced before it replaces the previous keystore. There
+    ///   is no background sync here, so `Periodic` syncs it too.
+    pub fn save(&self, path: &str, passphrase: &str, fsync: FsyncPolicy) -> io::Result<()> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let sealed = seal(
+            &self.keys.concat(),
+            &passphrase_key(passphrase, &salt, PBKDF2_ROUNDS),
+        );
+
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 5 + SALT_LEN + sealed.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&PBKDF2_ROUNDS.to_be_bytes());
+        bytes.extend_from_slice(&salt);
+        bytes.extend_from_slice(&sealed);
+        write_atomically(path, &bytes, fsync != FsyncPolicy::Never)
+    }
+
+    /// Reads a keystore written by [`Keystore::save`].
+    ///
+    /// # Parameters
+    /// - `path`: The keystore file.
+    /// - `passphrase`: The passphrase that protects the keys.
+    ///
+    /// # Returns
+    /// The keystore, or an `InvalidData` error if the file isn't a keystore, is corrupt or
+    /// the passphrase is wrong.
+    pub fn load(path: &str, passphrase: &str) -> io::Result<Self> {
+        let invalid_data =
+            |e: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e));
+
+        let bytes = fs::read(path)?;
+        let header_len = MAGIC.len() + 5 + SALT_LEN;
+        if bytes.len() < header_len || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a keystore file"));
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err(invalid_data("unsupported keystore version"));
+        }
+        let mut rounds = [0; 4];
+        rounds.copy_from_slice(&bytes[MAGIC.len() + 1..MAGIC.len() + 5]);
+        let salt = &bytes[MAGIC.len() + 5..header_len];
+
+        let key = passphrase_key(passphrase, salt, u32::from_be_bytes(rounds));
+        let keys = open(&bytes[header_len..], &key)
+            .map_err(|_| invalid_data("wrong passphrase or corrupt keystore"))?;
+        if keys.is_empty() || keys.len() % KEY_LEN != 0 {
+            return Err(invalid_data("corrupt keystore"));
+        }
+
+        Ok(Self {
+            keys: keys
+                .chunks(KEY_LEN)
+                .map(|chunk| {
+                    let mut key = [0; KEY_LEN];
+                    key.copy_from_slice(chunk);
+                    key
+                })
+                .collect(),
+        })
+    }
+}
+
+fn random_key() -> AtRestKey {
+    let mut key = [0; KEY_LEN];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// Derives the key that seals a keystore from its passphrase with PBKDF2-HMAC-SHA256.
+fn passphrase_key(passphrase: &str, salt: &[u8], rounds: u32) -> AtRestKey {
+    pbkdf2::pbkdf2_hmac_array::<Sha256, KEY_LEN>(passphrase.as_bytes(), salt, rounds)
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it over `path`. When `sync`
+/// is set the file is on disk before the rename, so a crash leaves either the old keystore or
+/// the new one, never a truncated one.
+fn write_atomically(path: &str, bytes: &[u8], sync: bool) -> io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(bytes)?;
+    if sync {
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keystore_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(name)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn keystore_roundtrip() {
+        let path = keystore_path("aero_keystore_roundtrip");
+        let keystore = Keystore::generate();
+        keystore.save(&path, "secreto", FsyncPolicy::Always).unwrap();
+
+        assert_eq!(Keystore::load(&path, "secreto").unwrap(), keystore);
+        assert!(Keystore::load(&path, "otro").is_err());
+    }
+
+    #[test]
+    fn rotation_keeps_the_previous_keys() {
+        let path = keystore_path("aero_keystore_rotation");
+        let mut keystore = Keystore::generate();
+        let first = keystore.active_key();
+        keystore.rotate();
+        keystore.save(&path, "secreto", FsyncPolicy::Always).unwrap();
+
+        let keystore = Keystore::load(&path, "secreto").unwrap();
+        assert_ne!(keystore.active_key(), first);
+        assert_eq!(keystore.keys(), &[keystore.active_key(), first]);
+        // El archivo temporal se renombra sobre el keystore
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+    }
+
+    #[test]
+    fn other_files_are_not_keystores() {
+        let path = keystore_path("aero_keystore_garbage");
+        fs::write(&path, b"DB_KEY=82917").unwrap();
+        assert!(Keystore::load(&path, "secreto").is_err());
+    }
+}
@@ -1,4 +1,7 @@
 pub mod base_encryption_functions;
+pub mod at_rest;
+pub mod keystore;
+pub mod credentials;
 
 use base_encryption_functions::{decrypt, encrypt};
 use rand::{rng, Rng};
@@ -7,6 +10,7 @@ use std::{
     net::TcpStream,
 };
 
+use crate::frame::compression::Compression;
 use crate::frame::Frame;
 
 // codigo ultra secreto
@@ -18,6 +22,8 @@ pub struct EncryptionHandler {
     public_key: u64,
     private_key: u64,
     shared_secret: Option<u64>,
+    /// The compression agreed on in the STARTUP, used once the connection is encrypted.
+    compression: Option<Compression>,
 }
 
 impl EncryptionHandler {
@@ -31,6 +37,7 @@ impl EncryptionHandler {
             public_key: my_public_key,
             private_key,
             shared_secret: None,
+            compression: None,
         }
     }
 
@@ -46,6 +53,7 @@ impl EncryptionHandler {
                 public_key: my_public_key,
                 private_key,
                 shared_secret: Some(shared_secret),
+                compression: None,
             },
             my_public_key,
             shared_secret,
@@ -67,17 +75,37 @@ impl EncryptionHandler {
         false
     }
 
+    /// Sets the compression of the bodies of the frames, applied once the connection is
+    /// encrypted so that the handshake is never compressed.
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.compression = compression;
+    }
+
+    /// Returns the compression in use, `None` until the connection is encrypted.
+    pub fn compression(&self) -> Option<Compression> {
+        self.shared_secret.and(self.compression)
+    }
+
     pub fn read(&self, stream: &mut TcpStream) -> io::Result<Frame> {
-        Frame::deserialize_from_stream(stream, &self.get_decryptor())
+        Frame::deserialize_from_stream(stream, &self.get_decryptor(), self.compression())
     }
 
     pub fn write(&self, stream: &mut TcpStream, frame: &Frame) -> io::Result<()> {
-        let bytes = frame.serialize();
-        let bytes = match self.shared_secret {
+        stream.write_all(&self.encrypt_frame(frame))
+    }
+
+    /// Serializes a frame, encrypted once the shared secret is agreed on.
+    pub fn encrypt_frame(&self, frame: &Frame) -> Vec<u8> {
+        let bytes = frame.serialize_compressed(self.compression());
+        match self.shared_secret {
             Some(shared_secret) => encrypt(&bytes, shared_secret),
             None => bytes,
-        };
-        stream.write_all(&bytes)
+        }
+    }
+
+    /// Decrypts bytes read from the connection, once the shared secret is agreed on.
+    pub fn decrypt(&self, bytes: &[u8]) -> Vec<u8> {
+        (self.get_decryptor())(bytes)
     }
 
     #[allow(clippy::type_complexity)]
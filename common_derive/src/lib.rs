@@ -0,0 +1,86 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Field, Fields, LitStr};
+
+/// Derives `common::client_manager::FromRow` for a struct with named fields. Each field is
+/// read with `Row::get` from the column with its name, or the one given with
+/// `#[row(rename = "column")]`, so its type must implement `FromColumn`. `Option` fields are
+/// `None` when the column is missing, empty or `null`; any other field missing from the row
+/// is an error.
+#[proc_macro_derive(FromRow, attributes(row))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "FromRow can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "FromRow can only be derived for structs",
+            ))
+        }
+    };
+
+    let reads = fields
+        .iter()
+        .map(read_field)
+        .collect::<syn::Result<Vec<_>>>()?;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::common::client_manager::FromRow for #name #type_generics #where_clause {
+            fn from_row(
+                row: &::common::client_manager::Row,
+            ) -> ::std::result::Result<Self, ::std::string::String> {
+                ::std::result::Result::Ok(Self {
+                    #(#reads,)*
+                })
+            }
+        }
+    })
+}
+
+/// Generates the initialization of a field from its column.
+fn read_field(field: &Field) -> syn::Result<TokenStream2> {
+    let Some(ident) = &field.ident else {
+        return Err(syn::Error::new_spanned(field, "Expected a named field"));
+    };
+    let column = column_name(field)?.unwrap_or_else(|| ident.to_string());
+    let ty = &field.ty;
+    Ok(quote! { #ident: row.get::<#ty>(#column)? })
+}
+
+/// Returns the column of a field renamed with `#[row(rename = "column")]`.
+fn column_name(field: &Field) -> syn::Result<Option<String>> {
+    let mut column = None;
+    for attribute in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("row"))
+    {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                column = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("Expected `rename = \"column\"`"))
+            }
+        })?;
+    }
+    Ok(column)
+}